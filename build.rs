@@ -1,9 +1,15 @@
 use cfg_aliases::cfg_aliases;
 
 fn main() {
+  // Set with RUSTFLAGS="--cfg bench_tuning" to let the bench binary tune internal constants
+  println!("cargo:rustc-check-cfg=cfg(bench_tuning)");
   cfg_aliases! {
     timeout_supported: {any(target_os = "linux", target_os = "macos", target_os = "darwin")},
     mlock_supported: {target_family = "unix"},
     mmap_advise_supported: {target_family = "unix"},
+    file_backend_supported: {not(target_arch = "wasm32")},
+    fd_sharing_supported: {target_os = "linux"},
+    proc_fs_supported: {target_os = "linux"},
+    poison_freed_pages: {any(debug_assertions, feature = "poison-freed-pages")},
   }
 }
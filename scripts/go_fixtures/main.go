@@ -0,0 +1,73 @@
+// Writes the Go bbolt databases in testdata/go that the compat tests open.
+//
+// Run from the repository root with etcd-io/bbolt v1.3 on a little endian machine:
+//
+//	go run ./scripts/go_fixtures testdata/go
+package main
+
+import (
+	"encoding/binary"
+	"fmt"
+	"log"
+	"os"
+	"path/filepath"
+
+	bolt "go.etcd.io/bbolt"
+)
+
+func main() {
+	if len(os.Args) != 2 {
+		log.Fatalf("usage: %s <dir>", os.Args[0])
+	}
+	dir := os.Args[1]
+	options := &bolt.Options{PageSize: 4096}
+
+	// A freshly created database
+	empty, err := create(filepath.Join(dir, "empty.db"), options)
+	if err != nil {
+		log.Fatal(err)
+	}
+	if err := empty.Close(); err != nil {
+		log.Fatal(err)
+	}
+
+	// One update with a bucket on its own leaf page and an inline bucket
+	db, err := create(filepath.Join(dir, "buckets.db"), options)
+	if err != nil {
+		log.Fatal(err)
+	}
+	err = db.Update(func(tx *bolt.Tx) error {
+		big, err := tx.CreateBucket([]byte("big"))
+		if err != nil {
+			return err
+		}
+		for i := uint32(0); i < 100; i++ {
+			key := make([]byte, 4)
+			binary.BigEndian.PutUint32(key, i)
+			if err := big.Put(key, []byte(fmt.Sprintf("value-%014d", i))); err != nil {
+				return err
+			}
+		}
+		widgets, err := tx.CreateBucket([]byte("widgets"))
+		if err != nil {
+			return err
+		}
+		if err := widgets.Put([]byte("foo"), []byte("bar")); err != nil {
+			return err
+		}
+		return widgets.Put([]byte("baz"), []byte("qux"))
+	})
+	if err != nil {
+		log.Fatal(err)
+	}
+	if err := db.Close(); err != nil {
+		log.Fatal(err)
+	}
+}
+
+func create(path string, options *bolt.Options) (*bolt.DB, error) {
+	if err := os.Remove(path); err != nil && !os.IsNotExist(err) {
+		return nil, err
+	}
+	return bolt.Open(path, 0600, options)
+}
@@ -15,3 +15,22 @@ pub mod size {
   pub const MAX_MAP_SIZE: Size = Size::from_const(256 * TEBIBYTE);
   pub const MAX_ALLOC_SIZE: Size = Size::from_const(2 * GIBIBYTE);
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod time {
+  pub use std::time::Instant;
+}
+
+// std::time::Instant panics on wasm32-unknown-unknown
+#[cfg(target_arch = "wasm32")]
+pub mod time {
+  pub use web_time::Instant;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod aligned {
+  pub use aligners::{alignment, AlignedBytes};
+}
+
+#[cfg(target_arch = "wasm32")]
+pub mod aligned;
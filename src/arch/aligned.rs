@@ -0,0 +1,135 @@
+//! A wasm32 stand-in for the parts of `aligners` the crate uses. `aligners` depends on
+//! page_size 0.4, which doesn't build on wasm32
+
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+pub mod alignment {
+  /// The alignment of an [AlignedBytes](super::AlignedBytes) buffer
+  pub trait Alignment {
+    fn size() -> usize;
+  }
+
+  /// Page alignment. wasm32 has no OS pages, so this is the smallest common native page size
+  #[derive(Debug)]
+  pub enum Page {}
+
+  impl Alignment for Page {
+    #[inline]
+    fn size() -> usize {
+      4096
+    }
+  }
+}
+
+use alignment::Alignment;
+
+/// A zero initialized byte buffer aligned to `A`
+pub struct AlignedBytes<A: Alignment> {
+  ptr: NonNull<u8>,
+  len: usize,
+  phantom: PhantomData<A>,
+}
+
+// AlignedBytes owns its buffer like a Box<[u8]>
+unsafe impl<A: Alignment> Send for AlignedBytes<A> {}
+unsafe impl<A: Alignment> Sync for AlignedBytes<A> {}
+
+impl<A: Alignment> AlignedBytes<A> {
+  fn layout(len: usize) -> Layout {
+    Layout::from_size_align(len, A::size()).expect("invalid aligned buffer size")
+  }
+
+  pub fn new_zeroed(len: usize) -> Self {
+    if len == 0 {
+      // Dangling, but aligned like any other buffer
+      let ptr = NonNull::new(A::size() as *mut u8).unwrap();
+      return AlignedBytes {
+        ptr,
+        len,
+        phantom: PhantomData,
+      };
+    }
+    let layout = Self::layout(len);
+    let ptr =
+      NonNull::new(unsafe { alloc_zeroed(layout) }).unwrap_or_else(|| handle_alloc_error(layout));
+    AlignedBytes {
+      ptr,
+      len,
+      phantom: PhantomData,
+    }
+  }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  #[inline]
+  pub fn as_ptr(&self) -> *const u8 {
+    self.ptr.as_ptr()
+  }
+
+  #[inline]
+  pub fn as_mut_ptr(&mut self) -> *mut u8 {
+    self.ptr.as_ptr()
+  }
+}
+
+impl<A: Alignment> Drop for AlignedBytes<A> {
+  fn drop(&mut self) {
+    if self.len != 0 {
+      unsafe { dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+  }
+}
+
+impl<A: Alignment> Deref for AlignedBytes<A> {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+  }
+}
+
+impl<A: Alignment> DerefMut for AlignedBytes<A> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+  }
+}
+
+impl<A: Alignment> AsRef<[u8]> for AlignedBytes<A> {
+  fn as_ref(&self) -> &[u8] {
+    self
+  }
+}
+
+impl<A: Alignment> AsMut<[u8]> for AlignedBytes<A> {
+  fn as_mut(&mut self) -> &mut [u8] {
+    self
+  }
+}
+
+impl<A: Alignment> Clone for AlignedBytes<A> {
+  fn clone(&self) -> Self {
+    let mut clone = Self::new_zeroed(self.len);
+    clone.copy_from_slice(self);
+    clone
+  }
+}
+
+impl<A: Alignment> Default for AlignedBytes<A> {
+  fn default() -> Self {
+    Self::new_zeroed(0)
+  }
+}
+
+impl<A: Alignment> std::fmt::Debug for AlignedBytes<A> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
@@ -0,0 +1,533 @@
+use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi};
+use crate::common::TxId;
+use crate::comparator::{meta_key, COMPARATOR_META_KEY, META_BUCKET, ROOT_KV_BUCKET};
+use crate::tx::{TxCell, TxIApi, TxRwIApi};
+use crate::{
+  format_hex, Bolt, BoltOptions, BucketApi, BucketImpl, CursorApi, DbApi, Error, TxApi, TxImpl,
+  TxRwApi,
+};
+use anyhow::anyhow;
+use hashbrown::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the manifest in an archive
+const MANIFEST_ENTRY: &str = "manifest.txt";
+/// The name of the database file in an archive
+const DB_ENTRY: &str = "bolt.db";
+/// The size of the keys and values copied in one transaction when building the archived
+/// copy, like the `txMaxSize` of `bbolt compact`. Bounds the dirty nodes held in memory.
+const COPY_TX_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/// A bucket listed in an [ArchiveManifest]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveBucket {
+  /// the names of the bucket and of its parents, starting with the top-level bucket
+  pub path: Vec<Vec<u8>>,
+  /// the number of keys in the bucket, not counting nested buckets
+  pub keys: u64,
+}
+
+/// The manifest of an archive written by [Bolt::export_archive]
+///
+/// Stored in the archive as `key: value` lines: `txid`, `created_at`, `page_size` and one
+/// `bucket` line per bucket with its number of keys and its hex encoded path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveManifest {
+  /// the id of the transaction the database was exported at
+  pub txid: TxId,
+  /// when the archive was created, in seconds since the Unix epoch
+  pub created_at: u64,
+  /// the page size of the archived database
+  pub page_size: usize,
+  /// every bucket of the database, depth first in key order
+  pub buckets: Vec<ArchiveBucket>,
+}
+
+impl Display for ArchiveManifest {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "txid: {}", self.txid)?;
+    writeln!(f, "created_at: {}", self.created_at)?;
+    writeln!(f, "page_size: {}", self.page_size)?;
+    for bucket in &self.buckets {
+      let path = bucket
+        .path
+        .iter()
+        .map(|name| format_hex(name))
+        .collect::<Vec<_>>()
+        .join("/");
+      writeln!(f, "bucket: {} {}", bucket.keys, path)?;
+    }
+    Ok(())
+  }
+}
+
+impl ArchiveManifest {
+  fn parse(s: &str) -> crate::Result<ArchiveManifest> {
+    let invalid = |line: &str| Error::Other(anyhow!("invalid manifest line: {}", line));
+    let mut txid = None;
+    let mut created_at = None;
+    let mut page_size = None;
+    let mut buckets = Vec::new();
+    for line in s.lines() {
+      let (key, value) = line.split_once(": ").ok_or_else(|| invalid(line))?;
+      match key {
+        "txid" => txid = Some(TxId(value.parse().map_err(|_| invalid(line))?)),
+        "created_at" => created_at = Some(value.parse().map_err(|_| invalid(line))?),
+        "page_size" => page_size = Some(value.parse().map_err(|_| invalid(line))?),
+        "bucket" => {
+          let (keys, path) = value.split_once(' ').ok_or_else(|| invalid(line))?;
+          buckets.push(ArchiveBucket {
+            path: path
+              .split('/')
+              .map(unhex)
+              .collect::<Option<_>>()
+              .ok_or_else(|| invalid(line))?,
+            keys: keys.parse().map_err(|_| invalid(line))?,
+          });
+        }
+        // Keys added by later versions are skipped
+        _ => {}
+      }
+    }
+    match (txid, created_at, page_size) {
+      (Some(txid), Some(created_at), Some(page_size)) => Ok(ArchiveManifest {
+        txid,
+        created_at,
+        page_size,
+        buckets,
+      }),
+      _ => Err(Error::Other(anyhow!("incomplete manifest"))),
+    }
+  }
+}
+
+/// Parses a bucket name written with [format_hex]
+fn unhex(s: &str) -> Option<Vec<u8>> {
+  if s.len() % 2 != 0 {
+    return None;
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+fn bucket_at<'tx>(root: BucketCell<'tx>, path: &[&[u8]]) -> crate::Result<BucketCell<'tx>> {
+  let mut bucket = root;
+  for name in path {
+    bucket = bucket.api_bucket(name).ok_or(Error::BucketNotFound)?;
+  }
+  Ok(bucket)
+}
+
+/// Returns the comparator recorded in `tx` for the bucket `name` in `path`
+fn comparator_of<'tx>(tx: TxCell<'tx>, path: &[&[u8]], name: &[u8]) -> Option<&'tx str> {
+  let mut child = Vec::new();
+  for n in path.iter().copied().chain([name]) {
+    child.extend_from_slice(&(n.len() as u32).to_be_bytes());
+    child.extend_from_slice(n);
+  }
+  let meta = tx.system_bucket(META_BUCKET)?;
+  std::str::from_utf8(meta.api_get(&meta_key(&child, COMPARATOR_META_KEY))?).ok()
+}
+
+/// Copies every bucket and key of `src` into `dst`, followed by the bucket metadata and the
+/// root data bucket, and returns the buckets copied. A transaction of `dst` is committed
+/// whenever `max_tx_size` bytes of keys and values were copied in it.
+fn copy_db(src: &TxImpl, dst: &mut Bolt, max_tx_size: usize) -> crate::Result<Vec<ArchiveBucket>> {
+  let mut buckets: Vec<ArchiveBucket> = Vec::new();
+  let mut index: HashMap<Vec<&[u8]>, usize> = HashMap::new();
+  let mut tx = dst.begin_rw_tx()?;
+  let mut size = 0;
+  for record in src.export_iter() {
+    let record_size = record.key.len() + record.value.map_or(0, |v| v.len());
+    if size + record_size > max_tx_size {
+      tx.commit()?;
+      tx = dst.begin_rw_tx()?;
+      size = 0;
+    }
+    size += record_size;
+
+    let bucket = bucket_at(tx.tx.root_bucket_mut(), &record.path)?;
+    match record.value {
+      Some(value) => {
+        bucket.api_put(record.key, value)?;
+        if let Some(i) = index.get(&*record.path) {
+          buckets[*i].keys += 1;
+        }
+      }
+      None => {
+        let child = match comparator_of(src.tx, &record.path, record.key) {
+          Some(comparator) => bucket.api_create_bucket_with_comparator(record.key, comparator)?,
+          None => bucket.api_create_bucket(record.key)?,
+        };
+        let mut path = record.path.to_vec();
+        path.push(record.key);
+        let sequence = bucket_at(src.tx.root_bucket(), &path)?.api_sequence();
+        if sequence != 0 {
+          child.api_set_sequence(sequence)?;
+        }
+        index.insert(path.clone(), buckets.len());
+        buckets.push(ArchiveBucket {
+          path: path.iter().map(|name| name.to_vec()).collect(),
+          keys: 0,
+        });
+      }
+    }
+  }
+
+  // The records refer to the buckets, so they are copied once the buckets are
+  for name in [META_BUCKET, ROOT_KV_BUCKET] {
+    let from = match src.tx.system_bucket(name) {
+      Some(from) => BucketImpl::from(from),
+      None => continue,
+    };
+    let mut c = from.cursor();
+    let mut entry = c.first();
+    while let Some((k, v)) = entry {
+      if let Some(v) = v {
+        if size + k.len() + v.len() > max_tx_size {
+          tx.commit()?;
+          tx = dst.begin_rw_tx()?;
+          size = 0;
+        }
+        size += k.len() + v.len();
+        tx.tx.system_bucket_mut(name)?.i_put(k, v)?;
+      }
+      entry = c.next();
+    }
+  }
+  tx.commit()?;
+  Ok(buckets)
+}
+
+fn append<W: Write, R: Read>(
+  builder: &mut tar::Builder<W>, name: &str, data: R, size: u64, mtime: u64,
+) -> crate::Result<()> {
+  let mut header = tar::Header::new_gnu();
+  header.set_size(size);
+  header.set_mode(0o644);
+  header.set_mtime(mtime);
+  builder.append_data(&mut header, name, data)?;
+  Ok(())
+}
+
+impl Bolt {
+  /// Writes the database to `w` as a single zstd compressed tar archive, e.g. for a support
+  /// bundle. Returns the manifest stored with it.
+  ///
+  /// The archive holds a compacted copy of the database, written as if every bucket and key
+  /// were put into a new database, and an [ArchiveManifest]. The copy is built in a
+  /// temporary file in transactions of bounded size, so the database doesn't need to fit in
+  /// memory. Comparators are kept, so the archive needs the same comparators registered to
+  /// be imported. See [BoltOptions::import_archive]
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("widgets")?;
+  ///     b.put("foo", "bar")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let mut archive = Vec::new();
+  ///   let manifest = db.export_archive(&mut archive)?;
+  ///   assert_eq!(vec![b"widgets".to_vec()], manifest.buckets[0].path);
+  ///
+  ///   let path = std::env::temp_dir().join("import_archive_doctest.db");
+  ///   let (copy, imported) = Bolt::import_archive(archive.as_slice(), &path)?;
+  ///   assert_eq!(manifest, imported);
+  ///   copy.view(|tx| {
+  ///     assert_eq!(Some(b"bar".as_slice()), tx.bucket("widgets").unwrap().get("foo"));
+  ///     Ok(())
+  ///   })?;
+  ///   copy.close();
+  ///   std::fs::remove_file(&path)?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn export_archive<W: Write>(&self, w: W) -> crate::Result<ArchiveManifest> {
+    let page_size = self.info().page_size;
+    let src = self.begin_tx()?;
+    let copy = tempfile::Builder::new()
+      .prefix("bbolt-archive-")
+      .suffix(".db")
+      .tempfile()?;
+    let mut dst = BoltOptions::builder()
+      .page_size(page_size)
+      .comparators(src.tx.comparators())
+      .no_sync()
+      .build()
+      .open(copy.path())?;
+    let buckets = copy_db(&src, &mut dst, COPY_TX_MAX_SIZE)?;
+    dst.close();
+
+    let manifest = ArchiveManifest {
+      txid: src.id(),
+      created_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0),
+      page_size,
+      buckets,
+    };
+    let text = manifest.to_string();
+    let mut builder = tar::Builder::new(zstd::Encoder::new(w, 0)?);
+    append(
+      &mut builder,
+      MANIFEST_ENTRY,
+      text.as_bytes(),
+      text.len() as u64,
+      manifest.created_at,
+    )?;
+    let data = copy.reopen()?;
+    let size = data.metadata()?.len();
+    append(&mut builder, DB_ENTRY, data, size, manifest.created_at)?;
+    builder.into_inner()?.finish()?;
+    Ok(manifest)
+  }
+
+  /// Imports an archive written by [Bolt::export_archive] into a new database file at
+  /// `path`. See [BoltOptions::import_archive]
+  pub fn import_archive<R: Read, P: AsRef<Path>>(
+    r: R, path: P,
+  ) -> crate::Result<(Bolt, ArchiveManifest)> {
+    BoltOptions::default().import_archive(r, path)
+  }
+}
+
+impl BoltOptions {
+  /// Imports an archive written by [Bolt::export_archive] into a new database file at
+  /// `path` and opens it. Returns the database and the manifest of the archive.
+  ///
+  /// The database is streamed to the file, so it doesn't need to fit in memory. Fails if
+  /// `path` already exists. The file is removed again if the archive is invalid. The
+  /// options need the comparators of the exported database.
+  pub fn import_archive<R: Read, P: AsRef<Path>>(
+    self, r: R, path: P,
+  ) -> crate::Result<(Bolt, ArchiveManifest)> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create_new(true)
+      .open(path)?;
+    let result = unpack_archive(r, &mut file).and_then(|manifest| {
+      drop(file);
+      let db = self.open(path)?;
+      if db.info().page_size != manifest.page_size {
+        db.close();
+        return Err(Error::Other(anyhow!(
+          "manifest page size {} doesn't match the database",
+          manifest.page_size
+        )));
+      }
+      Ok((db, manifest))
+    });
+    if result.is_err() {
+      let _ = fs::remove_file(path);
+    }
+    result
+  }
+}
+
+/// Writes the database of the archive `r` to `file` and returns the manifest
+fn unpack_archive<R: Read>(r: R, file: &mut File) -> crate::Result<ArchiveManifest> {
+  let mut archive = tar::Archive::new(zstd::Decoder::new(r)?);
+  let mut manifest = None;
+  let mut size = None;
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    let name = entry.path()?.to_string_lossy().into_owned();
+    match name.as_str() {
+      MANIFEST_ENTRY => {
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        let text = String::from_utf8(data)
+          .map_err(|_| Error::Other(anyhow!("manifest is not valid UTF-8")))?;
+        manifest = Some(ArchiveManifest::parse(&text)?);
+      }
+      DB_ENTRY => size = Some(io::copy(&mut entry, file)?),
+      _ => {}
+    }
+  }
+  let (manifest, size) = match (manifest, size) {
+    (Some(manifest), Some(size)) => (manifest, size),
+    (None, _) => return Err(Error::Other(anyhow!("archive has no {}", MANIFEST_ENTRY))),
+    (_, None) => return Err(Error::Other(anyhow!("archive has no {}", DB_ENTRY))),
+  };
+  match (manifest.page_size as u64).checked_mul(4) {
+    Some(min) if size >= min => {}
+    _ => return Err(Error::FileSizeTooSmall(size)),
+  }
+  file.sync_all()?;
+  Ok(manifest)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::archive::{append, copy_db, ArchiveManifest, DB_ENTRY, MANIFEST_ENTRY};
+  use crate::test_support::{temp_file, TestDb};
+  use crate::{
+    Bolt, BoltOptions, BucketApi, BucketRwApi, Comparators, CursorApi, DbApi, DbRwAPI, TxApi,
+    TxCheck, TxRwRefApi,
+  };
+  use std::io::Read;
+
+  #[test]
+  fn test_archive_round_trip() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let mut db = TestDb::with_options(
+      BoltOptions::builder()
+        .comparators(comparators.clone())
+        .build(),
+    )?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..500u32 {
+        b.put(i.to_be_bytes(), "value")?;
+      }
+      b.set_sequence(42)?;
+      b.set_meta("version", "3")?;
+      let mut sub = b.create_bucket_with_comparator("sub", "reverse")?;
+      sub.put("a", "1")?;
+      sub.put("b", "2")?;
+      tx.root_put("instance-id", "a1b2")?;
+      Ok(())
+    })?;
+    // Deleted keys leave free pages the archive doesn't copy
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..400u32 {
+        b.delete(i.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+
+    let mut archive = Vec::new();
+    let manifest = db.export_archive(&mut archive)?;
+    assert_eq!(2, manifest.buckets.len());
+    assert_eq!(100, manifest.buckets[0].keys);
+    assert_eq!(
+      vec![b"widgets".to_vec(), b"sub".to_vec()],
+      manifest.buckets[1].path
+    );
+    assert_eq!(manifest, ArchiveManifest::parse(&manifest.to_string())?);
+
+    let path = temp_file()?.into_temp_path();
+    let path = path.with_extension("imported");
+    assert!(Bolt::import_archive(archive.as_slice(), &path).is_err());
+    assert!(!path.exists());
+    let (copy, imported) = BoltOptions::builder()
+      .comparators(comparators)
+      .build()
+      .import_archive(archive.as_slice(), &path)?;
+    assert_eq!(manifest, imported);
+    copy.view(|tx| {
+      assert!(tx.check().is_empty());
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(42, b.sequence());
+      assert_eq!(Some(b"3".as_slice()), b.get_meta("version"));
+      assert_eq!(Some(b"a1b2".as_slice()), tx.root_get("instance-id"));
+      assert_eq!(Some(b"value".as_slice()), b.get(450u32.to_be_bytes()));
+      assert_eq!(None, b.get(7u32.to_be_bytes()));
+      let mut c = b.bucket("sub").unwrap().cursor();
+      assert_eq!(Some((b"b".as_slice(), Some(b"2".as_slice()))), c.first());
+      Ok(())
+    })?;
+    copy.close();
+    // An existing file is never overwritten
+    assert!(Bolt::import_archive(archive.as_slice(), &path).is_err());
+    assert!(path.exists());
+    std::fs::remove_file(&path)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_archive_copy_bounded_tx() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      b.set_meta("version", "3")?;
+      b.create_bucket("sub")?.put("a", "1")?;
+      tx.root_put("instance-id", "a1b2")?;
+      Ok(())
+    })?;
+
+    let mut dst = TestDb::new()?;
+    let before = dst.begin()?.id();
+    let src = db.begin_tx()?;
+    let buckets = copy_db(&src, &mut dst, 10 * 1024)?;
+    drop(src);
+    assert_eq!(2, buckets.len());
+    assert_eq!(1000, buckets[0].keys);
+    // About 100KiB of values are copied 10KiB at a time
+    assert!(dst.begin()?.id().0 - before.0 > 10);
+    dst.view(|tx| {
+      assert!(tx.check().is_empty());
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some([0u8; 100].as_slice()), b.get(999u32.to_be_bytes()));
+      assert_eq!(Some(b"3".as_slice()), b.get_meta("version"));
+      assert_eq!(Some(b"1".as_slice()), b.bucket("sub").unwrap().get("a"));
+      assert_eq!(Some(b"a1b2".as_slice()), tx.root_get("instance-id"));
+      Ok(())
+    })
+  }
+
+  #[test]
+  fn test_archive_invalid_page_size() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    let mut archive = Vec::new();
+    let mut manifest = db.export_archive(&mut archive)?;
+
+    // Rewrite the archive with a manifest that lies about the page size
+    let mut data = Vec::new();
+    let mut entries = tar::Archive::new(zstd::Decoder::new(archive.as_slice())?);
+    for entry in entries.entries()? {
+      let mut entry = entry?;
+      if entry.path()?.to_str() == Some(DB_ENTRY) {
+        entry.read_to_end(&mut data)?;
+      }
+    }
+    let path = temp_file()?.into_temp_path();
+    let path = path.with_extension("imported");
+    for page_size in [usize::MAX, manifest.page_size * 2] {
+      manifest.page_size = page_size;
+      let mut forged = Vec::new();
+      let text = manifest.to_string();
+      let mut builder = tar::Builder::new(zstd::Encoder::new(&mut forged, 0)?);
+      append(
+        &mut builder,
+        MANIFEST_ENTRY,
+        text.as_bytes(),
+        text.len() as u64,
+        0,
+      )?;
+      append(
+        &mut builder,
+        DB_ENTRY,
+        data.as_slice(),
+        data.len() as u64,
+        0,
+      )?;
+      builder.into_inner()?.finish()?;
+      assert!(Bolt::import_archive(forged.as_slice(), &path).is_err());
+      assert!(!path.exists());
+    }
+    Ok(())
+  }
+}
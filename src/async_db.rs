@@ -0,0 +1,285 @@
+use crate::{Bolt, DbApi, DbRwAPI, Error, TxRef, TxRwRef};
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// A unit of blocking work submitted to a [BlockingExecutor]
+pub type BlockingFn = Box<dyn FnOnce() + Send + 'static>;
+
+/// BlockingExecutor runs the blocking part of the async transaction functions.
+///
+/// Implement it to run transactions on your runtime's blocking thread pool, e.g. with
+/// Tokio's `spawn_blocking`:
+///
+/// ```rust,ignore
+/// struct TokioExecutor;
+///
+/// impl BlockingExecutor for TokioExecutor {
+///   fn spawn_blocking(&self, task: BlockingFn) {
+///     tokio::task::spawn_blocking(task);
+///   }
+/// }
+/// ```
+pub trait BlockingExecutor: Send + Sync {
+  /// Runs `task` on a thread that is allowed to block
+  fn spawn_blocking(&self, task: BlockingFn);
+}
+
+/// The default executor. Runs every task on a new thread.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ThreadExecutor;
+
+impl BlockingExecutor for ThreadExecutor {
+  fn spawn_blocking(&self, task: BlockingFn) {
+    thread::spawn(task);
+  }
+}
+
+struct TaskState<T> {
+  result: Option<crate::Result<T>>,
+  waker: Option<Waker>,
+}
+
+/// Completes the [BlockingTask]. Fails the task if dropped without a result.
+struct TaskSender<T> {
+  state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> TaskSender<T> {
+  fn send(self, result: crate::Result<T>) {
+    self.complete(result);
+  }
+
+  fn complete(&self, result: crate::Result<T>) {
+    let mut state = self.state.lock();
+    if state.result.is_none() {
+      state.result = Some(result);
+    }
+    if let Some(waker) = state.waker.take() {
+      waker.wake();
+    }
+  }
+}
+
+impl<T> Drop for TaskSender<T> {
+  fn drop(&mut self) {
+    if self.state.lock().result.is_none() {
+      self.complete(Err(Error::Other(anyhow!("blocking task dropped"))));
+    }
+  }
+}
+
+/// The future returned by the async transaction functions
+pub struct BlockingTask<T> {
+  state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+  type Output = crate::Result<T>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let mut state = self.state.lock();
+    match state.result.take() {
+      Some(result) => Poll::Ready(result),
+      None => {
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+      }
+    }
+  }
+}
+
+impl Bolt {
+  fn spawn_task<T, F>(&self, f: F) -> BlockingTask<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(Bolt) -> crate::Result<T> + Send + 'static,
+  {
+    let state = Arc::new(Mutex::new(TaskState {
+      result: None,
+      waker: None,
+    }));
+    let sender = TaskSender {
+      state: state.clone(),
+    };
+    let db = self.clone();
+    let executor = self.executor();
+    executor.spawn_blocking(Box::new(move || {
+      let result = catch_unwind(AssertUnwindSafe(|| f(db)))
+        .unwrap_or_else(|_| Err(Error::Other(anyhow!("blocking task panicked"))));
+      sender.send(result);
+    }));
+    BlockingTask { state }
+  }
+
+  /// Executes a function within the context of a read-only managed transaction
+  /// on the blocking executor.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let db = Bolt::open_mem()?;
+  ///
+  ///   let update = db.update_async(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   });
+  ///   block_on(update)?;
+  ///
+  ///   let view = db.view_async(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_ref()), b.get("key"));
+  ///     Ok(())
+  ///   });
+  ///   block_on(view)?;
+  ///
+  ///   Ok(())
+  /// }
+  /// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+  /// #   use std::task::{Context, Poll, Wake, Waker};
+  /// #   struct Unpark(std::thread::Thread);
+  /// #   impl Wake for Unpark {
+  /// #     fn wake(self: std::sync::Arc<Self>) {
+  /// #       self.0.unpark();
+  /// #     }
+  /// #   }
+  /// #   let waker = Waker::from(std::sync::Arc::new(Unpark(std::thread::current())));
+  /// #   let mut cx = Context::from_waker(&waker);
+  /// #   let mut f = std::pin::pin!(f);
+  /// #   loop {
+  /// #     if let Poll::Ready(r) = f.as_mut().poll(&mut cx) {
+  /// #       return r;
+  /// #     }
+  /// #     std::thread::park();
+  /// #   }
+  /// # }
+  /// ```
+  pub fn view_async<F>(&self, f: F) -> BlockingTask<()>
+  where
+    F: Fn(TxRef) -> crate::Result<()> + Send + 'static,
+  {
+    self.spawn_task(move |db| db.view(|tx| f(tx)))
+  }
+
+  /// Executes a function within the context of a read-write managed transaction
+  /// on the blocking executor. See [DbRwAPI::update]
+  pub fn update_async<F>(&self, mut f: F) -> BlockingTask<()>
+  where
+    F: FnMut(TxRwRef) -> crate::Result<()> + Send + 'static,
+  {
+    self.spawn_task(move |mut db| db.update(|tx| f(tx)))
+  }
+
+  /// Calls a function as part of a batch on the blocking executor. See [DbRwAPI::batch]
+  pub fn batch_async<F>(&self, f: F) -> BlockingTask<()>
+  where
+    F: FnMut(&mut TxRwRef) -> crate::Result<()> + Send + Sync + Clone + 'static,
+  {
+    self.spawn_task(move |mut db| db.batch(f))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::async_db::BlockingFn;
+  use crate::test_support::TestDb;
+  use crate::{BlockingExecutor, BucketApi, BucketRwApi, Error, TxApi, TxRwRefApi};
+  use std::future::Future;
+  use std::pin::pin;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::task::{Context, Poll, Wake, Waker};
+  use std::thread;
+
+  struct Unpark(thread::Thread);
+
+  impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  fn block_on<F: Future>(f: F) -> F::Output {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut f = pin!(f);
+    loop {
+      if let Poll::Ready(r) = f.as_mut().poll(&mut cx) {
+        return r;
+      }
+      thread::park();
+    }
+  }
+
+  #[derive(Default)]
+  struct CountingExecutor(AtomicUsize);
+
+  impl BlockingExecutor for CountingExecutor {
+    fn spawn_blocking(&self, task: BlockingFn) {
+      self.0.fetch_add(1, Ordering::SeqCst);
+      thread::spawn(task);
+    }
+  }
+
+  #[test]
+  fn test_db_async() -> crate::Result<()> {
+    let db = TestDb::new()?;
+    let executor = Arc::new(CountingExecutor::default());
+    db.set_executor(executor.clone());
+
+    block_on(db.update_async(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      Ok(())
+    }))?;
+    block_on(db.batch_async(|tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.put("baz", "bat")?;
+      Ok(())
+    }))?;
+    block_on(db.view_async(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+      assert_eq!(Some(b"bat".as_slice()), b.get("baz"));
+      Ok(())
+    }))?;
+    assert_eq!(3, executor.0.load(Ordering::SeqCst));
+
+    let r = block_on(db.view_async(|_| {
+      Err(Error::InvalidDatabase {
+        meta_can_read: false,
+        meta: None,
+      })
+    }));
+    assert_eq!(
+      Some(Error::InvalidDatabase {
+        meta_can_read: false,
+        meta: None,
+      }),
+      r.err()
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_async_dropped_task() -> crate::Result<()> {
+    struct DropExecutor;
+
+    impl BlockingExecutor for DropExecutor {
+      fn spawn_blocking(&self, _task: BlockingFn) {}
+    }
+
+    let db = TestDb::new()?;
+    db.set_executor(Arc::new(DropExecutor));
+    let r = block_on(db.view_async(|_| Ok(())));
+    assert!(matches!(r, Err(Error::Other(_))));
+    Ok(())
+  }
+}
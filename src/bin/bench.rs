@@ -1,4 +1,10 @@
+// The tool works on database files, which wasm32 doesn't support
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
 use anyhow::anyhow;
+#[cfg(bench_tuning)]
+use bbolt_rs::set_linear_search_max;
 use bbolt_rs::{
   Bolt, BoltOptions, BucketApi, BucketRwApi, CursorApi, DbApi, DbRwAPI, Error, TxApi, TxRwRefApi,
 };
@@ -26,12 +32,20 @@ struct Bench {
   batch_size: u64,
   #[arg(short, long, default_value_t = 8)]
   key_size: usize,
+  /// Bytes shared by every key in front of the generated part, e.g. for composite keys
+  #[arg(long, default_value_t = 0)]
+  key_prefix: usize,
   #[arg(short, long, default_value_t = 32)]
   value_size: usize,
   #[arg(short, long, default_value_t = 0.5f64)]
   fill_percent: f64,
   #[arg(short, long)]
   mem_backend: bool,
+  /// Nodes and pages with up to this many elements are scanned linearly. 0 always binary
+  /// searches. Only with `--cfg bench_tuning`
+  #[cfg(bench_tuning)]
+  #[arg(long)]
+  linear_search_max: Option<usize>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -121,6 +135,10 @@ static BENCH_BUCKET_NAME: &str = "bench";
 
 fn main() -> bbolt_rs::Result<()> {
   let mut bench = Bench::parse();
+  #[cfg(bench_tuning)]
+  if let Some(max) = bench.linear_search_max {
+    set_linear_search_max(max);
+  }
 
   if bench.batch_size == 0 {
     bench.batch_size = bench.count;
@@ -207,6 +225,13 @@ fn run_writes(
   Ok(n_keys)
 }
 
+fn new_key(options: &Bench, k: u32) -> Vec<u8> {
+  let mut key = vec![0xa5u8; options.key_prefix + options.key_size];
+  key[options.key_prefix..].fill(0);
+  BigEndian::write_u32(&mut key[options.key_prefix..], k);
+  key
+}
+
 fn run_write_with_sources<F>(
   db: &mut Bolt, options: &Bench, mut key_source: F,
 ) -> bbolt_rs::Result<Option<Vec<NestedKey>>>
@@ -225,10 +250,8 @@ where
       let bucket = Rc::new([]);
       b.set_fill_percent(options.fill_percent);
       for _ in 0..options.batch_size {
-        let mut key = vec![0u8; options.key_size];
+        let key = new_key(options, key_source());
         let value = vec![0u8; options.value_size];
-        let k = key_source();
-        BigEndian::write_u32(&mut key, k);
         b.put(&key, &value)?;
         if let Some(keys) = n_keys.as_mut() {
           keys.push(NestedKey {
@@ -264,10 +287,8 @@ where
       let mut b = top.create_bucket_if_not_exists(&name)?;
       b.set_fill_percent(options.fill_percent);
       for _ in 0..options.batch_size {
-        let mut key = vec![0u8; options.key_size];
+        let key = new_key(options, key_source());
         let value = vec![0u8; options.value_size];
-        let k = key_source();
-        BigEndian::write_u32(&mut key, k);
         b.put(&key, &value)?;
         if let Some(keys) = n_keys.as_mut() {
           keys.push(NestedKey {
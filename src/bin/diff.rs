@@ -0,0 +1,45 @@
+// The tool works on database files, which wasm32 doesn't support
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
+use bbolt_rs::diff::{diff, DiffRecord};
+use bbolt_rs::*;
+use clap::Parser;
+
+/// Prints the keys and buckets added, removed and changed between two databases
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Diff {
+  /// the database to compare against, like a backup
+  a: String,
+  /// the database to compare
+  b: String,
+}
+
+fn display_path(path: &[&[u8]], key: &[u8]) -> String {
+  path
+    .iter()
+    .chain([&key])
+    .map(|name| format_auto(name))
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+fn main() -> Result<()> {
+  let args = Diff::parse();
+  let a = Bolt::open_ro(&args.a)?;
+  let b = Bolt::open_ro(&args.b)?;
+  a.view(|ta| {
+    b.view(|tb| {
+      for record in diff(&ta, &tb) {
+        let kind = match record {
+          DiffRecord::Added(_) => '+',
+          DiffRecord::Removed(_) => '-',
+          DiffRecord::Changed { .. } => '~',
+        };
+        println!("{} {}", kind, display_path(record.path(), record.key()));
+      }
+      Ok(())
+    })
+  })
+}
@@ -0,0 +1,37 @@
+// The tool works on database files, which wasm32 doesn't support
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
+use bbolt_rs::replication::restore_to;
+use bbolt_rs::*;
+use clap::Parser;
+
+/// Restores a database as of a transaction from a base snapshot and replication logs
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Restore {
+  /// the base snapshot, like a backup
+  base: String,
+  /// the database file to create
+  dst: String,
+  /// the txid to restore to. Defaults to the last one in the logs
+  #[arg(long)]
+  txid: Option<u64>,
+  /// the replication logs, oldest first
+  #[arg(required = true)]
+  logs: Vec<String>,
+}
+
+fn main() -> Result<()> {
+  let args = Restore::parse();
+  let txid = TxId(args.txid.unwrap_or(u64::MAX));
+  let restored = restore_to(
+    &args.base,
+    &args.logs,
+    txid,
+    &args.dst,
+    BoltOptions::default(),
+  )?;
+  println!("restored {} to txid {}", args.dst, restored);
+  Ok(())
+}
@@ -1,3 +1,7 @@
+// The tool works on database files, which wasm32 doesn't support
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
 use bbolt_rs::*;
 use clap::Parser;
 
@@ -0,0 +1,26 @@
+// The tool works on database files, which wasm32 doesn't support
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
+use bbolt_rs::*;
+use clap::Parser;
+use std::process::ExitCode;
+
+/// Verifies a backup of a database and prints a report. Exits with 1 if problems were found
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct VerifyBackup {
+  /// the backup to verify
+  path: String,
+}
+
+fn main() -> Result<ExitCode> {
+  let args = VerifyBackup::parse();
+  let report = Bolt::verify_backup(&args.path)?;
+  print!("{}", report);
+  if report.is_valid() {
+    Ok(ExitCode::SUCCESS)
+  } else {
+    Ok(ExitCode::FAILURE)
+  }
+}
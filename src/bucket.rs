@@ -7,9 +7,19 @@ use crate::common::page::{
 use crate::common::tree::{
   MappedBranchPage, MappedLeafPage, TreePage, BRANCH_PAGE_ELEMENT_SIZE, LEAF_PAGE_ELEMENT_SIZE,
 };
-use crate::common::{BVec, HashMap, PgId, SplitRef, ZERO_PGID};
-use crate::cursor::{CursorIApi, CursorImpl, CursorRwIApi, CursorRwImpl, InnerCursor, PageNode};
+use crate::common::{BVec, HashMap, MapHasher, PgId, SplitRef, ZERO_PGID};
+use crate::comparator::{
+  bytewise, child_path, is_reserved, meta_key, path_names, top_level_name, KeyComparator,
+  COMPARATOR_META_KEY, META_BUCKET, SYSTEM_BUCKET,
+};
+use crate::cursor::{
+  CursorIApi, CursorImpl, CursorRwIApi, CursorRwImpl, InnerCursor, KeyValueFlags, PageNode,
+};
 use crate::node::NodeRwCell;
+use crate::quota::{BucketQuota, QuotaRecord, QuotaUsage, QUOTA_META_KEY};
+#[cfg(feature = "replication")]
+use crate::replication::Change;
+use crate::savepoint::Undo;
 use crate::tx::{TxCell, TxIApi, TxRwIApi};
 use crate::Error::{
   BucketExists, BucketNameRequired, BucketNotFound, IncompatibleValue, KeyRequired, KeyTooLarge,
@@ -18,14 +28,23 @@ use crate::Error::{
 use crate::{CursorRwApi, Error};
 use bumpalo::Bump;
 use bytemuck::{Pod, Zeroable};
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
 use getset::CopyGetters;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::alloc::Layout;
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::marker::PhantomData;
-use std::ops::{AddAssign, Deref, DerefMut};
+use std::ops::{AddAssign, Deref, DerefMut, SubAssign};
 use std::ptr::slice_from_raw_parts_mut;
 use std::slice::{from_raw_parts, from_raw_parts_mut};
 use std::{mem, ptr};
 
+/// The metadata entry recording that a bucket is kept paged. See [BucketRwApi::force_materialize]
+pub(crate) const PAGED_META_KEY: &[u8] = b"bbolt.paged";
+
 /// Read-only Bucket API
 pub trait BucketApi<'tx>
 where
@@ -139,6 +158,35 @@ where
   /// ```
   fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>>;
 
+  /// Retrieves a nested bucket by name like [BucketApi::bucket], but returns an error
+  /// instead of panicking if the bucket can't be opened, e.g. [Error::UnknownComparator]
+  /// if its recorded comparator isn't registered.
+  /// Returns None if the bucket does not exist.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.create_bucket_if_not_exists("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert!(b.try_bucket("sub")?.is_some());
+  ///     assert!(b.try_bucket("no bucket")?.is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>>;
+
   /// Retrieves the value for a key in the bucket.
   ///
   /// Returns None if the key does not exist or if the key is a nested bucket.
@@ -169,7 +217,11 @@ where
   /// ```
   fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<&[u8]>;
 
-  /// Returns the current integer for the bucket without incrementing it.
+  /// Retrieves the value for a key in the bucket.
+  ///
+  /// Returns None if the key does not exist or if the key is a nested bucket.
+  /// Unlike [BucketApi::get] this returns [Error::Corrupted] instead of panicking if a
+  /// corrupted page is found while searching for the key.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -185,21 +237,22 @@ where
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let seq = b.sequence();
-  ///     assert_eq!(0, seq);
+  ///     let get = b.try_get("key")?;
+  ///     assert_eq!(Some(b"value".as_slice()), get);
+  ///     let get = b.try_get("no value")?;
+  ///     assert_eq!(None, get);
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn sequence(&self) -> u64;
+  fn try_get<T: AsRef<[u8]>>(&self, key: T) -> crate::Result<Option<&[u8]>>;
 
-  /// Executes a function for each key/value pair in a bucket.
-  /// Because this uses a [`crate::CursorApi`], the iteration over keys is in lexicographical order.
+  /// Returns whether a key exists in the bucket.
   ///
-  /// If the provided function returns an error then the iteration is stopped and
-  /// the error is returned to the caller.
+  /// Returns false if the key is a nested bucket. The value isn't read, so this is cheap
+  /// even for values stored in overflow pages.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -209,33 +262,26 @@ where
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.put("key1", "value1")?;
-  ///     b.put("key2", "value2")?;
-  ///     b.put("key3", "value3")?;
+  ///     b.put("key", "value")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     b.for_each(|k,v| {
-  ///      println!("{:?}, {:?}", k, v);
-  ///      Ok(())
-  ///     })?;
+  ///     assert_eq!(true, b.contains_key("key"));
+  ///     assert_eq!(false, b.contains_key("no value"));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
-    &self, f: F,
-  ) -> crate::Result<()>;
+  fn contains_key<T: AsRef<[u8]>>(&self, key: T) -> bool;
 
-  /// Executes a function for each bucket in a bucket.
-  /// Because this function uses a [`crate::CursorApi`], the iteration over keys is in lexicographical order.
+  /// Returns the length of the value for a key in the bucket.
   ///
-  /// If the provided function returns an error then the iteration is stopped and
-  /// the error is returned to the caller.
+  /// Returns None if the key does not exist or if the key is a nested bucket. The value
+  /// isn't read, so this is cheap even for values stored in overflow pages.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -245,27 +291,28 @@ where
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub1")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub2")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub3")?;
+  ///     b.put("key", "value")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     b.for_each_bucket(|k| {
-  ///      println!("{:?}", k);
-  ///      Ok(())
-  ///     })?;
+  ///     assert_eq!(Some(5), b.value_len("key"));
+  ///     assert_eq!(None, b.value_len("no value"));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()>;
+  fn value_len<T: AsRef<[u8]>>(&self, key: T) -> Option<usize>;
 
-  /// Returns stats on a bucket.
+  /// Retrieves the values for several keys in the bucket. The values are returned in the
+  /// order of `keys`.
+  ///
+  /// The keys are looked up in sorted order, so keys near each other share the pages
+  /// from the root down instead of each searching the whole tree. A value is None if the key
+  /// does not exist or if the key is a nested bucket.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -277,28 +324,29 @@ where
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
   ///     b.put("key1", "value1")?;
   ///     b.put("key2", "value2")?;
-  ///     b.put("key3", "value3")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let stats = b.stats();
-  ///     assert_eq!(3, stats.key_n());
+  ///     let values = b.get_many(&["key2", "no value", "key1"]);
+  ///     assert_eq!(
+  ///       vec![Some(b"value2".as_slice()), None, Some(b"value1".as_slice())],
+  ///       values
+  ///     );
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn stats(&self) -> BucketStats;
-}
+  fn get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> Vec<Option<&[u8]>>;
 
-/// RW Bucket API
-pub trait BucketRwApi<'tx>: BucketApi<'tx> {
-  /// Retrieves a nested mutable bucket by name.
+  /// Retrieves the values for several keys in the bucket. The values are returned in the
+  /// order of `keys`.
   ///
-  /// Returns None if the bucket does not exist.
+  /// Unlike [BucketApi::get_many] this returns [Error::Corrupted] instead of panicking if a
+  /// corrupted page is found while searching for a key.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -308,25 +356,30 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
   ///     Ok(())
   ///   })?;
   ///
-  ///   db.update(|mut tx | {
-  ///     let mut b = tx.bucket_mut("test").unwrap();
-  ///     let mut sub = b.bucket_mut("sub").unwrap();
-  ///     sub.put("key1", "value1")?;
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let values = b.try_get_many(&["key2", "no value", "key1"])?;
+  ///     assert_eq!(
+  ///       vec![Some(b"value2".as_slice()), None, Some(b"value1".as_slice())],
+  ///       values
+  ///     );
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<impl BucketRwApi<'tx>>;
+  fn try_get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> crate::Result<Vec<Option<&[u8]>>>;
 
-  /// Creates a new bucket at the given key and returns the new bucket.
+  /// Retrieves the value for a key in the bucket as a [bytes::Bytes].
   ///
-  /// Returns an error if the key already exists, if the bucket name is blank, or if the bucket name is too long.
+  /// The value is copied, so the result outlives the transaction and can be cloned cheaply
+  /// and sent to other threads. Returns None under the same conditions as [BucketApi::get].
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -335,25 +388,49 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///   let mut db = Bolt::open_mem()?;
   ///
   ///   db.update(|mut tx| {
-  ///     let mut b = tx.create_bucket("test")?;
-  ///     let _ = b.create_bucket("sub")?;
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let tx = db.begin()?;
+  ///   let value = tx.bucket("test").unwrap().get_bytes("key");
+  ///   drop(tx);
+  ///   assert_eq!(Some(bytes::Bytes::from_static(b"value")), value);
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(feature = "bytes")]
+  fn get_bytes<T: AsRef<[u8]>>(&self, key: T) -> Option<Bytes>;
+
+  /// Returns the current integer for the bucket without incrementing it.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let _ = b.bucket("sub").unwrap();
+  ///     let seq = b.sequence();
+  ///     assert_eq!(0, seq);
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn create_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<impl BucketRwApi<'tx>>;
+  fn sequence(&self) -> u64;
 
-  /// CreateBucketIfNotExists creates a new bucket if it doesn't already exist and returns a reference to it.
-  ///
-  /// Returns an error if the bucket name is blank, or if the bucket name is too long.
+  /// Retrieves the metadata entry `key` of the bucket. See [BucketRwApi::set_meta]
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -363,24 +440,31 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub")?;
+  ///     b.set_meta("schema-version", 3u32.to_be_bytes())?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let _ = b.bucket("sub").unwrap();
+  ///     assert_eq!(Some(3u32.to_be_bytes().as_slice()), b.get_meta("schema-version"));
+  ///     assert_eq!(None, b.get_meta("missing"));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
-    &mut self, key: T,
-  ) -> crate::Result<impl BucketRwApi<'tx>>;
+  fn get_meta<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]>;
 
-  /// Cursor creates a cursor associated with the bucket.
+  /// Executes a function for each key/value pair in a bucket.
+  /// Because this uses a [`crate::CursorApi`], the iteration over keys is in lexicographical order.
+  ///
+  /// If the provided function returns an error then the iteration is stopped and
+  /// the error is returned to the caller.
+  ///
+  /// The function may read the transaction, including this bucket, e.g. with
+  /// [BucketApi::get], [BucketApi::bucket] or [BucketApi::cursor]. If it deletes this bucket
+  /// through another handle the iteration stops with [crate::Error::BucketNotFound].
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -396,65 +480,73 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///     Ok(())
   ///   })?;
   ///
-  ///   db.update(|mut tx| {
-  ///     let mut b = tx.bucket_mut("test").unwrap();
-  ///     let mut c = b.cursor_mut();
-  ///     c.seek("key2");
-  ///     c.delete()?;
-  ///     Ok(())
-  ///   })?;
-  ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let mut c = b.cursor();
-  ///     let seek = c.seek("key2");
-  ///     assert_eq!(Some((b"key3".as_slice(), Some(b"value3".as_slice()))), seek);
+  ///     b.for_each(|k,v| {
+  ///      println!("{:?}, {:?}", k, v);
+  ///      Ok(())
+  ///     })?;
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn cursor_mut(&self) -> impl CursorRwApi<'tx>;
+  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()>;
 
-  /// Deletes a bucket at the given key.
+  /// Executes a function for each key/value pair in a bucket in parallel.
+  /// The bucket's leaf pages are spread across the rayon thread pool, so the
+  /// iteration order is unspecified.
   ///
-  /// Returns an error if the bucket does not exist, or if the key represents a non-bucket value.
+  /// Nested buckets are reported with a `None` value, just like [BucketApi::for_each].
+  /// If the provided function returns an error then the remaining leaf pages are
+  /// skipped and one of the errors is returned to the caller.
   ///
   /// ```rust
   /// use bbolt_rs::*;
+  /// use std::sync::atomic::{AtomicUsize, Ordering};
   ///
   /// fn main() -> Result<()> {
   ///   let mut db = Bolt::open_mem()?;
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     let _ = b.create_bucket_if_not_exists("sub")?;
-  ///     Ok(())
-  ///   })?;
-  ///
-  ///   db.update(|mut tx| {
-  ///     let mut b = tx.bucket_mut("test").unwrap();
-  ///     b.delete_bucket("sub")?;
+  ///     for i in 0..1000u32 {
+  ///       b.put(i.to_be_bytes(), "value")?;
+  ///     }
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(false, b.bucket("sub").is_some());
+  ///     let count = AtomicUsize::new(0);
+  ///     b.par_for_each(|_, _| {
+  ///       count.fetch_add(1, Ordering::Relaxed);
+  ///       Ok(())
+  ///     })?;
+  ///     assert_eq!(1000, count.load(Ordering::Relaxed));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn delete_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+  #[cfg(feature = "rayon")]
+  fn par_for_each<F: Fn(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()> + Send + Sync>(
+    &self, f: F,
+  ) -> crate::Result<()>;
 
-  /// Sets the value for a key in the bucket.
+  /// Executes a function for each bucket in a bucket.
+  /// Because this function uses a [`crate::CursorApi`], the iteration over keys is in lexicographical order.
   ///
-  /// If the key exist then its previous value will be overwritten.
+  /// If the provided function returns an error then the iteration is stopped and
+  /// the error is returned to the caller.
   ///
-  /// Returns an error if the key is blank, if the key is too large, or if the value is too large.
+  /// The function may read the transaction, including this bucket and the nested bucket it
+  /// was called for. If it deletes this bucket through another handle the iteration stops
+  /// with [crate::Error::BucketNotFound].
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -464,35 +556,27 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.put("key1", "value1")?;
-  ///     b.put("key2", "value2")?;
-  ///     b.put("key3", "value3")?;
-  ///     Ok(())
-  ///   })?;
-  ///
-  ///   db.update(|mut tx| {
-  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.put("key2", "new value")?;
+  ///     let _ = b.create_bucket_if_not_exists("sub1")?;
+  ///     let _ = b.create_bucket_if_not_exists("sub2")?;
+  ///     let _ = b.create_bucket_if_not_exists("sub3")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let get = b.get("key1");
-  ///     assert_eq!(Some(b"value1".as_slice()), get);
-  ///     let get = b.get("key2");
-  ///     assert_eq!(Some(b"new value".as_slice()), get);
+  ///     b.for_each_bucket(|k| {
+  ///      println!("{:?}", k);
+  ///      Ok(())
+  ///     })?;
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, data: U) -> crate::Result<()>;
+  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()>;
 
-  /// Removes a key from the bucket.
-  ///
-  /// If the key does not exist then nothing is done.
+  /// Returns stats on a bucket.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -508,27 +592,54 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///     Ok(())
   ///   })?;
   ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let stats = b.stats();
+  ///     assert_eq!(3, stats.key_n());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn stats(&self) -> BucketStats;
+
+  /// Returns the number of keys in the bucket, including nested bucket keys
+  /// but not the contents of the nested buckets themselves.
+  ///
+  /// Unlike [BucketApi::stats] or iterating with a cursor this only reads the
+  /// element counts of the leaf pages and never materializes keys or values.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.delete("key2")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     b.create_bucket("sub")?;
+  ///     assert_eq!(3, b.key_n());
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     let get = b.get("key1");
-  ///     assert_eq!(Some(b"value1".as_slice()), get);
-  ///     let get = b.get("key2");
-  ///     assert_eq!(false, get.is_some());
+  ///     assert_eq!(3, b.key_n());
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+  fn key_n(&self) -> usize;
 
-  /// Updates the sequence number for the bucket.
+  /// Returns the approximate number of bytes used by the bucket's own pages.
+  ///
+  /// Committed pages are counted at their full allocated size, uncommitted nodes and
+  /// inline buckets at their serialized size. Nested buckets are not included.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -538,22 +649,22 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.set_sequence(42)?;
+  ///     b.put("key1", "value1")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(42, b.sequence());
+  ///     assert!(b.approx_size_bytes() > 0);
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn set_sequence(&mut self, v: u64) -> crate::Result<()>;
+  fn approx_size_bytes(&self) -> usize;
 
-  /// Returns the auto-incremented integer for the bucket.
+  /// Returns the number of levels in the bucket's B+tree. Nested buckets are not included.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -563,17 +674,27 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.set_sequence(42)?;
-  ///     assert_eq!(43, b.next_sequence()?);
+  ///     b.put("key1", "value1")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(1, b.depth());
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn next_sequence(&mut self) -> crate::Result<u64>;
+  fn depth(&self) -> usize;
+}
 
-  /// Set the fill percent of the bucket
+/// RW Bucket API
+pub trait BucketRwApi<'tx>: BucketApi<'tx> {
+  /// Retrieves a nested mutable bucket by name.
+  ///
+  /// Returns None if the bucket does not exist.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -583,1425 +704,3871 @@ pub trait BucketRwApi<'tx>: BucketApi<'tx> {
   ///
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     b.set_fill_percent(0.90);
+  ///     let _ = b.create_bucket_if_not_exists("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx | {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     let mut sub = b.bucket_mut("sub").unwrap();
+  ///     sub.put("key1", "value1")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn set_fill_percent(&mut self, fill_percent: f64);
-}
+  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<impl BucketRwApi<'tx>>;
+
+  /// Creates a new bucket at the given key and returns the new bucket.
+  ///
+  /// Returns an error if the key already exists, if the bucket name is blank, or if the bucket name is too long.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     let _ = b.create_bucket("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let _ = b.bucket("sub").unwrap();
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<impl BucketRwApi<'tx>>;
+
+  /// CreateBucketIfNotExists creates a new bucket if it doesn't already exist and returns a reference to it.
+  ///
+  /// Returns an error if the bucket name is blank, or if the bucket name is too long.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     let _ = b.create_bucket_if_not_exists("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let _ = b.bucket("sub").unwrap();
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
+    &mut self, key: T,
+  ) -> crate::Result<impl BucketRwApi<'tx>>;
+
+  /// Creates a new bucket at the given key whose keys are ordered by the comparator
+  /// registered as `comparator` in [BoltOptions](crate::BoltOptions). The comparator's
+  /// name is recorded in the database and the bucket uses it from then on.
+  ///
+  /// Returns an error if the comparator is not registered or for the same reasons
+  /// as [BucketRwApi::create_bucket].
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+  ///   let mut db = BoltOptions::builder().comparators(comparators).build().open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     let mut sub = b.create_bucket_with_comparator("sub", "reverse")?;
+  ///     sub.put("a", "1")?;
+  ///     sub.put("b", "2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let sub = b.bucket("sub").unwrap();
+  ///     let mut c = sub.cursor();
+  ///     assert_eq!(Some((b"b".as_slice(), Some(b"2".as_slice()))), c.first());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket_with_comparator<T: AsRef<[u8]>>(
+    &mut self, key: T, comparator: &str,
+  ) -> crate::Result<impl BucketRwApi<'tx>>;
+
+  /// Cursor creates a cursor associated with the bucket.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     b.put("key3", "value3")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     let mut c = b.cursor_mut();
+  ///     c.seek("key2");
+  ///     c.delete()?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let mut c = b.cursor();
+  ///     let seek = c.seek("key2");
+  ///     assert_eq!(Some((b"key3".as_slice(), Some(b"value3".as_slice()))), seek);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn cursor_mut(&self) -> impl CursorRwApi<'tx>;
+
+  /// Deletes a bucket at the given key.
+  ///
+  /// Returns an error if the bucket does not exist, or if the key represents a non-bucket value.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     let _ = b.create_bucket_if_not_exists("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     b.delete_bucket("sub")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(false, b.bucket("sub").is_some());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn delete_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+
+  /// Sets the value for a key in the bucket.
+  ///
+  /// If the key exist then its previous value will be overwritten.
+  ///
+  /// Returns an error if the key is blank, if the key is too large, or if the value is too large.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     b.put("key3", "value3")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key2", "new value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let get = b.get("key1");
+  ///     assert_eq!(Some(b"value1".as_slice()), get);
+  ///     let get = b.get("key2");
+  ///     assert_eq!(Some(b"new value".as_slice()), get);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, data: U) -> crate::Result<()>;
+
+  /// Sets the values of several keys in the bucket.
+  ///
+  /// The pairs are sorted by key and written with a single cursor that only searches the
+  /// part of the tree after the previous key, which is faster than calling
+  /// [BucketRwApi::put] for every pair. If a key appears more than once the last value
+  /// wins. Returns the same errors as [BucketRwApi::put]. Pairs written before an error
+  /// stay written.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put_many([("key3", "value3"), ("key1", "value1"), ("key2", "value2")])?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value1".as_slice()), b.get("key1"));
+  ///     assert_eq!(Some(b"value3".as_slice()), b.get("key3"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn put_many<I, K, V>(&mut self, pairs: I) -> crate::Result<()>
+  where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>;
+
+  /// Sets the value for a key in the bucket without copying the value.
+  ///
+  /// Works like [BucketRwApi::put], but the transaction holds on to `data` until it
+  /// closes instead of copying it into the transaction's memory. Accepts anything that
+  /// converts into a [bytes::Bytes] without copying, e.g. a frozen [bytes::BytesMut].
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use bytes::{BufMut, Bytes, BytesMut};
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   let mut buf = BytesMut::new();
+  ///   buf.put_slice(b"built value");
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put_bytes("key1", Bytes::from_static(b"static value"))?;
+  ///     b.put_bytes("key2", buf.clone())?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"static value".as_slice()), b.get("key1"));
+  ///     assert_eq!(Some(b"built value".as_slice()), b.get("key2"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(feature = "bytes")]
+  fn put_bytes<T: AsRef<[u8]>, U: Into<Bytes>>(&mut self, key: T, data: U) -> crate::Result<()>;
+
+  /// Removes a key from the bucket.
+  ///
+  /// If the key does not exist then nothing is done.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     b.put("key3", "value3")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.delete("key2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let get = b.get("key1");
+  ///     assert_eq!(Some(b"value1".as_slice()), get);
+  ///     let get = b.get("key2");
+  ///     assert_eq!(false, get.is_some());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+
+  /// Deletes all keys and sub-buckets of the bucket. The bucket itself and its sequence are
+  /// kept. Unmodified sub-buckets are freed page by page instead of key by key.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.create_bucket("sub")?;
+  ///     b.set_sequence(42)?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     b.clear()?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(None, b.get("key1"));
+  ///     assert!(b.bucket("sub").is_none());
+  ///     assert_eq!(42, b.sequence());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn clear(&mut self) -> crate::Result<()>;
+
+  /// Updates the sequence number for the bucket.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.set_sequence(42)?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(42, b.sequence());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn set_sequence(&mut self, v: u64) -> crate::Result<()>;
+
+  /// Returns the auto-incremented integer for the bucket.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.set_sequence(42)?;
+  ///     assert_eq!(43, b.next_sequence()?);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn next_sequence(&mut self) -> crate::Result<u64>;
+
+  /// Sets the metadata entry `key` of the bucket, e.g. a schema version.
+  ///
+  /// Metadata is kept apart from the keys of the bucket, with the database's own records, and is
+  /// deleted with the bucket.
+  /// Returns an error if the key is blank, if the key or the value is too large, or if the key
+  /// starts with the [RESERVED_PREFIX](crate::RESERVED_PREFIX).
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.set_meta("schema-version", "2")?;
+  ///     assert_eq!(None, b.get("schema-version"));
+  ///     b.delete_meta("schema-version")?;
+  ///     assert_eq!(None, b.get_meta("schema-version"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn set_meta<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()>;
+
+  /// Removes the metadata entry `key` of the bucket. See [BucketRwApi::set_meta]
+  fn delete_meta<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+
+  /// Limits the size of the top-level bucket and its nested buckets, or removes the limit
+  /// if `quota` is None. Puts that would grow the bucket beyond the quota fail with
+  /// [Error::QuotaExceeded](crate::Error::QuotaExceeded). Deletes always succeed, so a
+  /// bucket can be shrunk below a quota lowered under its current size.
+  ///
+  /// The quota is kept in the bucket's metadata with the counted usage, which is computed
+  /// when the quota is set and updated by every change afterward. [BucketApi::stats]
+  /// reports both. Returns [Error::IncompatibleValue](crate::Error::IncompatibleValue) for
+  /// nested and reserved buckets.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("tenant-1")?;
+  ///     b.set_quota(Some(BucketQuota {
+  ///       max_bytes: Some(1024),
+  ///       max_keys: None,
+  ///     }))?;
+  ///     b.put("small", "value")?;
+  ///     assert_eq!(Err(Error::QuotaExceeded), b.put("large", [0u8; 2048]));
+  ///     assert_eq!(Some(10), b.stats().quota_usage().map(|usage| usage.bytes));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn set_quota(&mut self, quota: Option<BucketQuota>) -> crate::Result<()>;
+
+  /// Writes the bucket to its own pages on commit, even if it's small enough to be stored
+  /// inline in its parent. Inline buckets are rewritten with their parent's page, so keeping
+  /// a frequently updated bucket paged can make commits smaller.
+  ///
+  /// The choice is recorded in the database, so later transactions keep the bucket paged
+  /// until [BucketRwApi::try_inline] is called. [BucketStats::is_inline] reports the current
+  /// representation.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("widgets")?;
+  ///     b.put("foo", "bar")?;
+  ///     b.force_materialize()?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert!(!tx.bucket("widgets").unwrap().stats().is_inline());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn force_materialize(&mut self) -> crate::Result<()>;
+
+  /// Stores the bucket inline in its parent on commit if it's small enough, a single leaf
+  /// without nested buckets of at most a quarter page. Returns whether the bucket qualifies
+  /// at the time of the call. Changes made afterward in the transaction are taken into
+  /// account on commit. Reverts [BucketRwApi::force_materialize] either way.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("widgets")?;
+  ///     b.put("foo", "bar")?;
+  ///     b.force_materialize()?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     assert!(tx.bucket_mut("widgets").unwrap().try_inline()?);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert!(tx.bucket("widgets").unwrap().stats().is_inline());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn try_inline(&mut self) -> crate::Result<bool>;
+
+  /// Set the fill percent of the bucket
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.set_fill_percent(0.90);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn set_fill_percent(&mut self, fill_percent: f64);
+
+  /// Returns a read-only view of the bucket as it was when the transaction started. The
+  /// transaction's own changes to the bucket are not visible in the view.
+  ///
+  /// Returns None if the bucket was created in this transaction.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "before")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     b.put("key", "after")?;
+  ///     b.put("new", "value")?;
+  ///
+  ///     let before = b.snapshot().unwrap();
+  ///     assert_eq!(Some(b"before".as_slice()), before.get("key"));
+  ///     assert_eq!(None, before.get("new"));
+  ///     assert_eq!(Some(b"after".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn snapshot(&self) -> Option<BucketImpl<'tx>>;
+}
+
+/// Read-only Bucket
+pub struct BucketImpl<'tx> {
+  b: BucketWrapper<'tx>,
+}
+pub enum BucketWrapper<'tx> {
+  RW(BucketCell<'tx>),
+}
+
+impl<'tx> From<BucketCell<'tx>> for BucketImpl<'tx> {
+  fn from(value: BucketCell<'tx>) -> Self {
+    BucketImpl {
+      b: BucketWrapper::RW(value),
+    }
+  }
+}
+
+impl<'tx> BucketApi<'tx> for BucketImpl<'tx> {
+  fn root(&self) -> PgId {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.root(),
+    }
+  }
+
+  fn writable(&self) -> bool {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.is_writeable(),
+    }
+  }
+
+  fn cursor(&self) -> CursorImpl<'tx> {
+    match &self.b {
+      BucketWrapper::RW(rw) => InnerCursor::new(*rw, rw.tx().bump()).into(),
+    }
+  }
+
+  fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_bucket(name.as_ref()).map(BucketImpl::from),
+    }
+  }
+
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    match &self.b {
+      BucketWrapper::RW(rw) => Ok(rw.api_try_bucket(name.as_ref())?.map(BucketImpl::from)),
+    }
+  }
+
+  fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<&[u8]> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_get(key.as_ref()),
+    }
+  }
+
+  fn try_get<T: AsRef<[u8]>>(&self, key: T) -> crate::Result<Option<&[u8]>> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_try_get(key.as_ref()),
+    }
+  }
+
+  fn contains_key<T: AsRef<[u8]>>(&self, key: T) -> bool {
+    self.value_len(key).is_some()
+  }
+
+  fn value_len<T: AsRef<[u8]>>(&self, key: T) -> Option<usize> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_value_len(key.as_ref()),
+    }
+  }
+
+  fn get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> Vec<Option<&[u8]>> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_get_many(keys),
+    }
+  }
+
+  fn try_get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> crate::Result<Vec<Option<&[u8]>>> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_try_get_many(keys),
+    }
+  }
+
+  #[cfg(feature = "bytes")]
+  fn get_bytes<T: AsRef<[u8]>>(&self, key: T) -> Option<Bytes> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_get(key.as_ref()).map(Bytes::copy_from_slice),
+    }
+  }
+
+  fn sequence(&self) -> u64 {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_sequence(),
+    }
+  }
+
+  fn get_meta<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_get_meta(key.as_ref()),
+    }
+  }
+
+  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_for_each(f),
+    }
+  }
+
+  #[cfg(feature = "rayon")]
+  fn par_for_each<F: Fn(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()> + Send + Sync>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_par_for_each(f),
+    }
+  }
+
+  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()> {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_for_each_bucket(f),
+    }
+  }
+
+  fn stats(&self) -> BucketStats {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_stats(),
+    }
+  }
+
+  fn key_n(&self) -> usize {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_key_n(),
+    }
+  }
+
+  fn approx_size_bytes(&self) -> usize {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_approx_size_bytes(),
+    }
+  }
+
+  fn depth(&self) -> usize {
+    match &self.b {
+      BucketWrapper::RW(rw) => rw.api_depth(),
+    }
+  }
+}
+
+/// Read/Write Bucket
+pub struct BucketRwImpl<'tx> {
+  b: BucketCell<'tx>,
+}
+
+impl<'tx> From<BucketCell<'tx>> for BucketRwImpl<'tx> {
+  fn from(value: BucketCell<'tx>) -> Self {
+    BucketRwImpl { b: value }
+  }
+}
+
+impl<'tx> BucketApi<'tx> for BucketRwImpl<'tx> {
+  fn root(&self) -> PgId {
+    self.b.root()
+  }
+
+  fn writable(&self) -> bool {
+    self.b.is_writeable()
+  }
+
+  fn cursor(&self) -> CursorImpl<'tx> {
+    InnerCursor::new(self.b, self.b.tx().bump()).into()
+  }
+
+  fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>> {
+    self.b.api_bucket(name.as_ref()).map(BucketImpl::from)
+  }
+
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    Ok(self.b.api_try_bucket(name.as_ref())?.map(BucketImpl::from))
+  }
+
+  fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<&[u8]> {
+    self.b.api_get(key.as_ref())
+  }
+
+  fn try_get<T: AsRef<[u8]>>(&self, key: T) -> crate::Result<Option<&[u8]>> {
+    self.b.api_try_get(key.as_ref())
+  }
+
+  fn contains_key<T: AsRef<[u8]>>(&self, key: T) -> bool {
+    self.value_len(key).is_some()
+  }
+
+  fn value_len<T: AsRef<[u8]>>(&self, key: T) -> Option<usize> {
+    self.b.api_value_len(key.as_ref())
+  }
+
+  fn get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> Vec<Option<&[u8]>> {
+    self.b.api_get_many(keys)
+  }
+
+  fn try_get_many<T: AsRef<[u8]>>(&self, keys: &[T]) -> crate::Result<Vec<Option<&[u8]>>> {
+    self.b.api_try_get_many(keys)
+  }
+
+  #[cfg(feature = "bytes")]
+  fn get_bytes<T: AsRef<[u8]>>(&self, key: T) -> Option<Bytes> {
+    self.b.api_get(key.as_ref()).map(Bytes::copy_from_slice)
+  }
+
+  fn sequence(&self) -> u64 {
+    self.b.api_sequence()
+  }
+
+  fn get_meta<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    self.b.api_get_meta(key.as_ref())
+  }
+
+  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.b.api_for_each(f)
+  }
+
+  #[cfg(feature = "rayon")]
+  fn par_for_each<F: Fn(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()> + Send + Sync>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.b.api_par_for_each(f)
+  }
+
+  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()> {
+    self.b.api_for_each_bucket(f)
+  }
+
+  fn stats(&self) -> BucketStats {
+    self.b.api_stats()
+  }
+
+  fn key_n(&self) -> usize {
+    self.b.api_key_n()
+  }
+
+  fn approx_size_bytes(&self) -> usize {
+    self.b.api_approx_size_bytes()
+  }
+
+  fn depth(&self) -> usize {
+    self.b.api_depth()
+  }
+}
+
+impl<'tx> BucketRwApi<'tx> for BucketRwImpl<'tx> {
+  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<impl BucketRwApi<'tx>> {
+    self.b.api_bucket(name.as_ref()).map(BucketRwImpl::from)
+  }
+
+  fn create_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<impl BucketRwApi<'tx>> {
+    self
+      .b
+      .api_create_bucket(key.as_ref())
+      .map(BucketRwImpl::from)
+  }
+
+  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
+    &mut self, key: T,
+  ) -> crate::Result<impl BucketRwApi<'tx>> {
+    self
+      .b
+      .api_create_bucket_if_not_exists(key.as_ref())
+      .map(BucketRwImpl::from)
+  }
+
+  fn create_bucket_with_comparator<T: AsRef<[u8]>>(
+    &mut self, key: T, comparator: &str,
+  ) -> crate::Result<impl BucketRwApi<'tx>> {
+    self
+      .b
+      .api_create_bucket_with_comparator(key.as_ref(), comparator)
+      .map(BucketRwImpl::from)
+  }
+
+  fn cursor_mut(&self) -> impl CursorRwApi<'tx> {
+    CursorRwImpl::new(InnerCursor::new(self.b, self.b.tx().bump()))
+  }
+
+  fn delete_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
+    self.b.api_delete_bucket(key.as_ref())
+  }
+
+  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, data: U) -> crate::Result<()> {
+    self.b.api_put(key.as_ref(), data.as_ref())
+  }
+
+  fn put_many<I, K, V>(&mut self, pairs: I) -> crate::Result<()>
+  where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    let pairs = pairs.into_iter().collect::<Vec<_>>();
+    let pairs = pairs
+      .iter()
+      .map(|(k, v)| (k.as_ref(), v.as_ref()))
+      .collect::<Vec<_>>();
+    self.b.api_put_many(&pairs)
+  }
+
+  #[cfg(feature = "bytes")]
+  fn put_bytes<T: AsRef<[u8]>, U: Into<Bytes>>(&mut self, key: T, data: U) -> crate::Result<()> {
+    self.b.api_put_bytes(key.as_ref(), data.into())
+  }
+
+  fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
+    self.b.api_delete(key.as_ref())
+  }
+
+  fn clear(&mut self) -> crate::Result<()> {
+    self.b.api_clear()
+  }
+
+  fn set_sequence(&mut self, v: u64) -> crate::Result<()> {
+    self.b.api_set_sequence(v)
+  }
+
+  fn next_sequence(&mut self) -> crate::Result<u64> {
+    self.b.api_next_sequence()
+  }
+
+  fn set_meta<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()> {
+    self.b.api_set_meta(key.as_ref(), value.as_ref())
+  }
+
+  fn delete_meta<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
+    self.b.api_delete_meta(key.as_ref())
+  }
+
+  fn set_quota(&mut self, quota: Option<BucketQuota>) -> crate::Result<()> {
+    self.b.api_set_quota(quota)
+  }
+
+  fn force_materialize(&mut self) -> crate::Result<()> {
+    self.b.api_force_materialize()
+  }
+
+  fn try_inline(&mut self) -> crate::Result<bool> {
+    self.b.api_try_inline()
+  }
+
+  fn set_fill_percent(&mut self, fill_percent: f64) {
+    // TODO: Move to cell api call
+    self.b.cell.borrow_mut().w.as_mut().unwrap().fill_percent = fill_percent;
+  }
+
+  fn snapshot(&self) -> Option<BucketImpl<'tx>> {
+    self.b.api_snapshot().map(BucketImpl::from)
+  }
+}
+
+/// BucketStats records statistics about resources used by a bucket.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct BucketStats {
+  // Page count statistics.
+  /// number of logical branch pages
+  branch_page_n: i64,
+  /// number of physical branch overflow pages
+  branch_overflow_n: i64,
+  /// number of logical leaf pages
+  leaf_page_n: i64,
+  /// number of physical leaf overflow pages
+  leaf_overflow_n: i64,
+
+  // Tree statistics.
+  /// number of keys/value pairs
+  pub(crate) key_n: i64,
+  /// number of levels in B+tree
+  depth: i64,
+
+  // Page size utilization.
+  /// bytes allocated for physical branch pages
+  branch_alloc: i64,
+  /// bytes actually used for branch data
+  branch_in_use: i64,
+  /// bytes allocated for physical leaf pages
+  leaf_alloc: i64,
+  /// bytes actually used for leaf data
+  leaf_in_use: i64,
+
+  // Bucket statistics
+  /// total number of buckets including the top bucket
+  bucket_n: i64,
+  /// total number on inlined buckets
+  inline_bucket_n: i64,
+  /// bytes used for inlined buckets (also accounted for in LeafInuse)
+  inline_bucket_in_use: i64,
+  /// whether the bucket itself is stored inline in its parent. See
+  /// [BucketRwApi::force_materialize] and [BucketRwApi::try_inline]
+  is_inline: bool,
+
+  // Quota statistics. Only top-level buckets have a quota
+  /// the quota of the bucket. See [BucketRwApi::set_quota]
+  quota: Option<BucketQuota>,
+  /// the usage counted against the quota
+  quota_usage: Option<QuotaUsage>,
+}
+
+impl AddAssign<BucketStats> for BucketStats {
+  fn add_assign(&mut self, rhs: BucketStats) {
+    self.branch_page_n += rhs.branch_page_n;
+    self.branch_overflow_n += rhs.branch_overflow_n;
+    self.leaf_page_n += rhs.leaf_page_n;
+    self.leaf_overflow_n += rhs.leaf_overflow_n;
+
+    self.key_n += rhs.key_n;
+    if self.depth < rhs.depth {
+      self.depth = rhs.depth;
+    }
+
+    self.branch_alloc += rhs.branch_alloc;
+    self.branch_in_use += rhs.branch_in_use;
+    self.leaf_alloc += rhs.leaf_alloc;
+    self.leaf_in_use += rhs.leaf_in_use;
+
+    self.bucket_n += rhs.bucket_n;
+    self.inline_bucket_n += rhs.inline_bucket_n;
+    self.inline_bucket_in_use += rhs.inline_bucket_in_use;
+  }
+}
+
+/// BucketMetrics counts the operations performed on a top-level bucket and its nested buckets.
+///
+/// See [crate::TxStats::bucket_metrics] and [crate::DbStats::bucket_metrics]
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct BucketMetrics {
+  /// number of key lookups
+  pub(crate) gets: u64,
+  /// number of keys written
+  pub(crate) puts: u64,
+  /// number of keys deleted
+  pub(crate) deletes: u64,
+  /// number of cursor moves, including the ones made by `for_each`
+  pub(crate) cursor_ops: u64,
+}
+
+impl AddAssign<BucketMetrics> for BucketMetrics {
+  fn add_assign(&mut self, rhs: BucketMetrics) {
+    self.gets += rhs.gets;
+    self.puts += rhs.puts;
+    self.deletes += rhs.deletes;
+    self.cursor_ops += rhs.cursor_ops;
+  }
+}
+
+impl SubAssign<BucketMetrics> for BucketMetrics {
+  fn sub_assign(&mut self, rhs: BucketMetrics) {
+    self.gets = self.gets.saturating_sub(rhs.gets);
+    self.puts = self.puts.saturating_sub(rhs.puts);
+    self.deletes = self.deletes.saturating_sub(rhs.deletes);
+    self.cursor_ops = self.cursor_ops.saturating_sub(rhs.cursor_ops);
+  }
+}
+
+/// BucketStructure describes the nested bucket layout of a database.
+#[derive(Clone, Debug, Eq, PartialEq, Default)]
+pub struct BucketStructure {
+  /// name of the bucket
+  pub name: Vec<u8>,
+  /// number of key/value pairs, excluding nested buckets
+  pub key_n: usize,
+  /// nested buckets
+  pub children: Vec<BucketStructure>,
+}
+
+/// DefaultFillPercent is the percentage that split pages are filled.
+/// This value can be changed by setting Bucket.FillPercent.
+const DEFAULT_FILL_PERCENT: f64 = 0.5;
+
+/// MAX_KEY_SIZE is the maximum length of a key, in bytes.
+pub(crate) const MAX_KEY_SIZE: u32 = 32768;
+
+/// MaxValueSize is the maximum length of a value, in bytes.
+pub(crate) const MAX_VALUE_SIZE: u32 = (1 << 31) - 2;
+const INLINE_BUCKET_ALIGNMENT: usize = mem::align_of::<InlineBucket>();
+const INLINE_BUCKET_SIZE: usize = mem::size_of::<InlineBucket>();
+
+pub(crate) const MIN_FILL_PERCENT: f64 = 0.1;
+pub(crate) const MAX_FILL_PERCENT: f64 = 1.0;
+
+/// A convenience struct representing an inline page header
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct InlineBucket {
+  header: BucketHeader,
+  page: PageHeader,
+}
+
+impl Default for InlineBucket {
+  fn default() -> Self {
+    InlineBucket {
+      header: BucketHeader::new(ZERO_PGID, 0),
+      page: PageHeader {
+        id: Default::default(),
+        flags: LEAF_PAGE_FLAG,
+        count: 0,
+        overflow: 0,
+      },
+    }
+  }
+}
+
+/// A bucket leaf that can be processed on any thread of the rayon pool
+#[cfg(feature = "rayon")]
+enum ParLeaf<'tx> {
+  Page(RefPage<'tx>),
+  Entries(Vec<(&'tx [u8], Option<&'tx [u8]>)>),
+}
+
+// Safe because pages aren't modified or unmapped while the transaction is borrowed
+#[cfg(feature = "rayon")]
+unsafe impl<'tx> Send for ParLeaf<'tx> {}
+
+/// The internal Bucket API
+pub(crate) trait BucketIApi<'tx, T: TxIApi<'tx>>:
+  SplitRef<BucketR<'tx>, T, InnerBucketW<'tx, T, Self>>
+{
+  fn new_r_in(
+    bump: &'tx Bump, bucket_header: BucketHeader, tx: T, inline_page: Option<RefPage<'tx>>,
+  ) -> Self;
+
+  fn new_rw_in(
+    bump: &'tx Bump, bucket_header: BucketHeader, tx: T, inline_page: Option<RefPage<'tx>>,
+  ) -> Self;
+
+  /// Returns whether the bucket is writable.
+  fn is_writeable(&self) -> bool;
+
+  /// Returns the sub-bucket `name` of the bucket with the root page `parent` if the
+  /// transaction's [crate::common::bucket_cache::BucketCache] has it
+  fn cached_bucket(self, parent: PgId, name: &[u8]) -> Option<Self>;
+
+  /// Remembers the sub-bucket `name` of the bucket with the root page `parent` in the
+  /// transaction's [crate::common::bucket_cache::BucketCache], if it has one
+  fn cache_bucket(self, parent: PgId, name: &[u8], child: Self);
+
+  /// Returns the rc ptr Tx of the bucket
+  fn tx(self) -> T {
+    self.split_bound()
+  }
+
+  /// Returns the root page id of the bucket
+  fn root(self) -> PgId {
+    self.split_r().bucket_header.root()
+  }
+
+  /// Returns whether the bucket was deleted in the transaction
+  fn is_deleted(self) -> bool {
+    self.split_ow().as_ref().is_some_and(|w| w.deleted)
+  }
+
+  /// Updates the operation counters of the bucket's top-level bucket
+  fn inc_metrics<F: FnOnce(&mut BucketMetrics)>(self, f: F) {
+    let path = self.split_r().path;
+    if let Some(name) = top_level_name(path) {
+      let tx = self.tx();
+      // Rolling back to a savepoint isn't counted as the application's accesses
+      if tx.split_ow().as_ref().is_some_and(|w| w.undoing) {
+        return;
+      }
+      tx.split_r().inc_bucket_metrics(name, f);
+    }
+  }
+
+  /// Create a new cursor for this Bucket
+  fn i_cursor(self) -> InnerCursor<'tx, T, Self> {
+    let tx = self.tx();
+    tx.split_r().stats.as_ref().unwrap().inc_cursor_count(1);
+    InnerCursor::new(self, tx.bump())
+  }
+
+  /// See [BucketApi::bucket]
+  fn api_bucket(self, name: &[u8]) -> Option<Self> {
+    match self.api_try_bucket(name) {
+      Ok(child) => child,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// See [BucketApi::try_bucket]
+  fn api_try_bucket(self, name: &[u8]) -> crate::Result<Option<Self>> {
+    if let Some(w) = self.split_ow().deref() {
+      if let Some(child) = w.buckets.get(name) {
+        return Ok(Some(*child));
+      }
+    }
+    let root = self.root();
+    if let Some(child) = self.cached_bucket(root, name) {
+      return Ok(Some(child));
+    }
+    let mut c = self.i_cursor();
+    // Move cursor to key.
+    let (k, v, flags) = match c.i_try_seek(name)? {
+      Some(kv) => kv,
+      None => return Ok(None),
+    };
+    // Return None if the key doesn't exist or it is not a bucket.
+    if !self.comparator()(name, k).is_eq() || (flags & BUCKET_LEAF_FLAG) == 0 {
+      return Ok(None);
+    }
+
+    // Otherwise create a bucket and cache it.
+    let path = child_path(self.tx().bump(), self.split_r().path, k);
+    let comparator = self.recorded_comparator(path)?;
+    let child = self.open_bucket(v);
+    {
+      let mut child_r = child.split_r_mut();
+      child_r.path = path;
+      child_r.comparator = comparator;
+    }
+    // Only the writer decides how buckets are stored, and only paged buckets are kept paged
+    if self.split_ow().is_some() && child.root() != ZERO_PGID && self.recorded_paged(path)? {
+      if let Some(ref mut w) = child.split_ow_mut().deref_mut() {
+        w.force_paged = true;
+      }
+    }
+    if let Some(ref mut w) = self.split_ow_mut().deref_mut() {
+      let tx = self.split_bound();
+      let bump = tx.bump();
+      let name = bump.alloc_slice_copy(name);
+      w.buckets.insert(name, child);
+    } else {
+      self.cache_bucket(root, name, child);
+    }
+
+    Ok(Some(child))
+  }
+
+  /// Returns the key comparator of the bucket
+  fn comparator(self) -> KeyComparator {
+    self.split_r().comparator
+  }
+
+  /// Returns the comparator recorded for the bucket at `path`.
+  ///
+  /// The database's own records always use the default ordering. Lookups are cached for
+  /// the rest of the transaction.
+  fn recorded_comparator(self, path: &'tx [u8]) -> crate::Result<KeyComparator> {
+    let tx = self.tx();
+    let comparators = tx.comparators();
+    if comparators.is_empty() || top_level_name(path) == Some(SYSTEM_BUCKET) {
+      return Ok(bytewise);
+    }
+    if let Some(comparator) = tx.split_r().cached_comparator(path) {
+      return Ok(comparator);
+    }
+    let name = match tx.system_bucket(META_BUCKET) {
+      Some(meta) => meta.api_try_get(&meta_key(path, COMPARATOR_META_KEY))?,
+      None => None,
+    };
+    let comparator = match name {
+      None => bytewise,
+      Some(name) => comparators
+        .get(name)
+        .ok_or_else(|| Error::UnknownComparator(String::from_utf8_lossy(name).into_owned()))?,
+    };
+    tx.split_r().cache_comparator(path, comparator);
+    Ok(comparator)
+  }
+
+  /// Returns whether the bucket at `path` is recorded as kept paged.
+  /// See [BucketRwApi::force_materialize]
+  fn recorded_paged(self, path: &'tx [u8]) -> crate::Result<bool> {
+    if top_level_name(path) == Some(SYSTEM_BUCKET) {
+      return Ok(false);
+    }
+    match self.tx().system_bucket(META_BUCKET) {
+      Some(meta) => Ok(meta.api_try_get(&meta_key(path, PAGED_META_KEY))?.is_some()),
+      None => Ok(false),
+    }
+  }
+
+  /// Helper method that re-interprets a sub-bucket value
+  /// from a parent into a Bucket
+  fn open_bucket(self, mut value: &[u8]) -> Self {
+    let tx = self.tx();
+    let bump = tx.bump();
+    // Unaligned access requires a copy to be made.
+    //TODO: use std is_aligned_to when it comes out
+    if !IsAligned::is_aligned_to::<InlineBucket>(value.as_ptr()) {
+      // TODO: Shove this into a centralized function somewhere
+      let layout = Layout::from_size_align(value.len(), INLINE_BUCKET_ALIGNMENT).unwrap();
+      let new_value = unsafe {
+        let data = bump.alloc_layout(layout).as_ptr();
+        ptr::write_bytes(data, 0, value.len());
+        &mut *slice_from_raw_parts_mut(data, value.len())
+      };
+      new_value.copy_from_slice(value);
+      value = new_value;
+    }
+    let bucket_header = *bytemuck::from_bytes::<BucketHeader>(value.split_at(BUCKET_HEADER_SIZE).0);
+    // Save a reference to the inline page if the bucket is inline.
+    let ref_page = if bucket_header.root() == ZERO_PGID {
+      assert!(
+        value.len() >= INLINE_BUCKET_SIZE,
+        "subbucket value not large enough. Expected at least {} bytes. Was {}",
+        INLINE_BUCKET_SIZE,
+        value.len()
+      );
+      unsafe {
+        let ref_page_ptr = value.as_ptr().add(BUCKET_HEADER_SIZE);
+        Some(RefPage::new(ref_page_ptr))
+      }
+    } else {
+      None
+    };
+    // Sub-buckets of read-only buckets are read-only, even in a writable transaction
+    if self.split_ow().is_some() {
+      Self::new_rw_in(bump, bucket_header, tx, ref_page)
+    } else {
+      Self::new_r_in(bump, bucket_header, tx, ref_page)
+    }
+  }
+
+  /// See [BucketApi::get]
+  fn api_get(self, key: &[u8]) -> Option<&'tx [u8]> {
+    match self.api_try_get(key) {
+      Ok(v) => v,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// See [BucketApi::try_get]
+  fn api_try_get(self, key: &[u8]) -> crate::Result<Option<&'tx [u8]>> {
+    self.inc_metrics(|m| m.gets += 1);
+    if let Some((k, v, flags)) = self.i_try_seek_cached(key)? {
+      // Return None if this is a bucket.
+      if (flags & BUCKET_LEAF_FLAG) != 0 {
+        return Ok(None);
+      }
+      // If our target node isn't the same key as what's passed in then return None.
+      if !self.comparator()(key, k).is_eq() {
+        return Ok(None);
+      }
+      Ok(Some(v))
+    } else {
+      Ok(None)
+    }
+  }
 
-/// Read-only Bucket
-pub struct BucketImpl<'tx> {
-  b: BucketWrapper<'tx>,
-}
-pub enum BucketWrapper<'tx> {
-  RW(BucketCell<'tx>),
-}
+  /// Seeks a cursor to `key` for a get. Read-only transactions search the cached leaf
+  /// page of a previous get if it covers the key. See [crate::common::leaf_cache::LeafCache]
+  fn i_try_seek_cached(self, key: &[u8]) -> crate::Result<Option<KeyValueFlags<'tx>>> {
+    let root = self.root();
+    let tx = self.tx();
+    tx.check_evicted()?;
+    let mut c = self.i_cursor();
+    // Inline buckets don't have a root page to tell them apart
+    if root == ZERO_PGID || self.is_writeable() {
+      return c.i_try_seek(key);
+    }
+    let cached = match &tx.split_r().leaf_cache {
+      None => return c.i_try_seek(key),
+      Some(cache) => cache.find(root, key, self.comparator()),
+    };
+    if let Some(leaf) = cached {
+      return c.i_try_seek_leaf(leaf, key);
+    }
+    let kv = c.i_try_seek(key)?;
+    if let (Some(leaf), Some(cache)) = (c.leaf_page(), &tx.split_r().leaf_cache) {
+      cache.insert(root, leaf);
+    }
+    Ok(kv)
+  }
 
-impl<'tx> From<BucketCell<'tx>> for BucketImpl<'tx> {
-  fn from(value: BucketCell<'tx>) -> Self {
-    BucketImpl {
-      b: BucketWrapper::RW(value),
+  /// See [BucketApi::value_len]
+  fn api_value_len(self, key: &[u8]) -> Option<usize> {
+    // The value is a slice into its page so none of its bytes are read
+    self.api_get(key).map(|v| v.len())
+  }
+
+  /// See [BucketApi::get_many]
+  fn api_get_many<K: AsRef<[u8]>>(self, keys: &[K]) -> Vec<Option<&'tx [u8]>> {
+    match self.api_try_get_many(keys) {
+      Ok(values) => values,
+      Err(e) => panic!("{}", e),
     }
   }
-}
 
-impl<'tx> BucketApi<'tx> for BucketImpl<'tx> {
-  fn root(&self) -> PgId {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.root(),
-    }
-  }
+  /// See [BucketApi::try_get_many]
+  fn api_try_get_many<K: AsRef<[u8]>>(self, keys: &[K]) -> crate::Result<Vec<Option<&'tx [u8]>>> {
+    let comparator = self.comparator();
+    let mut order = (0..keys.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| comparator(keys[*a].as_ref(), keys[*b].as_ref()));
+
+    let mut values = vec![None; keys.len()];
+    let mut c = self.i_cursor();
+    for i in order {
+      let key = keys[i].as_ref();
+      self.inc_metrics(|m| m.gets += 1);
+      if let Some((k, v, flags)) = c.i_try_seek_from(key)? {
+        if flags & BUCKET_LEAF_FLAG == 0 && comparator(key, k).is_eq() {
+          values[i] = Some(v);
+        }
+      }
+    }
+    Ok(values)
+  }
+
+  /// See [BucketApi::for_each]
+  fn api_for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
+    self, mut f: F,
+  ) -> crate::Result<()> {
+    let mut c = self.i_cursor();
+    let mut inode = c.api_first();
+    while let Some((k, v)) = inode {
+      f(k, v)?;
+      if self.is_deleted() {
+        return Err(BucketNotFound);
+      }
+      inode = c.api_next();
+    }
+    Ok(())
+  }
+
+  /// See [BucketApi::par_for_each]
+  #[cfg(feature = "rayon")]
+  fn api_par_for_each<F: Fn(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()> + Send + Sync>(
+    self, f: F,
+  ) -> crate::Result<()> {
+    // Gather the leaves on this thread as the bucket and its nodes can't be shared
+    let leaves = std::cell::RefCell::new(Vec::new());
+    self.for_each_page_node(|pn, _| match pn {
+      PageNode::Page(p) => {
+        if p.is_leaf() {
+          leaves.borrow_mut().push(ParLeaf::Page(*p));
+        }
+      }
+      PageNode::Node(n) => {
+        let node = n.cell.borrow();
+        if node.is_leaf {
+          let entries = node
+            .inodes
+            .iter()
+            .map(|inode| {
+              let value = if inode.flags() & BUCKET_LEAF_FLAG != 0 {
+                None
+              } else {
+                Some(inode.value())
+              };
+              (inode.key(), value)
+            })
+            .collect();
+          leaves.borrow_mut().push(ParLeaf::Entries(entries));
+        }
+      }
+    });
+    leaves
+      .into_inner()
+      .into_par_iter()
+      .try_for_each(|leaf| match leaf {
+        ParLeaf::Page(page) => {
+          let leaf_page = MappedLeafPage::coerce_ref(&page).unwrap();
+          leaf_page.iter().try_for_each(|elem| {
+            let value = if elem.is_bucket_entry() {
+              None
+            } else {
+              Some(elem.value())
+            };
+            f(elem.key(), value)
+          })
+        }
+        ParLeaf::Entries(entries) => entries.into_iter().try_for_each(|(k, v)| f(k, v)),
+      })
+  }
+
+  /// See [BucketApi::for_each_bucket]
+  fn api_for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(
+    self, mut f: F,
+  ) -> crate::Result<()> {
+    let mut c = self.i_cursor();
+    let mut inode = c.i_first();
+    while let Some((k, _, flags)) = inode {
+      if flags & BUCKET_LEAF_FLAG != 0 {
+        f(k)?;
+        if self.is_deleted() {
+          return Err(BucketNotFound);
+        }
+      }
+      inode = c.i_next();
+    }
+    Ok(())
+  }
+
+  /// forEachPage iterates over every page in a bucket, including inline pages.
+  fn for_each_page<F: FnMut(&RefPage<'tx>, usize, &mut BVec<PgId>)>(self, f: &mut F) {
+    let tx = self.tx();
+    let root = {
+      let r = self.split_r();
+      let root = r.bucket_header.root();
+      // If we have an inline page then just use that.
+      if let Some(page) = &r.inline_page {
+        let mut v = BVec::with_capacity_in(1, tx.bump());
+        v.push(root);
+        f(page, 0, &mut v);
+        return;
+      }
+      root
+    };
+    // Otherwise traverse the page hierarchy.
+    tx.for_each_page(root, f)
+  }
+
+  /// forEachPageNode iterates over every page (or node) in a bucket.
+  /// This also includes inline pages.
+  fn for_each_page_node<F: FnMut(&PageNode<'tx>, usize) + Copy>(self, mut f: F) {
+    let root = {
+      let (r, w) = self.split_ref();
+      // If we have an inline page or root node then just use that.
+      if let Some(page) = &r.inline_page {
+        let pn = match w.as_ref().and_then(|wb| wb.root_node) {
+          Some(root_node) => PageNode::Node(root_node),
+          None => PageNode::Page(*page),
+        };
+        f(&pn, 0);
+        return;
+      }
+      r.bucket_header.root()
+    };
+    self._for_each_page_node(root, 0, f);
+  }
+
+  fn _for_each_page_node<F: FnMut(&PageNode<'tx>, usize) + Copy>(
+    self, root: PgId, depth: usize, mut f: F,
+  ) {
+    let pn = self.page_node(root);
+
+    // Execute function.
+    f(&pn, depth);
+
+    // Recursively loop over children.
+    match &pn {
+      PageNode::Page(page) => {
+        if let Some(branch_page) = MappedBranchPage::coerce_ref(page) {
+          branch_page.elements().iter().for_each(|elem| {
+            self._for_each_page_node(elem.pgid(), depth + 1, f);
+          });
+        }
+      }
+      PageNode::Node(node) => {
+        let bump = self.tx().bump();
+        // To keep with our rules we much copy the inode pgids to temporary storage first
+        // This should be unnecessary, but working first *then* optimize
+        let v = {
+          let node_borrow = node.cell.borrow();
+          // The inodes of a leaf hold keys, not child pages
+          if node_borrow.is_leaf {
+            return;
+          }
+          let mut v = BVec::with_capacity_in(node_borrow.inodes.len(), bump);
+          let ids = node_borrow.inodes.iter().map(|inode| inode.pgid());
+          v.extend(ids);
+          v
+        };
+        v.into_iter()
+          .for_each(|pgid| self._for_each_page_node(pgid, depth + 1, f));
+      }
+    }
+  }
+
+  fn page_node(self, id: PgId) -> PageNode<'tx> {
+    match self.try_page_node(id) {
+      Ok(pn) => pn,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// Returns the node of page `id` if it's materialized, or the page otherwise.
+  /// Returns an error if the page can't be read. See [TxIApi::try_mem_page]
+  fn try_page_node(self, id: PgId) -> crate::Result<PageNode<'tx>> {
+    let (r, w) = self.split_ref();
+    // Inline buckets have a fake page embedded in their value so treat them
+    // differently. We'll return the rootNode (if available) or the fake page.
+    if r.bucket_header.root() == ZERO_PGID {
+      if id != ZERO_PGID {
+        return Err(Error::Corrupted {
+          pgid: id,
+          reason: Cow::Borrowed("page access in an inline bucket"),
+        });
+      }
+      if let Some(root_node) = w.as_ref().and_then(|wb| wb.root_node) {
+        return Ok(PageNode::Node(root_node));
+      }
+      let page = r.inline_page.unwrap();
+      self.tx().check_tree_page(&page)?;
+      return Ok(PageNode::Page(page));
+    }
+
+    // Check the node cache for non-inline buckets.
+    if let Some(wb) = w.deref() {
+      if let Some(node) = wb.nodes.get(&id) {
+        return Ok(PageNode::Node(*node));
+      }
+      if let Some(patched) = wb.patched.get(&id) {
+        return Ok(PageNode::Page(patched.page));
+      }
+    }
+
+    let page = self.tx().try_mem_page(id)?;
+    self.tx().check_tree_page(&page)?;
+    Ok(PageNode::Page(page))
+  }
+
+  /// See [BucketApi::sequence]
+  fn api_sequence(self) -> u64 {
+    self.split_r().bucket_header.sequence()
+  }
+
+  /// See [BucketApi::get_meta]
+  fn api_get_meta(self, key: &[u8]) -> Option<&'tx [u8]> {
+    let meta = self.tx().system_bucket(META_BUCKET)?;
+    meta.api_get(&meta_key(self.split_r().path, key))
+  }
+
+  /// Returns the key of the metadata entry holding the quota that applies to the bucket,
+  /// the one of its top-level bucket. The database's own records have no quota.
+  fn quota_key(self) -> Option<Vec<u8>> {
+    let path = self.split_r().path;
+    let top = top_level_name(path)?;
+    if top == SYSTEM_BUCKET {
+      return None;
+    }
+    Some(meta_key(&path[..4 + top.len()], QUOTA_META_KEY))
+  }
+
+  /// Returns the quota that applies to the bucket and its usage, if any
+  fn quota_record(self) -> crate::Result<Option<QuotaRecord>> {
+    let key = match self.quota_key() {
+      Some(key) => key,
+      None => return Ok(None),
+    };
+    let meta = self.tx().system_bucket(META_BUCKET);
+    match meta.and_then(|meta| meta.api_get(&key)) {
+      Some(data) => QuotaRecord::decode(data).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// Counts the keys and values of the bucket, and those of its nested buckets if `nested`
+  fn api_usage(self, nested: bool) -> crate::Result<QuotaUsage> {
+    let mut usage = QuotaUsage::default();
+    self.api_for_each(|k, v| {
+      match v {
+        Some(v) => {
+          usage.keys += 1;
+          usage.bytes += (k.len() + v.len()) as u64;
+        }
+        None if nested => {
+          let child = self.api_try_bucket(k)?.ok_or(BucketNotFound)?;
+          usage += child.api_usage(true)?
+        }
+        None => (),
+      }
+      Ok(())
+    })?;
+    Ok(usage)
+  }
+
+  /// Returns the maximum total size of a bucket to make it a candidate for inlining.
+  fn max_inline_bucket_size(self) -> usize {
+    self.tx().page_size() / 4
+  }
+
+  /// See [BucketApi::stats]
+  fn api_stats(self) -> BucketStats {
+    let mut s = BucketStats::default();
+    let mut sub_stats = BucketStats::default();
+    let page_size = self.tx().page_size();
+    s.bucket_n += 1;
+    if self.root() == ZERO_PGID {
+      s.inline_bucket_n += 1;
+      s.is_inline = true;
+    }
+    self.for_each_page(&mut |p, depth, _| {
+      if let Some(leaf_page) = MappedLeafPage::coerce_ref(p) {
+        s.key_n += p.count as i64;
+
+        // used totals the used bytes for the page
+        let mut used = PAGE_HEADER_SIZE;
+        if let Some(last_element) = leaf_page.elements().last() {
+          // If page has any elements, add all element headers.
+          used += LEAF_PAGE_ELEMENT_SIZE * (p.count - 1) as usize;
+
+          // Add all element key, value sizes.
+          // The computation takes advantage of the fact that the position
+          // of the last element's key/value equals to the total of the sizes
+          // of all previous elements' keys and values.
+          // It also includes the last element's header.
+          used += last_element.pos() as usize
+            + last_element.key_size() as usize
+            + last_element.value_size() as usize;
+        }
+
+        // For inlined bucket just update the inline stats
+        if self.root() == ZERO_PGID {
+          s.inline_bucket_in_use += used as i64;
+        } else {
+          // For non-inlined bucket update all the leaf stats
+          s.leaf_page_n += 1;
+          s.leaf_in_use += used as i64;
+          s.leaf_overflow_n += leaf_page.overflow as i64;
+
+          // Collect stats from sub-buckets.
+          // Do that by iterating over all element headers
+          // looking for the ones with the bucketLeafFlag.
+          for leaf_elem in leaf_page.iter() {
+            if leaf_elem.is_bucket_entry() {
+              // For any bucket element, open the element value
+              // and recursively call Stats on the contained bucket.
+              sub_stats += self.open_bucket(leaf_elem.value()).api_stats();
+            }
+          }
+        }
+      } else if let Some(branch_page) = MappedBranchPage::coerce_ref(p) {
+        s.branch_page_n += 1;
+        if let Some(last_element) = branch_page.elements().last() {
+          // used totals the used bytes for the page
+          // Add header and all element headers.
+          let mut used =
+            PAGE_HEADER_SIZE + (BRANCH_PAGE_ELEMENT_SIZE * (branch_page.count - 1) as usize);
+          // Add size of all keys and values.
+          // Again, use the fact that last element's position equals to
+          // the total of key, value sizes of all previous elements.
+          used += last_element.pos() as usize + last_element.key_size() as usize;
+          s.branch_in_use += used as i64;
+          s.branch_overflow_n += branch_page.overflow as i64;
+        }
+      }
+
+      if (depth + 1) as i64 > s.depth {
+        s.depth = (depth + 1) as i64;
+      }
+    });
+    // Alloc stats can be computed from page counts and pageSize.
+    s.branch_alloc = (s.branch_page_n + s.branch_overflow_n) * page_size as i64;
+    s.leaf_alloc = (s.leaf_page_n + s.leaf_overflow_n) * page_size as i64;
 
-  fn writable(&self) -> bool {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.is_writeable(),
-    }
-  }
+    // Add the max depth of sub-buckets to get total nested depth.
+    s.depth += sub_stats.depth;
+    // Add the stats for all sub-buckets
+    s += sub_stats;
 
-  fn cursor(&self) -> CursorImpl<'tx> {
-    match &self.b {
-      BucketWrapper::RW(rw) => InnerCursor::new(*rw, rw.tx().bump()).into(),
+    if path_names(self.split_r().path).len() == 1 {
+      if let Ok(Some(record)) = self.quota_record() {
+        s.quota = Some(record.quota);
+        s.quota_usage = Some(record.usage);
+      }
     }
+    s
   }
 
-  fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>> {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_bucket(name.as_ref()).map(BucketImpl::from),
-    }
+  /// See [BucketApi::key_n]
+  fn api_key_n(self) -> usize {
+    let key_n = Cell::new(0usize);
+    self.for_each_page_node(|pn, _| match pn {
+      PageNode::Page(p) => {
+        if p.is_leaf() {
+          key_n.set(key_n.get() + p.count as usize);
+        }
+      }
+      PageNode::Node(n) => {
+        let node = n.cell.borrow();
+        if node.is_leaf {
+          key_n.set(key_n.get() + node.inodes.len());
+        }
+      }
+    });
+    key_n.get()
   }
 
-  fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<&[u8]> {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_get(key.as_ref()),
-    }
+  /// See [BucketApi::approx_size_bytes]
+  fn api_approx_size_bytes(self) -> usize {
+    let page_size = self.tx().page_size();
+    let is_inline = self.root() == ZERO_PGID;
+    let size = Cell::new(0usize);
+    self.for_each_page_node(|pn, _| {
+      let page_bytes = match pn {
+        PageNode::Page(p) if is_inline => MappedLeafPage::coerce_ref(p)
+          .map(|leaf_page| {
+            leaf_page.iter().fold(PAGE_HEADER_SIZE, |used, elem| {
+              used + LEAF_PAGE_ELEMENT_SIZE + elem.key().len() + elem.value().len()
+            })
+          })
+          .unwrap_or(PAGE_HEADER_SIZE),
+        PageNode::Page(p) => (p.overflow as usize + 1) * page_size,
+        PageNode::Node(n) => n.size(),
+      };
+      size.set(size.get() + page_bytes);
+    });
+    size.get()
   }
 
-  fn sequence(&self) -> u64 {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_sequence(),
-    }
+  /// See [BucketApi::depth]
+  fn api_depth(self) -> usize {
+    let max_depth = Cell::new(0usize);
+    self.for_each_page_node(|_, depth| {
+      max_depth.set(max_depth.get().max(depth + 1));
+    });
+    max_depth.get()
   }
 
-  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
-    &self, f: F,
-  ) -> crate::Result<()> {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_for_each(f),
+  /// recursively_inspect builds the bucket structure of this bucket and all nested buckets.
+  fn recursively_inspect(self, name: &[u8]) -> crate::Result<BucketStructure> {
+    let mut bs = BucketStructure {
+      name: name.to_vec(),
+      ..Default::default()
+    };
+    let is_root = self.split_r().path.is_empty();
+    let mut c = self.i_cursor();
+    let mut inode = c.i_first();
+    while let Some((k, _, flags)) = inode {
+      if flags & BUCKET_LEAF_FLAG != 0 {
+        if is_root && is_reserved(k) {
+          inode = c.i_next();
+          continue;
+        }
+        if let Some(child) = self.api_try_bucket(k)? {
+          bs.children.push(child.recursively_inspect(k)?);
+        }
+      } else {
+        bs.key_n += 1;
+      }
+      inode = c.i_next();
     }
+    Ok(bs)
   }
 
-  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()> {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_for_each_bucket(f),
+  /// recursively_walk calls `f` for every key in this bucket and all nested buckets.
+  fn recursively_walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    self, path: &mut Vec<&'tx [u8]>, f: &mut F,
+  ) -> crate::Result<()> {
+    let is_root = self.split_r().path.is_empty();
+    let mut c = self.i_cursor();
+    let mut inode = c.i_first();
+    while let Some((k, v, flags)) = inode {
+      if flags & BUCKET_LEAF_FLAG != 0 {
+        if is_root && is_reserved(k) {
+          inode = c.i_next();
+          continue;
+        }
+        f(path.as_slice(), k, None)?;
+        let child = self.api_try_bucket(k)?.unwrap();
+        path.push(k);
+        child.recursively_walk(path, f)?;
+        path.pop();
+      } else {
+        f(path.as_slice(), k, Some(v))?;
+      }
+      inode = c.i_next();
     }
+    Ok(())
   }
 
-  fn stats(&self) -> BucketStats {
-    match &self.b {
-      BucketWrapper::RW(rw) => rw.api_stats(),
-    }
-  }
+  fn into_impl(self) -> BucketImpl<'tx>;
 }
 
-/// Read/Write Bucket
-pub struct BucketRwImpl<'tx> {
-  b: BucketCell<'tx>,
-}
+pub(crate) trait BucketRwIApi<'tx>: BucketIApi<'tx, TxCell<'tx>> {
+  /// Explicitly materialize the root node
+  fn materialize_root(self) -> NodeRwCell<'tx>;
 
-impl<'tx> From<BucketCell<'tx>> for BucketRwImpl<'tx> {
-  fn from(value: BucketCell<'tx>) -> Self {
-    BucketRwImpl { b: value }
-  }
-}
+  /// See [BucketRwApi::create_bucket]
+  fn api_create_bucket(self, key: &[u8]) -> crate::Result<Self>;
 
-impl<'tx> BucketApi<'tx> for BucketRwImpl<'tx> {
-  fn root(&self) -> PgId {
-    self.b.root()
-  }
+  /// See [BucketRwApi::create_bucket_if_not_exists]
+  fn api_create_bucket_if_not_exists(self, key: &[u8]) -> crate::Result<Self>;
 
-  fn writable(&self) -> bool {
-    self.b.is_writeable()
-  }
+  /// See [BucketRwApi::create_bucket_with_comparator]
+  fn api_create_bucket_with_comparator(self, key: &[u8], comparator: &str) -> crate::Result<Self>;
 
-  fn cursor(&self) -> CursorImpl<'tx> {
-    InnerCursor::new(self.b, self.b.tx().bump()).into()
-  }
+  /// See [BucketRwApi::delete_bucket]
+  fn api_delete_bucket(self, key: &[u8]) -> crate::Result<()>;
 
-  fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>> {
-    self.b.api_bucket(name.as_ref()).map(BucketImpl::from)
-  }
+  /// See [BucketRwApi::put]
+  fn api_put(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
 
-  fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<&[u8]> {
-    self.b.api_get(key.as_ref())
-  }
+  /// See [BucketRwApi::put_many]
+  fn api_put_many(self, pairs: &[(&[u8], &[u8])]) -> crate::Result<()>;
 
-  fn sequence(&self) -> u64 {
-    self.b.api_sequence()
-  }
+  /// See [BucketRwApi::put_bytes]
+  #[cfg(feature = "bytes")]
+  fn api_put_bytes(self, key: &[u8], value: Bytes) -> crate::Result<()>;
 
-  fn for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
-    &self, f: F,
-  ) -> crate::Result<()> {
-    self.b.api_for_each(f)
-  }
+  /// Validates a put and counts it
+  fn check_put(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
 
-  fn for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(&self, f: F) -> crate::Result<()> {
-    self.b.api_for_each_bucket(f)
-  }
+  /// Puts a record the database maintains itself, such as a quota or a comparator entry,
+  /// or replays a put that was validated before. Only the format's limits apply, not the
+  /// configured [crate::BoltOptions] ones, and the put isn't counted in the metrics.
+  fn i_put(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
 
-  fn stats(&self) -> BucketStats {
-    self.b.api_stats()
-  }
-}
+  /// Puts a key/value pair with a cursor positioned by a seek for the key. `kv` is the
+  /// result of the seek. `held` is the value if it lives as long as the transaction, in
+  /// which case it isn't copied.
+  fn put_at(
+    self, c: &mut InnerCursor<'tx, TxCell<'tx>, Self>, kv: Option<(&'tx [u8], &'tx [u8], u32)>,
+    key: &[u8], value: &[u8], held: Option<&'tx [u8]>,
+  ) -> crate::Result<()>;
 
-impl<'tx> BucketRwApi<'tx> for BucketRwImpl<'tx> {
-  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<impl BucketRwApi<'tx>> {
-    self.b.api_bucket(name.as_ref()).map(BucketRwImpl::from)
-  }
+  /// See [BucketRwApi::delete]
+  fn api_delete(self, key: &[u8]) -> crate::Result<()>;
 
-  fn create_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<impl BucketRwApi<'tx>> {
-    self
-      .b
-      .api_create_bucket(key.as_ref())
-      .map(BucketRwImpl::from)
-  }
+  /// See [BucketRwApi::clear]
+  fn api_clear(self) -> crate::Result<()>;
 
-  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
-    &mut self, key: T,
-  ) -> crate::Result<impl BucketRwApi<'tx>> {
-    self
-      .b
-      .api_create_bucket_if_not_exists(key.as_ref())
-      .map(BucketRwImpl::from)
-  }
+  /// See [BucketRwApi::set_sequence]
+  fn api_set_sequence(self, v: u64) -> crate::Result<()>;
 
-  fn cursor_mut(&self) -> impl CursorRwApi<'tx> {
-    CursorRwImpl::new(InnerCursor::new(self.b, self.b.tx().bump()))
-  }
+  /// See [BucketRwApi::next_sequence]
+  fn api_next_sequence(self) -> crate::Result<u64>;
 
-  fn delete_bucket<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
-    self.b.api_delete_bucket(key.as_ref())
-  }
+  /// See [BucketRwApi::set_meta]
+  fn api_set_meta(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
 
-  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, data: U) -> crate::Result<()> {
-    self.b.api_put(key.as_ref(), data.as_ref())
-  }
+  /// Sets a metadata entry without the configured size limits. See [BucketRwIApi::i_put]
+  fn i_set_meta(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
 
-  fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
-    self.b.api_delete(key.as_ref())
-  }
+  /// See [BucketRwApi::delete_meta]
+  fn api_delete_meta(self, key: &[u8]) -> crate::Result<()>;
 
-  fn set_sequence(&mut self, v: u64) -> crate::Result<()> {
-    self.b.api_set_sequence(v)
-  }
+  /// Deletes a metadata entry, including the reserved ones
+  fn i_delete_meta(self, key: &[u8]) -> crate::Result<()>;
 
-  fn next_sequence(&mut self) -> crate::Result<u64> {
-    self.b.api_next_sequence()
-  }
+  /// See [BucketRwApi::set_quota]
+  fn api_set_quota(self, quota: Option<BucketQuota>) -> crate::Result<()>;
 
-  fn set_fill_percent(&mut self, fill_percent: f64) {
-    // TODO: Move to cell api call
-    self.b.cell.borrow_mut().w.as_mut().unwrap().fill_percent = fill_percent;
-  }
+  /// See [BucketRwApi::force_materialize]
+  fn api_force_materialize(self) -> crate::Result<()>;
+
+  /// See [BucketRwApi::try_inline]
+  fn api_try_inline(self) -> crate::Result<bool>;
+
+  /// Sets whether the bucket is kept paged on commit and returns the previous setting.
+  /// The recorded [PAGED_META_KEY] entry isn't changed.
+  fn set_force_paged(self, force_paged: bool) -> bool;
+
+  /// Counts `keys` added keys and `bytes` added bytes against the quota that applies to the
+  /// bucket. Fails with [Error::QuotaExceeded] if the bucket would grow beyond the quota.
+  fn account(self, keys: i64, bytes: i64) -> crate::Result<()>;
+
+  /// Records how to revert a change to the bucket if the transaction has a savepoint.
+  /// `f` is called with the transaction's bump and the bucket's path.
+  fn record_undo<F: FnOnce(&'tx Bump, &'tx [u8]) -> Undo<'tx>>(self, f: F);
+
+  /// Records a change to the bucket for the replication log. `f` is called with the
+  /// bucket's path.
+  #[cfg(feature = "replication")]
+  fn record_change<'a, F: FnOnce(&'tx [u8]) -> Change<'a>>(self, f: F)
+  where
+    'tx: 'a;
+
+  /// See [BucketRwApi::snapshot]
+  fn api_snapshot(self) -> Option<Self>;
+
+  /// free recursively frees all pages in the bucket.
+  fn free(self);
+
+  /// free_tree frees all pages of the bucket and of its sub-buckets. The pages are walked
+  /// with an explicit stack instead of opening the sub-buckets, so it may only be used when
+  /// none of them has materialized nodes.
+  fn free_tree(self);
+
+  /// spill writes all the nodes for this bucket to dirty pages.
+  fn spill(self, bump: &'tx Bump) -> crate::Result<()>;
+
+  fn write(self, bump: &'tx Bump) -> &'tx [u8];
+
+  /// inlineable returns true if a bucket is small enough to be written inline
+  /// and if it contains no subbuckets. Otherwise returns false.
+  fn inlineable(self) -> bool;
+
+  /// own_in removes all references to the old mmap.
+  fn own_in(self);
+
+  /// node creates a node from a page and associates it with a given parent.
+  fn node(self, pgid: PgId, parent: Option<NodeRwCell<'tx>>) -> NodeRwCell<'tx>;
+
+  /// Overwrites the value at `index` of the leaf `page` with a value of the same size in a
+  /// copy of the page, which is written as is on commit. Saves materializing and spilling
+  /// the leaf for updates of fixed-size values. The parents of the leaf must be
+  /// materialized, `parent` is the node pointing to the leaf. Returns None if the leaf is a
+  /// copy already, as values returned before may point into it.
+  fn patch_leaf(
+    self, page: RefPage<'tx>, parent: NodeRwCell<'tx>, index: usize, value: &[u8],
+  ) -> Option<RefPage<'tx>>;
+
+  /// Writes the patched copies of leaf pages and points their parents to them
+  fn write_patched(self) -> crate::Result<()>;
+
+  /// rebalance attempts to balance all nodes.
+  fn rebalance(self);
 }
 
-/// BucketStats records statistics about resources used by a bucket.
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, CopyGetters)]
-#[getset(get_copy = "pub")]
-pub struct BucketStats {
-  // Page count statistics.
-  /// number of logical branch pages
-  branch_page_n: i64,
-  /// number of physical branch overflow pages
-  branch_overflow_n: i64,
-  /// number of logical leaf pages
-  leaf_page_n: i64,
-  /// number of physical leaf overflow pages
-  leaf_overflow_n: i64,
+pub struct BucketR<'tx> {
+  pub(crate) bucket_header: BucketHeader,
+  /// inline page reference
+  pub(crate) inline_page: Option<RefPage<'tx>>,
+  /// key of the bucket in the comparator bucket
+  pub(crate) path: &'tx [u8],
+  /// orders the keys of the bucket
+  pub(crate) comparator: KeyComparator,
+  p: PhantomData<&'tx u8>,
+}
 
-  // Tree statistics.
-  /// number of keys/value pairs
-  pub(crate) key_n: i64,
-  /// number of levels in B+tree
-  depth: i64,
+impl<'tx> BucketR<'tx> {
+  pub fn new(in_bucket: BucketHeader) -> BucketR<'tx> {
+    BucketR {
+      bucket_header: in_bucket,
+      inline_page: None,
+      path: &[],
+      comparator: bytewise,
+      p: Default::default(),
+    }
+  }
+}
 
-  // Page size utilization.
-  /// bytes allocated for physical branch pages
-  branch_alloc: i64,
-  /// bytes actually used for branch data
-  branch_in_use: i64,
-  /// bytes allocated for physical leaf pages
-  leaf_alloc: i64,
-  /// bytes actually used for leaf data
-  leaf_in_use: i64,
+pub struct InnerBucketW<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> {
+  /// materialized node for the root page.
+  pub(crate) root_node: Option<NodeRwCell<'tx>>,
+  /// subbucket cache
+  buckets: HashMap<'tx, &'tx [u8], B>,
+  /// node cache
+  pub(crate) nodes: HashMap<'tx, PgId, NodeRwCell<'tx>>,
 
-  // Bucket statistics
-  /// total number of buckets including the top bucket
-  bucket_n: i64,
-  /// total number on inlined buckets
-  inline_bucket_n: i64,
-  /// bytes used for inlined buckets (also accounted for in LeafInuse)
-  inline_bucket_in_use: i64,
+  /// Sets the threshold for filling nodes when they split. By default,
+  /// the bucket will fill to 50% but it can be useful to increase this
+  /// amount if you know that your write workloads are mostly append-only.
+  ///
+  /// This is non-persisted across transactions so it must be set in every Tx.
+  pub(crate) fill_percent: f64,
+  /// set once the bucket is deleted. Its handles and cursors can't be used anymore
+  pub(crate) deleted: bool,
+  /// set by [BucketRwApi::force_materialize]. The bucket isn't inlined on commit.
+  pub(crate) force_paged: bool,
+  /// copies of leaf pages whose values were overwritten in place, by their page id.
+  /// See [BucketRwIApi::patch_leaf]
+  pub(crate) patched: HashMap<'tx, PgId, PatchedLeaf<'tx>>,
+  phantom_t: PhantomData<T>,
 }
 
-impl AddAssign<BucketStats> for BucketStats {
-  fn add_assign(&mut self, rhs: BucketStats) {
-    self.branch_page_n += rhs.branch_page_n;
-    self.branch_overflow_n += rhs.branch_overflow_n;
-    self.leaf_page_n += rhs.leaf_page_n;
-    self.leaf_overflow_n += rhs.leaf_overflow_n;
+/// A copy of a leaf page with values overwritten in place. See [BucketRwIApi::patch_leaf]
+#[derive(Copy, Clone)]
+pub(crate) struct PatchedLeaf<'tx> {
+  pub(crate) page: RefPage<'tx>,
+  /// the node pointing to the leaf. Rebalancing updates it when it moves the leaf's inode
+  pub(crate) parent: NodeRwCell<'tx>,
+}
 
-    self.key_n += rhs.key_n;
-    if self.depth < rhs.depth {
-      self.depth = rhs.depth;
+impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> InnerBucketW<'tx, T, B> {
+  pub fn new_in(bump: &'tx Bump) -> InnerBucketW<'tx, T, B> {
+    InnerBucketW {
+      root_node: None,
+      buckets: HashMap::with_hasher_in(MapHasher::default(), bump),
+      nodes: HashMap::with_hasher_in(MapHasher::default(), bump),
+      fill_percent: DEFAULT_FILL_PERCENT,
+      deleted: false,
+      force_paged: false,
+      patched: HashMap::with_hasher_in(MapHasher::default(), bump),
+      phantom_t: PhantomData,
     }
+  }
+}
 
-    self.branch_alloc += rhs.branch_alloc;
-    self.branch_in_use += rhs.branch_in_use;
-    self.leaf_alloc += rhs.leaf_alloc;
-    self.leaf_in_use += rhs.leaf_in_use;
+pub type BucketW<'tx> = InnerBucketW<'tx, TxCell<'tx>, BucketCell<'tx>>;
 
-    self.bucket_n += rhs.bucket_n;
-    self.inline_bucket_n += rhs.inline_bucket_n;
-    self.inline_bucket_in_use += rhs.inline_bucket_in_use;
-  }
+pub struct BucketRW<'tx> {
+  pub(crate) r: BucketR<'tx>,
+  pub(crate) w: Option<BucketW<'tx>>,
 }
 
-/// DefaultFillPercent is the percentage that split pages are filled.
-/// This value can be changed by setting Bucket.FillPercent.
-const DEFAULT_FILL_PERCENT: f64 = 0.5;
+#[derive(Copy, Clone)]
+pub(crate) struct BucketCell<'tx> {
+  pub(crate) cell: BCell<'tx, BucketRW<'tx>, TxCell<'tx>>,
+}
 
-/// MAX_KEY_SIZE is the maximum length of a key, in bytes.
-const MAX_KEY_SIZE: u32 = 32768;
+impl<'tx> SplitRef<BucketR<'tx>, TxCell<'tx>, BucketW<'tx>> for BucketCell<'tx> {
+  fn split_r(&self) -> Ref<BucketR<'tx>> {
+    Ref::map(self.cell.borrow(), |b| &b.r)
+  }
 
-/// MaxValueSize is the maximum length of a value, in bytes.
-const MAX_VALUE_SIZE: u32 = (1 << 31) - 2;
-const INLINE_BUCKET_ALIGNMENT: usize = mem::align_of::<InlineBucket>();
-const INLINE_BUCKET_SIZE: usize = mem::size_of::<InlineBucket>();
+  fn split_ref(&self) -> (Ref<BucketR<'tx>>, Ref<Option<BucketW<'tx>>>) {
+    let (r, w) = Ref::map_split(self.cell.borrow(), |b| (&b.r, &b.w));
+    (r, w)
+  }
 
-pub(crate) const MIN_FILL_PERCENT: f64 = 0.1;
-pub(crate) const MAX_FILL_PERCENT: f64 = 1.0;
+  fn split_ow(&self) -> Ref<Option<BucketW<'tx>>> {
+    Ref::map(self.cell.borrow(), |b| &b.w)
+  }
 
-/// A convenience struct representing an inline page header
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-struct InlineBucket {
-  header: BucketHeader,
-  page: PageHeader,
-}
+  fn split_bound(&self) -> TxCell<'tx> {
+    self.cell.bound()
+  }
 
-impl Default for InlineBucket {
-  fn default() -> Self {
-    InlineBucket {
-      header: BucketHeader::new(ZERO_PGID, 0),
-      page: PageHeader {
-        id: Default::default(),
-        flags: LEAF_PAGE_FLAG,
-        count: 0,
-        overflow: 0,
-      },
-    }
+  fn split_r_mut(&self) -> RefMut<BucketR<'tx>> {
+    RefMut::map(self.cell.borrow_mut(), |b| &mut b.r)
+  }
+
+  fn split_ow_mut(&self) -> RefMut<Option<BucketW<'tx>>> {
+    RefMut::map(self.cell.borrow_mut(), |b| &mut b.w)
   }
 }
 
-/// The internal Bucket API
-pub(crate) trait BucketIApi<'tx, T: TxIApi<'tx>>:
-  SplitRef<BucketR<'tx>, T, InnerBucketW<'tx, T, Self>>
-{
+impl<'tx> BucketIApi<'tx, TxCell<'tx>> for BucketCell<'tx> {
   fn new_r_in(
-    bump: &'tx Bump, bucket_header: BucketHeader, tx: T, inline_page: Option<RefPage<'tx>>,
-  ) -> Self;
+    bump: &'tx Bump, bucket_header: BucketHeader, tx: TxCell<'tx>,
+    inline_page: Option<RefPage<'tx>>,
+  ) -> Self {
+    let r = BucketR {
+      bucket_header,
+      inline_page,
+      path: &[],
+      comparator: bytewise,
+      p: Default::default(),
+    };
+
+    BucketCell {
+      cell: BCell::new_in(BucketRW { r, w: None }, tx, bump),
+    }
+  }
 
   fn new_rw_in(
-    bump: &'tx Bump, bucket_header: BucketHeader, tx: T, inline_page: Option<RefPage<'tx>>,
-  ) -> Self;
+    bump: &'tx Bump, bucket_header: BucketHeader, tx: TxCell<'tx>,
+    inline_page: Option<RefPage<'tx>>,
+  ) -> Self {
+    let r = BucketR {
+      bucket_header,
+      inline_page,
+      path: &[],
+      comparator: bytewise,
+      p: Default::default(),
+    };
 
-  /// Returns whether the bucket is writable.
-  fn is_writeable(&self) -> bool;
+    let w = BucketW::new_in(bump);
 
-  /// Returns the rc ptr Tx of the bucket
-  fn tx(self) -> T {
-    self.split_bound()
+    BucketCell {
+      cell: BCell::new_in(BucketRW { r, w: Some(w) }, tx, bump),
+    }
   }
 
-  /// Returns the root page id of the bucket
-  fn root(self) -> PgId {
-    self.split_r().bucket_header.root()
+  #[inline(always)]
+  fn is_writeable(&self) -> bool {
+    self.cell.borrow().w.is_some()
   }
 
-  /// Create a new cursor for this Bucket
-  fn i_cursor(self) -> InnerCursor<'tx, T, Self> {
+  fn cached_bucket(self, parent: PgId, name: &[u8]) -> Option<Self> {
     let tx = self.tx();
-    tx.split_r().stats.as_ref().unwrap().inc_cursor_count(1);
-    InnerCursor::new(self, tx.bump())
+    let r = tx.split_r();
+    r.bucket_cache
+      .as_ref()
+      .and_then(|cache| cache.get(parent, name))
   }
 
-  /// See [BucketApi::bucket]
-  fn api_bucket(self, name: &[u8]) -> Option<Self> {
-    if let Some(w) = self.split_ow().deref() {
-      if let Some(child) = w.buckets.get(name) {
-        return Some(*child);
-      }
-    }
-    let mut c = self.i_cursor();
-    // Move cursor to key.
-    let (k, v, flags) = c.i_seek(name)?;
-    // Return None if the key doesn't exist or it is not a bucket.
-    if name != k || (flags & BUCKET_LEAF_FLAG) == 0 {
-      return None;
-    }
-
-    // Otherwise create a bucket and cache it.
-    let child = self.open_bucket(v);
-    if let Some(ref mut w) = self.split_ow_mut().deref_mut() {
-      let tx = self.split_bound();
-      let bump = tx.bump();
-      let name = bump.alloc_slice_copy(name);
-      w.buckets.insert(name, child);
+  fn cache_bucket(self, parent: PgId, name: &[u8], child: Self) {
+    if let Some(cache) = &self.tx().split_r().bucket_cache {
+      cache.insert(parent, name, child);
     }
+  }
 
-    Some(child)
+  fn into_impl(self) -> BucketImpl<'tx> {
+    self.into()
   }
+}
 
-  /// Helper method that re-interprets a sub-bucket value
-  /// from a parent into a Bucket
-  fn open_bucket(self, mut value: &[u8]) -> Self {
-    let tx = self.tx();
-    let bump = tx.bump();
-    // Unaligned access requires a copy to be made.
-    //TODO: use std is_aligned_to when it comes out
-    if !IsAligned::is_aligned_to::<InlineBucket>(value.as_ptr()) {
-      // TODO: Shove this into a centralized function somewhere
-      let layout = Layout::from_size_align(value.len(), INLINE_BUCKET_ALIGNMENT).unwrap();
-      let new_value = unsafe {
-        let data = bump.alloc_layout(layout).as_ptr();
-        ptr::write_bytes(data, 0, value.len());
-        &mut *slice_from_raw_parts_mut(data, value.len())
-      };
-      new_value.copy_from_slice(value);
-      value = new_value;
-    }
-    let bucket_header = *bytemuck::from_bytes::<BucketHeader>(value.split_at(BUCKET_HEADER_SIZE).0);
-    // Save a reference to the inline page if the bucket is inline.
-    let ref_page = if bucket_header.root() == ZERO_PGID {
-      assert!(
-        value.len() >= INLINE_BUCKET_SIZE,
-        "subbucket value not large enough. Expected at least {} bytes. Was {}",
-        INLINE_BUCKET_SIZE,
-        value.len()
-      );
-      unsafe {
-        let ref_page_ptr = value.as_ptr().add(BUCKET_HEADER_SIZE);
-        Some(RefPage::new(ref_page_ptr))
+impl<'tx> BucketRwIApi<'tx> for BucketCell<'tx> {
+  fn materialize_root(self) -> NodeRwCell<'tx> {
+    let root_id = {
+      let bucket = self.cell.borrow();
+      match bucket.w.as_ref().unwrap().root_node {
+        None => bucket.r.bucket_header.root(),
+        Some(root_node) => return root_node,
       }
-    } else {
-      None
     };
-    if tx.split_ow().is_some() {
-      Self::new_rw_in(bump, bucket_header, tx, ref_page)
-    } else {
-      Self::new_r_in(bump, bucket_header, tx, ref_page)
-    }
+    self.node(root_id, None)
   }
 
-  /// See [BucketApi::get]
-  fn api_get(self, key: &[u8]) -> Option<&'tx [u8]> {
-    if let Some((k, v, flags)) = self.i_cursor().i_seek(key) {
-      // Return None if this is a bucket.
-      if (flags & BUCKET_LEAF_FLAG) != 0 {
-        return None;
-      }
-      // If our target node isn't the same key as what's passed in then return None.
-      if key != k {
-        return None;
-      }
-      Some(v)
-    } else {
-      None
+  fn api_create_bucket(self, key: &[u8]) -> crate::Result<Self> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    if key.is_empty() {
+      return Err(BucketNameRequired);
     }
-  }
-
-  /// See [BucketApi::for_each]
-  fn api_for_each<F: FnMut(&'tx [u8], Option<&'tx [u8]>) -> crate::Result<()>>(
-    self, mut f: F,
-  ) -> crate::Result<()> {
     let mut c = self.i_cursor();
-    let mut inode = c.api_first();
-    while let Some((k, v)) = inode {
-      f(k, v)?;
-      inode = c.api_next();
+
+    if let Some((k, _, flags)) = c.i_try_seek(key)? {
+      if self.comparator()(k, key).is_eq() {
+        if flags & BUCKET_LEAF_FLAG != 0 {
+          return Err(BucketExists);
+        }
+        return Err(IncompatibleValue);
+      }
     }
-    Ok(())
+
+    let inline_page = InlineBucket::default();
+    let layout = Layout::from_size_align(INLINE_BUCKET_SIZE, INLINE_BUCKET_ALIGNMENT).unwrap();
+    let bump = self.tx().bump();
+    let data = bump.alloc_layout(layout).as_ptr();
+
+    let value = unsafe {
+      ptr::write_bytes(data, 0, INLINE_BUCKET_SIZE);
+      from_raw_parts_mut(data, INLINE_BUCKET_SIZE)
+    };
+    value.copy_from_slice(bytemuck::bytes_of(&inline_page));
+    let key = bump.alloc_slice_clone(key) as &[u8];
+
+    self.record_undo(|_, path| Undo::CreateBucket { path, key });
+    c.node().put(key, key, value, ZERO_PGID, BUCKET_LEAF_FLAG)?;
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::CreateBucket { path, key });
+
+    self.split_r_mut().inline_page = None;
+
+    self.api_try_bucket(key)?.ok_or(BucketNotFound)
   }
 
-  /// See [BucketApi::for_each_bucket]
-  fn api_for_each_bucket<F: FnMut(&'tx [u8]) -> crate::Result<()>>(
-    self, mut f: F,
-  ) -> crate::Result<()> {
-    let mut c = self.i_cursor();
-    let mut inode = c.i_first();
-    while let Some((k, _, flags)) = inode {
-      if flags & BUCKET_LEAF_FLAG != 0 {
-        f(k)?;
+  fn api_create_bucket_if_not_exists(self, key: &[u8]) -> crate::Result<Self> {
+    match self.api_create_bucket(key) {
+      Ok(child) => Ok(child),
+      Err(error) => {
+        if error == BucketExists {
+          self.api_try_bucket(key)?.ok_or(BucketNotFound)
+        } else {
+          Err(error)
+        }
       }
-      inode = c.i_next();
     }
-    Ok(())
   }
 
-  /// forEachPage iterates over every page in a bucket, including inline pages.
-  fn for_each_page<F: FnMut(&RefPage<'tx>, usize, &mut BVec<PgId>)>(self, f: &mut F) {
-    let tx = self.tx();
-    let root = {
-      let r = self.split_r();
-      let root = r.bucket_header.root();
-      // If we have an inline page then just use that.
-      if let Some(page) = &r.inline_page {
-        let mut v = BVec::with_capacity_in(1, tx.bump());
-        v.push(root);
-        f(page, 0, &mut v);
-        return;
-      }
-      root
-    };
-    // Otherwise traverse the page hierarchy.
-    tx.for_each_page(root, f)
+  fn api_create_bucket_with_comparator(self, key: &[u8], comparator: &str) -> crate::Result<Self> {
+    let compare = self
+      .tx()
+      .comparators()
+      .get(comparator.as_bytes())
+      .ok_or_else(|| Error::UnknownComparator(comparator.to_string()))?;
+    let child = self.api_create_bucket(key)?;
+    child.i_set_meta(COMPARATOR_META_KEY, comparator.as_bytes())?;
+    // The new bucket is empty so its keys can't be out of order yet
+    child.split_r_mut().comparator = compare;
+    let path = child.split_r().path;
+    self.tx().split_r().cache_comparator(path, compare);
+    Ok(child)
   }
 
-  /// forEachPageNode iterates over every page (or node) in a bucket.
-  /// This also includes inline pages.
-  fn for_each_page_node<F: FnMut(&PageNode<'tx>, usize) + Copy>(self, mut f: F) {
-    let root = {
-      let r = self.split_r();
-      // If we have an inline page or root node then just use that.
-      if let Some(page) = &r.inline_page {
-        f(&PageNode::Page(*page), 0);
-        return;
-      }
-      r.bucket_header.root()
-    };
-    self._for_each_page_node(root, 0, f);
-  }
+  fn api_delete_bucket(self, key: &[u8]) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    let mut c = self.i_cursor();
 
-  fn _for_each_page_node<F: FnMut(&PageNode<'tx>, usize) + Copy>(
-    self, root: PgId, depth: usize, mut f: F,
-  ) {
-    let pn = self.page_node(root);
+    let (k, _, flags) = c.i_try_seek(key)?.unwrap_or((&[], &[], 0));
+    if !self.comparator()(key, k).is_eq() {
+      return Err(BucketNotFound);
+    } else if flags & BUCKET_LEAF_FLAG == 0 {
+      return Err(IncompatibleValue);
+    }
 
-    // Execute function.
-    f(&pn, depth);
+    let child = self.api_try_bucket(key)?.unwrap();
+    let path = child.split_r().path;
+    // An unmodified bucket is freed page by page, skipping its sub-buckets entirely. Undo
+    // records need every sub-bucket to be visited, though.
+    let untouched = child
+      .split_ow()
+      .as_ref()
+      .is_some_and(|w| w.root_node.is_none() && w.nodes.is_empty() && w.buckets.is_empty());
+    let walk_pages = untouched && !self.tx().undo_enabled();
+    // Sub-buckets deleted one at a time count their own keys against the quota
+    if self.quota_record()?.is_some() {
+      let usage = child.api_usage(walk_pages)?;
+      self.account(-(usage.keys as i64), -(usage.bytes as i64))?;
+    }
+    if !walk_pages {
+      child.api_for_each_bucket(|k| {
+        match child.api_delete_bucket(k) {
+          Ok(_) => Ok(()),
+          // TODO: Ideally we want to properly chain errors here
+          Err(e) => Err(Error::Other(e.into())),
+        }
+      })?;
+    }
 
-    // Recursively loop over children.
-    match &pn {
-      PageNode::Page(page) => {
-        if let Some(branch_page) = MappedBranchPage::coerce_ref(page) {
-          branch_page.elements().iter().for_each(|elem| {
-            self._for_each_page_node(elem.pgid(), depth + 1, f);
-          });
+    // Forget the metadata of the bucket and of its sub-buckets. Their entries all start with
+    // the bucket's path, so they are next to each other.
+    let mut comparator = None;
+    if let Some(meta) = self.tx().system_bucket(META_BUCKET) {
+      comparator = meta.api_get(&meta_key(path, COMPARATOR_META_KEY));
+      let mut entries = Vec::new();
+      let mut cursor = meta.i_cursor();
+      let mut entry = cursor.i_try_seek(path)?;
+      while let Some((entry_key, _, _)) = entry {
+        if !entry_key.starts_with(path) {
+          break;
         }
+        entries.push(entry_key.to_vec());
+        entry = cursor.i_next();
       }
-      PageNode::Node(node) => {
-        let bump = self.tx().bump();
-        // To keep with our rules we much copy the inode pgids to temporary storage first
-        // This should be unnecessary, but working first *then* optimize
-        let v = {
-          let node_borrow = node.cell.borrow();
-          let mut v = BVec::with_capacity_in(node_borrow.inodes.len(), bump);
-          let ids = node_borrow.inodes.iter().map(|inode| inode.pgid());
-          v.extend(ids);
-          v
-        };
-        v.into_iter()
-          .for_each(|pgid| self._for_each_page_node(pgid, depth + 1, f));
+      for entry in entries {
+        meta.api_delete(&entry)?;
       }
     }
-  }
+    self.tx().split_r().forget_comparators(path);
+
+    if self.tx().undo_enabled() {
+      // Sub-buckets were recorded when they were deleted, so only the values are left
+      child.api_for_each(|k, v| {
+        if let Some(v) = v {
+          child.record_undo(|bump, path| Undo::Put {
+            path,
+            key: bump.alloc_slice_copy(k),
+            prev: Some(bump.alloc_slice_copy(v)),
+          });
+        }
+        Ok(())
+      })?;
+      let sequence = child.split_r().bucket_header.sequence();
+      self.record_undo(|bump, path| Undo::DeleteBucket {
+        path,
+        key: bump.alloc_slice_copy(k),
+        comparator: comparator
+          .and_then(|c| std::str::from_utf8(c).ok())
+          .map(|c| &*bump.alloc_str(c)),
+        sequence,
+      });
+    }
 
-  fn page_node(self, id: PgId) -> PageNode<'tx> {
-    let (r, w) = self.split_ref();
-    // Inline buckets have a fake page embedded in their value so treat them
-    // differently. We'll return the rootNode (if available) or the fake page.
-    if r.bucket_header.root() == ZERO_PGID {
-      if id != ZERO_PGID {
-        panic!("inline bucket non-zero page access(2): {} != 0", id)
-      }
-      return if let Some(root_node) = w.as_ref().map(|wb| wb.root_node).flatten() {
-        PageNode::Node(root_node)
-      } else {
-        PageNode::Page(r.inline_page.unwrap())
-      };
+    {
+      let mut self_mut = self.cell.borrow_mut();
+      let self_w = self_mut.w.as_mut().unwrap();
+      self_w.buckets.remove(key);
+      let mut child_mut = child.cell.borrow_mut();
+      let child_w = child_mut.w.as_mut().unwrap();
+      child_w.nodes.clear();
+      child_w.root_node = None;
+      child_w.deleted = true;
     }
 
-    // Check the node cache for non-inline buckets.
-    if let Some(wb) = w.deref() {
-      if let Some(node) = wb.nodes.get(&id) {
-        return PageNode::Node(*node);
-      }
+    if walk_pages {
+      child.free_tree();
+    } else {
+      child.free();
     }
 
-    PageNode::Page(self.tx().mem_page(id))
+    c.node().del(key);
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::DeleteBucket { path, key });
+    Ok(())
   }
 
-  /// See [BucketApi::sequence]
-  fn api_sequence(self) -> u64 {
-    self.split_r().bucket_header.sequence()
+  fn api_put(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    self.check_put(key, value)?;
+    let mut c = self.i_cursor();
+    let kv = c.i_try_seek(key)?;
+    self.put_at(&mut c, kv, key, value, None)
   }
 
-  /// Returns the maximum total size of a bucket to make it a candidate for inlining.
-  fn max_inline_bucket_size(self) -> usize {
-    self.tx().page_size() / 4
-  }
+  fn api_put_many(self, pairs: &[(&[u8], &[u8])]) -> crate::Result<()> {
+    let comparator = self.comparator();
+    // A stable sort keeps duplicate keys in order so the last one wins
+    let mut order = (0..pairs.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| comparator(pairs[*a].0, pairs[*b].0));
 
-  /// See [BucketApi::stats]
-  fn api_stats(self) -> BucketStats {
-    let mut s = BucketStats::default();
-    let mut sub_stats = BucketStats::default();
-    let page_size = self.tx().page_size();
-    s.bucket_n += 1;
-    if self.root() == ZERO_PGID {
-      s.inline_bucket_n += 1;
+    let mut c = self.i_cursor();
+    for i in order {
+      let (key, value) = pairs[i];
+      self.check_put(key, value)?;
+      let kv = c.i_try_seek_from(key)?;
+      self.put_at(&mut c, kv, key, value, None)?;
+      // The put turned the pages on the stack into nodes
+      c.sync_stack();
     }
-    self.for_each_page(&mut |p, depth, _| {
-      if let Some(leaf_page) = MappedLeafPage::coerce_ref(p) {
-        s.key_n += p.count as i64;
+    Ok(())
+  }
 
-        // used totals the used bytes for the page
-        let mut used = PAGE_HEADER_SIZE;
-        if let Some(last_element) = leaf_page.elements().last() {
-          // If page has any elements, add all element headers.
-          used += LEAF_PAGE_ELEMENT_SIZE * (p.count - 1) as usize;
+  #[cfg(feature = "bytes")]
+  fn api_put_bytes(self, key: &[u8], value: Bytes) -> crate::Result<()> {
+    self.check_put(key, &value)?;
+    let held = self.tx().hold_bytes(value);
+    let mut c = self.i_cursor();
+    let kv = c.i_try_seek(key)?;
+    self.put_at(&mut c, kv, key, held, Some(held))
+  }
 
-          // Add all element key, value sizes.
-          // The computation takes advantage of the fact that the position
-          // of the last element's key/value equals to the total of the sizes
-          // of all previous elements' keys and values.
-          // It also includes the last element's header.
-          used += last_element.pos() as usize
-            + last_element.key_size() as usize
-            + last_element.value_size() as usize;
-        }
+  fn check_put(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    if key.is_empty() {
+      return Err(KeyRequired);
+    } else if key.len() > self.tx().max_key_size() {
+      return Err(KeyTooLarge);
+    } else if value.len() > self.tx().max_value_size() {
+      return Err(ValueTooLarge);
+    }
+    self.inc_metrics(|m| m.puts += 1);
+    Ok(())
+  }
 
-        // For inlined bucket just update the inline stats
-        if self.root() == ZERO_PGID {
-          s.inline_bucket_in_use += used as i64;
-        } else {
-          // For non-inlined bucket update all the leaf stats
-          s.leaf_page_n += 1;
-          s.leaf_in_use += used as i64;
-          s.leaf_overflow_n += leaf_page.overflow as i64;
+  fn i_put(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    if key.is_empty() {
+      return Err(KeyRequired);
+    } else if key.len() > MAX_KEY_SIZE as usize {
+      return Err(KeyTooLarge);
+    } else if value.len() > MAX_VALUE_SIZE as usize {
+      return Err(ValueTooLarge);
+    }
+    let mut c = self.i_cursor();
+    let kv = c.i_try_seek(key)?;
+    self.put_at(&mut c, kv, key, value, None)
+  }
 
-          // Collect stats from sub-buckets.
-          // Do that by iterating over all element headers
-          // looking for the ones with the bucketLeafFlag.
-          for leaf_elem in leaf_page.iter() {
-            if leaf_elem.is_bucket_entry() {
-              // For any bucket element, open the element value
-              // and recursively call Stats on the contained bucket.
-              sub_stats += self.open_bucket(leaf_elem.value()).api_stats();
-            }
-          }
-        }
-      } else if let Some(branch_page) = MappedBranchPage::coerce_ref(p) {
-        s.branch_page_n += 1;
-        if let Some(last_element) = branch_page.elements().last() {
-          // used totals the used bytes for the page
-          // Add header and all element headers.
-          let mut used =
-            PAGE_HEADER_SIZE + (BRANCH_PAGE_ELEMENT_SIZE * (branch_page.count - 1) as usize);
-          // Add size of all keys and values.
-          // Again, use the fact that last element's position equals to
-          // the total of key, value sizes of all previous elements.
-          used += last_element.pos() as usize + last_element.key_size() as usize;
-          s.branch_in_use += used as i64;
-          s.branch_overflow_n += branch_page.overflow as i64;
+  fn put_at(
+    self, c: &mut InnerCursor<'tx, TxCell<'tx>, Self>, kv: Option<(&'tx [u8], &'tx [u8], u32)>,
+    key: &[u8], value: &[u8], held: Option<&'tx [u8]>,
+  ) -> crate::Result<()> {
+    let mut prev = None;
+    if let Some((k, v, flags)) = kv {
+      if self.comparator()(key, k).is_eq() {
+        if (flags & BUCKET_LEAF_FLAG) != 0 {
+          return Err(IncompatibleValue);
         }
+        prev = Some(v);
       }
-
-      if (depth + 1) as i64 > s.depth {
-        s.depth = (depth + 1) as i64;
-      }
+    }
+    match prev {
+      Some(v) => self.account(0, value.len() as i64 - v.len() as i64)?,
+      None => self.account(1, (key.len() + value.len()) as i64)?,
+    }
+    self.record_undo(|bump, path| Undo::Put {
+      path,
+      key: bump.alloc_slice_copy(key),
+      prev: prev.map(|v| &*bump.alloc_slice_copy(v)),
     });
-    // Alloc stats can be computed from page counts and pageSize.
-    s.branch_alloc = (s.branch_page_n + s.branch_overflow_n) * page_size as i64;
-    s.leaf_alloc = (s.leaf_page_n + s.leaf_overflow_n) * page_size as i64;
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::Put { path, key, value });
+    // A value of the same size doesn't change the size of the leaf, so it is
+    // replaced where it is instead of removing and reinserting the key.
+    // Held values are never copied, so they are always reinserted.
+    if held.is_none() && prev.is_some_and(|v| v.len() == value.len()) {
+      c.replace_value(value);
+      return Ok(());
+    }
 
-    // Add the max depth of sub-buckets to get total nested depth.
-    s.depth += sub_stats.depth;
-    // Add the stats for all sub-buckets
-    s += sub_stats;
-    s
+    let bump = self.tx().bump();
+    let key = &*bump.alloc_slice_clone(key);
+    let value = match held {
+      Some(held) => held,
+      None => &*bump.alloc_slice_clone(value),
+    };
+    c.node().put(key, key, value, ZERO_PGID, 0)
   }
 
-  fn into_impl(self) -> BucketImpl<'tx>;
-}
-
-pub(crate) trait BucketRwIApi<'tx>: BucketIApi<'tx, TxCell<'tx>> {
-  /// Explicitly materialize the root node
-  fn materialize_root(self) -> NodeRwCell<'tx>;
-
-  /// See [BucketRwApi::create_bucket]
-  fn api_create_bucket(self, key: &[u8]) -> crate::Result<Self>;
-
-  /// See [BucketRwApi::create_bucket_if_not_exists]
-  fn api_create_bucket_if_not_exists(self, key: &[u8]) -> crate::Result<Self>;
-
-  /// See [BucketRwApi::delete_bucket]
-  fn api_delete_bucket(self, key: &[u8]) -> crate::Result<()>;
-
-  /// See [BucketRwApi::put]
-  fn api_put(self, key: &[u8], value: &[u8]) -> crate::Result<()>;
-
-  /// See [BucketRwApi::delete]
-  fn api_delete(self, key: &[u8]) -> crate::Result<()>;
+  fn api_delete(self, key: &[u8]) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    self.inc_metrics(|m| m.deletes += 1);
+    let mut c = self.i_cursor();
+    let (k, v, flags) = match c.i_try_seek(key)? {
+      Some(kv) => kv,
+      None => return Ok(()),
+    };
 
-  /// See [BucketRwApi::set_sequence]
-  fn api_set_sequence(self, v: u64) -> crate::Result<()>;
+    if !self.comparator()(key, k).is_eq() {
+      return Ok(());
+    }
 
-  /// See [BucketRwApi::next_sequence]
-  fn api_next_sequence(self) -> crate::Result<u64>;
+    if flags & BUCKET_LEAF_FLAG != 0 {
+      return Err(IncompatibleValue);
+    }
 
-  /// free recursively frees all pages in the bucket.
-  fn free(self);
+    self.account(-1, -((k.len() + v.len()) as i64))?;
+    self.record_undo(|bump, path| Undo::Put {
+      path,
+      key: bump.alloc_slice_copy(k),
+      prev: Some(bump.alloc_slice_copy(v)),
+    });
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::Delete { path, key });
+    c.node().del(key);
 
-  /// spill writes all the nodes for this bucket to dirty pages.
-  fn spill(self, bump: &'tx Bump) -> crate::Result<()>;
+    Ok(())
+  }
 
-  fn write(self, bump: &'tx Bump) -> &'tx [u8];
+  fn api_clear(self) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    if self.tx().undo_enabled() {
+      // Every key has to be recorded, so they are deleted one at a time
+      let mut entries = Vec::new();
+      self.api_for_each(|k, v| {
+        entries.push((k, v.is_none()));
+        Ok(())
+      })?;
+      for (k, is_bucket) in entries {
+        if is_bucket {
+          self.api_delete_bucket(k)?;
+        } else {
+          self.api_delete(k)?;
+        }
+      }
+      return Ok(());
+    }
 
-  /// inlineable returns true if a bucket is small enough to be written inline
-  /// and if it contains no subbuckets. Otherwise returns false.
-  fn inlineable(self) -> bool;
+    let mut names = Vec::new();
+    self.api_for_each_bucket(|k| {
+      names.push(k);
+      Ok(())
+    })?;
+    for name in names {
+      self.api_delete_bucket(name)?;
+    }
+    if self.quota_record()?.is_some() {
+      let usage = self.api_usage(false)?;
+      self.account(-(usage.keys as i64), -(usage.bytes as i64))?;
+    }
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::Clear { path });
+    self.free();
 
-  /// own_in removes all references to the old mmap.
-  fn own_in(self);
+    // Continue as an empty inline bucket
+    let bump = self.tx().bump();
+    let inline_bucket = bump.alloc(InlineBucket::default()) as *const InlineBucket;
+    {
+      let mut bucket = self.cell.borrow_mut();
+      bucket.r.inline_page =
+        Some(unsafe { RefPage::new(inline_bucket.cast::<u8>().add(BUCKET_HEADER_SIZE)) });
+      let w = bucket.w.as_mut().unwrap();
+      w.nodes.clear();
+      w.root_node = None;
+    }
+    self.materialize_root();
+    Ok(())
+  }
 
-  /// node creates a node from a page and associates it with a given parent.
-  fn node(self, pgid: PgId, parent: Option<NodeRwCell<'tx>>) -> NodeRwCell<'tx>;
+  fn api_set_sequence(self, v: u64) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.materialize_root();
+    let prev = self.split_r().bucket_header.sequence();
+    self.record_undo(|_, path| Undo::Sequence { path, prev });
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::Sequence { path, value: v });
+    self.split_r_mut().bucket_header.set_sequence(v);
+    Ok(())
+  }
 
-  /// rebalance attempts to balance all nodes.
-  fn rebalance(self);
-}
+  fn api_set_meta(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    if key.is_empty() {
+      return Err(KeyRequired);
+    } else if is_reserved(key) {
+      return Err(Error::ReservedName);
+    } else if key.len() > self.tx().max_key_size() {
+      return Err(KeyTooLarge);
+    } else if value.len() > self.tx().max_value_size() {
+      return Err(ValueTooLarge);
+    }
+    self.i_set_meta(key, value)
+  }
 
-pub struct BucketR<'tx> {
-  pub(crate) bucket_header: BucketHeader,
-  /// inline page reference
-  pub(crate) inline_page: Option<RefPage<'tx>>,
-  p: PhantomData<&'tx u8>,
-}
+  fn i_set_meta(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    self
+      .tx()
+      .system_bucket_mut(META_BUCKET)?
+      .i_put(&meta_key(self.split_r().path, key), value)
+  }
 
-impl<'tx> BucketR<'tx> {
-  pub fn new(in_bucket: BucketHeader) -> BucketR<'tx> {
-    BucketR {
-      bucket_header: in_bucket,
-      inline_page: None,
-      p: Default::default(),
+  fn api_delete_meta(self, key: &[u8]) -> crate::Result<()> {
+    if is_reserved(key) {
+      return Err(Error::ReservedName);
     }
+    self.i_delete_meta(key)
   }
-}
 
-pub struct InnerBucketW<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> {
-  /// materialized node for the root page.
-  pub(crate) root_node: Option<NodeRwCell<'tx>>,
-  /// subbucket cache
-  buckets: HashMap<'tx, &'tx [u8], B>,
-  /// node cache
-  pub(crate) nodes: HashMap<'tx, PgId, NodeRwCell<'tx>>,
+  fn i_delete_meta(self, key: &[u8]) -> crate::Result<()> {
+    match self.tx().system_bucket(META_BUCKET) {
+      Some(meta) => meta.api_delete(&meta_key(self.split_r().path, key)),
+      None => Ok(()),
+    }
+  }
 
-  /// Sets the threshold for filling nodes when they split. By default,
-  /// the bucket will fill to 50% but it can be useful to increase this
-  /// amount if you know that your write workloads are mostly append-only.
-  ///
-  /// This is non-persisted across transactions so it must be set in every Tx.
-  pub(crate) fill_percent: f64,
-  phantom_t: PhantomData<T>,
-}
+  fn api_set_quota(self, quota: Option<BucketQuota>) -> crate::Result<()> {
+    if path_names(self.split_r().path).len() != 1 || self.quota_key().is_none() {
+      return Err(IncompatibleValue);
+    }
+    match quota {
+      Some(quota) => {
+        let usage = self.api_usage(true)?;
+        self.i_set_meta(QUOTA_META_KEY, &QuotaRecord { quota, usage }.encode())
+      }
+      None => self.i_delete_meta(QUOTA_META_KEY),
+    }
+  }
 
-impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> InnerBucketW<'tx, T, B> {
-  pub fn new_in(bump: &'tx Bump) -> InnerBucketW<'tx, T, B> {
-    InnerBucketW {
-      root_node: None,
-      buckets: HashMap::with_capacity_in(0, bump),
-      nodes: HashMap::with_capacity_in(0, bump),
-      fill_percent: DEFAULT_FILL_PERCENT,
-      phantom_t: PhantomData,
+  fn api_force_materialize(self) -> crate::Result<()> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    // A materialized root is written on commit even if the bucket is unchanged
+    self.materialize_root();
+    if !self.set_force_paged(true) {
+      self.i_set_meta(PAGED_META_KEY, &[])?;
     }
+    Ok(())
   }
-}
 
-pub type BucketW<'tx> = InnerBucketW<'tx, TxCell<'tx>, BucketCell<'tx>>;
+  fn api_try_inline(self) -> crate::Result<bool> {
+    self.tx().check_deadline()?;
+    self.tx().record_mutation();
+    if self.set_force_paged(false) {
+      self.i_delete_meta(PAGED_META_KEY)?;
+    }
+    if self.cell.borrow().w.as_ref().unwrap().root_node.is_some() {
+      return Ok(self.inlineable());
+    }
+    // An unchanged inline bucket stays inline
+    if self.root() == ZERO_PGID {
+      return Ok(true);
+    }
+    // Only materialize a bucket that qualifies, so the others aren't rewritten
+    let page = self.tx().mem_page(self.root());
+    let leaf_page = match MappedLeafPage::coerce_ref(&page) {
+      Some(leaf_page) => leaf_page,
+      None => return Ok(false),
+    };
+    let mut size = PAGE_HEADER_SIZE;
+    for elem in leaf_page.elements() {
+      size += LEAF_PAGE_ELEMENT_SIZE + elem.key_size() as usize + elem.value_size() as usize;
+      if elem.is_bucket_entry() || size > self.max_inline_bucket_size() {
+        return Ok(false);
+      }
+    }
+    self.materialize_root();
+    Ok(true)
+  }
 
-pub struct BucketRW<'tx> {
-  pub(crate) r: BucketR<'tx>,
-  pub(crate) w: Option<BucketW<'tx>>,
-}
+  fn set_force_paged(self, force_paged: bool) -> bool {
+    let prev = mem::replace(
+      &mut self.cell.borrow_mut().w.as_mut().unwrap().force_paged,
+      force_paged,
+    );
+    if prev != force_paged {
+      self.record_undo(|_, path| Undo::ForcePaged { path, prev });
+    }
+    prev
+  }
 
-#[derive(Copy, Clone)]
-pub(crate) struct BucketCell<'tx> {
-  pub(crate) cell: BCell<'tx, BucketRW<'tx>, TxCell<'tx>>,
-}
+  fn account(self, keys: i64, bytes: i64) -> crate::Result<()> {
+    // Rolling back restores the recorded usage along with the keys
+    if self.tx().undoing() {
+      return Ok(());
+    }
+    let mut record = match self.quota_record()? {
+      Some(record) => record,
+      None => return Ok(()),
+    };
+    record.add(keys, bytes)?;
+    let key = self.quota_key().unwrap();
+    let meta = self.tx().system_bucket(META_BUCKET).unwrap();
+    meta.i_put(&key, &record.encode())
+  }
 
-impl<'tx> SplitRef<BucketR<'tx>, TxCell<'tx>, BucketW<'tx>> for BucketCell<'tx> {
-  fn split_r(&self) -> Ref<BucketR<'tx>> {
-    Ref::map(self.cell.borrow(), |b| &b.r)
+  fn api_next_sequence(self) -> crate::Result<u64> {
+    self.tx().check_deadline()?;
+    self.materialize_root();
+    let prev = self.split_r().bucket_header.sequence();
+    self.record_undo(|_, path| Undo::Sequence { path, prev });
+    let sequence = {
+      let mut r = self.split_r_mut();
+      r.bucket_header.inc_sequence();
+      r.bucket_header.sequence()
+    };
+    #[cfg(feature = "replication")]
+    self.record_change(|path| Change::Sequence {
+      path,
+      value: sequence,
+    });
+    Ok(sequence)
   }
 
-  fn split_ref(&self) -> (Ref<BucketR<'tx>>, Ref<Option<BucketW<'tx>>>) {
-    let (r, w) = Ref::map_split(self.cell.borrow(), |b| (&b.r, &b.w));
-    (r, w)
+  fn record_undo<F: FnOnce(&'tx Bump, &'tx [u8]) -> Undo<'tx>>(self, f: F) {
+    let tx = self.tx();
+    if tx.undo_enabled() {
+      let undo = f(tx.bump(), self.split_r().path);
+      tx.push_undo(undo);
+    }
   }
 
-  fn split_ow(&self) -> Ref<Option<BucketW<'tx>>> {
-    Ref::map(self.cell.borrow(), |b| &b.w)
+  #[cfg(feature = "replication")]
+  fn record_change<'a, F: FnOnce(&'tx [u8]) -> Change<'a>>(self, f: F)
+  where
+    'tx: 'a,
+  {
+    let path = self.split_r().path;
+    self.tx().record_change(f(path));
   }
 
-  fn split_bound(&self) -> TxCell<'tx> {
-    self.cell.bound()
+  fn api_snapshot(self) -> Option<Self> {
+    let tx = self.tx();
+    // Pages are copied on write, so the pages of the transaction's meta still hold the
+    // committed state
+    let root = tx.meta().root();
+    let mut bucket = Self::new_r_in(tx.bump(), root, tx, None);
+    for name in path_names(self.split_r().path) {
+      bucket = bucket.api_bucket(name)?;
+    }
+    Some(bucket)
   }
 
-  fn split_r_mut(&self) -> RefMut<BucketR<'tx>> {
-    RefMut::map(self.cell.borrow_mut(), |b| &mut b.r)
+  fn free(self) {
+    if self.split_r().bucket_header.root() == ZERO_PGID {
+      return;
+    }
+
+    let tx = self.tx();
+    let txid = tx.meta().txid();
+
+    self.for_each_page_node(|pn, _| match pn {
+      PageNode::Page(page) => tx.freelist_free_page(txid, page, "bucket free"),
+      PageNode::Node(node) => node.free(),
+    });
+    self.split_r_mut().bucket_header.set_root(ZERO_PGID);
   }
 
-  fn split_ow_mut(&self) -> RefMut<Option<BucketW<'tx>>> {
-    RefMut::map(self.cell.borrow_mut(), |b| &mut b.w)
+  fn free_tree(self) {
+    let root = self.split_r().bucket_header.root();
+    // Inline buckets have no pages and no sub-buckets
+    if root == ZERO_PGID {
+      return;
+    }
+
+    let tx = self.tx();
+    let txid = tx.meta().txid();
+    let mut stack = vec![root];
+    while let Some(pgid) = stack.pop() {
+      let page = tx.mem_page(pgid);
+      if let Some(branch_page) = MappedBranchPage::coerce_ref(&page) {
+        stack.extend(branch_page.elements().iter().map(|elem| elem.pgid()));
+      } else if let Some(leaf_page) = MappedLeafPage::coerce_ref(&page) {
+        for elem in leaf_page.iter().filter(|elem| elem.is_bucket_entry()) {
+          let header =
+            bytemuck::pod_read_unaligned::<BucketHeader>(&elem.value()[..BUCKET_HEADER_SIZE]);
+          if header.root() != ZERO_PGID {
+            stack.push(header.root());
+          }
+        }
+      }
+      tx.freelist_free_page(txid, &page, "bucket delete");
+    }
+    self.split_r_mut().bucket_header.set_root(ZERO_PGID);
   }
-}
 
-impl<'tx> BucketIApi<'tx, TxCell<'tx>> for BucketCell<'tx> {
-  fn new_r_in(
-    bump: &'tx Bump, bucket_header: BucketHeader, tx: TxCell<'tx>,
-    inline_page: Option<RefPage<'tx>>,
-  ) -> Self {
-    let r = BucketR {
-      bucket_header,
-      inline_page,
-      p: Default::default(),
+  /// spill writes all the nodes for this bucket to dirty pages.
+  fn spill(self, bump: &'tx Bump) -> crate::Result<()> {
+    // To keep with our rules we much copy the bucket entries to temporary storage first
+    // This should be unnecessary, but working first *then* optimize
+    let v = {
+      let bucket = self.cell.borrow();
+      let mut v = BVec::with_capacity_in(bucket.w.as_ref().unwrap().buckets.len(), bump);
+      // v.extend() would be more idiomatic, but I'm too tired atm to figure out why
+      // it's not working
+      for (name, child) in &bucket.w.as_ref().unwrap().buckets {
+        v.push((*name, *child));
+      }
+      v
     };
 
-    BucketCell {
-      cell: BCell::new_in(BucketRW { r, w: None }, tx, bump),
+    for (name, child) in v.into_iter() {
+      let force_paged = child.cell.borrow().w.as_ref().unwrap().force_paged;
+      let value = if !force_paged && child.inlineable() {
+        child.free();
+        child.write(bump)
+      } else {
+        child.spill(bump)?;
+        let layout = Layout::from_size_align(BUCKET_HEADER_SIZE, INLINE_BUCKET_ALIGNMENT).unwrap();
+        let inline_bucket_ptr = bump.alloc_layout(layout).as_ptr();
+        unsafe {
+          let inline_bucket = &mut (*(inline_bucket_ptr as *mut BucketHeader));
+          *inline_bucket = child.split_r().bucket_header;
+          from_raw_parts(inline_bucket_ptr, BUCKET_HEADER_SIZE)
+        }
+      };
+      if child.cell.borrow().w.as_ref().unwrap().root_node.is_none() {
+        continue;
+      }
+      let mut c = self.i_cursor();
+      let (k, _, flags) = c.i_seek(name).unwrap();
+      assert_eq!(name, k, "misplaced bucket header");
+      assert_ne!(
+        flags & BUCKET_LEAF_FLAG,
+        0,
+        "unexpected bucket header flag: {:x}",
+        flags
+      );
+
+      c.node()
+        .put(name, name, value, ZERO_PGID, BUCKET_LEAF_FLAG)?;
+    }
+
+    let root_node = match self.cell.borrow().w.as_ref().unwrap().root_node {
+      None => return Ok(()),
+      Some(root_node) => root_node,
+    };
+
+    self.write_patched()?;
+    root_node.spill()?;
+    {
+      let mut self_borrow = self.cell.borrow_mut();
+      let new_root = root_node.root();
+      self_borrow.w.as_mut().unwrap().root_node = Some(new_root);
+      let borrow_root = new_root.cell.borrow_mut();
+      let new_pgid = borrow_root.pgid;
+      let tx_pgid = self.cell.bound().meta().pgid();
+      if new_pgid >= tx_pgid {
+        panic!("pgid ({}) above high water mark ({})", new_pgid, tx_pgid);
+      }
+      self_borrow.r.bucket_header.set_root(new_pgid);
+    }
+    Ok(())
+  }
+
+  fn write(self, bump: &'tx Bump) -> &'tx [u8] {
+    let root_node = self.materialize_root();
+    let page_size = BUCKET_HEADER_SIZE + root_node.size();
+    let layout = Layout::from_size_align(page_size, INLINE_BUCKET_ALIGNMENT).unwrap();
+    let inline_bucket_ptr = bump.alloc_layout(layout).as_ptr();
+
+    unsafe {
+      let inline_bucket = &mut (*(inline_bucket_ptr as *mut BucketHeader));
+      *inline_bucket = self.cell.borrow().r.bucket_header;
+      let mut mut_page = MutPage::new(inline_bucket_ptr.add(BUCKET_HEADER_SIZE));
+      mut_page.id = PgId(0);
+      mut_page.count = 0;
+      mut_page.overflow = 0;
+      root_node.write(&mut mut_page);
+      from_raw_parts(inline_bucket_ptr, page_size)
     }
   }
 
-  fn new_rw_in(
-    bump: &'tx Bump, bucket_header: BucketHeader, tx: TxCell<'tx>,
-    inline_page: Option<RefPage<'tx>>,
-  ) -> Self {
-    let r = BucketR {
-      bucket_header,
-      inline_page,
-      p: Default::default(),
-    };
+  fn inlineable(self) -> bool {
+    let bucket = self.cell.borrow_mut();
 
-    let w = BucketW::new_in(bump);
+    // Bucket must only contain a single leaf node.
+    let n = match bucket.w.as_ref().unwrap().root_node {
+      None => return false,
+      Some(n) => n,
+    };
 
-    BucketCell {
-      cell: BCell::new_in(BucketRW { r, w: Some(w) }, tx, bump),
+    let node_ref = n.cell.borrow();
+    if !node_ref.is_leaf {
+      return false;
     }
-  }
 
-  #[inline(always)]
-  fn is_writeable(&self) -> bool {
-    self.cell.borrow().w.is_some()
-  }
+    // Bucket is not inlineable if it contains subbuckets or if it goes beyond
+    // our threshold for inline bucket size.
+    let mut size = PAGE_HEADER_SIZE;
+    for inode in node_ref.inodes.deref() {
+      size += LEAF_PAGE_ELEMENT_SIZE + inode.key().len() + inode.value().len();
 
-  fn into_impl(self) -> BucketImpl<'tx> {
-    self.into()
+      if inode.flags() & BUCKET_LEAF_FLAG != 0 || size > self.max_inline_bucket_size() {
+        return false;
+      }
+    }
+
+    true
   }
-}
 
-impl<'tx> BucketRwIApi<'tx> for BucketCell<'tx> {
-  fn materialize_root(self) -> NodeRwCell<'tx> {
-    let root_id = {
+  fn own_in(self) {
+    let (bump, root, children) = {
+      let tx = self.split_bound();
       let bucket = self.cell.borrow();
-      match bucket.w.as_ref().unwrap().root_node {
-        None => bucket.r.bucket_header.root(),
-        Some(root_node) => return root_node,
-      }
+      let bump = tx.bump();
+      let mut children: BVec<BucketCell<'tx>> =
+        BVec::with_capacity_in(bucket.w.as_ref().unwrap().buckets.len(), bump);
+      children.extend(bucket.w.as_ref().unwrap().buckets.values());
+      (bump, bucket.w.as_ref().unwrap().root_node, children)
     };
-    self.node(root_id, None)
-  }
 
-  fn api_create_bucket(self, key: &[u8]) -> crate::Result<Self> {
-    if key.is_empty() {
-      return Err(BucketNameRequired);
+    if let Some(node) = root {
+      node.root().own_in(bump);
     }
-    let mut c = self.i_cursor();
 
-    if let Some((k, _, flags)) = c.i_seek(key) {
-      if k == key {
-        if flags & BUCKET_LEAF_FLAG != 0 {
-          return Err(BucketExists);
-        }
-        return Err(IncompatibleValue);
-      }
+    for child in children.into_iter() {
+      child.own_in();
     }
+  }
 
-    let inline_page = InlineBucket::default();
-    let layout = Layout::from_size_align(INLINE_BUCKET_SIZE, INLINE_BUCKET_ALIGNMENT).unwrap();
-    let bump = self.tx().bump();
-    let data = bump.alloc_layout(layout).as_ptr();
+  fn node(self, pgid: PgId, parent: Option<NodeRwCell<'tx>>) -> NodeRwCell<'tx> {
+    let (inline_page, patched) = {
+      let mut self_borrow = self.cell.borrow_mut();
 
-    let value = unsafe {
-      ptr::write_bytes(data, 0, INLINE_BUCKET_SIZE);
-      from_raw_parts_mut(data, INLINE_BUCKET_SIZE)
+      // Retrieve node if it's already been created.
+      if let Some(n) = self_borrow.w.as_ref().unwrap().nodes.get(&pgid) {
+        return *n;
+      }
+      // The node takes over the values of a patched leaf
+      let patched = self_borrow.w.as_mut().unwrap().patched.remove(&pgid);
+      (self_borrow.r.inline_page, patched)
     };
-    value.copy_from_slice(bytemuck::bytes_of(&inline_page));
-    let key = bump.alloc_slice_clone(key) as &[u8];
-
-    c.node().put(key, key, value, ZERO_PGID, BUCKET_LEAF_FLAG);
-
-    self.split_r_mut().inline_page = None;
 
-    Ok(self.api_bucket(key).unwrap())
-  }
+    // Otherwise create a node and cache it.
+    // Use the inline page if this is an inline bucket.
+    let page = match (inline_page, patched) {
+      (Some(page), _) => page,
+      (None, Some(patched)) => patched.page,
+      (None, None) => self.tx().mem_page(pgid),
+    };
 
-  fn api_create_bucket_if_not_exists(self, key: &[u8]) -> crate::Result<Self> {
-    match self.api_create_bucket(key) {
-      Ok(child) => Ok(child),
-      Err(error) => {
-        if error == BucketExists {
-          Ok(self.api_bucket(key).unwrap())
-        } else {
-          Err(error)
-        }
-      }
+    // Read the page into the node and cache it.
+    let n = NodeRwCell::read_in(self, parent, &page);
+    let mut bucket = self.cell.borrow_mut();
+    let wb = &mut bucket.w;
+    match parent {
+      None => wb.as_mut().unwrap().root_node = Some(n),
+      Some(parent_node) => parent_node.cell.borrow_mut().children.push(n),
     }
-  }
 
-  fn api_delete_bucket(self, key: &[u8]) -> crate::Result<()> {
-    let mut c = self.i_cursor();
+    wb.as_mut().unwrap().nodes.insert(pgid, n);
 
-    let (k, _, flags) = c.i_seek(key).unwrap_or((&[], &[], 0));
-    if key != k {
-      return Err(BucketNotFound);
-    } else if flags & BUCKET_LEAF_FLAG == 0 {
-      return Err(IncompatibleValue);
-    }
+    // Update statistics.
+    self
+      .split_bound()
+      .split_r()
+      .stats
+      .as_ref()
+      .unwrap()
+      .inc_node_count(1);
 
-    let child = self.api_bucket(key).unwrap();
-    child.api_for_each_bucket(|k| {
-      match child.api_delete_bucket(k) {
-        Ok(_) => Ok(()),
-        // TODO: Ideally we want to properly chain errors here
-        Err(e) => Err(Error::Other(e.into())),
-      }
-    })?;
+    n
+  }
 
+  fn patch_leaf(
+    self, page: RefPage<'tx>, parent: NodeRwCell<'tx>, index: usize, value: &[u8],
+  ) -> Option<RefPage<'tx>> {
+    if self
+      .split_ow()
+      .as_ref()
+      .unwrap()
+      .patched
+      .contains_key(&page.id)
     {
-      let mut self_mut = self.cell.borrow_mut();
-      let self_w = self_mut.w.as_mut().unwrap();
-      self_w.buckets.remove(key);
-      let mut child_mut = child.cell.borrow_mut();
-      let child_w = child_mut.w.as_mut().unwrap();
-      child_w.nodes.clear();
-      child_w.root_node = None;
+      return None;
     }
-
-    child.free();
-
-    c.node().del(key);
-    Ok(())
+    let tx = self.tx();
+    let len = (page.overflow as usize + 1) * tx.page_size();
+    let src = &*page as *const PageHeader as *const u8;
+    let prev = MappedLeafPage::coerce_ref(&page)?
+      .get_elem(index as u16)?
+      .value();
+    assert_eq!(prev.len(), value.len(), "patched value size changed");
+    let offset = prev.as_ptr() as usize - src as usize;
+    let layout = Layout::from_size_align(len, mem::align_of::<PageHeader>()).unwrap();
+    let dst = tx.bump().alloc_layout(layout).as_ptr();
+    unsafe {
+      ptr::copy_nonoverlapping(src, dst, len);
+      from_raw_parts_mut(dst.add(offset), value.len()).copy_from_slice(value);
+    }
+    let patched = RefPage::new(dst);
+    self.split_ow_mut().as_mut().unwrap().patched.insert(
+      page.id,
+      PatchedLeaf {
+        page: patched,
+        parent,
+      },
+    );
+    Some(patched)
   }
 
-  fn api_put(self, key: &[u8], value: &[u8]) -> crate::Result<()> {
-    if key.is_empty() {
-      return Err(KeyRequired);
-    } else if key.len() > MAX_KEY_SIZE as usize {
-      return Err(KeyTooLarge);
-    } else if value.len() > MAX_VALUE_SIZE as usize {
-      return Err(ValueTooLarge);
-    }
-    let mut c = self.i_cursor();
-    if let Some((k, _, flags)) = c.i_seek(key) {
-      if key == k && (flags & BUCKET_LEAF_FLAG) != 0 {
-        return Err(IncompatibleValue);
+  fn write_patched(self) -> crate::Result<()> {
+    let tx = self.tx();
+    let patched = {
+      let mut bucket = self.cell.borrow_mut();
+      let w = bucket.w.as_mut().unwrap();
+      BVec::from_iter_in(w.patched.drain(), tx.bump())
+    };
+    let page_size = tx.page_size();
+    let checksums = tx.page_checksums();
+    let txid = tx.api_id();
+    for (pgid, PatchedLeaf { page, parent }) in patched {
+      let key = parent
+        .cell
+        .borrow()
+        .inodes
+        .iter()
+        .find(|inode| inode.pgid() == pgid)
+        .map(|inode| inode.key())
+        .ok_or_else(|| {
+          Error::InvalidInternalState(format!("patched leaf {} has no parent", pgid))
+        })?;
+      tx.freelist_free_page(txid, &tx.mem_page(pgid), "patched leaf");
+
+      let count = page.overflow as usize + 1;
+      let len = count * page_size;
+      let mut p = tx.allocate(count)?;
+      let id = p.id;
+      unsafe {
+        ptr::copy_nonoverlapping(
+          &*page as *const PageHeader as *const u8,
+          &mut **p as *mut PageHeader as *mut u8,
+          len,
+        );
+      }
+      p.id = id;
+      if checksums || p.has_checksum() {
+        p.seal_checksum(len);
       }
+      tx.queue_page(p);
+      parent.put(key, key, &[], id, 0)?;
     }
-
-    let bump = self.tx().bump();
-    let key = &*bump.alloc_slice_clone(key);
-    let value = &*bump.alloc_slice_clone(value);
-    c.node().put(key, key, value, ZERO_PGID, 0);
     Ok(())
   }
 
-  fn api_delete(self, key: &[u8]) -> crate::Result<()> {
-    let mut c = self.i_cursor();
-    let (k, _, flags) = c.i_seek(key).unwrap();
-
-    if key != k {
-      return Ok(());
+  fn rebalance(self) {
+    let bump = self.tx().bump();
+    let (nodes, buckets) = {
+      let borrow = self.cell.borrow();
+      let nodes = BVec::from_iter_in(borrow.w.as_ref().unwrap().nodes.values().cloned(), bump);
+      let buckets = BVec::from_iter_in(borrow.w.as_ref().unwrap().buckets.values().cloned(), bump);
+      (nodes, buckets)
+    };
+    let _nodes = nodes.as_slice();
+    for node in nodes.into_iter() {
+      node.rebalance();
     }
-
-    if flags & BUCKET_LEAF_FLAG != 0 {
-      return Err(IncompatibleValue);
+    for bucket in buckets.into_iter() {
+      bucket.rebalance();
     }
+  }
+}
 
-    c.node().del(key);
+#[cfg(test)]
+mod tests {
+  use crate::bucket::{BucketImpl, BucketRwImpl, MAX_VALUE_SIZE};
+  use crate::common::page::PAGE_HEADER_SIZE;
+  use crate::comparator::META_BUCKET;
+  use crate::test_support::TestDb;
+  use crate::tx::TxIApi;
+  use crate::{
+    BoltOptions, BucketApi, BucketQuota, BucketRwApi, BucketStats, Comparators, CursorApi, DbApi,
+    DbRwAPI, Error, QuotaUsage, TxApi, TxRwRefApi,
+  };
+  use anyhow::anyhow;
+  use itertools::Itertools;
+  use rand::rngs::StdRng;
+  use rand::seq::SliceRandom;
+  use rand::SeedableRng;
+  use std::fs::OpenOptions;
+  use std::io::{Seek, SeekFrom, Write};
+  use std::sync::atomic::{AtomicU32, Ordering};
 
+  #[test]
+  fn test_bucket_get_non_existent() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let b = tx.create_bucket(b"widgets")?;
+      assert_eq!(None, b.get(b"foo"));
+      Ok(())
+    })?;
     Ok(())
   }
 
-  fn api_set_sequence(self, v: u64) -> crate::Result<()> {
-    self.materialize_root();
-    self.split_r_mut().bucket_header.set_sequence(v);
+  #[test]
+  fn test_bucket_get_from_node() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      b.put(b"foo", b"bar")?;
+      assert_eq!(Some(b"bar".as_slice()), b.get(b"foo"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_bucket_get_incompatible_value() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let _ = tx.create_bucket(b"widgets")?;
+      tx.bucket_mut(b"widgets").unwrap().create_bucket(b"foo")?;
+      assert_eq!(None, tx.bucket(b"widgets").unwrap().get(b"foo"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  // Ensure that a slice returned from a bucket has a capacity equal to its length.
+  // This also allows slices to be appended to since it will require a realloc by Go.
+  //
+  // https://github.com/boltdb/bolt/issues/544
+  #[test]
+  #[ignore]
+  fn test_bucket_get_capacity() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      b.put(b"key", b"val")?;
+      Ok(())
+    })?;
+    db.update(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      let mut c = b.cursor();
+      if let Some((_k, Some(_v))) = c.first() {
+        todo!("We don't allow modifying values in place for this first version");
+      }
+      Ok(())
+    })?;
     Ok(())
   }
 
-  fn api_next_sequence(self) -> crate::Result<u64> {
-    self.materialize_root();
-    let mut r = self.split_r_mut();
-    r.bucket_header.inc_sequence();
-    Ok(r.bucket_header.sequence())
+  #[test]
+  fn test_bucket_put() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      {
+        let mut b = tx.create_bucket(b"widgets")?;
+        b.put(b"foo", b"bar")?;
+      }
+
+      assert_eq!(
+        Some(b"bar".as_slice()),
+        tx.bucket(b"widgets").unwrap().get(b"foo")
+      );
+      Ok(())
+    })
   }
 
-  fn free(self) {
-    if self.split_r().bucket_header.root() == ZERO_PGID {
-      return;
-    }
+  #[test]
+  fn test_bucket_put_repeat() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      {
+        let mut b = tx.create_bucket(b"widgets")?;
+        b.put(b"foo", b"bar")?;
+        b.put(b"foo", b"baz")?;
+      }
 
-    let tx = self.tx();
-    let txid = tx.meta().txid();
+      assert_eq!(
+        Some(b"baz".as_slice()),
+        tx.bucket(b"widgets").unwrap().get(b"foo")
+      );
+      Ok(())
+    })
+  }
 
-    self.for_each_page_node(|pn, _| match pn {
-      PageNode::Page(page) => tx.freelist_free_page(txid, page),
-      PageNode::Node(node) => node.free(),
-    });
-    self.split_r_mut().bucket_header.set_root(ZERO_PGID);
+  #[test]
+  fn test_bucket_put_same_size() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), 0u64.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      for i in (0..1000u32).step_by(3) {
+        b.put(i.to_be_bytes(), (i as u64).to_be_bytes())?;
+        b.put(i.to_be_bytes(), (i as u64 + 1).to_be_bytes())?;
+      }
+      b.put(1u32.to_be_bytes(), b"longer than before")?;
+      assert_eq!(
+        Some(7u64.to_be_bytes().as_slice()),
+        b.get(6u32.to_be_bytes())
+      );
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      assert_eq!(1000, b.key_n());
+      for i in 0..1000u32 {
+        let expected = match i {
+          1 => b"longer than before".to_vec(),
+          i if i % 3 == 0 => (i as u64 + 1).to_be_bytes().to_vec(),
+          _ => 0u64.to_be_bytes().to_vec(),
+        };
+        assert_eq!(Some(expected.as_slice()), b.get(i.to_be_bytes()));
+      }
+      Ok(())
+    })
   }
 
-  /// spill writes all the nodes for this bucket to dirty pages.
-  fn spill(self, bump: &'tx Bump) -> crate::Result<()> {
-    // To keep with our rules we much copy the bucket entries to temporary storage first
-    // This should be unnecessary, but working first *then* optimize
-    let v = {
-      let bucket = self.cell.borrow();
-      let mut v = BVec::with_capacity_in(bucket.w.as_ref().unwrap().buckets.len(), bump);
-      // v.extend() would be more idiomatic, but I'm too tired atm to figure out why
-      // it's not working
-      for (name, child) in &bucket.w.as_ref().unwrap().buckets {
-        v.push((*name, *child));
+  #[test]
+  fn test_bucket_put_same_size_in_place() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), 0u64.to_be_bytes())?;
       }
-      v
+      Ok(())
+    })?;
+    // Returns the nodes materialized and spilled by a put
+    let mut churn = |value: &[u8]| -> crate::Result<(i64, i64)> {
+      let stats = db.stats();
+      let before = (stats.tx_stats().node_count(), stats.tx_stats().spill());
+      db.update(|mut tx| {
+        let mut b = tx.bucket_mut(b"widgets").unwrap();
+        b.put(500u32.to_be_bytes(), value)?;
+        assert_eq!(Some(value), b.get(500u32.to_be_bytes()));
+        Ok(())
+      })?;
+      let stats = db.stats();
+      Ok((
+        stats.tx_stats().node_count() - before.0,
+        stats.tx_stats().spill() - before.1,
+      ))
     };
+    let (in_place_nodes, in_place_spills) = churn(&1u64.to_be_bytes())?;
+    let (nodes, spills) = churn(b"longer than before")?;
+    // The leaf is written as a copy of its page without materializing it
+    assert!(in_place_nodes < nodes);
+    assert!(in_place_spills < spills);
 
-    for (name, child) in v.into_iter() {
-      let value = if child.inlineable() {
-        child.free();
-        child.write(bump)
-      } else {
-        child.spill(bump)?;
-        let layout = Layout::from_size_align(BUCKET_HEADER_SIZE, INLINE_BUCKET_ALIGNMENT).unwrap();
-        let inline_bucket_ptr = bump.alloc_layout(layout).as_ptr();
-        unsafe {
-          let inline_bucket = &mut (*(inline_bucket_ptr as *mut BucketHeader));
-          *inline_bucket = child.split_r().bucket_header;
-          from_raw_parts(inline_bucket_ptr, BUCKET_HEADER_SIZE)
-        }
-      };
-      if child.cell.borrow().w.as_ref().unwrap().root_node.is_none() {
-        continue;
-      }
-      let mut c = self.i_cursor();
-      let (k, _, flags) = c.i_seek(name).unwrap();
-      assert_eq!(name, k, "misplaced bucket header");
-      assert_ne!(
-        flags & BUCKET_LEAF_FLAG,
-        0,
-        "unexpected bucket header flag: {:x}",
-        flags
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      assert_eq!(1000, b.key_n());
+      assert_eq!(
+        Some(b"longer than before".as_slice()),
+        b.get(500u32.to_be_bytes())
       );
+      assert_eq!(
+        Some(0u64.to_be_bytes().as_slice()),
+        b.get(501u32.to_be_bytes())
+      );
+      Ok(())
+    })
+  }
 
-      c.node().put(name, name, value, ZERO_PGID, BUCKET_LEAF_FLAG);
-    }
-
-    let root_node = match self.cell.borrow().w.as_ref().unwrap().root_node {
-      None => return Ok(()),
-      Some(root_node) => root_node,
-    };
+  #[test]
+  #[cfg(not(miri))]
+  fn test_bucket_put_same_size_in_place_rebalanced() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 0..50000u32 {
+        b.put(i.to_be_bytes(), 0u64.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      b.put(25000u32.to_be_bytes(), 1u64.to_be_bytes())?;
+      // Empty the leaves around the patched one so rebalancing merges its parent
+      for i in (5000..24800u32).chain(25200..45000) {
+        b.delete(i.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      assert_eq!(50000 - 19800 - 19800, b.key_n());
+      assert_eq!(
+        Some(1u64.to_be_bytes().as_slice()),
+        b.get(25000u32.to_be_bytes())
+      );
+      assert_eq!(
+        Some(0u64.to_be_bytes().as_slice()),
+        b.get(25001u32.to_be_bytes())
+      );
+      Ok(())
+    })
+  }
 
-    root_node.spill()?;
-    {
-      let mut self_borrow = self.cell.borrow_mut();
-      let new_root = root_node.root();
-      self_borrow.w.as_mut().unwrap().root_node = Some(new_root);
-      let borrow_root = new_root.cell.borrow_mut();
-      let new_pgid = borrow_root.pgid;
-      let tx_pgid = self.cell.bound().meta().pgid();
-      if new_pgid >= tx_pgid {
-        panic!("pgid ({}) above high water mark ({})", new_pgid, tx_pgid);
+  #[test]
+  #[cfg(not(miri))]
+  fn test_bucket_put_large() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let count = 100;
+    let factor = 200;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 1..count {
+        b.put(
+          "0".repeat(i * factor).as_bytes(),
+          "X".repeat((count - i) * factor).as_bytes(),
+        )?;
       }
-      self_borrow.r.bucket_header.set_root(new_pgid);
-    }
-    Ok(())
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      for i in 1..count {
+        let v = b.get("0".repeat(i * factor).as_bytes()).unwrap();
+        assert_eq!((count - i) * factor, v.len());
+        v.iter().all(|c| c == &b'X');
+      }
+      Ok(())
+    })
   }
 
-  fn write(self, bump: &'tx Bump) -> &'tx [u8] {
-    let root_node = self.materialize_root();
-    let page_size = BUCKET_HEADER_SIZE + root_node.size();
-    let layout = Layout::from_size_align(page_size, INLINE_BUCKET_ALIGNMENT).unwrap();
-    let inline_bucket_ptr = bump.alloc_layout(layout).as_ptr();
+  #[test]
+  #[cfg(all(not(miri), feature = "long-tests"))]
 
-    unsafe {
-      let inline_bucket = &mut (*(inline_bucket_ptr as *mut BucketHeader));
-      *inline_bucket = self.cell.borrow().r.bucket_header;
-      let mut mut_page = MutPage::new(inline_bucket_ptr.add(BUCKET_HEADER_SIZE));
-      mut_page.id = PgId(0);
-      mut_page.count = 0;
-      mut_page.overflow = 0;
-      root_node.write(&mut mut_page);
-      from_raw_parts(inline_bucket_ptr, page_size)
+  fn test_db_put_very_large() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let n = 400000u64;
+    let batch_n = 200000u64;
+
+    let v = [0u8; 500];
+    for i in (0..n).step_by(batch_n as usize) {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists(b"widgets")?;
+        for j in 0..batch_n {
+          b.put((i + j).to_be_bytes().as_slice(), v)?;
+        }
+        Ok(())
+      })?;
     }
+    Ok(())
   }
 
-  fn inlineable(self) -> bool {
-    let bucket = self.cell.borrow_mut();
-
-    // Bucket must only contain a single leaf node.
-    let n = match bucket.w.as_ref().unwrap().root_node {
-      None => return false,
-      Some(n) => n,
-    };
+  #[test]
+  fn test_bucket_put_incompatible_value() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let _ = tx.create_bucket(b"widgets")?;
+      tx.bucket_mut(b"widgets").unwrap().create_bucket(b"foo")?;
 
-    let node_ref = n.cell.borrow();
-    if !node_ref.is_leaf {
-      return false;
-    }
+      assert_eq!(
+        Err(Error::IncompatibleValue),
+        tx.bucket_mut(b"widgets").unwrap().put(b"foo", b"bar")
+      );
+      Ok(())
+    })
+  }
 
-    // Bucket is not inlineable if it contains subbuckets or if it goes beyond
-    // our threshold for inline bucket size.
-    let mut size = PAGE_HEADER_SIZE;
-    for inode in node_ref.inodes.deref() {
-      size += LEAF_PAGE_ELEMENT_SIZE + inode.key().len() + inode.value().len();
+  #[test]
+  fn test_bucket_delete() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      b.put(b"foo", b"bar")?;
+      b.delete(b"foo")?;
+      assert_eq!(None, b.get(b"foo"));
+      Ok(())
+    })
+  }
 
-      if inode.flags() & BUCKET_LEAF_FLAG != 0 || size > self.max_inline_bucket_size() {
-        return false;
+  #[test]
+  #[cfg(not(miri))]
+  fn test_bucket_delete_large() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let var = [b'*'; 1024];
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 0..100 {
+        b.put(format!("{}", i).as_bytes(), var)?;
       }
-    }
-
-    true
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      for i in 0..100 {
+        b.delete(format!("{}", i).as_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      for i in 0..100 {
+        assert_eq!(None, b.get(format!("{}", i).as_bytes()));
+      }
+      Ok(())
+    })?;
+    Ok(())
   }
 
-  fn own_in(self) {
-    let (bump, root, children) = {
-      let tx = self.split_bound();
-      let bucket = self.cell.borrow();
-      let bump = tx.bump();
-      let mut children: BVec<BucketCell<'tx>> =
-        BVec::with_capacity_in(bucket.w.as_ref().unwrap().buckets.len(), bump);
-      children.extend(bucket.w.as_ref().unwrap().buckets.values());
-      (bump, bucket.w.as_ref().unwrap().root_node, children)
-    };
-
-    if let Some(node) = root {
-      node.root().own_in(bump);
-    }
+  #[test]
+  #[cfg(all(not(miri), feature = "long_tests"))]
+  fn test_bucket_delete_freelist_overflow() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
 
-    for child in children.into_iter() {
-      child.own_in();
+    //TODO: make this based off of page size
+    for i in 0u64..8192 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists(b"0")?;
+        for j in 0u64..1000 {
+          let mut k = [0u8; 16];
+          let (k0, k1) = k.split_at_mut(8);
+          k0.copy_from_slice(i.to_be_bytes().as_slice());
+          k1.copy_from_slice(j.to_be_bytes().as_slice());
+          b.put(k, [])?;
+        }
+        Ok(())
+      })?;
     }
-  }
-
-  fn node(self, pgid: PgId, parent: Option<NodeRwCell<'tx>>) -> NodeRwCell<'tx> {
-    let inline_page = {
-      let self_borrow = self.cell.borrow_mut();
-
-      // Retrieve node if it's already been created.
-      if let Some(n) = self_borrow.w.as_ref().unwrap().nodes.get(&pgid) {
-        return *n;
+    db.update(|mut tx| {
+      let b = tx.bucket_mut(b"0").unwrap();
+      let mut c = b.cursor_mut();
+      let mut node = c.first();
+      while node.is_some() {
+        c.delete()?;
+        node = c.next();
       }
-      self_borrow.r.inline_page
-    };
+      Ok(())
+    })?;
+    let stats = db.stats();
+    let free_page_n = stats.free_page_n();
+    let pending_page_n = stats.pending_page_n();
+    let free_pages = free_page_n + pending_page_n;
+    assert!(
+      free_pages > 0xFFFF,
+      "expected more than 0xFFFF free pages. Got {}",
+      free_pages
+    );
+    db.must_close();
+    db.must_reopen();
+    let stats = db.stats();
+    let reopen_free_pages = stats.free_page_n();
+    assert_eq!(
+      free_pages, reopen_free_pages,
+      "Expected {} free pages, got {:?}",
+      free_pages, reopen_free_pages
+    );
+    Ok(())
+  }
 
-    // Otherwise create a node and cache it.
-    // Use the inline page if this is an inline bucket.
-    let page = match inline_page {
-      None => self.tx().mem_page(pgid),
-      Some(page) => page,
-    };
+  #[test]
+  fn test_bucket_delete_non_existing() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      let _ = b.create_bucket(b"nested")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      b.delete(b"foo")?;
+      assert!(
+        b.bucket(b"nested").is_some(),
+        "nested bucket has been deleted"
+      );
+      Ok(())
+    })?;
+    Ok(())
+  }
 
-    // Read the page into the node and cache it.
-    let n = NodeRwCell::read_in(self, parent, &page);
-    let mut bucket = self.cell.borrow_mut();
-    let wb = &mut bucket.w;
-    match parent {
-      None => wb.as_mut().unwrap().root_node = Some(n),
-      Some(parent_node) => parent_node.cell.borrow_mut().children.push(n),
-    }
+  #[test]
+  #[cfg(not(miri))]
+  fn test_bucket_nested() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      // Create a widgets bucket.
+      let mut b = tx.create_bucket(b"widgets")?;
 
-    wb.as_mut().unwrap().nodes.insert(pgid, n);
+      // Create a widgets/foo bucket.
+      let _ = b.create_bucket(b"foo")?;
 
-    // Update statistics.
-    self
-      .split_bound()
-      .split_r()
-      .stats
-      .as_ref()
-      .unwrap()
-      .inc_node_count(1);
+      // Create a widgets/bar key.
+      b.put(b"bar", b"0000")?;
+      Ok(())
+    })?;
+    db.must_check();
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      b.put(b"bar", b"xxxx")?;
+      Ok(())
+    })?;
+    db.must_check();
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      for i in 0..10000 {
+        let s = format!("{}", i);
+        b.put(s.as_bytes(), s.as_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.must_check();
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut(b"widgets").unwrap();
+      {
+        let mut foo = b.bucket_mut(b"foo").unwrap();
+        foo.put(b"baz", b"yyyy")?;
+      }
+      b.put(b"bar", b"xxxx")?;
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      let foo = b.bucket(b"foo").unwrap();
+      assert_eq!(Some(b"yyyy".as_slice()), foo.get(b"baz"));
+      assert_eq!(Some(b"xxxx".as_slice()), b.get(b"bar"));
 
-    n
+      for i in 0..10000 {
+        let s = format!("{}", i);
+        assert_eq!(Some(s.as_bytes()), b.get(s.as_bytes()));
+      }
+      Ok(())
+    })?;
+    Ok(())
   }
 
-  fn rebalance(self) {
-    let bump = self.tx().bump();
-    let (nodes, buckets) = {
-      let borrow = self.cell.borrow();
-      let nodes = BVec::from_iter_in(borrow.w.as_ref().unwrap().nodes.values().cloned(), bump);
-      let buckets = BVec::from_iter_in(borrow.w.as_ref().unwrap().buckets.values().cloned(), bump);
-      (nodes, buckets)
-    };
-    let _nodes = nodes.as_slice();
-    for node in nodes.into_iter() {
-      node.rebalance();
-    }
-    for bucket in buckets.into_iter() {
-      bucket.rebalance();
-    }
+  #[test]
+  fn test_bucket_delete_bucket() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      let _ = b.create_bucket(b"foo")?;
+      assert_eq!(Err(Error::IncompatibleValue), b.delete(b"foo"));
+      Ok(())
+    })?;
+    Ok(())
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use crate::bucket::MAX_VALUE_SIZE;
-  use crate::test_support::TestDb;
-  use crate::{
-    BucketApi, BucketRwApi, BucketStats, CursorApi, DbApi, DbRwAPI, Error, TxApi, TxRwRefApi,
-  };
-  use anyhow::anyhow;
-  use itertools::Itertools;
-  use rand::rngs::StdRng;
-  use rand::seq::SliceRandom;
-  use rand::SeedableRng;
-  use std::sync::atomic::{AtomicU32, Ordering};
+  #[test]
+  // Ensure that deleting a bucket causes nested buckets to be deleted.
+  fn test_bucket_delete_bucket_nested() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      {
+        let mut widgets = tx.create_bucket(b"widgets")?;
+        let mut foo = widgets.create_bucket(b"foo")?;
+        let mut bar = foo.create_bucket(b"bar")?;
+        bar.put(b"baz", b"bat")?;
+      }
+      tx.bucket_mut(b"widgets").unwrap().delete_bucket(b"foo")?;
+      Ok(())
+    })?;
+    Ok(())
+  }
 
   #[test]
-  fn test_bucket_get_non_existent() -> crate::Result<()> {
+  // Ensure that deleting a bucket causes nested buckets to be deleted after they have been committed.
+  fn test_bucket_delete_bucket_nested2() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let b = tx.create_bucket(b"widgets")?;
-      assert_eq!(None, b.get(b"foo"));
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      let mut foo = widgets.create_bucket(b"foo")?;
+      let mut bar = foo.create_bucket(b"bar")?;
+      bar.put(b"baz", b"bat")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      {
+        let widgets = tx.bucket(b"widgets").unwrap();
+        let foo = widgets.bucket(b"foo").unwrap();
+        let bar = foo.bucket(b"bar").unwrap();
+        assert_eq!(Some(b"bat".as_slice()), bar.get(b"baz"));
+      }
+      tx.delete_bucket(b"widgets")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      assert!(tx.bucket(b"widgets").is_none());
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_get_from_node() -> crate::Result<()> {
+  #[cfg(not(miri))]
+  // Ensure that deleting a child bucket with multiple pages causes all pages to get collected.
+  // NOTE: Consistency check in bolt_test.DB.Close() will panic if pages not freed properly.
+  fn test_bucket_delete_bucket_large() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"foo", b"bar")?;
-      assert_eq!(Some(b"bar".as_slice()), b.get(b"foo"));
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      let mut foo = widgets.create_bucket(b"foo")?;
+      for i in 0..1000 {
+        let k = format!("{}", i);
+        let v = format!("{:0100}", i);
+        foo.put(k.as_bytes(), v.as_bytes())?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      tx.delete_bucket(b"widgets")?;
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_get_incompatible_value() -> crate::Result<()> {
-    let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      let _ = tx.create_bucket(b"widgets")?;
-      tx.bucket_mut(b"widgets").unwrap().create_bucket(b"foo")?;
-      assert_eq!(None, tx.bucket(b"widgets").unwrap().get(b"foo"));
-      Ok(())
-    })?;
+  #[cfg(not(miri))]
+  // Deleting an unmodified bucket walks its pages. An open savepoint needs every sub-bucket
+  // to be visited, so both ways must free the same pages.
+  fn test_bucket_delete_bucket_walk_pages() -> crate::Result<()> {
+    let mut free_page_n = Vec::new();
+    for savepoint in [false, true] {
+      let mut db = TestDb::new()?;
+      db.update(|mut tx| {
+        let mut widgets = tx.create_bucket(b"widgets")?;
+        for i in 0..20 {
+          let mut child = widgets.create_bucket(format!("{:02}", i))?;
+          for j in 0..200 {
+            child.put(format!("{:04}", j), format!("{:0100}", j))?;
+          }
+          child.create_bucket(b"inline")?.put(b"foo", b"bar")?;
+        }
+        Ok(())
+      })?;
+      db.update(|mut tx| {
+        // Held until the deletion is done, so it's undo logged
+        let savepoint = savepoint.then(|| tx.savepoint());
+        tx.delete_bucket(b"widgets")?;
+        assert!(tx.bucket(b"widgets").is_none());
+        if let Some(savepoint) = savepoint {
+          savepoint.release();
+        }
+        Ok(())
+      })?;
+      db.must_check();
+      let stats = db.stats();
+      free_page_n.push(stats.free_page_n() + stats.pending_page_n());
+    }
+    assert_eq!(free_page_n[0], free_page_n[1]);
     Ok(())
   }
 
-  // Ensure that a slice returned from a bucket has a capacity equal to its length.
-  // This also allows slices to be appended to since it will require a realloc by Go.
-  //
-  // https://github.com/boltdb/bolt/issues/544
   #[test]
-  #[ignore]
-  fn test_bucket_get_capacity() -> crate::Result<()> {
+  #[cfg(not(miri))]
+  fn test_bucket_clear() -> crate::Result<()> {
+    for savepoint in [false, true] {
+      let mut db = TestDb::new()?;
+      db.update(|mut tx| {
+        let mut widgets = tx.create_bucket(b"widgets")?;
+        widgets.set_sequence(7)?;
+        for i in 0..1000 {
+          widgets.put(format!("{:04}", i), format!("{:0100}", i))?;
+        }
+        widgets
+          .create_bucket(b"sub")?
+          .create_bucket(b"inline")?
+          .put(b"foo", b"bar")?;
+        Ok(())
+      })?;
+      db.update(|mut tx| {
+        let mut widgets = tx.bucket_mut(b"widgets").unwrap();
+        widgets.put(b"0001", b"changed")?;
+        if savepoint {
+          let sp = tx.savepoint();
+          widgets.clear()?;
+          sp.rollback()?;
+          widgets = tx.bucket_mut(b"widgets").unwrap();
+          assert_eq!(Some(b"changed".as_slice()), widgets.get(b"0001"));
+        }
+        widgets.clear()?;
+        assert_eq!(None, widgets.get(b"0001"));
+        widgets.put(b"foo", b"bar")?;
+        Ok(())
+      })?;
+      db.view(|tx| {
+        let widgets = tx.bucket(b"widgets").unwrap();
+        assert_eq!(7, widgets.sequence());
+        assert_eq!(Some(b"bar".as_slice()), widgets.get(b"foo"));
+        assert!(widgets.bucket(b"sub").is_none());
+        assert_eq!(1, widgets.stats().key_n);
+        Ok(())
+      })?;
+      db.must_check();
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_bucket_bucket_incompatible_value() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"key", b"val")?;
-      Ok(())
-    })?;
-    db.update(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      let mut c = b.cursor();
-      if let Some((_k, Some(_v))) = c.first() {
-        todo!("We don't allow modifying values in place for this first version");
-      }
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      widgets.put(b"foo", b"bar")?;
+      assert!(widgets.bucket(b"foo").is_none());
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_put() -> crate::Result<()> {
+  fn test_bucket_create_bucket_incompatible_value() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      {
-        let mut b = tx.create_bucket(b"widgets")?;
-        b.put(b"foo", b"bar")?;
-      }
-
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      widgets.put(b"foo", b"bar")?;
       assert_eq!(
-        Some(b"bar".as_slice()),
-        tx.bucket(b"widgets").unwrap().get(b"foo")
+        Some(Error::IncompatibleValue),
+        widgets.create_bucket(b"foo").err()
       );
       Ok(())
-    })
+    })?;
+    Ok(())
   }
 
   #[test]
-  fn test_bucket_put_repeat() -> crate::Result<()> {
+  fn test_bucket_delete_bucket_incompatible_value() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      {
-        let mut b = tx.create_bucket(b"widgets")?;
-        b.put(b"foo", b"bar")?;
-        b.put(b"foo", b"baz")?;
-      }
-
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      widgets.put(b"foo", b"bar")?;
       assert_eq!(
-        Some(b"baz".as_slice()),
-        tx.bucket(b"widgets").unwrap().get(b"foo")
+        Some(Error::IncompatibleValue),
+        widgets.delete_bucket(b"foo").err()
       );
       Ok(())
-    })
+    })?;
+    Ok(())
   }
 
   #[test]
-  #[cfg(not(miri))]
-  fn test_bucket_put_large() -> crate::Result<()> {
+  fn test_bucket_sequence() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let count = 100;
-    let factor = 200;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      for i in 1..count {
-        b.put(
-          "0".repeat(i * factor).as_bytes(),
-          "X".repeat((count - i) * factor).as_bytes(),
-        )?;
-      }
+      let mut bkt = tx.create_bucket(b"0")?;
+      assert_eq!(0, bkt.sequence());
+      bkt.set_sequence(1000)?;
+      assert_eq!(1000, bkt.sequence());
       Ok(())
     })?;
     db.view(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      for i in 1..count {
-        let v = b.get("0".repeat(i * factor).as_bytes()).unwrap();
-        assert_eq!((count - i) * factor, v.len());
-        v.iter().all(|c| c == &b'X');
-      }
+      let bkt = tx.bucket(b"0").unwrap();
+      assert_eq!(1000, bkt.sequence());
       Ok(())
-    })
+    })?;
+    Ok(())
   }
 
   #[test]
-  #[cfg(all(not(miri), feature = "long-tests"))]
-
-  fn test_db_put_very_large() -> crate::Result<()> {
+  fn test_bucket_next_sequence() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let n = 400000u64;
-    let batch_n = 200000u64;
+    db.update(|mut tx| {
+      let _ = tx.create_bucket(b"widgets")?;
+      let _ = tx.create_bucket(b"woojits")?;
+      {
+        let mut widgets = tx.bucket_mut("widgets").unwrap();
+        assert_eq!(1, widgets.next_sequence()?);
+        assert_eq!(2, widgets.next_sequence()?);
+      }
+      let mut woojits = tx.bucket_mut("woojits").unwrap();
+      assert_eq!(1, woojits.next_sequence()?);
 
-    let v = [0u8; 500];
-    for i in (0..n).step_by(batch_n as usize) {
-      db.update(|mut tx| {
-        let mut b = tx.create_bucket_if_not_exists(b"widgets")?;
-        for j in 0..batch_n {
-          b.put((i + j).to_be_bytes().as_slice(), v)?;
-        }
-        Ok(())
-      })?;
-    }
+      Ok(())
+    })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_put_incompatible_value() -> crate::Result<()> {
+  // Ensure that a bucket will persist an autoincrementing sequence even if its
+  // the only thing updated on the bucket.
+  // https://github.com/boltdb/bolt/issues/296
+  fn test_bucket_next_sequence_persist() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let _ = tx.create_bucket(b"widgets")?;
-      tx.bucket_mut(b"widgets").unwrap().create_bucket(b"foo")?;
+      tx.create_bucket(b"widgets")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut widgets = tx.bucket_mut(b"widgets").unwrap();
+      assert_eq!(1, widgets.next_sequence()?);
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut widgets = tx.bucket_mut(b"widgets").unwrap();
+      assert_eq!(2, widgets.next_sequence()?);
+      Ok(())
+    })?;
+    Ok(())
+  }
 
-      assert_eq!(
-        Err(Error::IncompatibleValue),
-        tx.bucket_mut(b"widgets").unwrap().put(b"foo", b"bar")
-      );
+  fn for_each_collect_kv<'tx, B: BucketApi<'tx>>(
+    b: B,
+  ) -> crate::Result<Vec<(&'tx [u8], Option<&'tx [u8]>)>> {
+    let items = std::cell::RefCell::new(Vec::new());
+    b.for_each(|k, v| {
+      items.borrow_mut().push((k, v));
       Ok(())
-    })
+    })?;
+    Ok(items.into_inner())
   }
 
-  #[test]
-  fn test_bucket_delete() -> crate::Result<()> {
-    let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"foo", b"bar")?;
-      b.delete(b"foo")?;
-      assert_eq!(None, b.get(b"foo"));
+  fn for_each_bucket_collect_k<'tx, B: BucketApi<'tx>>(b: B) -> crate::Result<Vec<&'tx [u8]>> {
+    let items = std::cell::RefCell::new(Vec::new());
+    b.for_each_bucket(|k| {
+      items.borrow_mut().push(k);
       Ok(())
-    })
+    })?;
+    Ok(items.into_inner())
   }
 
   #[test]
-  #[cfg(not(miri))]
-  fn test_bucket_delete_large() -> crate::Result<()> {
+  fn test_bucket_for_each() -> crate::Result<()> {
+    let expected_items = [
+      (b"bar".as_slice(), Some(b"0002".as_slice())),
+      (b"baz".as_slice(), Some(b"0001".as_slice())),
+      (b"csubbucket".as_slice(), None),
+      (b"foo".as_slice(), Some(b"0000".as_slice())),
+    ];
     let mut db = TestDb::new()?;
-    let var = [b'*'; 1024];
     db.update(|mut tx| {
       let mut b = tx.create_bucket(b"widgets")?;
-      for i in 0..100 {
-        b.put(format!("{}", i).as_bytes(), var)?;
-      }
-      Ok(())
-    })?;
-    db.update(|mut tx| {
-      let mut b = tx.bucket_mut(b"widgets").unwrap();
-      for i in 0..100 {
-        b.delete(format!("{}", i).as_bytes())?;
-      }
+      b.put(b"foo", b"0000")?;
+      b.put(b"baz", b"0001")?;
+      b.put(b"bar", b"0002")?;
+      b.create_bucket(b"csubbucket")?;
+
+      let items = for_each_collect_kv(b)?;
+      assert_eq!(
+        expected_items.as_slice(),
+        &items,
+        "what we iterated (ForEach) is not what we put"
+      );
       Ok(())
     })?;
     db.view(|tx| {
       let b = tx.bucket(b"widgets").unwrap();
-      for i in 0..100 {
-        assert_eq!(None, b.get(format!("{}", i).as_bytes()));
-      }
+      let items = for_each_collect_kv(b)?;
+      assert_eq!(
+        expected_items.as_slice(),
+        &items,
+        "what we iterated (ForEach) is not what we put"
+      );
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  #[cfg(all(not(miri), feature = "long_tests"))]
-  fn test_bucket_delete_freelist_overflow() -> crate::Result<()> {
+  fn test_bucket_for_each_bucket() -> crate::Result<()> {
+    let expected_items = [b"csubbucket".as_slice(), b"zsubbucket".as_slice()];
     let mut db = TestDb::new()?;
-
-    //TODO: make this based off of page size
-    for i in 0u64..8192 {
-      db.update(|mut tx| {
-        let mut b = tx.create_bucket_if_not_exists(b"0")?;
-        for j in 0u64..1000 {
-          let mut k = [0u8; 16];
-          let (k0, k1) = k.split_at_mut(8);
-          k0.copy_from_slice(i.to_be_bytes().as_slice());
-          k1.copy_from_slice(j.to_be_bytes().as_slice());
-          b.put(k, [])?;
-        }
-        Ok(())
-      })?;
-    }
     db.update(|mut tx| {
-      let b = tx.bucket_mut(b"0").unwrap();
-      let mut c = b.cursor_mut();
-      let mut node = c.first();
-      while node.is_some() {
-        c.delete()?;
-        node = c.next();
-      }
+      let mut b = tx.create_bucket(b"widgets")?;
+      b.put(b"foo", b"0000")?;
+      let _ = b.create_bucket(b"zsubbucket")?;
+      b.put(b"baz", b"0001")?;
+      b.put(b"bar", b"0002")?;
+      let _ = b.create_bucket(b"csubbucket")?;
+
+      let items = for_each_bucket_collect_k(b)?;
+      assert_eq!(
+        expected_items.as_slice(),
+        &items,
+        "what we iterated (ForEach) is not what we put"
+      );
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      let items = for_each_bucket_collect_k(b)?;
+      assert_eq!(
+        expected_items.as_slice(),
+        &items,
+        "what we iterated (ForEach) is not what we put"
+      );
       Ok(())
     })?;
-    let stats = db.stats();
-    let free_page_n = stats.free_page_n();
-    let pending_page_n = stats.pending_page_n();
-    let free_pages = free_page_n + pending_page_n;
-    assert!(
-      free_pages > 0xFFFF,
-      "expected more than 0xFFFF free pages. Got {}",
-      free_pages
-    );
-    db.must_close();
-    db.must_reopen();
-    let stats = db.stats();
-    let reopen_free_pages = stats.free_page_n();
-    assert_eq!(
-      free_pages, reopen_free_pages,
-      "Expected {} free pages, got {:?}",
-      free_pages, reopen_free_pages
-    );
     Ok(())
   }
 
   #[test]
-  fn test_bucket_delete_non_existing() -> crate::Result<()> {
+  fn test_bucket_for_each_bucket_no_buckets() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
       let mut b = tx.create_bucket(b"widgets")?;
-      let _ = b.create_bucket(b"nested")?;
+      b.put(b"foo", b"0000")?;
+      b.put(b"baz", b"0001")?;
+
+      let items = for_each_bucket_collect_k(b)?;
+      assert!(
+        items.is_empty(),
+        "what we iterated (ForEach) is not what we put"
+      );
       Ok(())
     })?;
-    db.update(|mut tx| {
-      let mut b = tx.bucket_mut(b"widgets").unwrap();
-      b.delete(b"foo")?;
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      let items = for_each_bucket_collect_k(b)?;
       assert!(
-        b.bucket(b"nested").is_some(),
-        "nested bucket has been deleted"
+        items.is_empty(),
+        "what we iterated (ForEach) is not what we put"
       );
       Ok(())
     })?;
@@ -2009,699 +4576,1219 @@ mod tests {
   }
 
   #[test]
-  #[cfg(not(miri))]
-  fn test_bucket_nested() -> crate::Result<()> {
+  fn test_bucket_for_each_short_circuit() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      // Create a widgets bucket.
-      let mut b = tx.create_bucket(b"widgets")?;
-
-      // Create a widgets/foo bucket.
-      let _ = b.create_bucket(b"foo")?;
-
-      // Create a widgets/bar key.
-      b.put(b"bar", b"0000")?;
-      Ok(())
-    })?;
-    db.must_check();
-    db.update(|mut tx| {
-      let mut b = tx.bucket_mut(b"widgets").unwrap();
-      b.put(b"bar", b"xxxx")?;
-      Ok(())
-    })?;
-    db.must_check();
-    db.update(|mut tx| {
-      let mut b = tx.bucket_mut(b"widgets").unwrap();
-      for i in 0..10000 {
-        let s = format!("{}", i);
-        b.put(s.as_bytes(), s.as_bytes())?;
-      }
-      Ok(())
-    })?;
-    db.must_check();
-    db.update(|mut tx| {
-      let mut b = tx.bucket_mut(b"widgets").unwrap();
+    let result = db.update(|mut tx| {
       {
-        let mut foo = b.bucket_mut(b"foo").unwrap();
-        foo.put(b"baz", b"yyyy")?;
+        let mut b = tx.create_bucket(b"widgets")?;
+        b.put(b"bar", b"0000")?;
+        b.put(b"baz", b"0000")?;
+        b.put(b"foo", b"0000")?;
       }
-      b.put(b"bar", b"xxxx")?;
+      let index = AtomicU32::new(0);
+      tx.bucket(b"widgets").unwrap().for_each(|k, _| {
+        index.fetch_add(1, Ordering::Relaxed);
+        if k == b"baz" {
+          return Err(Error::Other(anyhow!("marker")));
+        }
+        Ok(())
+      })?;
+
       Ok(())
-    })?;
-    db.must_check();
-    db.view(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      let foo = b.bucket(b"foo").unwrap();
-      assert_eq!(Some(b"yyyy".as_slice()), foo.get(b"baz"));
-      assert_eq!(Some(b"xxxx".as_slice()), b.get(b"bar"));
+    });
+    let e = result.map_err(|e| e.to_string()).err().unwrap();
+    assert_eq!("marker", e);
+    Ok(())
+  }
 
-      for i in 0..10000 {
-        let s = format!("{}", i);
-        assert_eq!(Some(s.as_bytes()), b.get(s.as_bytes()));
-      }
+  #[test]
+  fn test_bucket_put_empty_key() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      assert_eq!(Some(Error::KeyRequired), widgets.put([], []).err());
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_delete_bucket() -> crate::Result<()> {
+  fn test_bucket_put_key_too_large() -> crate::Result<()> {
     let mut db = TestDb::new()?;
+    let key = [0u8; 32769];
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      let _ = b.create_bucket(b"foo")?;
-      assert_eq!(Err(Error::IncompatibleValue), b.delete(b"foo"));
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      assert_eq!(
+        Some(Error::KeyTooLarge),
+        widgets.put(key.as_slice(), b"bar").err()
+      );
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  // Ensure that deleting a bucket causes nested buckets to be deleted.
-  fn test_bucket_delete_bucket_nested() -> crate::Result<()> {
+  fn test_bucket_put_value_too_large() -> crate::Result<()> {
     let mut db = TestDb::new()?;
+    let value = vec![0u8; MAX_VALUE_SIZE as usize + 1];
     db.update(|mut tx| {
-      {
-        let mut widgets = tx.create_bucket(b"widgets")?;
-        let mut foo = widgets.create_bucket(b"foo")?;
-        let mut bar = foo.create_bucket(b"bar")?;
-        bar.put(b"baz", b"bat")?;
-      }
-      tx.bucket_mut(b"widgets").unwrap().delete_bucket(b"foo")?;
+      let mut widgets = tx.create_bucket(b"widgets")?;
+      assert_eq!(
+        Some(Error::ValueTooLarge),
+        widgets.put(b"foo", value.as_slice()).err()
+      );
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  // Ensure that deleting a bucket causes nested buckets to be deleted after they have been committed.
-  fn test_bucket_delete_bucket_nested2() -> crate::Result<()> {
-    let mut db = TestDb::new()?;
+  fn test_bucket_put_configured_size_limits() -> crate::Result<()> {
+    let options = BoltOptions::builder()
+      .max_key_size(8)
+      .max_value_size(16)
+      .comparators(Comparators::new().with("reverse", |a, b| b.cmp(a)))
+      .build();
+    let mut db = TestDb::with_options(options)?;
     db.update(|mut tx| {
       let mut widgets = tx.create_bucket(b"widgets")?;
-      let mut foo = widgets.create_bucket(b"foo")?;
-      let mut bar = foo.create_bucket(b"bar")?;
-      bar.put(b"baz", b"bat")?;
+      widgets.put([0u8; 8], [0u8; 16])?;
+      assert_eq!(Err(Error::KeyTooLarge), widgets.put([0u8; 9], "bar"));
+      assert_eq!(Err(Error::ValueTooLarge), widgets.put("foo", [0u8; 17]));
+      assert_eq!(
+        Err(Error::ValueTooLarge),
+        widgets.set_meta("foo", [0u8; 17])
+      );
+
+      // Records the database keeps itself aren't limited
+      widgets.set_quota(Some(BucketQuota {
+        max_bytes: Some(1 << 20),
+        max_keys: None,
+      }))?;
+      widgets.put("foo", "bar")?;
+      widgets.set_meta("foo", "bar")?;
+      let mut sorted = widgets.create_bucket_with_comparator("sorted", "reverse")?;
+      sorted.put("a", "1")?;
       Ok(())
     })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(feature = "long-tests")]
+  fn test_bucket_stats() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let big_key = "really-big-value";
+    for i in 0..500 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("woojits")?;
+        b.put(format!("{:03}", i), format!("{}", i))?;
+        Ok(())
+      })?;
+    }
+    // TODO: Update to support different page sizes
+    let long_key_length = 10 * 4096 + 17;
     db.update(|mut tx| {
-      {
-        let widgets = tx.bucket(b"widgets").unwrap();
-        let foo = widgets.bucket(b"foo").unwrap();
-        let bar = foo.bucket(b"bar").unwrap();
-        assert_eq!(Some(b"bat".as_slice()), bar.get(b"baz"));
-      }
-      tx.delete_bucket(b"widgets")?;
+      tx.bucket_mut("woojits")
+        .unwrap()
+        .put(big_key, "*".repeat(long_key_length))?;
       Ok(())
     })?;
+    db.must_check();
+
+    // TODO: Support pagesize that's not 4096
+    let stat_4096 = BucketStats {
+      branch_page_n: 1,
+      branch_overflow_n: 0,
+      leaf_page_n: 7,
+      leaf_overflow_n: 10,
+      key_n: 501,
+      depth: 2,
+      branch_alloc: 4096,
+      branch_in_use: 149,
+      leaf_alloc: 69_632,
+      leaf_in_use: 0 +
+      7 * 16 + // leaf page header (x LeafPageN)
+      501 * 16 + // leaf elements
+      500 * 3 + big_key.len() as i64 + // leaf keys
+      1 * 10 + 2 * 90 + 3 * 400 + long_key_length as i64, // leaf values: 10 * 1digit, 90*2digits, ...
+      bucket_n: 1,
+      inline_bucket_n: 0,
+      inline_bucket_in_use: 0,
+      ..Default::default()
+    };
     db.view(|tx| {
-      assert!(tx.bucket(b"widgets").is_none());
+      let b = tx.bucket("woojits").unwrap();
+      let stats = b.stats();
+      assert_eq!(stat_4096, stats, "stats differs from expectations");
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  #[cfg(not(miri))]
-  // Ensure that deleting a child bucket with multiple pages causes all pages to get collected.
-  // NOTE: Consistency check in bolt_test.DB.Close() will panic if pages not freed properly.
-  fn test_bucket_delete_bucket_large() -> crate::Result<()> {
+  #[cfg(feature = "long-tests")]
+  fn test_bucket_stats_random_fill() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      let mut foo = widgets.create_bucket(b"foo")?;
-      for i in 0..1000 {
-        let k = format!("{}", i);
-        let v = format!("{:0100}", i);
-        foo.put(k.as_bytes(), v.as_bytes())?;
-      }
-      Ok(())
-    })?;
-    db.update(|mut tx| {
-      tx.delete_bucket(b"widgets")?;
+    let mut count = 0;
+    let mut rand = StdRng::seed_from_u64(42);
+    let mut outer_range = (0..1000).collect_vec();
+    outer_range.shuffle(&mut rand);
+    for i in outer_range {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("woojits")?;
+        b.set_fill_percent(0.90);
+        let mut inner_range = (0..100).collect_vec();
+        inner_range.shuffle(&mut rand);
+        for j in inner_range {
+          let index = (j * 1000) + i;
+          b.put(format!("{:015}", index), "0000000000")?;
+          count += 1;
+        }
+        Ok(())
+      })?;
+    }
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket("woojits").unwrap();
+      let stats = b.stats();
+      assert_eq!(68, stats.branch_page_n, "unexpected BranchPageN");
+      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
+      assert_eq!(2946, stats.leaf_page_n, "unexpected LeafPageN");
+      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
+      assert_eq!(10_0000, stats.key_n, "unexpected KeyN");
+      assert_eq!(94_491, stats.branch_in_use, "unexpected BranchInuse");
+      assert_eq!(4_147_136, stats.leaf_in_use, "unexpected LeafInuse");
+      assert_eq!(278_528, stats.branch_alloc, "unexpected BranchAlloc");
+      assert_eq!(12_066_816, stats.leaf_alloc, "unexpected LeafAlloc");
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_bucket_incompatible_value() -> crate::Result<()> {
+  fn test_bucket_stats_small() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      widgets.put(b"foo", b"bar")?;
-      assert!(widgets.bucket(b"foo").is_none());
+      let mut b = tx.create_bucket("whozawhats")?;
+      b.put("foo", "bar")?;
       Ok(())
     })?;
-    Ok(())
-  }
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket("whozawhats").unwrap();
+      let stats = b.stats();
+      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
+      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
+      assert_eq!(0, stats.leaf_page_n, "unexpected LeafPageN");
+      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
+      assert_eq!(1, stats.key_n, "unexpected KeyN");
+      assert_eq!(1, stats.depth, "unexpected Depth");
+      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
+      assert_eq!(0, stats.leaf_in_use, "unexpected LeafInuse");
 
-  #[test]
-  fn test_bucket_create_bucket_incompatible_value() -> crate::Result<()> {
-    let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      widgets.put(b"foo", b"bar")?;
+      //TODO: Fails if page_size != 4096? Db.Info?
+      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
+      assert_eq!(0, stats.leaf_alloc, "unexpected LeafAlloc");
+
+      assert_eq!(1, stats.bucket_n, "unexpected BucketN");
+      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
       assert_eq!(
-        Some(Error::IncompatibleValue),
-        widgets.create_bucket(b"foo").err()
+        16 + 16 + 6,
+        stats.inline_bucket_in_use,
+        "unexpected InlineBucketInuse"
       );
+
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_delete_bucket_incompatible_value() -> crate::Result<()> {
+  fn test_bucket_stats_empty_bucket() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      widgets.put(b"foo", b"bar")?;
+      tx.create_bucket("whozawhats")?;
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket("whozawhats").unwrap();
+      let stats = b.stats();
+      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
+      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
+      assert_eq!(0, stats.leaf_page_n, "unexpected LeafPageN");
+      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
+      assert_eq!(0, stats.key_n, "unexpected KeyN");
+      assert_eq!(1, stats.depth, "unexpected Depth");
+      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
+      assert_eq!(0, stats.leaf_in_use, "unexpected LeafInuse");
+
+      //TODO: Fails if page_size != 4096? Db.Info?
+      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
+      assert_eq!(0, stats.leaf_alloc, "unexpected LeafAlloc");
+
+      assert_eq!(1, stats.bucket_n, "unexpected BucketN");
+      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
       assert_eq!(
-        Some(Error::IncompatibleValue),
-        widgets.delete_bucket(b"foo").err()
+        16, stats.inline_bucket_in_use,
+        "unexpected InlineBucketInuse"
       );
+
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_sequence() -> crate::Result<()> {
+  fn test_bucket_stats_nested() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut bkt = tx.create_bucket(b"0")?;
-      assert_eq!(0, bkt.sequence());
-      bkt.set_sequence(1000)?;
-      assert_eq!(1000, bkt.sequence());
+      let mut b = tx.create_bucket("foo")?;
+      for i in 0..100 {
+        let i_str = format!("{:02}", i);
+        b.put(&i_str, &i_str)?;
+      }
+      let mut bar = b.create_bucket("bar")?;
+      for i in 0..10 {
+        let i_str = format!("{}", i);
+        bar.put(&i_str, &i_str)?;
+      }
+      let mut baz = bar.create_bucket("baz")?;
+      for i in 0..10 {
+        let i_str = format!("{}", i);
+        baz.put(&i_str, &i_str)?;
+      }
       Ok(())
     })?;
+    db.must_check();
+
     db.view(|tx| {
-      let bkt = tx.bucket(b"0").unwrap();
-      assert_eq!(1000, bkt.sequence());
+      let b = tx.bucket("foo").unwrap();
+      let stats = b.stats();
+      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
+      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
+      assert_eq!(2, stats.leaf_page_n, "unexpected LeafPageN");
+      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
+      assert_eq!(122, stats.key_n, "unexpected KeyN");
+      assert_eq!(3, stats.depth, "unexpected Depth");
+      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
+
+      let mut foo = 16; // foo (pghdr)
+      foo += 101 * 16; // foo leaf elements
+      foo += 100 * 2 + 100 * 2; // foo leaf key/values
+      foo += 3 + 16; // foo -> bar key/value
+
+      let mut bar = 16; // bar (pghdr)
+      bar += 11 * 16; // bar leaf elements
+      bar += 10 + 10; // bar leaf key/values
+      bar += 3 + 16; //bar -> baz key/value
+
+      let mut baz = 16; // baz (inline) (pghdr)
+      baz += 10 * 16; // baz leaf elements
+      baz += 10 + 10; // baz leaf key/values
+
+      assert_eq!(foo + bar + baz, stats.leaf_in_use, "unexpected LeafInuse");
+
+      //TODO: Fails if page_size != 4096? Db.Info?
+      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
+      assert_eq!(8192, stats.leaf_alloc, "unexpected LeafAlloc");
+
+      assert_eq!(3, stats.bucket_n, "unexpected BucketN");
+      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
+      assert_eq!(
+        baz, stats.inline_bucket_in_use,
+        "unexpected InlineBucketInuse"
+      );
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_next_sequence() -> crate::Result<()> {
+  #[cfg(feature = "long-tests")]
+  fn test_bucket_stats_large() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    db.update(|mut tx| {
-      let _ = tx.create_bucket(b"widgets")?;
-      let _ = tx.create_bucket(b"woojits")?;
-      {
-        let mut widgets = tx.bucket_mut("widgets").unwrap();
-        assert_eq!(1, widgets.next_sequence()?);
-        assert_eq!(2, widgets.next_sequence()?);
-      }
-      let mut woojits = tx.bucket_mut("woojits").unwrap();
-      assert_eq!(1, woojits.next_sequence()?);
+    let mut index = 0;
+    for _ in 0..100 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("widgets")?;
+        for _ in 0..1000 {
+          let i_str = format!("{}", index);
+          b.put(&i_str, &i_str)?;
+          index += 1;
+        }
+        Ok(())
+      })?;
+    }
+
+    db.must_check();
+
+    // TODO: Support pagesize that's not 4096
+    let stat_4096 = BucketStats {
+      branch_page_n: 13,
+      branch_overflow_n: 0,
+      leaf_page_n: 1196,
+      leaf_overflow_n: 0,
+      key_n: 100_000,
+      depth: 3,
+      branch_alloc: 53_248,
+      branch_in_use: 25_257,
+      leaf_alloc: 4_898_816,
+      leaf_in_use: 2_596_916,
+      bucket_n: 1,
+      inline_bucket_n: 0,
+      inline_bucket_in_use: 0,
+      ..Default::default()
+    };
 
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let stats = b.stats();
+      assert_eq!(stat_4096, stats, "stats differs from expectations");
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  // Ensure that a bucket will persist an autoincrementing sequence even if its
-  // the only thing updated on the bucket.
-  // https://github.com/boltdb/bolt/issues/296
-  fn test_bucket_next_sequence_persist() -> crate::Result<()> {
+  #[cfg(feature = "rayon")]
+  fn test_bucket_par_for_each() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      tx.create_bucket(b"widgets")?;
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..5000u32 {
+        b.put(i.to_be_bytes(), format!("{:08}", i))?;
+      }
+      let mut sub = b.create_bucket("sub")?;
+      sub.put("foo", "bar")?;
+      Ok(())
+    })?;
+
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let mut expected = Vec::new();
+      b.for_each(|k, v| {
+        expected.push((k, v));
+        Ok(())
+      })?;
+      let actual = std::sync::Mutex::new(Vec::new());
+      b.par_for_each(|k, v| {
+        actual.lock().unwrap().push((k, v));
+        Ok(())
+      })?;
+      let mut actual = actual.into_inner().unwrap();
+      actual.sort();
+      assert_eq!(5001, actual.len());
+      assert_eq!(expected, actual);
+
+      let r = b.par_for_each(|_, _| Err(Error::Other(anyhow!("marker"))));
+      assert!(r.is_err());
+      Ok(())
+    })?;
+
+    // Uncommitted changes in a write transaction are included
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.put("new", "value")?;
+      b.delete(0u32.to_be_bytes())?;
+      let actual = std::sync::Mutex::new(Vec::new());
+      b.par_for_each(|k, v| {
+        actual.lock().unwrap().push((k, v));
+        Ok(())
+      })?;
+      let actual = actual.into_inner().unwrap();
+      assert_eq!(5001, actual.len());
+      assert!(actual.contains(&(b"new".as_slice(), Some(b"value".as_slice()))));
+      assert!(actual.contains(&(b"sub".as_slice(), None)));
+      assert!(!actual.iter().any(|(k, _)| *k == 0u32.to_be_bytes()));
       Ok(())
     })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_bucket_key_n_matches_stats() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut widgets = tx.bucket_mut(b"widgets").unwrap();
-      assert_eq!(1, widgets.next_sequence()?);
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000 {
+        b.put(format!("{:04}", i), "*".repeat(100))?;
+      }
+      b.create_bucket("sub")?;
+      assert_eq!(1001, b.key_n());
       Ok(())
     })?;
-    db.update(|mut tx| {
-      let mut widgets = tx.bucket_mut(b"widgets").unwrap();
-      assert_eq!(2, widgets.next_sequence()?);
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let stats = b.stats();
+      assert_eq!(stats.key_n() as usize, b.key_n());
+      // stats includes the depth of the inline sub-bucket
+      assert_eq!(stats.depth() as usize, b.depth() + 1);
+      assert_eq!(
+        (stats.branch_alloc() + stats.leaf_alloc()) as usize,
+        b.approx_size_bytes()
+      );
+      assert_eq!(0, b.bucket("sub").unwrap().key_n());
       Ok(())
     })?;
     Ok(())
   }
 
-  fn for_each_collect_kv<'tx, B: BucketApi<'tx>>(
-    b: B,
-  ) -> crate::Result<Vec<(&'tx [u8], Option<&'tx [u8]>)>> {
-    let items = std::cell::RefCell::new(Vec::new());
-    b.for_each(|k, v| {
-      items.borrow_mut().push((k, v));
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_bucket_try_get_corrupted() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "*".repeat(2048))?;
       Ok(())
     })?;
-    Ok(items.into_inner())
-  }
+    let page_size = db.info().page_size as u64;
+    let root = {
+      let tx = db.begin()?;
+      tx.bucket("widgets").unwrap().root()
+    };
+    db.must_close();
 
-  fn for_each_bucket_collect_k<'tx, B: BucketApi<'tx>>(b: B) -> crate::Result<Vec<&'tx [u8]>> {
-    let items = std::cell::RefCell::new(Vec::new());
-    b.for_each_bucket(|k| {
-      items.borrow_mut().push(k);
+    // Overwrite the page id of the bucket's root page
+    {
+      let mut file = OpenOptions::new()
+        .write(true)
+        .open(db.tmp_file.as_ref().unwrap().path())?;
+      file.seek(SeekFrom::Start(root.0 * page_size))?;
+      file.write_all(&(root.0 + 1).to_ne_bytes())?;
+      file.flush()?;
+    }
+
+    db.must_reopen();
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert!(matches!(b.try_get("foo"), Err(Error::Corrupted { .. })));
+      assert!(matches!(
+        b.try_get_many(&["foo"]),
+        Err(Error::Corrupted { .. })
+      ));
+      assert!(matches!(
+        b.cursor().try_seek("foo"),
+        Err(Error::Corrupted { .. })
+      ));
       Ok(())
     })?;
-    Ok(items.into_inner())
+    // The database is intentionally corrupted so skip the consistency check on drop
+    db.must_close();
+    Ok(())
   }
 
   #[test]
-  fn test_bucket_for_each() -> crate::Result<()> {
-    let expected_items = [
-      (b"bar".as_slice(), Some(b"0002".as_slice())),
-      (b"baz".as_slice(), Some(b"0001".as_slice())),
-      (b"csubbucket".as_slice(), None),
-      (b"foo".as_slice(), Some(b"0000".as_slice())),
-    ];
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_bucket_element_out_of_bounds() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"foo", b"0000")?;
-      b.put(b"baz", b"0001")?;
-      b.put(b"bar", b"0002")?;
-      b.create_bucket(b"csubbucket")?;
-
-      let items = for_each_collect_kv(b)?;
-      assert_eq!(
-        expected_items.as_slice(),
-        &items,
-        "what we iterated (ForEach) is not what we put"
-      );
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "*".repeat(2048))?;
       Ok(())
     })?;
+    let page_size = db.info().page_size as u64;
+    let root = {
+      let tx = db.begin()?;
+      tx.bucket("widgets").unwrap().root()
+    };
+    db.must_close();
+
+    // Let the key of the first element reach past the end of the page
+    {
+      let mut file = OpenOptions::new()
+        .write(true)
+        .open(db.tmp_file.as_ref().unwrap().path())?;
+      let key_size = root.0 * page_size + PAGE_HEADER_SIZE as u64 + 8;
+      file.seek(SeekFrom::Start(key_size))?;
+      file.write_all(&(page_size as u32).to_ne_bytes())?;
+      file.flush()?;
+    }
+
+    db.must_reopen();
     db.view(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      let items = for_each_collect_kv(b)?;
-      assert_eq!(
-        expected_items.as_slice(),
-        &items,
-        "what we iterated (ForEach) is not what we put"
-      );
+      let b = tx.bucket("widgets").unwrap();
+      assert!(matches!(b.try_get("foo"), Err(Error::Corrupted { .. })));
       Ok(())
     })?;
+    let r = db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "bar"));
+    assert!(matches!(r, Err(Error::Corrupted { .. })));
+    let r = db.update(|mut tx| tx.bucket_mut("widgets").unwrap().delete("foo"));
+    assert!(matches!(r, Err(Error::Corrupted { .. })));
+    // The database is intentionally corrupted so skip the consistency check on drop
+    db.must_close();
     Ok(())
   }
 
   #[test]
-  fn test_bucket_for_each_bucket() -> crate::Result<()> {
-    let expected_items = [b"csubbucket".as_slice(), b"zsubbucket".as_slice()];
-    let mut db = TestDb::new()?;
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_bucket_comparator() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let options = BoltOptions::builder().comparators(comparators).build();
+    let mut db = TestDb::with_options(options)?;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"foo", b"0000")?;
-      let _ = b.create_bucket(b"zsubbucket")?;
-      b.put(b"baz", b"0001")?;
-      b.put(b"bar", b"0002")?;
-      let _ = b.create_bucket(b"csubbucket")?;
-
-      let items = for_each_bucket_collect_k(b)?;
       assert_eq!(
-        expected_items.as_slice(),
-        &items,
-        "what we iterated (ForEach) is not what we put"
+        Some(Error::UnknownComparator("missing".into())),
+        tx.create_bucket_with_comparator("widgets", "missing").err()
       );
+      let mut b = tx.create_bucket_with_comparator("widgets", "reverse")?;
+      for i in 0..2000u32 {
+        b.put(i.to_be_bytes(), "*".repeat(50))?;
+      }
+      let mut sub = b.create_bucket_with_comparator("sub", "reverse")?;
+      sub.put("a", "1")?;
+      sub.put("b", "2")?;
       Ok(())
     })?;
-    db.view(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      let items = for_each_bucket_collect_k(b)?;
+    db.must_check();
+    db.must_close();
+    db.must_reopen();
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.delete(1000u32.to_be_bytes())?;
+      let mut c = b.cursor();
+      // "sub" sorts after every four byte key, so it comes first in reverse
+      assert_eq!(Some(b"sub".as_slice()), c.first().map(|(k, _)| k));
       assert_eq!(
-        expected_items.as_slice(),
-        &items,
-        "what we iterated (ForEach) is not what we put"
+        Some(1999u32.to_be_bytes().as_slice()),
+        c.next().map(|(k, _)| k)
+      );
+      assert_eq!(
+        Some(0u32.to_be_bytes().as_slice()),
+        c.last().map(|(k, _)| k)
       );
+      let (k, _) = c.seek(1000u32.to_be_bytes()).unwrap();
+      assert_eq!(999u32.to_be_bytes().as_slice(), k);
+      assert!(b.get(1000u32.to_be_bytes()).is_none());
+      assert!(b.get(1001u32.to_be_bytes()).is_some());
+      let sub = b.bucket("sub").unwrap();
+      assert_eq!(Some(b"b".as_slice()), sub.cursor().first().map(|(k, _)| k));
       Ok(())
     })?;
+    db.must_check();
+
+    // Every recorded comparator must be registered
+    db.must_close();
+    let file = db.tmp_file.as_ref().unwrap().path().to_path_buf();
+    let r = BoltOptions::default().open(&file).err();
+    assert_eq!(Some(Error::UnknownComparator("reverse".into())), r);
+
+    // Deleting the buckets forgets their comparators
+    db.must_reopen();
+    db.update(|mut tx| tx.delete_bucket("widgets"))?;
+    db.must_close();
+    BoltOptions::default().open(&file)?.close();
+    db.must_reopen();
     Ok(())
   }
 
   #[test]
-  fn test_bucket_for_each_bucket_no_buckets() -> crate::Result<()> {
+  fn test_bucket_value_len() -> crate::Result<()> {
     let mut db = TestDb::new()?;
+    let large = vec![7u8; 4 << 20];
     db.update(|mut tx| {
-      let mut b = tx.create_bucket(b"widgets")?;
-      b.put(b"foo", b"0000")?;
-      b.put(b"baz", b"0001")?;
-
-      let items = for_each_bucket_collect_k(b)?;
-      assert!(
-        items.is_empty(),
-        "what we iterated (ForEach) is not what we put"
-      );
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("large", &large)?;
+      b.put("empty", [])?;
+      b.create_bucket("sub")?;
       Ok(())
     })?;
     db.view(|tx| {
-      let b = tx.bucket(b"widgets").unwrap();
-      let items = for_each_bucket_collect_k(b)?;
-      assert!(
-        items.is_empty(),
-        "what we iterated (ForEach) is not what we put"
-      );
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(large.len()), b.value_len("large"));
+      assert_eq!(Some(0), b.value_len("empty"));
+      assert!(b.contains_key("empty"));
+      assert!(!b.contains_key("sub"));
+      assert_eq!(None, b.value_len("sub"));
+      assert!(!b.contains_key("missing"));
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_for_each_short_circuit() -> crate::Result<()> {
+  fn test_bucket_reentrant_reads() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let result = db.update(|mut tx| {
-      {
-        let mut b = tx.create_bucket(b"widgets")?;
-        b.put(b"bar", b"0000")?;
-        b.put(b"baz", b"0000")?;
-        b.put(b"foo", b"0000")?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), i.to_le_bytes())?;
       }
-      let index = AtomicU32::new(0);
-      tx.bucket(b"widgets").unwrap().for_each(|k, _| {
-        index.fetch_add(1, Ordering::Relaxed);
-        if k == b"baz" {
-          return Err(Error::Other(anyhow!("marker")));
+      b.create_bucket("child")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let mut n = 0;
+      b.for_each(|k, v| {
+        assert_eq!(v, b.get(k));
+        assert_eq!(Some((k, v)), b.cursor().seek(k));
+        n += 1;
+        Ok(())
+      })?;
+      assert_eq!(1001, n);
+      b.for_each_bucket(|name| {
+        assert_eq!(Some(b"bar".as_slice()), b.bucket(name).unwrap().get("foo"));
+        Ok(())
+      })?;
+      tx.for_each(|name, b| {
+        assert_eq!(b.key_n(), tx.bucket(name).unwrap().key_n());
+        Ok(())
+      })?;
+      tx.walk(|path, k, v| {
+        if let ([name], Some(v)) = (path, v) {
+          assert_eq!(Some(v), tx.bucket(name).unwrap().get(k));
         }
         Ok(())
       })?;
-
       Ok(())
-    });
-    let e = result.map_err(|e| e.to_string()).err().unwrap();
-    assert_eq!("marker", e);
-    Ok(())
-  }
-
-  #[test]
-  fn test_bucket_put_empty_key() -> crate::Result<()> {
-    let mut db = TestDb::new()?;
+    })?;
     db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      assert_eq!(Some(Error::KeyRequired), widgets.put([], []).err());
+      let b = tx.bucket("widgets").unwrap();
+      let child = b.bucket("child").unwrap();
+      let mut parent = tx.bucket_mut("widgets").unwrap();
+      b.for_each(|k, v| {
+        assert_eq!(v, b.get(k));
+        Ok(())
+      })?;
+      // Deleting the bucket being iterated ends the iteration with an error
+      let r = child.for_each(|_, _| parent.delete_bucket("child"));
+      assert_eq!(Err(Error::BucketNotFound), r);
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_put_key_too_large() -> crate::Result<()> {
+  fn test_bucket_reentrant_read_api() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let key = [0u8; 32769];
     db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      assert_eq!(
-        Some(Error::KeyTooLarge),
-        widgets.put(key.as_slice(), b"bar").err()
-      );
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), i.to_le_bytes())?;
+      }
+      b.set_meta("version", "1")?;
+      b.create_bucket("child")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    // Every read of the bucket, its nested buckets and its transaction from within the
+    // callbacks iterating it, in a read transaction and in a write transaction with
+    // materialized nodes
+    let reads = |b: &BucketImpl, k: &[u8], v: Option<&[u8]>| -> crate::Result<()> {
+      assert_eq!(v, b.get(k));
+      assert_eq!(v, b.try_get(k)?);
+      assert_eq!(v.is_some(), b.contains_key(k));
+      assert_eq!(v.map(|v| v.len()), b.value_len(k));
+      assert_eq!(vec![v], b.get_many(&[k]));
+      assert_eq!(vec![v], b.try_get_many(&[k])?);
+      assert_eq!(Some((k, v)), b.cursor().seek(k));
+      assert_eq!(v.is_none(), b.bucket(k).is_some());
+      assert_eq!(v.is_none(), b.try_bucket(k)?.is_some());
+      assert_eq!(Some(b"1".as_slice()), b.get_meta("version"));
+      assert_eq!(1001, b.key_n());
+      assert!(b.depth() > 0);
+      assert!(b.approx_size_bytes() > 0);
+      let _ = (b.root(), b.sequence(), b.writable(), b.stats());
+      Ok(())
+    };
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      b.for_each(|k, v| reads(&b, k, v))?;
+      let mut c = b.cursor();
+      let mut entry = c.first();
+      while let Some((k, v)) = entry {
+        reads(&b, k, v)?;
+        entry = c.next();
+      }
+      b.for_each_bucket(|name| {
+        let child = b.bucket(name).unwrap();
+        child.for_each(|k, v| {
+          assert_eq!(v, child.get(k));
+          assert_eq!(v, b.bucket(name).unwrap().get(k));
+          Ok(())
+        })
+      })?;
+      tx.for_each(|name, b| {
+        b.for_each(|k, v| {
+          assert_eq!(v, tx.bucket(name).unwrap().get(k));
+          Ok(())
+        })
+      })?;
+      tx.walk(|path, k, v| {
+        if let ([name], Some(v)) = (path, v) {
+          reads(&tx.bucket(name).unwrap(), k, Some(v))?;
+        }
+        Ok(())
+      })?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        for i in (0..1000u32).step_by(7) {
+          b.put(i.to_be_bytes(), i.to_le_bytes())?;
+        }
+      }
+      let b = tx.bucket("widgets").unwrap();
+      b.for_each(|k, v| reads(&b, k, v))?;
+      b.bucket("child").unwrap().for_each(|k, v| {
+        reads(&b, b"child", None)?;
+        assert_eq!(v, b.bucket("child").unwrap().get(k));
+        Ok(())
+      })?;
+      tx.for_each(|name, b| {
+        b.for_each(|k, v| {
+          assert_eq!(v, tx.bucket(name).unwrap().get(k));
+          Ok(())
+        })
+      })?;
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_put_value_too_large() -> crate::Result<()> {
+  fn test_bucket_writes_during_iteration() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let value = vec![0u8; MAX_VALUE_SIZE as usize + 1];
     db.update(|mut tx| {
-      let mut widgets = tx.create_bucket(b"widgets")?;
-      assert_eq!(
-        Some(Error::ValueTooLarge),
-        widgets.put(b"foo", value.as_slice()).err()
-      );
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), i.to_le_bytes())?;
+      }
+      Ok(())
+    })?;
+    // Writes through another handle of the iterated bucket don't conflict with the
+    // iteration's borrows
+    db.update(|mut tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let mut alias = tx.bucket_mut("widgets").unwrap();
+      b.for_each(|k, _| alias.put(k, "changed"))?;
+      b.for_each(|_, v| {
+        assert_eq!(Some(b"changed".as_slice()), v);
+        Ok(())
+      })?;
+      let mut n = 0;
+      b.for_each(|k, _| {
+        n += 1;
+        alias.delete(k)
+      })?;
+      assert_eq!(1000, n);
+      assert!(b.cursor().first().is_none());
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  #[cfg(feature = "long-tests")]
-  fn test_bucket_stats() -> crate::Result<()> {
+  fn test_bucket_get_leaf_cache() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let big_key = "really-big-value";
-    for i in 0..500 {
-      db.update(|mut tx| {
-        let mut b = tx.create_bucket_if_not_exists("woojits")?;
-        b.put(format!("{:03}", i), format!("{}", i))?;
-        Ok(())
-      })?;
-    }
-    // TODO: Update to support different page sizes
-    let long_key_length = 10 * 4096 + 17;
     db.update(|mut tx| {
-      tx.bucket_mut("woojits")
-        .unwrap()
-        .put(big_key, "*".repeat(long_key_length))?;
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..10000u32 {
+        b.put(i.to_be_bytes(), i.to_le_bytes())?;
+      }
+      tx.create_bucket("other")?
+        .put(5u32.to_be_bytes(), "other")?;
       Ok(())
     })?;
-    db.must_check();
-
-    // TODO: Support pagesize that's not 4096
-    let stat_4096 = BucketStats {
-      branch_page_n: 1,
-      branch_overflow_n: 0,
-      leaf_page_n: 7,
-      leaf_overflow_n: 10,
-      key_n: 501,
-      depth: 2,
-      branch_alloc: 4096,
-      branch_in_use: 149,
-      leaf_alloc: 69_632,
-      leaf_in_use: 0 +
-      7 * 16 + // leaf page header (x LeafPageN)
-      501 * 16 + // leaf elements
-      500 * 3 + big_key.len() as i64 + // leaf keys
-      1 * 10 + 2 * 90 + 3 * 400 + long_key_length as i64, // leaf values: 10 * 1digit, 90*2digits, ...
-      bucket_n: 1,
-      inline_bucket_n: 0,
-      inline_bucket_in_use: 0,
-    };
     db.view(|tx| {
-      let b = tx.bucket("woojits").unwrap();
-      let stats = b.stats();
-      assert_eq!(stat_4096, stats, "stats differs from expectations");
+      let b = tx.bucket("widgets").unwrap();
+      let other = tx.bucket("other").unwrap();
+      let stats = tx.stats();
+      assert_eq!(
+        Some(4u32.to_le_bytes().as_slice()),
+        b.get(4u32.to_be_bytes())
+      );
+      let before = stats.seek_page_n();
+      // Keys in the same leaf, a missing key inside its range and a key of another bucket
+      for i in 0..10u32 {
+        assert_eq!(Some(i.to_le_bytes().as_slice()), b.get(i.to_be_bytes()));
+      }
+      assert_eq!(None, b.get([0u8, 0, 0, 1, 0]));
+      assert_eq!(11, stats.seek_page_n() - before);
+      assert_eq!(Some(b"other".as_slice()), other.get(5u32.to_be_bytes()));
+      for i in (0..10000u32).rev() {
+        assert_eq!(Some(i.to_le_bytes().as_slice()), b.get(i.to_be_bytes()));
+      }
+      assert_eq!(None, b.get(10000u32.to_be_bytes()));
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  #[cfg(feature = "long-tests")]
-  fn test_bucket_stats_random_fill() -> crate::Result<()> {
+  fn test_bucket_open_cache() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let mut count = 0;
-    let mut rand = StdRng::seed_from_u64(42);
-    let mut outer_range = (0..1000).collect_vec();
-    outer_range.shuffle(&mut rand);
-    for i in outer_range {
-      db.update(|mut tx| {
-        let mut b = tx.create_bucket_if_not_exists("woojits")?;
-        b.set_fill_percent(0.90);
-        let mut inner_range = (0..100).collect_vec();
-        inner_range.shuffle(&mut rand);
-        for j in inner_range {
-          let index = (j * 1000) + i;
-          b.put(format!("{:015}", index), "0000000000")?;
-          count += 1;
-        }
-        Ok(())
-      })?;
-    }
-    db.must_check();
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.create_bucket("foo")?.put("bar", "baz")?;
+      Ok(())
+    })?;
     db.view(|tx| {
-      let b = tx.bucket("woojits").unwrap();
-      let stats = b.stats();
-      assert_eq!(68, stats.branch_page_n, "unexpected BranchPageN");
-      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
-      assert_eq!(2946, stats.leaf_page_n, "unexpected LeafPageN");
-      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
-      assert_eq!(10_0000, stats.key_n, "unexpected KeyN");
-      assert_eq!(94_491, stats.branch_in_use, "unexpected BranchInuse");
-      assert_eq!(4_147_136, stats.leaf_in_use, "unexpected LeafInuse");
-      assert_eq!(278_528, stats.branch_alloc, "unexpected BranchAlloc");
-      assert_eq!(12_066_816, stats.leaf_alloc, "unexpected LeafAlloc");
+      let stats = tx.stats();
+      let b = tx.bucket("widgets").unwrap();
+      b.bucket("foo").unwrap();
+      let before = stats.seek_page_n();
+      for _ in 0..10 {
+        let b = tx.bucket("widgets").unwrap();
+        let foo = b.bucket("foo").unwrap();
+        assert_eq!(Some(b"baz".as_slice()), foo.get("bar"));
+      }
+      // Only the gets seek
+      assert_eq!(10, stats.seek_page_n() - before);
+      assert!(tx.bucket("missing").is_none());
+      assert!(b.bucket("bar").is_none());
+      assert_eq!(12, stats.seek_page_n() - before);
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_stats_small() -> crate::Result<()> {
+  fn test_bucket_put_many() -> crate::Result<()> {
     let mut db = TestDb::new()?;
+    let mut keys = (0..5000u32)
+      .map(|i| i.to_be_bytes().to_vec())
+      .collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(11);
+    keys.shuffle(&mut rng);
     db.update(|mut tx| {
-      let mut b = tx.create_bucket("whozawhats")?;
-      b.put("foo", "bar")?;
+      let mut b = tx.create_bucket("widgets")?;
+      b.put_many(keys[..2000].iter().map(|k| (k, "first")))?;
+      b.put_many([("dup", "1"), ("dup", "2"), ("dup", "3")])?;
+      b.put_many(Vec::<(&[u8], &[u8])>::new())?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      // Overwrites existing keys with same sized and larger values and adds new ones
+      b.put_many(
+        keys[..1000]
+          .iter()
+          .map(|k| (k.as_slice(), b"again".as_slice())),
+      )?;
+      b.put_many(
+        keys[1000..]
+          .iter()
+          .map(|k| (k.as_slice(), b"second!".as_slice())),
+      )?;
+      assert_eq!(Err(Error::KeyRequired), b.put_many([("", "v")]));
       Ok(())
     })?;
-    db.must_check();
     db.view(|tx| {
-      let b = tx.bucket("whozawhats").unwrap();
-      let stats = b.stats();
-      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
-      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
-      assert_eq!(0, stats.leaf_page_n, "unexpected LeafPageN");
-      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
-      assert_eq!(1, stats.key_n, "unexpected KeyN");
-      assert_eq!(1, stats.depth, "unexpected Depth");
-      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
-      assert_eq!(0, stats.leaf_in_use, "unexpected LeafInuse");
-
-      //TODO: Fails if page_size != 4096? Db.Info?
-      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
-      assert_eq!(0, stats.leaf_alloc, "unexpected LeafAlloc");
-
-      assert_eq!(1, stats.bucket_n, "unexpected BucketN");
-      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
-      assert_eq!(
-        16 + 16 + 6,
-        stats.inline_bucket_in_use,
-        "unexpected InlineBucketInuse"
-      );
-
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(5001, b.key_n());
+      assert_eq!(Some(b"3".as_slice()), b.get("dup"));
+      for key in &keys[..1000] {
+        assert_eq!(Some(b"again".as_slice()), b.get(key));
+      }
+      for key in &keys[1000..] {
+        assert_eq!(Some(b"second!".as_slice()), b.get(key));
+      }
       Ok(())
     })?;
+    db.must_check();
     Ok(())
   }
 
   #[test]
-  fn test_bucket_stats_empty_bucket() -> crate::Result<()> {
+  #[cfg(feature = "bytes")]
+  fn test_bucket_put_bytes() -> crate::Result<()> {
+    use bytes::{Bytes, BytesMut};
     let mut db = TestDb::new()?;
+    let value = Bytes::from(vec![7u8; 2000]);
     db.update(|mut tx| {
-      tx.create_bucket("whozawhats")?;
+      let mut b = tx.create_bucket("widgets")?;
+      b.put_bytes("foo", value.clone())?;
+      b.put_bytes("bar", BytesMut::from("baz"))?;
+      // A same sized overwrite replaces the held value
+      b.put_bytes("bar", Bytes::from_static(b"bat"))?;
+      assert_eq!(Err(Error::KeyRequired), b.put_bytes("", value.clone()));
       Ok(())
     })?;
-    db.must_check();
+    // The transaction released its handles
+    let value = value.try_into_mut().unwrap();
+    let copied = std::cell::RefCell::new(None);
     db.view(|tx| {
-      let b = tx.bucket("whozawhats").unwrap();
-      let stats = b.stats();
-      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
-      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
-      assert_eq!(0, stats.leaf_page_n, "unexpected LeafPageN");
-      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
-      assert_eq!(0, stats.key_n, "unexpected KeyN");
-      assert_eq!(1, stats.depth, "unexpected Depth");
-      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
-      assert_eq!(0, stats.leaf_in_use, "unexpected LeafInuse");
-
-      //TODO: Fails if page_size != 4096? Db.Info?
-      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
-      assert_eq!(0, stats.leaf_alloc, "unexpected LeafAlloc");
-
-      assert_eq!(1, stats.bucket_n, "unexpected BucketN");
-      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
-      assert_eq!(
-        16, stats.inline_bucket_in_use,
-        "unexpected InlineBucketInuse"
-      );
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(&value[..]), b.get("foo"));
+      assert_eq!(Some(b"bat".as_slice()), b.get("bar"));
+      assert_eq!(None, b.get_bytes("missing"));
+      *copied.borrow_mut() = b.get_bytes("foo");
+      Ok(())
+    })?;
+    assert_eq!(Some(value.freeze()), copied.into_inner());
+    db.must_check();
+    Ok(())
+  }
 
+  #[test]
+  fn test_bucket_get_many() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let mut keys = (0..5000u32)
+      .map(|i| (i * 2).to_be_bytes().to_vec())
+      .collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(7);
+    keys.shuffle(&mut rng);
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for key in &keys[..4000] {
+        b.put(key, key)?;
+      }
+      Ok(())
+    })?;
+    // Missing keys, keys between existing keys, duplicates and keys past the end
+    let mut lookups = keys.clone();
+    lookups.extend((0..100u32).map(|i| (i * 2 + 1).to_be_bytes().to_vec()));
+    lookups.push(u32::MAX.to_be_bytes().to_vec());
+    lookups.extend_from_slice(&keys[..10]);
+    lookups.shuffle(&mut rng);
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      // Lookups through nodes modified in the transaction
+      for key in &keys[4000..4500] {
+        b.put(key, key)?;
+      }
+      let expected = lookups.iter().map(|k| b.get(k)).collect::<Vec<_>>();
+      assert_eq!(expected, b.get_many(&lookups));
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let expected = lookups.iter().map(|k| b.get(k)).collect::<Vec<_>>();
+      assert_eq!(4500, expected.iter().flatten().count() - 10);
+      let stats = tx.stats();
+      let before = stats.seek_page_n();
+      assert_eq!(expected, b.get_many(&lookups));
+      assert!(stats.seek_page_n() - before < lookups.len() as i64 * 2);
+      assert!(b.get_many::<&[u8]>(&[]).is_empty());
       Ok(())
     })?;
     Ok(())
   }
 
   #[test]
-  fn test_bucket_stats_nested() -> crate::Result<()> {
+  fn test_bucket_snapshot() -> crate::Result<()> {
     let mut db = TestDb::new()?;
     db.update(|mut tx| {
-      let mut b = tx.create_bucket("foo")?;
-      for i in 0..100 {
-        let i_str = format!("{:02}", i);
-        b.put(&i_str, &i_str)?;
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "before")?;
       }
-      let mut bar = b.create_bucket("bar")?;
-      for i in 0..10 {
-        let i_str = format!("{}", i);
-        bar.put(&i_str, &i_str)?;
+      b.create_bucket("sub")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..2000u32 {
+        b.put(i.to_be_bytes(), "after")?;
       }
-      let mut baz = bar.create_bucket("baz")?;
-      for i in 0..10 {
-        let i_str = format!("{}", i);
-        baz.put(&i_str, &i_str)?;
+      b.delete(5u32.to_be_bytes())?;
+      b.delete_bucket("sub")?;
+      {
+        let mut new = b.create_bucket("new")?;
+        assert!(new.snapshot().is_none());
+        new.put("a", "b")?;
       }
+
+      let snapshot = b.snapshot().unwrap();
+      assert!(!snapshot.writable());
+      assert_eq!(1001, snapshot.key_n());
+      assert_eq!(Some(b"before".as_slice()), snapshot.get(5u32.to_be_bytes()));
+      assert_eq!(None, snapshot.get(1500u32.to_be_bytes()));
+      assert!(snapshot.bucket("new").is_none());
+      let sub = snapshot.bucket("sub").unwrap();
+      assert!(!sub.writable());
+      assert_eq!(Some(b"bar".as_slice()), sub.get("foo"));
+
+      let mut c = snapshot.cursor();
+      let (k, v) = c.last().unwrap();
+      assert_eq!(b"sub".as_slice(), k);
+      assert_eq!(None, v);
+      assert_eq!(None, b.get(5u32.to_be_bytes()));
+
+      // Writes after the snapshot was taken don't show in it
+      b.put(5u32.to_be_bytes(), "later")?;
+      b.bucket_mut("new").unwrap().put("c", "d")?;
+      assert_eq!(Some(b"before".as_slice()), snapshot.get(5u32.to_be_bytes()));
+      assert!(snapshot.bucket("new").is_none());
       Ok(())
     })?;
-    db.must_check();
+    Ok(())
+  }
 
+  #[test]
+  fn test_bucket_meta() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.set_meta("version", "1")?;
+      let mut child = b.create_bucket("child")?;
+      child.set_meta("version", "2")?;
+      let mut other = tx.create_bucket("widgets2")?;
+      other.set_meta("version", "3")?;
+      assert_eq!(Err(Error::KeyRequired), other.set_meta("", "x"));
+      assert_eq!(Err(Error::ReservedName), other.set_meta("bbolt.quota", "x"));
+      assert_eq!(Err(Error::ReservedName), other.delete_meta("bbolt.quota"));
+      Ok(())
+    })?;
     db.view(|tx| {
-      let b = tx.bucket("foo").unwrap();
-      let stats = b.stats();
-      assert_eq!(0, stats.branch_page_n, "unexpected BranchPageN");
-      assert_eq!(0, stats.branch_overflow_n, "unexpected BranchOverflowN");
-      assert_eq!(2, stats.leaf_page_n, "unexpected LeafPageN");
-      assert_eq!(0, stats.leaf_overflow_n, "unexpected LeafOverflowN");
-      assert_eq!(122, stats.key_n, "unexpected KeyN");
-      assert_eq!(3, stats.depth, "unexpected Depth");
-      assert_eq!(0, stats.branch_in_use, "unexpected BranchInuse");
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"1".as_slice()), b.get_meta("version"));
+      assert_eq!(None, b.get("version"));
+      let child = b.bucket("child").unwrap();
+      assert_eq!(Some(b"2".as_slice()), child.get_meta("version"));
+      Ok(())
+    })?;
 
-      let mut foo = 16; // foo (pghdr)
-      foo += 101 * 16; // foo leaf elements
-      foo += 100 * 2 + 100 * 2; // foo leaf key/values
-      foo += 3 + 16; // foo -> bar key/value
+    // Deleting a bucket deletes the metadata of its sub-buckets, but not of its siblings
+    db.update(|mut tx| {
+      tx.delete_bucket("widgets")?;
+      let mut b = tx.create_bucket("widgets")?;
+      assert_eq!(None, b.get_meta("version"));
+      assert_eq!(None, b.create_bucket("child")?.get_meta("version"));
+      // stats only reads committed pages, key_n sees the deletes of this transaction
+      let meta = BucketImpl::from(tx.tx.system_bucket(META_BUCKET).unwrap());
+      assert_eq!(1, meta.key_n());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
 
-      let mut bar = 16; // bar (pghdr)
-      bar += 11 * 16; // bar leaf elements
-      bar += 10 + 10; // bar leaf key/values
-      bar += 3 + 16; //bar -> baz key/value
+  #[test]
+  fn test_bucket_quota() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("tenant")?;
+      b.put("a", "1234")?;
+      b.set_quota(Some(BucketQuota {
+        max_bytes: Some(20),
+        max_keys: Some(3),
+      }))?;
+      let mut child = b.create_bucket("child")?;
+      child.put("b", "1234")?;
+      assert_eq!(
+        Err(Error::IncompatibleValue),
+        child.set_quota(Some(BucketQuota::default()))
+      );
+      Ok(())
+    })?;
+    let usage = |db: &TestDb| -> crate::Result<QuotaUsage> {
+      let tx = db.begin()?;
+      let usage = tx.bucket("tenant").unwrap().stats().quota_usage().unwrap();
+      Ok(usage)
+    };
+    assert_eq!(QuotaUsage { bytes: 10, keys: 2 }, usage(&db)?);
 
-      let mut baz = 16; // baz (inline) (pghdr)
-      baz += 10 * 16; // baz leaf elements
-      baz += 10 + 10; // baz leaf key/values
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("tenant").unwrap();
+      // Overwrites count the difference in size
+      b.put("a", "12345678")?;
+      assert_eq!(Err(Error::QuotaExceeded), b.put("a", "123456789012345"));
+      b.put("c", "1")?;
+      assert_eq!(Err(Error::QuotaExceeded), b.put("d", "1"));
+      b.delete("c")?;
+      Ok(())
+    })?;
+    assert_eq!(QuotaUsage { bytes: 14, keys: 2 }, usage(&db)?);
 
-      assert_eq!(foo + bar + baz, stats.leaf_in_use, "unexpected LeafInuse");
+    // Rolling back restores the usage
+    db.update(|mut tx| {
+      let savepoint = tx.savepoint();
+      tx.bucket_mut("tenant").unwrap().put("c", "1")?;
+      tx.bucket_mut("tenant").unwrap().delete_bucket("child")?;
+      savepoint.rollback()?;
+      let stats = tx.bucket("tenant").unwrap().stats();
+      assert_eq!(Some(QuotaUsage { bytes: 14, keys: 2 }), stats.quota_usage());
+      Ok(())
+    })?;
 
-      //TODO: Fails if page_size != 4096? Db.Info?
-      assert_eq!(0, stats.branch_alloc, "unexpected BranchAlloc");
-      assert_eq!(8192, stats.leaf_alloc, "unexpected LeafAlloc");
+    // Deleting a nested bucket releases its keys
+    db.update(|mut tx| tx.bucket_mut("tenant").unwrap().delete_bucket("child"))?;
+    assert_eq!(QuotaUsage { bytes: 9, keys: 1 }, usage(&db)?);
+    db.update(|mut tx| tx.bucket_mut("tenant").unwrap().clear())?;
+    assert_eq!(QuotaUsage::default(), usage(&db)?);
 
-      assert_eq!(3, stats.bucket_n, "unexpected BucketN");
-      assert_eq!(1, stats.inline_bucket_n, "unexpected InlineBucketN");
-      assert_eq!(
-        baz, stats.inline_bucket_in_use,
-        "unexpected InlineBucketInuse"
-      );
+    db.update(|mut tx| {
+      let mut meta = BucketRwImpl::from(tx.tx.system_bucket(META_BUCKET).unwrap());
+      assert_eq!(Err(Error::IncompatibleValue), meta.set_quota(None));
+      let mut b = tx.bucket_mut("tenant").unwrap();
+      b.set_quota(None)?;
+      b.put("large", [0u8; 100])?;
+      assert_eq!(None, b.stats().quota());
       Ok(())
     })?;
+    db.must_check();
     Ok(())
   }
 
   #[test]
-  #[cfg(feature = "long-tests")]
-  fn test_bucket_stats_large() -> crate::Result<()> {
+  fn test_bucket_inline_conversion() -> crate::Result<()> {
     let mut db = TestDb::new()?;
-    let mut index = 0;
-    for _ in 0..100 {
-      db.update(|mut tx| {
-        let mut b = tx.create_bucket_if_not_exists("widgets")?;
-        for _ in 0..1000 {
-          let i_str = format!("{}", index);
-          b.put(&i_str, &i_str)?;
-          index += 1;
-        }
-        Ok(())
-      })?;
-    }
+    db.update(|mut tx| {
+      let mut users = tx.create_bucket("users")?;
+      users.create_bucket("small")?.put("name", "alice")?;
+      let mut large = users.create_bucket("large")?;
+      for i in 0..100u32 {
+        large.put(i.to_be_bytes(), "*".repeat(100))?;
+      }
+      Ok(())
+    })?;
+    let is_inline = |db: &TestDb, name: &str| -> crate::Result<bool> {
+      let tx = db.begin()?;
+      let inline = tx
+        .bucket("users")
+        .unwrap()
+        .bucket(name)
+        .unwrap()
+        .stats()
+        .is_inline();
+      Ok(inline)
+    };
+    assert!(is_inline(&db, "small")?);
+    assert!(!is_inline(&db, "large")?);
 
+    db.update(|mut tx| {
+      let mut users = tx.bucket_mut("users").unwrap();
+      users.bucket_mut("small").unwrap().force_materialize()?;
+      Ok(())
+    })?;
     db.must_check();
+    assert!(!is_inline(&db, "small")?);
+    db.view(|tx| {
+      let users = tx.bucket("users").unwrap();
+      assert_eq!(0, users.stats().inline_bucket_n());
+      let small = users.bucket("small").unwrap();
+      assert_eq!(Some(b"alice".as_slice()), small.get("name"));
+      Ok(())
+    })?;
 
-    // TODO: Support pagesize that's not 4096
-    let stat_4096 = BucketStats {
-      branch_page_n: 13,
-      branch_overflow_n: 0,
-      leaf_page_n: 1196,
-      leaf_overflow_n: 0,
-      key_n: 100_000,
-      depth: 3,
-      branch_alloc: 53_248,
-      branch_in_use: 25_257,
-      leaf_alloc: 4_898_816,
-      leaf_in_use: 2_596_916,
-      bucket_n: 1,
-      inline_bucket_n: 0,
-      inline_bucket_in_use: 0,
-    };
+    // The choice outlives the transaction and survives a rolled back try_inline
+    db.update(|mut tx| {
+      let mut users = tx.bucket_mut("users").unwrap();
+      users.bucket_mut("small").unwrap().put("name", "bob")?;
+      let savepoint = tx.savepoint();
+      let mut users = tx.bucket_mut("users").unwrap();
+      assert!(users.bucket_mut("small").unwrap().try_inline()?);
+      savepoint.rollback()?;
+      Ok(())
+    })?;
+    db.must_check();
+    assert!(!is_inline(&db, "small")?);
 
-    db.view(|tx| {
-      let b = tx.bucket("widgets").unwrap();
-      let stats = b.stats();
-      assert_eq!(stat_4096, stats, "stats differs from expectations");
+    db.update(|mut tx| {
+      let mut users = tx.bucket_mut("users").unwrap();
+      assert!(!users.bucket_mut("large").unwrap().try_inline()?);
+      assert!(users.bucket_mut("small").unwrap().try_inline()?);
       Ok(())
     })?;
+    db.must_check();
+    assert!(is_inline(&db, "small")?);
+    assert!(!is_inline(&db, "large")?);
     Ok(())
   }
 
@@ -0,0 +1,243 @@
+use crate::common::TxId;
+use crate::replication::{ChangeListener, ChangeOp, Changeset};
+use crate::{Bolt, BucketApi, TxApi};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+type CacheKey = (Vec<u8>, Vec<u8>);
+
+struct CacheEntry<V> {
+  value: Option<V>,
+  /// The insertion order of the entry, to tell it from earlier entries of its key
+  seq: u64,
+}
+
+struct CacheState<V> {
+  entries: HashMap<CacheKey, CacheEntry<V>>,
+  /// The keys in insertion order. Keys of removed entries are skipped when evicting.
+  order: VecDeque<(u64, CacheKey)>,
+  next_seq: u64,
+  /// The last commit passed to the cache. Values read from an earlier snapshot may be
+  /// stale and are not inserted.
+  txid: TxId,
+}
+
+impl<V> CacheState<V> {
+  fn invalidate(&mut self, changeset: &Changeset) {
+    for op in changeset.ops() {
+      match op {
+        ChangeOp::Put { path, key, .. } | ChangeOp::Delete { path, key } if path.len() == 1 => {
+          self.entries.remove(&(path[0].clone(), key.clone()));
+        }
+        ChangeOp::CreateBucket { path, name } | ChangeOp::DeleteBucket { path, name } => {
+          match path.len() {
+            0 => self.entries.retain(|(bucket, _), _| bucket != name),
+            1 => {
+              self.entries.remove(&(path[0].clone(), name.clone()));
+            }
+            _ => (),
+          }
+        }
+        ChangeOp::Clear { path } if path.len() == 1 => {
+          self.entries.retain(|(bucket, _), _| *bucket != path[0])
+        }
+        _ => (),
+      }
+    }
+    // Readers of earlier snapshots may still be decoding values this commit replaced
+    self.txid = changeset.txid();
+  }
+
+  fn insert(&mut self, capacity: usize, key: CacheKey, value: Option<V>) {
+    if capacity == 0 {
+      return;
+    }
+    while self.entries.len() >= capacity {
+      match self.order.pop_front() {
+        Some((seq, key)) => {
+          if self.entries.get(&key).is_some_and(|entry| entry.seq == seq) {
+            self.entries.remove(&key);
+          }
+        }
+        None => break,
+      }
+    }
+    let seq = self.next_seq;
+    self.next_seq += 1;
+    self.order.push_back((seq, key.clone()));
+    self.entries.insert(key, CacheEntry { value, seq });
+    if self.order.len() > capacity * 2 {
+      let entries = &self.entries;
+      self
+        .order
+        .retain(|(seq, key)| entries.get(key).is_some_and(|entry| entry.seq == *seq));
+    }
+  }
+}
+
+/// Cache is a typed read-through cache of the values of top-level buckets.
+///
+/// Values are read and decoded on the first lookup of a (bucket, key) pair and served
+/// from memory afterwards. Every commit passes its changes to the cache, which drops the
+/// entries the commit modified, so a lookup never returns a value older than the last
+/// commit that finished before it. Missing keys are cached as well. Once `capacity`
+/// entries are cached the oldest entries are evicted.
+///
+/// Write transactions record their changes while a cache is open, as with
+/// [BoltOptions::replication_log](crate::BoltOptionsBuilder::replication_log).
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///   let cache = Cache::new(db.clone(), 1024, |value| {
+///     Ok(String::from_utf8_lossy(value).into_owned())
+///   });
+///
+///   db.update(|mut tx| {
+///     tx.create_bucket("users")?.put("1", "alice")?;
+///     Ok(())
+///   })?;
+///   assert_eq!(Some("alice".to_string()), cache.get("users", "1")?);
+///
+///   db.update(|mut tx| {
+///     tx.bucket_mut("users").unwrap().put("1", "bob")?;
+///     Ok(())
+///   })?;
+///   assert_eq!(Some("bob".to_string()), cache.get("users", "1")?);
+///   Ok(())
+/// }
+/// ```
+pub struct Cache<V: Clone + Send + 'static> {
+  db: Bolt,
+  capacity: usize,
+  state: Arc<Mutex<CacheState<V>>>,
+  decode: Box<dyn Fn(&[u8]) -> crate::Result<V> + Send + Sync>,
+  _listener: Arc<ChangeListener>,
+}
+
+impl<V: Clone + Send + 'static> Cache<V> {
+  /// Creates a cache of up to `capacity` entries which decodes values with `decode`.
+  ///
+  /// Waits for the running write transaction to finish, so it must not be called from
+  /// within one.
+  pub fn new<F>(db: Bolt, capacity: usize, decode: F) -> Cache<V>
+  where
+    F: Fn(&[u8]) -> crate::Result<V> + Send + Sync + 'static,
+  {
+    let state = Arc::new(Mutex::new(CacheState {
+      entries: HashMap::new(),
+      order: VecDeque::new(),
+      next_seq: 0,
+      txid: TxId(0),
+    }));
+    let listener_state = state.clone();
+    let listener: Arc<ChangeListener> =
+      Arc::new(move |changeset: &Changeset| listener_state.lock().invalidate(changeset));
+    db.add_change_listener(Arc::downgrade(&listener));
+    Cache {
+      db,
+      capacity,
+      state,
+      decode: Box::new(decode),
+      _listener: listener,
+    }
+  }
+
+  /// Returns the decoded value of `key` in the top-level bucket `bucket`, reading it from
+  /// the database if it's not cached.
+  ///
+  /// Returns None if the bucket or the key doesn't exist, or if the key is a sub-bucket.
+  pub fn get<B: AsRef<[u8]>, K: AsRef<[u8]>>(&self, bucket: B, key: K) -> crate::Result<Option<V>> {
+    let key = (bucket.as_ref().to_vec(), key.as_ref().to_vec());
+    if let Some(entry) = self.state.lock().entries.get(&key) {
+      return Ok(entry.value.clone());
+    }
+    let tx = self.db.begin_tx()?;
+    let value = match tx.bucket(&key.0) {
+      Some(b) => match b.get(&key.1) {
+        Some(data) => Some((self.decode)(data)?),
+        None => None,
+      },
+      None => None,
+    };
+    let txid = tx.id();
+    drop(tx);
+    let mut state = self.state.lock();
+    if txid >= state.txid {
+      state.insert(self.capacity, key, value.clone());
+    }
+    Ok(value)
+  }
+
+  /// Returns the number of cached entries
+  pub fn len(&self) -> usize {
+    self.state.lock().entries.len()
+  }
+
+  /// Returns true if no entries are cached
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Drops all cached entries
+  pub fn clear(&self) {
+    let mut state = self.state.lock();
+    state.entries.clear();
+    state.order.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Bolt, BucketRwApi, Cache, DbRwAPI, TxRwRefApi};
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  #[test]
+  fn test_cache() -> crate::Result<()> {
+    let mut db = Bolt::open_mem()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("a")?;
+      b.put("1", "one")?;
+      b.put("2", "two")?;
+      tx.create_bucket("b")?.put("1", "uno")?;
+      Ok(())
+    })?;
+    let decoded = Arc::new(AtomicUsize::new(0));
+    let counter = decoded.clone();
+    let cache = Cache::new(db.clone(), 2, move |value| {
+      counter.fetch_add(1, Ordering::SeqCst);
+      Ok(value.to_vec())
+    });
+
+    assert_eq!(Some(b"one".to_vec()), cache.get("a", "1")?);
+    assert_eq!(Some(b"one".to_vec()), cache.get("a", "1")?);
+    assert_eq!(Some(b"uno".to_vec()), cache.get("b", "1")?);
+    assert_eq!(2, decoded.load(Ordering::SeqCst));
+
+    // Only the modified key is invalidated
+    db.update(|mut tx| tx.bucket_mut("a").unwrap().put("1", "eins"))?;
+    assert_eq!(1, cache.len());
+    assert_eq!(Some(b"eins".to_vec()), cache.get("a", "1")?);
+    assert_eq!(Some(b"uno".to_vec()), cache.get("b", "1")?);
+    assert_eq!(3, decoded.load(Ordering::SeqCst));
+
+    // Missing keys are cached until they're put
+    assert_eq!(None, cache.get("a", "3")?);
+    assert_eq!(2, cache.len());
+    db.update(|mut tx| tx.bucket_mut("a").unwrap().put("3", "drei"))?;
+    assert_eq!(Some(b"drei".to_vec()), cache.get("a", "3")?);
+
+    // Deleting a bucket invalidates all of its entries
+    db.update(|mut tx| tx.delete_bucket("a"))?;
+    assert_eq!(None, cache.get("a", "1")?);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    Ok(())
+  }
+}
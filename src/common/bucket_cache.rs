@@ -0,0 +1,45 @@
+use crate::bucket::BucketCell;
+use crate::common::{HashMap, MapHasher, PgId};
+use bumpalo::Bump;
+use std::cell::RefCell;
+
+/// BucketCache remembers the sub-buckets opened in a read-only transaction, so opening the
+/// same bucket again skips the cursor seek.
+///
+/// Buckets are identified by the root page of their parent and their name. Inline buckets
+/// have no root page, but they can't hold sub-buckets either. The pages of a read-only
+/// transaction never change, so entries don't need to be invalidated.
+pub(crate) struct BucketCache<'tx> {
+  bump: &'tx Bump,
+  buckets: RefCell<HashMap<'tx, PgId, HashMap<'tx, &'tx [u8], BucketCell<'tx>>>>,
+}
+
+impl<'tx> BucketCache<'tx> {
+  pub(crate) fn new_in(bump: &'tx Bump) -> BucketCache<'tx> {
+    BucketCache {
+      bump,
+      buckets: RefCell::new(HashMap::with_hasher_in(MapHasher::default(), bump)),
+    }
+  }
+
+  /// Returns the sub-bucket `name` of the bucket with the root page `parent`
+  pub(crate) fn get(&self, parent: PgId, name: &[u8]) -> Option<BucketCell<'tx>> {
+    self
+      .buckets
+      .borrow()
+      .get(&parent)
+      .and_then(|children| children.get(name))
+      .copied()
+  }
+
+  /// Remembers the sub-bucket `name` of the bucket with the root page `parent`
+  pub(crate) fn insert(&self, parent: PgId, name: &[u8], bucket: BucketCell<'tx>) {
+    let bump = self.bump;
+    self
+      .buckets
+      .borrow_mut()
+      .entry(parent)
+      .or_insert_with(|| HashMap::with_hasher_in(MapHasher::default(), bump))
+      .insert(bump.alloc_slice_copy(name), bucket);
+  }
+}
@@ -8,12 +8,66 @@ pub const MAX_MMAP_STEP: Size = Size::from_const(1 * GiB);
 
 pub const VERSION: u32 = 2;
 
+/// The version of databases that may contain prefix compressed branch pages
+pub const VERSION_PREFIX_BRANCH: u32 = 3;
+
+/// The version of databases that may contain checksummed branch and leaf pages
+pub const VERSION_PAGE_CHECKSUMS: u32 = 4;
+
+/// The version of databases that may contain prefix compressed branch pages and
+/// checksummed branch and leaf pages
+pub const VERSION_PREFIX_BRANCH_CHECKSUMS: u32 = 5;
+
+/// The meta versions this build can open
+#[cfg(not(feature = "prefix-branch"))]
+pub const SUPPORTED_VERSIONS: &[u32] = &[VERSION, VERSION_PAGE_CHECKSUMS];
+/// The meta versions this build can open
+#[cfg(feature = "prefix-branch")]
+pub const SUPPORTED_VERSIONS: &[u32] = &[
+  VERSION,
+  VERSION_PREFIX_BRANCH,
+  VERSION_PAGE_CHECKSUMS,
+  VERSION_PREFIX_BRANCH_CHECKSUMS,
+];
+
+/// Returns true if databases of `version` may contain prefix compressed branch pages
+#[inline]
+pub const fn has_prefix_branch(version: u32) -> bool {
+  matches!(
+    version,
+    VERSION_PREFIX_BRANCH | VERSION_PREFIX_BRANCH_CHECKSUMS
+  )
+}
+
+/// Returns true if databases of `version` may contain checksummed pages
+#[inline]
+pub const fn has_page_checksums(version: u32) -> bool {
+  matches!(
+    version,
+    VERSION_PAGE_CHECKSUMS | VERSION_PREFIX_BRANCH_CHECKSUMS
+  )
+}
+
+/// Returns the version of databases whose pages may use the given features
+#[inline]
+pub const fn version_with(prefix_branch: bool, page_checksums: bool) -> u32 {
+  match (prefix_branch, page_checksums) {
+    (false, false) => VERSION,
+    (true, false) => VERSION_PREFIX_BRANCH,
+    (false, true) => VERSION_PAGE_CHECKSUMS,
+    (true, true) => VERSION_PREFIX_BRANCH_CHECKSUMS,
+  }
+}
+
 #[cfg(not(feature = "compat"))]
 // Chosen from https://nedbatchelder.com/text/hexwords.html
 // as we are using the Go BBolt project code as a scaffold
 pub const MAGIC: u32 = 0x5caff01d;
 #[cfg(feature = "compat")]
-pub const MAGIC: u32 = 0xED0CDAED;
+pub const MAGIC: u32 = GO_MAGIC;
+
+/// The magic number of etcd-io/bbolt databases
+pub const GO_MAGIC: u32 = 0xED0CDAED;
 
 pub const PGID_NO_FREE_LIST: PgId = PgId(0xffffffffffffffff);
 
@@ -23,4 +77,7 @@ pub const DEFAULT_MAX_BATCH_SIZE: u32 = 1000;
 pub const DEFAULT_MAX_BATCH_DELAY: Duration = Duration::from_millis(10);
 pub const DEFAULT_ALLOC_SIZE: Size = Size::from_const(16 * MiB);
 
+/// How often opening a database retries taking the file lock when it has a timeout
+pub const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
 pub static DEFAULT_PAGE_SIZE: Lazy<Size> = Lazy::new(|| Size::from_bytes(page_size::get()));
@@ -1,3 +1,6 @@
+use crate::common::meta::MetaDiagnostics;
+use crate::common::{PgId, TxId};
+use std::borrow::Cow;
 use std::io;
 use thiserror::Error;
 
@@ -15,18 +18,33 @@ pub enum Error {
   DatabaseOpen,
   /// InvalidDatabase is returned when both meta pages on a database are invalid.
   /// This typically occurs when a file is not a bolt database.
-  #[error("invalid database - meta_can_read: `{0}`")]
-  InvalidDatabase(bool),
+  ///
+  /// When opening a database `meta` describes what was found on meta page 0.
+  #[error("invalid database - meta_can_read: `{meta_can_read}`{}", meta_suffix(.meta))]
+  InvalidDatabase {
+    meta_can_read: bool,
+    meta: Option<Box<MetaDiagnostics>>,
+  },
   /// InvalidMapping is returned when the database file fails to get mapped.
   #[error("database isn't correctly mapped")]
   InvalidMapping,
   /// ErrVersionMismatch is returned when the data file was created with a
-  /// different version of Bolt.
-  #[error("version mismatch")]
-  VersionMismatch,
-  /// Checksum is returned when either meta page checksum does not match.
-  #[error("checksum mismatch")]
-  ChecksumMismatch,
+  /// different version of Bolt. See [crate::format]
+  #[error("version mismatch - found `{found}`, supported `{supported:?}`")]
+  VersionMismatch {
+    found: u32,
+    supported: &'static [u32],
+  },
+  /// Checksum is returned when either meta page checksum does not match, or the checksum
+  /// of a branch or leaf page written with [crate::BoltOptionsBuilder::page_checksums].
+  ///
+  /// `page` is the branch or leaf page that failed. When opening a database `meta`
+  /// describes the meta page, e.g. to tell a torn write from a damaged file.
+  #[error("checksum mismatch{}{}", page_suffix(.page), meta_suffix(.meta))]
+  ChecksumMismatch {
+    page: Option<PgId>,
+    meta: Option<Box<MetaDiagnostics>>,
+  },
   /// File size is below the minimum size a Bolt database could be
   #[error("file size too small: `{0}`")]
   FileSizeTooSmall(u64),
@@ -75,6 +93,88 @@ pub enum Error {
   /// Batch is disabled
   #[error("max batch delay or length is set to 0")]
   BatchDisabled,
+  /// TxTimeout is returned when a write transaction is used after its
+  /// deadline has passed.
+  #[error("tx deadline exceeded")]
+  TxTimeout,
+  /// TxEvicted is returned when a read transaction is used after it was
+  /// evicted for being open too long.
+  /// See [crate::BoltOptions::evict_readers_after]
+  #[error("tx evicted")]
+  TxEvicted,
+  /// QuotaExceeded is returned when a put would grow a bucket beyond its quota.
+  /// See [crate::BucketRwApi::set_quota]
+  #[error("bucket quota exceeded")]
+  QuotaExceeded,
+  /// Corrupted is returned when a page read from the database fails its
+  /// sanity checks.
+  #[error("page {pgid}: corrupted - {reason}")]
+  Corrupted {
+    pgid: PgId,
+    reason: Cow<'static, str>,
+  },
+  /// PageOutOfRange is returned when reading a page at or beyond the high water mark of
+  /// the meta the transaction began on. See [crate::TxApi::page_bytes]
+  #[error("page {pgid}: out of range - high water mark {high_water}")]
+  PageOutOfRange { pgid: PgId, high_water: PgId },
+  /// UnknownComparator is returned when a bucket uses a key comparator
+  /// that is not registered with the database.
+  #[error("unknown comparator: `{0}`")]
+  UnknownComparator(String),
+  /// ReservedName is returned when a bucket name or metadata key starts with the
+  /// [RESERVED_PREFIX](crate::RESERVED_PREFIX) kept for the database's own records.
+  #[error("reserved name")]
+  ReservedName,
+  /// SavepointReleased is returned when rolling back to a savepoint that was
+  /// discarded by rolling back to an earlier savepoint.
+  #[error("savepoint released")]
+  SavepointReleased,
+  /// InvalidInternalState is returned when an internal invariant doesn't hold, e.g. a
+  /// node would store a zero-length key. The transaction must be rolled back.
+  #[error("invalid internal state: {0}")]
+  InvalidInternalState(String),
+  /// ReplicationLogRequired is returned when subscribing to the changesets of a
+  /// database opened without a replication log.
+  #[error("replication log required")]
+  ReplicationLogRequired,
+  /// InvalidChangeset is returned when a changeset read from a replication log
+  /// can't be decoded.
+  #[error("invalid changeset: {0}")]
+  InvalidChangeset(&'static str),
+  /// WriterDetected is returned by a read-only database when the database file was
+  /// written to while it was open, which its shared file lock should have prevented.
+  /// The database has to be reopened.
+  #[error("database file written while opened read-only")]
+  WriterDetected,
+  /// DatabaseLocked is returned when opening a database whose sidecar lock file is held
+  /// by another process. See [crate::Bolt::force_unlock]
+  #[error("database locked by process {pid} on {host}")]
+  DatabaseLocked { pid: u32, host: String },
+  /// FreelistOverlap is returned when a commit would write a freelist that contains a
+  /// page the new meta still refers to, directly or through a page written by the
+  /// commit. The transaction is rolled back instead of persisting a corrupt database.
+  #[error("tx {txid}: page {pgid} is in use and on the freelist (pending: {pending})")]
+  FreelistOverlap {
+    pgid: PgId,
+    txid: TxId,
+    pending: bool,
+  },
+  /// InvalidFree is returned when a transaction frees a meta page or a page that is
+  /// already on the freelist. `context` names the operation that freed it. The
+  /// transaction can't be committed.
+  #[error("tx {txid}: {context} freed page {pgid}, which is {reason}")]
+  InvalidFree {
+    pgid: PgId,
+    txid: TxId,
+    context: &'static str,
+    reason: &'static str,
+  },
+  /// FileTruncated is returned when a transaction begins on a database whose file was
+  /// truncated by another process to fewer bytes than the database uses. Reading the
+  /// missing pages through the memory map would crash the process with SIGBUS.
+  /// See [crate::BoltOptionsBuilder::detect_truncation]
+  #[error("database file truncated to {file_size} bytes, {required} required")]
+  FileTruncated { file_size: u64, required: u64 },
   /// Chained errors from other sources
   #[error(transparent)]
   IO(#[from] io::Error),
@@ -85,32 +185,116 @@ pub enum Error {
 
 impl PartialEq for Error {
   fn eq(&self, other: &Self) -> bool {
-    matches!(
-      (self, other),
-      (&Error::DatabaseNotOpen, &Error::DatabaseNotOpen)
-        | (Error::DatabaseOpen, Error::DatabaseOpen)
-        | (Error::InvalidDatabase(_), Error::InvalidDatabase(_))
-        | (Error::InvalidMapping, Error::InvalidMapping)
-        | (Error::VersionMismatch, Error::VersionMismatch)
-        | (Error::ChecksumMismatch, Error::ChecksumMismatch)
-        | (Error::FileSizeTooSmall(_), Error::FileSizeTooSmall(_))
-        | (Error::Timeout, Error::Timeout)
-        | (Error::FreePagesNotLoaded, Error::FreePagesNotLoaded)
-        | (Error::BucketNotFound, Error::BucketNotFound)
-        | (Error::BucketExists, Error::BucketExists)
-        | (Error::BucketNameRequired, Error::BucketNameRequired)
-        | (Error::KeyRequired, Error::KeyRequired)
-        | (Error::KeyTooLarge, Error::KeyTooLarge)
-        | (Error::ValueTooLarge, Error::ValueTooLarge)
-        | (Error::IncompatibleValue, Error::IncompatibleValue)
-        | (Error::MMapTooSmall(_), Error::MMapTooSmall(_))
-        | (Error::MMapTooLarge, Error::MMapTooLarge)
-        | (Error::TrySolo, Error::TrySolo)
-        | (Error::BatchDisabled, Error::BatchDisabled)
-    )
+    match (self, other) {
+      (
+        Error::Corrupted { pgid, reason },
+        Error::Corrupted {
+          pgid: other_pgid,
+          reason: other_reason,
+        },
+      ) => pgid == other_pgid && reason == other_reason,
+      (
+        Error::PageOutOfRange { pgid, high_water },
+        Error::PageOutOfRange {
+          pgid: other_pgid,
+          high_water: other_high_water,
+        },
+      ) => pgid == other_pgid && high_water == other_high_water,
+      _ => matches!(
+        (self, other),
+        (&Error::DatabaseNotOpen, &Error::DatabaseNotOpen)
+          | (Error::DatabaseOpen, Error::DatabaseOpen)
+          | (Error::InvalidDatabase { .. }, Error::InvalidDatabase { .. })
+          | (Error::InvalidMapping, Error::InvalidMapping)
+          | (Error::VersionMismatch { .. }, Error::VersionMismatch { .. })
+          | (
+            Error::ChecksumMismatch { .. },
+            Error::ChecksumMismatch { .. }
+          )
+          | (Error::FileSizeTooSmall(_), Error::FileSizeTooSmall(_))
+          | (Error::Timeout, Error::Timeout)
+          | (Error::FreePagesNotLoaded, Error::FreePagesNotLoaded)
+          | (Error::BucketNotFound, Error::BucketNotFound)
+          | (Error::BucketExists, Error::BucketExists)
+          | (Error::BucketNameRequired, Error::BucketNameRequired)
+          | (Error::KeyRequired, Error::KeyRequired)
+          | (Error::KeyTooLarge, Error::KeyTooLarge)
+          | (Error::ValueTooLarge, Error::ValueTooLarge)
+          | (Error::IncompatibleValue, Error::IncompatibleValue)
+          | (Error::MMapTooSmall(_), Error::MMapTooSmall(_))
+          | (Error::MMapTooLarge, Error::MMapTooLarge)
+          | (Error::TrySolo, Error::TrySolo)
+          | (Error::BatchDisabled, Error::BatchDisabled)
+          | (Error::TxTimeout, Error::TxTimeout)
+          | (Error::TxEvicted, Error::TxEvicted)
+          | (Error::QuotaExceeded, Error::QuotaExceeded)
+          | (Error::UnknownComparator(_), Error::UnknownComparator(_))
+          | (Error::ReservedName, Error::ReservedName)
+          | (Error::SavepointReleased, Error::SavepointReleased)
+          | (
+            Error::InvalidInternalState(_),
+            Error::InvalidInternalState(_)
+          )
+          | (Error::ReplicationLogRequired, Error::ReplicationLogRequired)
+          | (Error::InvalidChangeset(_), Error::InvalidChangeset(_))
+          | (Error::WriterDetected, Error::WriterDetected)
+          | (Error::DatabaseLocked { .. }, Error::DatabaseLocked { .. })
+          | (Error::FreelistOverlap { .. }, Error::FreelistOverlap { .. })
+          | (Error::InvalidFree { .. }, Error::InvalidFree { .. })
+          | (Error::FileTruncated { .. }, Error::FileTruncated { .. })
+      ),
+    }
   }
 }
 
 impl Eq for Error {}
 
+impl Error {
+  /// Attaches the description of the meta page that failed validation
+  pub(crate) fn with_meta(self, diagnostics: MetaDiagnostics) -> Error {
+    match self {
+      Error::InvalidDatabase { meta_can_read, .. } => Error::InvalidDatabase {
+        meta_can_read,
+        meta: Some(Box::new(diagnostics)),
+      },
+      Error::ChecksumMismatch { page, .. } => Error::ChecksumMismatch {
+        page,
+        meta: Some(Box::new(diagnostics)),
+      },
+      e => e,
+    }
+  }
+}
+
+fn page_suffix(page: &Option<PgId>) -> String {
+  match page {
+    Some(page) => format!(" - page {}", page),
+    None => String::new(),
+  }
+}
+
+fn meta_suffix(meta: &Option<Box<MetaDiagnostics>>) -> String {
+  match meta {
+    Some(meta) => format!(" - {}", meta),
+    None => String::new(),
+  }
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_corrupted_eq() {
+    let corrupted = |pgid: u64, reason: &'static str| Error::Corrupted {
+      pgid: PgId(pgid),
+      reason: Cow::Borrowed(reason),
+    };
+    assert_eq!(corrupted(3, "bad"), corrupted(3, "bad"));
+    assert_ne!(corrupted(3, "bad"), corrupted(4, "bad"));
+    assert_ne!(corrupted(3, "bad"), corrupted(3, "worse"));
+    assert_ne!(corrupted(3, "bad"), Error::InvalidMapping);
+  }
+}
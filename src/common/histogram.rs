@@ -0,0 +1,142 @@
+use parking_lot::Mutex;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+/// log2 of the number of linear sub-buckets within each power of two
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Returns the bucket of a value
+fn bucket_index(value: u64) -> usize {
+  if value < SUB_BUCKETS {
+    return value as usize;
+  }
+  let shift = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+  let sub_bucket = (value >> shift) & (SUB_BUCKETS - 1);
+  ((shift as u64 + 1) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Returns the largest value counted in a bucket
+fn bucket_upper_bound(index: usize) -> u64 {
+  let index = index as u64;
+  if index < SUB_BUCKETS {
+    return index;
+  }
+  let shift = index / SUB_BUCKETS - 1;
+  let lower = (SUB_BUCKETS + index % SUB_BUCKETS) << shift;
+  lower + ((1 << shift) - 1)
+}
+
+/// A latency histogram with logarithmic buckets.
+///
+/// Durations are recorded in microseconds. Like an HDR histogram every power of two is
+/// split into linear sub-buckets, so reported values are within 12.5% of the recorded ones.
+#[derive(Default)]
+pub struct LatencyHistogram {
+  counts: Mutex<Vec<u64>>,
+}
+
+impl LatencyHistogram {
+  pub(crate) fn record(&self, duration: Duration) {
+    let index = bucket_index(duration.as_micros().min(u64::MAX as u128) as u64);
+    let mut counts = self.counts.lock();
+    if counts.len() <= index {
+      counts.resize(index + 1, 0);
+    }
+    counts[index] += 1;
+  }
+
+  /// The number of recorded durations
+  pub fn count(&self) -> u64 {
+    self.counts.lock().iter().sum()
+  }
+
+  /// Returns the duration at or below which `quantile` of the recorded durations fall.
+  /// `quantile` is clamped to 0.0..=1.0
+  ///
+  /// Returns [Duration::ZERO] if nothing was recorded.
+  pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+    let counts = self.counts.lock();
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+      return Duration::ZERO;
+    }
+    let target = ((quantile.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+    let mut seen = 0;
+    for (index, count) in counts.iter().enumerate() {
+      seen += count;
+      if seen >= target {
+        return Duration::from_micros(bucket_upper_bound(index));
+      }
+    }
+    Duration::from_micros(bucket_upper_bound(counts.len() - 1))
+  }
+
+  /// The largest recorded duration
+  pub fn max(&self) -> Duration {
+    self.value_at_quantile(1.0)
+  }
+
+  /// Returns the upper bound and count of every non-empty bucket in ascending order
+  pub fn buckets(&self) -> Vec<(Duration, u64)> {
+    self
+      .counts
+      .lock()
+      .iter()
+      .enumerate()
+      .filter(|(_, count)| **count > 0)
+      .map(|(index, count)| (Duration::from_micros(bucket_upper_bound(index)), *count))
+      .collect()
+  }
+}
+
+impl Clone for LatencyHistogram {
+  fn clone(&self) -> Self {
+    LatencyHistogram {
+      counts: Mutex::new(self.counts.lock().clone()),
+    }
+  }
+}
+
+impl Debug for LatencyHistogram {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("LatencyHistogram")
+      .field("count", &self.count())
+      .field("p50", &self.value_at_quantile(0.5))
+      .field("p99", &self.value_at_quantile(0.99))
+      .field("max", &self.max())
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::common::histogram::{bucket_index, bucket_upper_bound, LatencyHistogram};
+  use std::time::Duration;
+
+  #[test]
+  fn test_bucket_bounds() {
+    for value in (0..10_000).chain([u64::MAX / 3, u64::MAX]) {
+      let index = bucket_index(value);
+      assert!(value <= bucket_upper_bound(index));
+      if index > 0 {
+        assert!(value > bucket_upper_bound(index - 1));
+      }
+    }
+  }
+
+  #[test]
+  fn test_latency_histogram() {
+    let histogram = LatencyHistogram::default();
+    assert_eq!(Duration::ZERO, histogram.max());
+    for ms in 1..=100 {
+      histogram.record(Duration::from_millis(ms));
+    }
+    assert_eq!(100, histogram.count());
+    let p50 = histogram.value_at_quantile(0.5);
+    assert!(p50 >= Duration::from_millis(50) && p50 <= Duration::from_millis(57));
+    let max = histogram.max();
+    assert!(max >= Duration::from_millis(100) && max <= Duration::from_millis(113));
+    assert_eq!(100, histogram.buckets().iter().map(|(_, n)| n).sum::<u64>());
+  }
+}
@@ -1,7 +1,7 @@
 use crate::common::memory::{CodSlice, PhantomUnsend};
 use crate::common::page::{CoerciblePage, RefPage};
 use crate::common::tree::{
-  BranchElementRef, LeafElementRef, MappedBranchPage, MappedLeafPage, TreePage,
+  prefixed_key, BranchElementRef, LeafElementRef, MappedBranchPage, MappedLeafPage, TreePage,
 };
 use crate::common::PgId;
 use bumpalo::collections::Vec as BVec;
@@ -53,6 +53,11 @@ impl<'tx> INode<'tx> {
     inode
   }
 
+  /// Replaces the value, keeping the key
+  pub fn set_value_in(&mut self, value: &[u8], bump: &'tx Bump) {
+    self.value = CodSlice::Owned(bump.alloc_slice_copy(value));
+  }
+
   pub fn own_in(&mut self, bump: &'tx Bump) {
     self.key.own_in(bump);
     self.value.own_in(bump);
@@ -89,8 +94,19 @@ impl<'tx> INode<'tx> {
       inodes.extend(i);
       assert_eq!(leaf_page.count as usize, inodes.len())
     } else if let Some(branch_page) = MappedBranchPage::coerce_ref(page) {
-      let i = branch_page.iter().map(INode::from_branch_in);
-      inodes.extend(i);
+      let prefix = branch_page.prefix();
+      if prefix.is_empty() {
+        let i = branch_page.iter().map(INode::from_branch_in);
+        inodes.extend(i);
+      } else {
+        // The keys of a prefix compressed page are rebuilt in full
+        let bump = inodes.bump();
+        let i = branch_page.iter().map(|elem| {
+          let key = prefixed_key(bump, prefix, elem.key());
+          INode::new_mapped_in(0, elem.pgid(), key, &[])
+        });
+        inodes.extend(i);
+      }
       assert_eq!(branch_page.count as usize, inodes.len())
     } else {
       panic!(
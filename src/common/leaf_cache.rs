@@ -0,0 +1,77 @@
+use crate::common::page::{CoerciblePage, RefPage};
+use crate::common::tree::{MappedLeafPage, TreePage};
+use crate::common::{BVec, PgId};
+use crate::comparator::KeyComparator;
+use bumpalo::Bump;
+use std::cell::{Cell, RefCell};
+
+/// A leaf page and the range of keys it holds
+#[derive(Copy, Clone)]
+struct CachedLeaf<'tx> {
+  root: PgId,
+  page: RefPage<'tx>,
+  first: &'tx [u8],
+  last: &'tx [u8],
+}
+
+/// LeafCache remembers the leaf pages the last gets of a read-only transaction ended on, so
+/// a get for a key in the range of a cached leaf skips the branch pages.
+///
+/// Leaves are identified by the root page of their bucket. The pages of a read-only
+/// transaction never change, so entries don't need to be invalidated.
+pub(crate) struct LeafCache<'tx> {
+  leaves: RefCell<BVec<'tx, CachedLeaf<'tx>>>,
+  next: Cell<usize>,
+}
+
+impl<'tx> LeafCache<'tx> {
+  /// The number of leaves remembered. Once full the oldest leaf is replaced.
+  pub(crate) const CAPACITY: usize = 8;
+
+  pub(crate) fn new_in(bump: &'tx Bump) -> LeafCache<'tx> {
+    LeafCache {
+      leaves: RefCell::new(BVec::new_in(bump)),
+      next: Cell::new(0),
+    }
+  }
+
+  /// Returns the cached leaf of the bucket with the root page `root` whose keys cover `key`
+  pub(crate) fn find(
+    &self, root: PgId, key: &[u8], comparator: KeyComparator,
+  ) -> Option<RefPage<'tx>> {
+    self
+      .leaves
+      .borrow()
+      .iter()
+      .find(|leaf| {
+        leaf.root == root
+          && comparator(leaf.first, key).is_le()
+          && comparator(key, leaf.last).is_le()
+      })
+      .map(|leaf| leaf.page)
+  }
+
+  /// Remembers the leaf page `page` of the bucket with the root page `root`
+  pub(crate) fn insert(&self, root: PgId, page: RefPage<'tx>) {
+    let leaf_page = match MappedLeafPage::coerce_ref(&page) {
+      Some(leaf_page) if leaf_page.count > 0 => leaf_page,
+      _ => return,
+    };
+    let first = leaf_page.get_elem(0).unwrap();
+    let last = leaf_page.get_elem(leaf_page.count - 1).unwrap();
+    let leaf = CachedLeaf {
+      root,
+      page,
+      first: first.key(),
+      last: last.key(),
+    };
+    let mut leaves = self.leaves.borrow_mut();
+    if leaves.len() < Self::CAPACITY {
+      leaves.push(leaf);
+    } else {
+      let next = self.next.get();
+      leaves[next] = leaf;
+      self.next.set((next + 1) % Self::CAPACITY);
+    }
+  }
+}
@@ -1,11 +1,12 @@
 use crate::common::bucket::BucketHeader;
-use crate::common::defaults::{MAGIC, PGID_NO_FREE_LIST, VERSION};
+use crate::common::defaults::{MAGIC, PGID_NO_FREE_LIST, SUPPORTED_VERSIONS};
 use crate::common::page::{CoerciblePage, PageHeader, META_PAGE_FLAG};
 use crate::common::{PgId, TxId};
 use crate::Error::{ChecksumMismatch, InvalidDatabase, VersionMismatch};
 use bytemuck::{Pod, Zeroable};
 use fnv_rs::{Fnv64, FnvHasher};
 use getset::{CopyGetters, Setters};
+use std::fmt::{Display, Formatter};
 use std::hash::Hasher;
 use std::marker::PhantomData;
 use std::mem;
@@ -17,7 +18,7 @@ pub const META_HEADER_SIZE: usize = mem::size_of::<Meta>();
 ///
 /// `meta` in Go BBolt
 #[repr(C)]
-#[derive(Debug, Default, Copy, Clone, CopyGetters, Setters, Pod, Zeroable)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, CopyGetters, Setters, Pod, Zeroable)]
 #[getset(get_copy = "pub", set = "pub")]
 pub struct Meta {
   /// Uniquely ID for BBolt databases
@@ -39,15 +40,33 @@ pub struct Meta {
   checksum: u64,
 }
 
+/// Returns true if this binary can read databases of `version`.
+///
+/// Databases with prefix compressed branch pages can only be read with the
+/// `prefix-branch` feature.
+#[inline]
+pub(crate) fn version_supported(version: u32) -> bool {
+  SUPPORTED_VERSIONS.contains(&version)
+}
+
 impl Meta {
   /// validate checks the marker bytes and version of the meta page to ensure it matches this binary.
   pub fn validate(&self) -> crate::Result<()> {
     if self.magic != MAGIC {
-      return Err(InvalidDatabase(true));
-    } else if self.version != VERSION {
-      return Err(VersionMismatch);
+      return Err(InvalidDatabase {
+        meta_can_read: true,
+        meta: None,
+      });
+    } else if !version_supported(self.version) {
+      return Err(VersionMismatch {
+        found: self.version,
+        supported: SUPPORTED_VERSIONS,
+      });
     } else if self.checksum != self.sum64() {
-      return Err(ChecksumMismatch);
+      return Err(ChecksumMismatch {
+        page: None,
+        meta: None,
+      });
     }
     Ok(())
   }
@@ -77,6 +96,18 @@ impl Meta {
     mp.meta.set_checksum(mp.meta.sum64());
   }
 
+  /// Describes the meta page `id` for an error. See [MetaDiagnostics]
+  pub(crate) fn diagnose(&self, id: PgId, file_size: u64) -> MetaDiagnostics {
+    MetaDiagnostics {
+      id,
+      magic: self.magic,
+      version: self.version,
+      stored_checksum: self.checksum,
+      computed_checksum: self.sum64(),
+      file_size,
+    }
+  }
+
   /// generates the checksum for the meta.
   pub fn sum64(&self) -> u64 {
     let mut h = Fnv64::new();
@@ -87,6 +118,110 @@ impl Meta {
   }
 }
 
+/// MetaInfo represents human-readable information about a meta page.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MetaInfo {
+  /// meta page id, either 0 or 1
+  pub id: PgId,
+  pub magic: u32,
+  pub version: u32,
+  pub page_size: u32,
+  pub flags: u32,
+  /// root bucket page
+  pub root: PgId,
+  /// root bucket sequence
+  pub sequence: u64,
+  /// freelist page
+  pub free_list: PgId,
+  /// the end of the database where EOF = high_water_mark * page_size
+  pub high_water_mark: PgId,
+  pub txid: TxId,
+  pub checksum: u64,
+  /// whether the magic number matches this binary
+  pub magic_ok: bool,
+  /// whether the version matches this binary
+  pub version_ok: bool,
+  /// whether the stored checksum matches the meta fields
+  pub checksum_ok: bool,
+  /// whether this is the meta page the database currently uses
+  pub active: bool,
+}
+
+impl MetaInfo {
+  pub(crate) fn new(id: PgId, meta: &Meta) -> MetaInfo {
+    MetaInfo {
+      id,
+      magic: meta.magic,
+      version: meta.version,
+      page_size: meta.page_size,
+      flags: meta.flags,
+      root: meta.root.root(),
+      sequence: meta.root.sequence(),
+      free_list: meta.free_list,
+      high_water_mark: meta.pgid,
+      txid: meta.txid,
+      checksum: meta.checksum,
+      magic_ok: meta.magic == MAGIC,
+      version_ok: version_supported(meta.version),
+      checksum_ok: meta.checksum == meta.sum64(),
+      active: false,
+    }
+  }
+
+  /// Returns true if the meta page passes validation.
+  #[inline]
+  pub fn is_valid(&self) -> bool {
+    self.magic_ok && self.version_ok && self.checksum_ok
+  }
+}
+
+/// MetaDiagnostics describes a meta page that failed validation when opening a database.
+///
+/// A file that isn't a Bolt database has the wrong magic number. A torn meta page write
+/// has the right magic number and version, but its stored checksum doesn't match.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MetaDiagnostics {
+  /// meta page id, either 0 or 1
+  pub id: PgId,
+  /// the magic number found
+  pub magic: u32,
+  /// the version found
+  pub version: u32,
+  /// the checksum stored in the page
+  pub stored_checksum: u64,
+  /// the checksum computed from the page's fields
+  pub computed_checksum: u64,
+  /// the size of the database file in bytes
+  pub file_size: u64,
+}
+
+impl MetaDiagnostics {
+  /// Returns true if the magic number marks a Bolt database
+  pub fn is_bolt_magic(&self) -> bool {
+    self.magic == MAGIC
+  }
+
+  /// Returns true if the stored checksum matches the page's fields
+  pub fn checksum_ok(&self) -> bool {
+    self.stored_checksum == self.computed_checksum
+  }
+}
+
+impl Display for MetaDiagnostics {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "meta {}: magic {:#010x}, version {}, checksum {:#018x} (computed {:#018x}), file size {}",
+      self.id,
+      self.magic,
+      self.version,
+      self.stored_checksum,
+      self.computed_checksum,
+      self.file_size
+    )
+  }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct MetaPage {
@@ -138,7 +273,7 @@ impl DerefMut for MappedMetaPage {
 #[cfg(test)]
 mod test {
   use super::*;
-  use crate::common::defaults::DEFAULT_PAGE_SIZE;
+  use crate::common::defaults::{DEFAULT_PAGE_SIZE, VERSION};
   use crate::test_support::mapped_page;
 
   #[test]
@@ -1,17 +1,22 @@
 pub use bumpalo::collections::Vec as BVec;
 use bumpalo::Bump;
-use hashbrown::hash_map::DefaultHashBuilder;
 
 pub mod bucket;
+pub(crate) mod bucket_cache;
 pub mod defaults;
 pub mod errors;
+pub mod histogram;
 pub mod inode;
+pub(crate) mod leaf_cache;
 pub mod memory;
 pub mod meta;
 pub mod page;
+pub(crate) mod search;
 pub mod self_owned;
 pub mod tree;
 pub mod utility;
+#[cfg(file_backend_supported)]
+pub(crate) mod validate;
 
 pub mod bump;
 
@@ -19,15 +24,33 @@ pub mod cell;
 pub mod ids;
 pub mod lock;
 pub mod pool;
+#[cfg(any(feature = "testing", feature = "page-store"))]
+pub(crate) mod rng;
 
 pub use ids::{PgId, TxId};
 
 pub(crate) const ZERO_PGID: PgId = PgId(0);
 
-pub type HashMap<'tx, K, V> = hashbrown::HashMap<K, V, DefaultHashBuilder, &'tx Bump>;
+/// The hasher of the transaction and bucket maps. Their keys are page ids and short key
+/// slices, which ahash hashes much faster than SipHash.
+#[cfg(not(feature = "secure-hash"))]
+pub type MapHasher = hashbrown::hash_map::DefaultHashBuilder;
 
-pub type HashSet<'tx, K> = hashbrown::HashSet<K, DefaultHashBuilder, &'tx Bump>;
+/// The hasher of the transaction and bucket maps. SipHash resists hash flooding by keys
+/// chosen to collide.
+#[cfg(feature = "secure-hash")]
+pub type MapHasher = std::collections::hash_map::RandomState;
 
+pub type HashMap<'tx, K, V> = hashbrown::HashMap<K, V, MapHasher, &'tx Bump>;
+
+pub type HashSet<'tx, K> = hashbrown::HashSet<K, MapHasher, &'tx Bump>;
+
+/// Access to the sections of a bump allocated cell, such as a transaction or a bucket.
+///
+/// The borrows returned here are runtime checked. None of them may be held while calling
+/// into code that can re-enter the API, which includes every user callback like the ones
+/// of `for_each` and `walk`. Copy what's needed out of the borrow and drop it first. That
+/// rule is what lets callbacks read the transaction they're called from.
 pub(crate) trait SplitRef<R, B, W>: Copy + Clone {
   /// Access the read section of the struct
   fn split_r(&self) -> cell::Ref<R>;
@@ -1,10 +1,18 @@
+use crate::common::tree::{
+  BranchPageElement, LeafPageElement, BRANCH_PAGE_ELEMENT_SIZE, LEAF_PAGE_ELEMENT_SIZE,
+  PREFIX_LEN_SIZE,
+};
 use crate::common::PgId;
+use crate::kv_stringer::KvStringer;
+use crate::Error;
 use bytemuck::{Pod, Zeroable};
 use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
+use xxhash_rust::xxh64::xxh64;
 
 pub const MIN_KEYS_PER_PAGE: usize = 2;
 
@@ -14,6 +22,18 @@ pub const BRANCH_PAGE_FLAG: u16 = 0x01;
 pub const LEAF_PAGE_FLAG: u16 = 0x02;
 pub const META_PAGE_FLAG: u16 = 0x04;
 pub const FREE_LIST_PAGE_FLAG: u16 = 0x10;
+/// Set together with [BRANCH_PAGE_FLAG] on branch pages that store the common prefix of
+/// their keys once. Only written with the `prefix-branch` feature.
+pub const PREFIX_BRANCH_PAGE_FLAG: u16 = 0x20;
+/// The flags of a prefix compressed branch page
+pub(crate) const PREFIX_BRANCH_FLAGS: u16 = BRANCH_PAGE_FLAG | PREFIX_BRANCH_PAGE_FLAG;
+/// Set together with the type flags on branch and leaf pages whose last
+/// [PAGE_CHECKSUM_SIZE] bytes, including overflow pages, are an xxHash64 checksum of the
+/// bytes before.
+/// See [crate::BoltOptionsBuilder::page_checksums]
+pub const CHECKSUM_PAGE_FLAG: u16 = 0x40;
+/// The size of the checksum at the end of a checksummed page
+pub const PAGE_CHECKSUM_SIZE: usize = mem::size_of::<u64>();
 
 pub const BUCKET_LEAF_FLAG: u32 = 0x01;
 
@@ -68,7 +88,7 @@ pub trait CoerciblePage {
   where
     Self: Sized,
   {
-    if mapped_page.flags == Self::page_flag() {
+    if mapped_page.type_flags() == Self::page_flag() {
       Some(unsafe { Self::unchecked_ref(mapped_page) })
     } else {
       None
@@ -81,7 +101,7 @@ pub trait CoerciblePage {
   where
     Self: Sized,
   {
-    if mapped_page.flags == Self::page_flag() {
+    if mapped_page.type_flags() == Self::page_flag() {
       Some(unsafe { Self::unchecked_mut(mapped_page) })
     } else {
       None
@@ -106,6 +126,38 @@ impl<'tx> RefPage<'tx> {
   }
 }
 
+impl<'tx> RefPage<'tx> {
+  /// Returns [Error::ChecksumMismatch] if the page has a checksum that doesn't match its
+  /// bytes. Pages without a checksum always pass.
+  pub fn verify_checksum(&self, page_size: usize) -> crate::Result<()> {
+    if !self.has_checksum() {
+      return Ok(());
+    }
+    let len = (self.overflow as usize + 1) * page_size;
+    let bytes = unsafe { std::slice::from_raw_parts(self.bytes, len) };
+    verify_page_checksum(self.id, bytes)
+  }
+}
+
+/// Returns the xxHash64 checksum of a checksummed page's bytes, without the checksum itself
+fn page_checksum(bytes: &[u8]) -> u64 {
+  xxh64(&bytes[..bytes.len() - PAGE_CHECKSUM_SIZE], 0)
+}
+
+/// Returns [Error::ChecksumMismatch] if the checksum at the end of the checksummed page
+/// `bytes` doesn't match
+pub(crate) fn verify_page_checksum(pgid: PgId, bytes: &[u8]) -> crate::Result<()> {
+  let (_, stored) = bytes.split_at(bytes.len() - PAGE_CHECKSUM_SIZE);
+  let stored = u64::from_le_bytes(stored.try_into().unwrap());
+  if stored != page_checksum(bytes) {
+    return Err(Error::ChecksumMismatch {
+      page: Some(pgid),
+      meta: None,
+    });
+  }
+  Ok(())
+}
+
 impl<'tx> Deref for RefPage<'tx> {
   type Target = PageHeader;
 
@@ -130,6 +182,34 @@ impl<'tx> MutPage<'tx> {
   }
 }
 
+#[cfg(poison_freed_pages)]
+impl<'tx> MutPage<'tx> {
+  /// The pattern freed dirty pages are overwritten with
+  pub(crate) const POISON: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+
+  /// Overwrites everything but the header of the `len` bytes long page with [Self::POISON].
+  /// The header is kept so the page can still be written out.
+  pub(crate) fn poison(&mut self, len: usize) {
+    let data = unsafe {
+      std::slice::from_raw_parts_mut(self.bytes.add(PAGE_HEADER_SIZE), len - PAGE_HEADER_SIZE)
+    };
+    for (b, p) in data.iter_mut().zip(Self::POISON.iter().cycle()) {
+      *b = *p;
+    }
+  }
+}
+
+impl<'tx> MutPage<'tx> {
+  /// Sets [CHECKSUM_PAGE_FLAG] and stores the checksum of the page's `len` bytes, including
+  /// its overflow pages, in their last [PAGE_CHECKSUM_SIZE] bytes
+  pub(crate) fn seal_checksum(&mut self, len: usize) {
+    self.flags |= CHECKSUM_PAGE_FLAG;
+    let bytes = unsafe { std::slice::from_raw_parts_mut(self.bytes, len) };
+    let checksum = page_checksum(bytes);
+    bytes[len - PAGE_CHECKSUM_SIZE..].copy_from_slice(&checksum.to_le_bytes());
+  }
+}
+
 impl<'tx> AsRef<RefPage<'tx>> for MutPage<'tx> {
   fn as_ref(&self) -> &RefPage<'tx> {
     unsafe { &*(self as *const MutPage<'tx> as *const RefPage<'tx>) }
@@ -209,20 +289,45 @@ impl PageHeader {
   }
 
   pub fn fast_check(&self, id: PgId) {
-    assert_eq!(
-      self.id, id,
-      "Page expected to be {}, but self identifies as {}",
-      id, self.id
-    );
-    assert!(
-      self.flags == BRANCH_PAGE_FLAG
-        || self.flags == LEAF_PAGE_FLAG
-        || self.flags == META_PAGE_FLAG
-        || self.flags == FREE_LIST_PAGE_FLAG,
-      "page {}: has unexpected type/flags {}",
-      self.id,
-      self.flags
-    );
+    if let Err(e) = self.check(id) {
+      panic!("{}", e);
+    }
+  }
+
+  /// check returns [Error::Corrupted](crate::Error::Corrupted) if the page does not
+  /// identify as `id` or has an unexpected type.
+  pub fn check(&self, id: PgId) -> crate::Result<()> {
+    if self.id != id {
+      return Err(Error::Corrupted {
+        pgid: id,
+        reason: Cow::Owned(format!("page identifies as {}", self.id)),
+      });
+    }
+    // Only branch and leaf pages are checksummed
+    let flags = self.type_flags();
+    let is_data = flags == BRANCH_PAGE_FLAG
+      || (cfg!(feature = "prefix-branch") && flags == PREFIX_BRANCH_FLAGS)
+      || flags == LEAF_PAGE_FLAG;
+    let is_other = self.flags == META_PAGE_FLAG || self.flags == FREE_LIST_PAGE_FLAG;
+    if !is_data && !is_other {
+      return Err(Error::Corrupted {
+        pgid: id,
+        reason: Cow::Owned(format!("unexpected type/flags {}", self.flags)),
+      });
+    }
+    Ok(())
+  }
+
+  /// Returns the flags without [CHECKSUM_PAGE_FLAG]
+  #[inline]
+  pub fn type_flags(&self) -> u16 {
+    self.flags & !CHECKSUM_PAGE_FLAG
+  }
+
+  /// Returns true if the page ends in a checksum
+  #[inline]
+  pub fn has_checksum(&self) -> bool {
+    self.flags & CHECKSUM_PAGE_FLAG != 0
   }
 
   #[inline]
@@ -269,3 +374,164 @@ pub struct PageInfo {
   pub count: u64,
   pub overflow_count: u64,
 }
+
+impl Display for PageInfo {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "page {}: {}, {} items, {} overflow",
+      self.id, self.t, self.count, self.overflow_count
+    )
+  }
+}
+
+/// A bounds checked, read-only view of a page's elements.
+#[derive(Debug, Eq, PartialEq)]
+pub enum PageElements<'a> {
+  Branch(Vec<BranchElement<'a>>),
+  Leaf(Vec<LeafElement<'a>>),
+  FreeList(Vec<PgId>),
+}
+
+/// A branch page element
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BranchElement<'a> {
+  /// The prefix shared by all keys of a prefix compressed page. Empty otherwise.
+  pub prefix: &'a [u8],
+  /// The key, or the key without its prefix on a prefix compressed page
+  pub key: &'a [u8],
+  pub pgid: PgId,
+}
+
+/// A leaf page element
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LeafElement<'a> {
+  pub flags: u32,
+  pub key: &'a [u8],
+  pub value: &'a [u8],
+}
+
+impl<'a> LeafElement<'a> {
+  /// Returns true if the value is a nested bucket.
+  #[inline]
+  pub fn is_bucket_entry(&self) -> bool {
+    self.flags & BUCKET_LEAF_FLAG != 0
+  }
+}
+
+impl<'a> PageElements<'a> {
+  /// Parses the elements of a raw page, including its overflow pages.
+  ///
+  /// Returns None if the page is not a branch, leaf or freelist page or if any element
+  /// points outside the provided bytes.
+  pub fn parse(bytes: &'a [u8]) -> Option<PageElements<'a>> {
+    let header: PageHeader = bytemuck::pod_read_unaligned(bytes.get(..PAGE_HEADER_SIZE)?);
+    let count = header.count as usize;
+    match header.type_flags() {
+      BRANCH_PAGE_FLAG | PREFIX_BRANCH_FLAGS => {
+        let prefix = if header.type_flags() == PREFIX_BRANCH_FLAGS {
+          if !cfg!(feature = "prefix-branch") {
+            return None;
+          }
+          let len_off = PAGE_HEADER_SIZE + count * BRANCH_PAGE_ELEMENT_SIZE;
+          let len: u32 =
+            bytemuck::pod_read_unaligned(bytes.get(len_off..len_off + PREFIX_LEN_SIZE)?);
+          element_slice(bytes, len_off, PREFIX_LEN_SIZE, len)?
+        } else {
+          &[]
+        };
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+          let elem_off = PAGE_HEADER_SIZE + i * BRANCH_PAGE_ELEMENT_SIZE;
+          let elem: BranchPageElement =
+            bytemuck::pod_read_unaligned(bytes.get(elem_off..elem_off + BRANCH_PAGE_ELEMENT_SIZE)?);
+          let key = element_slice(bytes, elem_off, elem.pos() as usize, elem.key_size())?;
+          elements.push(BranchElement {
+            prefix,
+            key,
+            pgid: elem.pgid(),
+          });
+        }
+        Some(PageElements::Branch(elements))
+      }
+      LEAF_PAGE_FLAG => {
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+          let elem_off = PAGE_HEADER_SIZE + i * LEAF_PAGE_ELEMENT_SIZE;
+          let elem: LeafPageElement =
+            bytemuck::pod_read_unaligned(bytes.get(elem_off..elem_off + LEAF_PAGE_ELEMENT_SIZE)?);
+          let key = element_slice(bytes, elem_off, elem.pos() as usize, elem.key_size())?;
+          let value = element_slice(
+            bytes,
+            elem_off,
+            elem.pos() as usize + elem.key_size() as usize,
+            elem.value_size(),
+          )?;
+          elements.push(LeafElement {
+            flags: elem.flags(),
+            key,
+            value,
+          });
+        }
+        Some(PageElements::Leaf(elements))
+      }
+      FREE_LIST_PAGE_FLAG => {
+        let id_size = mem::size_of::<PgId>();
+        // A count of 0xFFFF means the real count is stored in the first element.
+        let (start, count) = if header.count == u16::MAX {
+          let count: u64 =
+            bytemuck::pod_read_unaligned(bytes.get(PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + id_size)?);
+          (PAGE_HEADER_SIZE + id_size, usize::try_from(count).ok()?)
+        } else {
+          (PAGE_HEADER_SIZE, count)
+        };
+        let end = count.checked_mul(id_size)?.checked_add(start)?;
+        let ids = bytes
+          .get(start..end)?
+          .chunks_exact(id_size)
+          .map(bytemuck::pod_read_unaligned::<PgId>)
+          .collect();
+        Some(PageElements::FreeList(ids))
+      }
+      _ => None,
+    }
+  }
+
+  /// Describes each element on a line, with keys and values rendered by `stringer`.
+  /// Values of nested buckets are left out.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// let elements = PageElements::FreeList(vec![PgId(3), PgId(4)]);
+  /// assert_eq!(vec!["pgid: 3", "pgid: 4"], elements.render(&HexKvStringer));
+  /// ```
+  pub fn render(&self, stringer: &dyn KvStringer) -> Vec<String> {
+    match self {
+      PageElements::Branch(elements) => elements
+        .iter()
+        .map(|e| {
+          let key = [e.prefix, e.key].concat();
+          format!("key: {}, pgid: {}", stringer.key_to_string(&key), e.pgid)
+        })
+        .collect(),
+      PageElements::Leaf(elements) => elements
+        .iter()
+        .map(|e| {
+          let key = stringer.key_to_string(e.key);
+          if e.is_bucket_entry() {
+            format!("key: {}, bucket", key)
+          } else {
+            format!("key: {}, value: {}", key, stringer.value_to_string(e.value))
+          }
+        })
+        .collect(),
+      PageElements::FreeList(ids) => ids.iter().map(|id| format!("pgid: {}", id)).collect(),
+    }
+  }
+}
+
+fn element_slice(bytes: &[u8], elem_off: usize, pos: usize, size: u32) -> Option<&[u8]> {
+  let start = elem_off.checked_add(pos)?;
+  bytes.get(start..start.checked_add(size as usize)?)
+}
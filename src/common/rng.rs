@@ -0,0 +1,28 @@
+/// A small deterministic generator, so the generated data doesn't depend on a random crate
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+  pub(crate) fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+
+  /// Returns a number in `[0, 1)`
+  #[cfg(feature = "page-store")]
+  pub(crate) fn next_f64(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  #[cfg(feature = "testing")]
+  pub(crate) fn fill(&mut self, len: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(len + 8);
+    while bytes.len() < len {
+      bytes.extend_from_slice(&self.next_u64().to_le_bytes());
+    }
+    bytes.truncate(len);
+    bytes
+  }
+}
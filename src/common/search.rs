@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+#[cfg(bench_tuning)]
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// Slices with up to this many elements are scanned linearly instead of binary searched.
+/// Chosen from the `bench` results in `docs/linear_search.md`.
+pub(crate) const LINEAR_SEARCH_MAX: usize = 64;
+
+#[cfg(bench_tuning)]
+static LINEAR_SEARCH_LIMIT: AtomicUsize = AtomicUsize::new(LINEAR_SEARCH_MAX);
+
+/// Returns [LINEAR_SEARCH_MAX]. Builds with `--cfg bench_tuning` can change it with
+/// [set_linear_search_max] to compare cutoffs.
+#[cfg(not(bench_tuning))]
+#[inline]
+pub(crate) fn linear_search_max() -> usize {
+  LINEAR_SEARCH_MAX
+}
+
+#[cfg(bench_tuning)]
+#[inline]
+pub(crate) fn linear_search_max() -> usize {
+  LINEAR_SEARCH_LIMIT.load(AtomicOrdering::Relaxed)
+}
+
+/// Sets the size up to which slices are scanned linearly instead of binary searched.
+/// `0` always binary searches.
+///
+/// Only exists in builds with `--cfg bench_tuning`, for the `bench` binary's
+/// `--linear-search-max` option.
+#[cfg(bench_tuning)]
+#[doc(hidden)]
+pub fn set_linear_search_max(max: usize) {
+  LINEAR_SEARCH_LIMIT.store(max, AtomicOrdering::Relaxed);
+}
+
+/// Searches a sorted slice like [slice::binary_search_by]. Small slices are scanned
+/// linearly. See [linear_search_max]
+#[inline]
+pub(crate) fn search_by<T, F>(items: &[T], mut f: F) -> Result<usize, usize>
+where
+  F: FnMut(&T) -> Ordering,
+{
+  if items.len() > linear_search_max() {
+    return items.binary_search_by(f);
+  }
+  for (i, item) in items.iter().enumerate() {
+    match f(item) {
+      Ordering::Less => {}
+      Ordering::Equal => return Ok(i),
+      Ordering::Greater => return Err(i),
+    }
+  }
+  Err(items.len())
+}
+
+/// Returns the index of the first element `pred` is false for like
+/// [slice::partition_point]. Small slices are scanned linearly. See [linear_search_max]
+#[inline]
+pub(crate) fn partition_point<T, P>(items: &[T], mut pred: P) -> usize
+where
+  P: FnMut(&T) -> bool,
+{
+  if items.len() > linear_search_max() {
+    return items.partition_point(pred);
+  }
+  items
+    .iter()
+    .position(|item| !pred(item))
+    .unwrap_or(items.len())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::common::search::{partition_point, search_by, LINEAR_SEARCH_MAX};
+
+  #[test]
+  fn test_search_matches_binary_search() {
+    for len in [0, 1, 2, LINEAR_SEARCH_MAX, LINEAR_SEARCH_MAX + 1, 100] {
+      let items = (0..len).map(|i| i * 2).collect::<Vec<_>>();
+      for key in 0..len * 2 + 2 {
+        assert_eq!(
+          items.binary_search(&key),
+          search_by(&items, |i| i.cmp(&key))
+        );
+        assert_eq!(
+          items.partition_point(|i| *i < key),
+          partition_point(&items, |i| *i < key)
+        );
+      }
+    }
+  }
+}
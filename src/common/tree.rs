@@ -1,12 +1,16 @@
 use crate::common::inode::INode;
 use crate::common::memory::{PhantomUnsend, RWSlice};
 use crate::common::page::{
-  CoerciblePage, PageHeader, BRANCH_PAGE_FLAG, BUCKET_LEAF_FLAG, LEAF_PAGE_FLAG, PAGE_HEADER_SIZE,
+  CoerciblePage, MutPage, PageHeader, RefPage, BRANCH_PAGE_FLAG, BUCKET_LEAF_FLAG, LEAF_PAGE_FLAG,
+  PAGE_HEADER_SIZE, PREFIX_BRANCH_FLAGS, PREFIX_BRANCH_PAGE_FLAG,
 };
 use crate::common::PgId;
+use crate::Error;
+use bumpalo::Bump;
 use bytemuck::{Pod, Zeroable};
 use getset::{CopyGetters, Setters};
 use itertools::izip;
+use std::borrow::Cow;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
@@ -14,6 +18,58 @@ use std::slice::{from_raw_parts, from_raw_parts_mut};
 
 pub const BRANCH_PAGE_ELEMENT_SIZE: usize = mem::size_of::<BranchPageElement>();
 pub const LEAF_PAGE_ELEMENT_SIZE: usize = mem::size_of::<LeafPageElement>();
+/// The size of the prefix length stored in front of the prefix of a compressed branch page
+pub const PREFIX_LEN_SIZE: usize = mem::size_of::<u32>();
+
+/// Returns the length of the key prefix to store once on a branch page holding `inodes`.
+///
+/// Returns 0 if storing the prefix separately doesn't save any space.
+pub(crate) fn branch_prefix_len(inodes: &[INode]) -> usize {
+  let (first, rest) = match inodes.split_first() {
+    Some(split) => split,
+    None => return 0,
+  };
+  let first = first.key();
+  let mut len = first.len();
+  for inode in rest {
+    len = first[..len]
+      .iter()
+      .zip(inode.key())
+      .take_while(|(a, b)| a == b)
+      .count();
+    if len == 0 {
+      return 0;
+    }
+  }
+  if len * rest.len() > PREFIX_LEN_SIZE {
+    len
+  } else {
+    0
+  }
+}
+
+/// Returns the bytes saved by storing a prefix of `prefix_len` once for `count` keys
+#[inline]
+pub(crate) fn branch_prefix_savings(prefix_len: usize, count: usize) -> usize {
+  if prefix_len == 0 {
+    0
+  } else {
+    prefix_len * (count - 1) - PREFIX_LEN_SIZE
+  }
+}
+
+/// Returns the full key of a prefix compressed branch element. The key is copied into `bump`
+/// unless `prefix` is empty.
+#[inline]
+pub(crate) fn prefixed_key<'a>(bump: &'a Bump, prefix: &[u8], suffix: &'a [u8]) -> &'a [u8] {
+  if prefix.is_empty() {
+    return suffix;
+  }
+  let key = bump.alloc_slice_fill_copy(prefix.len() + suffix.len(), 0u8);
+  key[..prefix.len()].copy_from_slice(prefix);
+  key[prefix.len()..].copy_from_slice(suffix);
+  key
+}
 
 pub struct MappedLeafPage {
   bytes: *mut u8,
@@ -137,6 +193,57 @@ impl MappedBranchPage {
       phantom: PhantomData,
     }
   }
+
+  /// Returns true if the page stores the common prefix of its keys once
+  #[inline]
+  pub fn is_prefix_compressed(&self) -> bool {
+    self.flags & PREFIX_BRANCH_PAGE_FLAG != 0
+  }
+
+  /// Returns the prefix shared by all keys of a prefix compressed page, otherwise an
+  /// empty slice. The element keys of a prefix compressed page only hold the rest of the key.
+  pub fn prefix<'tx>(&self) -> &'tx [u8] {
+    if !self.is_prefix_compressed() {
+      return &[];
+    }
+    unsafe {
+      let len_ptr = self
+        .bytes
+        .add(PAGE_HEADER_SIZE + BRANCH_PAGE_ELEMENT_SIZE * self.count as usize);
+      let len = (len_ptr as *const u32).read_unaligned();
+      from_raw_parts(len_ptr.add(PREFIX_LEN_SIZE), len as usize)
+    }
+  }
+
+  /// Returns the full key of element `i`, allocating it in `bump` if the page is prefix compressed
+  pub(crate) fn key_in<'tx>(&self, i: u16, bump: &'tx Bump) -> Option<&'tx [u8]> {
+    let elem = self.get_elem(i)?;
+    Some(prefixed_key(bump, self.prefix(), elem.key()))
+  }
+
+  /// write_prefixed_elements writes `inodes` storing the first `prefix_len` bytes of
+  /// their keys once
+  pub(crate) fn write_prefixed_elements(&mut self, inodes: &[INode], prefix_len: usize) -> u32 {
+    self.flags = PREFIX_BRANCH_FLAGS;
+    self.count = inodes.len() as u16;
+    let page_ptr = self.bytes;
+    let mut off = PAGE_HEADER_SIZE + BRANCH_PAGE_ELEMENT_SIZE * inodes.len();
+    let mut b = RWSlice::new_with_offset(page_ptr, off, (PREFIX_LEN_SIZE + prefix_len) as u32);
+    let (len, prefix) = b.split_at_mut(PREFIX_LEN_SIZE);
+    len.copy_from_slice(&(prefix_len as u32).to_ne_bytes());
+    prefix.copy_from_slice(&inodes[0].key()[..prefix_len]);
+    off += PREFIX_LEN_SIZE + prefix_len;
+    izip!(self.elements_mut(), inodes).for_each(|(elem, inode)| {
+      let suffix = &inode.key()[prefix_len..];
+      let mut b = RWSlice::new_with_offset(page_ptr, off, suffix.len() as u32);
+      off += suffix.len();
+      elem.set_pos(b.distance_from(elem));
+      elem.set_pgid(inode.pgid());
+      elem.set_key_size(suffix.len() as u32);
+      b.copy_from_slice(suffix);
+    });
+    off as u32
+  }
 }
 
 impl Deref for MappedBranchPage {
@@ -159,6 +266,25 @@ impl CoerciblePage for MappedBranchPage {
     BRANCH_PAGE_FLAG
   }
 
+  // Prefix compressed branch pages are branch pages as well
+  #[inline]
+  fn coerce_ref<'a>(mapped_page: &'a RefPage<'_>) -> Option<&'a Self> {
+    if mapped_page.type_flags() & !PREFIX_BRANCH_PAGE_FLAG == BRANCH_PAGE_FLAG {
+      Some(unsafe { Self::unchecked_ref(mapped_page) })
+    } else {
+      None
+    }
+  }
+
+  #[inline]
+  fn coerce_mut<'a>(mapped_page: &'a mut MutPage<'_>) -> Option<&'a mut Self> {
+    if mapped_page.type_flags() & !PREFIX_BRANCH_PAGE_FLAG == BRANCH_PAGE_FLAG {
+      Some(unsafe { Self::unchecked_mut(mapped_page) })
+    } else {
+      None
+    }
+  }
+
   fn own(bytes: *mut u8) -> MappedBranchPage {
     let mut page = unsafe { Self::new(bytes) };
     page.set_branch();
@@ -227,6 +353,47 @@ pub trait TreePage<'tx>: Deref<Target = PageHeader> + DerefMut {
   }
 
   fn get_elem(&self, i: u16) -> Option<Self::ElemRef>;
+
+  /// Returns the distance from the element to its data and the length of the data
+  fn elem_span(elem: &Self::Elem) -> (usize, usize);
+
+  /// Returns [Error::Corrupted](crate::Error::Corrupted) if data stored behind the
+  /// elements, which end at `elements_len`, doesn't fit in the `page_len` bytes of the page
+  fn check_trailer(&self, _elements_len: usize, _page_len: usize) -> crate::Result<()> {
+    Ok(())
+  }
+
+  /// check_elements returns [Error::Corrupted](crate::Error::Corrupted) if the element
+  /// headers, or the keys and values they point to, do not fit inside the page and its
+  /// overflow pages. Call it before reading the elements of a page from the file.
+  fn check_elements(&self, page_size: usize) -> crate::Result<()> {
+    let page_len = (self.overflow as usize + 1) * page_size;
+    let elements_len = PAGE_HEADER_SIZE + self.page_element_size() * self.count as usize;
+    if elements_len > page_len {
+      return Err(Error::Corrupted {
+        pgid: self.id,
+        reason: Cow::Owned(format!(
+          "{} elements overflow the page size {}",
+          self.count, page_len
+        )),
+      });
+    }
+    for (i, elem) in self.elements().iter().enumerate() {
+      let (pos, len) = Self::elem_span(elem);
+      let start = PAGE_HEADER_SIZE + self.page_element_size() * i + pos;
+      if start < elements_len || start + len > page_len {
+        return Err(Error::Corrupted {
+          pgid: self.id,
+          reason: Cow::Owned(format!(
+            "element {} at {} with {} bytes is outside the page size {}",
+            i, start, len, page_len
+          )),
+        });
+      }
+    }
+    self.check_trailer(elements_len, page_len)
+  }
+
   fn elements(&self) -> &[Self::Elem] {
     unsafe {
       let page_ptr = self.page_ptr();
@@ -311,6 +478,14 @@ impl<'tx> TreePage<'tx> for MappedLeafPage {
     LEAF_PAGE_ELEMENT_SIZE
   }
 
+  #[inline]
+  fn elem_span(elem: &LeafPageElement) -> (usize, usize) {
+    (
+      elem.pos as usize,
+      elem.key_size as usize + elem.value_size as usize,
+    )
+  }
+
   fn get_elem(&self, i: u16) -> Option<Self::ElemRef> {
     if i >= self.count {
       None
@@ -359,6 +534,30 @@ impl<'tx> TreePage<'tx> for MappedBranchPage {
     BRANCH_PAGE_ELEMENT_SIZE
   }
 
+  #[inline]
+  fn elem_span(elem: &BranchPageElement) -> (usize, usize) {
+    (elem.pos as usize, elem.key_size as usize)
+  }
+
+  fn check_trailer(&self, elements_len: usize, page_len: usize) -> crate::Result<()> {
+    if !self.is_prefix_compressed() {
+      return Ok(());
+    }
+    let prefix_end = if elements_len + PREFIX_LEN_SIZE > page_len {
+      None
+    } else {
+      let len = unsafe { (self.bytes.add(elements_len) as *const u32).read_unaligned() };
+      Some(elements_len + PREFIX_LEN_SIZE + len as usize)
+    };
+    match prefix_end {
+      Some(end) if end <= page_len => Ok(()),
+      _ => Err(Error::Corrupted {
+        pgid: self.id,
+        reason: Cow::Owned(format!("key prefix overflows the page size {}", page_len)),
+      }),
+    }
+  }
+
   fn get_elem(&self, i: u16) -> Option<Self::ElemRef> {
     if i >= self.count {
       None
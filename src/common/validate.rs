@@ -0,0 +1,211 @@
+use crate::common::bucket::{BucketHeader, BUCKET_HEADER_SIZE};
+use crate::common::defaults::PGID_NO_FREE_LIST;
+use crate::common::meta::Meta;
+use crate::common::page::{
+  verify_page_checksum, PageElements, PageHeader, BRANCH_PAGE_FLAG, FREE_LIST_PAGE_FLAG,
+  LEAF_PAGE_FLAG, PAGE_HEADER_SIZE, PREFIX_BRANCH_FLAGS,
+};
+use crate::common::{PgId, ZERO_PGID};
+use crate::Error;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// The minimum page size accepted when opening an untrusted database
+pub(crate) const UNTRUSTED_MIN_PAGE_SIZE: usize = 1024;
+/// The maximum page size accepted when opening an untrusted database
+pub(crate) const UNTRUSTED_MAX_PAGE_SIZE: usize = 64 * 1024;
+/// The maximum combined depth of b+trees and nested buckets
+pub(crate) const UNTRUSTED_MAX_DEPTH: usize = 64;
+/// The default maximum number of page elements, free page ids included, of an untrusted
+/// database
+pub(crate) const UNTRUSTED_MAX_ELEMENTS: u64 = 1 << 32;
+
+fn corrupted<T>(pgid: PgId, reason: impl Into<Cow<'static, str>>) -> crate::Result<T> {
+  Err(Error::Corrupted {
+    pgid,
+    reason: reason.into(),
+  })
+}
+
+/// `UntrustedValidator` walks every reachable page of a database file before it is used.
+///
+/// All reads are done through bounds checked slices of `data`, so a crafted file can only
+/// produce an [Error::Corrupted] and never an out of bounds read.
+pub(crate) struct UntrustedValidator<'a> {
+  data: &'a [u8],
+  page_size: usize,
+  high_water: PgId,
+  visited: HashSet<PgId>,
+  /// the number of elements left before the database is rejected
+  elements_left: u64,
+}
+
+impl<'a> UntrustedValidator<'a> {
+  /// Validates the file contents in `data` as described by `meta`. The pages may hold up to
+  /// `max_elements` elements and free page ids altogether.
+  pub(crate) fn validate(
+    data: &'a [u8], page_size: usize, meta: &Meta, max_elements: u64,
+  ) -> crate::Result<()> {
+    if !page_size.is_power_of_two()
+      || !(UNTRUSTED_MIN_PAGE_SIZE..=UNTRUSTED_MAX_PAGE_SIZE).contains(&page_size)
+      || meta.page_size() as usize != page_size
+    {
+      return Err(Error::InvalidDatabase {
+        meta_can_read: true,
+        meta: None,
+      });
+    }
+    let high_water = meta.pgid();
+    let end = high_water
+      .0
+      .checked_mul(page_size as u64)
+      .filter(|end| *end <= data.len() as u64);
+    if high_water.0 < 2 || end.is_none() {
+      return corrupted(high_water, "high water mark beyond file size");
+    }
+    let mut validator = UntrustedValidator {
+      data,
+      page_size,
+      high_water,
+      visited: HashSet::new(),
+      elements_left: max_elements,
+    };
+    if meta.free_list() != PGID_NO_FREE_LIST {
+      validator.validate_freelist(meta.free_list())?;
+    }
+    validator.validate_bucket(ZERO_PGID, meta.root(), None, 0)
+  }
+
+  /// Returns the bytes of page `pgid` including its overflow pages
+  fn page(&mut self, pgid: PgId, expected_flags: &[u16]) -> crate::Result<&'a [u8]> {
+    if pgid.0 < 2 || pgid >= self.high_water {
+      return corrupted(pgid, "page id outside of the data pages");
+    }
+    if !self.visited.insert(pgid) {
+      return corrupted(pgid, "page referenced multiple times");
+    }
+    let start = pgid.0 as usize * self.page_size;
+    let header = self.header(pgid, &self.data[start..])?;
+    if header.id != pgid {
+      return corrupted(pgid, format!("page identifies as {}", header.id));
+    }
+    if !expected_flags.contains(&header.type_flags())
+      || (header.has_checksum() && header.type_flags() == FREE_LIST_PAGE_FLAG)
+    {
+      return corrupted(pgid, format!("unexpected type/flags {}", header.flags));
+    }
+    let last = pgid.0 + header.overflow as u64;
+    if last >= self.high_water.0 {
+      return corrupted(pgid, "overflow beyond high water mark");
+    }
+    for overflow in pgid.0 + 1..=last {
+      if !self.visited.insert(PgId(overflow)) {
+        return corrupted(PgId(overflow), "page referenced multiple times");
+      }
+    }
+    let end = (last as usize + 1) * self.page_size;
+    let bytes = &self.data[start..end];
+    if header.has_checksum() {
+      verify_page_checksum(pgid, bytes)?;
+    }
+    Ok(bytes)
+  }
+
+  fn header(&self, pgid: PgId, bytes: &[u8]) -> crate::Result<PageHeader> {
+    match bytes.get(..PAGE_HEADER_SIZE) {
+      Some(header) => Ok(bytemuck::pod_read_unaligned(header)),
+      None => corrupted(pgid, "page header out of bounds"),
+    }
+  }
+
+  /// Parses the elements of a page once they fit into the remaining element count
+  fn elements(&mut self, pgid: PgId, bytes: &'a [u8]) -> crate::Result<PageElements<'a>> {
+    let header = self.header(pgid, bytes)?;
+    // A freelist page with 0xFFFF or more ids stores their count in the first element
+    let count = if header.type_flags() == FREE_LIST_PAGE_FLAG && header.count == u16::MAX {
+      match bytes.get(PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 8) {
+        Some(count) => bytemuck::pod_read_unaligned::<u64>(count),
+        None => return corrupted(pgid, "element count out of bounds"),
+      }
+    } else {
+      header.count as u64
+    };
+    self.elements_left = match self.elements_left.checked_sub(count) {
+      Some(left) => left,
+      None => return corrupted(pgid, "maximum element count exceeded"),
+    };
+    match PageElements::parse(bytes) {
+      Some(elements) => Ok(elements),
+      None => corrupted(pgid, "element out of bounds"),
+    }
+  }
+
+  fn validate_freelist(&mut self, pgid: PgId) -> crate::Result<()> {
+    let bytes = self.page(pgid, &[FREE_LIST_PAGE_FLAG])?;
+    if let PageElements::FreeList(ids) = self.elements(pgid, bytes)? {
+      for id in ids {
+        if id.0 < 2 || id >= self.high_water {
+          return corrupted(pgid, format!("free page {} outside of the data pages", id));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Validates a bucket. `inline` contains the bucket's value when the bucket is stored inline.
+  fn validate_bucket(
+    &mut self, parent: PgId, header: BucketHeader, inline: Option<&'a [u8]>, depth: usize,
+  ) -> crate::Result<()> {
+    if header.root() != ZERO_PGID {
+      return self.validate_page(header.root(), depth);
+    }
+    let bytes = match inline {
+      Some(value) => &value[BUCKET_HEADER_SIZE..],
+      None => return corrupted(parent, "root bucket stored inline"),
+    };
+    let inline_header = self.header(parent, bytes)?;
+    if inline_header.flags != LEAF_PAGE_FLAG || inline_header.overflow != 0 {
+      return corrupted(parent, "inline bucket is not a leaf page");
+    }
+    self.validate_elements(parent, bytes, depth)
+  }
+
+  fn validate_page(&mut self, pgid: PgId, depth: usize) -> crate::Result<()> {
+    let bytes = if cfg!(feature = "prefix-branch") {
+      self.page(
+        pgid,
+        &[BRANCH_PAGE_FLAG, PREFIX_BRANCH_FLAGS, LEAF_PAGE_FLAG],
+      )?
+    } else {
+      self.page(pgid, &[BRANCH_PAGE_FLAG, LEAF_PAGE_FLAG])?
+    };
+    self.validate_elements(pgid, bytes, depth)
+  }
+
+  fn validate_elements(&mut self, pgid: PgId, bytes: &'a [u8], depth: usize) -> crate::Result<()> {
+    if depth >= UNTRUSTED_MAX_DEPTH {
+      return corrupted(pgid, "maximum depth exceeded");
+    }
+    match self.elements(pgid, bytes)? {
+      PageElements::Branch(elements) => {
+        if elements.is_empty() {
+          return corrupted(pgid, "empty branch page");
+        }
+        for elem in elements {
+          self.validate_page(elem.pgid, depth + 1)?;
+        }
+      }
+      PageElements::Leaf(elements) => {
+        for elem in elements.iter().filter(|elem| elem.is_bucket_entry()) {
+          let header = match elem.value.get(..BUCKET_HEADER_SIZE) {
+            Some(header) => bytemuck::pod_read_unaligned(header),
+            None => return corrupted(pgid, "bucket header out of bounds"),
+          };
+          self.validate_bucket(pgid, header, Some(elem.value), depth + 1)?;
+        }
+      }
+      PageElements::FreeList(_) => return corrupted(pgid, "unexpected freelist page"),
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,196 @@
+use bumpalo::Bump;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// Orders the keys of a bucket
+pub type KeyComparator = fn(&[u8], &[u8]) -> Ordering;
+
+/// The prefix of the top-level bucket names and metadata keys the database keeps its own
+/// records under. Creating or deleting such a top-level bucket, or setting or deleting such a
+/// metadata key, fails with [crate::Error::ReservedName]. The buckets are never listed.
+pub const RESERVED_PREFIX: &[u8] = b"bbolt.";
+
+/// The hidden top-level bucket holding the database's own records in its sub-buckets
+pub(crate) const SYSTEM_BUCKET: &[u8] = b"bbolt.system";
+
+/// The sub-bucket of the [SYSTEM_BUCKET] holding the metadata set with
+/// [crate::BucketRwApi::set_meta], keyed by [meta_key]
+pub(crate) const META_BUCKET: &[u8] = b"meta";
+
+/// The sub-bucket of the [SYSTEM_BUCKET] holding the values stored with
+/// [crate::TxRwRefApi::root_put]
+pub(crate) const ROOT_KV_BUCKET: &[u8] = b"root_kv";
+
+/// The metadata key recording the comparator name of a bucket created with a custom comparator
+pub(crate) const COMPARATOR_META_KEY: &[u8] = b"bbolt.comparator";
+
+/// Whether `name` is reserved for the database's own records. See [RESERVED_PREFIX]
+#[inline]
+pub(crate) fn is_reserved(name: &[u8]) -> bool {
+  name.starts_with(RESERVED_PREFIX)
+}
+
+/// The default comparator. Orders keys bytewise.
+#[cfg(not(feature = "fast-compare"))]
+#[inline]
+pub(crate) fn bytewise(a: &[u8], b: &[u8]) -> Ordering {
+  a.cmp(b)
+}
+
+/// The default comparator. Orders keys bytewise.
+///
+/// Compares 8 bytes at a time inline instead of calling `memcmp`. See `docs/fast_compare.md`
+/// for measurements and how to compare it against the default with `bench --key-prefix`.
+#[cfg(feature = "fast-compare")]
+#[inline]
+pub(crate) fn bytewise(a: &[u8], b: &[u8]) -> Ordering {
+  let len = a.len().min(b.len());
+  let mut i = 0;
+  while i + 8 <= len {
+    // The first differing word decides, and big endian words order like their bytes
+    let x = u64::from_be_bytes(a[i..i + 8].try_into().unwrap());
+    let y = u64::from_be_bytes(b[i..i + 8].try_into().unwrap());
+    if x != y {
+      return x.cmp(&y);
+    }
+    i += 8;
+  }
+  a[i..].cmp(&b[i..])
+}
+
+/// Returns the path of the bucket `name` below the bucket at `parent`.
+///
+/// Each level of the bucket path is stored as its big endian u32 length followed by its name.
+pub(crate) fn child_path<'tx>(bump: &'tx Bump, parent: &[u8], name: &[u8]) -> &'tx [u8] {
+  let path = bump.alloc_slice_fill_copy(parent.len() + 4 + name.len(), 0u8);
+  path[..parent.len()].copy_from_slice(parent);
+  path[parent.len()..parent.len() + 4].copy_from_slice(&(name.len() as u32).to_be_bytes());
+  path[parent.len() + 4..].copy_from_slice(name);
+  path
+}
+
+/// Returns the key of the metadata entry `key` of the bucket at `path` in the [META_BUCKET].
+///
+/// The path is followed by a level length no bucket name can have, so the entries of a
+/// bucket and of all its sub-buckets start with the bucket's path.
+pub(crate) fn meta_key(path: &[u8], key: &[u8]) -> Vec<u8> {
+  let mut meta_key = Vec::with_capacity(path.len() + 4 + key.len());
+  meta_key.extend_from_slice(path);
+  meta_key.extend_from_slice(&u32::MAX.to_be_bytes());
+  meta_key.extend_from_slice(key);
+  meta_key
+}
+
+/// Splits an entry of the [META_BUCKET] into the path of its bucket and its key.
+/// See [meta_key]
+pub(crate) fn split_meta_key(entry: &[u8]) -> Option<(&[u8], &[u8])> {
+  let mut at = 0;
+  loop {
+    let len = u32::from_be_bytes(entry.get(at..at + 4)?.try_into().unwrap());
+    if len == u32::MAX {
+      return Some((&entry[..at], &entry[at + 4..]));
+    }
+    at += 4 + len as usize;
+  }
+}
+
+/// Returns the name of the top-level bucket of a bucket path. See [child_path]
+pub(crate) fn top_level_name(path: &[u8]) -> Option<&[u8]> {
+  let len = u32::from_be_bytes(path.get(..4)?.try_into().unwrap()) as usize;
+  path.get(4..4 + len)
+}
+
+/// Returns the bucket names of every level of a bucket path. See [child_path]
+pub(crate) fn path_names(mut path: &[u8]) -> Vec<&[u8]> {
+  let mut names = Vec::new();
+  while let Some(name) = top_level_name(path) {
+    names.push(name);
+    path = &path[4 + name.len()..];
+  }
+  names
+}
+
+/// Comparators registers the key comparators available to a database.
+///
+/// A bucket created with [crate::BucketRwApi::create_bucket_with_comparator] records the
+/// comparator's name in the database. The same name must be registered every time the
+/// database is opened, otherwise opening fails with [crate::Error::UnknownComparator].
+/// Comparators are compared by name.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+///   let _db = BoltOptions::builder().comparators(comparators).build().open_mem()?;
+///   Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Comparators {
+  comparators: Arc<HashMap<String, KeyComparator>>,
+}
+
+impl Comparators {
+  /// Creates an empty registry
+  pub fn new() -> Comparators {
+    Comparators::default()
+  }
+
+  /// Registers `comparator` under `name`
+  pub fn with<T: Into<String>>(mut self, name: T, comparator: KeyComparator) -> Comparators {
+    Arc::make_mut(&mut self.comparators).insert(name.into(), comparator);
+    self
+  }
+
+  pub(crate) fn get(&self, name: &[u8]) -> Option<KeyComparator> {
+    let name = std::str::from_utf8(name).ok()?;
+    self.comparators.get(name).copied()
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.comparators.is_empty()
+  }
+}
+
+impl Debug for Comparators {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_set().entries(self.comparators.keys()).finish()
+  }
+}
+
+impl PartialEq for Comparators {
+  fn eq(&self, other: &Self) -> bool {
+    self.comparators.len() == other.comparators.len()
+      && self
+        .comparators
+        .keys()
+        .all(|name| other.comparators.contains_key(name))
+  }
+}
+
+impl Eq for Comparators {}
+
+#[cfg(test)]
+mod test {
+  use crate::comparator::bytewise;
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  #[test]
+  fn test_comparator_bytewise() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let prefix = [7u8; 40];
+    for _ in 0..10000 {
+      let a_len = rng.gen_range(0..48);
+      let b_len = rng.gen_range(0..48);
+      let mut a = prefix[..a_len.min(40)].to_vec();
+      let mut b = prefix[..b_len.min(40)].to_vec();
+      a.extend((a.len()..a_len).map(|_| rng.gen_range(6..9u8)));
+      b.extend((b.len()..b_len).map(|_| rng.gen_range(6..9u8)));
+      assert_eq!(a.cmp(&b), bytewise(&a, &b), "{:?} {:?}", a, b);
+    }
+  }
+}
@@ -1,14 +1,32 @@
-use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi};
+use crate::bucket::{BucketCell, BucketIApi, BucketImpl, BucketRwIApi};
 use crate::common::page::{CoerciblePage, RefPage, BUCKET_LEAF_FLAG};
+use crate::common::search::{partition_point, search_by};
 use crate::common::tree::{MappedBranchPage, MappedLeafPage, TreePage};
-use crate::common::{BVec, PgId};
+use crate::common::{BVec, PgId, ZERO_PGID};
+use crate::comparator::is_reserved;
 use crate::node::NodeRwCell;
-use crate::tx::{TxCell, TxIApi};
+#[cfg(feature = "replication")]
+use crate::replication::Change;
+use crate::savepoint::Undo;
+use crate::tx::{TxCell, TxIApi, TxRwIApi};
+use crate::Error;
 use crate::Error::IncompatibleValue;
 use bumpalo::Bump;
+use std::borrow::Cow;
 use std::marker::PhantomData;
 
+/// A key and its value, None for nested buckets
+pub(crate) type KeyValue<'tx> = (&'tx [u8], Option<&'tx [u8]>);
+
+/// A key, its value and the element flags
+pub(crate) type KeyValueFlags<'tx> = (&'tx [u8], &'tx [u8], u32);
+
 /// Read-only Cursor API
+///
+/// A cursor may be used while the write transaction it belongs to modifies the bucket.
+/// After a modification the next move continues from the key the cursor was last on.
+/// If that key was deleted, `next` returns the key that followed it and `prev` the key
+/// that preceded it. Keys inserted ahead of the cursor are visited.
 pub trait CursorApi<'tx> {
   /// Moves the cursor to the first item in the bucket and returns its key and value.
   ///
@@ -166,6 +184,109 @@ pub trait CursorApi<'tx> {
   /// }
   /// ```
   fn seek<T: AsRef<[u8]>>(&mut self, seek: T) -> Option<(&'tx [u8], Option<&'tx [u8]>)>;
+
+  /// Moves the cursor to a given key using a b-tree search and returns it.
+  ///
+  /// Unlike [CursorApi::seek] this returns [Error::Corrupted] instead of panicking if a
+  /// corrupted page is found during the search.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let mut c = b.cursor();
+  ///     let seek = c.try_seek("key2")?;
+  ///     assert_eq!(Some((b"key2".as_slice(), Some(b"value2".as_slice()))), seek);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn try_seek<T: AsRef<[u8]>>(&mut self, seek: T) -> crate::Result<Option<KeyValue<'tx>>>;
+
+  /// Moves the cursor to the next nested bucket and returns its name and the bucket.
+  /// Key/value pairs are skipped.
+  ///
+  /// If the cursor is at the end of the bucket then None is returned.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test1")?;
+  ///     b.put("key", "value")?;
+  ///     tx.create_bucket_if_not_exists("test2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let mut c = tx.cursor();
+  ///     let mut names = Vec::new();
+  ///     let mut item = c.seek_bucket("");
+  ///     while let Some((name, b)) = item {
+  ///       names.push(name);
+  ///       if name == b"test1" {
+  ///         assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///       }
+  ///       item = c.next_bucket();
+  ///     }
+  ///     assert_eq!(vec![b"test1".as_slice(), b"test2".as_slice()], names);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn next_bucket(&mut self) -> Option<(&'tx [u8], BucketImpl<'tx>)>;
+
+  /// Moves the cursor to the first nested bucket named `name` or after and returns its
+  /// name and the bucket. Key/value pairs are skipped.
+  ///
+  /// If no such bucket exists then None is returned.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.create_bucket("key2")?;
+  ///     b.put("key3", "value3")?;
+  ///     b.create_bucket("key4")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let mut c = b.cursor();
+  ///     assert_eq!(Some(b"key2".as_slice()), c.seek_bucket("key1").map(|(k, _)| k));
+  ///     assert_eq!(Some(b"key4".as_slice()), c.seek_bucket("key3").map(|(k, _)| k));
+  ///     assert!(c.seek_bucket("key5").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn seek_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> Option<(&'tx [u8], BucketImpl<'tx>)>;
 }
 
 /// RW Bucket API
@@ -206,6 +327,92 @@ pub trait CursorRwApi<'tx>: CursorApi<'tx> {
   /// }
   /// ```
   fn delete(&mut self) -> crate::Result<()>;
+
+  /// Removes the current key/value under the cursor from the bucket and moves
+  /// the cursor to the following key. Returns the key/value now under the cursor.
+  ///
+  /// Unlike [CursorRwApi::delete] the cursor stays positioned, so matching keys
+  /// can be removed while iterating.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key2", "value2")?;
+  ///     b.put("key3", "value3")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     let mut c = b.cursor_mut();
+  ///     let mut item = c.first();
+  ///     while let Some((k, _)) = item {
+  ///       if k != b"key2" {
+  ///         item = c.delete_current()?;
+  ///       } else {
+  ///         item = c.next();
+  ///       }
+  ///     }
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let mut c = b.cursor();
+  ///     assert_eq!(Some((b"key2".as_slice(), Some(b"value2".as_slice()))), c.first());
+  ///     assert_eq!(None, c.next());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn delete_current(&mut self) -> crate::Result<Option<KeyValue<'tx>>>;
+
+  /// Sets the value for a key in the bucket and moves the cursor to the key.
+  ///
+  /// Iteration continues from the inserted key in either direction.
+  /// Returns an error if the key is blank, if the key is too large, if the value
+  /// is too large, or if the key is a bucket.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     b.put("key3", "value3")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     let mut c = b.cursor_mut();
+  ///     c.first();
+  ///     c.put("key2", "value2")?;
+  ///     assert_eq!(Some((b"key3".as_slice(), Some(b"value3".as_slice()))), c.next());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value2".as_slice()), b.get("key2"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()>;
 }
 
 pub(crate) enum CursorWrapper<'tx> {
@@ -256,6 +463,26 @@ impl<'tx> CursorApi<'tx> for CursorImpl<'tx> {
       CursorWrapper::RW(rw) => rw.api_seek(seek.as_ref()),
     }
   }
+
+  fn try_seek<T: AsRef<[u8]>>(&mut self, seek: T) -> crate::Result<Option<KeyValue<'tx>>> {
+    match &mut self.c {
+      CursorWrapper::RW(rw) => rw.api_try_seek(seek.as_ref()),
+    }
+  }
+
+  fn next_bucket(&mut self) -> Option<(&'tx [u8], BucketImpl<'tx>)> {
+    match &mut self.c {
+      CursorWrapper::RW(rw) => rw.api_next_bucket().map(|(k, b)| (k, b.into())),
+    }
+  }
+
+  fn seek_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> Option<(&'tx [u8], BucketImpl<'tx>)> {
+    match &mut self.c {
+      CursorWrapper::RW(rw) => rw
+        .api_seek_bucket(name.as_ref())
+        .map(|(k, b)| (k, b.into())),
+    }
+  }
 }
 
 /// Read/Write Cursor
@@ -295,12 +522,35 @@ impl<'tx> CursorApi<'tx> for CursorRwImpl<'tx> {
   fn seek<T: AsRef<[u8]>>(&mut self, seek: T) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
     self.c.api_seek(seek.as_ref())
   }
+
+  fn try_seek<T: AsRef<[u8]>>(&mut self, seek: T) -> crate::Result<Option<KeyValue<'tx>>> {
+    self.c.api_try_seek(seek.as_ref())
+  }
+
+  fn next_bucket(&mut self) -> Option<(&'tx [u8], BucketImpl<'tx>)> {
+    self.c.api_next_bucket().map(|(k, b)| (k, b.into()))
+  }
+
+  fn seek_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> Option<(&'tx [u8], BucketImpl<'tx>)> {
+    self
+      .c
+      .api_seek_bucket(name.as_ref())
+      .map(|(k, b)| (k, b.into()))
+  }
 }
 
 impl<'tx> CursorRwApi<'tx> for CursorRwImpl<'tx> {
   fn delete(&mut self) -> crate::Result<()> {
     self.c.api_delete()
   }
+
+  fn delete_current(&mut self) -> crate::Result<Option<KeyValue<'tx>>> {
+    self.c.api_delete_current()
+  }
+
+  fn put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()> {
+    self.c.api_put(key.as_ref(), value.as_ref())
+  }
 }
 
 pub(crate) trait CursorIApi<'tx>: Clone {
@@ -335,21 +585,42 @@ pub(crate) trait CursorIApi<'tx>: Clone {
   /// See [CursorApi::seek]
   fn api_seek(&mut self, seek: &[u8]) -> Option<(&'tx [u8], Option<&'tx [u8]>)>;
 
+  /// See [CursorApi::try_seek]
+  fn api_try_seek(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValue<'tx>>>;
+
   /// i_seek moves the cursor to a given key and returns it.
   /// If the key does not exist then the next key is used.
   fn i_seek(&mut self, seek: &[u8]) -> Option<(&'tx [u8], &'tx [u8], u32)>;
 
+  /// i_try_seek is [CursorIApi::i_seek], but returns an error if a corrupted page is found.
+  fn i_try_seek(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValueFlags<'tx>>>;
+
+  /// i_try_seek_from is [CursorIApi::i_try_seek] for a key that sorts at or after the key
+  /// the cursor was last seeked to. The search starts from the lowest page on the stack
+  /// that covers the key instead of from the root.
+  fn i_try_seek_from(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValueFlags<'tx>>>;
+
+  /// i_try_seek_leaf is [CursorIApi::i_try_seek] for a key in the range of the leaf page
+  /// `leaf`. Only the leaf is searched.
+  fn i_try_seek_leaf(
+    &mut self, leaf: RefPage<'tx>, seek: &[u8],
+  ) -> crate::Result<Option<KeyValueFlags<'tx>>>;
+
+  /// leaf_page returns the leaf page the cursor is positioned on. Returns None if the cursor
+  /// is positioned on a node.
+  fn leaf_page(&self) -> Option<RefPage<'tx>>;
+
   /// first moves the cursor to the first leaf element under the last page in the stack.
   fn go_to_first_element_on_the_stack(&mut self);
 
   /// search recursively performs a binary search against a given page/node until it finds a given key.
-  fn search(&mut self, key: &[u8], pgid: PgId);
+  fn search(&mut self, key: &[u8], pgid: PgId) -> crate::Result<()>;
 
-  fn search_inodes(&mut self, key: &[u8]);
+  fn search_inodes(&mut self, key: &[u8]) -> crate::Result<()>;
 
-  fn search_node(&mut self, key: &[u8], node: NodeRwCell<'tx>);
+  fn search_node(&mut self, key: &[u8], node: NodeRwCell<'tx>) -> crate::Result<()>;
 
-  fn search_page(&mut self, key: &[u8], page: &RefPage);
+  fn search_page(&mut self, key: &[u8], page: &RefPage) -> crate::Result<()>;
 }
 
 pub(crate) trait CursorRwIApi<'tx>: CursorIApi<'tx> {
@@ -358,6 +629,19 @@ pub(crate) trait CursorRwIApi<'tx>: CursorIApi<'tx> {
 
   /// See [CursorRwApi::delete]
   fn api_delete(&mut self) -> crate::Result<()>;
+
+  /// See [CursorRwApi::delete_current]
+  fn api_delete_current(&mut self) -> crate::Result<Option<KeyValue<'tx>>>;
+
+  /// See [CursorRwApi::put]
+  fn api_put(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()>;
+
+  /// replace_value replaces the value of the key the cursor is positioned on
+  /// without searching for the key again.
+  fn replace_value(&mut self, value: &[u8]);
+  /// sync_stack replaces the pages on the stack with their nodes so later searches from
+  /// the stack see the changes made through [CursorRwIApi::node].
+  fn sync_stack(&mut self);
 }
 
 #[derive(Copy, Clone)]
@@ -394,6 +678,11 @@ impl<'tx> ElemRef<'tx> {
 pub(crate) struct InnerCursor<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> {
   bucket: B,
   stack: BVec<'tx, ElemRef<'tx>>,
+  /// The key the cursor was last positioned on and the transaction's mutation count at the time
+  position: Option<(&'tx [u8], u64)>,
+  /// whether the cursor skips the reserved top-level buckets. Only set for cursors over the
+  /// root bucket handed to the application
+  hide_reserved: bool,
   phantom_t: PhantomData<T>,
 }
 
@@ -409,14 +698,109 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> InnerCursor<'tx, T, B> {
     InnerCursor {
       bucket: cell,
       stack: BVec::with_capacity_in(0, bump),
+      position: None,
+      hide_reserved: false,
       phantom_t: PhantomData,
     }
   }
+
+  /// Makes the cursor skip the reserved top-level buckets. See [crate::RESERVED_PREFIX]
+  pub(crate) fn hiding_reserved(mut self) -> Self {
+    self.hide_reserved = true;
+    self
+  }
+
+  /// Moves the cursor past reserved buckets it hides, forward or backward, starting at `kv`
+  fn skip_hidden(
+    &mut self, mut kv: Option<(&'tx [u8], &'tx [u8], u32)>, forward: bool,
+  ) -> Option<(&'tx [u8], &'tx [u8], u32)> {
+    while let Some((k, _, _)) = kv {
+      if !self.hide_reserved || !is_reserved(k) {
+        break;
+      }
+      kv = if forward {
+        self.i_next()
+      } else {
+        self.i_prev()
+      };
+    }
+    kv
+  }
+
+  /// mark remembers the key the cursor is positioned on.
+  fn mark(&mut self, key: Option<&'tx [u8]>) {
+    self.position = key.map(|key| (key, self.bucket.tx().mutations()));
+  }
+
+  /// restore moves the cursor back onto the marked key if the transaction modified a
+  /// bucket since the cursor was positioned. The stack may still reference pages or
+  /// node indexes that no longer match the bucket's contents.
+  ///
+  /// Returns true if the marked key was removed. The cursor is then on the following key.
+  /// Returns [Error::BucketNotFound] if the bucket was deleted.
+  fn restore(&mut self) -> crate::Result<bool> {
+    let mutations = self.bucket.tx().mutations();
+    let key = match self.position {
+      Some((key, seen)) if seen != mutations => key,
+      _ => return Ok(false),
+    };
+    if self.bucket.is_deleted() {
+      return Err(Error::BucketNotFound);
+    }
+    let mut kv = self.i_try_seek(key)?;
+    if let Some(elem_ref) = self.stack.last() {
+      if elem_ref.index >= elem_ref.count() as i32 {
+        kv = self.i_next();
+      }
+    }
+    if kv.is_some_and(|(k, _, _)| self.bucket.comparator()(k, key).is_eq()) {
+      self.position = Some((key, mutations));
+      return Ok(false);
+    }
+    // Keep the mark stale so the cursor is restored until it is moved
+    Ok(true)
+  }
+
+  fn restore_or_panic(&mut self) -> bool {
+    match self.restore() {
+      Ok(removed) => removed,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// See [CursorApi::next_bucket]
+  pub(crate) fn api_next_bucket(&mut self) -> Option<(&'tx [u8], B)> {
+    let item = self.api_next();
+    self.skip_to_bucket(item)
+  }
+
+  /// See [CursorApi::seek_bucket]
+  pub(crate) fn api_seek_bucket(&mut self, name: &[u8]) -> Option<(&'tx [u8], B)> {
+    let item = self.api_seek(name);
+    self.skip_to_bucket(item)
+  }
+
+  /// skip_to_bucket moves forward from `item` until it finds a nested bucket.
+  fn skip_to_bucket(
+    &mut self, mut item: Option<(&'tx [u8], Option<&'tx [u8]>)>,
+  ) -> Option<(&'tx [u8], B)> {
+    while let Some((k, v)) = item {
+      if v.is_none() {
+        return self.bucket.api_bucket(k).map(|b| (k, b));
+      }
+      item = self.api_next();
+    }
+    None
+  }
 }
 
 impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor<'tx, T, B> {
   fn api_first(&mut self) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
-    let (k, v, flags) = self.i_first()?;
+    self.bucket.inc_metrics(|m| m.cursor_ops += 1);
+    let kv = self.i_first();
+    let kv = self.skip_hidden(kv, true);
+    self.mark(kv.map(|(k, _, _)| k));
+    let (k, v, flags) = kv?;
     if (flags & BUCKET_LEAF_FLAG) != 0 {
       return Some((k, None));
     }
@@ -447,7 +831,14 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   fn api_next(&mut self) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
-    let (k, v, flags) = self.i_next()?;
+    self.bucket.inc_metrics(|m| m.cursor_ops += 1);
+    let kv = if self.restore_or_panic() {
+      self.key_value()
+    } else {
+      self.i_next()
+    };
+    let (k, v, flags) = self.skip_hidden(kv, true)?;
+    self.mark(Some(k));
     if flags & BUCKET_LEAF_FLAG != 0 {
       Some((k, None))
     } else {
@@ -496,7 +887,11 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   fn api_prev(&mut self) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
-    let (k, v, flags) = self.i_prev()?;
+    self.bucket.inc_metrics(|m| m.cursor_ops += 1);
+    self.restore_or_panic();
+    let kv = self.i_prev();
+    let (k, v, flags) = self.skip_hidden(kv, false)?;
+    self.mark(Some(k));
     if flags & BUCKET_LEAF_FLAG != 0 {
       Some((k, None))
     } else {
@@ -537,6 +932,7 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   fn api_last(&mut self) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
+    self.bucket.inc_metrics(|m| m.cursor_ops += 1);
     self.stack.truncate(0);
     let root = self.bucket.root();
     let pn = self.bucket.page_node(root);
@@ -550,10 +946,19 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
     }
 
     if self.stack.is_empty() {
+      self.mark(None);
       return None;
     }
 
-    let (k, v, flags) = self.key_value().unwrap();
+    let kv = self.key_value();
+    let (k, v, flags) = match self.skip_hidden(kv, false) {
+      Some(kv) => kv,
+      None => {
+        self.mark(None);
+        return None;
+      }
+    };
+    self.mark(Some(k));
 
     if flags & BUCKET_LEAF_FLAG != 0 {
       Some((k, None))
@@ -617,28 +1022,139 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   fn api_seek(&mut self, seek: &[u8]) -> Option<(&'tx [u8], Option<&'tx [u8]>)> {
-    let mut vals = self.i_seek(seek);
+    match self.api_try_seek(seek) {
+      Ok(vals) => vals,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  fn api_try_seek(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValue<'tx>>> {
+    self.bucket.inc_metrics(|m| m.cursor_ops += 1);
+    let mut vals = self.i_try_seek(seek)?;
 
     if let Some(elem_ref) = self.stack.last() {
       if elem_ref.index >= elem_ref.count() as i32 {
         vals = self.i_next();
       }
     }
+    let vals = self.skip_hidden(vals, true);
+    self.mark(vals.map(|(k, _, _)| k));
+
+    Ok(vals.map(|(k, v, flags)| {
+      if flags & BUCKET_LEAF_FLAG != 0 {
+        (k, None)
+      } else {
+        (k, Some(v))
+      }
+    }))
+  }
 
-    let (k, v, flags) = vals?;
-    if flags & BUCKET_LEAF_FLAG != 0 {
-      Some((k, None))
-    } else {
-      Some((k, Some(v)))
+  fn i_seek(&mut self, seek: &[u8]) -> Option<(&'tx [u8], &'tx [u8], u32)> {
+    match self.i_try_seek(seek) {
+      Ok(vals) => vals,
+      Err(e) => panic!("{}", e),
     }
   }
 
-  fn i_seek(&mut self, seek: &[u8]) -> Option<(&'tx [u8], &'tx [u8], u32)> {
+  fn i_try_seek(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValueFlags<'tx>>> {
     self.stack.truncate(0);
     let root = self.bucket.root();
-    self.search(seek, root);
+    self.search(seek, root)?;
 
-    self.key_value()
+    {
+      let tx = self.bucket.tx();
+      let tx_r = tx.split_r();
+      let stats = tx_r.stats.as_ref().unwrap();
+      stats.inc_seek_n(1);
+      stats.inc_seek_page_n(self.stack.len() as i64);
+    }
+
+    Ok(self.key_value())
+  }
+
+  fn i_try_seek_from(&mut self, seek: &[u8]) -> crate::Result<Option<KeyValueFlags<'tx>>> {
+    if self.stack.is_empty() {
+      return self.i_try_seek(seek);
+    }
+    let comparator = self.bucket.comparator();
+    // Keep the pages from the root down whose keys still cover the key. A page stops
+    // covering it once the next element of its parent starts at or before the key.
+    let mut keep = 1;
+    for depth in 1..self.stack.len() {
+      let parent = &self.stack[depth - 1];
+      let next = parent.index as usize + 1;
+      if next < parent.count() as usize {
+        let starts_before = match &parent.pn {
+          PageNode::Page(page) => {
+            let branch_page = MappedBranchPage::coerce_ref(page).unwrap();
+            let elem = &branch_page.elements()[next];
+            let elem_key = unsafe { elem.key(branch_page.page_ptr().cast_const()) };
+            let prefix = branch_page.prefix();
+            if prefix.is_empty() {
+              comparator(elem_key, seek).is_le()
+            } else {
+              comparator(&[prefix, elem_key].concat(), seek).is_le()
+            }
+          }
+          PageNode::Node(node) => comparator(node.cell.borrow().inodes[next].key(), seek).is_le(),
+        };
+        if starts_before {
+          break;
+        }
+      }
+      keep = depth + 1;
+    }
+    self.stack.truncate(keep);
+
+    let depth = self.stack.len();
+    let top = self.stack.last().unwrap();
+    let (pn, is_leaf) = (top.pn, top.is_leaf());
+    match pn {
+      _ if is_leaf => self.search_inodes(seek)?,
+      PageNode::Page(page) => self.search_page(seek, &page)?,
+      PageNode::Node(node) => self.search_node(seek, node)?,
+    }
+
+    {
+      let tx = self.bucket.tx();
+      let tx_r = tx.split_r();
+      let stats = tx_r.stats.as_ref().unwrap();
+      stats.inc_seek_n(1);
+      stats.inc_seek_page_n((self.stack.len() - depth + 1) as i64);
+    }
+
+    Ok(self.key_value())
+  }
+
+  fn i_try_seek_leaf(
+    &mut self, leaf: RefPage<'tx>, seek: &[u8],
+  ) -> crate::Result<Option<KeyValueFlags<'tx>>> {
+    self.stack.truncate(0);
+    self.stack.push(ElemRef {
+      pn: PageNode::Page(leaf),
+      index: 0,
+    });
+    self.search_inodes(seek)?;
+
+    {
+      let tx = self.bucket.tx();
+      let tx_r = tx.split_r();
+      let stats = tx_r.stats.as_ref().unwrap();
+      stats.inc_seek_n(1);
+      stats.inc_seek_page_n(1);
+    }
+
+    Ok(self.key_value())
+  }
+
+  fn leaf_page(&self) -> Option<RefPage<'tx>> {
+    match self.stack.last() {
+      Some(ElemRef {
+        pn: PageNode::Page(page),
+        ..
+      }) if page.is_leaf() => Some(*page),
+      _ => None,
+    }
   }
 
   /// first moves the cursor to the first leaf element under the last page in the stack.
@@ -671,12 +1187,28 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   /// search recursively performs a binary search against a given page/node until it finds a given key.
-  fn search(&mut self, key: &[u8], pgid: PgId) {
-    let pn = self.bucket.page_node(pgid);
+  fn search(&mut self, key: &[u8], pgid: PgId) -> crate::Result<()> {
+    self.bucket.tx().check_evicted()?;
+    let high_water = self.bucket.tx().meta().pgid();
+    if pgid >= high_water {
+      return Err(Error::Corrupted {
+        pgid,
+        reason: Cow::Owned(format!("above high water mark {}", high_water)),
+      });
+    }
+
+    let pn = self.bucket.try_page_node(pgid)?;
 
     if let PageNode::Page(page) = &pn {
+      // Inline pages don't carry their own page id.
+      if self.bucket.root() != ZERO_PGID {
+        page.check(pgid)?;
+      }
       if !page.is_leaf() && !page.is_branch() {
-        panic!("invalid page type: {}, {:X}", page.id, page.flags);
+        return Err(Error::Corrupted {
+          pgid,
+          reason: Cow::Owned(format!("invalid page type {}", page.page_type())),
+        });
       }
     }
 
@@ -688,8 +1220,7 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
     self.stack.push(elem);
 
     if elem_is_leaf {
-      self.search_inodes(key);
-      return;
+      return self.search_inodes(key);
     }
 
     match &pn {
@@ -699,32 +1230,33 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
   }
 
   /// search_inodes searches the leaf node on the top of the stack for a key.
-  fn search_inodes(&mut self, key: &[u8]) {
+  fn search_inodes(&mut self, key: &[u8]) -> crate::Result<()> {
+    let comparator = self.bucket.comparator();
     if let Some(elem) = self.stack.last_mut() {
       let index = match &elem.pn {
         // If we have a page then search its leaf elements.
         PageNode::Page(page) => {
           let leaf_page = MappedLeafPage::coerce_ref(page).unwrap();
-          leaf_page
-            .elements()
-            .partition_point(|elem| unsafe { elem.key(leaf_page.page_ptr().cast_const()) } < key)
+          partition_point(leaf_page.elements(), |elem| {
+            comparator(unsafe { elem.key(leaf_page.page_ptr().cast_const()) }, key).is_lt()
+          })
         }
         // If we have a node then search its inodes.
-        PageNode::Node(node) => node
-          .cell
-          .borrow()
-          .inodes
-          .partition_point(|inode| inode.key() < key),
+        PageNode::Node(node) => partition_point(&node.cell.borrow().inodes, |inode| {
+          comparator(inode.key(), key).is_lt()
+        }),
       };
       elem.index = index as i32;
     }
+    Ok(())
   }
 
-  fn search_node(&mut self, key: &[u8], node: NodeRwCell<'tx>) {
+  fn search_node(&mut self, key: &[u8], node: NodeRwCell<'tx>) -> crate::Result<()> {
+    let comparator = self.bucket.comparator();
     let (index, pgid) = {
       let w = node.cell.borrow();
 
-      let r = w.inodes.binary_search_by_key(&key, |inode| inode.key());
+      let r = search_by(&w.inodes, |inode| comparator(inode.key(), key));
       let index = r.unwrap_or_else(|index| if index > 0 { index - 1 } else { index });
       (index as u32, w.inodes[index].pgid())
     };
@@ -737,15 +1269,28 @@ impl<'tx, T: TxIApi<'tx>, B: BucketIApi<'tx, T>> CursorIApi<'tx> for InnerCursor
     self.search(key, pgid)
   }
 
-  fn search_page(&mut self, key: &[u8], page: &RefPage) {
+  fn search_page(&mut self, key: &[u8], page: &RefPage) -> crate::Result<()> {
     let branch_page = MappedBranchPage::coerce_ref(page).unwrap();
-    let elements = branch_page.elements();
-    debug_assert_ne!(0, elements.len());
-    let r = branch_page
-      .elements()
-      .binary_search_by_key(&key, |elem| unsafe {
-        elem.key(branch_page.page_ptr().cast_const())
+    if branch_page.count == 0 {
+      return Err(Error::Corrupted {
+        pgid: page.id,
+        reason: Cow::Borrowed("empty branch page"),
       });
+    }
+    let comparator = self.bucket.comparator();
+    let prefix = branch_page.prefix();
+    let mut full_key = Vec::new();
+    let r = search_by(branch_page.elements(), |elem| {
+      let elem_key = unsafe { elem.key(branch_page.page_ptr().cast_const()) };
+      if prefix.is_empty() {
+        comparator(elem_key, key)
+      } else {
+        full_key.clear();
+        full_key.extend_from_slice(prefix);
+        full_key.extend_from_slice(elem_key);
+        comparator(&full_key, key)
+      }
+    });
     let index = r.unwrap_or_else(|index| if index > 0 { index - 1 } else { index });
 
     if let Some(elem) = self.stack.last_mut() {
@@ -791,13 +1336,92 @@ impl<'tx, B: BucketRwIApi<'tx>> CursorRwIApi<'tx> for InnerCursor<'tx, TxCell<'t
   }
 
   fn api_delete(&mut self) -> crate::Result<()> {
-    let (k, _, flags) = self.key_value().unwrap();
+    self.bucket.tx().check_deadline()?;
+    if self.restore()? {
+      // The key under the cursor was already removed
+      return Ok(());
+    }
+    let (k, v, flags) = self.key_value().unwrap();
     if flags & BUCKET_LEAF_FLAG != 0 {
       return Err(IncompatibleValue);
     }
+    self.bucket.tx().record_mutation();
+    self.bucket.account(-1, -((k.len() + v.len()) as i64))?;
+    self.bucket.record_undo(|bump, path| Undo::Put {
+      path,
+      key: bump.alloc_slice_copy(k),
+      prev: Some(bump.alloc_slice_copy(v)),
+    });
+    #[cfg(feature = "replication")]
+    self
+      .bucket
+      .record_change(|path| Change::Delete { path, key: k });
     self.node().del(k);
     Ok(())
   }
+
+  fn api_delete_current(&mut self) -> crate::Result<Option<KeyValue<'tx>>> {
+    if self.restore()? {
+      let kv = self.key_value();
+      self.mark(kv.map(|(k, _, _)| k));
+      return Ok(kv.map(|(k, v, flags)| {
+        if flags & BUCKET_LEAF_FLAG != 0 {
+          (k, None)
+        } else {
+          (k, Some(v))
+        }
+      }));
+    }
+    let (k, _, _) = self.key_value().unwrap();
+    self.api_delete()?;
+    // The stack may still point at the unmodified page so seek to the following key
+    self.api_try_seek(k)
+  }
+
+  fn api_put(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+    self.bucket.api_put(key, value)?;
+    self.api_try_seek(key)?;
+    Ok(())
+  }
+
+  fn replace_value(&mut self, value: &[u8]) {
+    let last = self.stack.len() - 1;
+    let index = self.stack[last].index as usize;
+    let page = match self.stack[last].pn {
+      // The root leaf has no parent to point to a copy
+      PageNode::Page(page) if last > 0 => page,
+      _ => return self.node().replace_value(index, value),
+    };
+    // The parents point to the patched copy of the leaf once it's written
+    let mut n = match &self.stack[0].pn {
+      PageNode::Page(page) => self.bucket.node(page.id, None),
+      PageNode::Node(node) => *node,
+    };
+    for depth in 0..last {
+      self.stack[depth].pn = PageNode::Node(n);
+      if depth + 1 < last {
+        n = n.child_at(self.stack[depth].index as u32);
+      }
+    }
+    match self.bucket.patch_leaf(page, n, index, value) {
+      Some(patched) => self.stack[last].pn = PageNode::Page(patched),
+      None => self.node().replace_value(index, value),
+    }
+  }
+
+  fn sync_stack(&mut self) {
+    let mut n = match &self.stack.first().unwrap().pn {
+      PageNode::Page(page) => self.bucket.node(page.id, None),
+      PageNode::Node(node) => *node,
+    };
+    let last = self.stack.len() - 1;
+    for (depth, elem) in self.stack.iter_mut().enumerate() {
+      elem.pn = PageNode::Node(n);
+      if depth < last {
+        n = n.child_at(elem.index as u32);
+      }
+    }
+  }
 }
 
 #[cfg(test)]
@@ -884,6 +1508,142 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  #[cfg(not(miri))]
+  fn test_cursor_delete_current_and_put() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let count = 1000u64;
+    let value = [0u8; 100];
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in 0..count {
+        b.put(i.to_be_bytes(), value)?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let b = tx.bucket_mut(b"widgets").unwrap();
+      let mut c = b.cursor_mut();
+      let mut item = c.first();
+      while let Some((k, _)) = item {
+        let i = u64::from_be_bytes(k.try_into().unwrap());
+        if i >= count {
+          break;
+        } else if i % 2 == 1 {
+          item = c.delete_current()?;
+        } else {
+          // The cursor moves to the inserted key so return to the current one
+          c.put((count + i).to_be_bytes(), value)?;
+          c.seek(k);
+          item = c.next();
+        }
+      }
+      assert_eq!(Err(Error::KeyRequired), c.put([], value));
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let b = tx.bucket(b"widgets").unwrap();
+      for i in 0..count {
+        assert_eq!(i % 2 == 0, b.get(i.to_be_bytes()).is_some());
+        assert_eq!(i % 2 == 0, b.get((count + i).to_be_bytes()).is_some());
+      }
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_cursor_mutate_while_iterating() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let count = 1000u64;
+    let value = [0u8; 100];
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket(b"widgets")?;
+      for i in (0..count).step_by(2) {
+        b.put(i.to_be_bytes(), value)?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let b = tx.bucket_mut(b"widgets").unwrap();
+      let mut w = tx.bucket_mut(b"widgets").unwrap();
+      let mut c = b.cursor_mut();
+      let mut seen = Vec::new();
+      let mut item = c.first();
+      while let Some((k, _)) = item {
+        let i = u64::from_be_bytes(k.try_into().unwrap());
+        seen.push(i);
+        if i % 2 == 0 {
+          // Insert the following key and remove the next one ahead of the cursor
+          w.put((i + 1).to_be_bytes(), value)?;
+          w.delete((i + 2).to_be_bytes())?;
+        } else {
+          // Remove the key behind the cursor
+          w.delete((i - 1).to_be_bytes())?;
+        }
+        item = c.next();
+      }
+      let expected = (0..count)
+        .step_by(4)
+        .flat_map(|i| [i, i + 1])
+        .collect::<Vec<_>>();
+      assert_eq!(expected, seen);
+
+      // Keys inserted ahead of the cursor are visited in reverse too
+      let mut seen = Vec::new();
+      let mut item = c.last();
+      while let Some((k, _)) = item {
+        let i = u64::from_be_bytes(k.try_into().unwrap());
+        seen.push(i);
+        if i % 2 == 1 {
+          w.put((i - 1).to_be_bytes(), value)?;
+        }
+        item = c.prev();
+      }
+      let mut expected = (0..count)
+        .step_by(4)
+        .flat_map(|i| [i, i + 1])
+        .collect::<Vec<_>>();
+      expected.reverse();
+      assert_eq!(expected, seen);
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_cursor_next_bucket() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      for name in ["a", "b", "c"] {
+        let mut b = tx.create_bucket(name)?;
+        b.put("foo", name)?;
+      }
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let mut c = tx.cursor();
+      let (name, b) = c.seek_bucket("b").unwrap();
+      assert_eq!(b"b", name);
+      assert_eq!(Some(b"b".as_slice()), b.get("foo"));
+      let (name, b) = c.next_bucket().unwrap();
+      assert_eq!(b"c", name);
+      assert_eq!(Some(b"c".as_slice()), b.get("foo"));
+      assert!(c.next_bucket().is_none());
+      assert!(c.seek_bucket("d").is_none());
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let b = tx.bucket_mut("a").unwrap();
+      let mut c = b.cursor_mut();
+      assert!(c.seek_bucket("").is_none());
+      Ok(())
+    })
+  }
+
   #[test]
   #[cfg(not(miri))]
   fn test_cursor_seek_large() -> crate::Result<()> {
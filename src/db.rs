@@ -1,41 +1,84 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
 use crate::arch::size::MAX_MAP_SIZE;
-use crate::bucket::BucketRwIApi;
+use crate::arch::time::Instant;
+#[cfg(feature = "async")]
+use crate::async_db::{BlockingExecutor, ThreadExecutor};
+use crate::bucket::{BucketIApi, BucketMetrics, BucketRwIApi, MAX_KEY_SIZE, MAX_VALUE_SIZE};
 use crate::common::bucket::BucketHeader;
 use crate::common::bump::PinBump;
+#[cfg(file_backend_supported)]
+use crate::common::defaults::LOCK_RETRY_INTERVAL;
 use crate::common::defaults::{
-  DEFAULT_ALLOC_SIZE, DEFAULT_MAX_BATCH_DELAY, DEFAULT_MAX_BATCH_SIZE, DEFAULT_PAGE_SIZE, MAGIC,
-  MAX_MMAP_STEP, PGID_NO_FREE_LIST, VERSION,
+  has_page_checksums, has_prefix_branch, version_with, DEFAULT_ALLOC_SIZE, DEFAULT_MAX_BATCH_DELAY,
+  DEFAULT_MAX_BATCH_SIZE, DEFAULT_PAGE_SIZE, MAGIC, MAX_MMAP_STEP, PGID_NO_FREE_LIST, VERSION,
 };
+use crate::common::histogram::LatencyHistogram;
 use crate::common::lock::LockGuard;
+#[cfg(file_backend_supported)]
+use crate::common::meta::META_HEADER_SIZE;
 use crate::common::meta::{MappedMetaPage, Meta};
+#[cfg(file_backend_supported)]
+use crate::common::page::PAGE_HEADER_SIZE;
 use crate::common::page::{CoerciblePage, MutPage, PageHeader, RefPage};
 use crate::common::pool::{SyncPool, SyncReusable};
 use crate::common::self_owned::SelfOwned;
 use crate::common::tree::MappedLeafPage;
+#[cfg(file_backend_supported)]
+use crate::common::validate::{
+  UntrustedValidator, UNTRUSTED_MAX_ELEMENTS, UNTRUSTED_MAX_PAGE_SIZE, UNTRUSTED_MIN_PAGE_SIZE,
+};
 use crate::common::{BVec, PgId, SplitRef, TxId};
-use crate::freelist::{Freelist, MappedFreeListPage};
+#[cfg(file_backend_supported)]
+use crate::comparator::{path_names, ROOT_KV_BUCKET};
+use crate::comparator::{split_meta_key, Comparators, COMPARATOR_META_KEY, META_BUCKET};
+#[cfg(feature = "failpoints")]
+use crate::failpoints::{FailPoint, FailPoints};
+use crate::freelist::{Freelist, FreelistAllocStrategy, MappedFreeListPage};
+#[cfg(file_backend_supported)]
+use crate::lock_file::{check_unlocked, lock_file_path, read_owner, LockFile, LockOwner};
+#[cfg(file_backend_supported)]
+use crate::quota::{QuotaRecord, QUOTA_META_KEY};
+#[cfg(feature = "replication")]
+use crate::replication::{ChangeListener, Changeset, ReplicationLog, Subscription};
+#[cfg(feature = "sim")]
+use crate::sim::SimLog;
+#[cfg(feature = "page-store")]
+use crate::store::PageStore;
+#[cfg(file_backend_supported)]
+use crate::tx::TxRwIApi;
 use crate::tx::{
-  TxCell, TxClosingState, TxIApi, TxImpl, TxRef, TxRwApi, TxRwImpl, TxRwRef, TxStats,
+  TxCell, TxClosingState, TxDropPolicy, TxIApi, TxImpl, TxRef, TxRwApi, TxRwImpl, TxRwRef, TxStats,
 };
+#[cfg(file_backend_supported)]
+use crate::{BucketApi, BucketImpl, BucketRwApi, CursorApi, TxRwRefApi};
 use crate::{Error, TxApi};
-use aligners::{alignment, AlignedBytes};
 use anyhow::anyhow;
+#[cfg(file_backend_supported)]
 use fs4::FileExt;
+#[cfg(file_backend_supported)]
 use memmap2::{Advice, MmapOptions, MmapRaw};
 use monotonic_timer::{Guard, Timer};
-use parking_lot::{Mutex, MutexGuard, RwLock};
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+#[cfg(file_backend_supported)]
 use std::fs::File;
+#[cfg(file_backend_supported)]
 use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(file_backend_supported)]
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicI64, Ordering};
+#[cfg(file_backend_supported)]
+use std::slice::from_raw_parts;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::mpsc::{Receiver, SyncSender};
 use std::sync::{mpsc, Arc, OnceLock, Weak};
 use std::time::Duration;
-#[cfg(feature = "try-begin")]
-use std::time::Instant;
-use std::{fs, io, mem, thread};
+#[cfg(file_backend_supported)]
+use std::{fs, io};
+use std::{mem, thread};
 use typed_builder::TypedBuilder;
 
 /// Read-only DB API
@@ -118,6 +161,42 @@ where
   /// ```
   fn view<'tx, F: Fn(TxRef<'tx>) -> crate::Result<()>>(&'tx self, f: F) -> crate::Result<()>;
 
+  /// Executes a function within a managed read-only transaction like [DbApi::view], within
+  /// a time budget. Fails with [Error::Timeout](crate::Error::Timeout) if the transaction
+  /// can't be started before `deadline`, e.g. while the database is remapped.
+  ///
+  /// The function is passed a [Deadline] to poll between steps of long running work, so it
+  /// can give up once the budget is used up.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::time::{Duration, Instant};
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view_deadline(Instant::now() + Duration::from_secs(1), |tx, deadline| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     b.for_each(|k, _| {
+  ///       deadline.check()?;
+  ///       assert_eq!(b"key", k);
+  ///       Ok(())
+  ///     })
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn view_deadline<'tx, F: FnMut(TxRef<'tx>, &Deadline) -> crate::Result<()>>(
+    &'tx self, deadline: Instant, f: F,
+  ) -> crate::Result<()>;
+
   /// Stats retrieves ongoing performance stats for the database.
   ///
   /// This is only updated when a transaction closes.
@@ -143,9 +222,12 @@ where
   /// use bbolt_rs::*;
   ///
   /// fn main() -> Result<()> {
-  ///   let db = Bolt::open_mem()?;
+  ///   let db = BoltOptions::builder().no_sync().build().open_mem()?;
   ///
-  ///   assert_eq!(4096, db.info().page_size);
+  ///   let info = db.info();
+  ///   assert_eq!(4096, info.page_size);
+  ///   assert!(info.no_sync);
+  ///   assert!(!info.read_only);
   ///   Ok(())
   /// }
   /// ```
@@ -219,6 +301,30 @@ pub trait DbRwAPI: DbApi {
   /// ```
   fn begin_rw(&mut self) -> crate::Result<impl TxRwApi>;
 
+  /// Starts a new write transaction with the given options.
+  ///
+  /// When a timeout is set, every mutation and the commit attempted after the
+  /// deadline fails with [Error::TxTimeout](crate::Error::TxTimeout). The transaction
+  /// rolls back once dropped, so a hung writer can't hold the writer lock forever.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::time::Duration;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   let options = TxRwOptions::builder().timeout(Duration::from_secs(5)).build();
+  ///   let mut tx = db.begin_rw_tx_with(options)?;
+  ///   let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///   b.put("key", "value")?;
+  ///   tx.commit()?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn begin_rw_tx_with(&mut self, options: TxRwOptions) -> crate::Result<impl TxRwApi<'_>>;
+
   #[cfg(feature = "try-begin")]
   fn try_begin_rw(&self) -> crate::Result<Option<impl TxRwApi>>;
 
@@ -260,6 +366,37 @@ pub trait DbRwAPI: DbApi {
     &'tx mut self, f: F,
   ) -> crate::Result<()>;
 
+  /// Executes a function within a managed read-write transaction like [DbRwAPI::update],
+  /// within a time budget. Fails with [Error::Timeout](crate::Error::Timeout) if the writer
+  /// lock can't be taken before `deadline`.
+  ///
+  /// The function is passed a [Deadline] to poll between steps of long running work.
+  /// Mutations and the commit attempted after the deadline fail with
+  /// [Error::TxTimeout](crate::Error::TxTimeout) and the transaction is rolled back.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::time::{Duration, Instant};
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update_deadline(Instant::now() + Duration::from_secs(1), |mut tx, deadline| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     for i in 0..100u32 {
+  ///       deadline.check()?;
+  ///       b.put(i.to_be_bytes(), "value")?;
+  ///     }
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn update_deadline<'tx, F: FnMut(TxRwRef<'tx>, &Deadline) -> crate::Result<()>>(
+    &'tx mut self, deadline: Instant, f: F,
+  ) -> crate::Result<()>;
+
   /// Calls a function as part of a batch. It behaves similar to Update,
   /// except:
   ///
@@ -327,6 +464,18 @@ pub trait DbRwAPI: DbApi {
   fn sync(&mut self) -> crate::Result<()>;
 }
 
+/// ReaderPin describes the read transactions open on the snapshot of one txid.
+/// See [DbStats::pending_by_reader]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReaderPin {
+  /// the txid of the snapshot the readers pin
+  pub txid: TxId,
+  /// the number of open read transactions and pinned snapshots on it
+  pub readers: u64,
+  /// the number of pending pages that can't be reused while the snapshot is open
+  pub blocked_pages: u64,
+}
+
 #[derive(Default)]
 /// Stats represents statistics about the database.
 pub struct DbStats {
@@ -342,12 +491,24 @@ pub struct DbStats {
   free_alloc: AtomicI64,
   /// total bytes used by the freelist
   free_list_in_use: AtomicI64,
+  /// total number of contiguous free page spans
+  free_span_n: AtomicI64,
+  /// number of pages in the largest contiguous free page span
+  max_free_span: AtomicI64,
 
   // transaction stats
   /// total number of started read transactions
   tx_n: AtomicI64,
   /// number of currently open read transactions
   open_tx_n: AtomicI64,
+  /// the snapshots pinned by read transactions as of the last commit
+  pending_by_reader: Mutex<Vec<ReaderPin>>,
+  /// total number of evicted read transactions
+  evicted_tx_n: AtomicI64,
+
+  // commit stats
+  /// durations of committed write transactions
+  commit_latency: LatencyHistogram,
 }
 
 impl DbStats {
@@ -356,64 +517,174 @@ impl DbStats {
     &self.tx_stats
   }
 
+  /// operation counters of every top-level bucket of the closed transactions.
+  /// See [TxStats::bucket_metrics]
+  pub fn bucket_metrics(&self) -> std::collections::HashMap<Vec<u8>, BucketMetrics> {
+    self.tx_stats.bucket_metrics()
+  }
+
   /// total number of free pages on the freelist
   pub fn free_page_n(&self) -> i64 {
-    self.free_page_n.load(Ordering::Acquire)
+    self.free_page_n.load(Ordering::Relaxed)
   }
 
   pub(crate) fn set_free_page_n(&self, value: i64) {
-    self.free_page_n.store(value, Ordering::Release);
+    self.free_page_n.store(value, Ordering::Relaxed);
   }
 
   /// total number of pending pages on the freelist
   pub fn pending_page_n(&self) -> i64 {
-    self.pending_page_n.load(Ordering::Acquire)
+    self.pending_page_n.load(Ordering::Relaxed)
   }
 
   pub(crate) fn set_pending_page_n(&self, value: i64) {
-    self.pending_page_n.store(value, Ordering::Release);
+    self.pending_page_n.store(value, Ordering::Relaxed);
   }
 
   /// total bytes allocated in free pages
   pub fn free_alloc(&self) -> i64 {
-    self.free_alloc.load(Ordering::Acquire)
+    self.free_alloc.load(Ordering::Relaxed)
   }
 
   pub(crate) fn set_free_alloc(&self, value: i64) {
-    self.free_alloc.store(value, Ordering::Release);
+    self.free_alloc.store(value, Ordering::Relaxed);
   }
 
   /// total bytes used by the freelist
   pub fn free_list_in_use(&self) -> i64 {
-    self.free_list_in_use.load(Ordering::Acquire)
+    self.free_list_in_use.load(Ordering::Relaxed)
   }
 
   pub(crate) fn set_free_list_in_use(&self, value: i64) {
-    self.free_list_in_use.store(value, Ordering::Release);
+    self.free_list_in_use.store(value, Ordering::Relaxed);
+  }
+
+  /// total number of contiguous free page spans. A high count relative to
+  /// free_page_n means the free pages are fragmented into small runs.
+  pub fn free_span_n(&self) -> i64 {
+    self.free_span_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn set_free_span_n(&self, value: i64) {
+    self.free_span_n.store(value, Ordering::Relaxed);
+  }
+
+  /// number of pages in the largest contiguous free page span
+  pub fn max_free_span(&self) -> i64 {
+    self.max_free_span.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn set_max_free_span(&self, value: i64) {
+    self.max_free_span.store(value, Ordering::Relaxed);
   }
 
   /// total number of started read transactions
   pub fn tx_n(&self) -> i64 {
-    self.tx_n.load(Ordering::Acquire)
+    self.tx_n.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_tx_n(&self, delta: i64) {
-    self.tx_n.fetch_add(delta, Ordering::AcqRel);
+    self.tx_n.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of currently open read transactions
   pub fn open_tx_n(&self) -> i64 {
-    self.open_tx_n.load(Ordering::Acquire)
+    self.open_tx_n.load(Ordering::Relaxed)
+  }
+
+  /// total number of evicted read transactions.
+  /// See [BoltOptions::evict_readers_after]
+  pub fn evicted_tx_n(&self) -> i64 {
+    self.evicted_tx_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_evicted_tx_n(&self, delta: i64) {
+    self.evicted_tx_n.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// durations of committed write transactions
+  pub fn commit_latency(&self) -> &LatencyHistogram {
+    &self.commit_latency
+  }
+
+  /// Returns the snapshots pinned by open read transactions and how many pending pages
+  /// each one keeps from being reused, oldest first. A page is blocked by every snapshot
+  /// that can still reach it, so the oldest readers usually account for most of the
+  /// growth of the file.
+  ///
+  /// Blocked pages are counted when a write transaction closes. Snapshots opened since
+  /// don't block any pages yet and are left out.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   // Growing the mmap would wait for the read transaction
+  ///   let mut db = BoltOptions::builder()
+  ///     .initial_mmap_size(1 << 20)
+  ///     .build()
+  ///     .open_mem()?;
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket("widgets")?.put("foo", "bar")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let reader = db.clone();
+  ///   let tx = reader.begin()?;
+  ///   db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "baz"))?;
+  ///   let pins = db.stats().pending_by_reader();
+  ///   assert_eq!(tx.id(), pins[0].txid);
+  ///   assert!(pins[0].blocked_pages > 0);
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn pending_by_reader(&self) -> Vec<ReaderPin> {
+    self.pending_by_reader.lock().clone()
+  }
+
+  pub(crate) fn set_pending_by_reader(&self, pins: Vec<ReaderPin>) {
+    *self.pending_by_reader.lock() = pins;
+  }
+
+  /// Removes a closed reader of the snapshot `txid`
+  pub(crate) fn remove_reader(&self, txid: TxId) {
+    let mut pins = self.pending_by_reader.lock();
+    if let Some(pos) = pins.iter().position(|pin| pin.txid == txid) {
+      pins[pos].readers -= 1;
+      if pins[pos].readers == 0 {
+        pins.remove(pos);
+      }
+    }
   }
 
   pub(crate) fn sub(&self, rhs: &DbStats) -> DbStats {
     let diff = self.clone();
     diff.inc_tx_n(-rhs.tx_n());
     diff.tx_stats.sub_assign(&rhs.tx_stats);
+    // commit_latency is cumulative since open and isn't subtracted
     diff
   }
 }
 
+impl Debug for DbStats {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("DbStats")
+      .field("tx_stats", &self.tx_stats)
+      .field("free_page_n", &self.free_page_n())
+      .field("pending_page_n", &self.pending_page_n())
+      .field("free_alloc", &self.free_alloc())
+      .field("free_list_in_use", &self.free_list_in_use())
+      .field("free_span_n", &self.free_span_n())
+      .field("max_free_span", &self.max_free_span())
+      .field("tx_n", &self.tx_n())
+      .field("open_tx_n", &self.open_tx_n())
+      .field("pending_by_reader", &self.pending_by_reader())
+      .field("evicted_tx_n", &self.evicted_tx_n())
+      .field("commit_latency", &self.commit_latency)
+      .finish()
+  }
+}
+
 impl Clone for DbStats {
   fn clone(&self) -> Self {
     DbStats {
@@ -422,23 +693,38 @@ impl Clone for DbStats {
       pending_page_n: self.pending_page_n().into(),
       free_alloc: self.free_alloc().into(),
       free_list_in_use: self.free_list_in_use().into(),
+      free_span_n: self.free_span_n().into(),
+      max_free_span: self.max_free_span().into(),
       tx_n: self.tx_n().into(),
       open_tx_n: self.open_tx_n().into(),
+      pending_by_reader: Mutex::new(self.pending_by_reader()),
+      evicted_tx_n: self.evicted_tx_n().into(),
+      commit_latency: self.commit_latency.clone(),
     }
   }
 }
 
 pub struct DbState {
   txs: Vec<TxId>,
+  /// The read transactions to evict. See [BoltOptions::evict_readers_after]
+  leases: Vec<ReaderLease>,
   rwtx: Option<TxId>,
   is_open: bool,
   current_meta: Meta,
 }
 
+/// An open read transaction which is evicted once it's too old
+struct ReaderLease {
+  txid: TxId,
+  begin: Instant,
+  evicted: Arc<AtomicBool>,
+}
+
 impl DbState {
   fn new(current_meta: Meta) -> DbState {
     DbState {
       txs: vec![],
+      leases: vec![],
       rwtx: None,
       is_open: true,
       current_meta,
@@ -494,17 +780,91 @@ impl DbPath {
 #[derive(Clone, Debug)]
 pub struct DbInfo {
   pub page_size: usize,
+  /// The size of the memory mapped data in bytes
+  pub mmap_size: u64,
+  /// The initial mmap size the database was opened with
+  pub initial_mmap_size: Option<u64>,
+  /// How the freelist picks free spans
+  pub freelist_alloc_strategy: FreelistAllocStrategy,
+  /// Whether the freelist prefers the lowest page ids
+  pub freelist_prefer_low_pgid: bool,
+  /// Whether commits skip fsync
+  pub no_sync: bool,
+  /// Whether growing the file skips fsync
+  pub no_grow_sync: bool,
+  /// Whether the freelist is not written on commit
+  pub no_freelist_sync: bool,
+  /// Whether the free pages were loaded on open
+  pub preload_freelist: bool,
+  /// Whether commits share a single fsync
+  pub group_commit: bool,
+  /// Whether the database file is locked in memory
+  pub mlock: bool,
+  /// Whether the database was opened in read-only mode
+  pub read_only: bool,
+  /// Whether the newest meta page was invalid when the database was opened, e.g. after a
+  /// torn write, so the database was opened from the previous transaction's meta page
+  pub recovered_from_backup_meta: bool,
+}
+
+/// The state of the two meta pages of a database. See [Bolt::verify_meta]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MetaStatus {
+  /// The txids of meta pages 0 and 1, whether they are valid or not
+  pub txids: [TxId; 2],
+  /// Whether meta pages 0 and 1 pass validation
+  pub valid: [bool; 2],
+}
+
+impl MetaStatus {
+  /// Returns the index of the meta page with the highest txid
+  fn newest(&self) -> usize {
+    if self.txids[1] > self.txids[0] {
+      1
+    } else {
+      0
+    }
+  }
+
+  /// Returns the index of the meta page the database is read from, the valid one with
+  /// the highest txid. None if neither is valid.
+  pub fn active(&self) -> Option<usize> {
+    let newest = self.newest();
+    if self.valid[newest] {
+      Some(newest)
+    } else if self.valid[1 - newest] {
+      Some(1 - newest)
+    } else {
+      None
+    }
+  }
+
+  /// Returns true if the newest meta page is invalid and the older one is used instead
+  pub fn uses_backup(&self) -> bool {
+    self.active().is_some_and(|active| active != self.newest())
+  }
+
+  /// Returns true if both meta pages are valid
+  pub fn is_healthy(&self) -> bool {
+    self.valid == [true, true]
+  }
 }
 
 pub(crate) trait DBBackend: Send + Sync {
   fn page_size(&self) -> usize;
   fn data_size(&self) -> u64;
 
+  /// Returns the size of the database file in bytes, or of the data for backends
+  /// without a file
+  fn storage_size(&self) -> u64 {
+    self.data_size()
+  }
+
   fn validate_meta(&self) -> crate::Result<()> {
     let meta0 = self.meta0();
     let meta1 = self.meta1();
     if let (Err(error), Err(_)) = (meta0.meta.validate(), meta1.meta.validate()) {
-      return Err(error);
+      return Err(error.with_meta(meta0.meta.diagnose(PgId(0), self.storage_size())));
     }
     Ok(())
   }
@@ -522,6 +882,14 @@ pub(crate) trait DBBackend: Send + Sync {
     if meta_a.validate().is_ok() {
       return meta_a;
     } else if meta_b.validate().is_ok() {
+      #[cfg(feature = "log")]
+      log::warn!(
+        "meta for txid {} is invalid, falling back to txid {} (root {}, freelist {})",
+        meta_a.txid(),
+        meta_b.txid(),
+        meta_b.root().root(),
+        meta_b.free_list()
+      );
       return meta_b;
     }
     panic!("bolt.db.meta: invalid meta page")
@@ -531,7 +899,43 @@ pub(crate) trait DBBackend: Send + Sync {
 
   fn meta1(&self) -> MappedMetaPage;
 
-  fn page<'tx>(&self, pg_id: PgId) -> RefPage<'tx>;
+  /// Returns the txids of both meta pages, whether they are valid or not
+  fn meta_txids(&self) -> [TxId; 2] {
+    [self.meta0().meta.txid(), self.meta1().meta.txid()]
+  }
+
+  /// Returns [Error::FileTruncated] if the database file is too small to hold the pages
+  /// below `high_water`. Backends without a file can't be truncated.
+  fn check_truncated(&self, _high_water: PgId) -> crate::Result<()> {
+    Ok(())
+  }
+
+  /// Validates both meta pages
+  fn meta_status(&self) -> MetaStatus {
+    let meta0 = self.meta0();
+    let meta1 = self.meta1();
+    MetaStatus {
+      txids: [meta0.meta.txid(), meta1.meta.txid()],
+      valid: [meta0.meta.validate().is_ok(), meta1.meta.validate().is_ok()],
+    }
+  }
+
+  /// page returns a page of the database.
+  ///
+  /// Every backend keeps the whole database addressable, either memory mapped or in a
+  /// buffer, so pages are never read into separate buffers. There's nothing for a page
+  /// cache to hold; hot pages of a mapped file stay resident through the OS page cache.
+  ///
+  /// Panics if the page lies past the mapping. See [DBBackend::try_page]
+  fn page<'tx>(&self, pg_id: PgId) -> RefPage<'tx> {
+    match self.try_page(pg_id) {
+      Ok(page) => page,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// Returns a page of the database, or [Error::Corrupted] if it lies past the mapping
+  fn try_page<'tx>(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>>;
 
   /// grow grows the size of the database to the given `size`.
   fn grow(&self, size: u64) -> crate::Result<()>;
@@ -546,6 +950,26 @@ pub(crate) trait DBBackend: Send + Sync {
   fn freelist(&self) -> MutexGuard<Freelist>;
 }
 
+/// Returns page `pg_id` of the `len` mapped bytes at `ptr`.
+///
+/// Returns [Error::Corrupted] instead of reading arbitrary memory if the page lies past
+/// the mapping, e.g. because a corrupt page refers to it or the file was truncated.
+/// Callers bound reads of overflow pages themselves.
+fn mapped_page<'tx>(
+  ptr: *const u8, len: usize, page_size: usize, pg_id: PgId,
+) -> crate::Result<RefPage<'tx>> {
+  let end = pg_id.0.saturating_add(1).saturating_mul(page_size as u64);
+  if end > len as u64 {
+    return Err(Error::Corrupted {
+      pgid: pg_id,
+      reason: Cow::Owned(format!("beyond the {} mapped bytes", len)),
+    });
+  }
+  Ok(RefPage::new(unsafe {
+    ptr.add(pg_id.0 as usize * page_size)
+  }))
+}
+
 struct ClosedBackend {}
 
 impl DBBackend for ClosedBackend {
@@ -565,7 +989,7 @@ impl DBBackend for ClosedBackend {
     unreachable!()
   }
 
-  fn page<'tx>(&self, _pg_id: PgId) -> RefPage<'tx> {
+  fn try_page<'tx>(&self, _pg_id: PgId) -> crate::Result<RefPage<'tx>> {
     unreachable!()
   }
 
@@ -590,7 +1014,7 @@ impl DBBackend for ClosedBackend {
   }
 }
 
-struct MemBackend {
+pub(crate) struct MemBackend {
   mmap: Mutex<AlignedBytes<alignment::Page>>,
   freelist: OnceLock<Mutex<Freelist>>,
   page_size: usize,
@@ -603,6 +1027,35 @@ struct MemBackend {
 unsafe impl Send for MemBackend {}
 unsafe impl Sync for MemBackend {}
 
+impl MemBackend {
+  pub(crate) fn new(
+    mut mmap: AlignedBytes<alignment::Page>, page_size: usize, bolt_options: &BoltOptions,
+  ) -> MemBackend {
+    let file_size = mmap.len() as u64;
+    let data_size = if let Some(initial_mmap_size) = bolt_options.initial_map_size() {
+      file_size.max(initial_mmap_size)
+    } else {
+      file_size
+    };
+    if file_size < data_size {
+      let mut new_mmap = AlignedBytes::new_zeroed(data_size as usize);
+      new_mmap
+        .split_at_mut(file_size as usize)
+        .0
+        .copy_from_slice(&mmap);
+      mmap = new_mmap;
+    }
+    MemBackend {
+      mmap: Mutex::new(mmap),
+      freelist: OnceLock::new(),
+      page_size,
+      alloc_size: DEFAULT_ALLOC_SIZE.bytes() as u64,
+      file_size,
+      data_size,
+    }
+  }
+}
+
 impl DBBackend for MemBackend {
   fn page_size(&self) -> usize {
     self.page_size
@@ -622,10 +1075,9 @@ impl DBBackend for MemBackend {
     unsafe { MappedMetaPage::new(self.mmap.lock().as_ptr().add(self.page_size).cast_mut()) }
   }
 
-  fn page<'tx>(&self, pg_id: PgId) -> RefPage<'tx> {
+  fn try_page<'tx>(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
     let mmap = self.mmap.lock();
-    debug_assert!(((pg_id.0 as usize + 1) * self.page_size) <= mmap.len());
-    unsafe { RefPage::new(mmap.as_ptr().byte_add(pg_id.0 as usize * self.page_size)) }
+    mapped_page(mmap.as_ptr(), mmap.len(), self.page_size, pg_id)
   }
 
   fn grow(&self, size: u64) -> crate::Result<()> {
@@ -682,12 +1134,14 @@ impl DBBackend for MemBackend {
   }
 }
 
+#[cfg(file_backend_supported)]
 struct FileState {
   file: File,
   /// current on disk file size
   file_size: u64,
 }
 
+#[cfg(file_backend_supported)]
 impl Deref for FileState {
   type Target = File;
 
@@ -696,12 +1150,14 @@ impl Deref for FileState {
   }
 }
 
+#[cfg(file_backend_supported)]
 impl DerefMut for FileState {
   fn deref_mut(&mut self) -> &mut Self::Target {
     &mut self.file
   }
 }
 
+#[cfg(file_backend_supported)]
 pub struct FileBackend {
   path: Arc<PathBuf>,
   file: Mutex<FileState>,
@@ -713,8 +1169,11 @@ pub struct FileBackend {
   use_mlock: bool,
   grow_async: bool,
   read_only: bool,
+  /// the sidecar lock file of a writable database opened with one, released on drop
+  _lock_file: Option<LockFile>,
 }
 
+#[cfg(file_backend_supported)]
 impl FileBackend {
   fn invalidate(&mut self) {
     self.data_size = 0;
@@ -755,7 +1214,7 @@ impl FileBackend {
     let meta0_can_read = match Self::get_page_size_from_first_meta(file) {
       Ok(page_size) => return Ok(page_size),
       // We cannot read the page size from page 0, but can read page 0.
-      Err(Error::InvalidDatabase(meta_can_read)) => meta_can_read,
+      Err(Error::InvalidDatabase { meta_can_read, .. }) => meta_can_read,
       Err(e) => return Err(e),
     };
 
@@ -763,7 +1222,7 @@ impl FileBackend {
     let meta1_can_read = match Self::get_page_size_from_second_meta(file) {
       Ok(page_size) => return Ok(page_size),
       // We cannot read the page size from page 1, but can read page 1.
-      Err(Error::InvalidDatabase(meta_can_read)) => meta_can_read,
+      Err(Error::InvalidDatabase { meta_can_read, .. }) => meta_can_read,
       Err(e) => return Err(e),
     };
 
@@ -778,7 +1237,20 @@ impl FileBackend {
     if meta0_can_read || meta1_can_read {
       return Ok(DEFAULT_PAGE_SIZE.bytes() as usize);
     }
-    Err(Error::InvalidDatabase(false))
+    let file_size = file.metadata()?.len();
+    Err(Error::InvalidDatabase {
+      meta_can_read: false,
+      meta: Self::read_first_meta(file).map(|meta| Box::new(meta.diagnose(PgId(0), file_size))),
+    })
+  }
+
+  /// Reads the fields of the first meta page, whatever they contain. None if the file is
+  /// empty or can't be read.
+  fn read_first_meta(file: &mut File) -> Option<Meta> {
+    let mut buffer = [0u8; PAGE_HEADER_SIZE + META_HEADER_SIZE];
+    file.seek(SeekFrom::Start(0)).ok()?;
+    let bw = file.read(&mut buffer).ok()?;
+    (bw > 0).then(|| bytemuck::pod_read_unaligned::<Meta>(&buffer[PAGE_HEADER_SIZE..]))
   }
 
   //TODO: These can be done better
@@ -790,7 +1262,10 @@ impl FileBackend {
     let bw = file
       .seek(SeekFrom::Start(0))
       .and_then(|_| file.read(&mut buffer))
-      .map_err(|_| Error::InvalidDatabase(meta_can_read))?;
+      .map_err(|_| Error::InvalidDatabase {
+        meta_can_read,
+        meta: None,
+      })?;
     if bw == buffer.len() {
       meta_can_read = true;
       if let Some(meta_page) = MappedMetaPage::coerce_ref(&refpage) {
@@ -800,7 +1275,10 @@ impl FileBackend {
         }
       }
     }
-    Err(Error::InvalidDatabase(meta_can_read))
+    Err(Error::InvalidDatabase {
+      meta_can_read,
+      meta: None,
+    })
   }
 
   fn get_page_size_from_second_meta(file: &mut File) -> crate::Result<usize> {
@@ -817,7 +1295,10 @@ impl FileBackend {
       let bw = file
         .seek(SeekFrom::Start(pos))
         .and_then(|_| file.read(&mut buffer))
-        .map_err(|_| Error::InvalidDatabase(meta_can_read))? as u64;
+        .map_err(|_| Error::InvalidDatabase {
+          meta_can_read,
+          meta: None,
+        })? as u64;
       if bw == buffer.len() as u64 || bw == file_size - pos {
         meta_can_read = true;
         if let Some(meta_page) = MappedMetaPage::coerce_ref(&RefPage::new(buffer.as_ptr())) {
@@ -829,7 +1310,10 @@ impl FileBackend {
       // reset the buffer
       buffer.fill(0);
     }
-    Err(Error::InvalidDatabase(meta_can_read))
+    Err(Error::InvalidDatabase {
+      meta_can_read,
+      meta: None,
+    })
   }
 
   pub(crate) fn file_size(&self) -> crate::Result<u64> {
@@ -841,8 +1325,26 @@ impl FileBackend {
     }
     Ok(size)
   }
+
+  /// validate_untrusted checks the meta pages and every reachable page against the file size.
+  /// The pages may hold up to `max_elements` elements.
+  pub(crate) fn validate_untrusted(&self, file_size: u64, max_elements: u64) -> crate::Result<()> {
+    if !(UNTRUSTED_MIN_PAGE_SIZE..=UNTRUSTED_MAX_PAGE_SIZE).contains(&self.page_size) {
+      return Err(Error::InvalidDatabase {
+        meta_can_read: true,
+        meta: None,
+      });
+    }
+    self.validate_meta()?;
+    let mmap = self.mmap.as_ref().unwrap();
+    let len = (file_size as usize).min(mmap.len());
+    // Safe because the mmap covers at least `len` bytes and is only read here
+    let data = unsafe { from_raw_parts(mmap.as_ptr(), len) };
+    UntrustedValidator::validate(data, self.page_size, &self.meta(), max_elements)
+  }
 }
 
+#[cfg(file_backend_supported)]
 impl DBBackend for FileBackend {
   fn page_size(&self) -> usize {
     self.page_size
@@ -852,6 +1354,23 @@ impl DBBackend for FileBackend {
     self.data_size
   }
 
+  fn storage_size(&self) -> u64 {
+    self.file.lock().file_size
+  }
+
+  fn check_truncated(&self, high_water: PgId) -> crate::Result<()> {
+    // The cached file size only changes with our own writes, so ask the file system
+    let file_size = self.file.lock().metadata()?.len();
+    let required = high_water.0 * self.page_size as u64;
+    if file_size < required {
+      return Err(Error::FileTruncated {
+        file_size,
+        required,
+      });
+    }
+    Ok(())
+  }
+
   fn meta0(&self) -> MappedMetaPage {
     self
       .mmap
@@ -868,10 +1387,9 @@ impl DBBackend for FileBackend {
       .unwrap()
   }
 
-  fn page<'tx>(&self, pg_id: PgId) -> RefPage<'tx> {
-    let page_addr = pg_id.0 as usize * self.page_size;
-    let page_ptr = unsafe { self.mmap.as_ref().unwrap().as_ptr().add(page_addr) };
-    RefPage::new(page_ptr)
+  fn try_page<'tx>(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
+    let mmap = self.mmap.as_ref().unwrap();
+    mapped_page(mmap.as_ptr(), mmap.len(), self.page_size, pg_id)
   }
 
   fn grow(&self, mut size: u64) -> crate::Result<()> {
@@ -979,6 +1497,7 @@ impl DBBackend for FileBackend {
   }
 }
 
+#[cfg(file_backend_supported)]
 impl Drop for FileBackend {
   fn drop(&mut self) {
     if !self.read_only {
@@ -1001,18 +1520,30 @@ pub(crate) enum AllocateResult<'tx> {
 pub(crate) trait DbIApi<'tx>: 'tx {
   fn page(&self, pg_id: PgId) -> RefPage<'tx>;
 
+  /// Returns [Error::Corrupted] if the page lies past the mapping. See [DBBackend::try_page]
+  fn try_page(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>>;
+
   fn is_page_free(&self, pg_id: PgId) -> bool;
 
-  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>);
+  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>, lease: Option<Arc<AtomicBool>>);
   fn allocate(&self, tx: TxCell, page_count: u64) -> AllocateResult<'tx>;
 
-  fn free_page(&self, txid: TxId, p: &PageHeader);
+  fn free_page(&self, txid: TxId, p: &PageHeader, context: &'static str) -> crate::Result<()>;
   fn free_pages(&self, state: &mut DbState);
 
   fn freelist_count(&self) -> u64;
 
+  /// See [Freelist::find_freed]
+  fn find_freed(&self, ids: &[PgId]) -> Option<(PgId, bool)>;
+
   fn freelist_copyall(&self, all: &mut BVec<PgId>);
 
+  /// See [Freelist::free_page_ids]
+  fn freelist_free_ids(&self) -> Vec<PgId>;
+
+  /// See [Freelist::pending_page_ids]
+  fn freelist_pending_ids(&self) -> BTreeMap<TxId, Vec<PgId>>;
+
   fn commit_freelist(&self, tx: TxCell<'tx>) -> crate::Result<AllocateResult<'tx>>;
 
   fn write_all_at(&self, buf: &[u8], offset: u64) -> crate::Result<usize>;
@@ -1023,6 +1554,37 @@ pub(crate) trait DbIApi<'tx>: 'tx {
   fn remove_rw_tx(&self, tx_closing_state: TxClosingState, rem_tx: TxId, tx_stats: Arc<TxStats>);
 
   fn grow(&self, size: u64) -> crate::Result<()>;
+
+  #[cfg(feature = "failpoints")]
+  fn fail_point(&self, point: FailPoint);
+
+  fn group_commit(&self) -> Option<Arc<GroupCommit>>;
+
+  /// Appends the encoded changes of the transaction `txid` to the replication log
+  #[cfg(feature = "replication")]
+  fn append_changes(&self, txid: TxId, changes: &[u8]) -> crate::Result<()>;
+
+  /// Marks the changesets up to the transaction `txid` committed once its meta is durable
+  #[cfg(feature = "replication")]
+  fn commit_changes(&self, txid: TxId) -> crate::Result<()>;
+
+  /// Removes the changeset of the transaction `txid` whose meta failed to write
+  #[cfg(feature = "replication")]
+  fn retract_changes(&self, txid: TxId) -> crate::Result<()>;
+
+  /// Passes the encoded changes committed by the transaction `txid` to the change listeners
+  #[cfg(feature = "replication")]
+  fn notify_changes(&self, txid: TxId, changes: &[u8]);
+
+  fn comparators(&self) -> Comparators;
+
+  fn page_checksums(&self) -> bool;
+
+  fn max_key_size(&self) -> usize;
+
+  fn max_value_size(&self) -> usize;
+
+  fn commit_recorder(&self) -> CommitRecorder;
 }
 pub(crate) trait DbMutIApi<'tx>: DbIApi<'tx> {
   fn mmap_to_new_size(&mut self, min_size: u64, tx: TxCell) -> crate::Result<()>;
@@ -1036,6 +1598,13 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
     }
   }
 
+  fn try_page(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
+    match self {
+      LockGuard::R(guard) => guard.try_page(pg_id),
+      LockGuard::U(guard) => guard.borrow().try_page(pg_id),
+    }
+  }
+
   fn is_page_free(&self, pg_id: PgId) -> bool {
     match self {
       LockGuard::R(guard) => guard.is_page_free(pg_id),
@@ -1043,10 +1612,10 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
     }
   }
 
-  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>) {
+  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>, lease: Option<Arc<AtomicBool>>) {
     match self {
-      LockGuard::R(guard) => guard.remove_tx(rem_tx, tx_stats),
-      LockGuard::U(guard) => guard.borrow().remove_tx(rem_tx, tx_stats),
+      LockGuard::R(guard) => guard.remove_tx(rem_tx, tx_stats, lease),
+      LockGuard::U(guard) => guard.borrow().remove_tx(rem_tx, tx_stats, lease),
     }
   }
 
@@ -1057,10 +1626,10 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
     }
   }
 
-  fn free_page(&self, txid: TxId, p: &PageHeader) {
+  fn free_page(&self, txid: TxId, p: &PageHeader, context: &'static str) -> crate::Result<()> {
     match self {
-      LockGuard::R(guard) => guard.free_page(txid, p),
-      LockGuard::U(guard) => guard.borrow().free_page(txid, p),
+      LockGuard::R(guard) => guard.free_page(txid, p, context),
+      LockGuard::U(guard) => guard.borrow().free_page(txid, p, context),
     }
   }
 
@@ -1078,6 +1647,13 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
     }
   }
 
+  fn find_freed(&self, ids: &[PgId]) -> Option<(PgId, bool)> {
+    match self {
+      LockGuard::R(guard) => guard.find_freed(ids),
+      LockGuard::U(guard) => guard.borrow().find_freed(ids),
+    }
+  }
+
   fn freelist_copyall(&self, all: &mut BVec<PgId>) {
     match self {
       LockGuard::R(guard) => guard.freelist_copyall(all),
@@ -1085,6 +1661,20 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
     }
   }
 
+  fn freelist_free_ids(&self) -> Vec<PgId> {
+    match self {
+      LockGuard::R(guard) => guard.freelist_free_ids(),
+      LockGuard::U(guard) => guard.borrow().freelist_free_ids(),
+    }
+  }
+
+  fn freelist_pending_ids(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    match self {
+      LockGuard::R(guard) => guard.freelist_pending_ids(),
+      LockGuard::U(guard) => guard.borrow().freelist_pending_ids(),
+    }
+  }
+
   fn commit_freelist(&self, tx: TxCell<'tx>) -> crate::Result<AllocateResult<'tx>> {
     match self {
       LockGuard::R(guard) => guard.commit_freelist(tx),
@@ -1128,64 +1718,231 @@ impl<'tx> DbIApi<'tx> for LockGuard<'tx, DbShared> {
       LockGuard::U(guard) => guard.borrow().grow(size),
     }
   }
-}
 
-// In theory things are wired up ok. Here's hoping Miri is happy
-pub struct DbShared {
-  pub(crate) stats: Arc<DbStats>,
-  pub(crate) db_state: Arc<Mutex<DbState>>,
-  page_pool: Mutex<Vec<AlignedBytes<alignment::Page>>>,
-  pub(crate) backend: Box<dyn DBBackend>,
-  pub(crate) options: BoltOptions,
-}
+  #[cfg(feature = "failpoints")]
+  fn fail_point(&self, point: FailPoint) {
+    match self {
+      LockGuard::R(guard) => guard.fail_point(point),
+      LockGuard::U(guard) => guard.borrow().fail_point(point),
+    }
+  }
 
-// Safe because this is all protected by RwLock
-unsafe impl Sync for DbShared {}
-unsafe impl Send for DbShared {}
+  fn group_commit(&self) -> Option<Arc<GroupCommit>> {
+    match self {
+      LockGuard::R(guard) => guard.group_commit(),
+      LockGuard::U(guard) => guard.borrow().group_commit(),
+    }
+  }
 
-impl<'tx> DbIApi<'tx> for DbShared {
-  fn page(&self, pg_id: PgId) -> RefPage<'tx> {
-    self.backend.page(pg_id)
+  #[cfg(feature = "replication")]
+  fn append_changes(&self, txid: TxId, changes: &[u8]) -> crate::Result<()> {
+    match self {
+      LockGuard::R(guard) => guard.append_changes(txid, changes),
+      LockGuard::U(guard) => guard.borrow().append_changes(txid, changes),
+    }
   }
 
-  fn is_page_free(&self, pg_id: PgId) -> bool {
-    self.backend.freelist().freed(pg_id)
+  #[cfg(feature = "replication")]
+  fn commit_changes(&self, txid: TxId) -> crate::Result<()> {
+    match self {
+      LockGuard::R(guard) => guard.commit_changes(txid),
+      LockGuard::U(guard) => guard.borrow().commit_changes(txid),
+    }
   }
 
-  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>) {
-    let mut records = self.db_state.lock();
-    if let Some(pos) = records.txs.iter().position(|tx| *tx == rem_tx) {
-      records.txs.swap_remove(pos);
+  #[cfg(feature = "replication")]
+  fn retract_changes(&self, txid: TxId) -> crate::Result<()> {
+    match self {
+      LockGuard::R(guard) => guard.retract_changes(txid),
+      LockGuard::U(guard) => guard.borrow().retract_changes(txid),
     }
+  }
 
-    let n = records.txs.len();
-    self.stats.open_tx_n.store(n as i64, Ordering::Release);
-    self.stats.tx_stats.add_assign(&tx_stats);
+  #[cfg(feature = "replication")]
+  fn notify_changes(&self, txid: TxId, changes: &[u8]) {
+    match self {
+      LockGuard::R(guard) => guard.notify_changes(txid, changes),
+      LockGuard::U(guard) => guard.borrow().notify_changes(txid, changes),
+    }
   }
 
-  fn allocate(&self, tx: TxCell, page_count: u64) -> AllocateResult<'tx> {
-    let tx_id = tx.api_id();
-    let high_water = tx.meta().pgid();
-    let bytes = if page_count == 1 && !self.page_pool.lock().is_empty() {
-      let mut page = self.page_pool.lock().pop().unwrap();
-      page.fill(0);
-      page
+  fn commit_recorder(&self) -> CommitRecorder {
+    match self {
+      LockGuard::R(guard) => guard.commit_recorder(),
+      LockGuard::U(guard) => guard.borrow().commit_recorder(),
+    }
+  }
+
+  fn comparators(&self) -> Comparators {
+    match self {
+      LockGuard::R(guard) => guard.comparators(),
+      LockGuard::U(guard) => guard.borrow().comparators(),
+    }
+  }
+
+  fn page_checksums(&self) -> bool {
+    match self {
+      LockGuard::R(guard) => guard.page_checksums(),
+      LockGuard::U(guard) => guard.borrow().page_checksums(),
+    }
+  }
+
+  fn max_key_size(&self) -> usize {
+    match self {
+      LockGuard::R(guard) => guard.max_key_size(),
+      LockGuard::U(guard) => guard.borrow().max_key_size(),
+    }
+  }
+
+  fn max_value_size(&self) -> usize {
+    match self {
+      LockGuard::R(guard) => guard.max_value_size(),
+      LockGuard::U(guard) => guard.borrow().max_value_size(),
+    }
+  }
+}
+
+type SlowCommitFn = dyn Fn(Duration, &TxStats) + Send + Sync;
+
+/// The callback registered with `BoltOptionsBuilder::on_slow_commit`
+#[derive(Clone)]
+pub struct SlowCommit {
+  threshold: Duration,
+  callback: Arc<SlowCommitFn>,
+}
+
+impl Debug for SlowCommit {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("SlowCommit")
+      .field("threshold", &self.threshold)
+      .finish()
+  }
+}
+
+impl PartialEq for SlowCommit {
+  fn eq(&self, other: &Self) -> bool {
+    self.threshold == other.threshold && Arc::ptr_eq(&self.callback, &other.callback)
+  }
+}
+
+impl Eq for SlowCommit {}
+
+/// Records commit durations in [DbStats::commit_latency] and reports slow commits
+pub(crate) struct CommitRecorder {
+  stats: Arc<DbStats>,
+  on_slow_commit: Option<SlowCommit>,
+}
+
+impl CommitRecorder {
+  pub(crate) fn record(&self, elapsed: Duration, tx_stats: &TxStats) {
+    self.stats.commit_latency.record(elapsed);
+    if let Some(slow_commit) = &self.on_slow_commit {
+      if elapsed >= slow_commit.threshold {
+        (slow_commit.callback)(elapsed, tx_stats);
+      }
+    }
+  }
+}
+
+// In theory things are wired up ok. Here's hoping Miri is happy
+pub struct DbShared {
+  pub(crate) stats: Arc<DbStats>,
+  pub(crate) db_state: Arc<Mutex<DbState>>,
+  page_pool: Mutex<Vec<AlignedBytes<alignment::Page>>>,
+  pub(crate) backend: Box<dyn DBBackend>,
+  pub(crate) options: BoltOptions,
+  #[cfg(feature = "failpoints")]
+  pub(crate) failpoints: Arc<FailPoints>,
+  pub(crate) group_commit: Option<Arc<GroupCommit>>,
+  #[cfg(feature = "replication")]
+  replication_log: Option<Mutex<ReplicationLog>>,
+  /// called with the changeset of every commit. See [Bolt::add_change_listener]
+  #[cfg(feature = "replication")]
+  change_listeners: Mutex<Vec<Weak<ChangeListener>>>,
+  /// the txids of both meta pages when the database was opened
+  opened_meta_txids: [TxId; 2],
+  /// whether the newest meta page was invalid when the database was opened
+  recovered_from_backup_meta: bool,
+}
+
+// Safe because this is all protected by RwLock
+unsafe impl Sync for DbShared {}
+unsafe impl Send for DbShared {}
+
+impl<'tx> DbIApi<'tx> for DbShared {
+  fn page(&self, pg_id: PgId) -> RefPage<'tx> {
+    self.backend.page(pg_id)
+  }
+
+  fn try_page(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
+    self.backend.try_page(pg_id)
+  }
+
+  fn is_page_free(&self, pg_id: PgId) -> bool {
+    self.backend.freelist().freed(pg_id)
+  }
+
+  fn remove_tx(&self, rem_tx: TxId, tx_stats: Arc<TxStats>, lease: Option<Arc<AtomicBool>>) {
+    let mut records = self.db_state.lock();
+    // Evicted transactions were already removed
+    let evicted = match lease {
+      Some(lease) => {
+        records.leases.retain(|l| !Arc::ptr_eq(&l.evicted, &lease));
+        lease.load(Ordering::Acquire)
+      }
+      None => false,
+    };
+    if !evicted {
+      if let Some(pos) = records.txs.iter().position(|tx| *tx == rem_tx) {
+        records.txs.swap_remove(pos);
+      }
+      self.stats.remove_reader(rem_tx);
+    }
+
+    let n = records.txs.len();
+    self.stats.open_tx_n.store(n as i64, Ordering::Relaxed);
+    self.stats.tx_stats.add_assign(&tx_stats);
+  }
+
+  fn allocate(&self, tx: TxCell, page_count: u64) -> AllocateResult<'tx> {
+    let tx_id = tx.api_id();
+    let high_water = tx.meta().pgid();
+    let bytes = if page_count == 1 && !self.page_pool.lock().is_empty() {
+      let mut page = self.page_pool.lock().pop().unwrap();
+      page.fill(0);
+      page
     } else {
       AlignedBytes::new_zeroed(page_count as usize * self.backend.page_size())
     };
 
+    let (allocated, fallback) = {
+      let mut freelist = self.backend.freelist();
+      let allocated = freelist.allocate(tx_id, page_count);
+      let fallback = allocated.is_none() && page_count > 1 && freelist.free_count() >= page_count;
+      (allocated, fallback)
+    };
+
     //TODO: This should reside in tx.allocate
     {
       let tx = tx.cell.borrow();
       let stats = tx.r.stats.as_ref().unwrap();
       stats.inc_page_count(page_count as i64);
       stats.inc_page_alloc((page_count * tx.r.meta.page_size() as u64) as i64);
+      stats.max_assign_alloc_request(page_count as i64);
+      if allocated.is_some() {
+        stats.inc_reused_page_n(page_count as i64);
+      } else {
+        stats.inc_grown_page_n(page_count as i64);
+      }
+      if fallback {
+        stats.inc_alloc_fallback_n(1);
+      }
     }
 
     let mut mut_page = SelfOwned::new_with_map(bytes, |b| MutPage::new(b.as_mut_ptr()));
     mut_page.overflow = (page_count - 1) as u32;
 
-    if let Some(pid) = self.backend.freelist().allocate(tx_id, page_count) {
+    if let Some(pid) = allocated {
       mut_page.id = pid;
       return AllocateResult::Page(mut_page);
     }
@@ -1201,11 +1958,32 @@ impl<'tx> DbIApi<'tx> for DbShared {
     }
   }
 
-  fn free_page(&self, txid: TxId, p: &PageHeader) {
-    self.backend.freelist().free(txid, p)
+  fn free_page(&self, txid: TxId, p: &PageHeader, context: &'static str) -> crate::Result<()> {
+    self.backend.freelist().free(txid, p, context)
   }
 
   fn free_pages(&self, state: &mut DbState) {
+    if let Some(max_age) = self.options.reader_max_age() {
+      let now = Instant::now();
+      let DbState { txs, leases, .. } = state;
+      leases.retain(|lease| {
+        if now.duration_since(lease.begin) < max_age {
+          return true;
+        }
+        lease.evicted.store(true, Ordering::Release);
+        if let Some(pos) = txs.iter().position(|tx| *tx == lease.txid) {
+          txs.swap_remove(pos);
+        }
+        self.stats.remove_reader(lease.txid);
+        self.stats.inc_evicted_tx_n(1);
+        false
+      });
+      self
+        .stats
+        .open_tx_n
+        .store(txs.len() as i64, Ordering::Relaxed);
+    }
+
     let mut freelist = self.backend.freelist();
     // Free all pending pages prior to earliest open transaction.
 
@@ -1214,16 +1992,23 @@ impl<'tx> DbIApi<'tx> for DbShared {
     if !state.txs.is_empty() {
       min_id = *state.txs.first().unwrap();
     }
+    // With group commit pages freed by a transaction stay reachable from the
+    // durable meta until that transaction is durable.
+    let durable = self
+      .group_commit
+      .as_ref()
+      .map(|group| group.durable_txid())
+      .unwrap_or(TxId(0xFFFFFFFFFFFFFFFF));
     if min_id.0 > 0 {
-      freelist.release(min_id - 1);
+      freelist.release((min_id - 1).min(durable));
     }
 
     // Release unused txid extents.
     for t in &state.txs {
-      freelist.release_range(min_id, *t - 1);
+      freelist.release_range(min_id, (*t - 1).min(durable));
       min_id = *t + 1;
     }
-    freelist.release_range(min_id, TxId(0xFFFFFFFFFFFFFFFF));
+    freelist.release_range(min_id, durable);
     // Any page both allocated and freed in an extent is safe to release.
   }
 
@@ -1231,10 +2016,22 @@ impl<'tx> DbIApi<'tx> for DbShared {
     self.backend.freelist().count()
   }
 
+  fn find_freed(&self, ids: &[PgId]) -> Option<(PgId, bool)> {
+    self.backend.freelist().find_freed(ids)
+  }
+
   fn freelist_copyall(&self, all: &mut BVec<PgId>) {
     self.backend.freelist().copy_all(all)
   }
 
+  fn freelist_free_ids(&self) -> Vec<PgId> {
+    self.backend.freelist().free_page_ids()
+  }
+
+  fn freelist_pending_ids(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self.backend.freelist().pending_page_ids()
+  }
+
   fn commit_freelist(&self, tx: TxCell<'tx>) -> crate::Result<AllocateResult<'tx>> {
     // Allocate new pages for the new free list. This will overestimate
     // the size of the freelist but not underestimate the size (which would be bad).
@@ -1260,10 +2057,22 @@ impl<'tx> DbIApi<'tx> for DbShared {
   }
 
   fn write_all_at(&self, buf: &[u8], offset: u64) -> crate::Result<usize> {
+    #[cfg(feature = "failpoints")]
+    if let Some(n) = self.failpoints.take_short_write() {
+      self
+        .backend
+        .write_all_at(&buf[..n.min(buf.len())], offset)?;
+      return Err(Error::IO(std::io::Error::new(
+        std::io::ErrorKind::WriteZero,
+        "failpoint: short write",
+      )));
+    }
     self.backend.write_all_at(buf, offset)
   }
 
   fn fsync(&self) -> crate::Result<()> {
+    #[cfg(feature = "failpoints")]
+    self.failpoints.take_fsync_error()?;
     self.backend.fsync()
   }
 
@@ -1276,37 +2085,199 @@ impl<'tx> DbIApi<'tx> for DbShared {
 
     let page_size = self.backend.page_size();
     let mut freelist = self.backend.freelist();
+    // Committed transactions waiting for a group commit are not on disk yet
+    let new_meta = self
+      .group_commit
+      .as_ref()
+      .and_then(|group| group.latest_meta())
+      .unwrap_or_else(|| self.backend.meta());
     if tx_closing_state.is_rollback() {
       freelist.rollback(rem_tx);
-      if tx_closing_state.is_physical_rollback() {
-        let freelist_page_id = self.backend.meta().free_list();
-        let freelist_page_ref = self.backend.page(freelist_page_id);
-        let freelist_page = MappedFreeListPage::coerce_ref(&freelist_page_ref).unwrap();
-        freelist.reload(freelist_page);
-      }
+      // Pages allocated by the transaction are only returned by rereading the freelist
+      let freelist_page_id = new_meta.free_list();
+      let freelist_page_ref = self.backend.page(freelist_page_id);
+      let freelist_page = MappedFreeListPage::coerce_ref(&freelist_page_ref).unwrap();
+      freelist.reload(freelist_page);
     }
 
     let free_list_free_n = freelist.free_count();
     let free_list_pending_n = freelist.pending_count();
     let free_list_alloc = freelist.size();
+    let free_span_n = freelist.span_count();
+    let max_free_span = freelist.max_span();
+
+    let mut readers = state.txs.clone();
+    readers.sort();
+    let mut pins: Vec<ReaderPin> = Vec::new();
+    for txid in readers {
+      match pins.last_mut() {
+        Some(pin) if pin.txid == txid => pin.readers += 1,
+        _ => pins.push(ReaderPin {
+          txid,
+          readers: 1,
+          blocked_pages: 0,
+        }),
+      }
+    }
+    let txids = pins.iter().map(|pin| pin.txid).collect::<Vec<_>>();
+    for (pin, blocked) in pins.iter_mut().zip(freelist.pending_by_reader(&txids)) {
+      pin.blocked_pages = blocked;
+    }
+    pins.retain(|pin| pin.blocked_pages > 0);
 
-    let new_meta = self.backend.meta();
-    state.current_meta = new_meta;
+    // With group commit the meta is published once it's synced. See [GroupCommit::flush]
+    if self.group_commit.is_none() {
+      state.current_meta = new_meta;
+    }
 
     state.rwtx = None;
 
     self.stats.set_free_page_n(free_list_free_n as i64);
     self.stats.set_pending_page_n(free_list_pending_n as i64);
+    self.stats.set_pending_by_reader(pins);
     self
       .stats
       .set_free_alloc(((free_list_free_n + free_list_pending_n) * page_size as u64) as i64);
     self.stats.set_free_list_in_use(free_list_alloc as i64);
+    self.stats.set_free_span_n(free_span_n as i64);
+    self.stats.set_max_free_span(max_free_span as i64);
     self.stats.tx_stats.add_assign(&tx_stats);
   }
 
   fn grow(&self, size: u64) -> crate::Result<()> {
     self.backend.grow(size)
   }
+
+  #[cfg(feature = "failpoints")]
+  fn fail_point(&self, point: FailPoint) {
+    self.failpoints.hit(point);
+  }
+
+  fn group_commit(&self) -> Option<Arc<GroupCommit>> {
+    self.group_commit.clone()
+  }
+
+  #[cfg(feature = "replication")]
+  fn append_changes(&self, txid: TxId, changes: &[u8]) -> crate::Result<()> {
+    match &self.replication_log {
+      Some(log) => log.lock().append(txid, changes, !self.options.no_sync()),
+      None => Ok(()),
+    }
+  }
+
+  #[cfg(feature = "replication")]
+  fn commit_changes(&self, txid: TxId) -> crate::Result<()> {
+    match &self.replication_log {
+      Some(log) => log.lock().commit(txid),
+      None => Ok(()),
+    }
+  }
+
+  #[cfg(feature = "replication")]
+  fn retract_changes(&self, txid: TxId) -> crate::Result<()> {
+    match &self.replication_log {
+      Some(log) => log.lock().retract(txid),
+      None => Ok(()),
+    }
+  }
+
+  #[cfg(feature = "replication")]
+  fn notify_changes(&self, txid: TxId, changes: &[u8]) {
+    let listeners = {
+      let mut listeners = self.change_listeners.lock();
+      listeners.retain(|listener| listener.strong_count() > 0);
+      listeners
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>()
+    };
+    if listeners.is_empty() {
+      return;
+    }
+    // The changes were encoded by the committing transaction
+    let changeset = Changeset::decode(txid, changes).unwrap();
+    for listener in listeners {
+      listener(&changeset);
+    }
+  }
+
+  fn comparators(&self) -> Comparators {
+    self.options.comparators().clone()
+  }
+
+  fn page_checksums(&self) -> bool {
+    self.options.page_checksums()
+  }
+
+  fn max_key_size(&self) -> usize {
+    self.options.max_key_size()
+  }
+
+  fn max_value_size(&self) -> usize {
+    self.options.max_value_size()
+  }
+
+  fn commit_recorder(&self) -> CommitRecorder {
+    CommitRecorder {
+      stats: self.stats.clone(),
+      on_slow_commit: self.options.on_slow_commit().cloned(),
+    }
+  }
+}
+
+impl DbShared {
+  /// Returns true if write transactions have to record their changes for change listeners
+  #[cfg(feature = "replication")]
+  pub(crate) fn has_change_listeners(&self) -> bool {
+    !self.change_listeners.lock().is_empty()
+  }
+
+  /// Returns the meta the next write transaction builds on. With group commit it is the
+  /// newest committed meta, which may not be synced yet.
+  fn writer_meta(&self, state: &DbState) -> crate::Result<Meta> {
+    match &self.group_commit {
+      Some(group) => {
+        group.check_writable()?;
+        Ok(group.latest_meta().unwrap_or(state.current_meta))
+      }
+      None => Ok(state.current_meta),
+    }
+  }
+
+  /// Returns the version recorded by a write transaction on a `version` meta. The version
+  /// is only raised, pages written with a feature stay when it's disabled again.
+  fn writer_version(&self, version: u32) -> u32 {
+    #[cfg(feature = "prefix-branch")]
+    let prefix_branch = self.options.prefix_branch_keys();
+    #[cfg(not(feature = "prefix-branch"))]
+    let prefix_branch = false;
+    version_with(
+      has_prefix_branch(version) || prefix_branch,
+      has_page_checksums(version) || self.options.page_checksums(),
+    )
+  }
+
+  /// Returns [Error::WriterDetected] if the database is read-only and a meta page changed
+  /// since it was opened. Pages of the open snapshot may have been reused by the writer.
+  /// A pinned snapshot expects the writer to go on, its pages are kept by the lease.
+  fn check_read_only(&self) -> crate::Result<()> {
+    if self.options.read_only()
+      && self.options.pinned_meta().is_none()
+      && self.backend.meta_txids() != self.opened_meta_txids
+    {
+      return Err(Error::WriterDetected);
+    }
+    Ok(())
+  }
+
+  /// Returns [Error::FileTruncated] if truncation detection is enabled and the file is too
+  /// small for `meta`
+  fn check_truncated(&self, meta: &Meta) -> crate::Result<()> {
+    if self.options.detect_truncation() {
+      self.backend.check_truncated(meta.pgid())?;
+    }
+    Ok(())
+  }
 }
 
 impl<'tx> DbMutIApi<'tx> for DbShared {
@@ -1325,9 +2296,9 @@ pub struct BoltOptions {
     default,
     setter(
       strip_option,
-      skip,
       doc = "Timeout is the amount of time to wait to obtain a file lock. \
-    When set to zero it will wait indefinitely."
+    When set to zero it will wait indefinitely. Opening fails with Error::Timeout \
+    once it has passed."
     )
   )]
   timeout: Option<Duration>,
@@ -1402,20 +2373,163 @@ pub struct BoltOptions {
     )
   )]
   max_batch_delay: Option<Duration>,
+  #[builder(
+    default,
+    setter(
+      doc = "Selects how the freelist picks a free span when no span of the \
+    exact requested size exists. Defaults to first-fit."
+    )
+  )]
+  freelist_alloc_strategy: FreelistAllocStrategy,
+  #[builder(setter(
+    strip_bool,
+    doc = "Prefer reusing free pages with the lowest page ids. Keeps data packed \
+    towards the start of the file so free runs at the end can coalesce."
+  ))]
+  freelist_prefer_low_pgid: bool,
+  #[builder(setter(
+    strip_bool,
+    doc = "Validates every reachable page of the database file before it is used. \
+    Page headers, element offsets, key/value bounds and overflow counts are checked \
+    against the file size, and tree depth is limited. Use this when opening files \
+    from untrusted sources."
+  ))]
+  untrusted: bool,
+  #[builder(
+    default,
+    setter(
+      strip_option,
+      doc = "The maximum number of page elements an untrusted database may hold, keys, \
+    branch entries and free page ids altogether. Validation stops with Error::Corrupted \
+    before reading more. Defaults to 2^32."
+    )
+  )]
+  untrusted_max_elements: Option<u64>,
+  #[builder(setter(
+    strip_bool,
+    doc = "Enables group commit. Committing transactions write their pages without \
+    syncing and wait for a single fsync shared by all transactions committed in the \
+    meantime. Commits still return only once they are durable, in commit order. \
+    If the fsync fails, every transaction waiting for it fails and write transactions \
+    fail until the database is reopened."
+  ))]
+  group_commit: bool,
+  #[builder(
+    default,
+    setter(
+      doc = "Registers the key comparators available to buckets created with \
+    a custom comparator. Every comparator recorded in the database must be registered."
+    )
+  )]
+  comparators: Comparators,
+  #[builder(
+    default,
+    setter(
+      transform = |threshold: Duration, callback: impl Fn(Duration, &TxStats) + Send + Sync + 'static| {
+        Some(SlowCommit { threshold, callback: Arc::new(callback) })
+      },
+      doc = "Calls `callback` with the duration and the transaction's stats of every commit \
+    taking at least `threshold`, including the time spent waiting for fsync."
+    )
+  )]
+  on_slow_commit: Option<SlowCommit>,
+  #[builder(
+    default,
+    setter(
+      doc = "Selects what happens when a read-write transaction is dropped without \
+    calling commit or rollback. Use it to catch forgotten commits during development. \
+    Defaults to rolling back silently."
+    )
+  )]
+  tx_drop_policy: TxDropPolicy,
+  #[cfg(feature = "prefix-branch")]
+  #[builder(setter(
+    strip_bool,
+    doc = "Stores the common key prefix of each branch page once. Reduces the tree height \
+    for long keys sharing a prefix. The first write transaction upgrades the database \
+    version, after which it can only be opened with the `prefix-branch` feature."
+  ))]
+  prefix_branch_keys: bool,
+  #[builder(setter(
+    strip_bool,
+    doc = "Holds an advisory lock on a sidecar lock file next to the database file while it \
+    is open writable. The file records the pid, start time and host name of the owner. \
+    Opening the database fails with Error::DatabaseLocked instead of waiting while the lock \
+    is held. The operating system releases the lock when the owner exits, crashed or not. \
+    Network file systems may not, so the recorded owner decides: a lock left by an owner on \
+    the same host whose process is gone, or whose pid now belongs to a process started at \
+    another time, is taken over. Owner processes are only checked on Linux. A lock left by a \
+    crashed owner of another host needs [Bolt::force_unlock]"
+  ))]
+  lock_file: bool,
+  #[builder(setter(
+    strip_bool,
+    doc = "Checks the size of the database file whenever a transaction begins. If another \
+    process truncated the file below the pages the database uses, the transaction fails with \
+    Error::FileTruncated instead of the process crashing with SIGBUS on reading a missing \
+    page. Costs a file system call per transaction."
+  ))]
+  detect_truncation: bool,
+  #[builder(setter(
+    strip_bool,
+    doc = "Ends every branch and leaf page written with a checksum of the page and its \
+    overflow pages, which is verified whenever the page is read. A page damaged on disk fails \
+    with Error::Corrupted naming it instead of causing errors elsewhere. Pages need 8 more \
+    bytes and reads hash every page. The meta records format version 4, or 5 with prefix \
+    compressed branch pages, so Go Bolt and older builds refuse the database. \
+    [crate::TxCheck] reports damaged pages."
+  ))]
+  page_checksums: bool,
+  #[builder(
+    default,
+    setter(
+      strip_option,
+      doc = "Lowers the maximum length of a key, in bytes. Puts of longer keys fail with \
+    Error::KeyTooLarge. Limits above the built-in maximum of 32768 bytes have no effect."
+    )
+  )]
+  max_key_size: Option<usize>,
+  #[builder(
+    default,
+    setter(
+      strip_option,
+      doc = "Lowers the maximum length of a value, in bytes. Puts of longer values fail with \
+    Error::ValueTooLarge. Limits above the built-in maximum of 2 GiB have no effect."
+    )
+  )]
+  max_value_size: Option<usize>,
+  #[cfg(feature = "replication")]
+  #[builder(
+    default,
+    setter(
+      strip_option,
+      into,
+      doc = "Appends the changes of every committed transaction to the replication log at \
+    this path. See [crate::replication]"
+    )
+  )]
+  replication_log: Option<PathBuf>,
+  /// See [BoltOptions::evict_readers_after]
+  #[builder(default, setter(skip))]
+  evict_readers_after: Option<Duration>,
+  /// The meta of a snapshot pinned by another process. See [Bolt::open_shared]
+  #[builder(default, setter(skip))]
+  pinned_meta: Option<Meta>,
   #[builder(default = false, setter(skip))]
   /// Open database in read-only mode. Uses flock(..., LOCK_SH |LOCK_NB) to
   /// grab a shared lock (UNIX).
   read_only: bool,
 }
 
+// Options only used by the file backend are unused when it is not available
+#[cfg_attr(not(file_backend_supported), allow(dead_code))]
 impl BoltOptions {
   #[inline]
   pub(crate) fn timeout(&self) -> Option<Duration> {
-    if cfg!(timeout_supported) {
-      self.timeout
-    } else {
-      None
-    }
+    #[cfg(timeout_supported)]
+    return self.timeout;
+    #[cfg(not(timeout_supported))]
+    None
   }
 
   #[inline]
@@ -1454,11 +2568,10 @@ impl BoltOptions {
 
   #[inline]
   pub(crate) fn mlock(&self) -> bool {
-    if cfg!(mlock_supported) {
-      self.mlock
-    } else {
-      false
-    }
+    #[cfg(mlock_supported)]
+    return self.mlock;
+    #[cfg(not(mlock_supported))]
+    false
   }
 
   #[inline]
@@ -1466,23 +2579,234 @@ impl BoltOptions {
     self.read_only
   }
 
+  #[inline]
+  pub(crate) fn freelist_alloc_strategy(&self) -> FreelistAllocStrategy {
+    self.freelist_alloc_strategy
+  }
+
+  #[inline]
+  pub(crate) fn freelist_prefer_low_pgid(&self) -> bool {
+    self.freelist_prefer_low_pgid
+  }
+
+  #[inline]
+  pub(crate) fn untrusted(&self) -> bool {
+    self.untrusted
+  }
+
+  #[cfg(file_backend_supported)]
+  #[inline]
+  pub(crate) fn untrusted_max_elements(&self) -> u64 {
+    self
+      .untrusted_max_elements
+      .unwrap_or(UNTRUSTED_MAX_ELEMENTS)
+  }
+
+  #[inline]
+  pub(crate) fn group_commit(&self) -> bool {
+    self.group_commit
+  }
+
+  #[inline]
+  pub(crate) fn comparators(&self) -> &Comparators {
+    &self.comparators
+  }
+
+  #[inline]
+  pub(crate) fn on_slow_commit(&self) -> Option<&SlowCommit> {
+    self.on_slow_commit.as_ref()
+  }
+
+  #[inline]
+  pub(crate) fn tx_drop_policy(&self) -> TxDropPolicy {
+    self.tx_drop_policy
+  }
+
+  #[cfg(feature = "prefix-branch")]
+  #[inline]
+  pub(crate) fn prefix_branch_keys(&self) -> bool {
+    self.prefix_branch_keys
+  }
+
+  #[inline]
+  pub(crate) fn lock_file(&self) -> bool {
+    self.lock_file
+  }
+
+  #[inline]
+  pub(crate) fn detect_truncation(&self) -> bool {
+    self.detect_truncation
+  }
+
+  #[inline]
+  pub(crate) fn reader_max_age(&self) -> Option<Duration> {
+    self.evict_readers_after
+  }
+
+  /// Evicts read transactions older than `age` whenever a write transaction begins, so a
+  /// reader that is never closed can't keep the pages it reaches from being reused and
+  /// grow the database without bound. An evicted transaction fails with
+  /// [Error::TxEvicted] on its next page access. Evicted transactions still hold the mmap,
+  /// so they keep blocking writers that need to grow it.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::time::Duration;
+  ///
+  /// fn main() -> Result<()> {
+  ///   // Safety: no read transaction is used for longer than a minute
+  ///   let options = unsafe { BoltOptions::default().evict_readers_after(Duration::from_secs(60)) };
+  ///   let db = options.open_mem()?;
+  ///   db.view(|tx| {
+  ///     assert!(tx.bucket("widgets").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///   Ok(())
+  /// }
+  /// ```
+  ///
+  /// # Safety
+  ///
+  /// The pages of an evicted transaction are reused by later write transactions while the
+  /// keys and values it returned still point into them, and a page access already past the
+  /// eviction check can read a page while it's overwritten. The caller must guarantee that
+  /// no read transaction, nor anything borrowed from it, is used once it's older than
+  /// `age`. Eviction only reclaims readers that were leaked.
+  pub unsafe fn evict_readers_after(mut self, age: Duration) -> BoltOptions {
+    self.evict_readers_after = Some(age);
+    self
+  }
+
+  #[inline]
+  pub(crate) fn pinned_meta(&self) -> Option<Meta> {
+    self.pinned_meta
+  }
+
+  #[inline]
+  pub(crate) fn page_checksums(&self) -> bool {
+    self.page_checksums
+  }
+
+  #[inline]
+  pub(crate) fn max_key_size(&self) -> usize {
+    let max = MAX_KEY_SIZE as usize;
+    self.max_key_size.map_or(max, |size| size.min(max))
+  }
+
+  #[inline]
+  pub(crate) fn max_value_size(&self) -> usize {
+    let max = MAX_VALUE_SIZE as usize;
+    self.max_value_size.map_or(max, |size| size.min(max))
+  }
+
+  #[cfg(feature = "replication")]
+  #[inline]
+  pub(crate) fn replication_log(&self) -> Option<&PathBuf> {
+    self.replication_log.as_ref()
+  }
+
   /// Open creates and opens a database at the given path.
   /// If the file does not exist then it will be created automatically.
+  #[cfg(file_backend_supported)]
   pub fn open<T: AsRef<Path>>(self, path: T) -> crate::Result<Bolt> {
     Bolt::open_path(path, self)
   }
 
   /// Opens a database as read-only at the given path.
-  /// If the file does not exist then it will be created automatically.
-  pub fn open_ro<T: AsRef<Path>>(mut self, path: T) -> crate::Result<impl DbApi> {
+  ///
+  /// Any number of processes can open a database read-only at the same time. Opening it
+  /// writable waits until every reader has closed it, and opening it read-only waits while a
+  /// writer has it open. Set the `timeout` option to fail with [Error::Timeout] instead of
+  /// waiting indefinitely. Transactions of a read-only database fail with
+  /// [Error::WriterDetected] if the file is written anyway, e.g. on a file system that
+  /// ignores file locks.
+  #[cfg(file_backend_supported)]
+  pub fn open_ro<T: AsRef<Path>>(self, path: T) -> crate::Result<impl DbApi> {
+    self.open_ro_bolt(path)
+  }
+
+  #[cfg(file_backend_supported)]
+  pub(crate) fn open_ro_bolt<T: AsRef<Path>>(mut self, path: T) -> crate::Result<Bolt> {
     self.read_only = true;
     Bolt::open_path(path, self)
   }
 
+  /// Opens the database at `path` read-only as of `meta`, a snapshot pinned by another
+  /// process. See [Bolt::open_shared]
+  #[cfg(file_backend_supported)]
+  pub(crate) fn open_pinned_bolt<T: AsRef<Path>>(
+    mut self, path: T, meta: Meta,
+  ) -> crate::Result<Bolt> {
+    self.pinned_meta = Some(meta);
+    self.open_ro_bolt(path)
+  }
+
   /// Opens an in-memory database
   pub fn open_mem(self) -> crate::Result<Bolt> {
     Bolt::new_mem_with_options(self)
   }
+
+  /// Opens an in-memory database that records every page write in a [SimLog]
+  #[cfg(feature = "sim")]
+  pub fn open_sim(self) -> crate::Result<(Bolt, SimLog)> {
+    Bolt::new_sim_with_options(self)
+  }
+
+  /// Opens a memory backed database that persists its pages to a [PageStore]
+  #[cfg(feature = "page-store")]
+  pub fn open_store(self, store: Arc<dyn PageStore>) -> crate::Result<Bolt> {
+    Bolt::new_store_with_options(store, self)
+  }
+}
+
+/// Write transaction options
+#[derive(Clone, Default, Debug, PartialEq, Eq, TypedBuilder)]
+pub struct TxRwOptions {
+  #[builder(
+    default,
+    setter(
+      strip_option,
+      doc = "timeout is the maximum lifetime of the transaction. Once it has passed \
+    all further mutations and the commit fail with Error::TxTimeout."
+    )
+  )]
+  timeout: Option<Duration>,
+}
+
+/// Deadline is the time budget of [DbApi::view_deadline] and [DbRwAPI::update_deadline],
+/// passed to their functions to poll
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Deadline {
+  instant: Instant,
+}
+
+impl Deadline {
+  pub(crate) fn new(instant: Instant) -> Deadline {
+    Deadline { instant }
+  }
+
+  /// The instant the budget runs out
+  pub fn instant(&self) -> Instant {
+    self.instant
+  }
+
+  /// The time left, zero once the deadline has passed
+  pub fn remaining(&self) -> Duration {
+    self.instant.saturating_duration_since(Instant::now())
+  }
+
+  /// Returns whether the deadline has passed
+  pub fn is_expired(&self) -> bool {
+    Instant::now() >= self.instant
+  }
+
+  /// Fails with [Error::Timeout] once the deadline has passed
+  pub fn check(&self) -> crate::Result<()> {
+    if self.is_expired() {
+      return Err(Error::Timeout);
+    }
+    Ok(())
+  }
 }
 
 type BatchFn = dyn FnMut(&mut TxRwRef) -> crate::Result<()> + Send + Sync + 'static;
@@ -1652,6 +2976,164 @@ impl Batcher {
   }
 }
 
+struct GroupCommitState {
+  /// Metas of committed transactions that are not durable yet, in commit order
+  pending: Vec<Meta>,
+  durable: TxId,
+  flushing: bool,
+  /// The newest transaction of the pending chain that was discarded when a sync failed.
+  /// The in-memory freelist includes the discarded transactions, so write transactions
+  /// fail until the database is reopened.
+  failed: Option<TxId>,
+}
+
+/// Shares one fsync between all transactions committed while the previous one ran.
+///
+/// Committed transactions only write their data pages and hand their meta over.
+/// The first waiting committer becomes the leader, syncs the data pages of every
+/// pending transaction and writes a single meta. That meta must not go into the
+/// slot of the durable meta, so the leader picks the newest pending meta with the
+/// other slot's parity. Transactions behind it wait for the next round.
+///
+/// Read transactions only see a batch once its meta is synced. Write transactions
+/// build on the newest pending meta, so a failed sync fails every pending transaction.
+pub(crate) struct GroupCommit {
+  db: Weak<InnerDB>,
+  state: Mutex<GroupCommitState>,
+  flushed: Condvar,
+}
+
+impl GroupCommit {
+  fn new(db: Weak<InnerDB>, durable: TxId) -> GroupCommit {
+    GroupCommit {
+      db,
+      state: Mutex::new(GroupCommitState {
+        pending: Vec::new(),
+        durable,
+        flushing: false,
+        failed: None,
+      }),
+      flushed: Condvar::new(),
+    }
+  }
+
+  pub(crate) fn durable_txid(&self) -> TxId {
+    self.state.lock().durable
+  }
+
+  pub(crate) fn latest_meta(&self) -> Option<Meta> {
+    self.state.lock().pending.last().copied()
+  }
+
+  /// Returns an error once a sync failed. See [GroupCommitState::failed]
+  pub(crate) fn check_writable(&self) -> crate::Result<()> {
+    GroupCommit::check_failed(&self.state.lock())
+  }
+
+  fn check_failed(state: &GroupCommitState) -> crate::Result<()> {
+    match state.failed {
+      Some(failed) => Err(Error::Other(anyhow!(
+        "group commit failed to sync txid {}, reopen the database",
+        failed
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  /// Queues the meta of a transaction whose data pages are written. Fails if the chain
+  /// the transaction built on was discarded
+  pub(crate) fn push(&self, meta: Meta) -> crate::Result<()> {
+    let mut state = self.state.lock();
+    GroupCommit::check_failed(&state)?;
+    state.pending.push(meta);
+    Ok(())
+  }
+
+  /// Blocks until the transaction `txid` is durable
+  pub(crate) fn wait(&self, txid: TxId) -> crate::Result<()> {
+    let mut state = self.state.lock();
+    loop {
+      if state.durable >= txid {
+        return Ok(());
+      }
+      if state.failed.is_some() {
+        return Err(Error::Other(anyhow!(
+          "group commit failed to sync txid {}",
+          txid
+        )));
+      }
+      if state.flushing {
+        self.flushed.wait(&mut state);
+        continue;
+      }
+      let parity = state.durable.0 % 2;
+      let target = match state
+        .pending
+        .iter()
+        .rev()
+        .find(|meta| meta.txid().0 % 2 != parity)
+      {
+        Some(meta) => *meta,
+        None => {
+          return Err(Error::Other(anyhow!(
+            "group commit has no pending meta for txid {}",
+            txid
+          )))
+        }
+      };
+      state.flushing = true;
+      let r = MutexGuard::unlocked(&mut state, || self.flush(&target));
+      state.flushing = false;
+      match r {
+        Ok(_) => {
+          state.durable = target.txid();
+          state.pending.retain(|meta| meta.txid() > target.txid());
+        }
+        Err(_) => {
+          // The transactions behind the target build on it, so the whole chain is lost
+          state.failed = state.pending.last().map(|meta| meta.txid());
+          state.pending.clear();
+        }
+      }
+      self.flushed.notify_all();
+      r?;
+    }
+  }
+
+  /// Syncs the data pages and writes `meta`. New read transactions see it once it is
+  /// synced as well.
+  fn flush(&self, meta: &Meta) -> crate::Result<()> {
+    let inner = self.db.upgrade().ok_or(Error::DatabaseNotOpen)?;
+    let db = inner.db.read_recursive();
+    Bolt::require_open(&inner.db_state.lock())?;
+    db.fsync()?;
+    let page_size = db.backend.page_size();
+    let mut buffer = AlignedBytes::<alignment::Page>::new_zeroed(page_size);
+    let mut meta_page = unsafe { MappedMetaPage::new(buffer.as_mut_ptr()) };
+    meta.write(&mut meta_page);
+    let offset = meta_page.page.id.0 * page_size as u64;
+    let r = db.write_all_at(&buffer, offset).and_then(|_| {
+      #[cfg(feature = "failpoints")]
+      db.fail_point(FailPoint::AfterMeta);
+      db.fsync()
+    });
+    if let Err(e) = r {
+      // The meta may still reach the disk. Reopening must not see the failed transactions
+      let zeroed = AlignedBytes::<alignment::Page>::new_zeroed(page_size);
+      let _ = db.write_all_at(&zeroed, offset).and_then(|_| db.fsync());
+      return Err(e);
+    }
+    // Marking fails only if the log can't be written. Reopening the database marks them.
+    #[cfg(feature = "replication")]
+    let _ = db.commit_changes(meta.txid());
+    let mut state = inner.db_state.lock();
+    if meta.txid() > state.current_meta.txid() {
+      state.current_meta = *meta;
+    }
+    Ok(())
+  }
+}
+
 /// A BBolt Database
 pub struct InnerDB {
   path: Arc<DbPath>,
@@ -1660,26 +3142,95 @@ pub struct InnerDB {
   stats: Arc<DbStats>,
   db_state: Arc<Mutex<DbState>>,
   batcher: Arc<Batcher>,
+  #[cfg(feature = "failpoints")]
+  failpoints: Arc<FailPoints>,
+  #[cfg(feature = "async")]
+  executor: RwLock<Arc<dyn BlockingExecutor>>,
 }
 
 unsafe impl Send for InnerDB {}
 unsafe impl Sync for InnerDB {}
 
 /// The Bolt Database
+///
+/// `Bolt` is a handle to a shared database. Cloning it is cheap and only increments
+/// a reference count, and all clones refer to the same database. The handle is
+/// `Send + Sync`, so clones can be moved to other threads.
+///
+/// Transactions borrow the handle they were started from and are neither `Send` nor
+/// `Sync`. Start them on the thread that uses them. Read transactions may be started
+/// from any number of threads at once. Write transactions are serialized, so a thread
+/// starting one blocks until the current writer finishes.
+///
+/// ```rust
+/// use bbolt_rs::*;
+/// use std::thread;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///   db.update(|mut tx| {
+///     let mut b = tx.create_bucket("test")?;
+///     b.put("key", "value")?;
+///     Ok(())
+///   })?;
+///
+///   let readers: Vec<_> = (0..4)
+///     .map(|_| {
+///       let db = db.clone();
+///       thread::spawn(move || {
+///         db.view(|tx| {
+///           let b = tx.bucket("test").unwrap();
+///           assert_eq!(Some(b"value".as_ref()), b.get("key"));
+///           Ok(())
+///         })
+///       })
+///     })
+///     .collect();
+///   for reader in readers {
+///     reader.join().unwrap()?;
+///   }
+///
+///   Ok(())
+/// }
+/// ```
 #[derive(Clone)]
 pub struct Bolt {
   inner: Arc<InnerDB>,
 }
 
+// Bolt must stay shareable between threads
+const _: fn() = || {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Bolt>();
+};
+
+impl Debug for Bolt {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    // Skip the info instead of waiting for a writer remapping the database
+    let info = self.inner.db.try_read().and_then(|db| {
+      if self.inner.db_state.lock().is_open {
+        Some(Bolt::db_info(&db))
+      } else {
+        None
+      }
+    });
+    f.debug_struct("Bolt")
+      .field("path", &self.inner.path)
+      .field("info", &info)
+      .finish()
+  }
+}
+
 impl Bolt {
   /// Open creates and opens a database at the given path.
   /// If the file does not exist then it will be created automatically.
+  #[cfg(file_backend_supported)]
   pub fn open<T: AsRef<Path>>(path: T) -> crate::Result<Self> {
     Bolt::open_path(path, BoltOptions::default())
   }
 
-  /// Opens a database as read-only at the given path.
-  /// If the file does not exist then it will be created automatically.
+  /// Opens a database as read-only at the given path. See [BoltOptions::open_ro]
+  #[cfg(file_backend_supported)]
   pub fn open_ro<T: AsRef<Path>>(path: T) -> crate::Result<impl DbApi> {
     Bolt::open_path(
       path,
@@ -1690,15 +3241,103 @@ impl Bolt {
     )
   }
 
+  /// Removes the sidecar lock file of the database at `path`, even if its owner still
+  /// holds the lock. Returns the owner recorded in the removed file, or None if there was
+  /// none.
+  ///
+  /// Only call it once the owner is known to be gone but its lock wasn't released, e.g. a
+  /// lock on a network file system whose client host crashed. Locks of crashed owners on
+  /// the same host are taken over without it. Two writers on the same database corrupt it.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let dir = tempfile::tempdir()?;
+  ///   let path = dir.path().join("locked.db");
+  ///   std::fs::File::create(&path)?;
+  ///   let options = BoltOptions::builder().lock_file().build();
+  ///
+  ///   let db = options.clone().open(&path)?;
+  ///   let owner = Bolt::force_unlock(&path)?.unwrap();
+  ///   assert_eq!(std::process::id(), owner.pid());
+  ///   assert_eq!(None, Bolt::force_unlock(&path)?);
+  ///   db.close();
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(file_backend_supported)]
+  pub fn force_unlock<T: AsRef<Path>>(path: T) -> crate::Result<Option<LockOwner>> {
+    let lock_path = lock_file_path(path.as_ref());
+    let owner = match read_owner(&lock_path) {
+      Ok(owner) => owner,
+      // An unreadable lock is removed all the same
+      Err(Error::Other(_)) => None,
+      Err(e) => return Err(e),
+    };
+    match fs::remove_file(&lock_path) {
+      Ok(_) => Ok(owner),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  /// Validates both meta pages of the open database.
+  ///
+  /// Every commit overwrites the older meta page, so both are valid unless a write was
+  /// torn or the file was damaged. The database keeps working from the other meta page,
+  /// but a crash before the next commit would leave nothing to fall back on. Waits for the
+  /// current write transaction to finish.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket_if_not_exists("test")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let status = db.verify_meta()?;
+  ///   assert!(status.is_healthy());
+  ///   assert!(!status.uses_backup());
+  ///   assert!(!db.info().recovered_from_backup_meta);
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn verify_meta(&self) -> crate::Result<MetaStatus> {
+    let lock = self.inner.db.upgradable_read();
+    Bolt::require_open(&self.inner.db_state.lock())?;
+    Ok(lock.backend.meta_status())
+  }
+
+  #[cfg(file_backend_supported)]
   fn new_file_backend(path: &Path, bolt_options: BoltOptions) -> crate::Result<Bolt> {
     let read_only = bolt_options.read_only();
+    // A pinned snapshot is read while the writer holds the locks. The writer's lease keeps
+    // its pages from being reused instead.
+    let pinned = bolt_options.pinned_meta().is_some();
+    // The sidecar lock is checked first so a live owner fails the open instead of blocking
+    // on the file lock
+    let lock_file = if !bolt_options.lock_file() || pinned {
+      None
+    } else if read_only {
+      check_unlocked(&lock_file_path(path))?;
+      None
+    } else {
+      Some(LockFile::acquire(lock_file_path(path))?)
+    };
     let mut file = if bolt_options.read_only() {
       let file = fs::OpenOptions::new().read(true).open(path)?;
-      file.lock_shared()?;
+      if !pinned {
+        Self::lock_file(&file, true, bolt_options.timeout())?;
+      }
       file
     } else {
       let mut file = fs::OpenOptions::new().write(true).read(true).open(path)?;
-      file.lock_exclusive()?;
+      Self::lock_file(&file, false, bolt_options.timeout())?;
       if !path.exists() || path.metadata()?.len() == 0 {
         let page_size = bolt_options
           .page_size()
@@ -1744,52 +3383,91 @@ impl Bolt {
       use_mlock: bolt_options.mlock(),
       grow_async: !bolt_options.no_grow_sync(),
       read_only,
+      _lock_file: lock_file,
     };
-    backend.file_size()?;
+    let file_size = backend.file_size()?;
+    if bolt_options.untrusted() {
+      backend.validate_untrusted(file_size, bolt_options.untrusted_max_elements())?;
+    }
     let backend = Box::new(backend);
     Self::new_db(DbPath::FilePath(path.into()), bolt_options, backend)
   }
 
-  fn new_mem_with_options(bolt_options: BoltOptions) -> crate::Result<Bolt> {
-    let page_size = bolt_options
-      .page_size()
-      .unwrap_or(DEFAULT_PAGE_SIZE.bytes() as usize);
-    let mut mmap = Bolt::init_page(page_size);
-    let file_size = mmap.len() as u64;
-    let data_size = if let Some(initial_mmap_size) = bolt_options.initial_map_size() {
-      file_size.max(initial_mmap_size)
-    } else {
-      file_size
-    };
-    if file_size < data_size {
-      let mut new_mmap = AlignedBytes::new_zeroed(data_size as usize);
-      new_mmap
-        .split_at_mut(file_size as usize)
-        .0
-        .copy_from_slice(&mmap);
-      mmap = new_mmap;
-    }
-    let backend = MemBackend {
-      mmap: Mutex::new(mmap),
-      freelist: OnceLock::new(),
-      page_size,
-      alloc_size: DEFAULT_ALLOC_SIZE.bytes() as u64,
-      file_size,
-      data_size,
+  /// Locks the database file. Read-only databases take a shared lock, so any number of
+  /// processes can read the file at the same time. Writable databases take an exclusive
+  /// lock that excludes readers and other writers alike.
+  ///
+  /// Waits until the lock is free. Returns [Error::Timeout] once `timeout` has passed
+  /// unless it is None or zero.
+  #[cfg(file_backend_supported)]
+  fn lock_file(file: &File, shared: bool, timeout: Option<Duration>) -> crate::Result<()> {
+    let deadline = match timeout {
+      Some(timeout) if !timeout.is_zero() => Instant::now() + timeout,
+      _ => {
+        if shared {
+          FileExt::lock_shared(file)?;
+        } else {
+          FileExt::lock_exclusive(file)?;
+        }
+        return Ok(());
+      }
     };
-    let backend = Box::new(backend);
+    loop {
+      // std's inherent try_lock_shared shadows fs4's and reports contention differently
+      let result = if shared {
+        FileExt::try_lock_shared(file)
+      } else {
+        FileExt::try_lock_exclusive(file)
+      };
+      match result {
+        Ok(_) => return Ok(()),
+        Err(e) if e.raw_os_error() == fs4::lock_contended_error().raw_os_error() => {
+          if Instant::now() >= deadline {
+            return Err(Error::Timeout);
+          }
+          thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+        Err(e) => return Err(e.into()),
+      }
+    }
+  }
+
+  fn new_mem_with_options(bolt_options: BoltOptions) -> crate::Result<Bolt> {
+    let page_size = bolt_options
+      .page_size()
+      .unwrap_or(DEFAULT_PAGE_SIZE.bytes() as usize);
+    let mmap = Bolt::init_page(page_size);
+    let backend = Box::new(MemBackend::new(mmap, page_size, &bolt_options));
     Self::new_db(DbPath::Memory, bolt_options, backend)
   }
 
-  fn new_db(
+  pub(crate) fn new_db(
     db_path: DbPath, bolt_options: BoltOptions, backend: Box<dyn DBBackend>,
   ) -> crate::Result<Self> {
     backend.validate_meta()?;
     let mut free_count = 0u64;
+    let mut free_span_n = 0u64;
+    let mut max_free_span = 0u64;
     if bolt_options.preload_freelist() {
-      free_count = backend.freelist().free_count();
+      let mut freelist = backend.freelist();
+      freelist.set_alloc_policy(
+        bolt_options.freelist_alloc_strategy(),
+        bolt_options.freelist_prefer_low_pgid(),
+      );
+      free_count = freelist.free_count();
+      free_span_n = freelist.span_count();
+      max_free_span = freelist.max_span();
     }
-    let meta = backend.meta();
+    let meta = match bolt_options.pinned_meta() {
+      Some(meta) if meta.page_size() as usize != backend.page_size() => {
+        return Err(Error::InvalidDatabase {
+          meta_can_read: true,
+          meta: None,
+        })
+      }
+      Some(meta) => meta,
+      None => backend.meta(),
+    };
     if meta.free_list() == PGID_NO_FREE_LIST {
       return Err(Error::Other(anyhow!(
         "PGID_NO_FREE_LIST not currently supported"
@@ -1798,6 +3476,8 @@ impl Bolt {
     let db_state = Arc::new(Mutex::new(DbState::new(meta)));
     let stats = DbStats {
       free_page_n: (free_count as i64).into(),
+      free_span_n: (free_span_n as i64).into(),
+      max_free_span: (max_free_span as i64).into(),
       ..Default::default()
     };
     let arc_stats = Arc::new(stats);
@@ -1806,6 +3486,17 @@ impl Bolt {
       |bump| Pin::as_mut(bump).reset(),
     );
 
+    #[cfg(feature = "failpoints")]
+    let failpoints = Arc::new(FailPoints::default());
+    let opened_meta_txids = backend.meta_txids();
+    let recovered_from_backup_meta = backend.meta_status().uses_backup();
+    #[cfg(feature = "replication")]
+    let replication_log = match bolt_options.replication_log() {
+      Some(path) if !bolt_options.read_only() => {
+        Some(Mutex::new(ReplicationLog::open(path, meta.txid())?))
+      }
+      _ => None,
+    };
     let inner = Arc::new_cyclic(|weak| InnerDB {
       path: Arc::new(db_path),
       bump_pool,
@@ -1815,6 +3506,17 @@ impl Bolt {
         backend,
         page_pool: Mutex::new(vec![]),
         options: bolt_options.clone(),
+        #[cfg(feature = "failpoints")]
+        failpoints: failpoints.clone(),
+        group_commit: bolt_options
+          .group_commit()
+          .then(|| Arc::new(GroupCommit::new(weak.clone(), meta.txid()))),
+        #[cfg(feature = "replication")]
+        replication_log,
+        #[cfg(feature = "replication")]
+        change_listeners: Mutex::new(Vec::new()),
+        opened_meta_txids,
+        recovered_from_backup_meta,
       }),
       stats: arc_stats,
       db_state,
@@ -1828,10 +3530,244 @@ impl Bolt {
           .max_batch_size
           .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
       }),
+      #[cfg(feature = "failpoints")]
+      failpoints,
+      #[cfg(feature = "async")]
+      executor: RwLock::new(Arc::new(ThreadExecutor)),
     });
-    Ok(Bolt { inner })
+    let db = Bolt { inner };
+    db.check_comparators(bolt_options.comparators())?;
+    Ok(db)
+  }
+
+  /// Returns an error if a bucket uses a comparator missing from `comparators`
+  fn check_comparators(&self, comparators: &Comparators) -> crate::Result<()> {
+    self.view(|tx| {
+      if let Some(meta) = tx.tx.system_bucket(META_BUCKET) {
+        meta.api_for_each(|entry, name| match (split_meta_key(entry), name) {
+          (Some((_, key)), Some(name))
+            if key == COMPARATOR_META_KEY && comparators.get(name).is_none() =>
+          {
+            Err(Error::UnknownComparator(
+              String::from_utf8_lossy(name).into_owned(),
+            ))
+          }
+          _ => Ok(()),
+        })?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Returns the failpoints used to inject failures into this database's commits.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.failpoints().set(FailPoint::BeforeDataWrite, FailAction::FsyncError);
+  ///   let result = db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   });
+  ///   assert!(result.is_err());
+  ///
+  ///   db.view(|tx| {
+  ///     assert!(tx.bucket("test").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(feature = "failpoints")]
+  pub fn failpoints(&self) -> &FailPoints {
+    &self.inner.failpoints
+  }
+
+  /// Sets the executor used by the async transaction functions.
+  /// Defaults to [ThreadExecutor].
+  #[cfg(feature = "async")]
+  pub fn set_executor(&self, executor: Arc<dyn BlockingExecutor>) {
+    *self.inner.executor.write() = executor;
+  }
+
+  #[cfg(feature = "async")]
+  pub(crate) fn executor(&self) -> Arc<dyn BlockingExecutor> {
+    self.inner.executor.read().clone()
+  }
+
+  /// Reads the changesets of the database's replication log, starting with the one
+  /// committed by the transaction `txid`. See [crate::replication::subscribe_from]
+  ///
+  /// Returns [Error::ReplicationLogRequired] if the database was opened without
+  /// [BoltOptions::replication_log].
+  #[cfg(feature = "replication")]
+  pub fn subscribe_from(&self, txid: TxId) -> crate::Result<Subscription> {
+    let path = self.inner.db.read().options.replication_log().cloned();
+    match path {
+      Some(path) => crate::replication::subscribe_from(path, txid),
+      None => Err(Error::ReplicationLogRequired),
+    }
+  }
+
+  /// Calls `listener` with the changeset of every commit until it's dropped.
+  ///
+  /// The listener is called while the committing transaction holds the write lock, so it
+  /// must not begin a write transaction.
+  #[cfg(feature = "replication")]
+  pub(crate) fn add_change_listener(&self, listener: Weak<ChangeListener>) {
+    // Waits for the running write transaction, which doesn't record its changes
+    let db = self.inner.db.upgradable_read();
+    db.change_listeners.lock().push(listener);
+  }
+
+  /// Compacts the database into a new database file at `dst_path`. Buckets `skip` returns
+  /// true for are left out along with everything they contain. `skip` is called with the
+  /// names of the bucket and of its parents, starting with the top-level bucket.
+  ///
+  /// The database is read in one read-only transaction and the new file is written in one
+  /// write transaction. The new file uses the page size and key comparators of the database.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let dir = std::env::temp_dir().join(format!("bbolt-vacuum-{}", std::process::id()));
+  ///   std::fs::create_dir_all(&dir)?;
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket("data")?.put("key", "value")?;
+  ///     tx.create_bucket("cache")?.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let path = dir.join("vacuum.db");
+  ///   std::fs::File::create(&path)?;
+  ///   db.vacuum_into(&path, |path| path[0] == b"cache")?;
+  ///
+  ///   let db = Bolt::open_ro(&path)?;
+  ///   db.view(|tx| {
+  ///     assert_eq!(Some(b"value".as_slice()), tx.bucket("data").unwrap().get("key"));
+  ///     assert!(tx.bucket("cache").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   std::fs::remove_dir_all(&dir)?;
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(file_backend_supported)]
+  pub fn vacuum_into<P: AsRef<Path>, F: Fn(&[&[u8]]) -> bool>(
+    &self, dst_path: P, skip: F,
+  ) -> crate::Result<()> {
+    let (comparators, page_size) = {
+      let db = self.inner.db.read();
+      (db.options.comparators().clone(), db.backend.page_size())
+    };
+    let mut dst = BoltOptions::builder()
+      .page_size(page_size)
+      .comparators(comparators)
+      .build()
+      .open(dst_path)?;
+    let src = self.begin_tx()?;
+    dst.update(|mut dst_tx| {
+      let dst_cell = dst_tx.tx;
+      let meta = src.tx.system_bucket(META_BUCKET);
+      // Copies the metadata entries of the buckets that aren't skipped, either the recorded
+      // comparators or all the others
+      let copy_meta = |comparators: bool| -> crate::Result<()> {
+        let meta = match meta {
+          Some(meta) => meta,
+          None => return Ok(()),
+        };
+        meta.api_for_each(|entry, value| {
+          let (path, key, value) = match (split_meta_key(entry), value) {
+            (Some((path, key)), Some(value)) => (path, key, value),
+            _ => return Ok(()),
+          };
+          let names = path_names(path);
+          if (key == COMPARATOR_META_KEY) != comparators
+            || (1..=names.len()).any(|n| skip(&names[..n]))
+          {
+            return Ok(());
+          }
+          if key == QUOTA_META_KEY {
+            // Skipped nested buckets aren't copied, so the usage is counted again
+            let mut record = QuotaRecord::decode(value)?;
+            record.usage = dst_cell
+              .api_bucket(names[0])
+              .ok_or(Error::BucketNotFound)?
+              .api_usage(true)?;
+            return dst_cell
+              .system_bucket_mut(META_BUCKET)?
+              .i_put(entry, &record.encode());
+          }
+          dst_cell.system_bucket_mut(META_BUCKET)?.i_put(entry, value)
+        })
+      };
+      // Comparators are recorded before their buckets are created, so the new buckets are
+      // opened with them
+      copy_meta(true)?;
+
+      let mut path = Vec::new();
+      let mut c = src.cursor();
+      let mut entry = c.first();
+      while let Some((name, _)) = entry {
+        path.push(name);
+        if !skip(&path) {
+          let mut dst_bucket = dst_tx.create_bucket(name)?;
+          Self::vacuum_bucket(src.bucket(name).unwrap(), &mut dst_bucket, &mut path, &skip)?;
+        }
+        path.pop();
+        entry = c.next();
+      }
+
+      copy_meta(false)?;
+      if let Some(root_kv) = src.tx.system_bucket(ROOT_KV_BUCKET) {
+        let dst_root_kv = dst_cell.system_bucket_mut(ROOT_KV_BUCKET)?;
+        root_kv.api_for_each(|k, v| match v {
+          Some(v) => dst_root_kv.i_put(k, v),
+          None => Ok(()),
+        })?;
+      }
+      Ok(())
+    })?;
+    dst.close();
+    Ok(())
+  }
+
+  /// Copies the contents of `src` into `dst` for [Bolt::vacuum_into]. `path` holds the
+  /// names of `src` and of its parents.
+  #[cfg(file_backend_supported)]
+  fn vacuum_bucket<'a, 'b, B: BucketRwApi<'b>, F: Fn(&[&[u8]]) -> bool>(
+    src: BucketImpl<'a>, dst: &mut B, path: &mut Vec<&'a [u8]>, skip: &F,
+  ) -> crate::Result<()> {
+    dst.set_sequence(src.sequence())?;
+    let mut c = src.cursor();
+    let mut entry = c.first();
+    while let Some((k, v)) = entry {
+      match v {
+        Some(v) => dst.put(k, v)?,
+        None => {
+          path.push(k);
+          if !skip(path) {
+            let mut child = dst.create_bucket(k)?;
+            Self::vacuum_bucket(src.bucket(k).unwrap(), &mut child, path, skip)?;
+          }
+          path.pop();
+        }
+      }
+      entry = c.next();
+    }
+    Ok(())
   }
 
+  #[cfg(file_backend_supported)]
   fn open_path<T: AsRef<Path>>(path: T, db_options: BoltOptions) -> crate::Result<Self> {
     let pref = path.as_ref();
     Self::new_file_backend(pref, db_options)
@@ -1842,7 +3778,96 @@ impl Bolt {
     Bolt::new_mem_with_options(BoltOptions::default())
   }
 
-  fn init_page(page_size: usize) -> AlignedBytes<alignment::Page> {
+  /// Opens an in-memory database that records every page write in a [SimLog].
+  ///
+  /// The log can replay any prefix of the writes into a new database to simulate
+  /// a power loss at that point.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let (mut db, log) = Bolt::open_sim()?;
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   // Lose power before the meta page of the commit reaches the disk
+  ///   let (replayed, _) = log.replay(log.len() - 1, BoltOptions::default())?;
+  ///   replayed.view(|tx| {
+  ///     assert!(tx.bucket("test").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(feature = "sim")]
+  pub fn open_sim() -> crate::Result<(Self, SimLog)> {
+    Bolt::new_sim_with_options(BoltOptions::default())
+  }
+
+  /// Opens a memory backed database that persists its pages to a [PageStore].
+  ///
+  /// The database is loaded from the store if it is not empty. Otherwise a new
+  /// database is written to it.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::sync::{Arc, Mutex};
+  ///
+  /// #[derive(Default)]
+  /// struct VecStore(Mutex<Vec<u8>>);
+  ///
+  /// impl PageStore for VecStore {
+  ///   fn load(&self) -> Result<Option<Vec<u8>>> {
+  ///     let data = self.0.lock().unwrap();
+  ///     Ok(Some(data.clone()).filter(|data| !data.is_empty()))
+  ///   }
+  ///
+  ///   fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
+  ///     let mut store = self.0.lock().unwrap();
+  ///     let end = offset as usize + data.len();
+  ///     if store.len() < end {
+  ///       store.resize(end, 0);
+  ///     }
+  ///     store[offset as usize..end].copy_from_slice(data);
+  ///     Ok(())
+  ///   }
+  ///
+  ///   fn flush(&self) -> Result<()> {
+  ///     Ok(())
+  ///   }
+  /// }
+  ///
+  /// fn main() -> Result<()> {
+  ///   let store = Arc::new(VecStore::default());
+  ///   let mut db = Bolt::open_store(store.clone())?;
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///   db.close();
+  ///
+  ///   let db = Bolt::open_store(store)?;
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(feature = "page-store")]
+  pub fn open_store(store: Arc<dyn PageStore>) -> crate::Result<Self> {
+    Bolt::new_store_with_options(store, BoltOptions::default())
+  }
+
+  pub(crate) fn init_page(page_size: usize) -> AlignedBytes<alignment::Page> {
     let mut buffer = AlignedBytes::<alignment::Page>::new_zeroed(page_size * 4);
     for (i, page_bytes) in buffer.chunks_mut(page_size).enumerate() {
       let mut page = MutPage::new(page_bytes.as_mut_ptr());
@@ -1877,6 +3902,7 @@ impl Bolt {
     buffer
   }
 
+  #[cfg(file_backend_supported)]
   fn init(path: &Path, db: &mut File, page_size: usize) -> io::Result<usize> {
     let buffer = Bolt::init_page(page_size);
     #[cfg(unix)]
@@ -1892,6 +3918,25 @@ impl Bolt {
     Ok(buffer.len())
   }
 
+  fn db_info(db: &DbShared) -> DbInfo {
+    let options = &db.options;
+    DbInfo {
+      page_size: db.backend.page_size(),
+      mmap_size: db.backend.data_size(),
+      initial_mmap_size: options.initial_map_size(),
+      freelist_alloc_strategy: options.freelist_alloc_strategy(),
+      freelist_prefer_low_pgid: options.freelist_prefer_low_pgid(),
+      no_sync: options.no_sync(),
+      no_grow_sync: options.no_grow_sync(),
+      no_freelist_sync: options.no_freelist_sync(),
+      preload_freelist: options.preload_freelist(),
+      group_commit: options.group_commit(),
+      mlock: options.mlock(),
+      read_only: options.read_only(),
+      recovered_from_backup_meta: db.recovered_from_backup_meta,
+    }
+  }
+
   fn require_open(state: &DbState) -> crate::Result<()> {
     if !state.is_open {
       return Err(Error::DatabaseNotOpen);
@@ -1900,41 +3945,97 @@ impl Bolt {
   }
 
   pub(crate) fn begin_tx(&self) -> crate::Result<TxImpl> {
+    self.begin_tx_at(None)
+  }
+
+  /// Begins a read transaction on `meta`, or on the current meta if None
+  pub(crate) fn begin_tx_at(&self, meta: Option<Meta>) -> crate::Result<TxImpl<'_>> {
+    // The database lock is taken before the state, like in begin_rw_tx. A remap waits for
+    // the open readers, which need the state to close.
+    let lock = self.inner.db.read();
     let mut state = self.inner.db_state.lock();
     Bolt::require_open(&state)?;
-    let lock = self.inner.db.read();
+    lock.check_read_only()?;
+    let meta = meta.unwrap_or(state.current_meta);
+    lock.check_truncated(&meta)?;
     let bump = self.inner.bump_pool.pull();
-    let meta = state.current_meta;
     let txid = meta.txid();
     state.txs.push(txid);
+    let lease = Bolt::lease(&mut state, &lock, txid);
     self.inner.stats.inc_tx_n(1);
     self
       .inner
       .stats
       .open_tx_n
-      .store(state.txs.len() as i64, Ordering::Release);
-    Ok(TxImpl::new(bump, lock, meta))
+      .store(state.txs.len() as i64, Ordering::Relaxed);
+    let tx = TxImpl::new(bump, lock, meta);
+    tx.tx.split_r_mut().evicted = lease;
+    Ok(tx)
+  }
+
+  /// Registers a new read transaction of `txid` for eviction, if enabled
+  fn lease(state: &mut DbState, db: &DbShared, txid: TxId) -> Option<Arc<AtomicBool>> {
+    db.options.reader_max_age()?;
+    let evicted = Arc::new(AtomicBool::new(false));
+    state.leases.push(ReaderLease {
+      txid,
+      begin: Instant::now(),
+      evicted: evicted.clone(),
+    });
+    Some(evicted)
   }
 
-  #[cfg(feature = "try-begin")]
-  pub(crate) fn try_begin_tx<'a, F>(&'a self, f: F) -> crate::Result<Option<TxImpl>>
+  /// Registers the current meta as an open read transaction so the pages reachable from
+  /// it are not reused until [Bolt::unpin_meta] is called.
+  pub(crate) fn pin_meta(&self) -> crate::Result<Meta> {
+    let mut state = self.inner.db_state.lock();
+    Bolt::require_open(&state)?;
+    let meta = state.current_meta;
+    state.txs.push(meta.txid());
+    self
+      .inner
+      .stats
+      .open_tx_n
+      .store(state.txs.len() as i64, Ordering::Relaxed);
+    Ok(meta)
+  }
+
+  /// Releases a meta registered with [Bolt::pin_meta]
+  pub(crate) fn unpin_meta(&self, txid: TxId) {
+    let mut state = self.inner.db_state.lock();
+    if let Some(pos) = state.txs.iter().position(|tx| *tx == txid) {
+      state.txs.swap_remove(pos);
+    }
+    self
+      .inner
+      .stats
+      .open_tx_n
+      .store(state.txs.len() as i64, Ordering::Relaxed);
+    self.inner.stats.remove_reader(txid);
+  }
+
+  pub(crate) fn try_begin_tx<'a, F>(&'a self, f: F) -> crate::Result<Option<TxImpl<'a>>>
   where
     F: Fn() -> Option<RwLockReadGuard<'a, DbShared>>,
   {
-    let mut state = self.inner.db_state.lock();
-    Bolt::require_open(&state)?;
     if let Some(lock) = f() {
-      let bump = self.inner.bump_pool.pull();
+      let mut state = self.inner.db_state.lock();
+      Bolt::require_open(&state)?;
       let meta = state.current_meta;
+      lock.check_truncated(&meta)?;
+      let bump = self.inner.bump_pool.pull();
       let txid = meta.txid();
       state.txs.push(txid);
+      let lease = Bolt::lease(&mut state, &lock, txid);
       self.inner.stats.inc_tx_n(1);
       self
         .inner
         .stats
         .open_tx_n
-        .store(state.txs.len() as i64, Ordering::Release);
-      Ok(Some(TxImpl::new(bump, lock, meta)))
+        .store(state.txs.len() as i64, Ordering::Relaxed);
+      let tx = TxImpl::new(bump, lock, meta);
+      tx.tx.split_r_mut().evicted = lease;
+      Ok(Some(tx))
     } else {
       Ok(None)
     }
@@ -1944,28 +4045,31 @@ impl Bolt {
     let lock = self.inner.db.upgradable_read();
     let mut state = self.inner.db_state.lock();
     Bolt::require_open(&state)?;
+    lock.check_truncated(&state.current_meta)?;
     lock.free_pages(&mut state);
     let bump = self.inner.bump_pool.pull();
-    let mut meta = state.current_meta;
+    let mut meta = lock.writer_meta(&state)?;
     let txid = meta.txid() + 1;
     meta.set_txid(txid);
+    meta.set_version(lock.writer_version(meta.version()));
     state.rwtx = Some(txid);
     Ok(TxRwImpl::new(bump, lock, meta))
   }
 
-  #[cfg(feature = "try-begin")]
-  pub(crate) fn try_begin_rw_tx<'a, F>(&'a self, f: F) -> crate::Result<Option<TxRwImpl>>
+  pub(crate) fn try_begin_rw_tx<'a, F>(&'a self, f: F) -> crate::Result<Option<TxRwImpl<'a>>>
   where
     F: Fn() -> Option<RwLockUpgradableReadGuard<'a, DbShared>>,
   {
     if let Some(lock) = f() {
-      lock.free_pages();
       let mut state = self.inner.db_state.lock();
       Bolt::require_open(&state)?;
+      lock.check_truncated(&state.current_meta)?;
+      lock.free_pages(&mut state);
       let bump = self.inner.bump_pool.pull();
-      let mut meta = state.current_meta;
+      let mut meta = lock.writer_meta(&state)?;
       let txid = meta.txid() + 1;
       meta.set_txid(txid);
+      meta.set_version(lock.writer_version(meta.version()));
       state.rwtx = Some(txid);
       Ok(Some(TxRwImpl::new(bump, lock, meta)))
     } else {
@@ -1991,7 +4095,9 @@ impl DbApi for Bolt {
 
   #[cfg(feature = "try-begin")]
   fn try_begin_until(&self, instant: Instant) -> crate::Result<Option<impl TxApi>> {
-    self.try_begin_tx(|| self.inner.db.try_read_until(instant))
+    // The lock takes a std Instant, which isn't the arch Instant on wasm32
+    let timeout = instant.saturating_duration_since(Instant::now());
+    self.try_begin_tx(|| self.inner.db.try_read_for(timeout))
   }
 
   fn view<'tx, F: FnMut(TxRef<'tx>) -> crate::Result<()>>(
@@ -2003,6 +4109,19 @@ impl DbApi for Bolt {
     r
   }
 
+  fn view_deadline<'tx, F: FnMut(TxRef<'tx>, &Deadline) -> crate::Result<()>>(
+    &'tx self, deadline: Instant, mut f: F,
+  ) -> crate::Result<()> {
+    let deadline = Deadline::new(deadline);
+    deadline.check()?;
+    let tx = match self.try_begin_tx(|| self.inner.db.try_read_for(deadline.remaining()))? {
+      Some(tx) => tx,
+      None => return Err(Error::Timeout),
+    };
+    let tx_ref = tx.get_ref();
+    f(tx_ref, &deadline)
+  }
+
   fn stats(&self) -> Arc<DbStats> {
     self.inner.stats.clone()
   }
@@ -2012,9 +4131,7 @@ impl DbApi for Bolt {
   }
 
   fn info(&self) -> DbInfo {
-    DbInfo {
-      page_size: self.inner.db.read().backend.page_size(),
-    }
+    Bolt::db_info(&self.inner.db.read())
   }
 
   fn close(self) {
@@ -2038,6 +4155,12 @@ impl DbRwAPI for Bolt {
     self.begin_rw_tx()
   }
 
+  fn begin_rw_tx_with(&mut self, options: TxRwOptions) -> crate::Result<impl TxRwApi<'_>> {
+    let mut tx = self.begin_rw_tx()?;
+    tx.set_deadline(options.timeout.map(|timeout| Instant::now() + timeout));
+    Ok(tx)
+  }
+
   #[cfg(feature = "try-begin")]
   fn try_begin_rw(&self) -> crate::Result<Option<impl TxRwApi>> {
     self.try_begin_rw_tx(|| self.inner.db.try_upgradable_read())
@@ -2050,7 +4173,8 @@ impl DbRwAPI for Bolt {
 
   #[cfg(feature = "try-begin")]
   fn try_begin_rw_until(&self, instant: Instant) -> crate::Result<Option<impl TxRwApi>> {
-    self.try_begin_rw_tx(|| self.inner.db.try_upgradable_read_until(instant))
+    let timeout = instant.saturating_duration_since(Instant::now());
+    self.try_begin_rw_tx(|| self.inner.db.try_upgradable_read_for(timeout))
   }
 
   fn update<'tx, F: FnMut(TxRwRef<'tx>) -> crate::Result<()>>(
@@ -2070,6 +4194,30 @@ impl DbRwAPI for Bolt {
     }
   }
 
+  fn update_deadline<'tx, F: FnMut(TxRwRef<'tx>, &Deadline) -> crate::Result<()>>(
+    &'tx mut self, deadline: Instant, mut f: F,
+  ) -> crate::Result<()> {
+    let deadline = Deadline::new(deadline);
+    deadline.check()?;
+    let mut txrw =
+      match self.try_begin_rw_tx(|| self.inner.db.try_upgradable_read_for(deadline.remaining()))? {
+        Some(txrw) => txrw,
+        None => return Err(Error::Timeout),
+      };
+    txrw.set_deadline(Some(deadline.instant()));
+    let tx_ref = txrw.get_ref();
+    match f(tx_ref, &deadline) {
+      Ok(_) => {
+        txrw.commit()?;
+        Ok(())
+      }
+      Err(e) => {
+        let _ = txrw.rollback();
+        Err(e)
+      }
+    }
+  }
+
   fn batch<F>(&mut self, f: F) -> crate::Result<()>
   where
     F: FnMut(&mut TxRwRef) -> crate::Result<()> + Send + Sync + Clone + 'static,
@@ -2085,19 +4233,26 @@ impl DbRwAPI for Bolt {
 
 #[cfg(test)]
 mod test {
-  use crate::common::defaults::DEFAULT_PAGE_SIZE;
+  use crate::arch::aligned::{alignment, AlignedBytes};
+  use crate::arch::time::Instant;
+  use crate::common::defaults::{DEFAULT_PAGE_SIZE, SUPPORTED_VERSIONS};
   use crate::common::meta::MappedMetaPage;
+  use crate::common::page::{PageHeader, PAGE_HEADER_SIZE};
+  use crate::comparator::META_BUCKET;
   use crate::db::DbStats;
   use crate::test_support::{temp_file, TestDb};
+  use crate::tx::TxIApi;
   use crate::{
-    Bolt, BoltOptions, BucketApi, BucketRwApi, DbApi, DbPath, DbRwAPI, Error, PgId, TxApi, TxCheck,
-    TxRwApi, TxRwRefApi,
+    Bolt, BoltOptions, BucketApi, BucketImpl, BucketQuota, BucketRwApi, Comparators, CursorApi,
+    DbApi, DbPath, DbRwAPI, Error, PgId, QuotaUsage, TxApi, TxCheck, TxRwApi, TxRwOptions,
+    TxRwRefApi,
   };
-  use aligners::{alignment, AlignedBytes};
   use std::io::{Read, Seek, SeekFrom, Write};
+  use std::sync::atomic::{AtomicUsize, Ordering};
   use std::sync::mpsc::channel;
   use std::sync::Arc;
   use std::thread;
+  use std::time::Duration;
 
   #[test]
   #[cfg(not(miri))]
@@ -2166,7 +4321,17 @@ mod test {
       .as_file_mut()
       .write_all(b"this is not a bolt database")?;
     let r = Bolt::open(file.path());
-    assert_eq!(Some(Error::InvalidDatabase(false)), r.err());
+    match r.err() {
+      Some(Error::InvalidDatabase {
+        meta_can_read: false,
+        meta: Some(meta),
+      }) => {
+        assert_eq!(PgId(0), meta.id);
+        assert!(!meta.is_bolt_magic());
+        assert_eq!(27, meta.file_size);
+      }
+      e => panic!("unexpected result {:?}", e),
+    }
     Ok(())
   }
 
@@ -2180,17 +4345,23 @@ mod test {
     let mut bytes = AlignedBytes::<alignment::Page>::new_zeroed(4096 * 2);
     file.seek(SeekFrom::Start(0))?;
     file.read_exact(&mut bytes)?;
+    // The next version past every supported one, which may include feature gated versions
+    let unsupported = SUPPORTED_VERSIONS.iter().max().unwrap() + 1;
     let mut meta_0 = unsafe { MappedMetaPage::new(bytes.as_mut_ptr()) };
-    let meta0_version = meta_0.meta.version();
-    meta_0.meta.set_version(meta0_version + 1);
+    meta_0.meta.set_version(unsupported);
     let mut meta_1 = unsafe { MappedMetaPage::new(bytes.as_mut_ptr().add(4096)) };
-    let meta1_version = meta_1.meta.version();
-    meta_1.meta.set_version(meta1_version + 1);
+    meta_1.meta.set_version(unsupported);
     file.seek(SeekFrom::Start(0))?;
     file.write_all(&bytes)?;
     file.flush()?;
     let r = Bolt::open(file.path());
-    assert_eq!(Some(Error::VersionMismatch), r.err());
+    assert_eq!(
+      Some(Error::VersionMismatch {
+        found: unsupported,
+        supported: SUPPORTED_VERSIONS,
+      }),
+      r.err()
+    );
     Ok(())
   }
 
@@ -2213,8 +4384,120 @@ mod test {
     file.seek(SeekFrom::Start(0))?;
     file.write_all(&bytes)?;
     file.flush()?;
-    let r = Bolt::open(file.path());
-    assert_eq!(Some(Error::ChecksumMismatch), r.err());
+    let r = Bolt::open(file.path());
+    match r.err() {
+      Some(Error::ChecksumMismatch {
+        meta: Some(meta), ..
+      }) => {
+        assert_eq!(PgId(0), meta.id);
+        assert!(meta.is_bolt_magic());
+        assert!(!meta.checksum_ok());
+        assert_eq!(file.as_file().metadata()?.len(), meta.file_size);
+      }
+      e => panic!("unexpected result {:?}", e),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_page_beyond_mapping() {
+    let db = TestDb::new().unwrap();
+    let shared = db.inner.db.read();
+    match shared.backend.try_page(PgId(1 << 40)) {
+      Err(Error::Corrupted { pgid, .. }) => assert_eq!(PgId(1 << 40), pgid),
+      _ => panic!("expected a corrupted page"),
+    }
+  }
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_open_recovers_from_backup_meta() -> crate::Result<()> {
+    let mut file = temp_file()?;
+    let mut db = Bolt::open(file.path())?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+    let status = db.verify_meta()?;
+    assert!(status.is_healthy());
+    assert!(!db.info().recovered_from_backup_meta);
+    db.close();
+
+    // Tear the newest meta page
+    let newest = if status.txids[1] > status.txids[0] {
+      1
+    } else {
+      0
+    };
+    let mut bytes = AlignedBytes::<alignment::Page>::new_zeroed(4096);
+    file.seek(SeekFrom::Start(newest * 4096))?;
+    file.read_exact(&mut bytes)?;
+    let mut meta = unsafe { MappedMetaPage::new(bytes.as_mut_ptr()) };
+    let pgid = meta.meta.pgid();
+    meta.meta.set_pgid(pgid + 1);
+    file.seek(SeekFrom::Start(newest * 4096))?;
+    file.write_all(&bytes)?;
+    file.flush()?;
+
+    let mut db = Bolt::open(file.path())?;
+    assert!(db.info().recovered_from_backup_meta);
+    let status = db.verify_meta()?;
+    assert_eq!(Some(1 - newest as usize), status.active());
+    assert!(status.uses_backup());
+    db.view(|tx| {
+      assert!(tx.bucket("widgets").is_none());
+      Ok(())
+    })?;
+
+    // The next commit overwrites the torn meta page
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+    assert!(db.verify_meta()?.is_healthy());
+    db.close();
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_open_untrusted() -> crate::Result<()> {
+    let mut file = temp_file()?;
+    let mut db = Bolt::open(file.path())?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "*".repeat(2048))?;
+      let mut inline = b.create_bucket("inline")?;
+      inline.put("bar", "baz")?;
+      Ok(())
+    })?;
+    let root = {
+      let tx = db.begin()?;
+      tx.bucket("widgets").unwrap().root()
+    };
+    db.close();
+
+    let db = BoltOptions::builder()
+      .untrusted()
+      .build()
+      .open(file.path())?;
+    db.close();
+
+    // The root, widgets and inline buckets hold four elements
+    let r = BoltOptions::builder()
+      .untrusted()
+      .untrusted_max_elements(2)
+      .build()
+      .open(file.path());
+    assert!(matches!(r.err(), Some(Error::Corrupted { .. })));
+
+    // Point the first element of the bucket's root page outside of the page
+    let pos_offset = root.0 * 4096 + PAGE_HEADER_SIZE as u64 + 4;
+    file.seek(SeekFrom::Start(pos_offset))?;
+    file.write_all(&u32::MAX.to_ne_bytes())?;
+    file.flush()?;
+    let r = BoltOptions::builder().untrusted().build().open(file.path());
+    assert!(matches!(r.err(), Some(Error::Corrupted { pgid, .. }) if pgid == root));
     Ok(())
   }
 
@@ -2433,6 +4716,163 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  #[cfg(all(timeout_supported, not(any(miri, feature = "test-mem-backend"))))]
+  fn test_db_open_read_only_shared() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    let path = match db.path().clone() {
+      DbPath::Memory => panic!("Path is DbPath::Memory"),
+      DbPath::FilePath(path) => path,
+    };
+    db.must_close();
+
+    // Readers share the file and keep writers out
+    let ro1 = Bolt::open_ro(&path)?;
+    let ro2 = Bolt::open_ro(&path)?;
+    let timeout = BoltOptions::builder()
+      .timeout(Duration::from_millis(100))
+      .build();
+    assert_eq!(Some(Error::Timeout), timeout.clone().open(&path).err());
+    ro2.close();
+
+    // A write that ignores the lock is detected instead of read
+    let mut file = std::fs::OpenOptions::new()
+      .read(true)
+      .write(true)
+      .open(&path)?;
+    let page_size = ro1.info().page_size;
+    let mut meta1 = vec![0u8; page_size];
+    file.seek(SeekFrom::Start(page_size as u64))?;
+    file.read_exact(&mut meta1)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&meta1)?;
+    file.sync_all()?;
+    assert_eq!(Some(Error::WriterDetected), ro1.begin().err());
+    ro1.close();
+
+    let db = timeout.open(&path)?;
+    db.close();
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_page_checksums() -> crate::Result<()> {
+    use crate::common::defaults::VERSION_PAGE_CHECKSUMS;
+    use crate::format;
+    let mut file = temp_file()?;
+    let mut db = BoltOptions::builder()
+      .page_checksums()
+      .build()
+      .open(file.path())?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      b.put("large", vec![1u8; 10000])?;
+      Ok(())
+    })?;
+    let page_size = db.info().page_size;
+    let root = {
+      let tx = db.begin()?;
+      let root = tx.bucket("widgets").unwrap().root();
+      let header: PageHeader =
+        bytemuck::pod_read_unaligned(&tx.page_bytes(root)?[..PAGE_HEADER_SIZE]);
+      assert!(header.has_checksum());
+      assert!(tx.check().is_empty());
+      let issues = tx.compat_check();
+      assert!(issues.iter().any(|issue| issue.contains("checksummed")));
+      root
+    };
+    db.close();
+    // Readers without checksum support refuse the file
+    assert_eq!(VERSION_PAGE_CHECKSUMS, format::detect(file.path())?.found);
+
+    // Checksums are verified without the option as well
+    let mut db = Bolt::open(file.path())?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(vec![1u8; 10000].as_slice()), b.get("large"));
+      Ok(())
+    })?;
+    // and the version stays while checksummed pages may remain
+    db.update(|mut tx| {
+      tx.create_bucket("plain")?;
+      Ok(())
+    })?;
+    db.close();
+    assert_eq!(VERSION_PAGE_CHECKSUMS, format::detect(file.path())?.found);
+
+    // Damage the root page of the bucket
+    let offset = root.0 * page_size as u64 + page_size as u64 / 2;
+    let mut byte = [0u8];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut byte)?;
+    byte[0] ^= 0xFF;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&byte)?;
+    file.flush()?;
+
+    let db = Bolt::open(file.path())?;
+    db.view(|tx| {
+      let errors = tx.check();
+      assert_eq!(1, errors.len(), "{:?}", errors);
+      assert!(errors[0].contains("checksum mismatch"), "{:?}", errors);
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(
+        Some(Error::ChecksumMismatch {
+          page: Some(root),
+          meta: None
+        }),
+        b.try_get("large").err()
+      );
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(unix, not(any(miri, feature = "test-mem-backend"))))]
+  fn test_detect_truncation() -> crate::Result<()> {
+    let file = temp_file()?;
+    let mut db = BoltOptions::builder()
+      .detect_truncation()
+      .build()
+      .open(file.path())?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      Ok(())
+    })?;
+    let page_size = db.info().page_size as u64;
+
+    // Another process truncates the file below the high water mark
+    file.as_file().set_len(page_size * 4)?;
+    match db.begin() {
+      Err(Error::FileTruncated {
+        file_size,
+        required,
+      }) => {
+        assert_eq!(page_size * 4, file_size);
+        assert!(required > file_size);
+      }
+      r => panic!("unexpected result {:?}", r.err()),
+    }
+    assert!(matches!(
+      db.begin_rw().err(),
+      Some(Error::FileTruncated { .. })
+    ));
+    db.close();
+    Ok(())
+  }
+
   #[test]
   #[cfg(not(any(miri, feature = "test-mem-backend")))]
   fn test_open_big_page() -> crate::Result<()> {
@@ -2520,6 +4960,208 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_db_deadline() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let soon = || Instant::now() + Duration::from_millis(20);
+    let later = || Instant::now() + Duration::from_secs(60);
+    db.update_deadline(later(), |mut tx, deadline| {
+      assert!(!deadline.is_expired());
+      tx.create_bucket("widgets")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+
+    let past = Instant::now();
+    assert_eq!(Err(Error::Timeout), db.view_deadline(past, |_, _| Ok(())));
+    assert_eq!(Err(Error::Timeout), db.update_deadline(past, |_, _| Ok(())));
+
+    // The writer lock isn't free before the deadline
+    let mut writer = db.clone_db();
+    let tx = writer.begin_rw()?;
+    assert_eq!(
+      Err(Error::Timeout),
+      db.update_deadline(soon(), |_, _| Ok(()))
+    );
+    drop(tx);
+
+    // Work past the deadline fails and is rolled back
+    let r = db.update_deadline(soon(), |mut tx, deadline| {
+      tx.bucket_mut("widgets").unwrap().put("foo", "baz")?;
+      thread::sleep(deadline.remaining());
+      deadline.check()
+    });
+    assert_eq!(Err(Error::Timeout), r);
+    let r = db.update_deadline(soon(), |mut tx, deadline| {
+      thread::sleep(deadline.remaining());
+      tx.bucket_mut("widgets").unwrap().put("foo", "baz")
+    });
+    assert_eq!(Err(Error::TxTimeout), r);
+    db.view_deadline(later(), |tx, deadline| {
+      deadline.check()?;
+      assert_eq!(
+        Some(b"bar".as_slice()),
+        tx.bucket("widgets").unwrap().get("foo")
+      );
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_begin_rw_tx_with_timeout() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    {
+      let options = TxRwOptions::builder().timeout(Duration::ZERO).build();
+      let mut tx = db.begin_rw_tx_with(options)?;
+      assert_eq!(Some(Error::TxTimeout), tx.create_bucket("widgets").err());
+      assert_eq!(Some(Error::TxTimeout), tx.commit().err());
+    }
+    {
+      let options = TxRwOptions::builder()
+        .timeout(Duration::from_secs(60))
+        .build();
+      let mut tx = db.begin_rw_tx_with(options)?;
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      tx.commit()?;
+    }
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(feature = "failpoints", not(any(miri, feature = "test-mem-backend"))))]
+  fn test_db_failpoint_short_meta_write() -> crate::Result<()> {
+    use crate::{FailAction, FailPoint};
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      Ok(())
+    })?;
+
+    db.failpoints()
+      .set(FailPoint::BetweenDataAndMeta, FailAction::ShortWrite(40));
+    let r = db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.put("foo", "baz")?;
+      Ok(())
+    });
+    assert!(matches!(r, Err(Error::IO(_))));
+
+    let check = |db: &TestDb| {
+      db.view(|tx| {
+        let b = tx.bucket("widgets").unwrap();
+        assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+        Ok(())
+      })
+    };
+    check(&db)?;
+    db.must_close();
+    db.must_reopen();
+    check(&db)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_group_commit() -> crate::Result<()> {
+    let mut db = TestDb::with_options(BoltOptions::builder().group_commit().build())?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+
+    let writers: Vec<_> = (0..8u32)
+      .map(|i| {
+        let mut w_db = db.clone_db();
+        thread::spawn(move || -> crate::Result<()> {
+          for j in 0..50u32 {
+            w_db.update(|mut tx| {
+              let mut b = tx.bucket_mut("widgets").unwrap();
+              b.put((i * 50 + j).to_be_bytes(), "value")?;
+              Ok(())
+            })?;
+          }
+          Ok(())
+        })
+      })
+      .collect();
+    for writer in writers {
+      writer.join().unwrap()?;
+    }
+
+    let check = |db: &TestDb| {
+      db.view(|tx| {
+        let b = tx.bucket("widgets").unwrap();
+        for k in 0..400u32 {
+          assert_eq!(Some(b"value".as_slice()), b.get(k.to_be_bytes()));
+        }
+        Ok(())
+      })
+    };
+    check(&db)?;
+    #[cfg(not(any(miri, feature = "test-mem-backend")))]
+    {
+      db.must_close();
+      db.must_reopen();
+      check(&db)?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(feature = "failpoints", not(any(miri, feature = "test-mem-backend"))))]
+  fn test_db_group_commit_sync_failure() -> crate::Result<()> {
+    use crate::{FailAction, FailPoint};
+    for point in [FailPoint::BetweenDataAndMeta, FailPoint::AfterMeta] {
+      let mut db = TestDb::with_options(BoltOptions::builder().group_commit().build())?;
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket("widgets")?;
+        b.put("foo", "bar")?;
+        Ok(())
+      })?;
+
+      db.failpoints().set(point, FailAction::FsyncError);
+      let r = db.update(|mut tx| {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        b.put("foo", "baz")?;
+        Ok(())
+      });
+      assert!(matches!(r, Err(Error::IO(_))));
+      // The next writer must not build on the failed transaction
+      let r = db.update(|mut tx| {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        b.put("bar", "baz")?;
+        Ok(())
+      });
+      assert!(r.is_err());
+
+      let check = |db: &TestDb| {
+        db.view(|tx| {
+          let b = tx.bucket("widgets").unwrap();
+          assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+          assert_eq!(None, b.get("bar"));
+          Ok(())
+        })
+      };
+      check(&db)?;
+      db.must_close();
+      db.must_reopen();
+      check(&db)?;
+      db.update(|mut tx| {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        b.put("foo", "qux")?;
+        Ok(())
+      })?;
+      db.must_check();
+    }
+    Ok(())
+  }
+
   #[test]
   fn test_db_update_closed() -> crate::Result<()> {
     let mut db = TestDb::new()?;
@@ -2542,8 +5184,21 @@ mod test {
   #[test]
   fn test_db_view_error() -> crate::Result<()> {
     let db = TestDb::new()?;
-    let r = db.view(|_| Err(Error::InvalidDatabase(false))).err();
-    assert_eq!(Some(Error::InvalidDatabase(false)), r);
+    let r = db
+      .view(|_| {
+        Err(Error::InvalidDatabase {
+          meta_can_read: false,
+          meta: None,
+        })
+      })
+      .err();
+    assert_eq!(
+      Some(Error::InvalidDatabase {
+        meta_can_read: false,
+        meta: None
+      }),
+      r
+    );
     Ok(())
   }
 
@@ -2567,6 +5222,179 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_db_debug() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+    let debug = format!("{:?}", db.clone_db());
+    assert!(debug.starts_with("Bolt { path: "), "{}", debug);
+    assert!(
+      debug.contains("info: Some(DbInfo { page_size: "),
+      "{}",
+      debug
+    );
+    let stats = format!("{:?}", db.stats());
+    assert!(
+      stats.starts_with("DbStats { tx_stats: TxStats {"),
+      "{}",
+      stats
+    );
+    assert!(stats.contains("pending_page_n: 2"), "{}", stats);
+
+    let closed = Bolt::open_mem()?;
+    let handle = closed.clone();
+    closed.close();
+    assert!(format!("{:?}", handle).ends_with("info: None }"));
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_pending_by_reader() -> crate::Result<()> {
+    let options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    let mut db = TestDb::with_options(options)?;
+    let put = |db: &mut TestDb, value: &str| {
+      db.update(|mut tx| tx.create_bucket_if_not_exists("widgets")?.put("foo", value))
+    };
+    put(&mut db, "a")?;
+    let reader_db = db.clone_db();
+    let old = reader_db.begin()?;
+    put(&mut db, "b")?;
+    let new = reader_db.begin()?;
+    let new2 = reader_db.begin()?;
+    put(&mut db, "c")?;
+
+    let pins = db.stats().pending_by_reader();
+    assert_eq!(2, pins.len());
+    assert_eq!(old.id(), pins[0].txid);
+    assert_eq!(1, pins[0].readers);
+    assert_eq!(new.id(), pins[1].txid);
+    assert_eq!(2, pins[1].readers);
+    assert!(pins.iter().all(|pin| pin.blocked_pages > 0));
+
+    drop(old);
+    assert_eq!(
+      vec![new.id()],
+      db.stats()
+        .pending_by_reader()
+        .iter()
+        .map(|p| p.txid)
+        .collect::<Vec<_>>()
+    );
+    drop(new);
+    drop(new2);
+    assert!(db.stats().pending_by_reader().is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_evict_readers() -> crate::Result<()> {
+    let options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    // Values read by the evicted reader aren't used after it's evicted
+    let options = unsafe { options.evict_readers_after(Duration::from_millis(100)) };
+    let mut db = TestDb::with_options(options)?;
+    db.update(|mut tx| tx.create_bucket("widgets")?.put("foo", "bar"))?;
+    let reader_db = db.clone_db();
+    let old = reader_db.begin()?;
+    let b = old.bucket("widgets").unwrap();
+    assert_eq!(Some(b"bar".as_slice()), b.try_get("foo")?);
+    thread::sleep(Duration::from_millis(150));
+    let young = reader_db.begin()?;
+
+    // Only the reader older than the limit is evicted
+    db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "baz"))?;
+    assert_eq!(Err(Error::TxEvicted), b.try_get("foo"));
+    assert_eq!(Err(Error::TxEvicted), b.cursor().try_seek("foo"));
+    assert_eq!(
+      Some(b"bar".as_slice()),
+      young.bucket("widgets").unwrap().get("foo")
+    );
+    assert_eq!(1, db.stats().evicted_tx_n());
+    assert_eq!(1, db.stats().open_tx_n());
+    let pins = db.stats().pending_by_reader();
+    assert_eq!(
+      vec![young.id()],
+      pins.iter().map(|p| p.txid).collect::<Vec<_>>()
+    );
+
+    // Closing the evicted reader leaves the other reader of its snapshot registered
+    drop(old);
+    assert_eq!(1, db.stats().open_tx_n());
+    drop(young);
+    assert_eq!(0, db.stats().open_tx_n());
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_slow_commit() -> crate::Result<()> {
+    let slow = Arc::new(AtomicUsize::new(0));
+    let options = BoltOptions::builder()
+      .on_slow_commit(Duration::ZERO, {
+        let slow = slow.clone();
+        move |_, tx_stats| {
+          assert!(tx_stats.write() > 0);
+          slow.fetch_add(1, Ordering::SeqCst);
+        }
+      })
+      .build();
+    let mut db = TestDb::with_options(options)?;
+    for _ in 0..3 {
+      db.update(|mut tx| {
+        tx.create_bucket_if_not_exists("widgets")?;
+        Ok(())
+      })?;
+    }
+    assert_eq!(3, slow.load(Ordering::SeqCst));
+    let latency = db.stats().commit_latency().clone();
+    assert_eq!(3, latency.count());
+    assert!(latency.max() >= latency.value_at_quantile(0.5));
+    Ok(())
+  }
+
+  #[test]
+  fn test_db_bucket_metrics() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut a = tx.create_bucket("tenant-a")?;
+      a.put("foo", "bar")?;
+      a.put("baz", "bat")?;
+      a.delete("baz")?;
+      let mut nested = a.create_bucket("nested")?;
+      nested.put("foo", "bar")?;
+      let mut b = tx.create_bucket("tenant-b")?;
+      b.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let a = tx.bucket("tenant-a").unwrap();
+      assert_eq!(Some(b"bar".as_slice()), a.get("foo"));
+      let mut c = a.cursor();
+      c.first();
+      c.next();
+      // The transaction's own counters are up to date before it closes
+      let metrics = tx.stats().bucket_metrics();
+      assert_eq!(1, metrics[b"tenant-a".as_slice()].gets());
+      assert_eq!(2, metrics[b"tenant-a".as_slice()].cursor_ops());
+      Ok(())
+    })?;
+    let metrics = db.stats().bucket_metrics();
+    let a = metrics[b"tenant-a".as_slice()];
+    assert_eq!(1, a.gets());
+    assert_eq!(3, a.puts());
+    assert_eq!(1, a.deletes());
+    assert_eq!(2, a.cursor_ops());
+    let b = metrics[b"tenant-b".as_slice()];
+    assert_eq!(1, b.puts());
+    assert_eq!(0, b.gets());
+    Ok(())
+  }
+
   #[test]
   fn test_db_consistency() -> crate::Result<()> {
     let mut db = TestDb::new()?;
@@ -2614,6 +5442,58 @@ mod test {
     assert_eq!(14, diff.free_page_n());
   }
 
+  #[test]
+  fn test_db_concurrent_readers_and_writer() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let _ = tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+
+    let n = 100u32;
+    let mut w_db = db.clone_db();
+    let writer = thread::spawn(move || -> crate::Result<()> {
+      for i in 0..n {
+        w_db.update(|mut tx| {
+          let mut b = tx.bucket_mut("widgets").unwrap();
+          b.put(i.to_be_bytes(), i.to_be_bytes())
+        })?;
+      }
+      Ok(())
+    });
+
+    let mut readers = Vec::with_capacity(4);
+    for _ in 0..4 {
+      let r_db = db.clone_db();
+      let join = thread::spawn(move || -> crate::Result<()> {
+        let mut last_n = 0;
+        loop {
+          let tx = r_db.begin()?;
+          let b = tx.bucket("widgets").unwrap();
+          let mut key_n = 0;
+          b.for_each(|k, v| {
+            assert_eq!(Some(k), v);
+            key_n += 1;
+            Ok(())
+          })?;
+          // Each read transaction sees a consistent snapshot that never goes backwards
+          assert!(key_n >= last_n);
+          last_n = key_n;
+          if key_n == n as usize {
+            return Ok(());
+          }
+        }
+      });
+      readers.push(join);
+    }
+
+    writer.join().unwrap()?;
+    for reader in readers {
+      reader.join().unwrap()?;
+    }
+    Ok(())
+  }
+
   #[test]
   fn test_db_batch() -> crate::Result<()> {
     let mut db = TestDb::new()?;
@@ -2651,6 +5531,102 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  #[cfg(not(miri))]
+  fn test_db_vacuum_into() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let mut db = TestDb::with_options(
+      BoltOptions::builder()
+        .comparators(comparators.clone())
+        .build(),
+    )?;
+    db.update(|mut tx| {
+      let mut widgets = tx.create_bucket("widgets")?;
+      widgets.set_sequence(3)?;
+      widgets.set_meta("version", "2")?;
+      for i in 0..1000 {
+        widgets.put(format!("{:04}", i), format!("{:0100}", i))?;
+      }
+      {
+        let mut reversed = widgets.create_bucket_with_comparator("reversed", "reverse")?;
+        reversed.put("a", "1")?;
+        reversed.put("b", "2")?;
+      }
+      widgets.create_bucket("cache")?.put("foo", "bar")?;
+      tx.create_bucket_with_comparator("cache", "reverse")?
+        .put("foo", "bar")?;
+      tx.root_put("instance-id", "a1b2")?;
+      Ok(())
+    })?;
+
+    let file = temp_file()?;
+    db.vacuum_into(file.path(), |path| {
+      path.last() == Some(&b"cache".as_slice())
+    })?;
+
+    let dst = BoltOptions::builder()
+      .comparators(comparators)
+      .build()
+      .open(file.path())?;
+    dst.view(|tx| {
+      assert!(tx.check().is_empty());
+      assert!(tx.bucket("cache").is_none());
+      let widgets = tx.bucket("widgets").unwrap();
+      assert_eq!(3, widgets.sequence());
+      assert_eq!(Some(format!("{:0100}", 7).as_bytes()), widgets.get("0007"));
+      assert!(widgets.bucket("cache").is_none());
+      let reversed = widgets.bucket("reversed").unwrap();
+      assert_eq!(
+        Some((b"b".as_slice(), Some(b"2".as_slice()))),
+        reversed.cursor().first()
+      );
+      assert_eq!(Some(b"2".as_slice()), widgets.get_meta("version"));
+      assert_eq!(Some(b"a1b2".as_slice()), tx.root_get("instance-id"));
+      // The comparator of the skipped bucket isn't recorded
+      let meta = BucketImpl::from(tx.tx.system_bucket(META_BUCKET).unwrap());
+      assert_eq!(2, meta.stats().key_n);
+      Ok(())
+    })?;
+    dst.close();
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_db_vacuum_into_quota() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut tenant = tx.create_bucket("tenant")?;
+      tenant.put("a", "1234")?;
+      tenant.create_bucket("cache")?.put("b", "5678")?;
+      tenant.set_quota(Some(BucketQuota {
+        max_bytes: Some(100),
+        max_keys: None,
+      }))?;
+      Ok(())
+    })?;
+
+    let file = temp_file()?;
+    db.vacuum_into(file.path(), |path| path == [b"tenant".as_slice(), b"cache"])?;
+
+    let dst = Bolt::open_ro(file.path())?;
+    dst.view(|tx| {
+      let stats = tx.bucket("tenant").unwrap().stats();
+      assert_eq!(
+        Some(BucketQuota {
+          max_bytes: Some(100),
+          max_keys: None,
+        }),
+        stats.quota()
+      );
+      // The keys of the skipped bucket aren't counted
+      assert_eq!(Some(QuotaUsage { bytes: 5, keys: 1 }), stats.quota_usage());
+      Ok(())
+    })?;
+    dst.close();
+    Ok(())
+  }
+
   #[test]
   #[ignore]
   fn test_db_batch_panic() {
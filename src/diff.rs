@@ -0,0 +1,183 @@
+//! Compares the contents of two transactions.
+//!
+//! [diff] walks both transactions side by side with [TxApi::export_iter] and yields the
+//! differences as it finds them, so neither database is loaded into memory.
+use crate::{ExportIter, ExportRecord, TxApi};
+use std::cmp::Ordering;
+use std::iter::{once, Peekable};
+
+/// A difference between the transactions `a` and `b` compared by [diff]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiffRecord<'a, 'b> {
+  /// A key or bucket that is only in `b`
+  Added(ExportRecord<'b>),
+  /// A key or bucket that is only in `a`
+  Removed(ExportRecord<'a>),
+  /// A key whose value differs, or that is a bucket in one transaction and a value in the
+  /// other
+  Changed {
+    a: ExportRecord<'a>,
+    b: ExportRecord<'b>,
+  },
+}
+
+impl<'a, 'b> DiffRecord<'a, 'b> {
+  /// Returns the names of the bucket holding the key and of its parents
+  pub fn path(&self) -> &[&[u8]] {
+    match self {
+      DiffRecord::Added(b) => &b.path,
+      DiffRecord::Removed(a) => &a.path,
+      DiffRecord::Changed { a, .. } => &a.path,
+    }
+  }
+
+  /// Returns the key
+  pub fn key(&self) -> &[u8] {
+    match self {
+      DiffRecord::Added(b) => b.key,
+      DiffRecord::Removed(a) => a.key,
+      DiffRecord::Changed { a, .. } => a.key,
+    }
+  }
+}
+
+/// The iterator returned by [diff]
+pub struct DiffIter<'a, 'b> {
+  a: Peekable<ExportIter<'a>>,
+  b: Peekable<ExportIter<'b>>,
+}
+
+/// The bucket names and key of a record. Records are exported in the order of their full
+/// paths.
+fn full_path<'r>(record: &'r ExportRecord) -> impl Iterator<Item = &'r [u8]> {
+  record
+    .path
+    .iter()
+    .chain(once(&record.key))
+    .map(|name| &**name)
+}
+
+impl<'a, 'b> Iterator for DiffIter<'a, 'b> {
+  type Item = DiffRecord<'a, 'b>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let order = match (self.a.peek(), self.b.peek()) {
+        (None, None) => return None,
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(a), Some(b)) => full_path(a).cmp(full_path(b)),
+      };
+      match order {
+        Ordering::Less => return self.a.next().map(DiffRecord::Removed),
+        Ordering::Greater => return self.b.next().map(DiffRecord::Added),
+        Ordering::Equal => {
+          let a = self.a.next().unwrap();
+          let b = self.b.next().unwrap();
+          if a.value != b.value {
+            return Some(DiffRecord::Changed { a, b });
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Returns the keys and buckets added to, removed from and changed between the
+/// transactions `a` and `b`. Everything in an added or removed bucket is reported too.
+///
+/// Differences are found while iterating. Buckets are expected to use the default key
+/// order, since the transactions are walked in bytewise order.
+///
+/// ```rust
+/// use bbolt_rs::*;
+/// use bbolt_rs::diff::{diff, DiffRecord};
+///
+/// fn main() -> Result<()> {
+///   let mut backup = Bolt::open_mem()?;
+///   let mut db = Bolt::open_mem()?;
+///
+///   backup.update(|mut tx| {
+///     tx.create_bucket("test")?.put("key", "value")?;
+///     Ok(())
+///   })?;
+///   db.update(|mut tx| {
+///     tx.create_bucket("test")?.put("key", "new value")?;
+///     Ok(())
+///   })?;
+///
+///   backup.view(|a| {
+///     db.view(|b| {
+///       let changes = diff(&a, &b).collect::<Vec<_>>();
+///       assert_eq!(1, changes.len());
+///       assert!(matches!(changes[0], DiffRecord::Changed { .. }));
+///       assert_eq!(b"key", changes[0].key());
+///       Ok(())
+///     })
+///   })?;
+///
+///   Ok(())
+/// }
+/// ```
+pub fn diff<'a, 'b>(a: &impl TxApi<'a>, b: &impl TxApi<'b>) -> DiffIter<'a, 'b> {
+  DiffIter {
+    a: a.export_iter().peekable(),
+    b: b.export_iter().peekable(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::diff::{diff, DiffRecord};
+  use crate::test_support::TestDb;
+  use crate::{BucketRwApi, DbApi, DbRwAPI, TxRwRefApi};
+
+  #[test]
+  fn test_diff() -> crate::Result<()> {
+    let mut a = TestDb::new()?;
+    let mut b = TestDb::new()?;
+    a.update(|mut tx| {
+      let mut widgets = tx.create_bucket("widgets")?;
+      widgets.put("same", "1")?;
+      widgets.put("changed", "1")?;
+      widgets.put("removed", "1")?;
+      widgets.put("type", "1")?;
+      tx.create_bucket("old")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    b.update(|mut tx| {
+      let mut widgets = tx.create_bucket("widgets")?;
+      widgets.put("same", "1")?;
+      widgets.put("changed", "2")?;
+      widgets.put("added", "1")?;
+      widgets.create_bucket("type")?;
+      Ok(())
+    })?;
+    a.view(|ta| {
+      b.view(|tb| {
+        let changes = diff(&ta, &tb)
+          .map(|d| {
+            let kind = match &d {
+              DiffRecord::Added(_) => '+',
+              DiffRecord::Removed(_) => '-',
+              DiffRecord::Changed { .. } => '~',
+            };
+            (kind, d.path().concat(), d.key().to_vec())
+          })
+          .collect::<Vec<_>>();
+        let expected = [
+          ('-', "", "old"),
+          ('-', "old", "foo"),
+          ('+', "widgets", "added"),
+          ('~', "widgets", "changed"),
+          ('-', "widgets", "removed"),
+          ('~', "widgets", "type"),
+        ]
+        .map(|(kind, path, key)| (kind, path.as_bytes().to_vec(), key.as_bytes().to_vec()));
+        assert_eq!(expected.to_vec(), changes);
+        Ok(())
+      })
+    })?;
+    Ok(())
+  }
+}
@@ -0,0 +1,125 @@
+use crate::comparator::is_reserved;
+use crate::{BucketApi, BucketImpl, CursorApi, CursorImpl};
+use std::rc::Rc;
+
+/// A record of [ExportIter]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExportRecord<'tx> {
+  /// the names of the bucket holding the key and of its parents, starting with the
+  /// top-level bucket. Empty for the top-level buckets themselves. Shared by the records
+  /// of a bucket.
+  pub path: Rc<[&'tx [u8]]>,
+  /// the key
+  pub key: &'tx [u8],
+  /// the value, or None if the key is a bucket
+  pub value: Option<&'tx [u8]>,
+}
+
+impl<'tx> ExportRecord<'tx> {
+  /// Returns whether the record is a bucket
+  pub fn is_bucket(&self) -> bool {
+    self.value.is_none()
+  }
+}
+
+/// An open bucket, its cursor, whether it was started and its path
+type Frame<'tx> = (BucketImpl<'tx>, CursorImpl<'tx>, bool, Rc<[&'tx [u8]]>);
+
+/// ExportIter yields every bucket and key/value pair of a transaction. See
+/// [TxApi::export_iter](crate::TxApi::export_iter)
+///
+/// Records are yielded depth first in key order: a bucket comes right before its contents.
+/// The database's own records aren't exported, see [RESERVED_PREFIX](crate::RESERVED_PREFIX).
+pub struct ExportIter<'tx> {
+  /// the open buckets from the root down
+  stack: Vec<Frame<'tx>>,
+}
+
+impl<'tx> ExportIter<'tx> {
+  pub(crate) fn new(root: BucketImpl<'tx>) -> ExportIter<'tx> {
+    let cursor = root.cursor();
+    ExportIter {
+      stack: vec![(root, cursor, false, Rc::from([]))],
+    }
+  }
+}
+
+impl<'tx> Iterator for ExportIter<'tx> {
+  type Item = ExportRecord<'tx>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let (bucket, cursor, started, path) = self.stack.last_mut()?;
+      let entry = if *started {
+        cursor.next()
+      } else {
+        *started = true;
+        cursor.first()
+      };
+      match entry {
+        None => {
+          self.stack.pop();
+        }
+        // The reserved buckets are top-level buckets
+        Some((key, _)) if path.is_empty() && is_reserved(key) => (),
+        Some((key, value)) => {
+          let record = ExportRecord {
+            path: path.clone(),
+            key,
+            value,
+          };
+          if value.is_none() {
+            let child = bucket.bucket(key).unwrap();
+            let cursor = child.cursor();
+            let child_path = path.iter().copied().chain([key]).collect();
+            self.stack.push((child, cursor, false, child_path));
+          }
+          return Some(record);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_support::TestDb;
+  use crate::{BucketRwApi, DbApi, DbRwAPI, TxApi, TxRwRefApi};
+
+  #[test]
+  fn test_export_iter() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("b")?;
+      b.put("2", "two")?;
+      b.put("1", "one")?;
+      b.create_bucket("c")?.put("x", "y")?;
+      tx.create_bucket("a")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let records = tx
+        .export_iter()
+        .map(|r| (r.path.to_vec(), r.key, r.value))
+        .collect::<Vec<_>>();
+      let b: &[u8] = b"b";
+      assert_eq!(
+        vec![
+          (vec![], b"a".as_slice(), None),
+          (vec![], b, None),
+          (vec![b], b"1".as_slice(), Some(b"one".as_slice())),
+          (vec![b], b"2".as_slice(), Some(b"two".as_slice())),
+          (vec![b], b"c".as_slice(), None),
+          (
+            vec![b, b"c".as_slice()],
+            b"x".as_slice(),
+            Some(b"y".as_slice())
+          ),
+        ],
+        records
+      );
+      Ok(())
+    })?;
+    Ok(())
+  }
+}
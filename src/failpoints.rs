@@ -0,0 +1,82 @@
+use crate::Error;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io;
+
+/// A point during commit where a failure can be injected
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FailPoint {
+  /// After the transaction has been spilled, before any data page is written
+  BeforeDataWrite,
+  /// After the data pages are written and synced, before the meta page is written
+  BetweenDataAndMeta,
+  /// After the meta page is written, before it is synced
+  AfterMeta,
+}
+
+/// The failure injected once a [FailPoint] is reached
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FailAction {
+  /// The next write only writes the given number of bytes and then fails
+  ShortWrite(usize),
+  /// The next fsync fails without syncing
+  FsyncError,
+  /// The process aborts immediately
+  Abort,
+}
+
+/// FailPoints injects failures into the commit path of a database.
+///
+/// Each action fires once. Use it to verify that a crash or I/O error at any
+/// point of a commit leaves the database in its previous or its new state.
+#[derive(Debug, Default)]
+pub struct FailPoints {
+  actions: Mutex<HashMap<FailPoint, FailAction>>,
+  armed: Mutex<Option<FailAction>>,
+}
+
+impl FailPoints {
+  /// Sets the action to take the next time `point` is reached.
+  pub fn set(&self, point: FailPoint, action: FailAction) {
+    self.actions.lock().insert(point, action);
+  }
+
+  /// Removes all pending actions.
+  pub fn clear(&self) {
+    self.actions.lock().clear();
+    *self.armed.lock() = None;
+  }
+
+  /// Called when the commit reaches `point`
+  pub(crate) fn hit(&self, point: FailPoint) {
+    if let Some(action) = self.actions.lock().remove(&point) {
+      match action {
+        FailAction::Abort => std::process::abort(),
+        action => *self.armed.lock() = Some(action),
+      }
+    }
+  }
+
+  /// Returns the number of bytes to write if a short write is armed
+  pub(crate) fn take_short_write(&self) -> Option<usize> {
+    let mut armed = self.armed.lock();
+    if let Some(FailAction::ShortWrite(n)) = *armed {
+      *armed = None;
+      return Some(n);
+    }
+    None
+  }
+
+  /// Returns an error if a fsync error is armed
+  pub(crate) fn take_fsync_error(&self) -> crate::Result<()> {
+    let mut armed = self.armed.lock();
+    if let Some(FailAction::FsyncError) = *armed {
+      *armed = None;
+      return Err(Error::IO(io::Error::new(
+        io::ErrorKind::Other,
+        "failpoint: fsync error",
+      )));
+    }
+    Ok(())
+  }
+}
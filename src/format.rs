@@ -0,0 +1,301 @@
+//! Database file format versions.
+//!
+//! Every meta page records the version of the file format. [detect] reads it without opening
+//! the database and [migrate] upgrades a file in place when the newer format can read all of
+//! the file's pages as they are.
+use crate::arch::aligned::{alignment, AlignedBytes};
+pub use crate::common::defaults::SUPPORTED_VERSIONS;
+use crate::common::defaults::{has_page_checksums, has_prefix_branch, MAGIC};
+use crate::common::meta::{MappedMetaPage, Meta};
+use crate::common::page::{CoerciblePage, RefPage};
+use crate::Error;
+use fs4::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// FormatVersion describes the file format of a database file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FormatVersion {
+  /// the version recorded in the file's newest meta page
+  pub found: u32,
+  /// the versions this build can open
+  pub supported: &'static [u32],
+}
+
+impl FormatVersion {
+  /// Returns whether this build can open the file
+  pub fn is_supported(&self) -> bool {
+    self.supported.contains(&self.found)
+  }
+
+  /// Returns whether [migrate] can upgrade the file to `target` in place
+  pub fn can_migrate_to(&self, target: u32) -> bool {
+    self.found == target || can_upgrade(self.found, target)
+  }
+
+  fn mismatch(&self) -> Error {
+    Error::VersionMismatch {
+      found: self.found,
+      supported: self.supported,
+    }
+  }
+}
+
+/// Returns whether every page of a `from` file can be read as a `to` file.
+///
+/// Prefix compressed branch pages and checksummed pages only add page types and flags, so
+/// a file is valid as it is in a format with more of them.
+fn can_upgrade(from: u32, to: u32) -> bool {
+  from != to
+    && SUPPORTED_VERSIONS.contains(&from)
+    && SUPPORTED_VERSIONS.contains(&to)
+    && (!has_prefix_branch(from) || has_prefix_branch(to))
+    && (!has_page_checksums(from) || has_page_checksums(to))
+}
+
+/// Reads the meta page stored at `offset`. Returns None unless the magic number and
+/// checksum are valid. The version isn't checked.
+fn read_meta(file: &mut File, offset: u64) -> crate::Result<Option<Meta>> {
+  // we need this aligned to Page so we don't hit any runtime issues
+  let mut buffer = AlignedBytes::<alignment::Page>::new_zeroed(4096);
+  file.seek(SeekFrom::Start(offset))?;
+  let mut read = 0;
+  while read < buffer.len() {
+    match file.read(&mut buffer[read..])? {
+      0 => break,
+      n => read += n,
+    }
+  }
+  let meta = MappedMetaPage::coerce_ref(&RefPage::new(buffer.as_ptr())).map(|page| page.meta);
+  Ok(meta.filter(|meta| meta.magic() == MAGIC && meta.checksum() == meta.sum64()))
+}
+
+/// Reads both meta pages. The second meta page is located with the page size of the
+/// first one, or searched for like [crate::Bolt::open] does if the first one is invalid.
+fn read_metas(file: &mut File) -> crate::Result<[Option<Meta>; 2]> {
+  let meta_0 = read_meta(file, 0)?;
+  let meta_1 = match meta_0 {
+    Some(meta) => read_meta(file, meta.page_size() as u64)?,
+    None => {
+      let file_size = file.metadata()?.len();
+      let mut meta_1 = None;
+      for i in 0..15u64 {
+        let pos = 1024u64 << i;
+        if file_size < 1024 || pos >= file_size - 1024 {
+          break;
+        }
+        meta_1 = read_meta(file, pos)?.filter(|meta| meta.page_size() as u64 == pos);
+        if meta_1.is_some() {
+          break;
+        }
+      }
+      meta_1
+    }
+  };
+  if meta_0.is_none() && meta_1.is_none() {
+    return Err(Error::InvalidDatabase {
+      meta_can_read: false,
+      meta: None,
+    });
+  }
+  Ok([meta_0, meta_1])
+}
+
+fn newest(metas: &[Option<Meta>; 2]) -> Meta {
+  metas
+    .iter()
+    .flatten()
+    .max_by_key(|meta| meta.txid())
+    .copied()
+    .unwrap()
+}
+
+/// Writes `meta` to its meta page and syncs the file
+fn write_meta(file: &mut File, meta: &Meta) -> crate::Result<()> {
+  let page_size = meta.page_size() as usize;
+  let mut buffer = AlignedBytes::<alignment::Page>::new_zeroed(page_size);
+  let mut meta_page = unsafe { MappedMetaPage::new(buffer.as_mut_ptr()) };
+  meta.write(&mut meta_page);
+  file.seek(SeekFrom::Start(meta_page.page.id.0 * page_size as u64))?;
+  file.write_all(&buffer)?;
+  file.sync_all()?;
+  Ok(())
+}
+
+/// Reads the format version of the database file at `path` without opening it.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let dir = std::env::temp_dir().join(format!("bbolt-format-{}", std::process::id()));
+///   std::fs::create_dir_all(&dir)?;
+///   let path = dir.join("format.db");
+///   std::fs::File::create(&path)?;
+///   Bolt::open(&path)?.close();
+///
+///   let version = format::detect(&path)?;
+///   assert!(version.is_supported());
+///
+///   std::fs::remove_dir_all(&dir)?;
+///   Ok(())
+/// }
+/// ```
+pub fn detect<P: AsRef<Path>>(path: P) -> crate::Result<FormatVersion> {
+  let mut file = OpenOptions::new().read(true).open(path)?;
+  FileExt::lock_shared(&file)?;
+  let meta = newest(&read_metas(&mut file)?);
+  Ok(FormatVersion {
+    found: meta.version(),
+    supported: SUPPORTED_VERSIONS,
+  })
+}
+
+/// Upgrades the database file at `path` to the format `target` in place.
+///
+/// Only the meta pages are rewritten, so a file can only be migrated to a format that reads
+/// all of its pages as they are. Returns [Error::VersionMismatch] with the found version if
+/// the file can't be migrated or if this build can't open `target` files. The database must
+/// not be open while it's migrated.
+pub fn migrate<P: AsRef<Path>>(path: P, target: u32) -> crate::Result<FormatVersion> {
+  let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+  FileExt::lock_exclusive(&file)?;
+  let metas = read_metas(&mut file)?;
+  let version = FormatVersion {
+    found: newest(&metas).version(),
+    supported: SUPPORTED_VERSIONS,
+  };
+  if !SUPPORTED_VERSIONS.contains(&target) || !version.can_migrate_to(target) {
+    return Err(version.mismatch());
+  }
+  if version.found == target {
+    return Ok(version);
+  }
+
+  // The older meta is rewritten and synced first, so a crash tears at most one of them
+  let mut metas = metas.into_iter().flatten().collect::<Vec<_>>();
+  metas.sort_by_key(|meta| meta.txid());
+  for mut meta in metas {
+    if !can_upgrade(meta.version(), target) {
+      continue;
+    }
+    meta.set_version(target);
+    write_meta(&mut file, &meta)?;
+  }
+  Ok(FormatVersion {
+    found: target,
+    supported: SUPPORTED_VERSIONS,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use crate::arch::aligned::{alignment, AlignedBytes};
+  use crate::common::meta::MappedMetaPage;
+  use crate::format::{detect, migrate, SUPPORTED_VERSIONS};
+  use crate::test_support::temp_file;
+  use crate::{Bolt, DbApi, Error};
+  use std::io::{Read, Seek, SeekFrom, Write};
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_format_detect_and_migrate() -> crate::Result<()> {
+    let mut file = temp_file()?;
+    Bolt::open(file.path())?.close();
+    let version = detect(file.path())?;
+    assert_eq!(SUPPORTED_VERSIONS[0], version.found);
+    assert!(version.is_supported());
+
+    // Pretend the file was written by a newer version
+    let mut bytes = AlignedBytes::<alignment::Page>::new_zeroed(4096);
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut bytes)?;
+    let mut meta_page = unsafe { MappedMetaPage::new(bytes.as_mut_ptr()) };
+    let mut meta = meta_page.meta;
+    meta.set_version(99);
+    meta.write(&mut meta_page);
+    let page_size = meta.page_size() as u64;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&bytes)?;
+    file.as_file().sync_all()?;
+    file.seek(SeekFrom::Start(page_size))?;
+    file.write_all(&bytes)?;
+    file.as_file().sync_all()?;
+
+    let version = detect(file.path())?;
+    assert_eq!(99, version.found);
+    assert!(!version.is_supported());
+    let mismatch = Bolt::open(file.path()).err();
+    assert!(
+      matches!(mismatch, Some(Error::VersionMismatch { found: 99, supported }) if supported == SUPPORTED_VERSIONS)
+    );
+    let r = migrate(file.path(), SUPPORTED_VERSIONS[0]);
+    assert!(matches!(r, Err(Error::VersionMismatch { found: 99, .. })));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  #[cfg(feature = "prefix-branch")]
+  fn test_format_migrate_prefix_branch() -> crate::Result<()> {
+    use crate::common::defaults::{VERSION, VERSION_PREFIX_BRANCH};
+    use crate::TxCheck;
+
+    let file = temp_file()?;
+    Bolt::open(file.path())?.close();
+    assert_eq!(VERSION, detect(file.path())?.found);
+    let version = migrate(file.path(), VERSION_PREFIX_BRANCH)?;
+    assert_eq!(VERSION_PREFIX_BRANCH, version.found);
+    assert_eq!(VERSION_PREFIX_BRANCH, detect(file.path())?.found);
+    let db = Bolt::open(file.path())?;
+    db.view(|tx| {
+      assert!(tx.check().is_empty());
+      Ok(())
+    })?;
+    db.close();
+
+    let r = migrate(file.path(), VERSION);
+    assert!(matches!(
+      r,
+      Err(Error::VersionMismatch {
+        found: VERSION_PREFIX_BRANCH,
+        ..
+      })
+    ));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_format_migrate_page_checksums() -> crate::Result<()> {
+    use crate::common::defaults::{VERSION, VERSION_PAGE_CHECKSUMS};
+    use crate::{BoltOptions, BucketRwApi, DbRwAPI, TxRwRefApi};
+
+    let file = temp_file()?;
+    Bolt::open(file.path())?.close();
+    let version = migrate(file.path(), VERSION_PAGE_CHECKSUMS)?;
+    assert_eq!(VERSION_PAGE_CHECKSUMS, version.found);
+    let mut db = BoltOptions::builder()
+      .page_checksums()
+      .build()
+      .open(file.path())?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.close();
+    assert_eq!(VERSION_PAGE_CHECKSUMS, detect(file.path())?.found);
+
+    // Checksummed pages can't be read as plain pages
+    let r = migrate(file.path(), VERSION);
+    assert!(matches!(
+      r,
+      Err(Error::VersionMismatch {
+        found: VERSION_PAGE_CHECKSUMS,
+        ..
+      })
+    ));
+    Ok(())
+  }
+}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Formatter;
 use std::iter::{repeat, zip};
 use std::marker::PhantomData;
@@ -14,6 +14,7 @@ use crate::common::page::PageHeader;
 use crate::common::page::{CoerciblePage, FREE_LIST_PAGE_FLAG, PAGE_HEADER_SIZE};
 use crate::common::utility::is_sorted;
 use crate::common::{PgId, TxId};
+use crate::Error;
 
 pub struct MappedFreeListPage {
   bytes: *mut u8,
@@ -134,6 +135,18 @@ impl MappedFreeListPage {
   }
 }
 
+/// `FreelistAllocStrategy` selects the free span used for an allocation
+/// that has no exact size match.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum FreelistAllocStrategy {
+  /// Use the first span that is large enough
+  #[default]
+  FirstFit,
+  /// Use the smallest span that is large enough. Keeps large free runs intact
+  /// at the cost of a full scan of the free spans.
+  BestFit,
+}
+
 #[derive(Debug)]
 pub struct Freelist {
   pub(crate) allocs: HashMap<PgId, TxId>,
@@ -142,6 +155,8 @@ pub struct Freelist {
   free_maps: HashMap<u64, HashSet<PgId>>,
   forward_map: HashMap<PgId, u64>,
   backward_map: HashMap<PgId, u64>,
+  strategy: FreelistAllocStrategy,
+  prefer_low_pgid: bool,
 }
 
 impl fmt::Display for Freelist {
@@ -187,9 +202,20 @@ impl Freelist {
       free_maps: HashMap::new(),
       forward_map: HashMap::new(),
       backward_map: HashMap::new(),
+      strategy: FreelistAllocStrategy::default(),
+      prefer_low_pgid: false,
     }
   }
 
+  /// set_alloc_policy sets the span selection strategy and whether spans
+  /// with lower page ids are preferred.
+  pub(crate) fn set_alloc_policy(
+    &mut self, strategy: FreelistAllocStrategy, prefer_low_pgid: bool,
+  ) {
+    self.strategy = strategy;
+    self.prefer_low_pgid = prefer_low_pgid;
+  }
+
   /// returns count of free pages
   pub(crate) fn free_count(&self) -> u64 {
     self.forward_map.values().sum()
@@ -200,6 +226,16 @@ impl Freelist {
     self.pending.values().map(|txp| txp.ids.len() as u64).sum()
   }
 
+  /// span_count returns the number of contiguous free spans
+  pub(crate) fn span_count(&self) -> u64 {
+    self.forward_map.len() as u64
+  }
+
+  /// max_span returns the size of the largest contiguous free span
+  pub(crate) fn max_span(&self) -> u64 {
+    self.forward_map.values().copied().max().unwrap_or(0)
+  }
+
   /// count returns count of pages on the freelist
   pub(crate) fn count(&self) -> u64 {
     self.free_count() + self.pending_count()
@@ -220,6 +256,48 @@ impl Freelist {
     m
   }
 
+  /// returns the sorted pending page ids of each transaction that freed pages
+  pub(crate) fn pending_page_ids(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self
+      .pending
+      .iter()
+      .map(|(&txid, txp)| {
+        let mut ids = txp.ids.clone();
+        ids.sort();
+        (txid, ids)
+      })
+      .collect()
+  }
+
+  /// returns how many pending pages each of the sorted `readers` keeps from being released.
+  /// A page freed by `tid` is blocked by every reader that began after the page was
+  /// allocated and before `tid` committed.
+  ///
+  /// Each page only marks where its range of readers starts and ends. A single sweep over
+  /// the readers adds the ranges up, so the cost doesn't grow with pages times readers.
+  pub(crate) fn pending_by_reader(&self, readers: &[TxId]) -> Vec<u64> {
+    let mut deltas = vec![0i64; readers.len() + 1];
+    for (&tid, txp) in &self.pending {
+      let end = readers.partition_point(|&r| r < tid);
+      for i in 0..txp.ids.len() {
+        let alloc_txid = txp.alloc_tx.get(i).copied().unwrap_or_default();
+        let start = readers.partition_point(|&r| r < alloc_txid);
+        if start < end {
+          deltas[start] += 1;
+          deltas[end] -= 1;
+        }
+      }
+    }
+    let mut blocked = 0i64;
+    deltas[..readers.len()]
+      .iter()
+      .map(|delta| {
+        blocked += delta;
+        blocked as u64
+      })
+      .collect()
+  }
+
   /// copy_all copies a list of all free ids and all pending ids in one sorted list.
   pub(crate) fn copy_all(&self, dst: &mut [PgId]) {
     let mut pending_ids = Vec::with_capacity(self.pending_count() as usize);
@@ -260,37 +338,46 @@ impl Freelist {
       return None;
     }
     // if we have a exact size match just return short path
-    if let Some(pgid) = self
-      .free_maps
-      .get(&page_count)
-      .iter()
-      .flat_map(|set| set.iter())
-      .copied()
-      .next()
-    {
-      self.del_span(pgid, page_count);
-      self.allocs.insert(pgid, txid);
-      for i in 0..page_count {
-        self.cache.remove(&(pgid + i));
+    if !self.prefer_low_pgid {
+      if let Some(pgid) = self
+        .free_maps
+        .get(&page_count)
+        .iter()
+        .flat_map(|set| set.iter())
+        .copied()
+        .next()
+      {
+        self.del_span(pgid, page_count);
+        self.allocs.insert(pgid, txid);
+        for i in 0..page_count {
+          self.cache.remove(&(pgid + i));
+        }
+        return Some(pgid);
       }
-      return Some(pgid);
     }
 
     // lookup the map to find larger span
-    if let Some((size, pgid)) = self
+    let mut spans = self
       .free_maps
       .iter()
       .filter(|(&size, _)| size >= page_count)
-      .flat_map(|(&size, pgids)| zip(repeat(size), pgids.iter().copied()))
-      .next()
-    {
+      .flat_map(|(&size, pgids)| zip(repeat(size), pgids.iter().copied()));
+    let span = match (self.strategy, self.prefer_low_pgid) {
+      (FreelistAllocStrategy::FirstFit, false) => spans.next(),
+      (FreelistAllocStrategy::FirstFit, true) => spans.min_by_key(|&(_, pgid)| pgid),
+      (FreelistAllocStrategy::BestFit, false) => spans.min_by_key(|&(size, _)| size),
+      (FreelistAllocStrategy::BestFit, true) => spans.min(),
+    };
+    if let Some((size, pgid)) = span {
       // remove the initial
       self.del_span(pgid, size);
       self.allocs.insert(pgid, txid);
       let remain = size - page_count;
 
       // add remain span
-      self.add_span(pgid + page_count, remain);
+      if remain > 0 {
+        self.add_span(pgid + page_count, remain);
+      }
       for i in 0..page_count {
         self.cache.remove(&(pgid + i));
       }
@@ -300,9 +387,27 @@ impl Freelist {
   }
 
   /// free releases a page and its overflow for a given transaction id.
-  /// If the page is already free then a panic will occur.
-  pub(crate) fn free(&mut self, txid: TxId, p: &PageHeader) {
-    assert!(u64::from(p.id) > 1, "Cannot free page 0 or 1: {}", p.id);
+  ///
+  /// Returns [Error::InvalidFree] without changing the freelist if the page is a meta page
+  /// or if it or one of its overflow pages is already free. `context` names the caller.
+  pub(crate) fn free(
+    &mut self, txid: TxId, p: &PageHeader, context: &'static str,
+  ) -> crate::Result<()> {
+    let invalid = |pgid, reason| Error::InvalidFree {
+      pgid,
+      txid,
+      context,
+      reason,
+    };
+    if u64::from(p.id) <= 1 {
+      return Err(invalid(p.id, "meta page"));
+    }
+    // Verify that no page is already free.
+    for id in p.id.0..=p.id.0 + p.overflow as u64 {
+      if self.cache.contains(&PgId(id)) {
+        return Err(invalid(PgId(id), "already free"));
+      }
+    }
 
     // Free page and all its overflow pages.
     let txp = self.pending.entry(txid).or_insert_with(TxPending::new);
@@ -319,15 +424,12 @@ impl Freelist {
 
     for id in p.id.0..=p.id.0 + p.overflow as u64 {
       let pgid_i = PgId(id);
-      // Verify that page is not already free.
-      if self.cache.contains(&pgid_i) {
-        panic!("Page {} already freed", pgid_i);
-      }
       // Add to the freelist and cache.
       txp.ids.push(pgid_i);
       txp.alloc_tx.push(alloc_tx_id);
       self.cache.insert(pgid_i);
     }
+    Ok(())
   }
 
   /// merges pid to the existing free spans, try to merge it backward and forward
@@ -431,6 +533,8 @@ impl Freelist {
       // Remove pages from pending list and mark as free if allocated by txid.
       self.merge_spans(m);
     }
+    // Forget the pages allocated by txid. They are returned by reloading the freelist.
+    self.allocs.retain(|_, tx| *tx != txid);
   }
 
   /// freed returns whether a given page is in the free list.
@@ -438,6 +542,13 @@ impl Freelist {
     self.cache.contains(&pgid)
   }
 
+  /// Returns the first of `ids` that is in the free list, and whether it is pending
+  pub(crate) fn find_freed(&self, ids: &[PgId]) -> Option<(PgId, bool)> {
+    let id = *ids.iter().find(|id| self.cache.contains(id))?;
+    let pending = self.pending.values().any(|txp| txp.ids.contains(&id));
+    Some((id, pending))
+  }
+
   pub(crate) fn reindex(&mut self) {
     let ids = self.free_page_ids();
     let pending_count: usize = self
@@ -458,10 +569,20 @@ impl Freelist {
   }
 
   /// read initializes the freelist from a freelist page.
+  ///
+  /// Go bbolt writes the same page layout for its array and hashmap freelist types: the
+  /// page id count, extended into the first element once it reaches 0xFFFF, followed by
+  /// the ids. Like Go, the ids are sorted before use as the writer doesn't guarantee it.
   pub(crate) fn read(&mut self, page: &MappedFreeListPage) {
     // Copy the list of page ids from the freelist.
     let data = page.page_ids();
-    self.read_ids(data);
+    if is_sorted(data) {
+      self.read_ids(data);
+    } else {
+      let mut ids = data.to_vec();
+      ids.sort_unstable();
+      self.read_ids(&ids);
+    }
   }
 
   /// write writes the page ids onto a freelist page. All free and pending ids are
@@ -546,8 +667,9 @@ mod tests {
   use crate::common::ids::{pd, td};
   use crate::common::page::PageHeader;
   use crate::common::{PgId, TxId};
-  use crate::freelist::{Freelist, MappedFreeListPage, TxPending};
+  use crate::freelist::{Freelist, FreelistAllocStrategy, MappedFreeListPage, TxPending};
   use crate::test_support::mapped_page;
+  use crate::Error;
 
   fn hashset(ids: &[u64]) -> HashSet<PgId> {
     let mut set = HashSet::new();
@@ -563,7 +685,7 @@ mod tests {
       id: pd(12),
       ..Default::default()
     };
-    f.free(td(100), &p);
+    f.free(td(100), &p, "test").unwrap();
     assert_eq!(&[12], &f.pending.get(&td(100)).unwrap().ids.as_slice())
   }
 
@@ -576,13 +698,52 @@ mod tests {
       overflow: 3,
       ..Default::default()
     };
-    f.free(td(100), &p);
+    f.free(td(100), &p, "test").unwrap();
     assert_eq!(
       &[12, 13, 14, 15],
       &f.pending.get(&td(100)).unwrap().ids.as_slice()
     )
   }
 
+  #[test]
+  // Ensure that freeing a free page or a meta page is an error and changes nothing.
+  fn freelist_free_invalid() {
+    let mut f = Freelist::new();
+    f.free(
+      td(100),
+      &PageHeader {
+        id: pd(14),
+        ..Default::default()
+      },
+      "test",
+    )
+    .unwrap();
+    let overlapping = PageHeader {
+      id: pd(12),
+      overflow: 3,
+      ..Default::default()
+    };
+    match f.free(td(101), &overlapping, "test") {
+      Err(Error::InvalidFree { pgid, context, .. }) => {
+        assert_eq!(pd(14), pgid);
+        assert_eq!("test", context);
+      }
+      r => panic!("expected InvalidFree, got {:?}", r),
+    }
+    let meta = PageHeader::default();
+    assert_eq!(
+      Err(Error::InvalidFree {
+        pgid: pd(0),
+        txid: td(101),
+        context: "test",
+        reason: "meta page"
+      }),
+      f.free(td(101), &meta, "test")
+    );
+    assert!(!f.pending.contains_key(&td(101)));
+    assert_eq!(&[14], &f.pending.get(&td(100)).unwrap().ids.as_slice());
+  }
+
   #[test]
   // Ensure that a transaction's free pages can be released.
   fn freelist_release() {
@@ -594,21 +755,27 @@ mod tests {
         overflow: 1,
         ..Default::default()
       },
-    );
+      "test",
+    )
+    .unwrap();
     f.free(
       td(100),
       &PageHeader {
         id: pd(9),
         ..Default::default()
       },
-    );
+      "test",
+    )
+    .unwrap();
     f.free(
       td(102),
       &PageHeader {
         id: pd(39),
         ..Default::default()
       },
-    );
+      "test",
+    )
+    .unwrap();
     f.release(td(100));
     f.release(td(101));
     assert_eq!(&[9, 12, 13], f.free_page_ids().as_slice());
@@ -616,6 +783,35 @@ mod tests {
     assert_eq!(&[9, 12, 13, 39], f.free_page_ids().as_slice());
   }
 
+  #[test]
+  // Ensure that a pending page only blocks the readers between its allocation and its free.
+  fn freelist_pending_by_reader() {
+    let mut f = Freelist::new();
+    f.allocs.insert(pd(20), td(5));
+    f.free(
+      td(10),
+      &PageHeader {
+        id: pd(20),
+        overflow: 1,
+        ..Default::default()
+      },
+      "test",
+    )
+    .unwrap();
+    f.free(
+      td(12),
+      &PageHeader {
+        id: pd(30),
+        ..Default::default()
+      },
+      "test",
+    )
+    .unwrap();
+    let readers = [td(3), td(5), td(9), td(11), td(12)];
+    assert_eq!(vec![1, 3, 3, 1, 0], f.pending_by_reader(&readers));
+    assert!(f.pending_by_reader(&[]).is_empty());
+  }
+
   #[test]
   fn freelist_release_range() {
     #[derive(Debug, Copy, Clone, Default)]
@@ -753,7 +949,9 @@ mod tests {
             overflow: p.n as u32 - 1,
             ..Default::default()
           },
-        );
+          "test",
+        )
+        .unwrap();
       }
       for r in &c.release_ranges {
         f.release_range(r.b, r.e);
@@ -787,6 +985,77 @@ mod tests {
     assert_eq!(3, f.free_count());
   }
 
+  #[test]
+  // Ensure that a rollback forgets the transaction's allocations.
+  fn freelist_rollback() {
+    let mut f = Freelist::new();
+    f.read_ids(&[3, 4, 5, 6].iter().cloned().map(pd).collect_vec());
+    assert_eq!(Some(pd(3)), f.allocate(td(1), 1));
+    assert_eq!(Some(pd(4)), f.allocate(td(2), 2));
+    f.free(
+      td(2),
+      &PageHeader {
+        id: pd(3),
+        ..Default::default()
+      },
+      "test",
+    )
+    .unwrap();
+    f.free(
+      td(2),
+      &PageHeader {
+        id: pd(4),
+        ..Default::default()
+      },
+      "test",
+    )
+    .unwrap();
+
+    f.rollback(td(2));
+    assert!(f.pending.is_empty());
+    assert_eq!(Some(&td(1)), f.allocs.get(&pd(3)));
+    assert_eq!(None, f.allocs.get(&pd(4)));
+    assert_eq!(None, f.allocs.get(&pd(5)));
+    assert_eq!(vec![pd(4), pd(6)], f.free_page_ids());
+  }
+
+  #[test]
+  fn freelist_allocate_policy() {
+    let ids = [3, 4, 5, 6, 7, 9, 12, 13, 18, 20, 21]
+      .iter()
+      .cloned()
+      .map(pd)
+      .collect_vec();
+
+    let mut f = Freelist::new();
+    f.set_alloc_policy(FreelistAllocStrategy::BestFit, false);
+    f.read_ids(&ids);
+    assert_eq!(5, f.span_count());
+    assert_eq!(5, f.max_span());
+    // [12, 13] and [20, 21] are the best fits, the run starting at 3 stays intact
+    let pgid = f.allocate(td(1), 2).unwrap();
+    assert!(pgid == pd(12) || pgid == pd(20));
+    assert_eq!(5, f.max_span());
+
+    let mut f = Freelist::new();
+    f.set_alloc_policy(FreelistAllocStrategy::BestFit, true);
+    f.read_ids(&ids);
+    assert_eq!(Some(pd(12)), f.allocate(td(1), 2));
+    assert_eq!(Some(pd(20)), f.allocate(td(1), 2));
+    assert_eq!(Some(pd(9)), f.allocate(td(1), 1));
+    assert_eq!(Some(pd(3)), f.allocate(td(1), 2));
+
+    let mut f = Freelist::new();
+    f.set_alloc_policy(FreelistAllocStrategy::FirstFit, true);
+    f.read_ids(&ids);
+    assert_eq!(Some(pd(3)), f.allocate(td(1), 1));
+    assert_eq!(Some(pd(4)), f.allocate(td(1), 2));
+    assert_eq!(Some(pd(6)), f.allocate(td(1), 2));
+    assert_eq!(Some(pd(9)), f.allocate(td(1), 1));
+    assert_eq!(3, f.span_count());
+    assert_eq!(2, f.max_span());
+  }
+
   #[test]
   fn freelist_read() {
     let mut mapped_page = mapped_page::<MappedFreeListPage>(4096);
@@ -799,6 +1068,31 @@ mod tests {
     assert_eq!(&[23, 50], f.free_page_ids().as_slice());
   }
 
+  #[test]
+  fn freelist_read_unsorted() {
+    let mut mapped_page = mapped_page::<MappedFreeListPage>(4096);
+    mapped_page.set_free_list();
+    mapped_page
+      .page_ids_mut(4)
+      .copy_from_slice(&[pd(50), pd(23), pd(24), pd(7)]);
+    let mut f = Freelist::new();
+    f.read(&mapped_page);
+    assert_eq!(&[7, 23, 24, 50], f.free_page_ids().as_slice());
+  }
+
+  #[test]
+  fn freelist_read_extended_count() {
+    let count = 0xFFFF + 10;
+    let mut mapped_page = mapped_page::<MappedFreeListPage>(4096 + (count + 1) * 8);
+    mapped_page.set_free_list();
+    let ids = (0..count as u64).map(|i| pd(2 + i * 2)).collect_vec();
+    mapped_page.page_ids_mut(count as u64).copy_from_slice(&ids);
+    assert_eq!(0xFFFF, mapped_page.count);
+    let mut f = Freelist::new();
+    f.read(&mapped_page);
+    assert_eq!(ids, f.free_page_ids());
+  }
+
   #[test]
   fn freelist_write() {
     let mut mapped_page = mapped_page::<MappedFreeListPage>(4096);
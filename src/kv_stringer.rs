@@ -0,0 +1,203 @@
+use crate::Error;
+use anyhow::anyhow;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// KvStringer renders keys and values in diagnostics, like the reports of
+/// [TxCheck::check_with](crate::TxCheck::check_with) and
+/// [PageElements::render](crate::PageElements::render).
+///
+/// Applications with structured keys, e.g. keys prefixed with a uuid or a protobuf
+/// message, implement it to print them readably instead of as hex.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// struct Utf8Stringer;
+///
+/// impl KvStringer for Utf8Stringer {
+///   fn key_to_string(&self, key: &[u8]) -> String {
+///     String::from_utf8_lossy(key).into_owned()
+///   }
+///
+///   fn value_to_string(&self, value: &[u8]) -> String {
+///     format!("{} bytes", value.len())
+///   }
+/// }
+///
+/// assert_eq!("user/1", Utf8Stringer.key_to_string(b"user/1"));
+/// assert_eq!("[75, 31]", HexKvStringer.key_to_string(b"u1"));
+/// ```
+pub trait KvStringer {
+  /// Renders a key
+  fn key_to_string(&self, key: &[u8]) -> String;
+
+  /// Renders a value
+  fn value_to_string(&self, value: &[u8]) -> String;
+}
+
+/// HexKvStringer renders keys and values as lists of hex bytes. It's used when no other
+/// [KvStringer] is given.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HexKvStringer;
+
+impl KvStringer for HexKvStringer {
+  fn key_to_string(&self, key: &[u8]) -> String {
+    format!("{:02X?}", key)
+  }
+
+  fn value_to_string(&self, value: &[u8]) -> String {
+    format!("{:02X?}", value)
+  }
+}
+
+/// KeyFormat selects how keys and values are printed, like the `--format` flag of the bbolt
+/// command line tool
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// assert_eq!("666f6f0a", KeyFormat::Hex.format(b"foo\n"));
+/// assert_eq!("\"foo\\n\"", KeyFormat::EscapedAscii.format(b"foo\n"));
+/// assert_eq!("foo", KeyFormat::Auto.format(b"foo"));
+/// assert_eq!("00ff", KeyFormat::Auto.format(&[0, 255]));
+/// assert_eq!(KeyFormat::Hex, "hex".parse().unwrap());
+/// ```
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum KeyFormat {
+  /// Lowercase hex digits, e.g. `666f6f0a`. See [format_hex]
+  Hex,
+  /// Quoted with non-printable bytes escaped, e.g. `"foo\n"`. See [format_escaped_ascii]
+  EscapedAscii,
+  /// Printable text as is, hex otherwise. See [format_auto]
+  #[default]
+  Auto,
+}
+
+impl KeyFormat {
+  /// Formats `bytes`
+  pub fn format(self, bytes: &[u8]) -> String {
+    self.display(bytes).to_string()
+  }
+
+  /// Wraps `bytes` to format them with [Display]
+  pub fn display(self, bytes: &[u8]) -> DisplayBytes<'_> {
+    DisplayBytes {
+      bytes,
+      format: self,
+    }
+  }
+}
+
+impl FromStr for KeyFormat {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "hex" => Ok(KeyFormat::Hex),
+      "escaped-ascii" | "ascii-encoded" => Ok(KeyFormat::EscapedAscii),
+      "auto" => Ok(KeyFormat::Auto),
+      _ => Err(Error::Other(anyhow!("unknown key format {:?}", s))),
+    }
+  }
+}
+
+impl KvStringer for KeyFormat {
+  fn key_to_string(&self, key: &[u8]) -> String {
+    self.format(key)
+  }
+
+  fn value_to_string(&self, value: &[u8]) -> String {
+    self.format(value)
+  }
+}
+
+/// DisplayBytes formats a key or value with [Display]. See [KeyFormat::display]
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// assert_eq!("key: user/1", format!("key: {}", DisplayBytes::new(b"user/1")));
+/// assert_eq!("key: 7573657200", format!("key: {}", DisplayBytes::new(b"user\0")));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DisplayBytes<'a> {
+  bytes: &'a [u8],
+  format: KeyFormat,
+}
+
+impl<'a> DisplayBytes<'a> {
+  /// Wraps `bytes` to format them with [KeyFormat::Auto]
+  pub fn new(bytes: &'a [u8]) -> DisplayBytes<'a> {
+    KeyFormat::Auto.display(bytes)
+  }
+}
+
+impl Display for DisplayBytes<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self.format {
+      KeyFormat::Hex => {
+        for b in self.bytes {
+          write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+      }
+      KeyFormat::EscapedAscii => write!(f, "\"{}\"", self.bytes.escape_ascii()),
+      KeyFormat::Auto => match printable(self.bytes) {
+        Some(s) => f.write_str(s),
+        None => Display::fmt(&KeyFormat::Hex.display(self.bytes), f),
+      },
+    }
+  }
+}
+
+/// Returns `bytes` as text if they're valid UTF-8 without control characters
+fn printable(bytes: &[u8]) -> Option<&str> {
+  std::str::from_utf8(bytes)
+    .ok()
+    .filter(|s| !s.chars().any(char::is_control))
+}
+
+/// Formats `bytes` as lowercase hex digits
+pub fn format_hex(bytes: &[u8]) -> String {
+  KeyFormat::Hex.format(bytes)
+}
+
+/// Formats `bytes` as a quoted string, escaping quotes, backslashes and bytes that aren't
+/// printable ascii
+pub fn format_escaped_ascii(bytes: &[u8]) -> String {
+  KeyFormat::EscapedAscii.format(bytes)
+}
+
+/// Formats `bytes` as text if they're valid UTF-8 without control characters, as hex
+/// otherwise
+pub fn format_auto(bytes: &[u8]) -> String {
+  KeyFormat::Auto.format(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{format_auto, format_escaped_ascii, format_hex, DisplayBytes, KeyFormat};
+
+  #[test]
+  fn test_key_formats() {
+    assert_eq!("", format_hex(b""));
+    assert_eq!("0001abff", format_hex(&[0x00, 0x01, 0xab, 0xff]));
+
+    assert_eq!("\"\"", format_escaped_ascii(b""));
+    assert_eq!(
+      "\"a\\\"b\\\\c\\t\\x00\\xff\"",
+      format_escaped_ascii(b"a\"b\\c\t\x00\xff")
+    );
+
+    assert_eq!("", format_auto(b""));
+    assert_eq!("key 1", format_auto(b"key 1"));
+    assert_eq!("schlüssel", format_auto("schlüssel".as_bytes()));
+    assert_eq!("6b65790a", format_auto(b"key\n"));
+    assert_eq!("ff", format_auto(&[0xff]));
+
+    assert_eq!("6b6579", format!("{}", KeyFormat::Hex.display(b"key")));
+    assert_eq!("[key]", format!("[{}]", DisplayBytes::new(b"key")));
+    assert!("binary".parse::<KeyFormat>().is_err());
+  }
+}
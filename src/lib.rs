@@ -7,6 +7,7 @@
 //! * RwLock based transactions
 //! * File backed database
 //! * Memory backed database
+//! * `wasm32` support for the memory backed database. The file backend is unavailable
 //! * Miri tested to prevent memory errors in unsafe blocks
 //! * Simple and straightforward public APIs
 //!
@@ -24,21 +25,108 @@
 #![doc = document_features::document_features!()]
 
 mod arch;
+#[cfg(all(feature = "archive", file_backend_supported))]
+mod archive;
+#[cfg(feature = "async")]
+mod async_db;
 mod bucket;
+#[cfg(feature = "cache")]
+mod cache;
 mod common;
+mod comparator;
 mod cursor;
 mod db;
+pub mod diff;
+mod export;
+#[cfg(feature = "failpoints")]
+mod failpoints;
+#[cfg(file_backend_supported)]
+pub mod format;
 mod freelist;
+mod kv_stringer;
+#[cfg(file_backend_supported)]
+mod lock_file;
+#[cfg(feature = "model-test")]
+pub mod model;
 mod node;
+#[cfg(any(test, all(target_arch = "wasm32", feature = "opfs-store")))]
+mod opfs;
+#[cfg(feature = "owned-tx")]
+mod owned_tx;
+mod quota;
+#[cfg(feature = "replication")]
+pub mod replication;
+mod savepoint;
+mod schema;
+mod scrub;
+#[cfg(feature = "sim")]
+mod sim;
+mod size_report;
+mod snapshot;
+#[cfg(feature = "page-store")]
+mod store;
 #[cfg(test)]
 mod test_support;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod tx;
+mod u64_bucket;
 
-pub use bucket::{BucketApi, BucketImpl, BucketRwApi, BucketRwImpl, BucketStats};
+#[cfg(all(feature = "archive", file_backend_supported))]
+pub use archive::{ArchiveBucket, ArchiveManifest};
+#[cfg(feature = "async")]
+pub use async_db::{BlockingExecutor, BlockingFn, BlockingTask, ThreadExecutor};
+pub use bucket::{
+  BucketApi, BucketImpl, BucketMetrics, BucketRwApi, BucketRwImpl, BucketStats, BucketStructure,
+};
+#[cfg(feature = "cache")]
+pub use cache::Cache;
 pub use common::errors::{Error, Result};
+pub use common::histogram::LatencyHistogram;
 pub use common::ids::{PgId, TxId};
-pub use common::page::PageInfo;
+pub use common::meta::{MetaDiagnostics, MetaInfo};
+pub use common::page::{BranchElement, LeafElement, PageElements, PageInfo};
+#[cfg(bench_tuning)]
+#[doc(hidden)]
+pub use common::search::set_linear_search_max;
+pub use comparator::{Comparators, KeyComparator, RESERVED_PREFIX};
 pub use cursor::{CursorApi, CursorImpl, CursorRwApi, CursorRwImpl};
-pub use db::{Bolt, BoltOptions, BoltOptionsBuilder, DbApi, DbInfo, DbPath, DbRwAPI, DbStats};
+pub use db::{
+  Bolt, BoltOptions, BoltOptionsBuilder, DbApi, DbInfo, DbPath, DbRwAPI, DbStats, Deadline,
+  MetaStatus, ReaderPin, TxRwOptions, TxRwOptionsBuilder,
+};
+pub use export::{ExportIter, ExportRecord};
+#[cfg(feature = "failpoints")]
+pub use failpoints::{FailAction, FailPoint, FailPoints};
+pub use freelist::FreelistAllocStrategy;
+pub use kv_stringer::{
+  format_auto, format_escaped_ascii, format_hex, DisplayBytes, HexKvStringer, KeyFormat, KvStringer,
+};
+#[cfg(file_backend_supported)]
+pub use lock_file::LockOwner;
+#[cfg(all(
+  target_arch = "wasm32",
+  feature = "opfs-store",
+  not(target_feature = "atomics")
+))]
+pub use opfs::OpfsStore;
+#[cfg(feature = "owned-tx")]
+pub use owned_tx::{OwnedTx, ValueGuard};
+pub use quota::{BucketQuota, QuotaUsage};
+pub use savepoint::Savepoint;
+pub use schema::BucketSpec;
+pub use scrub::{ScrubReport, ScrubStats, Scrubber};
+#[cfg(feature = "sim")]
+pub use sim::{SimLog, SimWrite};
+pub use size_report::{BucketSizeReport, SizeReport};
+#[cfg(fd_sharing_supported)]
+pub use snapshot::SharedSnapshot;
+pub use snapshot::{BackupReport, SnapshotGuard};
+#[cfg(feature = "page-store")]
+pub use store::{InstrumentedStore, PageStore, StoreFaults, StoreOp};
 pub use tx::check::TxCheck;
-pub use tx::{TxApi, TxImpl, TxRef, TxRwApi, TxRwImpl, TxRwRef, TxRwRefApi, TxStats};
+pub use tx::{
+  PageUsageReport, TxApi, TxDropPolicy, TxImpl, TxPrepared, TxRef, TxRwApi, TxRwImpl, TxRwRef,
+  TxRwRefApi, TxStats,
+};
+pub use u64_bucket::{U64Bucket, U64Range};
@@ -0,0 +1,344 @@
+use crate::Error;
+use anyhow::anyhow;
+use fs4::FileExt;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// The process holding the sidecar lock file of a database.
+/// See [crate::BoltOptionsBuilder::lock_file]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockOwner {
+  pid: u32,
+  host: String,
+  /// 0 if unknown
+  started: u64,
+}
+
+impl LockOwner {
+  fn current() -> LockOwner {
+    let pid = process::id();
+    LockOwner {
+      pid,
+      host: host_name(),
+      started: process_start_time(pid).unwrap_or_default(),
+    }
+  }
+
+  fn parse(s: &str) -> Option<LockOwner> {
+    let mut fields = s.split_whitespace();
+    let owner = LockOwner {
+      pid: fields.next()?.parse().ok()?,
+      host: fields.next()?.to_string(),
+      started: match fields.next() {
+        Some(started) => started.parse().ok()?,
+        None => 0,
+      },
+    };
+    Some(owner)
+  }
+
+  /// The process id of the owner
+  pub fn pid(&self) -> u32 {
+    self.pid
+  }
+
+  /// The host name of the owner's machine
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  /// When the owner process started, in clock ticks since the boot of its machine. None if
+  /// the platform doesn't report it
+  pub fn started(&self) -> Option<u64> {
+    Some(self.started).filter(|started| *started != 0)
+  }
+
+  /// Returns whether the owner is known to be gone: it ran on this host, and no process
+  /// with its pid runs anymore or the one that does started at another time. The owners
+  /// of other hosts, or of platforms without process start times, are never stale.
+  fn is_stale(&self) -> bool {
+    if cfg!(not(proc_fs_supported)) || self.host != host_name() {
+      return false;
+    }
+    match process_start_time(self.pid) {
+      None => true,
+      Some(started) => self.started != 0 && started != self.started,
+    }
+  }
+}
+
+impl Display for LockOwner {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} {} {}", self.pid, self.host, self.started)
+  }
+}
+
+/// Returns when the process `pid` started in clock ticks since boot, or None if it doesn't
+/// run
+#[cfg(proc_fs_supported)]
+fn process_start_time(pid: u32) -> Option<u64> {
+  let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+  // The command name in parentheses may contain spaces. The start time is the 22nd field,
+  // the 20th after the name.
+  let (_, fields) = stat.rsplit_once(')')?;
+  fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(not(proc_fs_supported))]
+fn process_start_time(_pid: u32) -> Option<u64> {
+  None
+}
+
+fn host_name() -> String {
+  let name = fs::read_to_string("/proc/sys/kernel/hostname")
+    .ok()
+    .or_else(|| std::env::var("HOSTNAME").ok())
+    .or_else(|| std::env::var("COMPUTERNAME").ok())
+    .unwrap_or_default();
+  match name.trim() {
+    "" => "localhost".to_string(),
+    name => name.to_string(),
+  }
+}
+
+/// Returns the path of the sidecar lock file of the database at `path`
+pub(crate) fn lock_file_path(path: &Path) -> PathBuf {
+  let mut name = OsString::from(path.as_os_str());
+  name.push(".lock");
+  PathBuf::from(name)
+}
+
+fn parse_owner(s: &str, path: &Path) -> crate::Result<Option<LockOwner>> {
+  if s.trim().is_empty() {
+    return Ok(None);
+  }
+  LockOwner::parse(s)
+    .map(Some)
+    .ok_or_else(|| Error::Other(anyhow!("unreadable lock file {}", path.display())))
+}
+
+/// Reads the owner recorded in the lock file at `path`. Returns None if there is no lock
+/// file or its owner released it.
+pub(crate) fn read_owner(path: &Path) -> crate::Result<Option<LockOwner>> {
+  match fs::read_to_string(path) {
+    Ok(s) => parse_owner(&s, path),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+fn is_contended(e: &io::Error) -> bool {
+  e.raw_os_error() == fs4::lock_contended_error().raw_os_error()
+}
+
+/// Returns [Error::DatabaseLocked] if another process holds the lock file at `path`.
+/// `contended` is whether its advisory lock is held.
+///
+/// The advisory lock is only the fast path. Network file systems may grant it to several
+/// hosts, or keep it for a client that crashed, so the recorded owner decides: a lock is
+/// held while its owner isn't stale. Without a recorded owner the advisory lock decides,
+/// the owner may not have recorded itself yet or the platform may not let others read a
+/// locked file.
+fn check_owner(path: &Path, contended: bool) -> crate::Result<()> {
+  match read_owner(path).ok().flatten() {
+    Some(owner) if owner.is_stale() => Ok(()),
+    Some(owner) => Err(Error::DatabaseLocked {
+      pid: owner.pid,
+      host: owner.host,
+    }),
+    None if contended => Err(Error::DatabaseLocked {
+      pid: 0,
+      host: String::new(),
+    }),
+    None => Ok(()),
+  }
+}
+
+/// Returns [Error::DatabaseLocked] if another process holds the lock file at `path`
+pub(crate) fn check_unlocked(path: &Path) -> crate::Result<()> {
+  let file = match File::open(path) {
+    Ok(file) => file,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(e.into()),
+  };
+  // std's inherent try_lock_shared shadows fs4's and reports contention differently
+  let contended = match FileExt::try_lock_shared(&file) {
+    Ok(_) => {
+      FileExt::unlock(&file)?;
+      false
+    }
+    Err(e) if is_contended(&e) => true,
+    Err(e) => return Err(e.into()),
+  };
+  check_owner(path, contended)
+}
+
+/// A sidecar lock file held by this process.
+///
+/// The lock is an advisory lock on the open file, so the operating system releases it when
+/// the owner exits, crashed or not. The file records the owner, which is released on drop,
+/// so a lock whose recorded owner is gone is taken over. See [check_owner]
+pub(crate) struct LockFile {
+  file: File,
+}
+
+impl LockFile {
+  /// Locks the lock file at `path`, creating it if needed, and records this process as its
+  /// owner.
+  ///
+  /// Returns [Error::DatabaseLocked] if another process holds it.
+  pub(crate) fn acquire(path: PathBuf) -> crate::Result<LockFile> {
+    let mut file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(&path)?;
+    let contended = match FileExt::try_lock_exclusive(&file) {
+      Ok(_) => false,
+      Err(e) if is_contended(&e) => true,
+      Err(e) => return Err(e.into()),
+    };
+    if let Err(e) = check_owner(&path, contended) {
+      if !contended {
+        FileExt::unlock(&file)?;
+      }
+      return Err(e);
+    }
+    // Whoever recorded itself before doesn't hold the lock anymore
+    let owner = LockOwner::current();
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(owner.to_string().as_bytes())?;
+    file.sync_all()?;
+    // Taking over a stale advisory lock isn't exclusive, another process may have recorded
+    // itself since
+    if contended {
+      if let Some(other) = read_owner(&path)?.filter(|other| *other != owner) {
+        return Err(Error::DatabaseLocked {
+          pid: other.pid,
+          host: other.host,
+        });
+      }
+    }
+    Ok(LockFile { file })
+  }
+}
+
+impl Drop for LockFile {
+  fn drop(&mut self) {
+    // The file stays so a process that opened it while we held the lock can't end up
+    // locking a removed file
+    let _ = self.file.set_len(0);
+    let _ = FileExt::unlock(&self.file);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::lock_file::{host_name, lock_file_path, read_owner, LockOwner};
+  use crate::test_support::temp_file;
+  use crate::{Bolt, BoltOptions, DbApi, Error};
+  use fs4::FileExt;
+  use std::fs;
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_lock_file() -> crate::Result<()> {
+    let file = temp_file()?;
+    let path = file.path();
+    let lock_path = lock_file_path(path);
+    let options = BoltOptions::builder().lock_file().build();
+
+    let db = options.clone().open(path)?;
+    let owner = LockOwner::current();
+    assert_eq!(Some(owner.clone()), read_owner(&lock_path)?);
+    let locked = Error::DatabaseLocked {
+      pid: owner.pid,
+      host: owner.host.clone(),
+    };
+    assert_eq!(Some(&locked), options.clone().open_ro(path).err().as_ref());
+    assert_eq!(Some(&locked), options.clone().open(path).err().as_ref());
+    db.close();
+    assert_eq!(None, read_owner(&lock_path)?);
+
+    // The advisory lock may be released while the owner of another host still runs, e.g.
+    // on a network file system
+    let remote = LockOwner {
+      pid: owner.pid,
+      host: "elsewhere".to_string(),
+      started: 1,
+    };
+    fs::write(&lock_path, remote.to_string())?;
+    assert!(matches!(
+      options.clone().open(path),
+      Err(Error::DatabaseLocked { .. })
+    ));
+
+    // Removing the lock file lets the next process in
+    assert_eq!(Some(remote), Bolt::force_unlock(path)?);
+    assert!(!lock_path.exists());
+    let db = options.clone().open(path)?;
+    assert_eq!(Some(owner.clone()), Bolt::force_unlock(path)?);
+    db.close();
+    options.open(path)?.close();
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(proc_fs_supported, not(any(miri, feature = "test-mem-backend"))))]
+  fn test_lock_file_stale() -> crate::Result<()> {
+    let file = temp_file()?;
+    let path = file.path();
+    let lock_path = lock_file_path(path);
+    let options = BoltOptions::builder().lock_file().build();
+    options.clone().open(path)?.close();
+    let owner = LockOwner::current();
+    assert!(owner.started().is_some());
+    assert!(!owner.is_stale());
+
+    // An owner of this host that exited, or whose pid was reused, is gone
+    let exited = LockOwner {
+      pid: u32::MAX,
+      host: host_name(),
+      started: 1,
+    };
+    let reused = LockOwner {
+      started: owner.started + 1,
+      ..owner.clone()
+    };
+    for stale in [exited, reused] {
+      assert!(stale.is_stale());
+      fs::write(&lock_path, stale.to_string())?;
+      options.clone().open_ro(path)?.close();
+      let db = options.clone().open(path)?;
+      assert_eq!(Some(owner.clone()), read_owner(&lock_path)?);
+      db.close();
+    }
+
+    // The advisory lock of a crashed owner may never be released on a network file system
+    let stuck = fs::File::open(&lock_path)?;
+    FileExt::try_lock_exclusive(&stuck)?;
+    let crashed = LockOwner {
+      pid: u32::MAX,
+      host: host_name(),
+      started: 1,
+    };
+    fs::write(&lock_path, crashed.to_string())?;
+    let db = options.clone().open(path)?;
+    assert_eq!(Some(owner), read_owner(&lock_path)?);
+    assert!(matches!(
+      options.clone().open(path),
+      Err(Error::DatabaseLocked { .. })
+    ));
+    db.close();
+    FileExt::unlock(&stuck)?;
+    Ok(())
+  }
+}
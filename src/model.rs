@@ -0,0 +1,312 @@
+//! Model based testing.
+//!
+//! [ModelTest] applies random transactions to a [ModelTarget] and to an in-memory
+//! [Model] of the buckets and compares every result. Layers built on top of bbolt-rs can
+//! implement [ModelTarget] to run the same checks against their own code.
+//!
+//! ```rust
+//! use bbolt_rs::model::ModelTest;
+//! use bbolt_rs::*;
+//!
+//! fn main() -> Result<()> {
+//!   let mut db = Bolt::open_mem()?;
+//!   ModelTest::new(7).txs(20).run(&mut db)?;
+//!   Ok(())
+//! }
+//! ```
+use crate::common::rng::SplitMix64;
+use crate::{Bolt, BucketApi, BucketRwApi, DbApi, DbRwAPI, TxApi, TxRwApi, TxRwRefApi};
+use std::collections::BTreeMap;
+
+/// The expected state of the database: the top-level buckets and their key/value pairs
+pub type Model = BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>;
+
+/// An operation within a write transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Op {
+  /// Creates the top-level bucket. Fails if it exists.
+  CreateBucket(Vec<u8>),
+  /// Deletes the top-level bucket. Fails if it doesn't exist.
+  DeleteBucket(Vec<u8>),
+  /// Sets a key in a bucket. Fails if the bucket doesn't exist.
+  Put {
+    bucket: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+  },
+  /// Removes a key from a bucket. Fails if the bucket doesn't exist.
+  Delete { bucket: Vec<u8>, key: Vec<u8> },
+  /// Reads a key from a bucket. Fails if the bucket doesn't exist.
+  Get { bucket: Vec<u8>, key: Vec<u8> },
+}
+
+/// The result of an [Op]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+  /// The operation succeeded
+  Ok,
+  /// The value read by [Op::Get]
+  Value(Option<Vec<u8>>),
+  /// The operation failed
+  Err,
+}
+
+/// Applies `op` to `model` and returns the expected outcome
+pub fn apply(model: &mut Model, op: &Op) -> Outcome {
+  match op {
+    Op::CreateBucket(name) => {
+      if model.contains_key(name) {
+        return Outcome::Err;
+      }
+      model.insert(name.clone(), BTreeMap::new());
+      Outcome::Ok
+    }
+    Op::DeleteBucket(name) => match model.remove(name) {
+      Some(_) => Outcome::Ok,
+      None => Outcome::Err,
+    },
+    Op::Put { bucket, key, value } => match model.get_mut(bucket) {
+      Some(b) => {
+        b.insert(key.clone(), value.clone());
+        Outcome::Ok
+      }
+      None => Outcome::Err,
+    },
+    Op::Delete { bucket, key } => match model.get_mut(bucket) {
+      Some(b) => {
+        b.remove(key);
+        Outcome::Ok
+      }
+      None => Outcome::Err,
+    },
+    Op::Get { bucket, key } => match model.get(bucket) {
+      Some(b) => Outcome::Value(b.get(key).cloned()),
+      None => Outcome::Err,
+    },
+  }
+}
+
+/// A database, or a layer on top of one, that [ModelTest] can check
+pub trait ModelTarget {
+  /// Applies `ops` in a single write transaction and returns the outcome of every op. The
+  /// transaction is committed if `commit` is true and rolled back otherwise.
+  ///
+  /// A failed op must not abort the transaction. Returning an error fails the test.
+  fn apply(&mut self, ops: &[Op], commit: bool) -> crate::Result<Vec<Outcome>>;
+
+  /// Reads the committed state of the database
+  fn dump(&mut self) -> crate::Result<Model>;
+}
+
+impl ModelTarget for Bolt {
+  fn apply(&mut self, ops: &[Op], commit: bool) -> crate::Result<Vec<Outcome>> {
+    let mut tx = self.begin_rw()?;
+    let outcome = |r: crate::Result<()>| r.map_or(Outcome::Err, |_| Outcome::Ok);
+    let mut outcomes = Vec::with_capacity(ops.len());
+    for op in ops {
+      outcomes.push(match op {
+        Op::CreateBucket(name) => outcome(tx.create_bucket(name).map(|_| ())),
+        Op::DeleteBucket(name) => outcome(tx.delete_bucket(name)),
+        Op::Put { bucket, key, value } => match tx.bucket_mut(bucket) {
+          Some(mut b) => outcome(b.put(key, value)),
+          None => Outcome::Err,
+        },
+        Op::Delete { bucket, key } => match tx.bucket_mut(bucket) {
+          Some(mut b) => outcome(b.delete(key)),
+          None => Outcome::Err,
+        },
+        Op::Get { bucket, key } => match tx.bucket(bucket) {
+          Some(b) => Outcome::Value(b.get(key).map(|v| v.to_vec())),
+          None => Outcome::Err,
+        },
+      });
+    }
+    if commit {
+      tx.commit()?;
+    } else {
+      tx.rollback()?;
+    }
+    Ok(outcomes)
+  }
+
+  fn dump(&mut self) -> crate::Result<Model> {
+    let tx = self.begin()?;
+    let mut model = Model::new();
+    tx.for_each(|name, b| {
+      let mut pairs = BTreeMap::new();
+      b.for_each(|k, v| {
+        if let Some(v) = v {
+          pairs.insert(k.to_vec(), v.to_vec());
+        }
+        Ok(())
+      })?;
+      model.insert(name.to_vec(), pairs);
+      Ok(())
+    })?;
+    Ok(model)
+  }
+}
+
+/// ModelTest drives random transactions against a [ModelTarget]. The same seed always
+/// generates the same transactions, so a failure can be reproduced from the seed in the
+/// panic message.
+#[derive(Clone, Debug)]
+pub struct ModelTest {
+  seed: u64,
+  txs: usize,
+  ops_per_tx: usize,
+  buckets: u64,
+  keys: u64,
+  max_value_len: usize,
+}
+
+impl ModelTest {
+  /// Starts a test with 100 transactions of up to 50 ops on 4 buckets of 500 keys
+  pub fn new(seed: u64) -> ModelTest {
+    ModelTest {
+      seed,
+      txs: 100,
+      ops_per_tx: 50,
+      buckets: 4,
+      keys: 500,
+      max_value_len: 256,
+    }
+  }
+
+  /// Sets the number of transactions
+  pub fn txs(mut self, txs: usize) -> ModelTest {
+    self.txs = txs;
+    self
+  }
+
+  /// Sets the maximum number of ops in a transaction
+  pub fn ops_per_tx(mut self, ops_per_tx: usize) -> ModelTest {
+    self.ops_per_tx = ops_per_tx.max(1);
+    self
+  }
+
+  /// Sets the number of distinct bucket names
+  pub fn buckets(mut self, buckets: u64) -> ModelTest {
+    self.buckets = buckets.max(1);
+    self
+  }
+
+  /// Sets the number of distinct keys per bucket
+  pub fn keys(mut self, keys: u64) -> ModelTest {
+    self.keys = keys.max(1);
+    self
+  }
+
+  /// Sets the maximum length of the values
+  pub fn max_value_len(mut self, max_value_len: usize) -> ModelTest {
+    self.max_value_len = max_value_len;
+    self
+  }
+
+  fn gen_op(&self, rng: &mut SplitMix64) -> Op {
+    let bucket = format!("bucket-{}", rng.next_u64() % self.buckets).into_bytes();
+    let key = format!("key-{:08}", rng.next_u64() % self.keys).into_bytes();
+    match rng.next_u64() % 20 {
+      0 => Op::CreateBucket(bucket),
+      1 => Op::DeleteBucket(bucket),
+      2..=4 => Op::Delete { bucket, key },
+      5..=7 => Op::Get { bucket, key },
+      _ => {
+        let len = (rng.next_u64() % (self.max_value_len as u64 + 1)) as usize;
+        Op::Put {
+          bucket,
+          key,
+          value: rng.fill(len),
+        }
+      }
+    }
+  }
+
+  /// Runs the test. Panics on the first difference between `target` and the model.
+  ///
+  /// Returns the errors of [ModelTarget::apply] and [ModelTarget::dump].
+  pub fn run<T: ModelTarget>(&self, target: &mut T) -> crate::Result<()> {
+    let mut rng = SplitMix64(self.seed);
+    let mut model = target.dump()?;
+    for tx in 0..self.txs {
+      let op_n = 1 + (rng.next_u64() % self.ops_per_tx as u64) as usize;
+      let ops = (0..op_n).map(|_| self.gen_op(&mut rng)).collect::<Vec<_>>();
+      let commit = rng.next_u64() % 4 != 0;
+
+      let mut pending = model.clone();
+      let expected = ops
+        .iter()
+        .map(|op| apply(&mut pending, op))
+        .collect::<Vec<_>>();
+      let actual = target.apply(&ops, commit)?;
+      assert_eq!(
+        expected.len(),
+        actual.len(),
+        "seed {}: tx {tx} returned the wrong number of outcomes",
+        self.seed
+      );
+      for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        assert_eq!(
+          expected, actual,
+          "seed {}: tx {tx} op {i} {:?}",
+          self.seed, ops[i]
+        );
+      }
+      if commit {
+        model = pending;
+      }
+      assert!(
+        model == target.dump()?,
+        "seed {}: database differs from the model after tx {tx} (commit: {commit})",
+        self.seed
+      );
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::model::{apply, Model, ModelTarget, ModelTest, Op, Outcome};
+  use crate::test_support::TestDb;
+
+  #[test]
+  fn test_model_apply() {
+    let mut model = Model::new();
+    let put = Op::Put {
+      bucket: b"a".to_vec(),
+      key: b"k".to_vec(),
+      value: b"v".to_vec(),
+    };
+    let get = Op::Get {
+      bucket: b"a".to_vec(),
+      key: b"k".to_vec(),
+    };
+    assert_eq!(Outcome::Err, apply(&mut model, &put));
+    assert_eq!(
+      Outcome::Ok,
+      apply(&mut model, &Op::CreateBucket(b"a".to_vec()))
+    );
+    assert_eq!(
+      Outcome::Err,
+      apply(&mut model, &Op::CreateBucket(b"a".to_vec()))
+    );
+    assert_eq!(Outcome::Ok, apply(&mut model, &put));
+    assert_eq!(Outcome::Value(Some(b"v".to_vec())), apply(&mut model, &get));
+    assert_eq!(
+      Outcome::Ok,
+      apply(&mut model, &Op::DeleteBucket(b"a".to_vec()))
+    );
+    assert_eq!(Outcome::Err, apply(&mut model, &get));
+  }
+
+  #[test]
+  fn test_model_db() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    for seed in 0..4 {
+      ModelTest::new(seed).txs(50).run(&mut *db)?;
+    }
+    assert!(!db.dump()?.is_empty());
+    Ok(())
+  }
+}
@@ -1,16 +1,29 @@
 use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi, MAX_FILL_PERCENT, MIN_FILL_PERCENT};
+use crate::common::defaults::has_prefix_branch;
 use crate::common::inode::INode;
 use crate::common::memory::{CodSlice, LCell, SplitArray, VecOrSplit};
-use crate::common::page::{CoerciblePage, MutPage, RefPage, MIN_KEYS_PER_PAGE, PAGE_HEADER_SIZE};
+use crate::common::page::{
+  CoerciblePage, MutPage, RefPage, MIN_KEYS_PER_PAGE, PAGE_CHECKSUM_SIZE, PAGE_HEADER_SIZE,
+};
+use crate::common::search::search_by;
 use crate::common::tree::{
-  MappedBranchPage, MappedLeafPage, TreePage, BRANCH_PAGE_ELEMENT_SIZE, LEAF_PAGE_ELEMENT_SIZE,
+  branch_prefix_len, branch_prefix_savings, MappedBranchPage, MappedLeafPage, TreePage,
+  BRANCH_PAGE_ELEMENT_SIZE, LEAF_PAGE_ELEMENT_SIZE, PREFIX_LEN_SIZE,
 };
 use crate::common::{BVec, PgId, SplitRef, ZERO_PGID};
+use crate::comparator::KeyComparator;
 use crate::tx::{TxIApi, TxRwIApi};
+use crate::Error;
 use bumpalo::Bump;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 
+/// Returns true if the branch pages of `bucket` are written prefix compressed
+#[inline]
+fn prefix_branch(bucket: BucketCell) -> bool {
+  cfg!(feature = "prefix-branch") && has_prefix_branch(bucket.tx().meta().version())
+}
+
 /// NodeW represents an in-memory, deserialized page.
 pub struct NodeW<'tx> {
   pub(crate) is_leaf: bool,
@@ -18,6 +31,10 @@ pub struct NodeW<'tx> {
   pub(crate) pgid: PgId,
   pub(crate) inodes: VecOrSplit<'tx, INode<'tx>>,
   bucket: BucketCell<'tx>,
+  /// orders the keys of the node. Copied from the bucket
+  comparator: KeyComparator,
+  /// true if branch pages are written with their common key prefix stored once
+  prefix_branch: bool,
   parent: Option<NodeRwCell<'tx>>,
   is_unbalanced: bool,
   is_spilled: bool,
@@ -33,7 +50,7 @@ impl<'tx> PartialEq for NodeW<'tx> {
 impl<'tx> Eq for NodeW<'tx> {}
 
 impl<'tx> NodeW<'tx> {
-  fn new_parent_in(bucket: BucketCell<'tx>) -> NodeW<'tx> {
+  fn new_parent_in(bucket: BucketCell<'tx>, comparator: KeyComparator) -> NodeW<'tx> {
     let bump = bucket.tx().bump();
     NodeW {
       is_leaf: false,
@@ -42,6 +59,8 @@ impl<'tx> NodeW<'tx> {
       pgid: Default::default(),
       inodes: BVec::with_capacity_in(0, bump).into(),
       bucket,
+      comparator,
+      prefix_branch: prefix_branch(bucket),
       parent: None,
       is_unbalanced: false,
       is_spilled: false,
@@ -49,7 +68,9 @@ impl<'tx> NodeW<'tx> {
     }
   }
 
-  fn new_child_in(bucket: BucketCell<'tx>, is_leaf: bool, parent: NodeRwCell<'tx>) -> NodeW<'tx> {
+  fn new_child_in(
+    bucket: BucketCell<'tx>, comparator: KeyComparator, is_leaf: bool, parent: NodeRwCell<'tx>,
+  ) -> NodeW<'tx> {
     let bump = bucket.tx().bump();
     NodeW {
       is_leaf,
@@ -58,6 +79,8 @@ impl<'tx> NodeW<'tx> {
       pgid: Default::default(),
       inodes: BVec::with_capacity_in(0, bump).into(),
       bucket,
+      comparator,
+      prefix_branch: prefix_branch(bucket),
       parent: Some(parent),
       is_unbalanced: false,
       is_spilled: false,
@@ -84,6 +107,8 @@ impl<'tx> NodeW<'tx> {
       pgid: page.id,
       inodes: inodes.into(),
       bucket,
+      comparator: bucket.comparator(),
+      prefix_branch: prefix_branch(bucket),
       parent,
       is_unbalanced: false,
       is_spilled: false,
@@ -113,6 +138,15 @@ impl<'tx> NodeW<'tx> {
     self.key.get_ref()
   }
 
+  /// prefix_len returns the length of the key prefix stored once when the node is written.
+  pub(crate) fn prefix_len(&self) -> usize {
+    if self.prefix_branch && !self.is_leaf {
+      branch_prefix_len(&self.inodes)
+    } else {
+      0
+    }
+  }
+
   /// size returns the size of the node after serialization.
   pub(crate) fn size(&self) -> usize {
     let mut size = PAGE_HEADER_SIZE;
@@ -120,7 +154,7 @@ impl<'tx> NodeW<'tx> {
     for inode in self.inodes.deref() {
       size += elem_size + inode.key().len() + inode.value().len();
     }
-    size
+    size - branch_prefix_savings(self.prefix_len(), self.inodes.len())
   }
 
   fn write(&self, p: &mut MutPage) {
@@ -130,13 +164,19 @@ impl<'tx> NodeW<'tx> {
     if self.is_leaf {
       MappedLeafPage::mut_into(p).write_elements(&self.inodes);
     } else {
-      MappedBranchPage::mut_into(p).write_elements(&self.inodes);
+      let prefix_len = self.prefix_len();
+      if prefix_len > 0 {
+        MappedBranchPage::mut_into(p).write_prefixed_elements(&self.inodes, prefix_len);
+      } else {
+        MappedBranchPage::mut_into(p).write_elements(&self.inodes);
+      }
     }
   }
 
   /// del removes a key from the node.
   fn del(&mut self, key: &[u8]) {
-    if let Ok(index) = self.inodes.binary_search_by(|probe| probe.key().cmp(key)) {
+    let comparator = self.comparator;
+    if let Ok(index) = search_by(&self.inodes, |probe| comparator(probe.key(), key)) {
       self.inodes.get_mut_vec().remove(index);
       self.is_unbalanced = true;
     }
@@ -157,6 +197,8 @@ struct NodeSplit<'tx> {
   page_size: usize,
   threshold: usize,
   page_element_size: usize,
+  /// the key prefix length shared by every node split off. 0 if prefixes aren't stored once
+  prefix_len: usize,
   split_array: SplitArray<'tx, INode<'tx>>,
   next: Option<NodeRwCell<'tx>>,
 }
@@ -190,7 +232,7 @@ impl<'tx> NodeSplit<'tx> {
       if let Some(parent) = cell.parent {
         parent
       } else {
-        let parent = NodeRwCell::new_parent_in(cell.bucket);
+        let parent = NodeRwCell::new_parent_in(cell.bucket, cell.comparator);
         cell.parent = Some(parent);
         parent.cell.borrow_mut().children.push(node);
         parent
@@ -198,7 +240,7 @@ impl<'tx> NodeSplit<'tx> {
     };
 
     // Create a new node and add it to the parent.
-    let next = NodeRwCell::new_child_in(cell.bucket, cell.is_leaf, parent);
+    let next = NodeRwCell::new_child_in(cell.bucket, cell.comparator, cell.is_leaf, parent);
     let mut next_cell = next.cell.borrow_mut();
     //TODO: Rework to split right instead so we don't have to clone
     next_cell.inodes = self.split_array.clone().into();
@@ -217,11 +259,21 @@ impl<'tx> NodeSplit<'tx> {
     (node, Some(next))
   }
 
+  /// header_size returns the size of a page before its first element.
+  /// Sizes are overestimated when the split nodes end up without a stored prefix.
+  fn header_size(&self) -> usize {
+    if self.prefix_len == 0 {
+      PAGE_HEADER_SIZE
+    } else {
+      PAGE_HEADER_SIZE + PREFIX_LEN_SIZE + self.prefix_len
+    }
+  }
+
   /// splitIndex finds the position where a page will fill a given threshold.
   /// It returns the index as well as the size of the first page.
   /// This is only be called from split().
   fn split_index(&self, rem: &[INode]) -> (usize, usize) {
-    let mut size = PAGE_HEADER_SIZE;
+    let mut size = self.header_size();
     let mut index = 0;
     if rem.len() <= MIN_KEYS_PER_PAGE {
       return (index, size);
@@ -235,7 +287,8 @@ impl<'tx> NodeSplit<'tx> {
       .enumerate()
     {
       index = idx;
-      let elsize = self.page_element_size + inode.key().len() + inode.value().len();
+      let elsize =
+        self.page_element_size + inode.key().len() - self.prefix_len + inode.value().len();
 
       // If we have at least the minimum number of keys and adding another
       // node would put us over the threshold then exit and return.
@@ -253,10 +306,10 @@ impl<'tx> NodeSplit<'tx> {
   /// This is an optimization to avoid calculating a large node when we only need
   /// to know if it fits inside a certain page size.
   fn size_less_than(&self, rem: &[INode]) -> bool {
-    let mut size = PAGE_HEADER_SIZE;
+    let mut size = self.header_size();
     let elem_size = self.page_element_size;
     for inode in rem {
-      size += elem_size + inode.key().len() + inode.value().len();
+      size += elem_size + inode.key().len() - self.prefix_len + inode.value().len();
       if size > self.page_size {
         return false;
       }
@@ -284,18 +337,18 @@ pub struct NodeRwCell<'tx> {
 }
 
 impl<'tx> NodeRwCell<'tx> {
-  fn new_parent_in(bucket: BucketCell<'tx>) -> NodeRwCell<'tx> {
+  fn new_parent_in(bucket: BucketCell<'tx>, comparator: KeyComparator) -> NodeRwCell<'tx> {
     NodeRwCell {
-      cell: LCell::new_in(NodeW::new_parent_in(bucket), bucket.tx().bump()),
+      cell: LCell::new_in(NodeW::new_parent_in(bucket, comparator), bucket.tx().bump()),
     }
   }
 
   fn new_child_in(
-    bucket: BucketCell<'tx>, is_leaf: bool, parent: NodeRwCell<'tx>,
+    bucket: BucketCell<'tx>, comparator: KeyComparator, is_leaf: bool, parent: NodeRwCell<'tx>,
   ) -> NodeRwCell<'tx> {
     NodeRwCell {
       cell: LCell::new_in(
-        NodeW::new_child_in(bucket, is_leaf, parent),
+        NodeW::new_child_in(bucket, comparator, is_leaf, parent),
         bucket.tx().bump(),
       ),
     }
@@ -338,9 +391,9 @@ impl<'tx> NodeRwCell<'tx> {
     let child_key = child.cell.borrow().key;
     let result = {
       let self_borrow = self.cell.borrow();
-      self_borrow
-        .inodes
-        .binary_search_by(|probe| probe.key().cmp(&child_key))
+      search_by(&self_borrow.inodes, |probe| {
+        (self_borrow.comparator)(probe.key(), &child_key)
+      })
     };
     result
       .map_err(|_| child_key.as_ref())
@@ -379,37 +432,55 @@ impl<'tx> NodeRwCell<'tx> {
   }
 
   /// put inserts a key/value.
+  ///
+  /// Returns [Error::InvalidInternalState] for a zero-length key or a pgid above the
+  /// high water mark.
   pub(crate) fn put(
     self: NodeRwCell<'tx>, old_key: &'tx [u8], new_key: &'tx [u8], value: &'tx [u8], pgid: PgId,
     flags: u32,
-  ) {
+  ) -> crate::Result<()> {
     let mut self_borrow = self.cell.borrow_mut();
-    if pgid >= self_borrow.bucket.tx().meta().pgid() {
-      panic!(
-        "pgid {} above high water mark {}",
-        pgid,
-        self_borrow.bucket.tx().meta().pgid()
-      );
+    let high_water_mark = self_borrow.bucket.tx().meta().pgid();
+    if pgid >= high_water_mark {
+      return Err(Error::InvalidInternalState(format!(
+        "put: pgid {} above high water mark {}",
+        pgid, high_water_mark
+      )));
     } else if old_key.is_empty() {
-      panic!("put: zero-length old key");
+      return Err(Error::InvalidInternalState(
+        "put: zero-length old key".to_string(),
+      ));
     } else if new_key.is_empty() {
-      panic!("put: zero-length new key");
+      return Err(Error::InvalidInternalState(
+        "put: zero-length new key".to_string(),
+      ));
     }
 
     // Find insertion index.
-    let index = self_borrow
-      .inodes
-      .binary_search_by(|probe| probe.key().cmp(old_key));
+    let comparator = self_borrow.comparator;
+    let index = search_by(&self_borrow.inodes, |probe| {
+      comparator(probe.key(), old_key)
+    });
     let new_node = INode::new_owned_in(flags, pgid, new_key, value, self_borrow.bucket.tx().bump());
-    if new_node.key().is_empty() {
-      panic!("put: zero-length new key");
-    }
 
     // Add capacity and shift nodes if we don't have an exact match and need to insert.
     match index {
       Ok(exact) => *self_borrow.inodes.get_mut(exact).unwrap() = new_node,
       Err(closest) => self_borrow.inodes.get_mut_vec().insert(closest, new_node),
     }
+    Ok(())
+  }
+
+  /// replace_value replaces the value of the inode at `index`.
+  /// The key and the position of the inode are unchanged.
+  pub(crate) fn replace_value(self: NodeRwCell<'tx>, index: usize, value: &[u8]) {
+    let mut self_borrow = self.cell.borrow_mut();
+    let bump = self_borrow.bucket.tx().bump();
+    self_borrow
+      .inodes
+      .get_mut(index)
+      .unwrap()
+      .set_value_in(value, bump);
   }
 
   /// del removes a key from the node.
@@ -432,7 +503,12 @@ impl<'tx> NodeRwCell<'tx> {
       mpage.write_elements(&self_borrow.inodes);
     } else {
       let mpage = MappedBranchPage::mut_into(page);
-      mpage.write_elements(&self_borrow.inodes);
+      let prefix_len = self_borrow.prefix_len();
+      if prefix_len > 0 {
+        mpage.write_prefixed_elements(&self_borrow.inodes, prefix_len);
+      } else {
+        mpage.write_elements(&self_borrow.inodes);
+      }
     }
   }
 
@@ -445,7 +521,10 @@ impl<'tx> NodeRwCell<'tx> {
       if cell.is_spilled {
         return Ok(());
       }
-      cell.children.sort_by_key(|child| child.cell.borrow().key());
+      let comparator = cell.comparator;
+      cell
+        .children
+        .sort_by(|a, b| comparator(a.cell.borrow().key(), b.cell.borrow().key()));
       cell.bucket.tx()
     };
 
@@ -464,6 +543,8 @@ impl<'tx> NodeRwCell<'tx> {
     self.cell.borrow_mut().children.clear();
 
     let page_size = tx.page_size();
+    let checksums = tx.page_checksums();
+    let checksum_size = if checksums { PAGE_CHECKSUM_SIZE } else { 0 };
 
     // Split nodes into appropriate sizes. The first node will always be n.
     for node in self.split(page_size) {
@@ -471,14 +552,14 @@ impl<'tx> NodeRwCell<'tx> {
         let mut node_cell = node.cell.borrow_mut();
         if node_cell.pgid > ZERO_PGID {
           let any_page = tx.any_page(node_cell.pgid);
-          tx.freelist_free_page(tx.api_id(), &any_page);
+          tx.freelist_free_page(tx.api_id(), &any_page, "node spill");
           node_cell.pgid = ZERO_PGID;
         }
         node_cell.size()
       };
 
       // Allocate contiguous space for the node.
-      let mut p = tx.allocate((node_size + tx.page_size() - 1) / tx.page_size())?;
+      let mut p = tx.allocate((node_size + checksum_size).div_ceil(page_size))?;
 
       // Write the node.
       if p.id >= tx.meta().pgid() {
@@ -488,6 +569,10 @@ impl<'tx> NodeRwCell<'tx> {
 
       node_cell.pgid = p.id;
       node_cell.write(&mut p);
+      if checksums {
+        let len = (p.overflow as usize + 1) * page_size;
+        p.seal_checksum(len);
+      }
       tx.queue_page(p);
       // TODO: node is spilled here so the inodes shouldn't be touched anymore?
       node_cell.is_spilled = true;
@@ -507,7 +592,7 @@ impl<'tx> NodeRwCell<'tx> {
           &[],
           node_cell.pgid,
           0,
-        );
+        )?;
         node_cell.key = node_cell.inodes.deref()[0].cod_key();
       }
 
@@ -547,9 +632,16 @@ impl<'tx> NodeRwCell<'tx> {
 
     let mut inodes = BVec::with_capacity_in(0, self_borrow.inodes.get_vec().bump());
     mem::swap(&mut inodes, self_borrow.inodes.get_mut_vec());
+    // Every node split off shares at least the prefix of the whole node
+    let prefix_len = if self_borrow.prefix_branch && !self_borrow.is_leaf {
+      branch_prefix_len(&inodes)
+    } else {
+      0
+    };
     NodeSplit {
       page_size,
       threshold,
+      prefix_len,
       split_array: SplitArray::new(inodes),
       page_element_size: self_borrow.page_element_size(),
       next: Some(self),
@@ -593,11 +685,15 @@ impl<'tx> NodeRwCell<'tx> {
 
         let mut bucket = self_borrow.bucket.cell.borrow_mut();
 
-        // Reparent all child nodes being moved.
+        // Reparent all child nodes and patched leaves being moved.
+        let w = bucket.w.as_mut().unwrap();
         for inode in self_borrow.inodes.get_vec() {
-          if let Some(child) = bucket.w.as_mut().unwrap().nodes.get_mut(&inode.pgid()) {
+          if let Some(child) = w.nodes.get_mut(&inode.pgid()) {
             child.cell.borrow_mut().parent = Some(self);
           }
+          if let Some(patched) = w.patched.get_mut(&inode.pgid()) {
+            patched.parent = self;
+          }
         }
 
         // Remove old child.
@@ -657,7 +753,7 @@ impl<'tx> NodeRwCell<'tx> {
         let inodes: &'tx [INode] = unsafe { mem::transmute(target_borrow.inodes.deref()) };
         // Drop as child_parent may be self.
         drop(target_borrow);
-        // Reparent all child nodes being moved.
+        // Reparent all child nodes and patched leaves being moved.
         for inode in inodes {
           if let Some(child) = bucket.w.as_ref().unwrap().nodes.get(&inode.pgid()).cloned() {
             let child_parent = child.cell.borrow().parent.unwrap();
@@ -665,6 +761,9 @@ impl<'tx> NodeRwCell<'tx> {
             child.cell.borrow_mut().parent = Some(self);
             self_borrow.children.push(child);
           }
+          if let Some(patched) = bucket.w.as_mut().unwrap().patched.get_mut(&inode.pgid()) {
+            patched.parent = self;
+          }
         }
       }
 
@@ -688,7 +787,7 @@ impl<'tx> NodeRwCell<'tx> {
         let inodes: &'tx [INode] = unsafe { mem::transmute(self_borrow.inodes.deref()) };
         // Drop as child_parent may be self.
         drop(self_borrow);
-        // Reparent all child nodes being moved.
+        // Reparent all child nodes and patched leaves being moved.
         for inode in inodes {
           if let Some(child) = bucket.w.as_ref().unwrap().nodes.get(&inode.pgid()).cloned() {
             let child_parent = child.cell.borrow().parent.unwrap();
@@ -696,6 +795,9 @@ impl<'tx> NodeRwCell<'tx> {
             child.cell.borrow_mut().parent = Some(target);
             target_borrow.children.push(child);
           }
+          if let Some(patched) = bucket.w.as_mut().unwrap().patched.get_mut(&inode.pgid()) {
+            patched.parent = target;
+          }
         }
       }
       // Copy over inodes to target and remove node.
@@ -748,7 +850,9 @@ impl<'tx> NodeRwCell<'tx> {
   /// free adds the node's underlying page to the freelist.
   pub(crate) fn free(self: NodeRwCell<'tx>) {
     let (pgid, api_tx) = {
-      let self_borrow = self.cell.borrow();
+      let mut self_borrow = self.cell.borrow_mut();
+      // A freed node left the tree, so the bucket doesn't rebalance and free it again
+      self_borrow.is_unbalanced = false;
       if self_borrow.pgid == ZERO_PGID {
         return;
       }
@@ -756,22 +860,23 @@ impl<'tx> NodeRwCell<'tx> {
     };
     let page = api_tx.mem_page(pgid);
     let txid = api_tx.meta().txid();
-    api_tx.freelist_free_page(txid, &page);
+    api_tx.freelist_free_page(txid, &page, "node free");
   }
 }
 
 #[cfg(test)]
 mod test {
+  use crate::arch::aligned::{alignment, AlignedBytes};
   use crate::bucket::BucketRwIApi;
   use crate::common::ids::pd;
   use crate::common::page::{CoerciblePage, MutPage, RefPage, LEAF_PAGE_FLAG, PAGE_HEADER_SIZE};
   use crate::common::tree::{LeafPageElement, MappedLeafPage, TreePage, LEAF_PAGE_ELEMENT_SIZE};
-  use crate::common::ZERO_PGID;
+  use crate::common::{PgId, ZERO_PGID};
   use crate::node::NodeW;
   use crate::test_support::TestDb;
   use crate::tx::check::UnsealRwTx;
   use crate::tx::TxRwIApi;
-  use aligners::{alignment, AlignedBytes};
+  use crate::Error;
   use itertools::Itertools;
 
   #[test]
@@ -781,19 +886,33 @@ mod test {
     let txrw = tx.unseal_rw();
     let root_bucket = txrw.root_bucket_mut();
     let n = root_bucket.materialize_root();
-    n.put(b"baz", b"baz", b"2", ZERO_PGID, 0);
-    n.put(b"foo", b"foo", b"0", ZERO_PGID, 0);
-    n.put(b"bar", b"bar", b"1", ZERO_PGID, 0);
-    n.put(b"foo", b"foo", b"3", ZERO_PGID, LEAF_PAGE_FLAG as u32);
+    n.put(b"baz", b"baz", b"2", ZERO_PGID, 0)?;
+    n.put(b"foo", b"foo", b"0", ZERO_PGID, 0)?;
+    n.put(b"bar", b"bar", b"1", ZERO_PGID, 0)?;
+    n.put(b"foo", b"foo", b"3", ZERO_PGID, LEAF_PAGE_FLAG as u32)?;
 
     assert_eq!(3, n.cell.borrow().inodes.len());
-    let inode = &n.cell.borrow().inodes[0];
-    assert_eq!(b"bar1".split_at(3), (inode.key(), inode.value()));
-    let inode = &n.cell.borrow().inodes[1];
-    assert_eq!(b"baz2".split_at(3), (inode.key(), inode.value()));
-    let inode = &n.cell.borrow().inodes[2];
-    assert_eq!(b"foo3".split_at(3), (inode.key(), inode.value()));
-    assert_eq!(LEAF_PAGE_FLAG as u32, n.cell.borrow().inodes[2].flags());
+    {
+      let node = n.cell.borrow();
+      let inode = &node.inodes[0];
+      assert_eq!(b"bar1".split_at(3), (inode.key(), inode.value()));
+      let inode = &node.inodes[1];
+      assert_eq!(b"baz2".split_at(3), (inode.key(), inode.value()));
+      let inode = &node.inodes[2];
+      assert_eq!(b"foo3".split_at(3), (inode.key(), inode.value()));
+      assert_eq!(LEAF_PAGE_FLAG as u32, node.inodes[2].flags());
+    }
+
+    // Invalid puts are rejected without changing the node
+    assert!(matches!(
+      n.put(b"", b"", b"4", ZERO_PGID, 0),
+      Err(Error::InvalidInternalState(_))
+    ));
+    assert!(matches!(
+      n.put(b"qux", b"qux", b"", PgId(u64::MAX), 0),
+      Err(Error::InvalidInternalState(_))
+    ));
+    assert_eq!(3, n.cell.borrow().inodes.len());
     Ok(())
   }
 
@@ -859,9 +978,9 @@ mod test {
       .root_node
       .unwrap();
     {
-      n.put(b"susy", b"susy", b"que", pd(0), 0);
-      n.put(b"ricki", b"ricki", b"lake", pd(0), 0);
-      n.put(b"john", b"john", b"johnson", pd(0), 0);
+      n.put(b"susy", b"susy", b"que", pd(0), 0)?;
+      n.put(b"ricki", b"ricki", b"lake", pd(0), 0)?;
+      n.put(b"john", b"john", b"johnson", pd(0), 0)?;
       let mut mut_page = MutPage::new(page.as_mut_ptr());
       n.write(&mut mut_page);
       n.del(b"susy");
@@ -895,11 +1014,11 @@ mod test {
     let txrw = tx.unseal_rw();
     let root_bucket = txrw.root_bucket_mut();
     let n = root_bucket.materialize_root();
-    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000003", b"00000003", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000004", b"00000004", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000005", b"00000005", b"0123456701234567", ZERO_PGID, 0);
+    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000003", b"00000003", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000004", b"00000004", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000005", b"00000005", b"0123456701234567", ZERO_PGID, 0)?;
     let split_nodes = n.split(100).collect_vec();
     let binding = n.cell.borrow().parent.unwrap();
     let parent_children = &binding.cell.borrow().children;
@@ -916,8 +1035,8 @@ mod test {
     let txrw = tx.unseal_rw();
     let root_bucket = txrw.root_bucket_mut();
     let n = root_bucket.materialize_root();
-    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0);
+    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0)?;
     let _split_nodes = n.split(20).collect_vec();
     assert!(n.cell.borrow().parent.is_none(), "expected none parent");
     Ok(())
@@ -930,13 +1049,60 @@ mod test {
     let txrw = tx.unseal_rw();
     let root_bucket = txrw.root_bucket_mut();
     let n = root_bucket.materialize_root();
-    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000003", b"00000003", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000004", b"00000004", b"0123456701234567", ZERO_PGID, 0);
-    n.put(b"00000005", b"00000005", b"0123456701234567", ZERO_PGID, 0);
+    n.put(b"00000001", b"00000001", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000002", b"00000002", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000003", b"00000003", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000004", b"00000004", b"0123456701234567", ZERO_PGID, 0)?;
+    n.put(b"00000005", b"00000005", b"0123456701234567", ZERO_PGID, 0)?;
     let _split_nodes = n.split(4096).collect_vec();
     assert!(n.cell.borrow().parent.is_none(), "expected none parent");
     Ok(())
   }
+
+  #[test]
+  #[cfg(feature = "prefix-branch")]
+  fn test_node_prefix_branch() -> crate::Result<()> {
+    use crate::common::defaults::VERSION_PREFIX_BRANCH;
+    use crate::tx::check::UnsealTx;
+    use crate::tx::TxIApi;
+    use crate::{
+      BoltOptions, BucketApi, BucketRwApi, DbApi, DbRwAPI, PageElements, TxApi, TxRwRefApi,
+    };
+
+    let prefix = "tenant/0001/collection/0002/document/";
+    let mut test_db = TestDb::with_options(BoltOptions::builder().prefix_branch_keys().build())?;
+    test_db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..2000 {
+        b.put(format!("{}{:06}", prefix, i), "value")?;
+      }
+      Ok(())
+    })?;
+    test_db.must_check();
+    test_db.view(|tx| {
+      assert_eq!(VERSION_PREFIX_BRANCH, tx.unseal().meta().version());
+      let b = tx.bucket("widgets").unwrap();
+      match tx.page_elements(b.root())? {
+        Some(PageElements::Branch(elements)) => {
+          assert!(elements[0].prefix.starts_with(prefix.as_bytes()));
+        }
+        _ => panic!("expected a branch page"),
+      }
+      for i in (0..2000).step_by(7) {
+        let key = format!("{}{:06}", prefix, i);
+        assert_eq!(Some(b"value".as_slice()), b.get(key));
+      }
+      assert_eq!(2000, b.key_n());
+      Ok(())
+    })?;
+    test_db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in (0..2000).step_by(2) {
+        b.delete(format!("{}{:06}", prefix, i))?;
+      }
+      Ok(())
+    })?;
+    test_db.must_check();
+    Ok(())
+  }
 }
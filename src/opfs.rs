@@ -0,0 +1,504 @@
+use crate::Error;
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The `FileSystemSyncAccessHandle` operations [OpfsFile] needs. Mocked in the tests, which
+/// can't run in a browser worker
+pub(crate) trait AccessHandle: Clone + Send + Sync + 'static {
+  fn size(&self) -> crate::Result<u64>;
+
+  /// Reads into `buf` from byte `at`, returning the number of bytes read
+  fn read_at(&self, buf: &mut [u8], at: u64) -> crate::Result<usize>;
+
+  /// Writes `data` at byte `at`, returning the number of bytes written
+  fn write_at(&self, data: &[u8], at: u64) -> crate::Result<usize>;
+
+  fn flush(&self) -> crate::Result<()>;
+
+  fn truncate(&self, size: u64) -> crate::Result<()>;
+
+  fn close(&self);
+}
+
+#[derive(Default)]
+struct DeferredState {
+  /// a deferred flush is scheduled and hasn't started
+  scheduled: bool,
+  /// the error of the last deferred flush, until it's reported
+  failed: Option<Error>,
+}
+
+/// A deferred flush. Runs once the worker's current task yields and covers every write
+/// made until then
+pub(crate) struct DeferredFlush<H: AccessHandle> {
+  handle: H,
+  state: Arc<Mutex<DeferredState>>,
+}
+
+impl<H: AccessHandle> DeferredFlush<H> {
+  pub(crate) fn run(self) {
+    {
+      let mut state = self.state.lock();
+      // Dropping the file flushed it already
+      if !state.scheduled {
+        return;
+      }
+      // Writes from here on need another flush
+      state.scheduled = false;
+    }
+    if let Err(e) = self.handle.flush() {
+      self.state.lock().failed = Some(e);
+    }
+  }
+}
+
+/// The page store logic of `OpfsStore` over an [AccessHandle]
+pub(crate) struct OpfsFile<H: AccessHandle> {
+  handle: H,
+  deferred_flush: AtomicBool,
+  state: Arc<Mutex<DeferredState>>,
+}
+
+impl<H: AccessHandle> OpfsFile<H> {
+  pub(crate) fn new(handle: H) -> OpfsFile<H> {
+    OpfsFile {
+      handle,
+      deferred_flush: AtomicBool::new(false),
+      state: Arc::new(Mutex::new(DeferredState::default())),
+    }
+  }
+
+  pub(crate) fn set_deferred_flush(&self, deferred: bool) {
+    self.deferred_flush.store(deferred, Ordering::Release);
+  }
+
+  /// Returns the error of a deferred flush that failed since the last call
+  pub(crate) fn take_failed(&self) -> crate::Result<()> {
+    match self.state.lock().failed.take() {
+      Some(e) => Err(e),
+      None => Ok(()),
+    }
+  }
+
+  pub(crate) fn load(&self) -> crate::Result<Option<Vec<u8>>> {
+    let size = self.handle.size()? as usize;
+    if size == 0 {
+      return Ok(None);
+    }
+    let mut data = vec![0u8; size];
+    let mut read = 0;
+    while read < size {
+      let n = self.handle.read_at(&mut data[read..], read as u64)?;
+      if n == 0 {
+        data.truncate(read);
+        break;
+      }
+      read += n;
+    }
+    Ok(Some(data))
+  }
+
+  pub(crate) fn write(&self, offset: u64, data: &[u8]) -> crate::Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+      let n = self
+        .handle
+        .write_at(&data[written..], offset + written as u64)?;
+      if n == 0 {
+        return Err(Error::Other(anyhow!(
+          "write made no progress at {}",
+          offset
+        )));
+      }
+      written += n;
+    }
+    Ok(())
+  }
+
+  /// Flushes the handle, or with a deferred flush returns the flush to schedule if none is
+  /// scheduled yet
+  pub(crate) fn flush(&self) -> crate::Result<Option<DeferredFlush<H>>> {
+    // A failed deferred flush fails the commit after it
+    self.take_failed()?;
+    if !self.deferred_flush.load(Ordering::Acquire) {
+      return self.handle.flush().map(|_| None);
+    }
+    let mut state = self.state.lock();
+    if state.scheduled {
+      return Ok(None);
+    }
+    state.scheduled = true;
+    Ok(Some(DeferredFlush {
+      handle: self.handle.clone(),
+      state: self.state.clone(),
+    }))
+  }
+
+  pub(crate) fn grow(&self, size: u64) -> crate::Result<()> {
+    if size > self.handle.size()? {
+      self.handle.truncate(size)?;
+    }
+    Ok(())
+  }
+}
+
+impl<H: AccessHandle> Drop for OpfsFile<H> {
+  fn drop(&mut self) {
+    // A deferred flush that didn't run yet is done here, while the handle is open
+    let scheduled = std::mem::take(&mut self.state.lock().scheduled);
+    if scheduled {
+      let _ = self.handle.flush();
+    }
+    self.handle.close();
+  }
+}
+
+// Without the atomics target feature wasm32 runs a single thread, see Local
+#[cfg(all(
+  target_arch = "wasm32",
+  feature = "opfs-store",
+  not(target_feature = "atomics")
+))]
+pub use worker::OpfsStore;
+
+#[cfg(all(
+  target_arch = "wasm32",
+  feature = "opfs-store",
+  not(target_feature = "atomics")
+))]
+mod worker {
+  use super::{AccessHandle, OpfsFile};
+  use crate::{Error, PageStore};
+  use anyhow::anyhow;
+  use js_sys::Promise;
+  use parking_lot::Mutex;
+  use wasm_bindgen::{JsCast, JsValue};
+  use wasm_bindgen_futures::{future_to_promise, JsFuture};
+  use web_sys::{
+    FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetFileOptions,
+    FileSystemReadWriteOptions, FileSystemSyncAccessHandle, WorkerGlobalScope,
+  };
+
+  fn js_error(context: &str, e: JsValue) -> Error {
+    Error::Other(anyhow!("{}: {:?}", context, e))
+  }
+
+  /// A JS value that's only used by the thread that created it
+  #[derive(Clone)]
+  struct Local<T>(T);
+
+  // Safety: the module is only built without the atomics target feature, where wasm32 has
+  // a single thread, so a Local is never moved or shared to another thread
+  unsafe impl<T> Send for Local<T> {}
+  unsafe impl<T> Sync for Local<T> {}
+
+  impl AccessHandle for Local<FileSystemSyncAccessHandle> {
+    fn size(&self) -> crate::Result<u64> {
+      Ok(self.0.get_size().map_err(|e| js_error("size", e))? as u64)
+    }
+
+    fn read_at(&self, buf: &mut [u8], at: u64) -> crate::Result<usize> {
+      let options = FileSystemReadWriteOptions::new();
+      options.set_at(at as f64);
+      let n = self
+        .0
+        .read_with_u8_array_and_options(buf, &options)
+        .map_err(|e| js_error("read", e))?;
+      Ok(n as usize)
+    }
+
+    fn write_at(&self, data: &[u8], at: u64) -> crate::Result<usize> {
+      let options = FileSystemReadWriteOptions::new();
+      options.set_at(at as f64);
+      let n = self
+        .0
+        .write_with_u8_array_and_options(data, &options)
+        .map_err(|e| js_error("write", e))?;
+      Ok(n as usize)
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+      self.0.flush().map_err(|e| js_error("flush", e))
+    }
+
+    fn truncate(&self, size: u64) -> crate::Result<()> {
+      self
+        .0
+        .truncate_with_f64(size as f64)
+        .map_err(|e| js_error("grow", e))
+    }
+
+    fn close(&self) {
+      self.0.close();
+    }
+  }
+
+  /// OpfsStore is a [PageStore] that keeps the database in a file of the browser's Origin
+  /// Private File System.
+  ///
+  /// Pages are written through a `FileSystemSyncAccessHandle`, which browsers only hand out
+  /// to dedicated workers, so the database has to be opened in a Web Worker. The handle
+  /// holds an exclusive lock on the file until the store is dropped. The store isn't
+  /// available with the `atomics` target feature, as its JS handles can't cross threads.
+  ///
+  /// Flushes are synchronous by default: a commit returns once its pages are durable. With
+  /// [OpfsStore::set_deferred_flush] a commit returns before its pages are flushed, the
+  /// flush runs once the worker's current task yields and covers every commit made until
+  /// then. [OpfsStore::flushed] waits for it. Like the `no_sync` option, commits that
+  /// aren't flushed yet may be lost if the browser crashes.
+  ///
+  /// ```rust,no_run
+  /// use bbolt_rs::*;
+  /// use std::sync::Arc;
+  ///
+  /// async fn open() -> Result<()> {
+  ///   let store = Arc::new(OpfsStore::open("app.db").await?);
+  ///   store.set_deferred_flush(true);
+  ///   let mut db = Bolt::open_store(store.clone())?;
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket_if_not_exists("widgets")?.put("foo", "bar")?;
+  ///     Ok(())
+  ///   })?;
+  ///   store.flushed().await
+  /// }
+  /// ```
+  pub struct OpfsStore {
+    file: OpfsFile<Local<FileSystemSyncAccessHandle>>,
+    /// the last scheduled deferred flush
+    pending: Mutex<Option<Local<Promise>>>,
+  }
+
+  impl OpfsStore {
+    /// Opens the file `name` in the root of the Origin Private File System, creating it if
+    /// it doesn't exist. Fails outside of a dedicated worker.
+    pub async fn open(name: &str) -> crate::Result<OpfsStore> {
+      let global = js_sys::global()
+        .dyn_into::<WorkerGlobalScope>()
+        .map_err(|_| Error::Other(anyhow!("OpfsStore needs a dedicated worker")))?;
+      let root = JsFuture::from(global.navigator().storage().get_directory())
+        .await
+        .map_err(|e| js_error("opening the origin private file system", e))?
+        .unchecked_into::<FileSystemDirectoryHandle>();
+      let options = FileSystemGetFileOptions::new();
+      options.set_create(true);
+      let file = JsFuture::from(root.get_file_handle_with_options(name, &options))
+        .await
+        .map_err(|e| js_error(name, e))?
+        .unchecked_into::<FileSystemFileHandle>();
+      let handle = JsFuture::from(file.create_sync_access_handle())
+        .await
+        .map_err(|e| js_error(name, e))?
+        .unchecked_into::<FileSystemSyncAccessHandle>();
+      Ok(OpfsStore {
+        file: OpfsFile::new(Local(handle)),
+        pending: Mutex::new(None),
+      })
+    }
+
+    /// Sets whether [PageStore::flush] returns before the writes are durable. See
+    /// [OpfsStore]
+    pub fn set_deferred_flush(&self, deferred: bool) {
+      self.file.set_deferred_flush(deferred);
+    }
+
+    /// Waits until the writes before the last flush are durable. Returns the error of a
+    /// deferred flush that failed since the last call.
+    pub async fn flushed(&self) -> crate::Result<()> {
+      let pending = self.pending.lock().clone();
+      if let Some(pending) = pending {
+        // The flush records its error instead of rejecting
+        let _ = JsFuture::from(pending.0).await;
+      }
+      self.file.take_failed()
+    }
+  }
+
+  impl PageStore for OpfsStore {
+    fn load(&self) -> crate::Result<Option<Vec<u8>>> {
+      self.file.load()
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> crate::Result<()> {
+      self.file.write(offset, data)
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+      if let Some(deferred) = self.file.flush()? {
+        let promise = future_to_promise(async move {
+          deferred.run();
+          Ok(JsValue::UNDEFINED)
+        });
+        *self.pending.lock() = Some(Local(promise));
+      }
+      Ok(())
+    }
+
+    fn grow(&self, size: u64) -> crate::Result<()> {
+      self.file.grow(size)
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{AccessHandle, OpfsFile};
+  use crate::Error;
+  use anyhow::anyhow;
+  use parking_lot::Mutex;
+  use std::sync::Arc;
+
+  #[derive(Default)]
+  struct MockFile {
+    data: Vec<u8>,
+    /// data as of the last flush
+    durable: Vec<u8>,
+    /// the most bytes a read or write handles per call
+    chunk: usize,
+    fail_flush: bool,
+    flushes: usize,
+    closed: bool,
+  }
+
+  #[derive(Clone, Default)]
+  struct MockHandle(Arc<Mutex<MockFile>>);
+
+  impl MockHandle {
+    fn with_chunk(chunk: usize) -> MockHandle {
+      let handle = MockHandle::default();
+      handle.0.lock().chunk = chunk;
+      handle
+    }
+  }
+
+  impl AccessHandle for MockHandle {
+    fn size(&self) -> crate::Result<u64> {
+      Ok(self.0.lock().data.len() as u64)
+    }
+
+    fn read_at(&self, buf: &mut [u8], at: u64) -> crate::Result<usize> {
+      let file = self.0.lock();
+      let at = (at as usize).min(file.data.len());
+      let n = buf.len().min(file.chunk).min(file.data.len() - at);
+      buf[..n].copy_from_slice(&file.data[at..at + n]);
+      Ok(n)
+    }
+
+    fn write_at(&self, data: &[u8], at: u64) -> crate::Result<usize> {
+      let mut file = self.0.lock();
+      let at = at as usize;
+      let n = data.len().min(file.chunk);
+      if file.data.len() < at + n {
+        file.data.resize(at + n, 0);
+      }
+      file.data[at..at + n].copy_from_slice(&data[..n]);
+      Ok(n)
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+      let mut file = self.0.lock();
+      if file.closed || file.fail_flush {
+        return Err(Error::Other(anyhow!("flush failed")));
+      }
+      file.durable = file.data.clone();
+      file.flushes += 1;
+      Ok(())
+    }
+
+    fn truncate(&self, size: u64) -> crate::Result<()> {
+      self.0.lock().data.resize(size as usize, 0);
+      Ok(())
+    }
+
+    fn close(&self) {
+      self.0.lock().closed = true;
+    }
+  }
+
+  #[test]
+  fn test_opfs_file_read_write() -> crate::Result<()> {
+    let handle = MockHandle::with_chunk(3);
+    let file = OpfsFile::new(handle.clone());
+    assert_eq!(None, file.load()?);
+    file.write(2, b"hello world")?;
+    file.grow(8)?;
+    assert_eq!(13, handle.size()?);
+    file.grow(16)?;
+    let mut expected = b"\0\0hello world".to_vec();
+    expected.resize(16, 0);
+    assert_eq!(Some(expected), file.load()?);
+    Ok(())
+  }
+
+  #[test]
+  fn test_opfs_file_flush() -> crate::Result<()> {
+    let handle = MockHandle::with_chunk(4096);
+    let file = OpfsFile::new(handle.clone());
+    file.write(0, b"foo")?;
+    assert!(file.flush()?.is_none());
+    assert_eq!(b"foo", handle.0.lock().durable.as_slice());
+    Ok(())
+  }
+
+  #[test]
+  fn test_opfs_file_deferred_flush() -> crate::Result<()> {
+    let handle = MockHandle::with_chunk(4096);
+    let file = OpfsFile::new(handle.clone());
+    file.set_deferred_flush(true);
+    file.write(0, b"foo")?;
+    let deferred = file.flush()?.expect("deferred flush");
+    file.write(3, b"bar")?;
+    // The scheduled flush covers both commits
+    assert!(file.flush()?.is_none());
+    assert!(handle.0.lock().durable.is_empty());
+    deferred.run();
+    assert_eq!(b"foobar", handle.0.lock().durable.as_slice());
+    assert_eq!(1, handle.0.lock().flushes);
+
+    // Once it started, writes need another flush
+    file.write(6, b"baz")?;
+    let deferred = file.flush()?.expect("deferred flush");
+    deferred.run();
+    assert_eq!(b"foobarbaz", handle.0.lock().durable.as_slice());
+    file.take_failed()
+  }
+
+  #[test]
+  fn test_opfs_file_deferred_flush_error() -> crate::Result<()> {
+    let handle = MockHandle::with_chunk(4096);
+    let file = OpfsFile::new(handle.clone());
+    file.set_deferred_flush(true);
+    file.write(0, b"foo")?;
+    handle.0.lock().fail_flush = true;
+    file.flush()?.expect("deferred flush").run();
+    handle.0.lock().fail_flush = false;
+
+    // The next commit reports the failed flush
+    assert!(file.flush().is_err());
+    assert!(file.take_failed().is_ok());
+    file.flush()?.expect("deferred flush").run();
+    assert_eq!(b"foo", handle.0.lock().durable.as_slice());
+
+    handle.0.lock().fail_flush = true;
+    file.flush()?.expect("deferred flush").run();
+    assert!(file.take_failed().is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_opfs_file_drop_flushes() -> crate::Result<()> {
+    let handle = MockHandle::with_chunk(4096);
+    let file = OpfsFile::new(handle.clone());
+    file.set_deferred_flush(true);
+    file.write(0, b"foo")?;
+    let deferred = file.flush()?.expect("deferred flush");
+    drop(file);
+    assert!(handle.0.lock().closed);
+    assert_eq!(b"foo", handle.0.lock().durable.as_slice());
+    // The flush scheduled before the drop doesn't touch the closed handle
+    deferred.run();
+    assert_eq!(1, handle.0.lock().flushes);
+    Ok(())
+  }
+}
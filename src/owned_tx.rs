@@ -0,0 +1,220 @@
+use crate::{Bolt, BucketApi, TxApi, TxId, TxImpl, TxRef};
+use parking_lot::Mutex;
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// OwnedTx is a read-only transaction that owns a handle to its database instead of
+/// borrowing it, so it can be stored in structs and held across function boundaries
+/// without threading the `'tx` lifetime through them.
+///
+/// Values are returned as [ValueGuard]s, which keep the transaction open until the last
+/// of them is dropped. References into the transaction can't escape [OwnedTx::view].
+///
+/// OwnedTx and its ValueGuards can be sent to and shared between threads. Calls on the
+/// same OwnedTx take turns.
+///
+/// Like any read transaction, an open OwnedTx blocks [crate::DbApi::close] and remapping
+/// the database. Drop it as soon as the values are no longer needed.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///
+///   db.update(|mut tx| {
+///     let mut b = tx.create_bucket_if_not_exists("users")?;
+///     b.put("alice", "admin")?;
+///     Ok(())
+///   })?;
+///
+///   let role = {
+///     let tx = db.begin_owned()?;
+///     tx.get("users", "alice")
+///   };
+///   assert_eq!(Some(b"admin".as_ref()), role.as_deref());
+///
+///   Ok(())
+/// }
+/// ```
+pub struct OwnedTx {
+  inner: Arc<OwnedTxInner>,
+}
+
+struct OwnedTxInner {
+  // Declared before `db` so the transaction is dropped first
+  tx: Mutex<TxImpl<'static>>,
+  db: Bolt,
+}
+
+// Safety: the transaction is only used behind the mutex, one thread at a time. The pages
+// the guards' values point into are never written while the transaction is open, and the
+// database read lock the transaction holds may be released from any thread.
+unsafe impl Send for OwnedTxInner {}
+unsafe impl Sync for OwnedTxInner {}
+
+// OwnedTx and its values must stay shareable between threads
+const _: fn() = || {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<OwnedTx>();
+  assert_send_sync::<ValueGuard>();
+};
+
+impl OwnedTx {
+  /// Returns the transaction id
+  pub fn id(&self) -> TxId {
+    self.inner.tx.lock().id()
+  }
+
+  /// Returns the database the transaction was started on
+  pub fn db(&self) -> &Bolt {
+    &self.inner.db
+  }
+
+  /// Retrieves the value for a key in the top level bucket `bucket`.
+  ///
+  /// Returns None if the bucket or the key don't exist, or if the key is a nested bucket.
+  pub fn get<B: AsRef<[u8]>, K: AsRef<[u8]>>(&self, bucket: B, key: K) -> Option<ValueGuard> {
+    let tx = self.inner.tx.lock();
+    let b = tx.bucket(bucket)?;
+    let value = b.get(key)?;
+    // Safety: the value points into pages of the transaction, which the guard keeps open
+    let value = unsafe { &*(value as *const [u8]) };
+    Some(ValueGuard {
+      _tx: self.inner.clone(),
+      value,
+    })
+  }
+
+  /// Retrieves the value for a key in the nested bucket at `path`.
+  ///
+  /// Returns None if a bucket on the path or the key don't exist.
+  pub fn get_path<P: AsRef<[u8]>, K: AsRef<[u8]>>(&self, path: &[P], key: K) -> Option<ValueGuard> {
+    let (first, rest) = path.split_first()?;
+    let tx = self.inner.tx.lock();
+    let mut b = tx.bucket(first)?;
+    for name in rest {
+      b = b.bucket(name)?;
+    }
+    let value = b.get(key)?;
+    // Safety: the value points into pages of the transaction, which the guard keeps open
+    let value = unsafe { &*(value as *const [u8]) };
+    Some(ValueGuard {
+      _tx: self.inner.clone(),
+      value,
+    })
+  }
+
+  /// Executes a function with the underlying transaction.
+  ///
+  /// The function must work for any transaction lifetime, so buckets, cursors and values
+  /// borrowed from the transaction can't be returned. Copy what's needed instead.
+  pub fn view<R, F: for<'a> FnOnce(TxRef<'a>) -> R>(&self, f: F) -> R {
+    f(self.inner.tx.lock().get_ref())
+  }
+}
+
+impl Debug for OwnedTx {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("OwnedTx").field("id", &self.id()).finish()
+  }
+}
+
+/// A value read by an [OwnedTx]. Keeps the transaction open until dropped.
+pub struct ValueGuard {
+  _tx: Arc<OwnedTxInner>,
+  value: &'static [u8],
+}
+
+impl Deref for ValueGuard {
+  type Target = [u8];
+
+  fn deref(&self) -> &Self::Target {
+    self.value
+  }
+}
+
+impl AsRef<[u8]> for ValueGuard {
+  fn as_ref(&self) -> &[u8] {
+    self.value
+  }
+}
+
+impl Debug for ValueGuard {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("ValueGuard").field(&self.value).finish()
+  }
+}
+
+impl Bolt {
+  /// Begins a read-only transaction that owns a handle to the database. See [OwnedTx]
+  pub fn begin_owned(&self) -> crate::Result<OwnedTx> {
+    let db = self.clone();
+    let tx = db.begin_tx()?;
+    // Safety: the transaction borrows the shared database state behind the handle's Arc,
+    // not the handle itself. OwnedTxInner keeps a handle and drops the transaction first.
+    let tx = unsafe { mem::transmute::<TxImpl<'_>, TxImpl<'static>>(tx) };
+    Ok(OwnedTx {
+      inner: Arc::new(OwnedTxInner {
+        tx: Mutex::new(tx),
+        db,
+      }),
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::common::defaults::DEFAULT_PAGE_SIZE;
+  use crate::test_support::TestDb;
+  use crate::{BoltOptions, BucketApi, BucketRwApi, DbRwAPI, TxApi, TxRwRefApi};
+
+  #[test]
+  fn test_owned_tx() -> crate::Result<()> {
+    // The update would deadlock growing the mmap while the guards keep the read transaction open
+    let db_options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    let mut db = TestDb::with_options(db_options)?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      let mut child = b.create_bucket("child")?;
+      child.put("baz", "bat")?;
+      Ok(())
+    })?;
+
+    let tx = db.begin_owned()?;
+    let foo = tx.get("widgets", "foo");
+    let baz = tx.get_path(&["widgets", "child"], "baz");
+    assert!(tx.get("widgets", "missing").is_none());
+    assert!(tx.get("missing", "foo").is_none());
+    assert!(tx.get("widgets", "child").is_none());
+    let copied = tx.view(|tx| tx.bucket("widgets")?.get("foo").map(|v| v.to_vec()));
+    assert_eq!(Some(b"bar".to_vec()), copied);
+    drop(tx);
+
+    // The guards keep reading the original transaction after it's dropped
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.put("foo", "changed")?;
+      Ok(())
+    })?;
+    assert_eq!(Some(b"bar".as_ref()), foo.as_deref());
+    assert_eq!(Some(b"bat".as_ref()), baz.as_deref());
+
+    // The guards can be moved to another thread
+    let handle = std::thread::spawn(move || (foo, baz));
+    let (foo, baz) = handle.join().unwrap();
+    assert_eq!(Some(b"bar".as_ref()), foo.as_deref());
+    drop((foo, baz));
+    let tx = db.begin_owned()?;
+    let value = std::thread::spawn(move || tx.get("widgets", "foo"))
+      .join()
+      .unwrap();
+    assert_eq!(Some(b"changed".as_ref()), value.as_deref());
+    Ok(())
+  }
+}
@@ -0,0 +1,94 @@
+use crate::Error;
+use anyhow::anyhow;
+use std::ops::AddAssign;
+
+/// The metadata entry holding the quota of a top-level bucket and its usage
+pub(crate) const QUOTA_META_KEY: &[u8] = b"bbolt.quota";
+
+/// The limits are followed by the usage, each a big endian u64
+const RECORD_SIZE: usize = 32;
+
+/// BucketQuota limits the size of a top-level bucket, including its nested buckets.
+/// See [BucketRwApi::set_quota](crate::BucketRwApi::set_quota)
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct BucketQuota {
+  /// The maximum total size of the keys and values, or None for no limit
+  pub max_bytes: Option<u64>,
+  /// The maximum number of keys, or None for no limit. Nested buckets aren't counted.
+  pub max_keys: Option<u64>,
+}
+
+/// QuotaUsage is the size of a bucket counted against its [BucketQuota]
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct QuotaUsage {
+  /// The total size of the keys and values
+  pub bytes: u64,
+  /// The number of keys. Nested buckets aren't counted.
+  pub keys: u64,
+}
+
+impl AddAssign for QuotaUsage {
+  fn add_assign(&mut self, rhs: QuotaUsage) {
+    self.bytes += rhs.bytes;
+    self.keys += rhs.keys;
+  }
+}
+
+/// The quota of a bucket and its usage as stored in the bucket's metadata
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct QuotaRecord {
+  pub(crate) quota: BucketQuota,
+  pub(crate) usage: QuotaUsage,
+}
+
+impl QuotaRecord {
+  pub(crate) fn decode(data: &[u8]) -> crate::Result<QuotaRecord> {
+    if data.len() != RECORD_SIZE {
+      return Err(Error::Other(anyhow!(
+        "invalid quota record size {}",
+        data.len()
+      )));
+    }
+    let field = |i: usize| u64::from_be_bytes(data[i * 8..(i + 1) * 8].try_into().unwrap());
+    let limit = |i: usize| Some(field(i)).filter(|max| *max != u64::MAX);
+    Ok(QuotaRecord {
+      quota: BucketQuota {
+        max_bytes: limit(0),
+        max_keys: limit(1),
+      },
+      usage: QuotaUsage {
+        bytes: field(2),
+        keys: field(3),
+      },
+    })
+  }
+
+  pub(crate) fn encode(&self) -> [u8; RECORD_SIZE] {
+    let mut data = [0u8; RECORD_SIZE];
+    let fields = [
+      self.quota.max_bytes.unwrap_or(u64::MAX),
+      self.quota.max_keys.unwrap_or(u64::MAX),
+      self.usage.bytes,
+      self.usage.keys,
+    ];
+    for (i, field) in fields.iter().enumerate() {
+      data[i * 8..(i + 1) * 8].copy_from_slice(&field.to_be_bytes());
+    }
+    data
+  }
+
+  /// Adds `keys` keys and `bytes` bytes to the usage. Fails with [Error::QuotaExceeded] if
+  /// a growing usage exceeds its limit.
+  pub(crate) fn add(&mut self, keys: i64, bytes: i64) -> crate::Result<()> {
+    self.usage.keys = self.usage.keys.saturating_add_signed(keys);
+    self.usage.bytes = self.usage.bytes.saturating_add_signed(bytes);
+    let exceeds =
+      |delta: i64, used: u64, max: Option<u64>| delta > 0 && max.is_some_and(|max| used > max);
+    if exceeds(keys, self.usage.keys, self.quota.max_keys)
+      || exceeds(bytes, self.usage.bytes, self.quota.max_bytes)
+    {
+      return Err(Error::QuotaExceeded);
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,862 @@
+//! Logical replication of committed transactions.
+//!
+//! With [crate::BoltOptions::replication_log] set, every committed read-write transaction
+//! appends its changes to a sidecar log, in the order they were made. [subscribe_from] reads
+//! the changesets back as they are committed and an [Applier] replays them into another
+//! database, such as a warm standby. [restore_to] replays them onto a base snapshot for
+//! point-in-time recovery.
+//!
+//! ```rust
+//! use bbolt_rs::replication::Applier;
+//! use bbolt_rs::*;
+//!
+//! fn main() -> Result<()> {
+//!   let dir = tempfile::tempdir()?;
+//!   let mut db = BoltOptions::builder()
+//!     .replication_log(dir.path().join("db.replication"))
+//!     .build()
+//!     .open_mem()?;
+//!   let mut applier = Applier::new(Bolt::open_mem()?);
+//!   let mut feed = db.subscribe_from(TxId(0))?;
+//!
+//!   db.update(|mut tx| {
+//!     tx.create_bucket("widgets")?.put("foo", "bar")?;
+//!     Ok(())
+//!   })?;
+//!   for changeset in &mut feed {
+//!     applier.apply(&changeset?)?;
+//!   }
+//!
+//!   applier.db().view(|tx| {
+//!     assert_eq!(Some(b"bar".as_slice()), tx.bucket("widgets").unwrap().get("foo"));
+//!     Ok(())
+//!   })?;
+//!   Ok(())
+//! }
+//! ```
+use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi};
+use crate::common::{BVec, TxId};
+use crate::comparator::{meta_key, path_names, COMPARATOR_META_KEY, META_BUCKET, SYSTEM_BUCKET};
+use crate::tx::{TxCell, TxRwIApi};
+use crate::{Bolt, DbRwAPI, Error};
+#[cfg(file_backend_supported)]
+use crate::{BoltOptions, DbApi, TxApi};
+#[cfg(file_backend_supported)]
+use anyhow::anyhow;
+use fnv_rs::{Fnv64, FnvHasher};
+#[cfg(file_backend_supported)]
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::hash::Hasher;
+#[cfg(file_backend_supported)]
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Each record of the log starts with the txid, the length and the checksum of its changes,
+/// followed by a flag that is set once the transaction's meta page is durable
+const RECORD_HEADER_SIZE: usize = 21;
+
+/// The offset of the committed flag in a record header
+const COMMITTED_OFFSET: u64 = 20;
+
+const PUT: u8 = 0;
+const DELETE: u8 = 1;
+const CREATE_BUCKET: u8 = 2;
+const DELETE_BUCKET: u8 = 3;
+const CLEAR: u8 = 4;
+const SEQUENCE: u8 = 5;
+
+/// A change made by a read-write transaction, as recorded in its changeset.
+///
+/// `path` is the path of the modified bucket. See [crate::comparator::child_path]
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Change<'a> {
+  Put {
+    path: &'a [u8],
+    key: &'a [u8],
+    value: &'a [u8],
+  },
+  Delete {
+    path: &'a [u8],
+    key: &'a [u8],
+  },
+  CreateBucket {
+    path: &'a [u8],
+    key: &'a [u8],
+  },
+  DeleteBucket {
+    path: &'a [u8],
+    key: &'a [u8],
+  },
+  Clear {
+    path: &'a [u8],
+  },
+  Sequence {
+    path: &'a [u8],
+    value: u64,
+  },
+}
+
+fn encode_bytes(out: &mut BVec<u8>, bytes: &[u8]) {
+  out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+  out.extend_from_slice(bytes);
+}
+
+impl<'a> Change<'a> {
+  /// Appends the change to an encoded changeset
+  pub(crate) fn encode(&self, out: &mut BVec<u8>) {
+    match *self {
+      Change::Put { path, key, value } => {
+        out.push(PUT);
+        encode_bytes(out, path);
+        encode_bytes(out, key);
+        encode_bytes(out, value);
+      }
+      Change::Delete { path, key } => {
+        out.push(DELETE);
+        encode_bytes(out, path);
+        encode_bytes(out, key);
+      }
+      Change::CreateBucket { path, key } => {
+        out.push(CREATE_BUCKET);
+        encode_bytes(out, path);
+        encode_bytes(out, key);
+      }
+      Change::DeleteBucket { path, key } => {
+        out.push(DELETE_BUCKET);
+        encode_bytes(out, path);
+        encode_bytes(out, key);
+      }
+      Change::Clear { path } => {
+        out.push(CLEAR);
+        encode_bytes(out, path);
+      }
+      Change::Sequence { path, value } => {
+        out.push(SEQUENCE);
+        encode_bytes(out, path);
+        out.extend_from_slice(&value.to_be_bytes());
+      }
+    }
+  }
+}
+
+/// A single change of a [Changeset].
+///
+/// `path` holds the names of the buckets leading to the modified bucket. It is empty for
+/// top-level buckets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeOp {
+  /// A key was set to a value
+  Put {
+    path: Vec<Vec<u8>>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+  },
+  /// A key was deleted
+  Delete { path: Vec<Vec<u8>>, key: Vec<u8> },
+  /// A bucket was created
+  CreateBucket { path: Vec<Vec<u8>>, name: Vec<u8> },
+  /// A bucket was deleted along with its contents
+  DeleteBucket { path: Vec<Vec<u8>>, name: Vec<u8> },
+  /// The keys of a bucket were removed. Its sub-buckets were deleted by the changes
+  /// preceding it.
+  Clear { path: Vec<Vec<u8>> },
+  /// The sequence of a bucket was set
+  SetSequence { path: Vec<Vec<u8>>, sequence: u64 },
+}
+
+impl ChangeOp {
+  /// Returns the path of the modified bucket
+  pub fn path(&self) -> &[Vec<u8>] {
+    match self {
+      ChangeOp::Put { path, .. }
+      | ChangeOp::Delete { path, .. }
+      | ChangeOp::CreateBucket { path, .. }
+      | ChangeOp::DeleteBucket { path, .. }
+      | ChangeOp::Clear { path }
+      | ChangeOp::SetSequence { path, .. } => path,
+    }
+  }
+}
+
+/// Reads the encoded changes of a changeset
+struct Decoder<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+  fn take(&mut self, n: usize) -> crate::Result<&'a [u8]> {
+    if self.data.len() < n {
+      return Err(Error::InvalidChangeset("change truncated"));
+    }
+    let (head, tail) = self.data.split_at(n);
+    self.data = tail;
+    Ok(head)
+  }
+
+  fn bytes(&mut self) -> crate::Result<&'a [u8]> {
+    let len = u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize;
+    self.take(len)
+  }
+
+  fn path(&mut self) -> crate::Result<Vec<Vec<u8>>> {
+    let path = self.bytes()?;
+    Ok(path_names(path).into_iter().map(|n| n.to_vec()).collect())
+  }
+
+  fn op(&mut self) -> crate::Result<ChangeOp> {
+    let kind = self.take(1)?[0];
+    let path = self.path()?;
+    let op = match kind {
+      PUT => ChangeOp::Put {
+        path,
+        key: self.bytes()?.to_vec(),
+        value: self.bytes()?.to_vec(),
+      },
+      DELETE => ChangeOp::Delete {
+        path,
+        key: self.bytes()?.to_vec(),
+      },
+      CREATE_BUCKET => ChangeOp::CreateBucket {
+        path,
+        name: self.bytes()?.to_vec(),
+      },
+      DELETE_BUCKET => ChangeOp::DeleteBucket {
+        path,
+        name: self.bytes()?.to_vec(),
+      },
+      CLEAR => ChangeOp::Clear { path },
+      SEQUENCE => ChangeOp::SetSequence {
+        path,
+        sequence: u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+      },
+      _ => return Err(Error::InvalidChangeset("unknown change kind")),
+    };
+    Ok(op)
+  }
+}
+
+/// Called with the changeset of every commit. See [Bolt::add_change_listener]
+pub(crate) type ChangeListener = dyn Fn(&Changeset) + Send + Sync;
+
+/// The changes committed by one read-write transaction, in the order they were made
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Changeset {
+  txid: TxId,
+  ops: Vec<ChangeOp>,
+}
+
+impl Changeset {
+  pub(crate) fn decode(txid: TxId, data: &[u8]) -> crate::Result<Changeset> {
+    let mut decoder = Decoder { data };
+    let mut ops = Vec::new();
+    while !decoder.data.is_empty() {
+      ops.push(decoder.op()?);
+    }
+    Ok(Changeset { txid, ops })
+  }
+
+  /// The id of the transaction that committed the changes
+  pub fn txid(&self) -> TxId {
+    self.txid
+  }
+
+  /// The changes in the order they were made
+  pub fn ops(&self) -> &[ChangeOp] {
+    &self.ops
+  }
+
+  /// Returns the comparator recorded for the bucket `name` in `path` by the changes after
+  /// the change at `index`
+  fn comparator_after(&self, index: usize, path: &[Vec<u8>], name: &[u8]) -> Option<&str> {
+    let mut child = Vec::new();
+    for n in path.iter().map(|n| n.as_slice()).chain([name]) {
+      child.extend_from_slice(&(n.len() as u32).to_be_bytes());
+      child.extend_from_slice(n);
+    }
+    let entry = meta_key(&child, COMPARATOR_META_KEY);
+    self.ops[index + 1..].iter().find_map(|op| match op {
+      ChangeOp::Put { path, key, value }
+        if path.len() == 2
+          && path[0] == SYSTEM_BUCKET
+          && path[1] == META_BUCKET
+          && *key == entry =>
+      {
+        std::str::from_utf8(value).ok()
+      }
+      _ => None,
+    })
+  }
+
+  /// Replays the changes in the transaction `tx`
+  pub(crate) fn apply_to(&self, tx: TxCell) -> crate::Result<()> {
+    for (index, op) in self.ops.iter().enumerate() {
+      let bucket = bucket_at(tx, op.path())?;
+      match op {
+        // The source database validated the put already
+        ChangeOp::Put { key, value, .. } => bucket.i_put(key, value)?,
+        ChangeOp::Delete { key, .. } => bucket.api_delete(key)?,
+        ChangeOp::CreateBucket { path, name } => {
+          // A bucket's comparator has to be set when it is created. The entry recorded for it
+          // is put again by a later change, after the buckets holding it that setting the
+          // comparator created already.
+          match self.comparator_after(index, path, name) {
+            Some(comparator) => bucket.api_create_bucket_with_comparator(name, comparator)?,
+            None if path.first().unwrap_or(name) == SYSTEM_BUCKET => {
+              bucket.api_create_bucket_if_not_exists(name)?
+            }
+            None => bucket.api_create_bucket(name)?,
+          };
+        }
+        ChangeOp::DeleteBucket { name, .. } => bucket.api_delete_bucket(name)?,
+        ChangeOp::Clear { .. } => bucket.api_clear()?,
+        ChangeOp::SetSequence { sequence, .. } => bucket.api_set_sequence(*sequence)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+fn bucket_at<'tx>(tx: TxCell<'tx>, path: &[Vec<u8>]) -> crate::Result<BucketCell<'tx>> {
+  let mut bucket = tx.root_bucket_mut();
+  for name in path {
+    bucket = bucket.api_bucket(name).ok_or(Error::BucketNotFound)?;
+  }
+  Ok(bucket)
+}
+
+fn checksum(data: &[u8]) -> u64 {
+  let mut h = Fnv64::new();
+  h.update(data);
+  h.finish()
+}
+
+/// A changeset record of the replication log
+struct Record {
+  txid: TxId,
+  /// Whether the transaction's meta page is durable
+  committed: bool,
+  data: Vec<u8>,
+}
+
+impl Record {
+  /// The size of the record in the log
+  fn size(&self) -> u64 {
+    (RECORD_HEADER_SIZE + self.data.len()) as u64
+  }
+}
+
+/// Reads the record at `offset`. Returns None if the log ends before the record does and
+/// [Error::ChecksumMismatch] if the record is damaged.
+fn read_record(file: &mut File, offset: u64) -> crate::Result<Option<Record>> {
+  let len = file.metadata()?.len();
+  if len < offset + RECORD_HEADER_SIZE as u64 {
+    return Ok(None);
+  }
+  let mut header = [0u8; RECORD_HEADER_SIZE];
+  file.seek(SeekFrom::Start(offset))?;
+  file.read_exact(&mut header)?;
+  let txid = TxId(u64::from_be_bytes(header[0..8].try_into().unwrap()));
+  let data_len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as u64;
+  let sum = u64::from_be_bytes(header[12..20].try_into().unwrap());
+  let committed = header[COMMITTED_OFFSET as usize] != 0;
+  if len < offset + RECORD_HEADER_SIZE as u64 + data_len {
+    return Ok(None);
+  }
+  let mut data = vec![0u8; data_len as usize];
+  file.read_exact(&mut data)?;
+  if checksum(&data) != sum {
+    return Err(Error::ChecksumMismatch {
+      page: None,
+      meta: None,
+    });
+  }
+  Ok(Some(Record {
+    txid,
+    committed,
+    data,
+  }))
+}
+
+/// The writer of a database's replication log.
+///
+/// A transaction's changeset is appended before its meta page is written, so the log never
+/// misses a commit, and marked committed once the meta page is durable. Subscribers stop at
+/// the first record that isn't committed yet. The record of a transaction whose meta page
+/// fails to write is cut off again, so the txid can be reused.
+pub(crate) struct ReplicationLog {
+  file: File,
+  len: u64,
+  /// The txids and offsets of the records that aren't marked committed yet
+  prepared: Vec<(TxId, u64)>,
+  /// Set if a retracted record couldn't be cut off. The next append cuts it off.
+  stale_tail: bool,
+}
+
+impl ReplicationLog {
+  /// Opens the log at `path` of a database whose last commit is `txid`.
+  ///
+  /// A crash can leave records of transactions that never committed at the end of the log,
+  /// or a torn record. Both are cut off. Records of committed transactions that weren't
+  /// marked yet are marked.
+  pub(crate) fn open(path: &Path, txid: TxId) -> crate::Result<ReplicationLog> {
+    let mut file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(path)?;
+    let mut len = 0;
+    let mut changed = false;
+    while let Ok(Some(record)) = read_record(&mut file, len) {
+      if record.txid > txid {
+        break;
+      }
+      if !record.committed {
+        mark_committed(&mut file, len)?;
+        changed = true;
+      }
+      len += record.size();
+    }
+    if file.metadata()?.len() != len {
+      file.set_len(len)?;
+      changed = true;
+    }
+    if changed {
+      file.sync_all()?;
+    }
+    Ok(ReplicationLog {
+      file,
+      len,
+      prepared: Vec::new(),
+      stale_tail: false,
+    })
+  }
+
+  /// Appends the changeset of the transaction `txid`. It stays hidden from subscribers until
+  /// [ReplicationLog::commit] marks it.
+  pub(crate) fn append(&mut self, txid: TxId, changes: &[u8], sync: bool) -> crate::Result<()> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + changes.len());
+    record.extend_from_slice(&txid.0.to_be_bytes());
+    record.extend_from_slice(&(changes.len() as u32).to_be_bytes());
+    record.extend_from_slice(&checksum(changes).to_be_bytes());
+    record.push(0);
+    record.extend_from_slice(changes);
+    if self.stale_tail {
+      self.file.set_len(self.len)?;
+      self.stale_tail = false;
+    }
+    self.file.seek(SeekFrom::Start(self.len))?;
+    let result =
+      self
+        .file
+        .write_all(&record)
+        .and_then(|_| if sync { self.file.sync_data() } else { Ok(()) });
+    if let Err(e) = result {
+      // Don't leave a partial record behind for the next append
+      let _ = self.file.set_len(self.len);
+      return Err(e.into());
+    }
+    self.prepared.push((txid, self.len));
+    self.len += record.len() as u64;
+    Ok(())
+  }
+
+  /// Marks the records up to and including the transaction `txid` committed, once its meta
+  /// page is durable
+  pub(crate) fn commit(&mut self, txid: TxId) -> crate::Result<()> {
+    let n = self.prepared.partition_point(|(t, _)| *t <= txid);
+    for (_, offset) in &self.prepared[..n] {
+      mark_committed(&mut self.file, *offset)?;
+    }
+    self.prepared.drain(..n);
+    Ok(())
+  }
+
+  /// Cuts off the record of the transaction `txid`, whose meta page failed to write
+  pub(crate) fn retract(&mut self, txid: TxId) -> crate::Result<()> {
+    match self.prepared.last() {
+      Some((t, offset)) if *t == txid => {
+        let offset = *offset;
+        // The next append overwrites the record even if cutting it off fails
+        self.prepared.pop();
+        self.len = offset;
+        self.stale_tail = true;
+        self.file.set_len(offset)?;
+        self.stale_tail = false;
+        self.file.sync_data()?;
+        Ok(())
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Sets the committed flag of the record at `offset`
+fn mark_committed(file: &mut File, offset: u64) -> crate::Result<()> {
+  file.seek(SeekFrom::Start(offset + COMMITTED_OFFSET))?;
+  file.write_all(&[1])?;
+  Ok(())
+}
+
+/// Subscription iterates over the changesets of a replication log. See [subscribe_from]
+///
+/// Once the end of the log is reached `next` returns None. Calling it again later returns the
+/// changesets committed in the meantime, so a standby can poll it.
+pub struct Subscription {
+  file: File,
+  offset: u64,
+  txid: TxId,
+}
+
+impl Iterator for Subscription {
+  type Item = crate::Result<Changeset>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let record = match read_record(&mut self.file, self.offset) {
+        // The transaction may still fail to commit, so wait until its meta page is durable
+        Ok(Some(record)) if !record.committed => return None,
+        Ok(Some(record)) => record,
+        Ok(None) => return None,
+        Err(e) => return Some(Err(e)),
+      };
+      self.offset += record.size();
+      if record.txid >= self.txid {
+        return Some(Changeset::decode(record.txid, &record.data));
+      }
+    }
+  }
+}
+
+/// Reads the changesets of the replication log at `path`, starting with the one committed
+/// by the transaction `txid`. Pass the txid after the last one applied to resume a standby.
+///
+/// The log can be read from another process while the database appends to it.
+pub fn subscribe_from<P: AsRef<Path>>(path: P, txid: TxId) -> crate::Result<Subscription> {
+  let file = File::open(path)?;
+  Ok(Subscription {
+    file,
+    offset: 0,
+    txid,
+  })
+}
+
+/// Applier replays changesets into another database, e.g. to keep a warm standby.
+///
+/// Each changeset is applied in its own transaction. Changesets already applied are
+/// skipped, so the applier can be fed a subscription that starts too early. Comparators
+/// used by the source database must be registered with the target database, too.
+pub struct Applier {
+  db: Bolt,
+  last_txid: Option<TxId>,
+}
+
+impl Applier {
+  /// Creates an applier writing to `db`
+  pub fn new(db: Bolt) -> Applier {
+    Applier {
+      db,
+      last_txid: None,
+    }
+  }
+
+  /// Creates an applier writing to `db` that has already applied the changesets up to
+  /// and including `txid`
+  pub fn resume(db: Bolt, txid: TxId) -> Applier {
+    Applier {
+      db,
+      last_txid: Some(txid),
+    }
+  }
+
+  /// The txid of the last changeset applied
+  pub fn last_txid(&self) -> Option<TxId> {
+    self.last_txid
+  }
+
+  /// The database the changesets are applied to
+  pub fn db(&self) -> &Bolt {
+    &self.db
+  }
+
+  /// Applies `changeset` in a new transaction. Does nothing if it was applied already.
+  pub fn apply(&mut self, changeset: &Changeset) -> crate::Result<()> {
+    if self.last_txid.is_some_and(|txid| changeset.txid <= txid) {
+      return Ok(());
+    }
+    self.db.update(|tx| changeset.apply_to(tx.tx))?;
+    self.last_txid = Some(changeset.txid);
+    Ok(())
+  }
+}
+
+/// Restores a database as it was when the transaction `txid` committed into a new file at
+/// `dst`. Returns the txid of the last changeset applied, or the txid of `base` if none was.
+///
+/// The new file starts as a copy of the database file `base`, such as one written with
+/// [crate::SnapshotGuard::write_to], and the changesets committed after it and up to `txid`
+/// are replayed from `logs`. Pass rotated or archived replication logs oldest first.
+/// `options` are used to open the new file and need the comparators of the source database.
+///
+/// ```rust
+/// use bbolt_rs::replication::restore_to;
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let dir = tempfile::tempdir()?;
+///   let log = dir.path().join("db.replication");
+///   let mut db = BoltOptions::builder()
+///     .replication_log(&log)
+///     .build()
+///     .open_mem()?;
+///   db.update(|mut tx| {
+///     tx.create_bucket("widgets")?.put("foo", "1")?;
+///     Ok(())
+///   })?;
+///   let base = dir.path().join("base.db");
+///   db.pin_snapshot()?
+///     .write_to(&mut std::fs::File::create(&base)?)?;
+///
+///   db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "2"))?;
+///   let txid = db.begin()?.id();
+///   db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "3"))?;
+///
+///   let dst = dir.path().join("restored.db");
+///   assert_eq!(txid, restore_to(&base, [&log], txid, &dst, BoltOptions::default())?);
+///   Bolt::open_ro(&dst)?.view(|tx| {
+///     assert_eq!(Some(b"2".as_slice()), tx.bucket("widgets").unwrap().get("foo"));
+///     Ok(())
+///   })?;
+///   Ok(())
+/// }
+/// ```
+#[cfg(file_backend_supported)]
+pub fn restore_to<B, L, I, D>(
+  base: B, logs: I, txid: TxId, dst: D, options: BoltOptions,
+) -> crate::Result<TxId>
+where
+  B: AsRef<Path>,
+  L: AsRef<Path>,
+  I: IntoIterator<Item = L>,
+  D: AsRef<Path>,
+{
+  let dst = dst.as_ref();
+  let mut file = OpenOptions::new().write(true).create_new(true).open(dst)?;
+  let result = io::copy(&mut File::open(base)?, &mut file)
+    .map_err(Error::from)
+    .and_then(|_| {
+      drop(file);
+      restore_into(options.open(dst)?, logs, txid)
+    });
+  if result.is_err() {
+    let _ = fs::remove_file(dst);
+  }
+  result
+}
+
+#[cfg(file_backend_supported)]
+fn restore_into<L, I>(db: Bolt, logs: I, txid: TxId) -> crate::Result<TxId>
+where
+  L: AsRef<Path>,
+  I: IntoIterator<Item = L>,
+{
+  let base_txid = db.begin()?.id();
+  if base_txid > txid {
+    return Err(Error::Other(anyhow!(
+      "the base database is at txid {}, after txid {}",
+      base_txid,
+      txid
+    )));
+  }
+  let mut applier = Applier::resume(db, base_txid);
+  for log in logs {
+    for changeset in subscribe_from(log, base_txid + 1)? {
+      let changeset = changeset?;
+      if changeset.txid > txid {
+        return Ok(applier.last_txid().unwrap());
+      }
+      applier.apply(&changeset)?;
+    }
+  }
+  Ok(applier.last_txid().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::common::BVec;
+  #[cfg(file_backend_supported)]
+  use crate::replication::restore_to;
+  use crate::replication::{
+    subscribe_from, Applier, Change, ChangeOp, Changeset, ReplicationLog, PUT,
+  };
+  use crate::test_support::TestDb;
+  use crate::{Bolt, BoltOptions, BucketApi, BucketRwApi, DbApi, DbRwAPI, TxApi, TxId, TxRwRefApi};
+  use bumpalo::Bump;
+
+  #[test]
+  fn test_replication_feed() -> crate::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("db.replication");
+    let mut db = BoltOptions::builder()
+      .replication_log(log.clone())
+      .build()
+      .open_mem()?;
+    db.update(|mut tx| {
+      let mut widgets = tx.create_bucket("widgets")?;
+      widgets.put("a", "1")?;
+      widgets.put("b", "2")?;
+      widgets.create_bucket("sub")?.put("c", "3")?;
+      widgets.set_sequence(5)?;
+      Ok(())
+    })?;
+    let first = db.begin()?.id();
+    db.update(|mut tx| {
+      let mut widgets = tx.bucket_mut("widgets").unwrap();
+      widgets.delete("a")?;
+      widgets.delete_bucket("sub")?;
+      let savepoint = tx.savepoint();
+      tx.create_bucket("discarded")?;
+      savepoint.rollback()?;
+      Ok(())
+    })?;
+    // Rolled back transactions aren't logged
+    let _ = db.update(|mut tx| {
+      tx.create_bucket("failed")?;
+      Err(crate::Error::BucketNotFound)
+    });
+
+    let changesets = db
+      .subscribe_from(TxId(0))?
+      .collect::<crate::Result<Vec<_>>>()?;
+    assert_eq!(2, changesets.len());
+    assert_eq!(first, changesets[0].txid());
+    assert_eq!(
+      ChangeOp::Put {
+        path: vec![b"widgets".to_vec()],
+        key: b"a".to_vec(),
+        value: b"1".to_vec()
+      },
+      changesets[0].ops()[1]
+    );
+    let later = db.subscribe_from(changesets[1].txid())?.count();
+    assert_eq!(1, later);
+
+    let mut applier = Applier::new(Bolt::open_mem()?);
+    for changeset in crate::replication::subscribe_from(&log, TxId(0))? {
+      applier.apply(&changeset?)?;
+    }
+    assert_eq!(Some(changesets[1].txid()), applier.last_txid());
+    applier.db().view(|tx| {
+      let widgets = tx.bucket("widgets").unwrap();
+      assert_eq!(None, widgets.get("a"));
+      assert_eq!(Some(b"2".as_slice()), widgets.get("b"));
+      assert!(widgets.bucket("sub").is_none());
+      assert_eq!(5, widgets.sequence());
+      assert!(tx.bucket("discarded").is_none());
+      assert!(tx.bucket("failed").is_none());
+      Ok(())
+    })?;
+
+    let db = TestDb::new()?;
+    assert_eq!(
+      Some(crate::Error::ReplicationLogRequired),
+      db.subscribe_from(TxId(0)).err()
+    );
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(file_backend_supported)]
+  fn test_restore_to() -> crate::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("db.replication");
+    let mut db = BoltOptions::builder()
+      .replication_log(log.clone())
+      .build()
+      .open_mem()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("a", "0")?;
+      Ok(())
+    })?;
+    let base = dir.path().join("base.db");
+    db.pin_snapshot()?
+      .write_to(&mut std::fs::File::create(&base)?)?;
+    let base_txid = db.begin()?.id();
+    let mut txids = vec![];
+    for i in 1..=3u32 {
+      db.update(|mut tx| {
+        let mut widgets = tx.bucket_mut("widgets").unwrap();
+        widgets.put("a", i.to_string())?;
+        widgets.create_bucket(format!("sub{}", i))?;
+        Ok(())
+      })?;
+      txids.push(db.begin()?.id());
+    }
+
+    let restore = |txid: TxId, name: &str| {
+      let dst = dir.path().join(name);
+      restore_to(&base, [&log], txid, &dst, BoltOptions::default()).map(|restored| (restored, dst))
+    };
+    let (restored, dst) = restore(txids[1], "1.db")?;
+    assert_eq!(txids[1], restored);
+    Bolt::open_ro(&dst)?.view(|tx| {
+      let widgets = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"2".as_slice()), widgets.get("a"));
+      assert!(widgets.bucket("sub2").is_some());
+      assert!(widgets.bucket("sub3").is_none());
+      Ok(())
+    })?;
+
+    let (restored, _) = restore(base_txid, "base-copy.db")?;
+    assert_eq!(base_txid, restored);
+    let (restored, _) = restore(TxId(u64::MAX), "latest.db")?;
+    assert_eq!(txids[2], restored);
+
+    // The base must not be newer than the target and the destination must not exist
+    assert!(restore(TxId(base_txid.0 - 1), "old.db").is_err());
+    assert!(!dir.path().join("old.db").exists());
+    assert!(restore(txids[0], "1.db").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_replication_log_commit() -> crate::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("db.replication");
+    let mut log = ReplicationLog::open(&path, TxId(1))?;
+    let bump = Bump::new();
+    let mut changes = BVec::new_in(&bump);
+    Change::Clear { path: b"" }.encode(&mut changes);
+
+    log.append(TxId(2), &changes, true)?;
+    // Not visible until the meta is durable
+    assert_eq!(0, subscribe_from(&path, TxId(0))?.count());
+    log.commit(TxId(2))?;
+    assert_eq!(1, subscribe_from(&path, TxId(0))?.count());
+
+    // The txid of a failed commit is reused
+    log.append(TxId(3), &changes, true)?;
+    log.retract(TxId(3))?;
+    log.append(TxId(3), &changes, true)?;
+    log.commit(TxId(3))?;
+    let txids = subscribe_from(&path, TxId(0))?
+      .map(|changeset| changeset.map(|changeset| changeset.txid()))
+      .collect::<crate::Result<Vec<_>>>()?;
+    assert_eq!(vec![TxId(2), TxId(3)], txids);
+
+    // Records of committed transactions are marked when reopened, others are cut off
+    log.append(TxId(4), &changes, true)?;
+    log.append(TxId(5), &changes, true)?;
+    drop(log);
+    ReplicationLog::open(&path, TxId(4))?;
+    assert_eq!(3, subscribe_from(&path, TxId(0))?.count());
+
+    assert_eq!(
+      Some(crate::Error::InvalidChangeset("")),
+      Changeset::decode(TxId(2), &[PUT]).err()
+    );
+    Ok(())
+  }
+}
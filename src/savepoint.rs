@@ -0,0 +1,260 @@
+use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi};
+use crate::comparator::path_names;
+use crate::tx::{TxCell, TxRwIApi};
+use crate::Error::BucketNotFound;
+
+/// An undo log entry. Replaying an entry reverts one change to a bucket.
+///
+/// `path` is the path of the modified bucket. See [crate::comparator::child_path]
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Undo<'tx> {
+  /// A key was set or removed. Restores its previous value, or removes it if it didn't exist.
+  Put {
+    path: &'tx [u8],
+    key: &'tx [u8],
+    prev: Option<&'tx [u8]>,
+  },
+  /// A bucket was created. Deletes it.
+  CreateBucket { path: &'tx [u8], key: &'tx [u8] },
+  /// A bucket was deleted. Recreates it empty. Its contents are restored by the
+  /// entries recorded before it.
+  DeleteBucket {
+    path: &'tx [u8],
+    key: &'tx [u8],
+    comparator: Option<&'tx str>,
+    sequence: u64,
+  },
+  /// The sequence of a bucket was changed. Restores the previous sequence.
+  Sequence { path: &'tx [u8], prev: u64 },
+  /// A bucket was kept paged or allowed to be inlined. Restores the previous setting, its
+  /// metadata entry is restored by its own entry.
+  ForcePaged { path: &'tx [u8], prev: bool },
+}
+
+fn bucket_at<'tx>(tx: TxCell<'tx>, path: &[u8]) -> crate::Result<BucketCell<'tx>> {
+  let mut bucket = tx.root_bucket_mut();
+  for name in path_names(path) {
+    bucket = bucket.api_bucket(name).ok_or(BucketNotFound)?;
+  }
+  Ok(bucket)
+}
+
+/// Reverts the change recorded in `undo`
+pub(crate) fn replay<'tx>(tx: TxCell<'tx>, undo: Undo<'tx>) -> crate::Result<()> {
+  match undo {
+    Undo::Put {
+      path,
+      key,
+      prev: Some(prev),
+    } => bucket_at(tx, path)?.i_put(key, prev),
+    Undo::Put {
+      path,
+      key,
+      prev: None,
+    } => bucket_at(tx, path)?.api_delete(key),
+    Undo::CreateBucket { path, key } => bucket_at(tx, path)?.api_delete_bucket(key),
+    Undo::DeleteBucket {
+      path,
+      key,
+      comparator,
+      sequence,
+    } => {
+      let parent = bucket_at(tx, path)?;
+      let child = match comparator {
+        Some(comparator) => parent.api_create_bucket_with_comparator(key, comparator)?,
+        None => parent.api_create_bucket(key)?,
+      };
+      if sequence != 0 {
+        child.api_set_sequence(sequence)?;
+      }
+      Ok(())
+    }
+    Undo::Sequence { path, prev } => bucket_at(tx, path)?.api_set_sequence(prev),
+    Undo::ForcePaged { path, prev } => {
+      bucket_at(tx, path)?.set_force_paged(prev);
+      Ok(())
+    }
+  }
+}
+
+/// Savepoint marks a point within a write transaction that the transaction can be rolled
+/// back to without discarding the changes made before it.
+///
+/// Savepoints nest: rolling back to a savepoint also releases every savepoint taken after
+/// it. Buckets and cursors opened before a rollback must be reopened afterward. Dropping a
+/// savepoint releases it.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///
+///   db.update(|mut tx| {
+///     tx.create_bucket("widgets")?.put("a", "1")?;
+///
+///     let savepoint = tx.savepoint();
+///     tx.bucket_mut("widgets").unwrap().put("a", "2")?;
+///     tx.create_bucket("failed")?;
+///     savepoint.rollback()?;
+///
+///     assert!(tx.bucket("failed").is_none());
+///     assert_eq!(Some(b"1".as_slice()), tx.bucket("widgets").unwrap().get("a"));
+///     Ok(())
+///   })?;
+///
+///   Ok(())
+/// }
+/// ```
+pub struct Savepoint<'tx> {
+  tx: TxCell<'tx>,
+  id: u64,
+}
+
+impl<'tx> Savepoint<'tx> {
+  pub(crate) fn new(tx: TxCell<'tx>) -> Savepoint<'tx> {
+    let id = tx.api_savepoint();
+    Savepoint { tx, id }
+  }
+
+  /// Reverts every change made in the transaction since the savepoint was taken.
+  ///
+  /// Returns [crate::Error::SavepointReleased] if an earlier savepoint was already rolled
+  /// back. If a change can't be reverted, the remaining changes are still reverted, the
+  /// first error is returned and the transaction can no longer be committed.
+  pub fn rollback(self) -> crate::Result<()> {
+    self.tx.api_rollback_to_savepoint(self.id)
+  }
+
+  /// Keeps the changes made since the savepoint was taken. Changes are only recorded for
+  /// rollbacks while the transaction has a savepoint, so savepoints that won't be rolled
+  /// back should be released.
+  pub fn release(self) {
+    // Released when dropped
+  }
+}
+
+impl<'tx> Drop for Savepoint<'tx> {
+  fn drop(&mut self) {
+    // Savepoints rolled back to are already released
+    self.tx.api_release_savepoint(self.id)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::arch::time::Instant;
+  use crate::test_support::TestDb;
+  use crate::tx::TxRwIApi;
+  use crate::{BucketApi, BucketRwApi, DbApi, DbRwAPI, Error, TxApi, TxCheck, TxRwApi, TxRwRefApi};
+
+  #[test]
+  fn test_savepoint_rollback() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "before")?;
+      }
+      b.create_bucket("sub")?.put("foo", "bar")?;
+      b.set_sequence(7)?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let savepoint = tx.savepoint();
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..2000u32 {
+        b.put(i.to_be_bytes(), "after")?;
+      }
+      b.delete(5u32.to_be_bytes())?;
+      b.next_sequence()?;
+      tx.create_bucket("new")?.put("a", "b")?;
+      tx.delete_bucket("widgets")?;
+      savepoint.rollback()?;
+
+      assert!(tx.bucket("new").is_none());
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"before".as_slice()), b.get(999u32.to_be_bytes()));
+      assert_eq!(7, b.sequence());
+      assert_eq!(Some(b"before".as_slice()), b.get(5u32.to_be_bytes()));
+      assert_eq!(None, b.get(1500u32.to_be_bytes()));
+      assert_eq!(Some(b"bar".as_slice()), b.bucket("sub").unwrap().get("foo"));
+      assert!(tx.check().is_empty());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_savepoint_nested() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      let outer = tx.savepoint();
+      tx.bucket_mut("widgets").unwrap().put("a", "1")?;
+      let inner = tx.savepoint();
+      tx.bucket_mut("widgets").unwrap().put("b", "2")?;
+      let released = tx.savepoint();
+      released.release();
+      tx.bucket_mut("widgets").unwrap().put("c", "3")?;
+      inner.rollback()?;
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"1".as_slice()), b.get("a"));
+      assert_eq!(None, b.get("b"));
+      assert_eq!(None, b.get("c"));
+
+      let stale = tx.savepoint();
+      outer.rollback()?;
+      assert_eq!(Some(Error::SavepointReleased), stale.rollback().err());
+      assert_eq!(None, tx.bucket("widgets").unwrap().get("a"));
+      Ok(())
+    })?;
+    db.view(|tx| {
+      assert_eq!(0, tx.bucket("widgets").unwrap().key_n());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_savepoint_drop() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      {
+        let _savepoint = tx.savepoint();
+        assert!(tx.tx.undo_enabled());
+      }
+      // Dropping the savepoint stops recording changes
+      assert!(!tx.tx.undo_enabled());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_savepoint_rollback_past_deadline() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("a", "1")?;
+      Ok(())
+    })?;
+    let mut tx = db.begin_rw_tx()?;
+    let savepoint = tx.savepoint();
+    let mut b = tx.bucket_mut("widgets").unwrap();
+    for i in 0..100u32 {
+      b.put(i.to_be_bytes(), "value")?;
+    }
+    b.put("a", "2")?;
+    tx.set_deadline(Some(Instant::now()));
+    assert_eq!(Some(Error::TxTimeout), tx.create_bucket("late").err());
+    // The rollback isn't cut short by the deadline
+    savepoint.rollback()?;
+    let b = tx.bucket("widgets").unwrap();
+    assert_eq!(Some(b"1".as_slice()), b.get("a"));
+    assert_eq!(1, b.key_n());
+    tx.rollback()?;
+    Ok(())
+  }
+}
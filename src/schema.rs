@@ -0,0 +1,85 @@
+use crate::bucket::{BucketCell, BucketIApi, BucketRwIApi};
+use crate::comparator::{is_reserved, COMPARATOR_META_KEY};
+use crate::tx::{TxCell, TxRwIApi};
+use crate::Error;
+
+/// BucketSpec describes a bucket for
+/// [TxRwRefApi::ensure_schema](crate::TxRwRefApi::ensure_schema)
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// let spec = BucketSpec::new(&["users", "by-email"])
+///   .sequence(100)
+///   .comparator("ignore-case");
+/// assert_eq!(2, spec.path().len());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BucketSpec {
+  path: Vec<Vec<u8>>,
+  sequence: Option<u64>,
+  comparator: Option<String>,
+}
+
+impl BucketSpec {
+  /// Describes the bucket at `path`, starting with the top-level bucket
+  pub fn new<P: AsRef<[u8]>>(path: &[P]) -> BucketSpec {
+    BucketSpec {
+      path: path.iter().map(|name| name.as_ref().to_vec()).collect(),
+      sequence: None,
+      comparator: None,
+    }
+  }
+
+  /// Sets the sequence of the bucket when it's created
+  pub fn sequence(mut self, sequence: u64) -> BucketSpec {
+    self.sequence = Some(sequence);
+    self
+  }
+
+  /// Orders the keys of the bucket by the comparator registered as `comparator`
+  pub fn comparator(mut self, comparator: &str) -> BucketSpec {
+    self.comparator = Some(comparator.to_string());
+    self
+  }
+
+  /// Returns the path of the bucket
+  pub fn path(&self) -> &[Vec<u8>] {
+    &self.path
+  }
+}
+
+/// Opens the bucket described by `spec`, creating it and its missing parents.
+///
+/// Returns [Error::IncompatibleValue] if the bucket exists with another comparator.
+pub(crate) fn ensure_bucket<'tx>(
+  tx: TxCell<'tx>, spec: &BucketSpec,
+) -> crate::Result<BucketCell<'tx>> {
+  let (name, parents) = spec.path.split_last().ok_or(Error::BucketNameRequired)?;
+  if is_reserved(&spec.path[0]) {
+    return Err(Error::ReservedName);
+  }
+  let mut parent = tx.root_bucket_mut();
+  for p in parents {
+    parent = parent.api_create_bucket_if_not_exists(p)?;
+  }
+  match parent.api_bucket(name) {
+    Some(bucket) => {
+      let recorded = bucket.api_get_meta(COMPARATOR_META_KEY);
+      if recorded != spec.comparator.as_ref().map(|c| c.as_bytes()) {
+        return Err(Error::IncompatibleValue);
+      }
+      Ok(bucket)
+    }
+    None => {
+      let bucket = match &spec.comparator {
+        Some(comparator) => parent.api_create_bucket_with_comparator(name, comparator)?,
+        None => parent.api_create_bucket(name)?,
+      };
+      if let Some(sequence) = spec.sequence {
+        bucket.api_set_sequence(sequence)?;
+      }
+      Ok(bucket)
+    }
+  }
+}
@@ -0,0 +1,387 @@
+use crate::common::page::CoerciblePage;
+use crate::common::{PgId, TxId};
+use crate::freelist::MappedFreeListPage;
+use crate::tx::TxIApi;
+use crate::Bolt;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// ScrubReport describes the pages verified by one run of [Bolt::scrub]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+  /// The transaction whose snapshot was verified
+  pub txid: TxId,
+  /// The first page of the run
+  pub start: PgId,
+  /// The page the next run resumes at
+  pub next: PgId,
+  /// The number of pages verified, free pages included
+  pub pages: u64,
+  /// Whether the run reached the end of the database. The next run starts over at page 0.
+  pub wrapped: bool,
+  /// Every inconsistency found
+  pub errors: Vec<String>,
+}
+
+impl Display for ScrubReport {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "txid: {}", self.txid)?;
+    writeln!(f, "pages: {}-{}", self.start, self.next)?;
+    writeln!(f, "wrapped: {}", self.wrapped)?;
+    for error in &self.errors {
+      writeln!(f, "error: {}", error)?;
+    }
+    Ok(())
+  }
+}
+
+/// ScrubStats counts the work of a [Scrubber]
+#[derive(Default)]
+pub struct ScrubStats {
+  /// number of finished runs
+  runs: AtomicU64,
+  /// number of verified pages
+  pages: AtomicU64,
+  /// number of passes over the whole database
+  passes: AtomicU64,
+  /// number of reported inconsistencies
+  errors: AtomicU64,
+}
+
+impl ScrubStats {
+  /// number of finished runs
+  pub fn runs(&self) -> u64 {
+    self.runs.load(Ordering::Relaxed)
+  }
+
+  /// number of verified pages
+  pub fn pages(&self) -> u64 {
+    self.pages.load(Ordering::Relaxed)
+  }
+
+  /// number of passes over the whole database
+  pub fn passes(&self) -> u64 {
+    self.passes.load(Ordering::Relaxed)
+  }
+
+  /// number of reported inconsistencies
+  pub fn errors(&self) -> u64 {
+    self.errors.load(Ordering::Relaxed)
+  }
+
+  fn record(&self, report: &ScrubReport) {
+    self.runs.fetch_add(1, Ordering::Relaxed);
+    self.pages.fetch_add(report.pages, Ordering::Relaxed);
+    self
+      .errors
+      .fetch_add(report.errors.len() as u64, Ordering::Relaxed);
+    if report.wrapped {
+      self.passes.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+}
+
+/// Scrubber verifies the pages of a database in the background. See [Bolt::scrubber]
+///
+/// Dropping the scrubber stops it, waiting for a running run to finish.
+pub struct Scrubber {
+  stop: Option<Sender<()>>,
+  handle: Option<JoinHandle<()>>,
+  stats: Arc<ScrubStats>,
+}
+
+impl Scrubber {
+  /// The work done so far
+  pub fn stats(&self) -> &ScrubStats {
+    &self.stats
+  }
+}
+
+impl Drop for Scrubber {
+  fn drop(&mut self) {
+    // Disconnecting wakes up the scrubber thread
+    self.stop.take();
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+impl Bolt {
+  /// Verifies up to `max_pages` pages in a read transaction, in page id order starting at
+  /// `start`. Pass [ScrubReport::next] of the previous run to resume where it left off.
+  ///
+  /// Each page that isn't on the freelist of the snapshot must identify as its page id,
+  /// have a known type, end within the database and match its checksum, if it has one.
+  /// The overflow pages of a page must not be on the freelist. Meta pages are only
+  /// checked by their header since writers rewrite them while the snapshot is open. Unlike
+  /// [TxCheck::check](crate::TxCheck::check), the pages aren't checked to be reachable, so
+  /// the cost of a run is bounded by `max_pages`.
+  ///
+  /// The page at `start` is verified like any other. If it doesn't identify as its page id
+  /// because a page written since the previous run covers it with its overflow, the run
+  /// continues after that page.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket("widgets")?.put("foo", "bar")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let mut next = PgId(0);
+  ///   loop {
+  ///     let report = db.scrub(next, 2)?;
+  ///     assert!(report.errors.is_empty());
+  ///     if report.wrapped {
+  ///       break;
+  ///     }
+  ///     next = report.next;
+  ///   }
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn scrub(&self, start: PgId, max_pages: u64) -> crate::Result<ScrubReport> {
+    let guard = self.begin_tx()?;
+    let tx = guard.tx;
+    tx.without_checksums(|| {
+      let txid = tx.meta().txid();
+      let high_water = tx.meta().pgid();
+      let page_size = tx.page_size();
+      let mut errors = Vec::new();
+
+      let freelist_pgid = tx.meta().free_list();
+      let freelist_page = tx.mem_page(freelist_pgid);
+      let mut free = match MappedFreeListPage::coerce_ref(&freelist_page) {
+        Some(freelist_page) => freelist_page.page_ids().to_vec(),
+        None => {
+          errors.push(format!(
+            "page {}: invalid freelist page type: {}",
+            freelist_pgid,
+            freelist_page.page_type()
+          ));
+          Vec::new()
+        }
+      };
+      free.sort_unstable();
+      let is_free = |id: PgId| free.binary_search(&id).is_ok();
+      // Returns the end of the page before `id` whose overflow covers it, if any. Overflow
+      // pages are never free, so the search stops at the first free page.
+      let covered_until = |id: PgId| {
+        (0..id.0)
+          .rev()
+          .map(PgId)
+          .take_while(|id| !is_free(*id))
+          .find_map(|id| {
+            let page = tx.mem_page(id);
+            (page.id == id).then(|| id + page.overflow as u64 + 1)
+          })
+          .filter(|end| *end > id && *end <= high_water)
+      };
+
+      // The database may have shrunk since the previous run
+      let start = if start < high_water { start } else { PgId(0) };
+      let mut pgid = start;
+      let mut pages = 0;
+      while pages < max_pages && pgid < high_water {
+        if is_free(pgid) {
+          pgid += 1;
+          pages += 1;
+          continue;
+        }
+        let page = tx.mem_page(pgid);
+        if pgid == start && page.id != pgid {
+          if let Some(end) = covered_until(pgid) {
+            pages += end.0 - pgid.0;
+            pgid = end;
+            continue;
+          }
+        }
+        if let Err(e) = page.check(pgid) {
+          errors.push(e.to_string());
+          pgid += 1;
+          pages += 1;
+          continue;
+        }
+        let end = pgid + page.overflow as u64 + 1;
+        if end > high_water {
+          errors.push(format!(
+            "page {}: overflow {} beyond the high water mark {}",
+            pgid, page.overflow, high_water
+          ));
+          pages += high_water.0 - pgid.0;
+          pgid = high_water;
+          continue;
+        }
+        if let Err(e) = page.verify_checksum(page_size) {
+          errors.push(e.to_string());
+        }
+        for i in 1..=page.overflow as u64 {
+          if is_free(pgid + i) {
+            errors.push(format!("page {}: overflow page {} is free", pgid, pgid + i));
+          }
+        }
+        pages += end.0 - pgid.0;
+        pgid = end;
+      }
+
+      let wrapped = pgid >= high_water;
+      Ok(ScrubReport {
+        txid,
+        start,
+        next: if wrapped { PgId(0) } else { pgid },
+        pages,
+        wrapped,
+        errors,
+      })
+    })
+  }
+
+  /// Starts a thread which calls [Bolt::scrub] every `interval` to verify up to
+  /// `pages_per_run` pages, resuming where the previous run left off, and passes each
+  /// report to `on_report`.
+  ///
+  /// Full checks are too expensive to run on large databases regularly. The scrubber
+  /// spreads a pass over many short read transactions, so it holds back neither writers
+  /// nor the reuse of freed pages for long. The scrubber stops when it's dropped or when
+  /// a run fails, e.g. because the database was closed.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::time::Duration;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let db = Bolt::open_mem()?;
+  ///   let scrubber = db.scrubber(Duration::from_secs(60), 1024, |report| {
+  ///     for error in &report.errors {
+  ///       eprintln!("scrub: {}", error);
+  ///     }
+  ///   });
+  ///   assert_eq!(0, scrubber.stats().errors());
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn scrubber<F>(&self, interval: Duration, pages_per_run: u64, mut on_report: F) -> Scrubber
+  where
+    F: FnMut(&ScrubReport) + Send + 'static,
+  {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let stats = Arc::new(ScrubStats::default());
+    let thread_stats = stats.clone();
+    let db = self.clone();
+    let handle = thread::spawn(move || {
+      let mut next = PgId(0);
+      while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        let report = match db.scrub(next, pages_per_run) {
+          Ok(report) => report,
+          Err(_) => break,
+        };
+        thread_stats.record(&report);
+        on_report(&report);
+        next = report.next;
+      }
+    });
+    Scrubber {
+      stop: Some(stop),
+      handle: Some(handle),
+      stats,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_support::TestDb;
+  use crate::{BucketApi, BucketRwApi, DbApi, DbRwAPI, PgId, TxApi, TxRwRefApi};
+  use std::fs::OpenOptions;
+  use std::io::{Seek, SeekFrom, Write};
+  use std::sync::mpsc;
+  use std::time::Duration;
+
+  #[test]
+  fn test_scrub() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "value")?;
+      }
+      b.put("large", "*".repeat(10000))?;
+      Ok(())
+    })?;
+    let high_water = db.begin()?.size() / db.info().page_size as u64;
+
+    let mut next = PgId(0);
+    let mut pages = 0;
+    loop {
+      let report = db.scrub(next, 3)?;
+      assert_eq!(next, report.start);
+      assert!(report.errors.is_empty(), "{}", report);
+      pages += report.pages;
+      if report.wrapped {
+        break;
+      }
+      next = report.next;
+    }
+    assert_eq!(high_water, pages);
+
+    let (tx, rx) = mpsc::channel();
+    let scrubber = db.scrubber(Duration::from_millis(1), 2, move |report| {
+      let _ = tx.send(report.clone());
+    });
+    let report = rx.recv().unwrap();
+    assert_eq!(PgId(0), report.start);
+    assert_eq!(report.next, rx.recv().unwrap().start);
+    drop(scrubber);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_scrub_corrupted() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "*".repeat(2048))?;
+      Ok(())
+    })?;
+    let page_size = db.info().page_size as u64;
+    let root = {
+      let tx = db.begin()?;
+      tx.bucket("widgets").unwrap().root()
+    };
+    db.must_close();
+
+    // Overwrite the page id of the bucket's root page
+    {
+      let mut file = OpenOptions::new()
+        .write(true)
+        .open(db.tmp_file.as_ref().unwrap().path())?;
+      file.seek(SeekFrom::Start(root.0 * page_size))?;
+      file.write_all(&(root.0 + 1).to_ne_bytes())?;
+      file.flush()?;
+    }
+
+    db.must_reopen();
+    let report = db.scrub(PgId(0), u64::MAX)?;
+    assert!(report.wrapped);
+    assert_eq!(1, report.errors.len(), "{}", report);
+    assert!(report.errors[0].starts_with(&format!("page {}:", root)));
+
+    // A run resuming at the damaged page verifies it
+    let report = db.scrub(root, 1)?;
+    assert_eq!(1, report.errors.len(), "{}", report);
+    assert!(report.errors[0].starts_with(&format!("page {}:", root)));
+    // The database is intentionally corrupted so skip the consistency check on drop
+    db.must_close();
+    Ok(())
+  }
+}
@@ -0,0 +1,257 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
+use crate::common::defaults::DEFAULT_PAGE_SIZE;
+use crate::common::meta::MappedMetaPage;
+use crate::common::page::RefPage;
+use crate::common::PgId;
+use crate::db::{DBBackend, DbPath, MemBackend};
+use crate::freelist::Freelist;
+use crate::tx::TxCell;
+use crate::{Bolt, BoltOptions};
+use parking_lot::{Mutex, MutexGuard};
+use std::sync::Arc;
+
+/// A single page write recorded by the simulation backend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimWrite {
+  offset: u64,
+  data: Vec<u8>,
+}
+
+impl SimWrite {
+  /// The byte offset of the write
+  pub fn offset(&self) -> u64 {
+    self.offset
+  }
+
+  /// The bytes written
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+}
+
+struct SimLogInner {
+  page_size: usize,
+  initial: Vec<u8>,
+  writes: Vec<SimWrite>,
+  /// The number of writes durable at each fsync
+  sync_points: Vec<usize>,
+  /// The number of writes issued before each grow and the grown size
+  grows: Vec<(usize, u64)>,
+}
+
+/// SimLog records every page write of a simulated database.
+///
+/// Replaying a prefix of the log reconstructs the database exactly as it would be
+/// found after losing power once that many writes reached the disk. Cloning a
+/// `SimLog` is cheap and all clones refer to the same log.
+#[derive(Clone)]
+pub struct SimLog {
+  inner: Arc<Mutex<SimLogInner>>,
+}
+
+impl SimLog {
+  fn new(page_size: usize, initial: Vec<u8>) -> SimLog {
+    SimLog {
+      inner: Arc::new(Mutex::new(SimLogInner {
+        page_size,
+        initial,
+        writes: Vec::new(),
+        sync_points: Vec::new(),
+        grows: Vec::new(),
+      })),
+    }
+  }
+
+  /// Returns the number of recorded writes
+  pub fn len(&self) -> usize {
+    self.inner.lock().writes.len()
+  }
+
+  /// Returns true if no writes have been recorded
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Returns the recorded writes in order
+  pub fn writes(&self) -> Vec<SimWrite> {
+    self.inner.lock().writes.clone()
+  }
+
+  /// Returns the number of writes that were durable at each fsync
+  pub fn sync_points(&self) -> Vec<usize> {
+    self.inner.lock().sync_points.clone()
+  }
+
+  /// Returns the database image after the first `n` writes.
+  ///
+  /// `n` is clamped to the number of recorded writes.
+  pub fn image(&self, n: usize) -> Vec<u8> {
+    let inner = self.inner.lock();
+    let n = n.min(inner.writes.len());
+    let mut image = inner.initial.clone();
+    let size = inner
+      .grows
+      .iter()
+      .filter(|(at, _)| *at <= n)
+      .map(|(_, size)| *size as usize)
+      .max()
+      .unwrap_or(0);
+    if image.len() < size {
+      image.resize(size, 0);
+    }
+    for write in &inner.writes[..n] {
+      let start = write.offset as usize;
+      let end = start + write.data.len();
+      if image.len() < end {
+        image.resize(end, 0);
+      }
+      image[start..end].copy_from_slice(&write.data);
+    }
+    image
+  }
+
+  /// Opens a new simulated database from the image after the first `n` writes.
+  pub fn replay(&self, n: usize, bolt_options: BoltOptions) -> crate::Result<(Bolt, SimLog)> {
+    let page_size = self.inner.lock().page_size;
+    let image = self.image(n);
+    Bolt::new_sim_from_image(image, page_size, bolt_options)
+  }
+
+  fn record_write(&self, buffer: &[u8], offset: u64) {
+    self.inner.lock().writes.push(SimWrite {
+      offset,
+      data: buffer.to_vec(),
+    });
+  }
+
+  fn record_sync(&self) {
+    let mut inner = self.inner.lock();
+    let n = inner.writes.len();
+    inner.sync_points.push(n);
+  }
+
+  fn record_grow(&self, size: u64) {
+    let mut inner = self.inner.lock();
+    let n = inner.writes.len();
+    inner.grows.push((n, size));
+  }
+}
+
+/// A memory backend that records its writes in a [SimLog]
+struct SimBackend {
+  mem: MemBackend,
+  log: SimLog,
+}
+
+impl DBBackend for SimBackend {
+  fn page_size(&self) -> usize {
+    self.mem.page_size()
+  }
+
+  fn data_size(&self) -> u64 {
+    self.mem.data_size()
+  }
+
+  fn meta0(&self) -> MappedMetaPage {
+    self.mem.meta0()
+  }
+
+  fn meta1(&self) -> MappedMetaPage {
+    self.mem.meta1()
+  }
+
+  fn try_page<'tx>(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
+    self.mem.try_page(pg_id)
+  }
+
+  fn grow(&self, size: u64) -> crate::Result<()> {
+    self.mem.grow(size)?;
+    self.log.record_grow(size);
+    Ok(())
+  }
+
+  fn mmap(&mut self, min_size: u64, tx: TxCell) -> crate::Result<()> {
+    self.mem.mmap(min_size, tx)
+  }
+
+  fn fsync(&self) -> crate::Result<()> {
+    self.mem.fsync()?;
+    self.log.record_sync();
+    Ok(())
+  }
+
+  fn write_all_at(&self, buffer: &[u8], offset: u64) -> crate::Result<usize> {
+    let written = self.mem.write_all_at(buffer, offset)?;
+    self.log.record_write(buffer, offset);
+    Ok(written)
+  }
+
+  fn freelist(&self) -> MutexGuard<Freelist> {
+    self.mem.freelist()
+  }
+}
+
+impl Bolt {
+  pub(crate) fn new_sim_with_options(bolt_options: BoltOptions) -> crate::Result<(Bolt, SimLog)> {
+    let page_size = bolt_options
+      .page_size()
+      .unwrap_or(DEFAULT_PAGE_SIZE.bytes() as usize);
+    let image = Bolt::init_page(page_size).to_vec();
+    Bolt::new_sim_from_image(image, page_size, bolt_options)
+  }
+
+  fn new_sim_from_image(
+    image: Vec<u8>, page_size: usize, bolt_options: BoltOptions,
+  ) -> crate::Result<(Bolt, SimLog)> {
+    let mut mmap = AlignedBytes::<alignment::Page>::new_zeroed(image.len());
+    mmap.copy_from_slice(&image);
+    let log = SimLog::new(page_size, image);
+    let backend = SimBackend {
+      mem: MemBackend::new(mmap, page_size, &bolt_options),
+      log: log.clone(),
+    };
+    let db = Bolt::new_db(DbPath::Memory, bolt_options, Box::new(backend))?;
+    Ok((db, log))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{Bolt, BoltOptions, BucketApi, BucketRwApi, DbApi, DbRwAPI, TxApi, TxRwRefApi};
+  use std::cell::Cell;
+
+  #[test]
+  fn test_sim_replay_prefixes() -> crate::Result<()> {
+    let (mut db, log) = Bolt::open_sim()?;
+    for i in 0..4u8 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("widgets")?;
+        b.put([i], [i])?;
+        Ok(())
+      })?;
+    }
+    assert!(!log.is_empty());
+    assert_eq!(4 * 2, log.sync_points().len());
+
+    // Every prefix must open and contain the keys of the last committed meta
+    let mut last_count = 0;
+    for n in 0..=log.len() {
+      let (replayed, _) = log.replay(n, BoltOptions::default())?;
+      let count = Cell::new(0);
+      replayed.view(|tx| {
+        if let Some(b) = tx.bucket("widgets") {
+          b.for_each(|k, v| {
+            assert_eq!(Some(k), v);
+            count.set(count.get() + 1);
+            Ok(())
+          })?;
+        }
+        Ok(())
+      })?;
+      assert!(count.get() >= last_count);
+      last_count = count.get();
+    }
+    assert_eq!(4, last_count);
+    Ok(())
+  }
+}
@@ -0,0 +1,105 @@
+use crate::bucket::BucketIApi;
+use crate::comparator::is_reserved;
+use crate::tx::TxIApi;
+use crate::Error;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// SizeReport describes the sizes of the values of every bucket. See
+/// [TxApi::size_report](crate::TxApi::size_report)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SizeReport {
+  /// The buckets in depth-first order, each followed by its nested buckets
+  pub buckets: Vec<BucketSizeReport>,
+}
+
+/// BucketSizeReport describes the sizes of the values of a bucket, not counting its
+/// nested buckets
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BucketSizeReport {
+  /// The names of the bucket and its parents, starting with the top-level bucket
+  pub path: Vec<Vec<u8>>,
+  /// The number of values
+  pub value_n: u64,
+  /// The total size of the keys and values, in bytes
+  pub total_bytes: u64,
+  /// The value counts by size. Entry 0 counts empty values and entry `i` counts the values
+  /// of `2^(i-1)` up to `2^i - 1` bytes.
+  pub value_histogram: Vec<u64>,
+  /// The keys of the largest values with their sizes, largest first
+  pub largest: Vec<(Vec<u8>, u64)>,
+}
+
+impl BucketSizeReport {
+  /// The largest value size, in bytes
+  pub fn max_value_size(&self) -> u64 {
+    self.largest.first().map_or(0, |(_, size)| *size)
+  }
+}
+
+/// Walks the buckets below `root`. Only the keys of the `top_n` largest values of each
+/// bucket are copied.
+pub(crate) fn size_report<'tx, T: TxIApi<'tx>>(
+  root: T::BucketType, top_n: usize,
+) -> crate::Result<SizeReport> {
+  let mut report = SizeReport::default();
+  let mut path = Vec::new();
+  visit::<T>(root, top_n, &mut path, &mut report)?;
+  Ok(report)
+}
+
+fn visit<'tx, T: TxIApi<'tx>>(
+  bucket: T::BucketType, top_n: usize, path: &mut Vec<Vec<u8>>, report: &mut SizeReport,
+) -> crate::Result<()> {
+  let mut sizes = BucketSizeReport {
+    path: path.clone(),
+    ..Default::default()
+  };
+  // A min-heap of the largest values so far
+  let mut largest: BinaryHeap<Reverse<(u64, &'tx [u8])>> = BinaryHeap::with_capacity(top_n + 1);
+  let mut children = Vec::new();
+  bucket.api_for_each(|k, v| {
+    let v = match v {
+      Some(v) => v,
+      None => {
+        // The database's own records aren't reported
+        if !path.is_empty() || !is_reserved(k) {
+          children.push(k);
+        }
+        return Ok(());
+      }
+    };
+    let size = v.len() as u64;
+    sizes.value_n += 1;
+    sizes.total_bytes += (k.len() + v.len()) as u64;
+    let index = (u64::BITS - size.leading_zeros()) as usize;
+    if sizes.value_histogram.len() <= index {
+      sizes.value_histogram.resize(index + 1, 0);
+    }
+    sizes.value_histogram[index] += 1;
+    if top_n > 0 {
+      largest.push(Reverse((size, k)));
+      if largest.len() > top_n {
+        largest.pop();
+      }
+    }
+    Ok(())
+  })?;
+  sizes.largest = largest
+    .into_sorted_vec()
+    .into_iter()
+    .map(|Reverse((size, key))| (key.to_vec(), size))
+    .collect();
+  // The root bucket only holds the top-level buckets
+  if !path.is_empty() {
+    report.buckets.push(sizes);
+  }
+
+  for name in children {
+    let child = bucket.api_try_bucket(name)?.ok_or(Error::BucketNotFound)?;
+    path.push(name.to_vec());
+    visit::<T>(child, top_n, path, report)?;
+    path.pop();
+  }
+  Ok(())
+}
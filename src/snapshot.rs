@@ -0,0 +1,518 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
+use crate::common::meta::{MappedMetaPage, Meta};
+use crate::common::page::PageHeader;
+use crate::common::{PgId, TxId};
+use crate::tx::TxIApi;
+use crate::{Bolt, MetaStatus, TxRef};
+#[cfg(file_backend_supported)]
+use crate::{BoltOptions, DbApi, Error, TxApi, TxCheck};
+#[cfg(fd_sharing_supported)]
+use anyhow::anyhow;
+use std::fmt::{Display, Formatter};
+#[cfg(fd_sharing_supported)]
+use std::fs::File;
+use std::io::Write;
+use std::ops::Deref;
+#[cfg(fd_sharing_supported)]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(file_backend_supported)]
+use std::path::Path;
+use std::slice::from_raw_parts;
+
+/// SnapshotGuard pins the state of the database at the time [Bolt::pin_snapshot] was called.
+///
+/// Write transactions continue while the guard is held, but the pages of the pinned
+/// snapshot are not reused until the guard is dropped. The guard can be sent to another
+/// thread, e.g. to take a backup.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///
+///   db.update(|mut tx| {
+///     let mut b = tx.create_bucket_if_not_exists("test")?;
+///     b.put("key", "before")?;
+///     Ok(())
+///   })?;
+///
+///   let snapshot = db.pin_snapshot()?;
+///
+///   db.update(|mut tx| {
+///     let mut b = tx.bucket_mut("test").unwrap();
+///     b.put("key", "after")?;
+///     Ok(())
+///   })?;
+///
+///   snapshot.view(|tx| {
+///     let b = tx.bucket("test").unwrap();
+///     assert_eq!(Some(b"before".as_ref()), b.get("key"));
+///     Ok(())
+///   })?;
+///
+///   let mut backup = Vec::new();
+///   snapshot.write_to(&mut backup)?;
+///   assert_eq!(backup.len() as u64, snapshot.size());
+///
+///   Ok(())
+/// }
+/// ```
+pub struct SnapshotGuard {
+  db: Bolt,
+  meta: Meta,
+}
+
+impl SnapshotGuard {
+  /// Returns the id of the transaction the snapshot was taken at
+  pub fn txid(&self) -> TxId {
+    self.meta.txid()
+  }
+
+  /// Returns the size of the database in bytes as seen by the snapshot
+  pub fn size(&self) -> u64 {
+    self.meta.pgid().0 * self.meta.page_size() as u64
+  }
+
+  /// Executes a function within the context of a read-only transaction on the snapshot.
+  ///
+  /// Returns [crate::Error::DatabaseNotOpen] if the database was closed.
+  pub fn view<'tx, F: FnMut(TxRef<'tx>) -> crate::Result<()>>(
+    &'tx self, mut f: F,
+  ) -> crate::Result<()> {
+    let tx = self.db.begin_tx_at(Some(self.meta))?;
+    let tx_ref = tx.get_ref();
+    f(tx_ref)
+  }
+
+  /// Writes the entire snapshot to a writer as a database file.
+  ///
+  /// Returns the number of bytes written.
+  pub fn write_to<W: Write>(&self, w: &mut W) -> crate::Result<u64> {
+    let tx = self.db.begin_tx_at(Some(self.meta))?;
+    let page_size = self.meta.page_size() as usize;
+
+    // Both meta pages are written from the pinned meta
+    let mut buffer = AlignedBytes::<alignment::Page>::new_zeroed(page_size);
+    let mut meta_page = unsafe { MappedMetaPage::new(buffer.as_mut_ptr()) };
+    for id in 0..2 {
+      self.meta.write(&mut meta_page);
+      meta_page.page.id = PgId(id);
+      w.write_all(&buffer)?;
+    }
+
+    // Data pages are written one at a time. Overflow counts of free pages can't be trusted.
+    for id in 2..self.meta.pgid().0 {
+      let page = tx.tx.mem_page(PgId(id));
+      let ptr = (page.deref() as *const PageHeader).cast::<u8>();
+      w.write_all(unsafe { from_raw_parts(ptr, page_size) })?;
+    }
+    Ok(self.size())
+  }
+
+  /// Shares the snapshot with sidecar processes without copying it. See [SharedSnapshot]
+  ///
+  /// Returns [Error::Other] for in-memory databases, which have no file to share.
+  #[cfg(fd_sharing_supported)]
+  pub fn share(self) -> crate::Result<SharedSnapshot> {
+    let file = match self.db.path().file_path() {
+      Some(path) => File::open(path)?,
+      None => return Err(Error::Other(anyhow!("in-memory databases can't be shared"))),
+    };
+    Ok(SharedSnapshot {
+      file,
+      snapshot: self,
+    })
+  }
+}
+
+impl Drop for SnapshotGuard {
+  fn drop(&mut self) {
+    self.db.unpin_meta(self.meta.txid());
+  }
+}
+
+impl Bolt {
+  /// Pins the current state of the database. See [SnapshotGuard]
+  pub fn pin_snapshot(&self) -> crate::Result<SnapshotGuard> {
+    let meta = self.pin_meta()?;
+    Ok(SnapshotGuard {
+      db: self.clone(),
+      meta,
+    })
+  }
+}
+
+/// SharedSnapshot hands a [SnapshotGuard] to sidecar processes, e.g. for analytics.
+///
+/// Sidecars map the pages of the database file directly alongside the writer, nothing is
+/// copied. Hand them the read-only file descriptor by inheritance or over a Unix socket
+/// together with the [SharedSnapshot::lease] and open it with [Bolt::open_shared]. They
+/// read the database as of [SharedSnapshot::txid] while the writer goes on.
+///
+/// The SharedSnapshot is the reader lease: the pages of the snapshot aren't reused while
+/// it is alive. Keep it until every sidecar closed the database.
+///
+/// # Platform support
+///
+/// Only available on Linux. [Bolt::open_shared] maps the file by reopening the
+/// descriptor through `/proc/self/fd`, so the sidecar needs procfs mounted. Other
+/// platforms can copy a snapshot with [SnapshotGuard::write_to] instead.
+///
+/// The snapshot isn't exposed through a memfd or POSIX shared memory. The database isn't
+/// stored in either, so every page of the snapshot would have to be copied into it, and
+/// the copy would use as much memory as the database. Sharing the database file lets the
+/// sidecar map the pages the writer already has in the page cache.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let path = std::env::temp_dir().join("shared_snapshot_doctest.db");
+///   std::fs::File::create(&path)?;
+///   let mut db = Bolt::open(&path)?;
+///   db.update(|mut tx| {
+///     let mut b = tx.create_bucket_if_not_exists("test")?;
+///     b.put("key", "before")?;
+///     Ok(())
+///   })?;
+///
+///   let shared = db.pin_snapshot()?.share()?;
+///   db.update(|mut tx| tx.bucket_mut("test").unwrap().put("key", "after"))?;
+///
+///   // In the sidecar, with the descriptor and lease received from the writer
+///   let sidecar = Bolt::open_shared(&shared, &shared.lease())?;
+///   sidecar.view(|tx| {
+///     assert_eq!(Some(b"before".as_ref()), tx.bucket("test").unwrap().get("key"));
+///     Ok(())
+///   })?;
+///   drop(sidecar);
+///   drop(shared);
+///   db.close();
+///   std::fs::remove_file(&path)?;
+///   Ok(())
+/// }
+/// ```
+#[cfg(fd_sharing_supported)]
+pub struct SharedSnapshot {
+  file: File,
+  snapshot: SnapshotGuard,
+}
+
+#[cfg(fd_sharing_supported)]
+impl SharedSnapshot {
+  /// Returns the id of the transaction the snapshot was taken at
+  pub fn txid(&self) -> TxId {
+    self.snapshot.txid()
+  }
+
+  /// Returns the meta of the snapshot for [Bolt::open_shared]. The meta pages of the file
+  /// move on with the writer, so sidecars need it to find the snapshot.
+  pub fn lease(&self) -> Vec<u8> {
+    bytemuck::bytes_of(&self.snapshot.meta).to_vec()
+  }
+}
+
+#[cfg(fd_sharing_supported)]
+impl AsFd for SharedSnapshot {
+  fn as_fd(&self) -> BorrowedFd<'_> {
+    self.file.as_fd()
+  }
+}
+
+#[cfg(fd_sharing_supported)]
+impl AsRawFd for SharedSnapshot {
+  fn as_raw_fd(&self) -> RawFd {
+    self.file.as_raw_fd()
+  }
+}
+
+/// The result of [Bolt::verify_backup]
+///
+/// Displays as `key: value` lines for scripts: `valid`, `txid`, one `meta<N>` line per meta
+/// page and one `error` line per problem found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupReport {
+  /// The state of the meta pages. None if the backup couldn't be opened
+  pub meta: Option<MetaStatus>,
+  /// The id of the transaction the backup was taken at. None if it couldn't be opened
+  pub txid: Option<TxId>,
+  /// The problems found in the backup
+  pub errors: Vec<String>,
+}
+
+impl BackupReport {
+  /// Returns true if no problems were found
+  pub fn is_valid(&self) -> bool {
+    self.errors.is_empty()
+  }
+}
+
+impl Display for BackupReport {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "valid: {}", self.is_valid())?;
+    if let Some(txid) = self.txid {
+      writeln!(f, "txid: {}", txid)?;
+    }
+    if let Some(meta) = &self.meta {
+      for i in 0..2 {
+        let state = if meta.valid[i] { "valid" } else { "invalid" };
+        writeln!(f, "meta{}: txid {} {}", i, meta.txids[i], state)?;
+      }
+    }
+    for error in &self.errors {
+      writeln!(f, "error: {}", error)?;
+    }
+    Ok(())
+  }
+}
+
+impl Bolt {
+  /// Opens a snapshot shared with [SnapshotGuard::share] read-only. `fd` is the descriptor
+  /// of the [SharedSnapshot] and `lease` its [SharedSnapshot::lease]. The descriptor can be
+  /// closed once the database is open.
+  ///
+  /// The database file isn't locked, the writer keeps it open. Returns
+  /// [Error::InvalidDatabase] or [Error::ChecksumMismatch] if `lease` isn't a valid meta.
+  ///
+  /// Only available on Linux, the file is reopened through `/proc/self/fd`. See
+  /// [SharedSnapshot#platform-support]
+  #[cfg(fd_sharing_supported)]
+  pub fn open_shared<F: AsFd>(fd: F, lease: &[u8]) -> crate::Result<impl DbApi> {
+    let meta = match bytemuck::try_pod_read_unaligned::<Meta>(lease) {
+      Ok(meta) => meta,
+      Err(_) => {
+        return Err(Error::InvalidDatabase {
+          meta_can_read: false,
+          meta: None,
+        })
+      }
+    };
+    meta.validate()?;
+    let path = format!("/proc/self/fd/{}", fd.as_fd().as_raw_fd());
+    BoltOptions::default().open_pinned_bolt(path, meta)
+  }
+
+  /// Verifies a backup of a database, e.g. a file written by [SnapshotGuard::write_to].
+  ///
+  /// The backup is opened read-only with [crate::BoltOptionsBuilder::untrusted] validation,
+  /// both meta pages are checked and every bucket is walked like [crate::TxCheck::check],
+  /// including the checksums of pages written with
+  /// [crate::BoltOptionsBuilder::page_checksums]. Problems with the backup are returned in
+  /// the report. Returns an error only if the file can't be read.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::fs;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let path = std::env::temp_dir().join("verify_backup_doctest.db");
+  ///   let mut file = fs::File::create(&path)?;
+  ///   db.pin_snapshot()?.write_to(&mut file)?;
+  ///
+  ///   let report = Bolt::verify_backup(&path)?;
+  ///   assert!(report.is_valid());
+  ///   fs::remove_file(&path)?;
+  ///   Ok(())
+  /// }
+  /// ```
+  #[cfg(file_backend_supported)]
+  pub fn verify_backup<T: AsRef<Path>>(path: T) -> crate::Result<BackupReport> {
+    let options = BoltOptions::builder().untrusted().build();
+    let db = match options.open_ro_bolt(path) {
+      Ok(db) => db,
+      Err(Error::IO(e)) => return Err(Error::IO(e)),
+      Err(e) => {
+        return Ok(BackupReport {
+          meta: None,
+          txid: None,
+          errors: vec![e.to_string()],
+        })
+      }
+    };
+    let meta = db.verify_meta()?;
+    let mut errors = Vec::new();
+    for i in 0..2 {
+      if !meta.valid[i] {
+        errors.push(format!("meta page {} is invalid", i));
+      }
+    }
+    // The transaction has to end before the database closes
+    let txid = {
+      let tx = db.begin()?;
+      errors.extend(tx.check());
+      tx.id()
+    };
+    db.close();
+    Ok(BackupReport {
+      meta: Some(meta),
+      txid: Some(txid),
+      errors,
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::common::page::PAGE_HEADER_SIZE;
+  use crate::test_support::{temp_file, TestDb};
+  use crate::{
+    Bolt, BoltOptions, BucketApi, BucketRwApi, DbApi, DbRwAPI, Error, TxApi, TxCheck, TxRwRefApi,
+  };
+  use std::io::Write;
+  use std::thread;
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_snapshot_write_to() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "before")?;
+      }
+      Ok(())
+    })?;
+    let snapshot = db.pin_snapshot()?;
+    for _ in 0..5 {
+      db.update(|mut tx| {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        for i in 0..1000u32 {
+          b.put(i.to_be_bytes(), "after!")?;
+        }
+        Ok(())
+      })?;
+    }
+
+    let backup = thread::spawn(move || {
+      let mut backup = Vec::new();
+      snapshot.write_to(&mut backup).map(|_| backup)
+    })
+    .join()
+    .unwrap()?;
+
+    let mut file = temp_file()?;
+    file.write_all(&backup)?;
+    let copy = BoltOptions::default().open(file.path())?;
+    copy.view(|tx| {
+      assert!(tx.check().is_empty());
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"before".as_slice()), b.get(7u32.to_be_bytes()));
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"after!".as_slice()), b.get(7u32.to_be_bytes()));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(miri, feature = "test-mem-backend")))]
+  fn test_verify_backup() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..100u32 {
+        b.put(i.to_be_bytes(), "value")?;
+      }
+      Ok(())
+    })?;
+    let snapshot = db.pin_snapshot()?;
+    let mut backup = Vec::new();
+    snapshot.write_to(&mut backup)?;
+
+    let mut file = temp_file()?;
+    file.write_all(&backup)?;
+    let report = Bolt::verify_backup(file.path())?;
+    assert!(report.is_valid(), "{}", report);
+    assert_eq!(Some(snapshot.txid()), report.txid);
+    assert!(report.to_string().starts_with("valid: true\n"));
+
+    // A damaged meta page is reported even though the backup still opens
+    backup[PAGE_HEADER_SIZE + 16] ^= 0xFF;
+    let mut file = temp_file()?;
+    file.write_all(&backup)?;
+    let report = Bolt::verify_backup(file.path())?;
+    assert!(!report.is_valid());
+    assert_eq!(Some([false, true]), report.meta.map(|m| m.valid));
+
+    let mut file = temp_file()?;
+    file.write_all(&[0xAB; 8192])?;
+    let report = Bolt::verify_backup(file.path())?;
+    assert!(!report.is_valid());
+    assert_eq!(None, report.meta);
+
+    let missing = file.path().with_extension("missing");
+    assert!(Bolt::verify_backup(missing).is_err());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(fd_sharing_supported, not(any(miri, feature = "test-mem-backend"))))]
+  fn test_snapshot_share() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "before")?;
+      }
+      Ok(())
+    })?;
+    let shared = db.pin_snapshot()?.share()?;
+    // Without the lease these commits would reuse the pages of the snapshot
+    for _ in 0..5 {
+      db.update(|mut tx| {
+        let mut b = tx.bucket_mut("widgets").unwrap();
+        for i in 0..1000u32 {
+          b.put(i.to_be_bytes(), "after!")?;
+        }
+        Ok(())
+      })?;
+    }
+
+    let sidecar = Bolt::open_shared(&shared, &shared.lease())?;
+    sidecar.view(|tx| {
+      assert_eq!(tx.id(), shared.txid());
+      assert!(tx.check().is_empty());
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"before".as_slice()), b.get(7u32.to_be_bytes()));
+      Ok(())
+    })?;
+    assert!((&shared.file).write_all(b"changed").is_err());
+
+    let mut lease = shared.lease();
+    lease[20] ^= 0xFF;
+    assert!(Bolt::open_shared(&shared, &lease).is_err());
+    assert!(Bolt::open_shared(&shared, &lease[..8]).is_err());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(fd_sharing_supported)]
+  fn test_snapshot_share_mem() -> crate::Result<()> {
+    let db = Bolt::open_mem()?;
+    assert!(db.pin_snapshot()?.share().is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_snapshot_closed() -> crate::Result<()> {
+    let db = TestDb::new()?;
+    let snapshot = db.pin_snapshot()?;
+    db.clone_db().close();
+    assert_eq!(
+      Some(Error::DatabaseNotOpen),
+      snapshot.view(|_| Ok(())).err()
+    );
+    Ok(())
+  }
+}
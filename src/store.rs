@@ -0,0 +1,490 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
+use crate::common::defaults::DEFAULT_PAGE_SIZE;
+use crate::common::meta::{MappedMetaPage, Meta, META_HEADER_SIZE};
+use crate::common::page::{RefPage, PAGE_HEADER_SIZE};
+use crate::common::rng::SplitMix64;
+use crate::common::PgId;
+use crate::db::{DBBackend, DbPath, MemBackend};
+use crate::freelist::Freelist;
+use crate::tx::TxCell;
+use crate::{Bolt, BoltOptions, Error};
+use parking_lot::{Mutex, MutexGuard};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// PageStore persists the pages of a memory backed database.
+///
+/// The database is kept in memory and every page write is passed on to the store.
+/// `flush` is called at the same points a file backed database would fsync, once
+/// the data pages and once the meta page of a commit are written, and a commit is
+/// only reported durable once `flush` returns. The crate doesn't ship stores for
+/// specific platforms. All calls are synchronous, so a store that persists
+/// asynchronously has to block in `flush` until its writes are durable.
+pub trait PageStore: Send + Sync {
+  /// Returns the stored database image, or `None` if the store is empty
+  fn load(&self) -> crate::Result<Option<Vec<u8>>>;
+
+  /// Stores `data` at byte `offset`, growing the store if needed
+  fn write(&self, offset: u64, data: &[u8]) -> crate::Result<()>;
+
+  /// Makes all previous writes durable
+  fn flush(&self) -> crate::Result<()>;
+
+  /// Called when the database grows to `size` bytes, before the pages past the old end
+  /// are written. Stores that preallocate can reserve the space here
+  fn grow(&self, _size: u64) -> crate::Result<()> {
+    Ok(())
+  }
+}
+
+/// A type of [PageStore] operation
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum StoreOp {
+  /// [PageStore::load]
+  Load,
+  /// [PageStore::write]
+  Write,
+  /// [PageStore::flush]
+  Flush,
+}
+
+impl StoreOp {
+  fn index(self) -> usize {
+    match self {
+      StoreOp::Load => 0,
+      StoreOp::Write => 1,
+      StoreOp::Flush => 2,
+    }
+  }
+}
+
+/// The faults [InstrumentedStore] injects into one type of operation
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct StoreFaults {
+  /// The delay added to every operation
+  pub latency: Duration,
+  /// The probability of an operation failing with an I/O error without reaching the
+  /// wrapped store, from 0 to 1
+  pub error_rate: f64,
+  /// The probability of an operation being cut short, from 0 to 1. A short load returns
+  /// a prefix of the image, a short write stores a prefix of the data and then fails.
+  /// Flushes can't be cut short.
+  pub short_rate: f64,
+}
+
+/// InstrumentedStore wraps a [PageStore] and injects latency, errors, short loads and torn
+/// writes, to validate how an application copes with a slow or failing disk.
+///
+/// The faults are drawn from a generator seeded on creation, so the same seed and
+/// sequence of operations inject the same faults. Latency is added with
+/// [std::thread::sleep], which isn't available on the main thread of a browser.
+///
+/// ```rust
+/// use bbolt_rs::*;
+/// use parking_lot::Mutex;
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct VecStore(Mutex<Vec<u8>>);
+///
+/// impl PageStore for VecStore {
+///   fn load(&self) -> Result<Option<Vec<u8>>> {
+///     let data = self.0.lock();
+///     Ok(Some(data.clone()).filter(|data| !data.is_empty()))
+///   }
+///
+///   fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
+///     let mut store = self.0.lock();
+///     let end = offset as usize + data.len();
+///     if store.len() < end {
+///       store.resize(end, 0);
+///     }
+///     store[offset as usize..end].copy_from_slice(data);
+///     Ok(())
+///   }
+///
+///   fn flush(&self) -> Result<()> {
+///     Ok(())
+///   }
+/// }
+///
+/// fn main() -> Result<()> {
+///   let store = Arc::new(InstrumentedStore::new(Arc::new(VecStore::default()), 42));
+///   let mut db = Bolt::open_store(store.clone())?;
+///
+///   store.set_faults(
+///     StoreOp::Write,
+///     StoreFaults {
+///       error_rate: 1.0,
+///       ..Default::default()
+///     },
+///   );
+///   assert!(db.update(|mut tx| tx.root_put("key", "value")).is_err());
+///   assert!(store.injected(StoreOp::Write) > 0);
+///
+///   store.clear();
+///   db.update(|mut tx| tx.root_put("key", "value"))?;
+///   Ok(())
+/// }
+/// ```
+pub struct InstrumentedStore {
+  inner: Arc<dyn PageStore>,
+  faults: Mutex<[StoreFaults; 3]>,
+  rng: Mutex<SplitMix64>,
+  injected: [AtomicU64; 3],
+}
+
+impl InstrumentedStore {
+  /// Wraps `inner` without injecting faults yet. `seed` seeds the generator the faults
+  /// are drawn from.
+  pub fn new(inner: Arc<dyn PageStore>, seed: u64) -> InstrumentedStore {
+    InstrumentedStore {
+      inner,
+      faults: Mutex::new([StoreFaults::default(); 3]),
+      rng: Mutex::new(SplitMix64(seed)),
+      injected: Default::default(),
+    }
+  }
+
+  /// Sets the faults injected into the operations of type `op` from now on
+  pub fn set_faults(&self, op: StoreOp, faults: StoreFaults) {
+    self.faults.lock()[op.index()] = faults;
+  }
+
+  /// Stops injecting faults
+  pub fn clear(&self) {
+    *self.faults.lock() = [StoreFaults::default(); 3];
+  }
+
+  /// The number of errors, short loads and torn writes injected into the operations of
+  /// type `op`. Latency isn't counted.
+  pub fn injected(&self, op: StoreOp) -> u64 {
+    self.injected[op.index()].load(Ordering::Relaxed)
+  }
+
+  /// Adds the latency of `op` and draws the fault to inject
+  fn inject(&self, op: StoreOp) -> Fault {
+    let faults = self.faults.lock()[op.index()];
+    if !faults.latency.is_zero() {
+      thread::sleep(faults.latency);
+    }
+    let mut rng = self.rng.lock();
+    let fault = if faults.error_rate > 0.0 && rng.next_f64() < faults.error_rate {
+      Fault::Error
+    } else if faults.short_rate > 0.0 && rng.next_f64() < faults.short_rate {
+      Fault::Short(rng.next_f64())
+    } else {
+      return Fault::None;
+    };
+    self.injected[op.index()].fetch_add(1, Ordering::Relaxed);
+    fault
+  }
+
+  fn injected_error(op: StoreOp) -> Error {
+    Error::IO(io::Error::new(
+      io::ErrorKind::Other,
+      format!("injected {:?} error", op),
+    ))
+  }
+}
+
+/// A fault drawn by [InstrumentedStore::inject]
+enum Fault {
+  None,
+  Error,
+  /// Cut short to the given fraction of the data
+  Short(f64),
+}
+
+impl PageStore for InstrumentedStore {
+  fn load(&self) -> crate::Result<Option<Vec<u8>>> {
+    match self.inject(StoreOp::Load) {
+      Fault::None => self.inner.load(),
+      Fault::Error => Err(InstrumentedStore::injected_error(StoreOp::Load)),
+      Fault::Short(fraction) => Ok(self.inner.load()?.map(|mut image| {
+        image.truncate((image.len() as f64 * fraction) as usize);
+        image
+      })),
+    }
+  }
+
+  fn write(&self, offset: u64, data: &[u8]) -> crate::Result<()> {
+    match self.inject(StoreOp::Write) {
+      Fault::None => self.inner.write(offset, data),
+      Fault::Error => Err(InstrumentedStore::injected_error(StoreOp::Write)),
+      Fault::Short(fraction) => {
+        let len = (data.len() as f64 * fraction) as usize;
+        self.inner.write(offset, &data[..len])?;
+        Err(InstrumentedStore::injected_error(StoreOp::Write))
+      }
+    }
+  }
+
+  fn flush(&self) -> crate::Result<()> {
+    match self.inject(StoreOp::Flush) {
+      Fault::Error => Err(InstrumentedStore::injected_error(StoreOp::Flush)),
+      _ => self.inner.flush(),
+    }
+  }
+
+  fn grow(&self, size: u64) -> crate::Result<()> {
+    self.inner.grow(size)
+  }
+}
+
+/// A memory backend that passes its writes on to a [PageStore]
+struct StoreBackend {
+  mem: MemBackend,
+  store: Arc<dyn PageStore>,
+}
+
+impl DBBackend for StoreBackend {
+  fn page_size(&self) -> usize {
+    self.mem.page_size()
+  }
+
+  fn data_size(&self) -> u64 {
+    self.mem.data_size()
+  }
+
+  fn meta0(&self) -> MappedMetaPage {
+    self.mem.meta0()
+  }
+
+  fn meta1(&self) -> MappedMetaPage {
+    self.mem.meta1()
+  }
+
+  fn try_page<'tx>(&self, pg_id: PgId) -> crate::Result<RefPage<'tx>> {
+    self.mem.try_page(pg_id)
+  }
+
+  fn grow(&self, size: u64) -> crate::Result<()> {
+    self.store.grow(size)?;
+    self.mem.grow(size)
+  }
+
+  fn mmap(&mut self, min_size: u64, tx: TxCell) -> crate::Result<()> {
+    self.mem.mmap(min_size, tx)
+  }
+
+  fn fsync(&self) -> crate::Result<()> {
+    self.store.flush()
+  }
+
+  fn write_all_at(&self, buffer: &[u8], offset: u64) -> crate::Result<usize> {
+    self.store.write(offset, buffer)?;
+    self.mem.write_all_at(buffer, offset)
+  }
+
+  fn freelist(&self) -> MutexGuard<Freelist> {
+    self.mem.freelist()
+  }
+}
+
+/// Reads the page size from the first valid meta page of `image`
+fn image_page_size(image: &[u8]) -> crate::Result<usize> {
+  let read_meta = |pos: usize| {
+    image
+      .get(pos + PAGE_HEADER_SIZE..pos + PAGE_HEADER_SIZE + META_HEADER_SIZE)
+      .map(bytemuck::pod_read_unaligned::<Meta>)
+      .filter(|meta| meta.validate().is_ok())
+      .map(|meta| meta.page_size() as usize)
+  };
+  if let Some(page_size) = read_meta(0) {
+    return Ok(page_size);
+  }
+  (0..15)
+    .map(|i| 1024usize << i)
+    .take_while(|pos| *pos < image.len())
+    .find_map(read_meta)
+    .ok_or(Error::InvalidDatabase {
+      meta_can_read: image.len() >= PAGE_HEADER_SIZE + META_HEADER_SIZE,
+      meta: None,
+    })
+}
+
+impl Bolt {
+  pub(crate) fn new_store_with_options(
+    store: Arc<dyn PageStore>, bolt_options: BoltOptions,
+  ) -> crate::Result<Bolt> {
+    let (mmap, page_size) = match store.load()? {
+      Some(image) => {
+        let page_size = image_page_size(&image)?;
+        let mut mmap = AlignedBytes::<alignment::Page>::new_zeroed(image.len());
+        mmap.copy_from_slice(&image);
+        (mmap, page_size)
+      }
+      None => {
+        let page_size = bolt_options
+          .page_size()
+          .unwrap_or(DEFAULT_PAGE_SIZE.bytes() as usize);
+        let mmap = Bolt::init_page(page_size);
+        store.write(0, &mmap)?;
+        store.flush()?;
+        (mmap, page_size)
+      }
+    };
+    let backend = StoreBackend {
+      mem: MemBackend::new(mmap, page_size, &bolt_options),
+      store,
+    };
+    Bolt::new_db(DbPath::Memory, bolt_options, Box::new(backend))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::arch::time::Instant;
+  use crate::{
+    Bolt, BucketApi, BucketRwApi, DbApi, DbRwAPI, Error, InstrumentedStore, PageStore, StoreFaults,
+    StoreOp, TxApi, TxRwRefApi,
+  };
+  use parking_lot::Mutex;
+  use std::sync::atomic::{AtomicU64, Ordering};
+  use std::sync::Arc;
+  use std::time::Duration;
+
+  #[derive(Default)]
+  struct VecStore {
+    data: Mutex<Vec<u8>>,
+    flushed: Mutex<Vec<u8>>,
+    grown: AtomicU64,
+  }
+
+  impl PageStore for VecStore {
+    fn load(&self) -> crate::Result<Option<Vec<u8>>> {
+      let flushed = self.flushed.lock();
+      Ok(Some(flushed.clone()).filter(|data| !data.is_empty()))
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> crate::Result<()> {
+      let mut store = self.data.lock();
+      let end = offset as usize + data.len();
+      if store.len() < end {
+        store.resize(end, 0);
+      }
+      store[offset as usize..end].copy_from_slice(data);
+      Ok(())
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+      *self.flushed.lock() = self.data.lock().clone();
+      Ok(())
+    }
+
+    fn grow(&self, size: u64) -> crate::Result<()> {
+      self.grown.fetch_max(size, Ordering::Relaxed);
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn test_store_reopen() -> crate::Result<()> {
+    let store = Arc::new(VecStore::default());
+    {
+      let mut db = Bolt::open_store(store.clone())?;
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket("widgets")?;
+        b.put("foo", "bar")?;
+        Ok(())
+      })?;
+      db.close();
+    }
+    let db = Bolt::open_store(store)?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_store_grow() -> crate::Result<()> {
+    let store = Arc::new(VecStore::default());
+    let mut db = Bolt::open_store(store.clone())?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0u32..1000 {
+        b.put(i.to_be_bytes(), [0u8; 512])?;
+      }
+      Ok(())
+    })?;
+    let grown = store.grown.load(Ordering::Relaxed);
+    assert!(grown > 0);
+    assert!(grown >= store.data.lock().len() as u64);
+    Ok(())
+  }
+
+  #[test]
+  fn test_instrumented_store() -> crate::Result<()> {
+    let inner = Arc::new(VecStore::default());
+    let store = Arc::new(InstrumentedStore::new(inner.clone(), 7));
+    {
+      let mut db = Bolt::open_store(store.clone())?;
+      db.update(|mut tx| tx.create_bucket("widgets")?.put("foo", "bar"))?;
+
+      // Torn writes fail the commit, which is rolled back
+      store.set_faults(
+        StoreOp::Write,
+        StoreFaults {
+          short_rate: 1.0,
+          ..Default::default()
+        },
+      );
+      let r = db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "baz"));
+      assert!(matches!(r, Err(Error::IO(_))), "{:?}", r);
+      assert_eq!(1, store.injected(StoreOp::Write));
+
+      let latency = Duration::from_millis(10);
+      store.clear();
+      store.set_faults(
+        StoreOp::Flush,
+        StoreFaults {
+          latency,
+          ..Default::default()
+        },
+      );
+      let start = Instant::now();
+      db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "qux"))?;
+      assert!(start.elapsed() >= latency);
+      assert_eq!(0, store.injected(StoreOp::Flush));
+      db.close();
+    }
+
+    store.set_faults(
+      StoreOp::Load,
+      StoreFaults {
+        error_rate: 1.0,
+        ..Default::default()
+      },
+    );
+    assert!(matches!(Bolt::open_store(store.clone()), Err(Error::IO(_))));
+    store.clear();
+    let db = Bolt::open_store(store)?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"qux".as_slice()), b.get("foo"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_store_invalid_image() {
+    let store = VecStore::default();
+    *store.flushed.lock() = vec![0u8; 8192];
+    let r = Bolt::open_store(Arc::new(store)).err();
+    assert_eq!(
+      Some(Error::InvalidDatabase {
+        meta_can_read: true,
+        meta: None
+      }),
+      r
+    );
+  }
+}
@@ -1,8 +1,8 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
 use crate::common::page::CoerciblePage;
 use crate::common::self_owned::SelfOwned;
 use crate::tx::check::{TxCheck, UnsealRwTx, UnsealTx};
 use crate::{Bolt, BoltOptions, DbApi, TxApi, TxRwRefApi};
-use aligners::{alignment, AlignedBytes};
 use std::ops::{Deref, DerefMut};
 use tempfile::{Builder, NamedTempFile};
 
@@ -0,0 +1,251 @@
+//! Helpers for testing code built on bbolt-rs.
+//!
+//! ```rust
+//! use bbolt_rs::testing::{assert_consistent, sequential_keys, values, MemDb};
+//! use bbolt_rs::*;
+//!
+//! fn main() -> Result<()> {
+//!   let db = MemDb::new()
+//!     .bucket("widgets", sequential_keys(0).zip(values(16, 7)).take(100))
+//!     .open()?;
+//!
+//!   db.view(|tx| {
+//!     assert_eq!(100, tx.bucket("widgets").unwrap().key_n());
+//!     Ok(())
+//!   })?;
+//!   assert_consistent(&db);
+//!   Ok(())
+//! }
+//! ```
+use crate::common::rng::SplitMix64;
+use crate::{Bolt, BoltOptions, BucketRwApi, DbApi, DbRwAPI, TxCheck, TxRwRefApi};
+#[cfg(file_backend_supported)]
+use std::ops::{Deref, DerefMut};
+#[cfg(file_backend_supported)]
+use std::path::Path;
+#[cfg(file_backend_supported)]
+use tempfile::{Builder, NamedTempFile};
+
+/// Panics with every error reported by [TxCheck::check] if the database is inconsistent
+pub fn assert_consistent<T: DbApi>(db: &T) {
+  let errors = match db.begin() {
+    Ok(tx) => tx.check(),
+    Err(e) => vec![e.to_string()],
+  };
+  assert!(
+    errors.is_empty(),
+    "database is inconsistent:\n{}",
+    errors.join("\n")
+  );
+}
+
+/// Returns the big-endian u64 keys `start`, `start + 1`, ... so keys sort in the order
+/// they're generated
+pub fn sequential_keys(start: u64) -> impl Iterator<Item = Vec<u8>> {
+  (start..).map(|i| i.to_be_bytes().to_vec())
+}
+
+/// Returns random keys of `len` bytes. The same `seed` always generates the same keys.
+pub fn random_keys(len: usize, seed: u64) -> impl Iterator<Item = Vec<u8>> {
+  let mut rng = SplitMix64(seed);
+  std::iter::repeat_with(move || rng.fill(len))
+}
+
+/// Returns random values of `len` bytes. The same `seed` always generates the same values.
+pub fn values(len: usize, seed: u64) -> impl Iterator<Item = Vec<u8>> {
+  random_keys(len, !seed)
+}
+
+type Seed = (Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>);
+
+/// MemDb builds a memory backed database filled with test data
+#[derive(Default)]
+pub struct MemDb {
+  options: BoltOptions,
+  buckets: Vec<Seed>,
+}
+
+impl MemDb {
+  /// Starts an empty database with the default options
+  pub fn new() -> MemDb {
+    MemDb::default()
+  }
+
+  /// Sets the options the database is opened with
+  pub fn options(mut self, options: BoltOptions) -> MemDb {
+    self.options = options;
+    self
+  }
+
+  /// Adds the key/value pairs to the top-level bucket `name`, creating it if needed
+  pub fn bucket<N, I, K, V>(mut self, name: N, pairs: I) -> MemDb
+  where
+    N: AsRef<[u8]>,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    let pairs = pairs
+      .into_iter()
+      .map(|(k, v)| (k.as_ref().to_vec(), v.as_ref().to_vec()))
+      .collect();
+    self.buckets.push((name.as_ref().to_vec(), pairs));
+    self
+  }
+
+  /// Opens the database and writes the buckets in a single transaction
+  pub fn open(self) -> crate::Result<Bolt> {
+    let mut db = self.options.open_mem()?;
+    seed(&mut db, &self.buckets)?;
+    Ok(db)
+  }
+}
+
+fn seed(db: &mut Bolt, buckets: &[Seed]) -> crate::Result<()> {
+  if buckets.is_empty() {
+    return Ok(());
+  }
+  db.update(|mut tx| {
+    for (name, pairs) in buckets {
+      let mut b = tx.create_bucket_if_not_exists(name)?;
+      for (k, v) in pairs {
+        b.put(k, v)?;
+      }
+    }
+    Ok(())
+  })
+}
+
+/// TempDb is a file backed database in a temporary file. The file is deleted when the
+/// TempDb is dropped.
+#[cfg(file_backend_supported)]
+pub struct TempDb {
+  db: Option<Bolt>,
+  file: NamedTempFile,
+  options: BoltOptions,
+}
+
+#[cfg(file_backend_supported)]
+impl TempDb {
+  /// Opens a new database with the default options
+  pub fn new() -> crate::Result<TempDb> {
+    TempDb::with_options(BoltOptions::default())
+  }
+
+  /// Opens a new database with `options`
+  pub fn with_options(options: BoltOptions) -> crate::Result<TempDb> {
+    let file = Builder::new()
+      .prefix("bbolt-rs-")
+      .suffix(".db")
+      .tempfile()?;
+    let db = options.clone().open(file.path())?;
+    Ok(TempDb {
+      db: Some(db),
+      file,
+      options,
+    })
+  }
+
+  /// Returns the path of the database file
+  pub fn path(&self) -> &Path {
+    self.file.path()
+  }
+
+  /// Closes and reopens the database with the same options, e.g. to test that data
+  /// survives a restart
+  pub fn reopen(&mut self) -> crate::Result<()> {
+    if let Some(db) = self.db.take() {
+      db.close();
+    }
+    self.db = Some(self.options.clone().open(self.file.path())?);
+    Ok(())
+  }
+}
+
+#[cfg(file_backend_supported)]
+impl Deref for TempDb {
+  type Target = Bolt;
+
+  fn deref(&self) -> &Self::Target {
+    self.db.as_ref().unwrap()
+  }
+}
+
+#[cfg(file_backend_supported)]
+impl DerefMut for TempDb {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    self.db.as_mut().unwrap()
+  }
+}
+
+#[cfg(file_backend_supported)]
+impl Drop for TempDb {
+  fn drop(&mut self) {
+    // Close before the file is deleted
+    if let Some(db) = self.db.take() {
+      db.close();
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::testing::{assert_consistent, random_keys, sequential_keys, values, MemDb};
+  use crate::{BucketApi, DbApi, TxApi};
+
+  #[test]
+  fn test_testing_generators() {
+    let keys = sequential_keys(5).take(3).collect::<Vec<_>>();
+    assert_eq!(
+      vec![5u64.to_be_bytes().to_vec(), 6u64.to_be_bytes().to_vec()],
+      keys[..2]
+    );
+    assert_eq!(
+      random_keys(13, 1).take(10).collect::<Vec<_>>(),
+      random_keys(13, 1).take(10).collect::<Vec<_>>()
+    );
+    assert!(values(13, 1).take(10).all(|v| v.len() == 13));
+    assert_ne!(random_keys(8, 1).next(), random_keys(8, 2).next());
+  }
+
+  #[test]
+  fn test_testing_mem_db() -> crate::Result<()> {
+    let db = MemDb::new()
+      .bucket("a", sequential_keys(0).zip(values(4, 0)).take(500))
+      .bucket("b", [("foo", "bar")])
+      .open()?;
+    db.view(|tx| {
+      assert_eq!(500, tx.bucket("a").unwrap().key_n());
+      assert_eq!(Some(b"bar".as_slice()), tx.bucket("b").unwrap().get("foo"));
+      Ok(())
+    })?;
+    assert_consistent(&db);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn test_testing_temp_db() -> crate::Result<()> {
+    use crate::testing::TempDb;
+    use crate::{BucketRwApi, DbRwAPI, TxRwRefApi};
+
+    let mut db = TempDb::new()?;
+    let path = db.path().to_path_buf();
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.reopen()?;
+    db.view(|tx| {
+      assert_eq!(
+        Some(b"bar".as_slice()),
+        tx.bucket("widgets").unwrap().get("foo")
+      );
+      Ok(())
+    })?;
+    assert_consistent(&*db);
+    drop(db);
+    assert!(!path.exists());
+    Ok(())
+  }
+}
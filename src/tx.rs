@@ -1,28 +1,49 @@
+use crate::arch::aligned::{alignment, AlignedBytes};
 use crate::arch::size::MAX_ALLOC_SIZE;
+use crate::arch::time::Instant;
 use crate::bucket::{
-  BucketCell, BucketIApi, BucketImpl, BucketR, BucketRW, BucketRwIApi, BucketRwImpl, BucketW,
+  BucketCell, BucketIApi, BucketImpl, BucketMetrics, BucketR, BucketRW, BucketRwIApi, BucketRwImpl,
+  BucketStructure, BucketW,
 };
+use crate::common::bucket_cache::BucketCache;
 use crate::common::bump::PinBump;
 use crate::common::cell::{Ref, RefCell, RefMut};
-use crate::common::defaults::IGNORE_NO_SYNC;
+use crate::common::defaults::{IGNORE_NO_SYNC, PGID_NO_FREE_LIST};
+use crate::common::leaf_cache::LeafCache;
 use crate::common::lock::{LockGuard, PinLockGuard};
 use crate::common::memory::BCell;
-use crate::common::meta::{MappedMetaPage, Meta, MetaPage};
-use crate::common::page::{CoerciblePage, MutPage, PageHeader, PageInfo, RefPage};
+use crate::common::meta::{MappedMetaPage, Meta, MetaInfo, MetaPage};
+use crate::common::page::{
+  CoerciblePage, MutPage, PageElements, PageHeader, PageInfo, RefPage, PAGE_HEADER_SIZE,
+};
 use crate::common::pool::SyncReusable;
 use crate::common::self_owned::SelfOwned;
-use crate::common::tree::{MappedBranchPage, TreePage};
-use crate::common::{BVec, HashMap, PgId, SplitRef, TxId};
+use crate::common::tree::{
+  MappedBranchPage, MappedLeafPage, TreePage, BRANCH_PAGE_ELEMENT_SIZE, LEAF_PAGE_ELEMENT_SIZE,
+};
+use crate::common::{BVec, HashMap, HashSet, MapHasher, PgId, SplitRef, TxId, ZERO_PGID};
+use crate::comparator::{is_reserved, Comparators, KeyComparator, ROOT_KV_BUCKET, SYSTEM_BUCKET};
 use crate::cursor::{CursorImpl, InnerCursor};
 use crate::db::{AllocateResult, DbIApi, DbMutIApi, DbShared};
+use crate::export::ExportIter;
+#[cfg(feature = "failpoints")]
+use crate::failpoints::FailPoint;
+#[cfg(feature = "replication")]
+use crate::replication::Change;
+use crate::savepoint::{replay, Savepoint, Undo};
+use crate::schema::{ensure_bucket, BucketSpec};
+use crate::size_report::{size_report, SizeReport};
 use crate::tx::check::TxICheck;
-use crate::TxCheck;
+use crate::{Error, TxCheck};
 use aliasable::boxed::AliasableBox;
-use aligners::{alignment, AlignedBytes};
 use bumpalo::Bump;
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+use getset::CopyGetters;
 use parking_lot::{Mutex, RwLockReadGuard, RwLockUpgradableReadGuard};
 use std::alloc::Layout;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Formatter};
 use std::io::Write;
 use std::marker::PhantomData;
@@ -31,10 +52,10 @@ use std::mem::MaybeUninit;
 use std::ops::{Deref, SubAssign};
 use std::pin::Pin;
 use std::ptr::{addr_of, addr_of_mut};
-use std::slice::from_raw_parts_mut;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 /// Read-only transaction API
 pub trait TxApi<'tx>: TxCheck<'tx> {
@@ -169,12 +190,93 @@ pub trait TxApi<'tx>: TxCheck<'tx> {
   /// ```
   fn bucket<T: AsRef<[u8]>>(&self, name: T) -> Option<BucketImpl<'tx>>;
 
+  /// Retrieves a bucket by name like [TxApi::bucket], but returns an error instead of
+  /// panicking if the bucket can't be opened, e.g. [crate::Error::UnknownComparator] if its
+  /// recorded comparator isn't registered.
+  /// Returns None if the bucket does not exist.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket_if_not_exists("test")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert!(tx.try_bucket("test")?.is_some());
+  ///     assert!(tx.try_bucket("missing")?.is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>>;
+
+  /// Retrieves the root data bucket, which holds values stored directly in the database with
+  /// [TxRwRefApi::root_put] instead of in a named bucket. It's kept with the database's own
+  /// records and isn't listed among the buckets, see [RESERVED_PREFIX](crate::RESERVED_PREFIX).
+  ///
+  /// Returns None if no value was stored yet.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.root_put("schema-version", "3")?;
+  ///     tx.root_put("instance-id", "a1b2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert_eq!(2, tx.root_kv().unwrap().key_n());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn root_kv(&self) -> Option<BucketImpl<'tx>>;
+
+  /// Retrieves the value of a key of the root data bucket. See [TxApi::root_kv]
+  ///
+  /// Returns None if the key does not exist.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| tx.root_put("schema-version", "3"))?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert_eq!(Some(b"3".as_slice()), tx.root_get("schema-version"));
+  ///     assert_eq!(None, tx.root_get("instance-id"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn root_get<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]>;
+
   /// Executes a function for each key/value pair in a bucket.
   /// Because ForEach uses a Cursor, the iteration over keys is in lexicographical order.
   ///
   /// If the provided function returns an error then the iteration is stopped and
   /// the error is returned to the caller.
   ///
+  /// The function may read the transaction, e.g. open other buckets with [TxApi::bucket]
+  /// or a cursor with [TxApi::cursor].
+  ///
   /// ```rust
   /// use bbolt_rs::*;
   ///
@@ -207,6 +309,44 @@ pub trait TxApi<'tx>: TxCheck<'tx> {
     &self, f: F,
   ) -> crate::Result<()>;
 
+  /// Executes a function for every key in the database, walking nested buckets depth first.
+  ///
+  /// The function receives the names of the buckets containing the key, starting at the
+  /// top level bucket, the key and its value. Nested buckets are passed with a None value
+  /// before their contents. Keys are visited in lexicographical order within each bucket.
+  ///
+  /// If the provided function returns an error then the walk is stopped and
+  /// the error is returned to the caller. The function may read the transaction.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key1", "value1")?;
+  ///     let mut nested = b.create_bucket("nested")?;
+  ///     nested.put("key2", "value2")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     tx.walk(|path, k, v| {
+  ///       println!("{:?} {:?}->{:?}", path, k, v);
+  ///       Ok(())
+  ///     })?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()>;
+
   /// Returns page information for a given page number.
   ///
   /// This is only safe for concurrent use when used by a writable transaction.
@@ -234,13 +374,13 @@ pub trait TxApi<'tx>: TxCheck<'tx> {
   /// }
   /// ```
   fn page(&self, id: PgId) -> Option<PageInfo>;
-}
 
-/// RW transaction API
-pub trait TxRwRefApi<'tx>: TxApi<'tx> {
-  /// Retrieves a mutable bucket by name.
+  /// Returns the raw bytes of a page, including its overflow pages.
   ///
-  /// Returns None if the bucket does not exist.
+  /// Returns [Error::PageOutOfRange] if the page is at or beyond the high water mark of the
+  /// meta the transaction began on. Pages modified or allocated by a writable transaction
+  /// are returned in their committed state. The bytes are only valid for the life of the
+  /// transaction.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -254,26 +394,86 @@ pub trait TxRwRefApi<'tx>: TxApi<'tx> {
   ///     Ok(())
   ///   })?;
   ///
+  ///   let page_size = db.info().page_size;
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     let bytes = tx.page_bytes(b.root())?;
+  ///     assert_eq!(page_size, bytes.len());
+  ///     assert!(tx.page_bytes(PgId(u64::MAX)).is_err());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn page_bytes(&self, id: PgId) -> crate::Result<&[u8]>;
+
+  /// Returns the bounds checked elements of a branch, leaf or freelist page.
+  ///
+  /// Returns [Error::PageOutOfRange] like [TxApi::page_bytes]. Returns None if the page is
+  /// not a branch, leaf or freelist page, or if any of its elements are out of bounds.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
   ///   db.update(|mut tx| {
-  ///     let mut b = tx.bucket_mut("test").unwrap();
-  ///     b.put("key", "new value")?;
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     // Enough keys that the bucket isn't inlined into its parent's page
+  ///     for i in 0..100 {
+  ///       b.put(format!("key{:03}", i), "value")?;
+  ///     }
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(Some(b"new value".as_slice()), b.get("key"));
+  ///     match tx.page_elements(b.root())? {
+  ///       Some(PageElements::Leaf(elements)) => {
+  ///         assert_eq!(100, elements.len());
+  ///         assert_eq!(b"key000", elements[0].key);
+  ///         assert_eq!(b"value", elements[0].value);
+  ///       }
+  ///       _ => panic!("expected a leaf page"),
+  ///     }
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<BucketRwImpl<'tx>>;
+  fn page_elements(&self, id: PgId) -> crate::Result<Option<PageElements<'_>>>;
 
-  /// Creates a new bucket.
+  /// Returns information about both meta pages, indexed by meta page id.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.create_bucket_if_not_exists("test")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let metas = tx.meta_info();
+  ///     assert!(metas.iter().all(|m| m.is_valid()));
+  ///     let active = metas.iter().find(|m| m.active).unwrap();
+  ///     assert_eq!(tx.id(), active.txid);
+  ///     Ok(())
+  ///   })?;
   ///
-  /// Returns an error if the bucket already exists, if the bucket name is blank, or if the bucket name is too long.
+  ///   Ok(())
+  /// }
+  /// ```
+  fn meta_info(&self) -> [MetaInfo; 2];
+
+  /// Returns the structure of all buckets in the database, starting from a virtual
+  /// `root` bucket. Fails if a nested bucket can't be opened, e.g. because it's corrupted.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -282,25 +482,31 @@ pub trait TxRwRefApi<'tx>: TxApi<'tx> {
   ///   let mut db = Bolt::open_mem()?;
   ///
   ///   db.update(|mut tx| {
-  ///     let mut b = tx.create_bucket("test")?;
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
   ///     b.put("key", "value")?;
+  ///     b.create_bucket("nested")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
-  ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     let root = tx.inspect()?;
+  ///     assert_eq!(b"root", root.name.as_slice());
+  ///     assert_eq!(b"test", root.children[0].name.as_slice());
+  ///     assert_eq!(1, root.children[0].key_n);
+  ///     assert_eq!(b"nested", root.children[0].children[0].name.as_slice());
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn create_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<BucketRwImpl<'tx>>;
+  fn inspect(&self) -> crate::Result<BucketStructure>;
 
-  /// Creates a new bucket if it doesn't already exist.
+  /// Returns a report on how the pages of the database are used, including per type page
+  /// counts, fill ratios, overflow chains and free page runs.
   ///
-  /// Returns an error if the bucket name is blank, or if the bucket name is too long.
+  /// This walks every page reachable from the root bucket and can be expensive for
+  /// large databases.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -315,20 +521,24 @@ pub trait TxRwRefApi<'tx>: TxApi<'tx> {
   ///   })?;
   ///
   ///   db.view(|tx| {
-  ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     let report = tx.page_usage_report();
+  ///     assert_eq!(2, report.meta_page_n());
+  ///     println!("leaf fill ratio: {}", report.leaf_fill_ratio());
+  ///     println!("largest free run: {}", report.max_free_run());
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
-    &mut self, name: T,
-  ) -> crate::Result<BucketRwImpl<'tx>>;
+  fn page_usage_report(&self) -> PageUsageReport;
 
-  /// DeleteBucket deletes a bucket.
-  /// Returns an error if the bucket cannot be found or if the key represents a non-bucket value.
+  /// Returns a histogram of the value sizes of every bucket and the keys of the `top_n`
+  /// largest values of each, to find the values that take up the space of the database.
+  ///
+  /// Values aren't copied, but this reads every key and value of the database and can be
+  /// expensive for large databases. Returns an error if a page can't be read, e.g.
+  /// [crate::Error::Corrupted].
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -337,52 +547,126 @@ pub trait TxRwRefApi<'tx>: TxApi<'tx> {
   ///   let mut db = Bolt::open_mem()?;
   ///
   ///   db.update(|mut tx| {
-  ///     let mut b = tx.create_bucket("test")?;
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("small", "value")?;
+  ///     b.put("large", [0u8; 10000])?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let report = tx.size_report(1)?;
+  ///     let bucket = &report.buckets[0];
+  ///     assert_eq!(vec![b"test".to_vec()], bucket.path);
+  ///     assert_eq!(vec![(b"large".to_vec(), 10000)], bucket.largest);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn size_report(&self, top_n: usize) -> crate::Result<SizeReport>;
+
+  /// Returns the sorted ids of the pages on the freelist that can be allocated.
+  ///
+  /// Like [TxApi::page], this describes the database's current freelist, which writers
+  /// change after a read-only transaction began. Together with
+  /// [TxApi::pending_pages_by_txid] it lists every page the freelist holds.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
   ///     b.put("key", "value")?;
   ///     Ok(())
   ///   })?;
   ///
+  ///   db.view(|tx| {
+  ///     for id in tx.freelist_pages() {
+  ///       assert_eq!("free", tx.page(id).unwrap().t);
+  ///     }
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn freelist_pages(&self) -> Vec<PgId>;
+
+  /// Returns the sorted ids of the pages freed by each committed transaction that are
+  /// still pending release. The pages of transaction `txid` become allocatable once every
+  /// read-only transaction older than `txid` is closed.
+  ///
+  /// Like [TxApi::page], this describes the database's current freelist.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
   ///   db.update(|mut tx| {
-  ///     tx.delete_bucket("test")?;
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
   ///     Ok(())
   ///   })?;
   ///
   ///   db.view(|tx| {
-  ///     assert_eq!(false, tx.bucket("test").is_some());
+  ///     for (txid, ids) in tx.pending_pages_by_txid() {
+  ///       println!("freed by {}: {:?}", txid, ids);
+  ///     }
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn delete_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<()>;
+  fn pending_pages_by_txid(&self) -> BTreeMap<TxId, Vec<PgId>>;
 
-  /// OnCommit adds a handler function to be executed after the transaction successfully commits.
+  /// Returns an iterator over every bucket and key/value pair in the transaction. Records
+  /// are yielded depth first in key order, so the same data always exports the same way.
+  /// A bucket's record has no value and comes right before its contents.
+  ///
+  /// This is the building block for dump, replication and diff tools.
   ///
   /// ```rust
   /// use bbolt_rs::*;
-  /// use std::cell::RefCell;
   ///
   /// fn main() -> Result<()> {
   ///   let mut db = Bolt::open_mem()?;
   ///
-  ///   let tx_committed = RefCell::new(false);
   ///   db.update(|mut tx| {
   ///     let mut b = tx.create_bucket_if_not_exists("test")?;
-  ///     tx.on_commit(|| *tx_committed.borrow_mut() = true);
+  ///     b.put("key", "value")?;
+  ///     b.create_bucket("nested")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let records = tx.export_iter().collect::<Vec<_>>();
+  ///     assert_eq!(3, records.len());
+  ///     assert_eq!(b"test", records[0].key);
+  ///     assert!(records[0].is_bucket());
+  ///     assert_eq!([b"test".as_slice()], *records[1].path);
+  ///     assert_eq!(Some(b"value".as_slice()), records[1].value);
+  ///     assert_eq!(b"nested", records[2].key);
   ///     Ok(())
   ///   })?;
   ///
-  ///   assert_eq!(true, *tx_committed.borrow());
   ///   Ok(())
   /// }
   /// ```
-  fn on_commit<F: FnMut() + 'tx>(&mut self, f: F);
+  fn export_iter(&self) -> ExportIter<'tx>;
 }
 
-/// RW transaction API + Commit
-pub trait TxRwApi<'tx>: TxRwRefApi<'tx> {
-  /// Closes the transaction and ignores all previous updates.
+/// RW transaction API
+pub trait TxRwRefApi<'tx>: TxApi<'tx> {
+  /// Retrieves a mutable bucket by name.
+  ///
+  /// Returns None if the bucket does not exist.
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -396,24 +680,25 @@ pub trait TxRwApi<'tx>: TxRwRefApi<'tx> {
   ///     Ok(())
   ///   })?;
   ///
-  ///   let mut tx = db.begin_rw()?;
-  ///   let mut b = tx.bucket_mut("test").unwrap();
-  ///   b.put("key", "new value")?;
-  ///   tx.rollback()?;
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     b.put("key", "new value")?;
+  ///     Ok(())
+  ///   })?;
   ///
   ///   db.view(|tx| {
   ///     let b = tx.bucket("test").unwrap();
-  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     assert_eq!(Some(b"new value".as_slice()), b.get("key"));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
-  fn rollback(self) -> crate::Result<()>;
+  fn bucket_mut<T: AsRef<[u8]>>(&mut self, name: T) -> Option<BucketRwImpl<'tx>>;
 
-  /// commit writes all changes to disk and updates the meta page.
-  /// Returns an error if a disk write error occurs
+  /// Retrieves the root data bucket for writing, creating it if it doesn't exist.
+  /// See [TxApi::root_kv]
   ///
   /// ```rust
   /// use bbolt_rs::*;
@@ -421,28 +706,402 @@ pub trait TxRwApi<'tx>: TxRwRefApi<'tx> {
   /// fn main() -> Result<()> {
   ///   let mut db = Bolt::open_mem()?;
   ///
-  ///   let mut tx = db.begin_rw()?;
-  ///   tx.create_bucket_if_not_exists("test1")?;
-  ///   tx.create_bucket_if_not_exists("test2")?;
-  ///   tx.create_bucket_if_not_exists("test3")?;
-  ///   tx.commit()?;
+  ///   db.update(|mut tx| {
+  ///     let mut settings = tx.root_kv_mut()?;
+  ///     settings.put("schema-version", "3")?;
+  ///     settings.put("instance-id", "a1b2")?;
+  ///     Ok(())
+  ///   })?;
   ///
   ///   db.view(|tx| {
-  ///     let mut c = tx.cursor();
-  ///     assert_eq!(Some((b"test1".as_slice(), None)), c.first());
-  ///     assert_eq!(Some((b"test2".as_slice(), None)), c.next());
-  ///     assert_eq!(Some((b"test3".as_slice(), None)), c.next());
+  ///     assert_eq!(Some(b"a1b2".as_slice()), tx.root_get("instance-id"));
   ///     Ok(())
   ///   })?;
   ///
   ///   Ok(())
   /// }
   /// ```
+  fn root_kv_mut(&mut self) -> crate::Result<BucketRwImpl<'tx>>;
 
-  fn commit(self) -> crate::Result<()>;
-}
+  /// Sets the value of a key of the root data bucket, creating the bucket if it doesn't
+  /// exist. See [TxApi::root_kv]
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| tx.root_put("schema-version", "3"))?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert_eq!(Some(b"3".as_slice()), tx.root_get("schema-version"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn root_put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()>;
 
-/// Stats for the transaction
+  /// Removes a key from the root data bucket. Removing a key that doesn't exist does
+  /// nothing. See [TxApi::root_kv]
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| tx.root_put("schema-version", "3"))?;
+  ///   db.update(|mut tx| tx.root_delete("schema-version"))?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert_eq!(None, tx.root_get("schema-version"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn root_delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()>;
+
+  /// Creates a new bucket.
+  ///
+  /// Returns an error if the bucket already exists, if the bucket name is blank, if the bucket name is too long,
+  /// or if it starts with the [RESERVED_PREFIX](crate::RESERVED_PREFIX).
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<BucketRwImpl<'tx>>;
+
+  /// Creates a new bucket if it doesn't already exist.
+  ///
+  /// Returns an error if the bucket name is blank, or if the bucket name is too long.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
+    &mut self, name: T,
+  ) -> crate::Result<BucketRwImpl<'tx>>;
+
+  /// Creates a new bucket whose keys are ordered by the comparator registered as
+  /// `comparator` in [BoltOptions](crate::BoltOptions).
+  ///
+  /// Returns an error if the comparator is not registered or for the same reasons
+  /// as [TxRwRefApi::create_bucket].
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let comparators = Comparators::new().with("ignore-case", |a, b| {
+  ///     a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+  ///   });
+  ///   let mut db = BoltOptions::builder().comparators(comparators).build().open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_with_comparator("test", "ignore-case")?;
+  ///     b.put("Key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("KEY"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_bucket_with_comparator<T: AsRef<[u8]>>(
+    &mut self, name: T, comparator: &str,
+  ) -> crate::Result<BucketRwImpl<'tx>>;
+
+  /// Creates the top-level buckets `names` and returns them in the same order.
+  ///
+  /// Either all buckets are created or, if one can't be for the same reasons as
+  /// [TxRwRefApi::create_bucket], none are.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut buckets = tx.create_buckets(&["users", "posts"])?;
+  ///     buckets[1].put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     assert_eq!(Some(Error::BucketExists), tx.create_buckets(&["tags", "posts"]).err());
+  ///     assert!(tx.bucket("tags").is_none());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn create_buckets<T: AsRef<[u8]>>(
+    &mut self, names: &[T],
+  ) -> crate::Result<Vec<BucketRwImpl<'tx>>>;
+
+  /// Provisions the buckets described by `specs`, e.g. on application startup, and returns
+  /// them in the same order.
+  ///
+  /// Missing buckets are created with the sequence and comparator of their spec, and missing
+  /// parents without a spec are created plain. Existing buckets are kept as they are. Either
+  /// all buckets are provisioned or none are. Returns [Error::IncompatibleValue] if a bucket
+  /// exists with another comparator than its spec's or a key is in the way.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///   let schema = [
+  ///     BucketSpec::new(&["users"]).sequence(1000),
+  ///     BucketSpec::new(&["users", "by-email"]),
+  ///   ];
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut buckets = tx.ensure_schema(&schema)?;
+  ///     assert_eq!(1001, buckets[0].next_sequence()?);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   // Provisioning again keeps the buckets
+  ///   db.update(|mut tx| {
+  ///     let buckets = tx.ensure_schema(&schema)?;
+  ///     assert_eq!(1001, buckets[0].sequence());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn ensure_schema(&mut self, specs: &[BucketSpec]) -> crate::Result<Vec<BucketRwImpl<'tx>>>;
+
+  /// DeleteBucket deletes a bucket.
+  /// Returns an error if the bucket cannot be found or if the key represents a non-bucket value.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     tx.delete_bucket("test")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert_eq!(false, tx.bucket("test").is_some());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn delete_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<()>;
+
+  /// OnCommit adds a handler function to be executed after the transaction successfully commits.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  /// use std::cell::RefCell;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   let tx_committed = RefCell::new(false);
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     tx.on_commit(|| *tx_committed.borrow_mut() = true);
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   assert_eq!(true, *tx_committed.borrow());
+  ///   Ok(())
+  /// }
+  /// ```
+  fn on_commit<F: FnMut() + 'tx>(&mut self, f: F);
+
+  /// Marks the current state of the transaction. Rolling back the [Savepoint] reverts the
+  /// changes made after it while keeping the ones made before it.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///
+  ///     let savepoint = tx.savepoint();
+  ///     let mut b = tx.bucket_mut("test").unwrap();
+  ///     b.delete("key")?;
+  ///     savepoint.rollback()?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn savepoint(&mut self) -> Savepoint<'tx>;
+}
+
+/// RW transaction API + Commit
+pub trait TxRwApi<'tx>: TxRwRefApi<'tx> {
+  /// Closes the transaction and ignores all previous updates.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   db.update(|mut tx| {
+  ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+  ///     b.put("key", "value")?;
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   let mut tx = db.begin_rw()?;
+  ///   let mut b = tx.bucket_mut("test").unwrap();
+  ///   b.put("key", "new value")?;
+  ///   tx.rollback()?;
+  ///
+  ///   db.view(|tx| {
+  ///     let b = tx.bucket("test").unwrap();
+  ///     assert_eq!(Some(b"value".as_slice()), b.get("key"));
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn rollback(self) -> crate::Result<()>;
+
+  /// commit writes all changes to disk and updates the meta page.
+  /// Returns an error if a disk write error occurs
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   let mut tx = db.begin_rw()?;
+  ///   tx.create_bucket_if_not_exists("test1")?;
+  ///   tx.create_bucket_if_not_exists("test2")?;
+  ///   tx.create_bucket_if_not_exists("test3")?;
+  ///   tx.commit()?;
+  ///
+  ///   db.view(|tx| {
+  ///     let mut c = tx.cursor();
+  ///     assert_eq!(Some((b"test1".as_slice(), None)), c.first());
+  ///     assert_eq!(Some((b"test2".as_slice(), None)), c.next());
+  ///     assert_eq!(Some((b"test3".as_slice(), None)), c.next());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+
+  fn commit(self) -> crate::Result<()>;
+
+  /// prepare runs the first phase of a two-phase commit. It writes and syncs all changes
+  /// except the meta page, so a commit can be coordinated with an external system like an
+  /// outbox or a write-ahead log. Finish with [TxPrepared::confirm], which only writes the
+  /// meta page, or discard the changes with [TxPrepared::abort].
+  ///
+  /// Returns an error if a disk write error occurs. The transaction is rolled back then.
+  ///
+  /// ```rust
+  /// use bbolt_rs::*;
+  ///
+  /// fn main() -> Result<()> {
+  ///   let mut db = Bolt::open_mem()?;
+  ///
+  ///   let mut tx = db.begin_rw()?;
+  ///   tx.create_bucket_if_not_exists("test")?;
+  ///   let prepared = tx.prepare()?;
+  ///   // Record the commit in the external system here
+  ///   prepared.confirm()?;
+  ///
+  ///   db.view(|tx| {
+  ///     assert!(tx.bucket("test").is_some());
+  ///     Ok(())
+  ///   })?;
+  ///
+  ///   Ok(())
+  /// }
+  /// ```
+  fn prepare(self) -> crate::Result<TxPrepared<'tx>>;
+}
+
+/// Stats for the transaction
+///
+/// The counters are relaxed atomics. Updating them on hot paths like cursor seeks only
+/// needs a shared borrow of the transaction and doesn't synchronize with other threads.
 #[derive(Default)]
 pub struct TxStats {
   // Page statistics.
@@ -452,10 +1111,26 @@ pub struct TxStats {
   /// total bytes allocated
   page_alloc: AtomicI64,
 
+  // Allocation statistics.
+  //
+  /// number of pages reused from the freelist
+  reused_page_n: AtomicI64,
+  /// number of pages allocated at the high water mark
+  grown_page_n: AtomicI64,
+  /// largest contiguous allocation requested, in pages
+  max_alloc_request: AtomicI64,
+  /// number of contiguous allocations that grew the database even though the
+  /// freelist held enough pages in total
+  alloc_fallback_n: AtomicI64,
+
   // Cursor statistics.
   //
   /// number of cursors created
   cursor_count: AtomicI64,
+  /// number of key searches
+  seek_n: AtomicI64,
+  /// number of pages and nodes visited by key searches
+  seek_page_n: AtomicI64,
 
   // Node statistics
   //
@@ -486,61 +1161,137 @@ pub struct TxStats {
   write: AtomicI64,
   /// total time spent writing to disk
   write_time: Mutex<Duration>,
+
+  // Bucket statistics.
+  //
+  /// operation counters of the top-level buckets
+  bucket_metrics: Mutex<std::collections::HashMap<Vec<u8>, BucketMetrics>>,
 }
 
 impl TxStats {
   /// total bytes allocated
   pub fn page_alloc(&self) -> i64 {
-    self.page_alloc.load(Ordering::Acquire)
+    self.page_alloc.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_page_alloc(&self, delta: i64) {
-    self.page_alloc.fetch_add(delta, Ordering::AcqRel);
+    self.page_alloc.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of page allocations
   pub fn page_count(&self) -> i64 {
-    self.page_count.load(Ordering::Acquire)
+    self.page_count.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_page_count(&self, delta: i64) {
-    self.page_count.fetch_add(delta, Ordering::AcqRel);
+    self.page_count.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// number of pages reused from the freelist
+  pub fn reused_page_n(&self) -> i64 {
+    self.reused_page_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_reused_page_n(&self, delta: i64) {
+    self.reused_page_n.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// number of pages allocated at the high water mark
+  pub fn grown_page_n(&self) -> i64 {
+    self.grown_page_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_grown_page_n(&self, delta: i64) {
+    self.grown_page_n.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// largest contiguous allocation requested, in pages
+  pub fn max_alloc_request(&self) -> i64 {
+    self.max_alloc_request.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn max_assign_alloc_request(&self, value: i64) {
+    self.max_alloc_request.fetch_max(value, Ordering::Relaxed);
+  }
+
+  /// number of contiguous allocations that grew the database even though the
+  /// freelist held enough pages in total. A growing count means free space is
+  /// too fragmented to be reused.
+  pub fn alloc_fallback_n(&self) -> i64 {
+    self.alloc_fallback_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_alloc_fallback_n(&self, delta: i64) {
+    self.alloc_fallback_n.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of cursors created
   pub fn cursor_count(&self) -> i64 {
-    self.cursor_count.load(Ordering::Acquire)
+    self.cursor_count.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_cursor_count(&self, delta: i64) {
-    self.cursor_count.fetch_add(delta, Ordering::AcqRel);
+    self.cursor_count.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// number of key searches, including the ones made by get, put and delete
+  pub fn seek_n(&self) -> i64 {
+    self.seek_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_seek_n(&self, delta: i64) {
+    self.seek_n.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// number of pages and nodes visited by key searches
+  pub fn seek_page_n(&self) -> i64 {
+    self.seek_page_n.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn inc_seek_page_n(&self, delta: i64) {
+    self.seek_page_n.fetch_add(delta, Ordering::Relaxed);
+  }
+
+  /// average number of pages and nodes visited per key search. Returns 0 if there
+  /// were no searches.
+  pub fn pages_per_seek(&self) -> f64 {
+    match self.seek_n() {
+      0 => 0.0,
+      seek_n => self.seek_page_n() as f64 / seek_n as f64,
+    }
+  }
+
+  /// average number of branch pages and nodes visited per key search. Every search ends
+  /// on a single leaf so this is one less than [TxStats::pages_per_seek].
+  pub fn avg_branch_depth(&self) -> f64 {
+    (self.pages_per_seek() - 1.0).max(0.0)
   }
 
   /// number of node allocations
   pub fn node_count(&self) -> i64 {
-    self.node_count.load(Ordering::Acquire)
+    self.node_count.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_node_count(&self, delta: i64) {
-    self.node_count.fetch_add(delta, Ordering::AcqRel);
+    self.node_count.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of node dereferences
   pub fn node_deref(&self) -> i64 {
-    self.node_deref.load(Ordering::Acquire)
+    self.node_deref.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_node_deref(&self, delta: i64) {
-    self.node_deref.fetch_add(delta, Ordering::AcqRel);
+    self.node_deref.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of node rebalances
   pub fn rebalance(&self) -> i64 {
-    self.rebalance.load(Ordering::Acquire)
+    self.rebalance.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_rebalance(&self, delta: i64) {
-    self.rebalance.fetch_add(delta, Ordering::AcqRel);
+    self.rebalance.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// total time spent rebalancing
@@ -554,20 +1305,20 @@ impl TxStats {
 
   /// number of nodes split
   pub fn split(&self) -> i64 {
-    self.split.load(Ordering::Acquire)
+    self.split.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_split(&self, delta: i64) {
-    self.split.fetch_add(delta, Ordering::AcqRel);
+    self.split.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// number of nodes spilled
   pub fn spill(&self) -> i64 {
-    self.spill.load(Ordering::Acquire)
+    self.spill.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_spill(&self, delta: i64) {
-    self.spill.fetch_add(delta, Ordering::AcqRel);
+    self.spill.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// total time spent spilling
@@ -581,11 +1332,11 @@ impl TxStats {
 
   /// number of writes performed
   pub fn write(&self) -> i64 {
-    self.write.load(Ordering::Acquire)
+    self.write.load(Ordering::Relaxed)
   }
 
   pub(crate) fn inc_write(&self, delta: i64) {
-    self.write.fetch_add(delta, Ordering::AcqRel);
+    self.write.fetch_add(delta, Ordering::Relaxed);
   }
 
   /// total time spent writing to disk
@@ -597,10 +1348,37 @@ impl TxStats {
     *self.write_time.lock() += delta;
   }
 
+  /// operation counters of every top-level bucket, keyed by bucket name.
+  /// Operations on nested buckets are counted against their top-level bucket.
+  pub fn bucket_metrics(&self) -> std::collections::HashMap<Vec<u8>, BucketMetrics> {
+    self.bucket_metrics.lock().clone()
+  }
+
+  /// Applies `f` to the counters of every bucket in `metrics` under a single lock
+  pub(crate) fn merge_bucket_metrics<'a, I, F>(&self, metrics: I, f: F)
+  where
+    I: IntoIterator<Item = (&'a [u8], BucketMetrics)>,
+    F: Fn(&mut BucketMetrics, BucketMetrics),
+  {
+    let mut bucket_metrics = self.bucket_metrics.lock();
+    for (name, delta) in metrics {
+      match bucket_metrics.get_mut(name) {
+        Some(m) => f(m, delta),
+        None => f(bucket_metrics.entry(name.to_vec()).or_default(), delta),
+      }
+    }
+  }
+
   pub(crate) fn add_assign(&self, rhs: &TxStats) {
     self.inc_page_count(rhs.page_count());
     self.inc_page_alloc(rhs.page_alloc());
+    self.inc_reused_page_n(rhs.reused_page_n());
+    self.inc_grown_page_n(rhs.grown_page_n());
+    self.max_assign_alloc_request(rhs.max_alloc_request());
+    self.inc_alloc_fallback_n(rhs.alloc_fallback_n());
     self.inc_cursor_count(rhs.cursor_count());
+    self.inc_seek_n(rhs.seek_n());
+    self.inc_seek_page_n(rhs.seek_page_n());
     self.inc_node_count(rhs.node_count());
     self.inc_node_deref(rhs.node_deref());
     self.inc_rebalance(rhs.rebalance());
@@ -610,6 +1388,11 @@ impl TxStats {
     self.inc_spill_time(rhs.spill_time());
     self.inc_write(rhs.write());
     self.inc_write_time(rhs.write_time());
+    let rhs_metrics = rhs.bucket_metrics();
+    self.merge_bucket_metrics(
+      rhs_metrics.iter().map(|(name, m)| (name.as_slice(), *m)),
+      |m, delta| *m += delta,
+    );
   }
 
   pub(crate) fn add(&self, rhs: &TxStats) -> TxStats {
@@ -621,7 +1404,13 @@ impl TxStats {
   pub(crate) fn sub_assign(&self, rhs: &TxStats) {
     self.inc_page_count(-rhs.page_count());
     self.inc_page_alloc(-rhs.page_alloc());
+    self.inc_reused_page_n(-rhs.reused_page_n());
+    self.inc_grown_page_n(-rhs.grown_page_n());
+    // max_alloc_request is a high water mark and can't be subtracted
+    self.inc_alloc_fallback_n(-rhs.alloc_fallback_n());
     self.inc_cursor_count(-rhs.cursor_count());
+    self.inc_seek_n(-rhs.seek_n());
+    self.inc_seek_page_n(-rhs.seek_page_n());
     self.inc_node_count(-rhs.node_count());
     self.inc_node_deref(-rhs.node_deref());
     self.inc_rebalance(-rhs.rebalance());
@@ -631,6 +1420,11 @@ impl TxStats {
     self.spill_time.lock().sub_assign(rhs.spill_time());
     self.inc_write(-rhs.write());
     self.write_time.lock().sub_assign(rhs.write_time());
+    let rhs_metrics = rhs.bucket_metrics();
+    self.merge_bucket_metrics(
+      rhs_metrics.iter().map(|(name, m)| (name.as_slice(), *m)),
+      |m, delta| *m -= delta,
+    );
   }
 
   pub(crate) fn sub(&self, rhs: &TxStats) -> TxStats {
@@ -645,7 +1439,13 @@ impl Clone for TxStats {
     TxStats {
       page_count: self.page_count().into(),
       page_alloc: self.page_alloc().into(),
+      reused_page_n: self.reused_page_n().into(),
+      grown_page_n: self.grown_page_n().into(),
+      max_alloc_request: self.max_alloc_request().into(),
+      alloc_fallback_n: self.alloc_fallback_n().into(),
       cursor_count: self.cursor_count().into(),
+      seek_n: self.seek_n().into(),
+      seek_page_n: self.seek_page_n().into(),
       node_count: self.node_count().into(),
       node_deref: self.node_deref().into(),
       rebalance: self.rebalance().into(),
@@ -655,6 +1455,7 @@ impl Clone for TxStats {
       spill_time: self.spill_time().into(),
       write: self.write().into(),
       write_time: self.write_time().into(),
+      bucket_metrics: self.bucket_metrics().into(),
     }
   }
 }
@@ -663,7 +1464,13 @@ impl PartialEq for TxStats {
   fn eq(&self, other: &Self) -> bool {
     self.page_count() == other.page_count()
       && self.page_alloc() == other.page_alloc()
+      && self.reused_page_n() == other.reused_page_n()
+      && self.grown_page_n() == other.grown_page_n()
+      && self.max_alloc_request() == other.max_alloc_request()
+      && self.alloc_fallback_n() == other.alloc_fallback_n()
       && self.cursor_count() == other.cursor_count()
+      && self.seek_n() == other.seek_n()
+      && self.seek_page_n() == other.seek_page_n()
       && self.node_count() == other.node_count()
       && self.node_deref() == other.node_deref()
       && self.rebalance() == other.rebalance()
@@ -673,6 +1480,7 @@ impl PartialEq for TxStats {
       && self.spill_time() == other.spill_time()
       && self.write() == other.write()
       && self.write_time() == other.write_time()
+      && self.bucket_metrics() == other.bucket_metrics()
   }
 }
 
@@ -683,7 +1491,13 @@ impl Debug for TxStats {
     f.debug_struct("TxStats")
       .field("page_count", &self.page_count())
       .field("page_alloc", &self.page_alloc())
+      .field("reused_page_n", &self.reused_page_n())
+      .field("grown_page_n", &self.grown_page_n())
+      .field("max_alloc_request", &self.max_alloc_request())
+      .field("alloc_fallback_n", &self.alloc_fallback_n())
       .field("cursor_count", &self.cursor_count())
+      .field("seek_n", &self.seek_n())
+      .field("seek_page_n", &self.seek_page_n())
       .field("node_count", &self.node_count())
       .field("node_deref", &self.node_deref())
       .field("rebalance", &self.rebalance())
@@ -693,10 +1507,163 @@ impl Debug for TxStats {
       .field("spill_time", &self.spill_time())
       .field("write", &self.write())
       .field("write_time", &self.write_time())
+      .field("bucket_metrics", &self.bucket_metrics())
       .finish()
   }
 }
 
+/// PageUsageReport summarizes how the pages of a database are used.
+///
+/// All figures describe the committed state visible to the transaction.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct PageUsageReport {
+  /// size of a page in bytes
+  page_size: u64,
+  /// total number of pages up to the high water mark
+  high_water_mark: u64,
+
+  // Page type statistics.
+  /// number of meta pages
+  meta_page_n: u64,
+  /// number of physical freelist pages
+  freelist_page_n: u64,
+  /// number of logical branch pages
+  branch_page_n: u64,
+  /// number of logical leaf pages
+  leaf_page_n: u64,
+  /// number of free and pending pages
+  free_page_n: u64,
+
+  // Overflow statistics.
+  /// number of branch and leaf pages with overflow pages
+  overflow_chain_n: u64,
+  /// number of physical overflow pages
+  overflow_page_n: u64,
+  /// length of the longest overflow chain, including the first page
+  max_overflow_chain: u64,
+
+  // Page size utilization.
+  /// bytes allocated for physical branch pages
+  branch_alloc: u64,
+  /// bytes actually used for branch data
+  branch_in_use: u64,
+  /// bytes allocated for physical leaf pages
+  leaf_alloc: u64,
+  /// bytes actually used for leaf data
+  leaf_in_use: u64,
+
+  // Free space statistics.
+  /// number of contiguous runs of free pages
+  free_run_n: u64,
+  /// length of the largest contiguous run of free pages
+  max_free_run: u64,
+}
+
+impl PageUsageReport {
+  /// The average fill ratio of all branch pages, between 0.0 and 1.0.
+  pub fn branch_fill_ratio(&self) -> f64 {
+    if self.branch_alloc == 0 {
+      return 0.0;
+    }
+    self.branch_in_use as f64 / self.branch_alloc as f64
+  }
+
+  /// The average fill ratio of all leaf pages, between 0.0 and 1.0.
+  pub fn leaf_fill_ratio(&self) -> f64 {
+    if self.leaf_alloc == 0 {
+      return 0.0;
+    }
+    self.leaf_in_use as f64 / self.leaf_alloc as f64
+  }
+
+  /// The fraction of pages below the high water mark that are free, between 0.0 and 1.0.
+  pub fn free_ratio(&self) -> f64 {
+    if self.high_water_mark == 0 {
+      return 0.0;
+    }
+    self.free_page_n as f64 / self.high_water_mark as f64
+  }
+
+  fn add_tree_page(&mut self, p: &RefPage) {
+    let overflow = p.overflow as u64;
+    if overflow > 0 {
+      self.overflow_chain_n += 1;
+      self.overflow_page_n += overflow;
+      self.max_overflow_chain = self.max_overflow_chain.max(overflow + 1);
+    }
+    let alloc = (overflow + 1) * self.page_size;
+    if let Some(leaf_page) = MappedLeafPage::coerce_ref(p) {
+      self.leaf_page_n += 1;
+      self.leaf_alloc += alloc;
+      let mut used = PAGE_HEADER_SIZE;
+      if let Some(last_element) = leaf_page.elements().last() {
+        used += LEAF_PAGE_ELEMENT_SIZE * (p.count - 1) as usize
+          + last_element.pos() as usize
+          + last_element.key_size() as usize
+          + last_element.value_size() as usize;
+      }
+      self.leaf_in_use += used as u64;
+    } else if let Some(branch_page) = MappedBranchPage::coerce_ref(p) {
+      self.branch_page_n += 1;
+      self.branch_alloc += alloc;
+      let mut used = PAGE_HEADER_SIZE;
+      if let Some(last_element) = branch_page.elements().last() {
+        used += BRANCH_PAGE_ELEMENT_SIZE * (p.count - 1) as usize
+          + last_element.pos() as usize
+          + last_element.key_size() as usize;
+      }
+      self.branch_in_use += used as u64;
+    }
+  }
+
+  fn add_free_pages(&mut self, ids: &[PgId]) {
+    self.free_page_n = ids.len() as u64;
+    let mut run = 0;
+    for (i, id) in ids.iter().enumerate() {
+      if i > 0 && ids[i - 1] + 1 == *id {
+        run += 1;
+      } else {
+        self.free_run_n += 1;
+        run = 1;
+      }
+      self.max_free_run = self.max_free_run.max(run);
+    }
+  }
+}
+
+/// Marks the page `id` and its `overflow` pages in use. See [TxIApi::api_page_usage_report]
+fn mark_used(used: &mut [bool], id: PgId, overflow: u64) {
+  let start = (id.0 as usize).min(used.len());
+  let end = ((id.0 + overflow + 1) as usize).min(used.len());
+  used[start..end].fill(true);
+}
+
+/// Returns page `id` of the transaction's snapshot.
+///
+/// Returns [Error::TxEvicted] if the transaction was evicted, and [Error::Corrupted] if
+/// `id` isn't below the high water mark or lies past the mapping. Checksums are verified
+/// by [TxIApi::check_tree_page].
+#[inline]
+fn read_page<'tx>(r: &TxR<'tx>, id: PgId) -> crate::Result<RefPage<'tx>> {
+  check_evicted(r)?;
+  let high_water_mark = r.meta.pgid();
+  if id >= high_water_mark {
+    return Err(Error::Corrupted {
+      pgid: id,
+      reason: Cow::Owned(format!("beyond the high water mark {}", high_water_mark)),
+    });
+  }
+  r.db.try_page(id)
+}
+
+fn check_evicted(r: &TxR) -> crate::Result<()> {
+  match &r.evicted {
+    Some(evicted) if evicted.load(Ordering::Acquire) => Err(Error::TxEvicted),
+    _ => Ok(()),
+  }
+}
+
 pub(crate) enum AnyPage<'a, 'tx: 'a> {
   Ref(RefPage<'tx>),
   Pending(RefPage<'a>),
@@ -714,12 +1681,28 @@ impl<'a, 'tx: 'a> Deref for AnyPage<'tx, 'a> {
   }
 }
 
+/// `TxDropPolicy` selects what happens when a read-write transaction is dropped without
+/// calling [TxRwApi::commit] or [TxRwApi::rollback]. The transaction is rolled back in
+/// every case.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum TxDropPolicy {
+  /// Roll back without notice
+  #[default]
+  RollbackSilently,
+  /// Log a warning with the `log` feature enabled
+  LogWarning,
+  /// Panic in debug builds. Release builds roll back silently.
+  PanicInDebug,
+}
+
 #[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq)]
 pub(crate) enum TxClosingState {
   #[default]
   Rollback,
   ExplicitRollback,
   PhysicalRollback,
+  /// Prepared but neither confirmed nor aborted
+  Prepared,
   Commit,
 }
 
@@ -731,13 +1714,9 @@ impl TxClosingState {
       TxClosingState::Rollback
         | TxClosingState::ExplicitRollback
         | TxClosingState::PhysicalRollback
+        | TxClosingState::Prepared
     )
   }
-
-  #[inline]
-  pub(crate) fn is_physical_rollback(&self) -> bool {
-    matches!(self, TxClosingState::PhysicalRollback)
-  }
 }
 
 pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
@@ -760,8 +1739,83 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
     Ref::map(self.split_r(), |tx| &tx.meta)
   }
 
+  /// The number of modifications made to the transaction's buckets
+  fn mutations(self) -> u64 {
+    self.split_ow().as_ref().map(|w| w.mutations).unwrap_or(0)
+  }
+
+  /// Returns page `id` of the transaction's snapshot. Panics if it can't be read.
+  /// See [TxIApi::try_mem_page]
   fn mem_page(self, id: PgId) -> RefPage<'tx> {
-    self.split_r().db.page(id)
+    match self.try_mem_page(id) {
+      Ok(page) => page,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// Returns page `id` of the transaction's snapshot. See [read_page] for the errors
+  fn try_mem_page(self, id: PgId) -> crate::Result<RefPage<'tx>> {
+    read_page(&self.split_r(), id)
+  }
+
+  /// Returns [Error::ChecksumMismatch] if the branch or leaf page `page` fails its checksum
+  /// and [Error::Corrupted] if its elements, or the keys and values they point to, don't fit
+  /// in the page. Pages of the snapshot are only checked the first time the transaction
+  /// reads them.
+  fn check_tree_page(self, page: &RefPage<'tx>) -> crate::Result<()> {
+    let r = self.split_r();
+    // Inline pages don't carry their own page id
+    let id = page.id;
+    if id != ZERO_PGID && r.checked_pages.borrow().contains(&id) {
+      return Ok(());
+    }
+    if r.verify_checksums {
+      page.verify_checksum(r.page_size)?;
+    }
+    if let Some(leaf_page) = MappedLeafPage::coerce_ref(page) {
+      leaf_page.check_elements(r.page_size)?;
+    } else if let Some(branch_page) = MappedBranchPage::coerce_ref(page) {
+      branch_page.check_elements(r.page_size)?;
+    }
+    // Pages read while checksums are off are verified again later
+    if id != ZERO_PGID && r.verify_checksums {
+      r.checked_pages.borrow_mut().insert(id);
+    }
+    Ok(())
+  }
+
+  /// Runs `f` without verifying the checksums of the pages it reads, so damaged pages are
+  /// reported instead of panicking on reading them. Restores the previous setting after.
+  fn without_checksums<R>(self, f: impl FnOnce() -> R) -> R {
+    let verify = std::mem::replace(&mut self.split_r_mut().verify_checksums, false);
+    let result = f();
+    self.split_r_mut().verify_checksums = verify;
+    result
+  }
+
+  /// Returns [Error::TxEvicted] if the read transaction was evicted
+  fn check_evicted(self) -> crate::Result<()> {
+    check_evicted(&self.split_r())
+  }
+
+  /// The key comparators registered with the database
+  fn comparators(self) -> Comparators {
+    self.split_r().db.comparators()
+  }
+
+  /// Whether written branch and leaf pages end in a checksum
+  fn page_checksums(self) -> bool {
+    self.split_r().db.page_checksums()
+  }
+
+  /// The maximum length of a key, in bytes
+  fn max_key_size(self) -> usize {
+    self.split_r().db.max_key_size()
+  }
+
+  /// The maximum length of a value, in bytes
+  fn max_value_size(self) -> usize {
+    self.split_r().db.max_value_size()
   }
 
   fn any_page<'a>(&'a self, id: PgId) -> AnyPage<'a, 'tx> {
@@ -771,7 +1825,10 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
         return AnyPage::Pending(*page);
       }
     }
-    let page = self.split_r().db.page(id);
+    let page = match read_page(&self.split_r(), id) {
+      Ok(page) => page,
+      Err(e) => panic!("{}", e),
+    };
     page.fast_check(id);
     AnyPage::Ref(page)
   }
@@ -792,12 +1849,14 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
   /// See [TxApi::cursor]
   fn api_cursor(self) -> InnerCursor<'tx, Self, Self::BucketType> {
     let root_bucket = self.root_bucket();
-    root_bucket.i_cursor()
+    root_bucket.i_cursor().hiding_reserved()
   }
 
   /// See [TxApi::stats]
   fn api_stats(self) -> Arc<TxStats> {
-    self.split_r().stats.as_ref().unwrap().clone()
+    let r = self.split_r();
+    r.merge_bucket_metrics();
+    r.stats.as_ref().unwrap().clone()
   }
 
   #[inline]
@@ -807,22 +1866,58 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
 
   /// See [TxApi::bucket]
   fn api_bucket(self, name: &[u8]) -> Option<Self::BucketType> {
+    if is_reserved(name) {
+      return None;
+    }
     let root_bucket = self.root_bucket();
     root_bucket.api_bucket(name)
   }
 
+  /// See [TxApi::try_bucket]
+  fn api_try_bucket(self, name: &[u8]) -> crate::Result<Option<Self::BucketType>> {
+    if is_reserved(name) {
+      return Ok(None);
+    }
+    let root_bucket = self.root_bucket();
+    root_bucket.api_try_bucket(name)
+  }
+
+  /// Retrieves the sub-bucket `name` of the hidden [SYSTEM_BUCKET]
+  fn system_bucket(self, name: &[u8]) -> Option<Self::BucketType> {
+    self
+      .root_bucket()
+      .api_bucket(SYSTEM_BUCKET)?
+      .api_bucket(name)
+  }
+
+  /// See [TxApi::root_get]
+  fn api_root_get(self, key: &[u8]) -> Option<&'tx [u8]> {
+    self.system_bucket(ROOT_KV_BUCKET)?.api_get(key)
+  }
+
   /// See [TxApi::for_each]
   fn api_for_each<F: FnMut(&[u8], BucketImpl<'tx>) -> crate::Result<()>>(
     &self, mut f: F,
   ) -> crate::Result<()> {
     let root_bucket = self.root_bucket();
     root_bucket.api_for_each_bucket(|k| {
-      let bucket = root_bucket.api_bucket(k).unwrap();
+      if is_reserved(k) {
+        return Ok(());
+      }
+      let bucket = root_bucket.api_try_bucket(k)?.unwrap();
       f(k, bucket.into_impl())?;
       Ok(())
     })
   }
 
+  /// See [TxApi::walk]
+  fn api_walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, mut f: F,
+  ) -> crate::Result<()> {
+    let mut path = Vec::new();
+    self.root_bucket().recursively_walk(&mut path, &mut f)
+  }
+
   /// forEachPage iterates over every page within a given page and executes a function.
   fn for_each_page<F: FnMut(&RefPage<'tx>, usize, &mut BVec<PgId>)>(self, pg_id: PgId, f: &mut F) {
     let mut stack = BVec::with_capacity_in(10, self.bump());
@@ -855,6 +1950,118 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
     Ok(())
   }
 
+  /// See [TxApi::inspect]
+  fn api_inspect(self) -> crate::Result<BucketStructure> {
+    self.root_bucket().recursively_inspect(b"root")
+  }
+
+  /// See [TxApi::page_usage_report]
+  fn api_page_usage_report(self) -> PageUsageReport {
+    let db = self.split_r().db;
+    let mut report = PageUsageReport {
+      page_size: self.page_size() as u64,
+      high_water_mark: self.meta().pgid().0,
+      meta_page_n: 2,
+      ..Default::default()
+    };
+    // Pages of this snapshot that are in use, indexed by page id
+    let mut used = BVec::with_capacity_in(report.high_water_mark as usize, self.bump());
+    used.resize(report.high_water_mark as usize, false);
+    mark_used(&mut used, PgId(0), 1);
+    let freelist_pgid = self.meta().free_list();
+    if freelist_pgid != PGID_NO_FREE_LIST {
+      let overflow = self.mem_page(freelist_pgid).overflow as u64;
+      report.freelist_page_n = overflow + 1;
+      mark_used(&mut used, freelist_pgid, overflow);
+    }
+
+    self.page_usage_bucket(self.root_bucket(), &mut report, &mut used);
+
+    if self.split_ow().is_some() {
+      // The writer owns the freelist, so it matches this transaction
+      let mut free_ids = BVec::with_capacity_in(db.freelist_count() as usize, self.bump());
+      free_ids.resize(db.freelist_count() as usize, ZERO_PGID);
+      db.freelist_copyall(&mut free_ids);
+      report.add_free_pages(&free_ids);
+    } else {
+      // Later commits change the freelist, so read transactions count the pages their
+      // snapshot doesn't reach instead
+      let mut free_ids = BVec::new_in(self.bump());
+      free_ids.extend(
+        used
+          .iter()
+          .enumerate()
+          .filter(|(_, used)| !**used)
+          .map(|(id, _)| PgId(id as u64)),
+      );
+      report.add_free_pages(&free_ids);
+    }
+    report
+  }
+
+  /// See [TxApi::size_report]
+  fn api_size_report(self, top_n: usize) -> crate::Result<SizeReport> {
+    size_report::<Self>(self.root_bucket(), top_n)
+  }
+
+  fn page_usage_bucket(
+    self, bucket: Self::BucketType, report: &mut PageUsageReport, used: &mut BVec<bool>,
+  ) {
+    // inline buckets live inside their parent's leaf pages
+    if bucket.root() == ZERO_PGID {
+      return;
+    }
+    self.for_each_page(bucket.root(), &mut |p, _, _| {
+      report.add_tree_page(p);
+      mark_used(used, p.id, p.overflow as u64);
+    });
+    bucket
+      .api_for_each_bucket(|key| {
+        let child = bucket.api_bucket(key).unwrap();
+        self.page_usage_bucket(child, report, used);
+        Ok(())
+      })
+      .unwrap();
+  }
+
+  /// See [TxApi::page_bytes]
+  fn api_page_bytes(&self, id: PgId) -> crate::Result<&'tx [u8]> {
+    let r = self.split_r();
+    let high_water = r.committed_pgid;
+    if id >= high_water {
+      return Err(Error::PageOutOfRange {
+        pgid: id,
+        high_water,
+      });
+    }
+    let p = r.db.page(id);
+    // Never read past the high water mark, even if the overflow count says otherwise.
+    let page_n = (p.overflow as u64 + 1).min(high_water.0 - id.0);
+    let len = page_n as usize * r.page_size;
+    let ptr = (p.deref() as *const PageHeader).cast::<u8>();
+    Ok(unsafe { from_raw_parts(ptr, len) })
+  }
+
+  /// See [TxApi::meta_info]
+  fn api_meta_info(self) -> [MetaInfo; 2] {
+    let mut infos = [PgId(0), PgId(1)].map(|id| {
+      let page = self.mem_page(id);
+      let meta_page = unsafe { MappedMetaPage::unchecked_ref(&page) };
+      MetaInfo::new(id, &meta_page.meta)
+    });
+    // The database uses the valid meta page with the highest transaction id.
+    let active = match (infos[0].is_valid(), infos[1].is_valid()) {
+      (true, true) if infos[1].txid > infos[0].txid => Some(1),
+      (true, _) => Some(0),
+      (false, true) => Some(1),
+      (false, false) => None,
+    };
+    if let Some(active) = active {
+      infos[active].active = true;
+    }
+    infos
+  }
+
   /// See [TxApi::page]
   fn api_page(&self, id: PgId) -> Option<PageInfo> {
     let r = self.split_r();
@@ -885,10 +2092,21 @@ pub(crate) trait TxIApi<'tx>: SplitRef<TxR<'tx>, Self::BucketType, TxW<'tx>> {
 }
 
 pub(crate) trait TxRwIApi<'tx>: TxIApi<'tx> + TxICheck<'tx> {
-  fn freelist_free_page(self, txid: TxId, p: &PageHeader);
+  /// Frees a page. A failure is kept and returned when the transaction commits
+  fn freelist_free_page(self, txid: TxId, p: &PageHeader, context: &'static str);
+
+  /// check_deadline returns [Error::TxTimeout] if the transaction's deadline has passed
+  fn check_deadline(self) -> crate::Result<()>;
+
+  /// Records a modification of one of the transaction's buckets
+  fn record_mutation(self);
 
   fn root_bucket_mut(self) -> BucketCell<'tx>;
 
+  /// Retrieves the sub-bucket `name` of the hidden [SYSTEM_BUCKET], creating both if
+  /// they don't exist
+  fn system_bucket_mut(self, name: &[u8]) -> crate::Result<Self::BucketType>;
+
   fn allocate(
     self, count: usize,
   ) -> crate::Result<SelfOwned<AlignedBytes<alignment::Page>, MutPage<'tx>>>;
@@ -901,6 +2119,21 @@ pub(crate) trait TxRwIApi<'tx>: TxIApi<'tx> + TxICheck<'tx> {
   /// See [TxRwRefApi::create_bucket_if_not_exists]
   fn api_create_bucket_if_not_exist(self, name: &[u8]) -> crate::Result<Self::BucketType>;
 
+  /// See [TxRwRefApi::create_bucket_with_comparator]
+  fn api_create_bucket_with_comparator(
+    self, name: &[u8], comparator: &str,
+  ) -> crate::Result<Self::BucketType>;
+
+  /// See [TxRwRefApi::create_buckets]
+  fn api_create_buckets(self, names: &[&[u8]]) -> crate::Result<Vec<Self::BucketType>>;
+
+  /// See [TxRwRefApi::ensure_schema]
+  fn api_ensure_schema(self, specs: &[BucketSpec]) -> crate::Result<Vec<Self::BucketType>>;
+
+  /// Runs `f` and rolls back its changes if it fails or panics. Returns the error of `f`,
+  /// a failed rollback fails the commit instead.
+  fn all_or_nothing<T, F: FnOnce() -> crate::Result<T>>(self, f: F) -> crate::Result<T>;
+
   /// See [TxRwRefApi::delete_bucket]
   fn api_delete_bucket(self, name: &[u8]) -> crate::Result<()>;
 
@@ -911,6 +2144,32 @@ pub(crate) trait TxRwIApi<'tx>: TxIApi<'tx> + TxICheck<'tx> {
   /// See [TxRwRefApi::on_commit]
   fn api_on_commit(self, f: Box<dyn FnOnce() + 'tx>);
 
+  /// Returns whether changes are recorded in the undo log, i.e. whether the transaction
+  /// has a savepoint
+  fn undo_enabled(self) -> bool;
+
+  /// Returns whether the transaction is rolling back to a savepoint
+  fn undoing(self) -> bool;
+
+  fn push_undo(self, undo: Undo<'tx>);
+
+  /// Records a change in the transaction's changeset if the database has a replication log
+  #[cfg(feature = "replication")]
+  fn record_change(self, change: Change);
+
+  /// Keeps `bytes` alive until the transaction closes and returns its contents
+  #[cfg(feature = "bytes")]
+  fn hold_bytes(self, bytes: Bytes) -> &'tx [u8];
+
+  /// See [TxRwRefApi::savepoint]
+  fn api_savepoint(self) -> u64;
+
+  /// See [Savepoint::release]
+  fn api_release_savepoint(self, id: u64);
+
+  /// See [Savepoint::rollback]
+  fn api_rollback_to_savepoint(self, id: u64) -> crate::Result<()>;
+
   fn physical_rollback(self) -> crate::Result<()> {
     if let Some(w) = self.split_ow_mut().as_mut() {
       w.tx_closing_state = TxClosingState::PhysicalRollback;
@@ -924,15 +2183,86 @@ pub struct TxR<'tx> {
   page_size: usize,
   db: &'tx LockGuard<'tx, DbShared>,
   pub(crate) stats: Option<Arc<TxStats>>,
+  /// operation counters of the top-level buckets that aren't merged into `stats` yet.
+  /// Bucket operations only touch this map. [TxR::merge_bucket_metrics] takes the stats lock
+  /// when the transaction closes or its stats are read.
+  pub(crate) bucket_metrics: RefCell<HashMap<'tx, &'tx [u8], BucketMetrics>>,
+  /// recorded comparators of the bucket paths opened so far
+  comparator_cache: RefCell<HashMap<'tx, &'tx [u8], KeyComparator>>,
+  /// the pages whose elements were checked so far. See [TxIApi::check_tree_page]
+  checked_pages: RefCell<HashSet<'tx, PgId>>,
   pub(crate) meta: Meta,
+  /// the high water mark of the meta the transaction began on. A writable transaction's
+  /// own meta grows as it allocates pages, which aren't mapped yet
+  committed_pgid: PgId,
+  /// leaf pages of recent gets. Only read-only transactions have one
+  pub(crate) leaf_cache: Option<LeafCache<'tx>>,
+  /// sub-buckets opened so far. Only read-only transactions have one
+  pub(crate) bucket_cache: Option<BucketCache<'tx>>,
+  /// whether reading a page verifies its checksum. Off while the transaction is checked
+  verify_checksums: bool,
+  /// set once the read transaction is evicted. See [crate::BoltOptions::evict_readers_after]
+  pub(crate) evicted: Option<Arc<AtomicBool>>,
   marker: PhantomData<&'tx u8>,
 }
 
+impl<'tx> TxR<'tx> {
+  /// Updates the operation counters of the top-level bucket `name`
+  pub(crate) fn inc_bucket_metrics<F: FnOnce(&mut BucketMetrics)>(&self, name: &'tx [u8], f: F) {
+    f(self.bucket_metrics.borrow_mut().entry(name).or_default());
+  }
+
+  /// Moves the counters gathered so far into the transaction's stats
+  pub(crate) fn merge_bucket_metrics(&self) {
+    let mut bucket_metrics = self.bucket_metrics.borrow_mut();
+    if bucket_metrics.is_empty() {
+      return;
+    }
+    if let Some(stats) = self.stats.as_ref() {
+      stats.merge_bucket_metrics(bucket_metrics.drain(), |m, delta| *m += delta);
+    }
+  }
+
+  /// Returns the cached comparator of the bucket at `path`
+  pub(crate) fn cached_comparator(&self, path: &[u8]) -> Option<KeyComparator> {
+    self.comparator_cache.borrow().get(path).copied()
+  }
+
+  /// Caches the comparator of the bucket at `path`
+  pub(crate) fn cache_comparator(&self, path: &'tx [u8], comparator: KeyComparator) {
+    self.comparator_cache.borrow_mut().insert(path, comparator);
+  }
+
+  /// Drops the cached comparators of the bucket at `path` and of all its sub-buckets
+  pub(crate) fn forget_comparators(&self, path: &[u8]) {
+    self
+      .comparator_cache
+      .borrow_mut()
+      .retain(|cached, _| !cached.starts_with(path));
+  }
+}
+
 pub struct TxW<'tx> {
   pages: HashMap<'tx, PgId, SelfOwned<AlignedBytes<alignment::Page>, MutPage<'tx>>>,
   commit_handlers: BVec<'tx, Box<dyn FnOnce() + 'tx>>,
   no_sync: bool,
   tx_closing_state: TxClosingState,
+  drop_policy: TxDropPolicy,
+  deadline: Option<Instant>,
+  mutations: u64,
+  undo: BVec<'tx, Undo<'tx>>,
+  savepoints: BVec<'tx, (u64, usize)>,
+  next_savepoint: u64,
+  /// set while rolling back to a savepoint
+  pub(crate) undoing: bool,
+  /// the first error from freeing a page or rolling back to a savepoint. Fails the commit
+  fatal_error: Option<Error>,
+  /// the encoded changes for the replication log. None if the database has none
+  #[cfg(feature = "replication")]
+  changes: Option<BVec<'tx, u8>>,
+  /// values put without copying. Released when the transaction is dropped
+  #[cfg(feature = "bytes")]
+  held_bytes: BVec<'tx, Bytes>,
   marker: PhantomData<&'tx u8>,
 }
 
@@ -979,14 +2309,63 @@ impl<'tx> TxIApi<'tx> for TxCell<'tx> {
 }
 
 impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
-  fn freelist_free_page(self, txid: TxId, p: &PageHeader) {
-    self.cell.borrow().r.db.free_page(txid, p)
+  fn freelist_free_page(self, txid: TxId, p: &PageHeader, context: &'static str) {
+    let freed = self.cell.borrow().r.db.free_page(txid, p, context);
+    match freed {
+      Ok(_) => {
+        // A dirty page was never visible to other transactions, so nothing can
+        // legitimately read it anymore
+        #[cfg(poison_freed_pages)]
+        {
+          let mut tx = self.cell.borrow_mut();
+          let page_size = tx.r.page_size;
+          if let Some(page) = tx.w.as_mut().unwrap().pages.get_mut(&p.id) {
+            let len = (page.overflow as usize + 1) * page_size;
+            page.poison(len);
+          }
+        }
+      }
+      Err(e) => {
+        let mut w = self.split_ow_mut();
+        if let Some(w) = w.as_mut() {
+          w.fatal_error.get_or_insert(e);
+        }
+      }
+    }
+  }
+
+  fn record_mutation(self) {
+    if let Some(w) = self.split_ow_mut().as_mut() {
+      w.mutations += 1;
+    }
+  }
+
+  fn check_deadline(self) -> crate::Result<()> {
+    match self.split_ow().as_ref() {
+      // Rolling back to a savepoint must not stop halfway
+      Some(w) if w.undoing => Ok(()),
+      Some(w)
+        if w
+          .deadline
+          .is_some_and(|deadline| Instant::now() >= deadline) =>
+      {
+        Err(Error::TxTimeout)
+      }
+      _ => Ok(()),
+    }
   }
 
   fn root_bucket_mut(self) -> BucketCell<'tx> {
     self.split_bound()
   }
 
+  fn system_bucket_mut(self, name: &[u8]) -> crate::Result<Self::BucketType> {
+    self
+      .root_bucket()
+      .api_create_bucket_if_not_exists(SYSTEM_BUCKET)?
+      .api_create_bucket_if_not_exists(name)
+  }
+
   fn allocate(
     self, count: usize,
   ) -> crate::Result<SelfOwned<AlignedBytes<alignment::Page>, MutPage<'tx>>> {
@@ -1004,7 +2383,17 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
 
   fn queue_page(self, page: SelfOwned<AlignedBytes<alignment::Page>, MutPage<'tx>>) {
     let mut tx = self.cell.borrow_mut();
+    #[cfg(poison_freed_pages)]
+    let page_size = tx.r.page_size;
     if let Some(pending) = tx.w.as_mut().unwrap().pages.insert(page.id, page) {
+      // The replaced page is reused for other pages
+      #[cfg(poison_freed_pages)]
+      let pending = {
+        let mut pending = pending;
+        let len = (pending.overflow as usize + 1) * page_size;
+        pending.poison(len);
+        pending
+      };
       if pending.overflow == 0 {
         tx.r
           .db
@@ -1016,16 +2405,76 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
   }
 
   fn api_create_bucket(self, name: &[u8]) -> crate::Result<Self::BucketType> {
+    if is_reserved(name) {
+      return Err(Error::ReservedName);
+    }
     let root_bucket = self.root_bucket();
     root_bucket.api_create_bucket(name)
   }
 
   fn api_create_bucket_if_not_exist(self, name: &[u8]) -> crate::Result<Self::BucketType> {
+    if is_reserved(name) {
+      return Err(Error::ReservedName);
+    }
     let root_bucket = self.root_bucket();
     root_bucket.api_create_bucket_if_not_exists(name)
   }
 
+  fn api_create_bucket_with_comparator(
+    self, name: &[u8], comparator: &str,
+  ) -> crate::Result<Self::BucketType> {
+    if is_reserved(name) {
+      return Err(Error::ReservedName);
+    }
+    let root_bucket = self.root_bucket();
+    root_bucket.api_create_bucket_with_comparator(name, comparator)
+  }
+
+  fn api_create_buckets(self, names: &[&[u8]]) -> crate::Result<Vec<Self::BucketType>> {
+    self.all_or_nothing(|| {
+      names
+        .iter()
+        .map(|name| self.api_create_bucket(name))
+        .collect()
+    })
+  }
+
+  fn api_ensure_schema(self, specs: &[BucketSpec]) -> crate::Result<Vec<Self::BucketType>> {
+    // Parents are provisioned before their children, so they get their own specs
+    let mut order = (0..specs.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| specs[*i].path().len());
+    self.all_or_nothing(|| {
+      let mut buckets = vec![None; specs.len()];
+      for i in order {
+        buckets[i] = Some(ensure_bucket(self, &specs[i])?);
+      }
+      Ok(buckets.into_iter().map(Option::unwrap).collect())
+    })
+  }
+
+  fn all_or_nothing<T, F: FnOnce() -> crate::Result<T>>(self, f: F) -> crate::Result<T> {
+    let savepoint = self.api_savepoint();
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+      Ok(Ok(value)) => {
+        self.api_release_savepoint(savepoint);
+        Ok(value)
+      }
+      // A failed rollback fails the commit, so the caller learns why `f` failed instead
+      Ok(Err(e)) => {
+        let _ = self.api_rollback_to_savepoint(savepoint);
+        Err(e)
+      }
+      Err(panic) => {
+        let _ = self.api_rollback_to_savepoint(savepoint);
+        std::panic::resume_unwind(panic)
+      }
+    }
+  }
+
   fn api_delete_bucket(self, name: &[u8]) -> crate::Result<()> {
+    if is_reserved(name) {
+      return Err(Error::ReservedName);
+    }
     let root_bucket = self.root_bucket();
     root_bucket.api_delete_bucket(name)
   }
@@ -1033,7 +2482,7 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
   fn write(self) -> crate::Result<()> {
     let (pages, db, page_size, no_sync) = {
       let mut tx = self.cell.borrow_mut();
-      let mut swap_pages = HashMap::with_capacity_in(0, tx.r.b);
+      let mut swap_pages = HashMap::with_hasher_in(MapHasher::default(), tx.r.b);
       // Clear out page cache early.
       mem::swap(&mut swap_pages, &mut tx.w.as_mut().unwrap().pages);
       let mut pages = BVec::from_iter_in(swap_pages.into_iter().map(|(_, page)| page), tx.r.b);
@@ -1050,6 +2499,9 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
 
     let r = self.split_r();
 
+    #[cfg(feature = "failpoints")]
+    db.fail_point(FailPoint::BeforeDataWrite);
+
     // Write pages to disk in order.
     for page in &pages {
       let mut rem = (page.overflow as usize + 1) * page_size;
@@ -1080,6 +2532,9 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
       db.fsync()?;
     }
 
+    #[cfg(feature = "failpoints")]
+    db.fail_point(FailPoint::BetweenDataAndMeta);
+
     for page in pages.into_iter() {
       if page.overflow == 0 {
         db.repool_allocated(page.into_owner());
@@ -1103,6 +2558,9 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
     let buf = unsafe { from_raw_parts_mut(ptr.as_ptr(), page_size) };
     db.write_all_at(buf, offset)?;
 
+    #[cfg(feature = "failpoints")]
+    db.fail_point(FailPoint::AfterMeta);
+
     if !tx.w.as_ref().unwrap().no_sync || IGNORE_NO_SYNC {
       db.fsync()?;
     }
@@ -1122,6 +2580,99 @@ impl<'tx> TxRwIApi<'tx> for TxCell<'tx> {
       .commit_handlers
       .push(f);
   }
+
+  fn undo_enabled(self) -> bool {
+    self
+      .split_ow()
+      .as_ref()
+      .map(|w| !w.savepoints.is_empty() && !w.undoing)
+      .unwrap_or(false)
+  }
+
+  fn undoing(self) -> bool {
+    self.split_ow().as_ref().is_some_and(|w| w.undoing)
+  }
+
+  fn push_undo(self, undo: Undo<'tx>) {
+    if let Some(w) = self.split_ow_mut().as_mut() {
+      w.undo.push(undo);
+    }
+  }
+
+  #[cfg(feature = "replication")]
+  fn record_change(self, change: Change) {
+    if let Some(changes) = self
+      .split_ow_mut()
+      .as_mut()
+      .and_then(|w| w.changes.as_mut())
+    {
+      change.encode(changes);
+    }
+  }
+
+  #[cfg(feature = "bytes")]
+  fn hold_bytes(self, bytes: Bytes) -> &'tx [u8] {
+    // The contents of a Bytes don't move when the handle does
+    let value = unsafe { from_raw_parts(bytes.as_ptr(), bytes.len()) };
+    let mut w = self.split_ow_mut();
+    w.as_mut().unwrap().held_bytes.push(bytes);
+    value
+  }
+
+  fn api_savepoint(self) -> u64 {
+    let mut w = self.split_ow_mut();
+    let w = w.as_mut().unwrap();
+    let id = w.next_savepoint;
+    w.next_savepoint += 1;
+    let mark = w.undo.len();
+    w.savepoints.push((id, mark));
+    id
+  }
+
+  fn api_release_savepoint(self, id: u64) {
+    let mut w = self.split_ow_mut();
+    let w = w.as_mut().unwrap();
+    w.savepoints.retain(|(savepoint, _)| *savepoint != id);
+    if w.savepoints.is_empty() {
+      w.undo.clear();
+    }
+  }
+
+  fn api_rollback_to_savepoint(self, id: u64) -> crate::Result<()> {
+    let entries = {
+      let mut w = self.split_ow_mut();
+      let w = w.as_mut().unwrap();
+      let index = w
+        .savepoints
+        .iter()
+        .position(|(savepoint, _)| *savepoint == id)
+        .ok_or(Error::SavepointReleased)?;
+      let mark = w.savepoints[index].1;
+      w.savepoints.truncate(index);
+      w.undoing = true;
+      w.undo.drain(mark..).rev().collect::<Vec<_>>()
+    };
+    // Revert as much as possible, the first error is reported
+    let mut result = Ok(());
+    for undo in entries {
+      match replay(self, undo) {
+        Err(e) if result.is_ok() => result = Err(e),
+        _ => (),
+      }
+    }
+    let mut w = self.split_ow_mut();
+    let w = w.as_mut().unwrap();
+    w.undoing = false;
+    if w.savepoints.is_empty() {
+      w.undo.clear();
+    }
+    // The transaction holds a partial rollback, it must not be committed
+    if let Err(e) = &result {
+      w.fatal_error
+        .get_or_insert_with(|| Error::InvalidInternalState(format!("partial rollback: {}", e)));
+    }
+    result
+  }
 }
 
 /// Read-only Transaction
@@ -1153,7 +2704,15 @@ impl<'tx> TxImpl<'tx> {
           page_size,
           db,
           meta,
+          committed_pgid: meta.pgid(),
           stats: Some(Default::default()),
+          bucket_metrics: RefCell::new(HashMap::with_hasher_in(MapHasher::default(), bump)),
+          comparator_cache: RefCell::new(HashMap::with_hasher_in(MapHasher::default(), bump)),
+          checked_pages: RefCell::new(HashSet::with_hasher_in(MapHasher::default(), bump)),
+          leaf_cache: Some(LeafCache::new_in(bump)),
+          bucket_cache: Some(BucketCache::new_in(bump)),
+          verify_checksums: true,
+          evicted: None,
           marker: Default::default(),
         };
 
@@ -1190,8 +2749,12 @@ impl<'tx> TxImpl<'tx> {
 impl<'tx> Drop for TxImpl<'tx> {
   fn drop(&mut self) {
     let tx_id = self.id();
-    let stats = self.tx.cell.borrow_mut().r.stats.take().unwrap();
-    Pin::as_ref(&self.db).guard().remove_tx(tx_id, stats);
+    let (stats, lease) = {
+      let mut tx = self.tx.cell.borrow_mut();
+      tx.r.merge_bucket_metrics();
+      (tx.r.stats.take().unwrap(), tx.r.evicted.take())
+    };
+    Pin::as_ref(&self.db).guard().remove_tx(tx_id, stats, lease);
   }
 }
 
@@ -1223,20 +2786,74 @@ impl<'tx> TxApi<'tx> for TxImpl<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketImpl::from)
   }
 
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    Ok(self.tx.api_try_bucket(name.as_ref())?.map(BucketImpl::from))
+  }
+
+  fn root_kv(&self) -> Option<BucketImpl<'tx>> {
+    self.tx.system_bucket(ROOT_KV_BUCKET).map(BucketImpl::from)
+  }
+
+  fn root_get<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    self.tx.api_root_get(key.as_ref())
+  }
+
   fn for_each<F: FnMut(&[u8], BucketImpl<'tx>) -> crate::Result<()>>(
     &self, f: F,
   ) -> crate::Result<()> {
     self.tx.api_for_each(f)
   }
 
+  fn walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.tx.api_walk(f)
+  }
+
   fn page(&self, id: PgId) -> Option<PageInfo> {
     self.tx.api_page(id)
   }
+
+  fn page_bytes(&self, id: PgId) -> crate::Result<&[u8]> {
+    self.tx.api_page_bytes(id)
+  }
+
+  fn page_elements(&self, id: PgId) -> crate::Result<Option<PageElements<'_>>> {
+    self.tx.api_page_bytes(id).map(PageElements::parse)
+  }
+
+  fn meta_info(&self) -> [MetaInfo; 2] {
+    self.tx.api_meta_info()
+  }
+
+  fn inspect(&self) -> crate::Result<BucketStructure> {
+    self.tx.api_inspect()
+  }
+
+  fn page_usage_report(&self) -> PageUsageReport {
+    self.tx.api_page_usage_report()
+  }
+
+  fn size_report(&self, top_n: usize) -> crate::Result<SizeReport> {
+    self.tx.api_size_report(top_n)
+  }
+
+  fn freelist_pages(&self) -> Vec<PgId> {
+    self.tx.split_r().db.freelist_free_ids()
+  }
+
+  fn pending_pages_by_txid(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self.tx.split_r().db.freelist_pending_ids()
+  }
+
+  fn export_iter(&self) -> ExportIter<'tx> {
+    ExportIter::new(self.tx.root_bucket().into())
+  }
 }
 
 /// Read-only Transaction reference used in managed transactions
 pub struct TxRef<'tx> {
-  tx: TxCell<'tx>,
+  pub(crate) tx: TxCell<'tx>,
 }
 
 impl<'tx> TxApi<'tx> for TxRef<'tx> {
@@ -1267,15 +2884,69 @@ impl<'tx> TxApi<'tx> for TxRef<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketImpl::from)
   }
 
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    Ok(self.tx.api_try_bucket(name.as_ref())?.map(BucketImpl::from))
+  }
+
+  fn root_kv(&self) -> Option<BucketImpl<'tx>> {
+    self.tx.system_bucket(ROOT_KV_BUCKET).map(BucketImpl::from)
+  }
+
+  fn root_get<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    self.tx.api_root_get(key.as_ref())
+  }
+
   fn for_each<F: FnMut(&[u8], BucketImpl<'tx>) -> crate::Result<()>>(
     &self, f: F,
   ) -> crate::Result<()> {
     self.tx.api_for_each(f)
   }
 
+  fn walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.tx.api_walk(f)
+  }
+
   fn page(&self, id: PgId) -> Option<PageInfo> {
     self.tx.api_page(id)
   }
+
+  fn page_bytes(&self, id: PgId) -> crate::Result<&[u8]> {
+    self.tx.api_page_bytes(id)
+  }
+
+  fn page_elements(&self, id: PgId) -> crate::Result<Option<PageElements<'_>>> {
+    self.tx.api_page_bytes(id).map(PageElements::parse)
+  }
+
+  fn meta_info(&self) -> [MetaInfo; 2] {
+    self.tx.api_meta_info()
+  }
+
+  fn inspect(&self) -> crate::Result<BucketStructure> {
+    self.tx.api_inspect()
+  }
+
+  fn page_usage_report(&self) -> PageUsageReport {
+    self.tx.api_page_usage_report()
+  }
+
+  fn size_report(&self, top_n: usize) -> crate::Result<SizeReport> {
+    self.tx.api_size_report(top_n)
+  }
+
+  fn freelist_pages(&self) -> Vec<PgId> {
+    self.tx.split_r().db.freelist_free_ids()
+  }
+
+  fn pending_pages_by_txid(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self.tx.split_r().db.freelist_pending_ids()
+  }
+
+  fn export_iter(&self) -> ExportIter<'tx> {
+    ExportIter::new(self.tx.root_bucket().into())
+  }
 }
 
 /// Read/Write Transaction
@@ -1286,6 +2957,12 @@ pub struct TxRwImpl<'tx> {
 }
 
 impl<'tx> TxRwImpl<'tx> {
+  pub(crate) fn set_deadline(&mut self, deadline: Option<Instant>) {
+    if let Some(w) = self.tx.split_ow_mut().as_mut() {
+      w.deadline = deadline;
+    }
+  }
+
   pub(crate) fn get_ref(&self) -> TxRwRef<'tx> {
     TxRwRef {
       tx: TxCell { cell: self.tx.cell },
@@ -1296,7 +2973,11 @@ impl<'tx> TxRwImpl<'tx> {
     bump: SyncReusable<Pin<Box<PinBump>>>, lock: RwLockUpgradableReadGuard<'tx, DbShared>,
     meta: Meta,
   ) -> TxRwImpl<'tx> {
-    let no_sync = lock.options.no_sync();
+    // Group commit syncs once for all transactions in the group
+    let no_sync = lock.options.no_sync() || lock.options.group_commit();
+    let drop_policy = lock.options.tx_drop_policy();
+    #[cfg(feature = "replication")]
+    let replicated = lock.options.replication_log().is_some() || lock.has_change_listeners();
     let page_size = meta.page_size() as usize;
     let inline_bucket = meta.root();
     let mut uninit: MaybeUninit<TxRwImpl<'tx>> = MaybeUninit::uninit();
@@ -1315,14 +2996,34 @@ impl<'tx> TxRwImpl<'tx> {
           page_size,
           db,
           meta,
+          committed_pgid: meta.pgid(),
           stats: Some(Default::default()),
+          bucket_metrics: RefCell::new(HashMap::with_hasher_in(MapHasher::default(), bump)),
+          comparator_cache: RefCell::new(HashMap::with_hasher_in(MapHasher::default(), bump)),
+          checked_pages: RefCell::new(HashSet::with_hasher_in(MapHasher::default(), bump)),
+          leaf_cache: None,
+          bucket_cache: None,
+          verify_checksums: true,
+          evicted: None,
           marker: Default::default(),
         };
         let tx_w = TxW {
-          pages: HashMap::with_capacity_in(0, bump),
+          pages: HashMap::with_hasher_in(MapHasher::default(), bump),
           commit_handlers: BVec::with_capacity_in(0, bump),
           no_sync,
           tx_closing_state: TxClosingState::Rollback,
+          drop_policy,
+          deadline: None,
+          mutations: 0,
+          undo: BVec::with_capacity_in(0, bump),
+          savepoints: BVec::with_capacity_in(0, bump),
+          next_savepoint: 0,
+          undoing: false,
+          fatal_error: None,
+          #[cfg(feature = "replication")]
+          changes: replicated.then(|| BVec::new_in(bump)),
+          #[cfg(feature = "bytes")]
+          held_bytes: BVec::new_in(bump),
           marker: Default::default(),
         };
 
@@ -1386,12 +3087,37 @@ impl<'tx> TxRwImpl<'tx> {
 impl<'tx> Drop for TxRwImpl<'tx> {
   fn drop(&mut self) {
     let mut cell = self.tx.cell.borrow_mut();
-    let tx_closing_state = cell.w.as_ref().unwrap().tx_closing_state;
+    let w = cell.w.as_mut().unwrap();
+    // The transaction's nodes no longer point into the held values
+    #[cfg(feature = "bytes")]
+    w.held_bytes.clear();
+    let tx_closing_state = w.tx_closing_state;
+    let drop_policy = w.drop_policy;
     let tx_id = cell.r.meta.txid();
+    cell.r.merge_bucket_metrics();
     let stats = cell.r.stats.take().unwrap();
+    drop(cell);
     Pin::as_ref(&self.db)
       .guard()
       .remove_rw_tx(tx_closing_state, tx_id, stats);
+    // Neither commit nor rollback was called
+    if matches!(
+      tx_closing_state,
+      TxClosingState::Rollback | TxClosingState::Prepared
+    ) {
+      match drop_policy {
+        TxDropPolicy::RollbackSilently => {}
+        TxDropPolicy::LogWarning => {
+          #[cfg(feature = "log")]
+          log::warn!("tx {} dropped without commit or rollback", tx_id);
+        }
+        TxDropPolicy::PanicInDebug => {
+          if cfg!(debug_assertions) && !std::thread::panicking() {
+            panic!("tx {} dropped without commit or rollback", tx_id);
+          }
+        }
+      }
+    }
   }
 }
 
@@ -1422,15 +3148,69 @@ impl<'tx> TxApi<'tx> for TxRwImpl<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketImpl::from)
   }
 
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    Ok(self.tx.api_try_bucket(name.as_ref())?.map(BucketImpl::from))
+  }
+
+  fn root_kv(&self) -> Option<BucketImpl<'tx>> {
+    self.tx.system_bucket(ROOT_KV_BUCKET).map(BucketImpl::from)
+  }
+
+  fn root_get<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    self.tx.api_root_get(key.as_ref())
+  }
+
   fn for_each<F: FnMut(&[u8], BucketImpl<'tx>) -> crate::Result<()>>(
     &self, f: F,
   ) -> crate::Result<()> {
     self.tx.api_for_each(f)
   }
 
+  fn walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.tx.api_walk(f)
+  }
+
   fn page(&self, id: PgId) -> Option<PageInfo> {
     self.tx.api_page(id)
   }
+
+  fn page_bytes(&self, id: PgId) -> crate::Result<&[u8]> {
+    self.tx.api_page_bytes(id)
+  }
+
+  fn page_elements(&self, id: PgId) -> crate::Result<Option<PageElements<'_>>> {
+    self.tx.api_page_bytes(id).map(PageElements::parse)
+  }
+
+  fn meta_info(&self) -> [MetaInfo; 2] {
+    self.tx.api_meta_info()
+  }
+
+  fn inspect(&self) -> crate::Result<BucketStructure> {
+    self.tx.api_inspect()
+  }
+
+  fn page_usage_report(&self) -> PageUsageReport {
+    self.tx.api_page_usage_report()
+  }
+
+  fn size_report(&self, top_n: usize) -> crate::Result<SizeReport> {
+    self.tx.api_size_report(top_n)
+  }
+
+  fn freelist_pages(&self) -> Vec<PgId> {
+    self.tx.split_r().db.freelist_free_ids()
+  }
+
+  fn pending_pages_by_txid(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self.tx.split_r().db.freelist_pending_ids()
+  }
+
+  fn export_iter(&self) -> ExportIter<'tx> {
+    ExportIter::new(self.tx.root_bucket().into())
+  }
 }
 
 impl<'tx> TxRwRefApi<'tx> for TxRwImpl<'tx> {
@@ -1438,6 +3218,27 @@ impl<'tx> TxRwRefApi<'tx> for TxRwImpl<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketRwImpl::from)
   }
 
+  fn root_kv_mut(&mut self) -> crate::Result<BucketRwImpl<'tx>> {
+    self
+      .tx
+      .system_bucket_mut(ROOT_KV_BUCKET)
+      .map(BucketRwImpl::from)
+  }
+
+  fn root_put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()> {
+    self
+      .tx
+      .system_bucket_mut(ROOT_KV_BUCKET)?
+      .api_put(key.as_ref(), value.as_ref())
+  }
+
+  fn root_delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
+    match self.tx.system_bucket(ROOT_KV_BUCKET) {
+      Some(bucket) => bucket.api_delete(key.as_ref()),
+      None => Ok(()),
+    }
+  }
+
   fn create_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<BucketRwImpl<'tx>> {
     self
       .tx
@@ -1454,6 +3255,28 @@ impl<'tx> TxRwRefApi<'tx> for TxRwImpl<'tx> {
       .map(BucketRwImpl::from)
   }
 
+  fn create_bucket_with_comparator<T: AsRef<[u8]>>(
+    &mut self, name: T, comparator: &str,
+  ) -> crate::Result<BucketRwImpl<'tx>> {
+    self
+      .tx
+      .api_create_bucket_with_comparator(name.as_ref(), comparator)
+      .map(BucketRwImpl::from)
+  }
+
+  fn create_buckets<T: AsRef<[u8]>>(
+    &mut self, names: &[T],
+  ) -> crate::Result<Vec<BucketRwImpl<'tx>>> {
+    let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+    let buckets = self.tx.api_create_buckets(&names)?;
+    Ok(buckets.into_iter().map(BucketRwImpl::from).collect())
+  }
+
+  fn ensure_schema(&mut self, specs: &[BucketSpec]) -> crate::Result<Vec<BucketRwImpl<'tx>>> {
+    let buckets = self.tx.api_ensure_schema(specs)?;
+    Ok(buckets.into_iter().map(BucketRwImpl::from).collect())
+  }
+
   fn delete_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<()> {
     self.tx.api_delete_bucket(name.as_ref())
   }
@@ -1461,6 +3284,10 @@ impl<'tx> TxRwRefApi<'tx> for TxRwImpl<'tx> {
   fn on_commit<F: FnOnce() + 'tx>(&mut self, f: F) {
     self.tx.api_on_commit(Box::new(f))
   }
+
+  fn savepoint(&mut self) -> Savepoint<'tx> {
+    Savepoint::new(self.tx)
+  }
 }
 
 impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
@@ -1469,12 +3296,44 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
   }
 
   fn commit(mut self) -> crate::Result<()> {
+    // Handle the case where the rollback is called within a managed transaction
+    if self.tx.split_ow().as_ref().unwrap().tx_closing_state == TxClosingState::ExplicitRollback {
+      return Ok(());
+    }
+    let commit_start = Instant::now();
+    let (tx_stats, dirty_page_n) = self.commit_data()?;
+    self.commit_meta(commit_start, tx_stats, dirty_page_n)
+  }
+
+  fn prepare(mut self) -> crate::Result<TxPrepared<'tx>> {
+    let commit_start = Instant::now();
+    let (tx_stats, dirty_page_n) = self.commit_data()?;
+    self.tx.split_ow_mut().as_mut().unwrap().tx_closing_state = TxClosingState::Prepared;
+    Ok(TxPrepared {
+      tx: self,
+      commit_start,
+      tx_stats,
+      dirty_page_n,
+    })
+  }
+}
+
+impl<'tx> TxRwImpl<'tx> {
+  /// Runs the first phase of a commit. Rebalances, spills and writes the data pages and
+  /// the freelist, but not the meta page. Returns the transaction's stats and the number
+  /// of dirty pages written.
+  fn commit_data(&mut self) -> crate::Result<(Arc<TxStats>, u64)> {
     let tx_stats = {
       let mut tx = self.tx.cell.borrow_mut();
-
-      // Handle the case where the rollback is called within a managed transaction
-      if tx.w.as_ref().unwrap().tx_closing_state == TxClosingState::ExplicitRollback {
-        return Ok(());
+      if tx
+        .w
+        .as_ref()
+        .unwrap()
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+      {
+        tx.w.as_mut().unwrap().tx_closing_state = TxClosingState::ExplicitRollback;
+        return Err(Error::TxTimeout);
       }
       tx.w.as_mut().unwrap().tx_closing_state = TxClosingState::Commit;
       tx.r.stats.as_ref().cloned().unwrap()
@@ -1482,6 +3341,11 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
 
     let bump = self.tx.bump();
 
+    // Rebalance and spill only run here rather than incrementally during the transaction.
+    // Nodes, inodes and dirty pages all live in the transaction's bump arena, which can't
+    // release individual allocations, so spilling early wouldn't lower peak memory. A
+    // spilled node is also final: is_spilled skips it and its children are cleared, so any
+    // later change to it would be lost.
     let start_time = Instant::now();
     self.tx.root_bucket().rebalance();
     if tx_stats.rebalance() > 0 {
@@ -1498,7 +3362,7 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
         return Err(e);
       }
     }
-    {
+    let freed = {
       let new_bucket = self.tx.cell.bound().split_r().bucket_header;
       let mut tx = self.tx.cell.borrow_mut();
       tx.r.meta.set_root(new_bucket);
@@ -1506,7 +3370,18 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
       //TODO: implement pgidNoFreeList
       let freelist_pg = tx.r.db.page(tx.r.meta.free_list());
       let tx_id = tx.r.meta.txid();
-      Pin::as_ref(&self.db).guard().free_page(tx_id, &freelist_pg);
+      let freed = Pin::as_ref(&self.db)
+        .guard()
+        .free_page(tx_id, &freelist_pg, "commit");
+      // Report the first invalid free or failed rollback of the transaction
+      match tx.w.as_mut().unwrap().fatal_error.take() {
+        Some(e) => Err(e),
+        None => freed,
+      }
+    };
+    if let Err(e) = freed {
+      let _ = self.tx.physical_rollback();
+      return Err(e);
     }
     // TODO: implement noFreelistSync
 
@@ -1520,20 +3395,33 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
 
     let new_pgid = self.tx.meta().pgid();
     let page_size = self.tx.meta().page_size();
-    {
+    let dirty_page_n = {
       let tx = self.tx.cell.borrow();
+      let mut dirty_page_n = 0;
       for page in tx.w.as_ref().unwrap().pages.values() {
         assert!(page.id.0 > 1, "Invalid page id");
+        dirty_page_n += page.overflow as u64 + 1;
       }
+      dirty_page_n
+    };
+    if let Err(e) = self.check_freelist_overlap() {
+      let _ = self.tx.physical_rollback();
+      return Err(e);
     }
     if new_pgid > opgid {
-      Pin::as_ref(&self.db)
+      if let Err(e) = Pin::as_ref(&self.db)
         .guard()
-        .grow((new_pgid.0 + 1) * page_size as u64)?;
+        .grow((new_pgid.0 + 1) * page_size as u64)
+      {
+        let _ = self.tx.physical_rollback();
+        return Err(e);
+      }
     }
     let start_time = Instant::now();
     match self.tx.write() {
-      Ok(_) => {}
+      Ok(_) => {
+        tx_stats.inc_write_time(start_time.elapsed());
+      }
       Err(e) => {
         let _ = self.tx.physical_rollback();
         return Err(e);
@@ -1542,21 +3430,113 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
 
     #[cfg(feature = "strict")]
     {
-      let errors = self.tx.check();
+      let errors = self.tx.check(&crate::kv_stringer::HexKvStringer);
       if !errors.is_empty() {
         panic!("check fail: {}", errors.join("\n"))
       }
     }
 
-    match self.tx.write_meta() {
+    Ok((tx_stats, dirty_page_n))
+  }
+
+  /// Returns [Error::FreelistOverlap] if the root page, the freelist page or a dirty page
+  /// is on the freelist. A later transaction could allocate it and overwrite a page in use.
+  fn check_freelist_overlap(&self) -> crate::Result<()> {
+    let tx = self.tx.cell.borrow();
+    let meta = tx.r.meta;
+    let mut ids = vec![meta.root().root(), meta.free_list()];
+    for page in tx.w.as_ref().unwrap().pages.values() {
+      ids.extend((0..=page.overflow as u64).map(|i| page.id + i));
+    }
+    match tx.r.db.find_freed(&ids) {
+      Some((pgid, pending)) => Err(Error::FreelistOverlap {
+        pgid,
+        txid: meta.txid(),
+        pending,
+      }),
+      None => Ok(()),
+    }
+  }
+
+  /// Appends the transaction's changes to the replication log. It happens before the meta
+  /// page is written, so the log never misses a commit. Subscribers only see them once
+  /// [DbIApi::commit_changes] marks them after the meta page is durable.
+  #[cfg(feature = "replication")]
+  fn append_changes(&self) -> crate::Result<()> {
+    let tx = self.tx.cell.borrow();
+    let w = tx.w.as_ref().unwrap();
+    match &w.changes {
+      Some(changes) if !changes.is_empty() => tx.r.db.append_changes(tx.r.meta.txid(), changes),
+      _ => Ok(()),
+    }
+  }
+
+  /// Passes the transaction's changes to the change listeners of the database
+  #[cfg(feature = "replication")]
+  fn notify_changes(&self) {
+    let tx = self.tx.cell.borrow();
+    let w = tx.w.as_ref().unwrap();
+    match &w.changes {
+      Some(changes) if !changes.is_empty() => tx.r.db.notify_changes(tx.r.meta.txid(), changes),
+      _ => (),
+    }
+  }
+
+  /// Runs the second phase of a commit. Writes the meta page, which makes the data
+  /// written by [TxRwImpl::commit_data] visible, and runs the commit handlers.
+  #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+  fn commit_meta(
+    self, commit_start: Instant, tx_stats: Arc<TxStats>, dirty_page_n: u64,
+  ) -> crate::Result<()> {
+    self.tx.split_ow_mut().as_mut().unwrap().tx_closing_state = TxClosingState::Commit;
+    #[cfg(feature = "replication")]
+    if let Err(e) = self.append_changes() {
+      let _ = self.tx.physical_rollback();
+      return Err(e);
+    }
+    let start_time = Instant::now();
+    let group_commit = Pin::as_ref(&self.db).guard().group_commit();
+    let write_meta = match &group_commit {
+      // The meta is written by the group commit once the data pages are synced
+      Some(group) => group.push(*self.tx.meta()),
+      None => self.tx.write_meta(),
+    };
+    match write_meta {
       Ok(_) => {
         tx_stats.inc_write_time(start_time.elapsed());
+        #[cfg(feature = "log")]
+        {
+          let meta = self.tx.meta();
+          log::info!(
+            "commit txid {}: root {}, freelist {}, {} dirty pages, {} bytes written",
+            meta.txid(),
+            meta.root().root(),
+            meta.free_list(),
+            dirty_page_n,
+            (dirty_page_n + 1) * meta.page_size() as u64
+          );
+        }
       }
       Err(e) => {
+        // The next transaction reuses the txid, so its changes must not stay in the log
+        #[cfg(feature = "replication")]
+        let _ = Pin::as_ref(&self.db)
+          .guard()
+          .retract_changes(self.tx.meta().txid());
         let _ = self.tx.physical_rollback();
         return Err(e);
       }
     }
+    #[cfg(feature = "replication")]
+    if group_commit.is_none() {
+      // The meta is durable. Marking fails only if the log can't be written, and the next
+      // commit or reopening the database marks the changes again.
+      let _ = Pin::as_ref(&self.db)
+        .guard()
+        .commit_changes(self.tx.meta().txid());
+    }
+    #[cfg(feature = "replication")]
+    self.notify_changes();
 
     let mut tx = self.tx.cell.borrow_mut();
     let mut commit_handlers = BVec::with_capacity_in(0, tx.r.b);
@@ -1564,13 +3544,57 @@ impl<'tx> TxRwApi<'tx> for TxRwImpl<'tx> {
       &mut commit_handlers,
       &mut tx.w.as_mut().unwrap().commit_handlers,
     );
+    let commit_recorder = Pin::as_ref(&self.db).guard().commit_recorder();
+    if let Some(group) = group_commit {
+      let txid = tx.r.meta.txid();
+      // The handlers outlive the transaction's bump, which goes back to the pool below
+      let commit_handlers = commit_handlers.into_iter().collect::<Vec<_>>();
+      drop(tx);
+      // Release the write lock so the next writer can join the group
+      drop(self);
+      // The transaction only counts as committed once its meta is synced
+      group.wait(txid)?;
+      for f in commit_handlers.into_iter() {
+        f();
+      }
+      commit_recorder.record(commit_start.elapsed(), &tx_stats);
+      return Ok(());
+    }
     for f in commit_handlers.into_iter() {
       f();
     }
+    commit_recorder.record(commit_start.elapsed(), &tx_stats);
     Ok(())
   }
 }
 
+/// A read-write transaction whose data pages are written and synced, but whose meta page
+/// isn't. Its changes aren't visible and are lost on a crash until [TxPrepared::confirm]
+/// writes the meta page. See [TxRwApi::prepare]
+///
+/// The database stays locked for writers until the transaction is confirmed or aborted.
+/// Dropping it aborts the transaction.
+pub struct TxPrepared<'tx> {
+  tx: TxRwImpl<'tx>,
+  commit_start: Instant,
+  tx_stats: Arc<TxStats>,
+  dirty_page_n: u64,
+}
+
+impl<'tx> TxPrepared<'tx> {
+  /// Writes the meta page, which completes the commit
+  pub fn confirm(self) -> crate::Result<()> {
+    self
+      .tx
+      .commit_meta(self.commit_start, self.tx_stats, self.dirty_page_n)
+  }
+
+  /// Discards the transaction. The pages written by [TxRwApi::prepare] are freed again.
+  pub fn abort(self) -> crate::Result<()> {
+    self.tx.tx.physical_rollback()
+  }
+}
+
 /// Read/Write Transaction reference used in managed transactions
 pub struct TxRwRef<'tx> {
   pub(crate) tx: TxCell<'tx>,
@@ -1603,15 +3627,69 @@ impl<'tx> TxApi<'tx> for TxRwRef<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketImpl::from)
   }
 
+  fn try_bucket<T: AsRef<[u8]>>(&self, name: T) -> crate::Result<Option<BucketImpl<'tx>>> {
+    Ok(self.tx.api_try_bucket(name.as_ref())?.map(BucketImpl::from))
+  }
+
+  fn root_kv(&self) -> Option<BucketImpl<'tx>> {
+    self.tx.system_bucket(ROOT_KV_BUCKET).map(BucketImpl::from)
+  }
+
+  fn root_get<T: AsRef<[u8]>>(&self, key: T) -> Option<&'tx [u8]> {
+    self.tx.api_root_get(key.as_ref())
+  }
+
   fn for_each<F: FnMut(&[u8], BucketImpl<'tx>) -> crate::Result<()>>(
     &self, f: F,
   ) -> crate::Result<()> {
     self.tx.api_for_each(f)
   }
 
+  fn walk<F: FnMut(&[&[u8]], &[u8], Option<&[u8]>) -> crate::Result<()>>(
+    &self, f: F,
+  ) -> crate::Result<()> {
+    self.tx.api_walk(f)
+  }
+
   fn page(&self, id: PgId) -> Option<PageInfo> {
     self.tx.api_page(id)
   }
+
+  fn page_bytes(&self, id: PgId) -> crate::Result<&[u8]> {
+    self.tx.api_page_bytes(id)
+  }
+
+  fn page_elements(&self, id: PgId) -> crate::Result<Option<PageElements<'_>>> {
+    self.tx.api_page_bytes(id).map(PageElements::parse)
+  }
+
+  fn meta_info(&self) -> [MetaInfo; 2] {
+    self.tx.api_meta_info()
+  }
+
+  fn inspect(&self) -> crate::Result<BucketStructure> {
+    self.tx.api_inspect()
+  }
+
+  fn page_usage_report(&self) -> PageUsageReport {
+    self.tx.api_page_usage_report()
+  }
+
+  fn size_report(&self, top_n: usize) -> crate::Result<SizeReport> {
+    self.tx.api_size_report(top_n)
+  }
+
+  fn freelist_pages(&self) -> Vec<PgId> {
+    self.tx.split_r().db.freelist_free_ids()
+  }
+
+  fn pending_pages_by_txid(&self) -> BTreeMap<TxId, Vec<PgId>> {
+    self.tx.split_r().db.freelist_pending_ids()
+  }
+
+  fn export_iter(&self) -> ExportIter<'tx> {
+    ExportIter::new(self.tx.root_bucket().into())
+  }
 }
 
 impl<'tx> TxRwRefApi<'tx> for TxRwRef<'tx> {
@@ -1619,6 +3697,27 @@ impl<'tx> TxRwRefApi<'tx> for TxRwRef<'tx> {
     self.tx.api_bucket(name.as_ref()).map(BucketRwImpl::from)
   }
 
+  fn root_kv_mut(&mut self) -> crate::Result<BucketRwImpl<'tx>> {
+    self
+      .tx
+      .system_bucket_mut(ROOT_KV_BUCKET)
+      .map(BucketRwImpl::from)
+  }
+
+  fn root_put<T: AsRef<[u8]>, U: AsRef<[u8]>>(&mut self, key: T, value: U) -> crate::Result<()> {
+    self
+      .tx
+      .system_bucket_mut(ROOT_KV_BUCKET)?
+      .api_put(key.as_ref(), value.as_ref())
+  }
+
+  fn root_delete<T: AsRef<[u8]>>(&mut self, key: T) -> crate::Result<()> {
+    match self.tx.system_bucket(ROOT_KV_BUCKET) {
+      Some(bucket) => bucket.api_delete(key.as_ref()),
+      None => Ok(()),
+    }
+  }
+
   fn create_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<BucketRwImpl<'tx>> {
     self
       .tx
@@ -1626,15 +3725,37 @@ impl<'tx> TxRwRefApi<'tx> for TxRwRef<'tx> {
       .map(BucketRwImpl::from)
   }
 
-  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
-    &mut self, name: T,
+  fn create_bucket_if_not_exists<T: AsRef<[u8]>>(
+    &mut self, name: T,
+  ) -> crate::Result<BucketRwImpl<'tx>> {
+    self
+      .tx
+      .api_create_bucket_if_not_exist(name.as_ref())
+      .map(BucketRwImpl::from)
+  }
+
+  fn create_bucket_with_comparator<T: AsRef<[u8]>>(
+    &mut self, name: T, comparator: &str,
   ) -> crate::Result<BucketRwImpl<'tx>> {
     self
       .tx
-      .api_create_bucket_if_not_exist(name.as_ref())
+      .api_create_bucket_with_comparator(name.as_ref(), comparator)
       .map(BucketRwImpl::from)
   }
 
+  fn create_buckets<T: AsRef<[u8]>>(
+    &mut self, names: &[T],
+  ) -> crate::Result<Vec<BucketRwImpl<'tx>>> {
+    let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+    let buckets = self.tx.api_create_buckets(&names)?;
+    Ok(buckets.into_iter().map(BucketRwImpl::from).collect())
+  }
+
+  fn ensure_schema(&mut self, specs: &[BucketSpec]) -> crate::Result<Vec<BucketRwImpl<'tx>>> {
+    let buckets = self.tx.api_ensure_schema(specs)?;
+    Ok(buckets.into_iter().map(BucketRwImpl::from).collect())
+  }
+
   fn delete_bucket<T: AsRef<[u8]>>(&mut self, name: T) -> crate::Result<()> {
     self.tx.api_delete_bucket(name.as_ref())
   }
@@ -1642,14 +3763,24 @@ impl<'tx> TxRwRefApi<'tx> for TxRwRef<'tx> {
   fn on_commit<F: FnOnce() + 'tx>(&mut self, f: F) {
     self.tx.api_on_commit(Box::new(f))
   }
+
+  fn savepoint(&mut self) -> Savepoint<'tx> {
+    Savepoint::new(self.tx)
+  }
 }
 
 pub(crate) mod check {
   use crate::bucket::BucketIApi;
-  use crate::common::page::{CoerciblePage, RefPage};
+  use crate::common::defaults::{GO_MAGIC, VERSION};
+  use crate::common::page::{CoerciblePage, RefPage, BRANCH_PAGE_FLAG, LEAF_PAGE_FLAG};
   use crate::common::tree::{MappedBranchPage, MappedLeafPage, TreePage};
-  use crate::common::{BVec, HashMap, HashSet, PgId, ZERO_PGID};
+  use crate::common::{BVec, HashMap, HashSet, MapHasher, PgId, ZERO_PGID};
+  use crate::comparator::{
+    path_names, split_meta_key, KeyComparator, COMPARATOR_META_KEY, META_BUCKET,
+  };
   use crate::db::DbIApi;
+  use crate::freelist::MappedFreeListPage;
+  use crate::kv_stringer::{HexKvStringer, KvStringer};
   use crate::tx::{TxCell, TxIApi, TxImpl, TxRef, TxRwIApi, TxRwImpl, TxRwRef};
 
   pub(crate) trait UnsealTx<'tx> {
@@ -1705,13 +3836,103 @@ pub(crate) mod check {
   /// Check performs several consistency checks on the database for this transaction.
   /// An error is returned if any inconsistency is found.
   ///
-  /// It can be safely run concurrently on a writable transaction. However, this
-  /// incurs a high cost for large databases and databases with a lot of subbuckets
-  /// because of caching. This overhead can be removed if running on a read-only
-  /// transaction, however, it is not safe to execute other writer transactions at
-  /// the same time.
+  /// It can be run on a writable transaction. However, this incurs a high cost for large
+  /// databases and databases with a lot of subbuckets because of caching. Running it on a
+  /// read-only transaction removes this overhead and doesn't block writers: it checks the
+  /// snapshot of the transaction against the freelist stored with that snapshot, so
+  /// transactions committed while it runs don't cause false reports.
   pub trait TxCheck<'tx> {
+    /// Returns a description of every inconsistency found in the transaction's view of
+    /// the database. An empty result means the database is consistent.
+    ///
+    /// ```rust
+    /// use bbolt_rs::*;
+    ///
+    /// fn main() -> Result<()> {
+    ///   let mut db = Bolt::open_mem()?;
+    ///
+    ///   db.update(|mut tx| {
+    ///     tx.create_bucket("widgets")?.put("foo", "bar")?;
+    ///     Ok(())
+    ///   })?;
+    ///
+    ///   db.view(|tx| {
+    ///     assert!(tx.check().is_empty());
+    ///     Ok(())
+    ///   })?;
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
     fn check(&self) -> Vec<String>;
+
+    /// Like [TxCheck::check], but renders the keys in the reports with `stringer` instead
+    /// of as hex.
+    ///
+    /// ```rust
+    /// use bbolt_rs::*;
+    ///
+    /// struct Utf8Stringer;
+    ///
+    /// impl KvStringer for Utf8Stringer {
+    ///   fn key_to_string(&self, key: &[u8]) -> String {
+    ///     String::from_utf8_lossy(key).into_owned()
+    ///   }
+    ///
+    ///   fn value_to_string(&self, value: &[u8]) -> String {
+    ///     String::from_utf8_lossy(value).into_owned()
+    ///   }
+    /// }
+    ///
+    /// fn main() -> Result<()> {
+    ///   let db = Bolt::open_mem()?;
+    ///
+    ///   db.view(|tx| {
+    ///     for error in tx.check_with(&Utf8Stringer) {
+    ///       println!("{}", error);
+    ///     }
+    ///     Ok(())
+    ///   })?;
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    fn check_with(&self, stringer: &dyn KvStringer) -> Vec<String>;
+
+    /// Reports everything that keeps the database from being read and written by
+    /// [etcd-io/bbolt](https://github.com/etcd-io/bbolt).
+    ///
+    /// Files written with the `compat` feature and the default options share Go bbolt's
+    /// file format: the magic number, the meta pages including their FNV-1a checksum, the
+    /// branch, leaf and freelist page layouts and the version field are byte compatible.
+    /// Without the `compat` feature the magic number differs. The file also stops being
+    /// compatible when it uses a feature Go bbolt doesn't know about, e.g. prefix
+    /// compressed branch pages or buckets with a custom comparator. An empty result means
+    /// the file can be handed to Go bbolt as is.
+    ///
+    /// ```rust
+    /// use bbolt_rs::*;
+    ///
+    /// fn main() -> Result<()> {
+    ///   let mut db = Bolt::open_mem()?;
+    ///
+    ///   db.update(|mut tx| {
+    ///     let mut b = tx.create_bucket_if_not_exists("test")?;
+    ///     b.put("key", "value")?;
+    ///     Ok(())
+    ///   })?;
+    ///
+    ///   db.view(|tx| {
+    ///     for issue in tx.compat_check() {
+    ///       println!("{}", issue);
+    ///     }
+    ///     Ok(())
+    ///   })?;
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    fn compat_check(&self) -> Vec<String>;
   }
 
   impl<'tx, T> TxCheck<'tx> for T
@@ -1719,27 +3940,63 @@ pub(crate) mod check {
     T: UnsealTx<'tx>,
   {
     fn check(&self) -> Vec<String> {
+      self.check_with(&HexKvStringer)
+    }
+
+    fn check_with(&self, stringer: &dyn KvStringer) -> Vec<String> {
+      let tx = self.unseal();
+      tx.check(stringer)
+    }
+
+    fn compat_check(&self) -> Vec<String> {
       let tx = self.unseal();
-      tx.check()
+      tx.compat_check()
     }
   }
 
+  /// How the keys of the checked bucket are ordered and rendered in the reports
+  #[derive(Copy, Clone)]
+  pub(crate) struct KeyOrder<'a> {
+    comparator: KeyComparator,
+    stringer: &'a dyn KvStringer,
+  }
+
   pub(crate) trait TxICheck<'tx>: TxIApi<'tx> {
-    fn check(self) -> Vec<String> {
+    fn check(self, stringer: &dyn KvStringer) -> Vec<String> {
+      self.without_checksums(|| self.check_pages(stringer))
+    }
+
+    fn check_pages(self, stringer: &dyn KvStringer) -> Vec<String> {
       let mut errors = Vec::new();
       let bump = self.bump();
-      let db = self.split_r().db;
-      let freelist_count = db.freelist_count();
       let high_water = self.meta().pgid();
-      // TODO: ReadOnly mode handling
 
-      // Check if any pages are double freed.
-      let mut freed = HashSet::new_in(bump);
-      let mut all = BVec::with_capacity_in(freelist_count as usize, bump);
-      for _ in 0..freelist_count {
-        all.push(ZERO_PGID);
+      // A read transaction checks the freelist of its snapshot. The database's freelist
+      // already holds the pages writers allocated and freed since.
+      let freelist_pgid = self.meta().free_list();
+      let mut all = BVec::new_in(bump);
+      if self.split_ow().is_some() {
+        let db = self.split_r().db;
+        let freelist_count = db.freelist_count();
+        all.reserve(freelist_count as usize);
+        for _ in 0..freelist_count {
+          all.push(ZERO_PGID);
+        }
+        db.freelist_copyall(&mut all);
+      } else {
+        let page = self.mem_page(freelist_pgid);
+        match MappedFreeListPage::coerce_ref(&page) {
+          Some(freelist_page) => all.extend_from_slice(freelist_page.page_ids()),
+          None => errors.push(format!(
+            "page {}: invalid freelist page type: {}",
+            freelist_pgid,
+            page.page_type()
+          )),
+        }
       }
-      db.freelist_copyall(&mut all);
+
+      // Check if any pages are double freed.
+      let mut freed = HashSet::with_hasher_in(MapHasher::default(), bump);
       for id in &all {
         if freed.contains(id) {
           errors.push(format!("page {}: already freed", id));
@@ -1749,17 +4006,22 @@ pub(crate) mod check {
       }
 
       // Track every reachable page.
-      let mut reachable = HashMap::new_in(bump);
+      let mut reachable = HashMap::with_hasher_in(MapHasher::default(), bump);
       reachable.insert(PgId(0), self.mem_page(PgId(0))); //meta 0
       reachable.insert(PgId(1), self.mem_page(PgId(1))); // meta 1
-      let freelist_pgid = self.meta().free_list();
       for i in 0..=self.mem_page(freelist_pgid).overflow {
         let pg_id = freelist_pgid + i as u64;
         reachable.insert(pg_id, self.mem_page(freelist_pgid));
       }
 
       // Recursively check buckets.
-      self.check_bucket(self.split_bound(), &mut reachable, &mut freed, &mut errors);
+      self.check_bucket(
+        self.split_bound(),
+        &mut reachable,
+        &mut freed,
+        stringer,
+        &mut errors,
+      );
 
       // Ensure all pages below high water mark are either reachable or freed.
       for i in 0..high_water.0 {
@@ -1772,16 +4034,88 @@ pub(crate) mod check {
       errors
     }
 
+    fn compat_check(self) -> Vec<String> {
+      let mut issues = Vec::new();
+      let magic = self.meta().magic();
+      if magic != GO_MAGIC {
+        issues.push(format!(
+          "meta: magic {:#x} is not supported, Go bbolt requires {:#x}",
+          magic, GO_MAGIC
+        ));
+      }
+      let version = self.meta().version();
+      if version != VERSION {
+        issues.push(format!(
+          "meta: version {} is not supported, Go bbolt requires version {}",
+          version, VERSION
+        ));
+      }
+
+      self.compat_check_bucket(self.split_bound(), &mut issues);
+
+      // Go bbolt orders the keys of every bucket bytewise
+      if let Some(meta) = self.system_bucket(META_BUCKET) {
+        meta
+          .api_for_each(|entry, name| {
+            let path = match (split_meta_key(entry), name) {
+              (Some((path, key)), Some(_)) if key == COMPARATOR_META_KEY => path,
+              _ => return Ok(()),
+            };
+            let path = path_names(path)
+              .into_iter()
+              .map(String::from_utf8_lossy)
+              .collect::<Vec<_>>();
+            issues.push(format!(
+              "bucket {:?}: custom comparator {:?}",
+              path,
+              String::from_utf8_lossy(name.unwrap_or_default())
+            ));
+            Ok(())
+          })
+          .unwrap();
+      }
+      issues
+    }
+
+    fn compat_check_bucket(&self, bucket: Self::BucketType, issues: &mut Vec<String>) {
+      if bucket.root() != ZERO_PGID {
+        self.for_each_page(bucket.root(), &mut |p, _, _| {
+          if p.has_checksum() {
+            issues.push(format!(
+              "page {}: checksummed pages are not supported",
+              p.id
+            ));
+          }
+          let flags = p.type_flags();
+          if flags != BRANCH_PAGE_FLAG && flags != LEAF_PAGE_FLAG {
+            issues.push(format!("page {}: unsupported flags {:#x}", p.id, flags));
+          }
+        });
+      }
+
+      bucket
+        .api_for_each_bucket(|key| {
+          let child = bucket.api_bucket(key).unwrap();
+          self.compat_check_bucket(child, issues);
+          Ok(())
+        })
+        .unwrap();
+    }
+
     fn check_bucket(
       &self, bucket: Self::BucketType, reachable: &mut HashMap<PgId, RefPage<'tx>>,
-      freed: &mut HashSet<PgId>, errors: &mut Vec<String>,
+      freed: &mut HashSet<PgId>, stringer: &dyn KvStringer, errors: &mut Vec<String>,
     ) {
       // ignore inline buckets
       if bucket.root() == ZERO_PGID {
         return;
       }
 
+      let page_size = self.page_size();
       self.for_each_page(bucket.root(), &mut |p, _, pgid_stack| {
+        if let Err(e) = p.verify_checksum(page_size) {
+          errors.push(format!("{} (stack: {:?})", e, pgid_stack));
+        }
         if p.id > self.meta().pgid() {
           errors.push(format!(
             "page {}: out of bounds: {} (stack: {:?})",
@@ -1813,44 +4147,45 @@ pub(crate) mod check {
         }
       });
 
-      self.recursively_check_pages(bucket.root(), errors);
+      self.recursively_check_pages(bucket.root(), bucket.comparator(), stringer, errors);
 
       bucket
         .api_for_each_bucket(|key| {
           let child = bucket.api_bucket(key).unwrap();
-          self.check_bucket(child, reachable, freed, errors);
+          self.check_bucket(child, reachable, freed, stringer, errors);
           Ok(())
         })
         .unwrap();
     }
 
-    fn recursively_check_pages(self, pg_id: PgId, errors: &mut Vec<String>) {
+    fn recursively_check_pages(
+      self, pg_id: PgId, comparator: KeyComparator, stringer: &dyn KvStringer,
+      errors: &mut Vec<String>,
+    ) {
       let bump = self.bump();
       let mut pgid_stack = BVec::new_in(bump);
-      self.recursively_check_pages_internal(pg_id, &[], &[], &mut pgid_stack, errors);
+      let keys = KeyOrder {
+        comparator,
+        stringer,
+      };
+      self.recursively_check_pages_internal(pg_id, keys, &[], &[], &mut pgid_stack, errors);
     }
 
     fn recursively_check_pages_internal(
-      self, pg_id: PgId, min_key_closed: &[u8], max_key_open: &[u8], pageid_stack: &mut BVec<PgId>,
-      errors: &mut Vec<String>,
+      self, pg_id: PgId, keys: KeyOrder, min_key_closed: &[u8], max_key_open: &[u8],
+      pageid_stack: &mut BVec<PgId>, errors: &mut Vec<String>,
     ) -> &'tx [u8] {
       let p = self.mem_page(pg_id);
       pageid_stack.push(pg_id);
       let mut max_key_in_subtree = [].as_slice();
       if let Some(branch_page) = MappedBranchPage::coerce_ref(&p) {
+        let bump = self.bump();
         let mut running_min = min_key_closed;
         let elements_len = branch_page.elements().len();
-        for (i, (pg_id, key)) in branch_page
-          .elements()
-          .iter()
-          .map(|e| {
-            (e.pgid(), unsafe {
-              e.key(branch_page.page_ptr().cast_const())
-            })
-          })
-          .enumerate()
-        {
+        for (i, pg_id) in branch_page.elements().iter().map(|e| e.pgid()).enumerate() {
+          let key = branch_page.key_in(i as u16, bump).unwrap();
           self.verify_key_order(
+            keys,
             pg_id,
             "branch",
             i,
@@ -1862,10 +4197,10 @@ pub(crate) mod check {
           );
           let mut max_key = max_key_open;
           if i < elements_len - 1 {
-            max_key = branch_page.get_elem(i as u16 + 1).unwrap().key();
+            max_key = branch_page.key_in(i as u16 + 1, bump).unwrap();
           }
           max_key_in_subtree =
-            self.recursively_check_pages_internal(pg_id, key, max_key, pageid_stack, errors);
+            self.recursively_check_pages_internal(pg_id, keys, key, max_key, pageid_stack, errors);
           running_min = max_key_in_subtree;
         }
         pageid_stack.pop();
@@ -1879,6 +4214,7 @@ pub(crate) mod check {
           .enumerate()
         {
           self.verify_key_order(
+            keys,
             pg_id,
             "leaf",
             i,
@@ -1906,21 +4242,25 @@ pub(crate) mod check {
      * is within range determined by (previousKey..maxKeyOpen) and reports found violations to the channel (ch).
      */
     fn verify_key_order(
-      self, pg_id: PgId, page_type: &str, index: usize, key: &[u8], previous_key: &[u8],
-      max_key_open: &[u8], pageid_stack: &mut BVec<PgId>, errors: &mut Vec<String>,
+      self, keys: KeyOrder, pg_id: PgId, page_type: &str, index: usize, key: &[u8],
+      previous_key: &[u8], max_key_open: &[u8], pageid_stack: &mut BVec<PgId>,
+      errors: &mut Vec<String>,
     ) {
-      if index == 0 && !previous_key.is_empty() && previous_key > key {
-        errors.push(format!("the first key[{}]={:02X?} on {} page({}) needs to be >= the key in the ancestor ({:02X?}). Stack: {:?}", index, key, page_type, pg_id, previous_key, pageid_stack));
+      let order = (keys.comparator)(previous_key, key);
+      // Keys are only rendered for the reports
+      let render = |key: &[u8]| keys.stringer.key_to_string(key);
+      if index == 0 && !previous_key.is_empty() && order.is_gt() {
+        errors.push(format!("the first key[{}]={} on {} page({}) needs to be >= the key in the ancestor ({}). Stack: {:?}", index, render(key), page_type, pg_id, render(previous_key), pageid_stack));
       }
       if index > 0 {
-        if previous_key > key {
-          errors.push(format!("key[{}]=(hex){:02X?} on {} page({}) needs to be > (found <) than previous element (hex){:02X?}. Stack: {:?}", index, key, page_type, pg_id, previous_key, pageid_stack));
-        } else if previous_key == key {
-          errors.push(format!("key[{}]=(hex){:02X?} on {} page({}) needs to be > (found =) than previous element (hex){:02X?}. Stack: {:?}", index, key, page_type, pg_id, previous_key, pageid_stack));
+        if order.is_gt() {
+          errors.push(format!("key[{}]={} on {} page({}) needs to be > (found <) than previous element {}. Stack: {:?}", index, render(key), page_type, pg_id, render(previous_key), pageid_stack));
+        } else if order.is_eq() {
+          errors.push(format!("key[{}]={} on {} page({}) needs to be > (found =) than previous element {}. Stack: {:?}", index, render(key), page_type, pg_id, render(previous_key), pageid_stack));
         }
       }
-      if !max_key_open.is_empty() && key >= max_key_open {
-        errors.push(format!("key[{}]=(hex){:02X?} on {} page({}) needs to be < than key of the next element in ancestor (hex){:02X?}. Pages stack: {:?}", index, key, page_type, pg_id, previous_key, pageid_stack));
+      if !max_key_open.is_empty() && (keys.comparator)(key, max_key_open).is_ge() {
+        errors.push(format!("key[{}]={} on {} page({}) needs to be < than key of the next element in ancestor {}. Pages stack: {:?}", index, render(key), page_type, pg_id, render(previous_key), pageid_stack));
       }
     }
   }
@@ -1932,12 +4272,17 @@ pub(crate) mod check {
 mod test {
   use crate::common::cell::RefCell;
   use crate::common::defaults::DEFAULT_PAGE_SIZE;
+  #[cfg(poison_freed_pages)]
+  use crate::common::page::{MutPage, PAGE_HEADER_SIZE};
+  use crate::common::SplitRef;
+  use crate::db::DbIApi;
   use crate::test_support::TestDb;
-  use crate::tx::check::TxCheck;
-  use crate::tx::{TxRwApi, TxStats};
+  use crate::tx::check::{TxCheck, UnsealTx};
+  use crate::tx::{TxIApi, TxRwApi, TxRwIApi, TxStats};
   use crate::{
-    Bolt, BoltOptions, BucketApi, BucketRwApi, CursorApi, DbApi, DbRwAPI, Error, TxApi, TxImpl,
-    TxRwRefApi,
+    Bolt, BoltOptions, BucketApi, BucketRwApi, BucketSpec, BucketStructure, Comparators, CursorApi,
+    DbApi, DbRwAPI, Error, HexKvStringer, KvStringer, PageElements, PgId, TxApi, TxDropPolicy,
+    TxImpl, TxRwRefApi,
   };
   use anyhow::anyhow;
   use std::time::Duration;
@@ -1964,6 +4309,210 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_tx_check_concurrent_writer() -> crate::Result<()> {
+    // The writer would deadlock growing the mmap while the read transaction is open
+    let db_options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    let mut db = TestDb::with_options(db_options)?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..500u32 {
+        b.delete(i.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+
+    let view_db = db.clone_db();
+    let tx = view_db.begin()?;
+    // Reuses the pages freed above and frees pages the snapshot of tx still refers to
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..400u32 {
+        b.put(i.to_be_bytes(), [1u8; 100])?;
+      }
+      for i in 500..700u32 {
+        b.delete(i.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+    let errors = tx.check();
+    assert!(errors.is_empty(), "{:?}", errors);
+    drop(tx);
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_freelist_pages() -> crate::Result<()> {
+    let db_options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    let mut db = TestDb::with_options(db_options)?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..100u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      Ok(())
+    })?;
+
+    // The open read transaction keeps the pages freed by the next commit pending
+    let view_db = db.clone_db();
+    let reader = view_db.begin()?;
+    db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "bar"))?;
+    let writer_txid = reader.id() + 1;
+    db.view(|tx| {
+      let pending = tx.pending_pages_by_txid();
+      let ids = pending.get(&writer_txid).unwrap();
+      assert!(!ids.is_empty());
+      assert!(ids.windows(2).all(|w| w[0] < w[1]));
+      for id in ids {
+        assert!(!tx.freelist_pages().contains(id));
+        assert_eq!("free", tx.page(*id).unwrap().t);
+      }
+      Ok(())
+    })?;
+    drop(reader);
+
+    db.update(|mut tx| tx.bucket_mut("widgets").unwrap().put("foo", "baz"))?;
+    db.view(|tx| {
+      assert!(!tx.pending_pages_by_txid().contains_key(&writer_txid));
+      let free = tx.freelist_pages();
+      assert!(free.windows(2).all(|w| w[0] < w[1]));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_compat_check() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let mut db = TestDb::with_options(BoltOptions::builder().comparators(comparators).build())?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "value")?;
+      }
+      b.create_bucket("nested")?;
+      Ok(())
+    })?;
+    let mut expected = Vec::new();
+    if !cfg!(feature = "compat") {
+      expected
+        .push("meta: magic 0x5caff01d is not supported, Go bbolt requires 0xed0cdaed".to_string());
+    }
+    db.view(|tx| {
+      assert_eq!(expected, tx.compat_check());
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      b.create_bucket_with_comparator("reversed", "reverse")?;
+      Ok(())
+    })?;
+    expected.push(r#"bucket ["widgets", "reversed"]: custom comparator "reverse""#.to_string());
+    db.view(|tx| {
+      assert_eq!(expected, tx.compat_check());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Copies a database written by Go bbolt (see scripts/go_fixtures) into a temp file and opens it
+  #[cfg(all(feature = "compat", not(any(miri, feature = "test-mem-backend"))))]
+  fn open_go_fixture(bytes: &[u8]) -> crate::Result<(tempfile::NamedTempFile, Bolt)> {
+    let file = crate::test_support::temp_file()?;
+    std::fs::write(file.path(), bytes)?;
+    let db = Bolt::open(file.path())?;
+    Ok((file, db))
+  }
+
+  #[test]
+  #[cfg(all(feature = "compat", not(any(miri, feature = "test-mem-backend"))))]
+  fn test_tx_go_fixture_empty() -> crate::Result<()> {
+    let (file, mut db) = open_go_fixture(include_bytes!("../testdata/go/empty.db"))?;
+    assert_eq!(4096, db.info().page_size);
+    db.view(|tx| {
+      assert_eq!(crate::common::ids::td(1), tx.id());
+      assert_eq!(None, tx.cursor().first());
+      assert!(tx.check().is_empty());
+      assert!(tx.compat_check().is_empty());
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      Ok(())
+    })?;
+    db.close();
+
+    let mut db = Bolt::open(file.path())?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some("bar".as_bytes()), b.get("foo"));
+      assert!(tx.check().is_empty());
+      assert!(tx.compat_check().is_empty());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(feature = "compat", not(any(miri, feature = "test-mem-backend"))))]
+  fn test_tx_go_fixture_buckets() -> crate::Result<()> {
+    let (file, mut db) = open_go_fixture(include_bytes!("../testdata/go/buckets.db"))?;
+    db.view(|tx| {
+      assert_eq!(crate::common::ids::td(2), tx.id());
+      let big = tx.bucket("big").unwrap();
+      let mut count = 0u32;
+      big.for_each(|k, v| {
+        assert_eq!(count.to_be_bytes(), k);
+        assert_eq!(Some(format!("value-{:014}", count).as_bytes()), v);
+        count += 1;
+        Ok(())
+      })?;
+      assert_eq!(100, count);
+      let widgets = tx.bucket("widgets").unwrap();
+      assert_eq!(Some("bar".as_bytes()), widgets.get("foo"));
+      assert_eq!(Some("qux".as_bytes()), widgets.get("baz"));
+      assert!(tx.check().is_empty());
+      assert!(tx.compat_check().is_empty());
+      Ok(())
+    })?;
+    // Writing on top of Go's freelist must keep the file usable by Go
+    db.update(|mut tx| {
+      let mut big = tx.bucket_mut("big").unwrap();
+      for i in 100..1000u32 {
+        big.put(i.to_be_bytes(), format!("value-{:014}", i))?;
+      }
+      tx.delete_bucket("widgets")?;
+      Ok(())
+    })?;
+    db.close();
+
+    let mut db = Bolt::open(file.path())?;
+    db.view(|tx| {
+      assert!(tx.bucket("widgets").is_none());
+      assert_eq!(
+        Some("value-00000000000999".as_bytes()),
+        tx.bucket("big").unwrap().get(999u32.to_be_bytes())
+      );
+      assert!(tx.check().is_empty());
+      assert!(tx.compat_check().is_empty());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
   #[test]
   fn test_tx_cursor() -> crate::Result<()> {
     let mut db = TestDb::new()?;
@@ -2127,6 +4676,48 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_tx_walk() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("foo", "bar")?;
+      let mut nested = b.create_bucket("nested")?;
+      nested.put("baz", "bat")?;
+      tx.create_bucket("woojits")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let mut visited = Vec::new();
+      tx.walk(|path, k, v| {
+        let path = path.iter().map(|p| p.to_vec()).collect::<Vec<_>>();
+        visited.push((path, k.to_vec(), v.map(|v| v.to_vec())));
+        Ok(())
+      })?;
+      let expected = vec![
+        (vec![], b"widgets".to_vec(), None),
+        (
+          vec![b"widgets".to_vec()],
+          b"foo".to_vec(),
+          Some(b"bar".to_vec()),
+        ),
+        (vec![b"widgets".to_vec()], b"nested".to_vec(), None),
+        (
+          vec![b"widgets".to_vec(), b"nested".to_vec()],
+          b"baz".to_vec(),
+          Some(b"bat".to_vec()),
+        ),
+        (vec![], b"woojits".to_vec(), None),
+      ];
+      assert_eq!(expected, visited);
+
+      let r = tx.walk(|_, _, _| Err(Error::Other(anyhow!("marker"))));
+      assert_eq!("marker", r.map_err(|e| e.to_string()).err().unwrap());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
   #[test]
   fn test_tx_on_commit() -> crate::Result<()> {
     let x = RefCell::new(0u64);
@@ -2200,6 +4791,150 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_tx_commit_freelist_overlap() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+    // Free the unchanged root page, which the new meta still refers to
+    let tx = db.begin_rw_tx()?;
+    let root = {
+      let cell = tx.tx.cell.borrow();
+      let root = cell.r.meta.root().root();
+      let page = cell.r.db.page(root);
+      cell.r.db.free_page(cell.r.meta.txid(), &page, "test")?;
+      root
+    };
+    match tx.commit() {
+      Err(Error::FreelistOverlap { pgid, pending, .. }) => {
+        assert_eq!(root, pgid);
+        assert!(pending);
+      }
+      r => panic!("unexpected result {:?}", r),
+    }
+
+    db.view(|tx| {
+      assert!(tx.bucket("widgets").is_some());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(poison_freed_pages)]
+  fn test_tx_poison_freed_page() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let tx = db.begin_rw_tx()?;
+    let mut page = tx.tx.allocate(2)?;
+    page.set_leaf();
+    let id = page.id;
+    tx.tx.queue_page(page);
+    let txid = tx.tx.meta().txid();
+    let header = **tx.tx.any_page(id);
+    tx.tx.freelist_free_page(txid, &header, "test");
+    {
+      let cell = tx.tx.cell.borrow();
+      let page = &cell.w.as_ref().unwrap().pages[&id];
+      let bytes = page.ref_owner();
+      assert_eq!(id, page.id);
+      assert_eq!(2 * tx.tx.page_size(), bytes.len());
+      for chunk in bytes[PAGE_HEADER_SIZE..].chunks(4) {
+        assert_eq!(chunk, &MutPage::POISON[..chunk.len()]);
+      }
+    }
+    tx.rollback()
+  }
+
+  #[test]
+  fn test_tx_rollback_restores_freelist() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      Ok(())
+    })?;
+    // Beginning a write transaction releases the pages pending from the last commit
+    db.begin_rw_tx()?.rollback()?;
+    let free_n = db.stats().free_page_n();
+    let pending_n = db.stats().pending_page_n();
+
+    // Deleting the bucket frees its pages before the transaction commits
+    let mut tx = db.begin_rw_tx()?;
+    tx.delete_bucket("widgets")?;
+    tx.rollback()?;
+    assert_eq!(free_n, db.stats().free_page_n());
+    assert_eq!(pending_n, db.stats().pending_page_n());
+
+    // An open read transaction holds back the pages freed after it began
+    let reader = db.clone_db();
+    let tx = reader.begin_tx()?;
+    db.update(|mut tx| {
+      tx.delete_bucket("widgets")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      tx.create_bucket("gadgets")?;
+      Ok(())
+    })?;
+    let held_free_n = db.stats().free_page_n();
+    drop(tx);
+    db.update(|mut tx| {
+      tx.create_bucket("sprockets")?;
+      Ok(())
+    })?;
+    assert!(db.stats().free_page_n() > held_free_n);
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_interleaved_rollback() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    for round in 0..20u32 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("widgets")?;
+        for i in 0..100u32 {
+          b.put((round * 100 + i).to_be_bytes(), [round as u8; 200])?;
+        }
+        if round > 0 {
+          for i in 0..50u32 {
+            b.delete(((round - 1) * 100 + i).to_be_bytes())?;
+          }
+        }
+        Ok(())
+      })?;
+      let r = db.update(|mut tx| {
+        {
+          let mut b = tx.bucket_mut("widgets").unwrap();
+          for i in 0..100u32 {
+            b.put((100_000 + round * 100 + i).to_be_bytes(), [0u8; 200])?;
+            b.delete((round * 100 + i).to_be_bytes())?;
+          }
+        }
+        tx.delete_bucket("widgets")?;
+        Err(Error::Other(anyhow!("aborted")))
+      });
+      assert!(r.is_err());
+    }
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      for round in 0..20u32 {
+        for i in 0..100u32 {
+          assert_eq!(None, b.get((100_000 + round * 100 + i).to_be_bytes()));
+          let deleted = round < 19 && i < 50;
+          assert_eq!(!deleted, b.get((round * 100 + i).to_be_bytes()).is_some());
+        }
+      }
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
   #[test]
   fn test_tx_release_range() -> crate::Result<()> {
     // Set initial mmap size well beyond the limit we will hit in this
@@ -2324,16 +5059,43 @@ mod test {
     stats.inc_spill_time(Duration::from_secs(10001));
     assert_eq!(10001, stats.spill_time().as_secs());
 
-    stats.inc_write(100_000);
-    assert_eq!(100_000, stats.write());
+    stats.inc_write(100_000);
+    assert_eq!(100_000, stats.write());
+
+    stats.inc_write_time(Duration::from_secs(100_001));
+    assert_eq!(100_001, stats.write_time().as_secs());
+
+    stats.inc_reused_page_n(4);
+    assert_eq!(4, stats.reused_page_n());
+
+    stats.inc_grown_page_n(5);
+    assert_eq!(5, stats.grown_page_n());
+
+    stats.max_assign_alloc_request(6);
+    stats.max_assign_alloc_request(2);
+    assert_eq!(6, stats.max_alloc_request());
+
+    stats.inc_alloc_fallback_n(7);
+    assert_eq!(7, stats.alloc_fallback_n());
 
-    stats.inc_write_time(Duration::from_secs(100_001));
-    assert_eq!(100_001, stats.write_time().as_secs());
+    stats.inc_seek_n(15);
+    assert_eq!(15, stats.seek_n());
+
+    stats.inc_seek_page_n(45);
+    assert_eq!(45, stats.seek_page_n());
+    assert_eq!(3.0, stats.pages_per_seek());
+    assert_eq!(2.0, stats.avg_branch_depth());
 
     let expected_stats = TxStats {
       page_count: 1.into(),
       page_alloc: 2.into(),
+      reused_page_n: 4.into(),
+      grown_page_n: 5.into(),
+      max_alloc_request: 6.into(),
+      alloc_fallback_n: 7.into(),
       cursor_count: 3.into(),
+      seek_n: 15.into(),
+      seek_page_n: 45.into(),
       node_count: 100.into(),
       node_deref: 101.into(),
       rebalance: 1000.into(),
@@ -2343,6 +5105,7 @@ mod test {
       spill_time: Duration::from_secs(10001).into(),
       write: 100_000.into(),
       write_time: Duration::from_secs(100_001).into(),
+      bucket_metrics: Default::default(),
     };
 
     assert_eq!(expected_stats, stats);
@@ -2353,7 +5116,13 @@ mod test {
     let stats_a = TxStats {
       page_count: 1.into(),
       page_alloc: 2.into(),
+      reused_page_n: 4.into(),
+      grown_page_n: 5.into(),
+      max_alloc_request: 6.into(),
+      alloc_fallback_n: 7.into(),
       cursor_count: 3.into(),
+      seek_n: 15.into(),
+      seek_page_n: 45.into(),
       node_count: 100.into(),
       node_deref: 101.into(),
       rebalance: 1000.into(),
@@ -2363,12 +5132,19 @@ mod test {
       spill_time: Duration::from_secs(10001).into(),
       write: 100_000.into(),
       write_time: Duration::from_secs(100_001).into(),
+      bucket_metrics: Default::default(),
     };
 
     let stats_b = TxStats {
       page_count: 2.into(),
       page_alloc: 3.into(),
+      reused_page_n: 5.into(),
+      grown_page_n: 7.into(),
+      max_alloc_request: 8.into(),
+      alloc_fallback_n: 8.into(),
       cursor_count: 4.into(),
+      seek_n: 20.into(),
+      seek_page_n: 60.into(),
       node_count: 101.into(),
       node_deref: 102.into(),
       rebalance: 1001.into(),
@@ -2378,13 +5154,20 @@ mod test {
       spill_time: Duration::from_secs(11002).into(),
       write: 110_001.into(),
       write_time: Duration::from_secs(110_010).into(),
+      bucket_metrics: Default::default(),
     };
 
     let diff = stats_b.sub(&stats_a);
     let expected_stats = TxStats {
       page_count: 1.into(),
       page_alloc: 1.into(),
+      reused_page_n: 1.into(),
+      grown_page_n: 2.into(),
+      max_alloc_request: 8.into(),
+      alloc_fallback_n: 1.into(),
       cursor_count: 1.into(),
+      seek_n: 5.into(),
+      seek_page_n: 15.into(),
       node_count: 1.into(),
       node_deref: 1.into(),
       rebalance: 1.into(),
@@ -2394,6 +5177,7 @@ mod test {
       spill_time: Duration::from_secs(1001).into(),
       write: 10001.into(),
       write_time: Duration::from_secs(10009).into(),
+      bucket_metrics: Default::default(),
     };
 
     assert_eq!(expected_stats, diff);
@@ -2410,7 +5194,13 @@ mod test {
     let stats_a = TxStats {
       page_count: 1.into(),
       page_alloc: 2.into(),
+      reused_page_n: 4.into(),
+      grown_page_n: 5.into(),
+      max_alloc_request: 6.into(),
+      alloc_fallback_n: 7.into(),
       cursor_count: 3.into(),
+      seek_n: 15.into(),
+      seek_page_n: 45.into(),
       node_count: 100.into(),
       node_deref: 101.into(),
       rebalance: 1000.into(),
@@ -2420,12 +5210,19 @@ mod test {
       spill_time: Duration::from_secs(10001).into(),
       write: 100_000.into(),
       write_time: Duration::from_secs(100_001).into(),
+      bucket_metrics: Default::default(),
     };
 
     let stats_b = TxStats {
       page_count: 2.into(),
       page_alloc: 3.into(),
+      reused_page_n: 5.into(),
+      grown_page_n: 7.into(),
+      max_alloc_request: 8.into(),
+      alloc_fallback_n: 8.into(),
       cursor_count: 4.into(),
+      seek_n: 20.into(),
+      seek_page_n: 60.into(),
       node_count: 101.into(),
       node_deref: 102.into(),
       rebalance: 1001.into(),
@@ -2435,13 +5232,20 @@ mod test {
       spill_time: Duration::from_secs(11002).into(),
       write: 110_001.into(),
       write_time: Duration::from_secs(110_010).into(),
+      bucket_metrics: Default::default(),
     };
 
     let add = stats_b.add(&stats_a);
     let expected_stats = TxStats {
       page_count: 3.into(),
       page_alloc: 5.into(),
+      reused_page_n: 9.into(),
+      grown_page_n: 12.into(),
+      max_alloc_request: 8.into(),
+      alloc_fallback_n: 15.into(),
       cursor_count: 7.into(),
+      seek_n: 35.into(),
+      seek_page_n: 105.into(),
       node_count: 201.into(),
       node_deref: 203.into(),
       rebalance: 2001.into(),
@@ -2451,8 +5255,643 @@ mod test {
       spill_time: Duration::from_secs(21003).into(),
       write: 210001.into(),
       write_time: Duration::from_secs(210011).into(),
+      bucket_metrics: Default::default(),
     };
 
     assert_eq!(expected_stats, add);
   }
+
+  #[test]
+  fn test_tx_stats_allocation() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    for i in 0..10 {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("widgets")?;
+        b.put(format!("{:04}", i), "*".repeat(5000))?;
+        Ok(())
+      })?;
+    }
+    let stats = db.stats();
+    let tx_stats = stats.tx_stats();
+    assert!(tx_stats.grown_page_n() > 0);
+    assert!(tx_stats.reused_page_n() > 0);
+    assert_eq!(
+      tx_stats.page_count(),
+      tx_stats.reused_page_n() + tx_stats.grown_page_n()
+    );
+    assert!(tx_stats.max_alloc_request() > 1);
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_stats_seek() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), "value")?;
+      }
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      let before = tx.stats().as_ref().clone();
+      // Cursor seeks always start at the root, gets may start at a cached leaf
+      let mut c = b.cursor();
+      for i in 0..100u32 {
+        assert!(c.seek(i.to_be_bytes()).is_some());
+      }
+      let stats = tx.stats().sub(&before);
+      assert_eq!(100, stats.seek_n());
+      assert!(stats.avg_branch_depth() >= 1.0);
+      assert_eq!(stats.pages_per_seek() - 1.0, stats.avg_branch_depth());
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_allocate_overflow() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let page_size = db.info().page_size;
+    for pages in [5usize, 3, 8, 2, 8, 1] {
+      db.update(|mut tx| {
+        let mut b = tx.create_bucket_if_not_exists("widgets")?;
+        b.put("big", vec![pages as u8; pages * page_size])?;
+        b.put(format!("{:04}", pages), "*".repeat(page_size / 2))?;
+        Ok(())
+      })?;
+      db.view(|tx| {
+        let b = tx.bucket("widgets").unwrap();
+        assert_eq!(
+          Some(vec![pages as u8; pages * page_size].as_slice()),
+          b.get("big")
+        );
+        Ok(())
+      })?;
+      db.must_check();
+    }
+    let stats = db.stats();
+    let tx_stats = stats.tx_stats();
+    assert!(tx_stats.max_alloc_request() > 8);
+    assert!(tx_stats.reused_page_n() > 0);
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_inspect() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut widgets = tx.create_bucket("widgets")?;
+      widgets.put("foo", "bar")?;
+      widgets.put("baz", "bat")?;
+      let mut sub = widgets.create_bucket("sub")?;
+      sub.put("key", "value")?;
+      tx.create_bucket("empty")?;
+      Ok(())
+    })?;
+    let expected = BucketStructure {
+      name: b"root".to_vec(),
+      key_n: 0,
+      children: vec![
+        BucketStructure {
+          name: b"empty".to_vec(),
+          key_n: 0,
+          children: vec![],
+        },
+        BucketStructure {
+          name: b"widgets".to_vec(),
+          key_n: 2,
+          children: vec![BucketStructure {
+            name: b"sub".to_vec(),
+            key_n: 1,
+            children: vec![],
+          }],
+        },
+      ],
+    };
+    db.view(|tx| {
+      assert_eq!(expected, tx.inspect()?);
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_page_usage_report() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000 {
+        b.put(format!("{:04}", i), "*".repeat(100))?;
+      }
+      let mut big = tx.create_bucket("big")?;
+      big.put("key", "*".repeat(DEFAULT_PAGE_SIZE.bytes() as usize * 3))?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..500 {
+        b.delete(format!("{:04}", i))?;
+      }
+      Ok(())
+    })?;
+    db.must_check();
+    db.view(|tx| {
+      let report = tx.page_usage_report();
+      assert_eq!(DEFAULT_PAGE_SIZE.bytes() as u64, report.page_size());
+      assert_eq!(2, report.meta_page_n());
+      assert!(report.freelist_page_n() >= 1);
+      assert!(report.branch_page_n() >= 1);
+      assert!(report.leaf_page_n() > 1);
+      assert_eq!(1, report.overflow_chain_n());
+      assert_eq!(report.overflow_page_n() + 1, report.max_overflow_chain());
+      assert!(report.leaf_fill_ratio() > 0.0 && report.leaf_fill_ratio() <= 1.0);
+      assert!(report.free_page_n() > 0);
+      assert!(report.max_free_run() >= 1 && report.max_free_run() <= report.free_page_n());
+
+      // every page below the high water mark is accounted for
+      let accounted = report.meta_page_n()
+        + report.freelist_page_n()
+        + report.branch_page_n()
+        + report.leaf_page_n()
+        + report.overflow_page_n()
+        + report.free_page_n();
+      assert_eq!(report.high_water_mark(), accounted);
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_page_usage_report_concurrent_writer() -> crate::Result<()> {
+    // The writer would deadlock growing the mmap while the read transaction is open
+    let db_options = BoltOptions::builder()
+      .initial_mmap_size(DEFAULT_PAGE_SIZE.bytes() as u64 * 1000)
+      .build();
+    let mut db = TestDb::with_options(db_options)?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000u32 {
+        b.put(i.to_be_bytes(), [0u8; 100])?;
+      }
+      Ok(())
+    })?;
+
+    let view_db = db.clone_db();
+    let tx = view_db.begin()?;
+    let before = tx.page_usage_report();
+    // Frees pages the snapshot of tx still refers to
+    db.update(|mut tx| {
+      let mut b = tx.bucket_mut("widgets").unwrap();
+      for i in 0..700u32 {
+        b.delete(i.to_be_bytes())?;
+      }
+      Ok(())
+    })?;
+    assert_eq!(before, tx.page_usage_report());
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_size_report() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      b.put("empty", "")?;
+      for i in 0..100u64 {
+        b.put(format!("{:03}", i), vec![0u8; i as usize])?;
+      }
+      b.create_bucket("child")?.put("blob", vec![0u8; 5000])?;
+      b.set_meta("owner", "ops")?;
+      tx.root_put("version", "1")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      // The database's own buckets aren't reported
+      let report = tx.size_report(3)?;
+      assert_eq!(2, report.buckets.len());
+      let widgets = &report.buckets[0];
+      assert_eq!(vec![b"widgets".to_vec()], widgets.path);
+      assert_eq!(101, widgets.value_n);
+      // "000" and "empty" are empty, 1, 2-3, 4-7, ..., 64-99
+      assert_eq!(vec![2, 1, 2, 4, 8, 16, 32, 36], widgets.value_histogram);
+      assert_eq!(
+        vec![
+          (b"099".to_vec(), 99),
+          (b"098".to_vec(), 98),
+          (b"097".to_vec(), 97)
+        ],
+        widgets.largest
+      );
+      assert_eq!(99, widgets.max_value_size());
+
+      let child = &report.buckets[1];
+      assert_eq!(vec![b"widgets".to_vec(), b"child".to_vec()], child.path);
+      assert_eq!(vec![(b"blob".to_vec(), 5000)], child.largest);
+      assert_eq!(5004, child.total_bytes);
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_root_kv() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.view(|tx| {
+      assert!(tx.root_kv().is_none());
+      assert_eq!(None, tx.root_get("version"));
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      // Deleting from a missing root data bucket doesn't create it
+      tx.root_delete("version")?;
+      assert!(tx.root_kv().is_none());
+      tx.root_put("version", "1")?;
+      tx.root_put("owner", "ops")?;
+      assert_eq!(Some(b"1".as_slice()), tx.root_get("version"));
+      tx.root_kv_mut()?.put("version", "2")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      assert_eq!(Some(b"2".as_slice()), tx.root_get("version"));
+      tx.root_delete("owner")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      assert_eq!(None, tx.root_get("owner"));
+      let root_kv = tx.root_kv().unwrap();
+      assert_eq!(1, root_kv.key_n());
+      // It's kept with the database's own records
+      assert!(tx.cursor().first().is_none());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_reserved_buckets() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let mut db = TestDb::with_options(BoltOptions::builder().comparators(comparators).build())?;
+    db.update(|mut tx| {
+      for name in ["a", "bbolt", "bbolt-", "zeta"] {
+        tx.create_bucket(name)?.set_meta("version", "1")?;
+      }
+      tx.create_bucket_with_comparator("reversed", "reverse")?;
+      tx.root_put("instance-id", "a1b2")?;
+      assert_eq!(
+        Err(Error::ReservedName),
+        tx.create_bucket("bbolt.system").map(|_| ())
+      );
+      assert_eq!(
+        Err(Error::ReservedName),
+        tx.create_bucket_if_not_exists("bbolt.other").map(|_| ())
+      );
+      assert_eq!(Err(Error::ReservedName), tx.delete_bucket("bbolt.system"));
+      assert_eq!(
+        Err(Error::ReservedName),
+        tx.ensure_schema(&[BucketSpec::new(&["bbolt.system", "meta"])])
+          .map(|_| ())
+      );
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let names = ["a", "bbolt", "bbolt-", "reversed", "zeta"]
+        .map(|name| name.as_bytes().to_vec())
+        .to_vec();
+      assert!(tx.bucket("bbolt.system").is_none());
+      assert!(tx.try_bucket("bbolt.system")?.is_none());
+
+      let mut listed = Vec::new();
+      tx.for_each(|name, _| {
+        listed.push(name.to_vec());
+        Ok(())
+      })?;
+      assert_eq!(names, listed);
+      let exported = tx.export_iter().map(|r| r.key.to_vec()).collect::<Vec<_>>();
+      assert_eq!(names, exported);
+      let mut walked = Vec::new();
+      tx.walk(|path, key, _| {
+        assert!(path.is_empty());
+        walked.push(key.to_vec());
+        Ok(())
+      })?;
+      assert_eq!(names, walked);
+      let inspected = tx.inspect()?;
+      assert_eq!(5, inspected.children.len());
+
+      let mut c = tx.cursor();
+      assert_eq!(Some((b"a".as_slice(), None)), c.first());
+      assert_eq!(Some((b"bbolt".as_slice(), None)), c.next());
+      assert_eq!(Some((b"bbolt-".as_slice(), None)), c.next());
+      assert_eq!(Some((b"reversed".as_slice(), None)), c.next());
+      assert_eq!(Some((b"reversed".as_slice(), None)), c.seek("bbolt.system"));
+      assert_eq!(Some((b"bbolt-".as_slice(), None)), c.prev());
+      assert_eq!(Some((b"zeta".as_slice(), None)), c.last());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_page_elements() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000 {
+        b.put(format!("{:04}", i), "*".repeat(100))?;
+      }
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      tx.bucket_mut("widgets").unwrap().delete("0000")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let root = tx.bucket("widgets").unwrap().root();
+      let branches = match tx.page_elements(root)? {
+        Some(PageElements::Branch(elements)) => elements,
+        _ => panic!("expected a branch page"),
+      };
+      assert_eq!(b"0001", branches[0].key);
+      match tx.page_elements(branches[0].pgid)? {
+        Some(PageElements::Leaf(elements)) => {
+          assert_eq!(b"0001", elements[0].key);
+          assert_eq!("*".repeat(100).as_bytes(), elements[0].value);
+          assert!(!elements[0].is_bucket_entry());
+        }
+        _ => panic!("expected a leaf page"),
+      }
+
+      let freelist = tx.unseal().meta().free_list();
+      assert!(matches!(
+        tx.page_elements(freelist)?,
+        Some(PageElements::FreeList(_))
+      ));
+      assert_eq!(None, tx.page_elements(PgId(0))?);
+      let high_water = tx.unseal().meta().pgid();
+      assert_eq!(
+        Some(Error::PageOutOfRange {
+          pgid: high_water,
+          high_water
+        }),
+        tx.page_bytes(high_water).err()
+      );
+
+      // Elements pointing outside the page are rejected
+      let bytes = tx.page_bytes(branches[0].pgid)?;
+      assert_eq!(None, PageElements::parse(&bytes[..20]));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_read_page_beyond_high_water() -> crate::Result<()> {
+    let db = TestDb::new()?;
+    let tx = db.begin_tx()?;
+    let high_water = tx.unseal().meta().pgid();
+    // Checked in release builds too
+    match tx.unseal().try_mem_page(high_water) {
+      Err(Error::Corrupted { pgid, .. }) => assert_eq!(high_water, pgid),
+      _ => panic!("expected a corrupted page"),
+    }
+    assert!(tx.unseal().try_mem_page(high_water - 1).is_ok());
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_page_bytes_allocated_page() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let tx = db.begin_rw_tx()?;
+    let high_water = tx.tx.meta().pgid();
+    // A page allocated by the transaction isn't written, or even mapped, until the commit
+    let page = tx.tx.allocate(4)?;
+    assert_eq!(high_water, page.id);
+    assert!(tx.tx.meta().pgid() > high_water);
+    assert_eq!(
+      Some(Error::PageOutOfRange {
+        pgid: page.id,
+        high_water
+      }),
+      tx.page_bytes(page.id).err()
+    );
+    assert!(tx.page_bytes(high_water - 1).is_ok());
+    drop(page);
+    tx.rollback()?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_page_elements_render() -> crate::Result<()> {
+    struct Utf8Stringer;
+    impl KvStringer for Utf8Stringer {
+      fn key_to_string(&self, key: &[u8]) -> String {
+        String::from_utf8_lossy(key).into_owned()
+      }
+
+      fn value_to_string(&self, value: &[u8]) -> String {
+        format!("{} bytes", value.len())
+      }
+    }
+
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = tx.create_bucket("widgets")?;
+      for i in 0..1000 {
+        b.put(format!("{:04}", i), "*".repeat(100))?;
+      }
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let root = tx.bucket("widgets").unwrap().root();
+      let branches = tx.page_elements(root)?.unwrap().render(&Utf8Stringer);
+      assert!(branches[0].starts_with("key: 0000, pgid: "));
+
+      let pgid = match tx.page_elements(root)? {
+        Some(PageElements::Branch(elements)) => elements[0].pgid,
+        _ => panic!("expected a branch page"),
+      };
+      let leaves = tx.page_elements(pgid)?.unwrap();
+      assert_eq!(
+        "key: 0000, value: 100 bytes",
+        leaves.render(&Utf8Stringer)[0]
+      );
+      assert_eq!(
+        "key: [30, 30, 30, 30], value: [2A, 2A",
+        &leaves.render(&HexKvStringer)[0][..37]
+      );
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_meta_info() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("widgets")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      tx.create_bucket("woojits")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let metas = tx.meta_info();
+      assert_eq!(PgId(0), metas[0].id);
+      assert_eq!(PgId(1), metas[1].id);
+      for meta in &metas {
+        assert!(meta.is_valid());
+        assert_eq!(DEFAULT_PAGE_SIZE.bytes() as u32, meta.page_size);
+        assert_eq!(meta.id.0, meta.txid.0 % 2);
+      }
+      assert!(!metas[0].active || !metas[1].active);
+      let active = metas.iter().find(|m| m.active).unwrap();
+      let previous = metas.iter().find(|m| !m.active).unwrap();
+      assert_eq!(tx.id(), active.txid);
+      assert_eq!(active.txid.0 - 1, previous.txid.0);
+      assert_eq!(tx.unseal().meta().root().root(), active.root);
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_drop_policy() -> crate::Result<()> {
+    let mut db = BoltOptions::builder()
+      .tx_drop_policy(TxDropPolicy::PanicInDebug)
+      .build()
+      .open_mem()?;
+    // Committed, rolled back and managed transactions end without complaint
+    let mut tx = db.begin_rw()?;
+    tx.create_bucket("widgets")?;
+    tx.commit()?;
+    db.begin_rw()?.rollback()?;
+    db.update(|mut tx| {
+      tx.bucket_mut("widgets").unwrap().put("foo", "bar")?;
+      Ok(())
+    })?;
+    assert!(db.update(|_| Err(Error::BucketNotFound)).is_err());
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+      let mut tx = db.begin_rw().unwrap();
+      tx.bucket_mut("widgets").unwrap().put("baz", "bat").unwrap();
+    }));
+    assert_eq!(cfg!(debug_assertions), result.is_err());
+
+    // The forgotten transaction was rolled back and released the writer lock
+    db.update(|tx| {
+      assert_eq!(None, tx.bucket("widgets").unwrap().get("baz"));
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_prepare() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    let mut tx = db.begin_rw()?;
+    tx.create_bucket("widgets")?.put("foo", "bar")?;
+    tx.prepare()?.confirm()?;
+
+    // Aborted and dropped prepared transactions are rolled back
+    let mut tx = db.begin_rw()?;
+    tx.bucket_mut("widgets").unwrap().put("baz", "bat")?;
+    tx.prepare()?.abort()?;
+    let mut tx = db.begin_rw()?;
+    tx.bucket_mut("widgets").unwrap().put("baz", "bat")?;
+    drop(tx.prepare()?);
+
+    db.view(|tx| {
+      let b = tx.bucket("widgets").unwrap();
+      assert_eq!(Some(b"bar".as_slice()), b.get("foo"));
+      assert_eq!(None, b.get("baz"));
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_ensure_schema() -> crate::Result<()> {
+    let comparators = Comparators::new().with("reverse", |a, b| b.cmp(a));
+    let mut db = TestDb::with_options(BoltOptions::builder().comparators(comparators).build())?;
+    // Children may be listed before their parents
+    let schema = [
+      BucketSpec::new(&["users", "by-name"]).comparator("reverse"),
+      BucketSpec::new(&["users"]).sequence(7),
+      BucketSpec::new(&["posts", "drafts"]),
+    ];
+    db.update(|mut tx| {
+      let mut buckets = tx.ensure_schema(&schema)?;
+      assert_eq!(7, buckets[1].sequence());
+      buckets[0].put("a", "1")?;
+      buckets[0].put("b", "2")?;
+      Ok(())
+    })?;
+    db.update(|mut tx| {
+      let buckets = tx.ensure_schema(&schema)?;
+      let mut c = buckets[0].cursor();
+      assert_eq!(Some((b"b".as_slice(), Some(b"2".as_slice()))), c.first());
+      assert!(tx.bucket("posts").unwrap().bucket("drafts").is_some());
+
+      // A failed provisioning leaves no buckets behind
+      let conflict = [
+        BucketSpec::new(&["tags"]),
+        BucketSpec::new(&["users", "by-name"]),
+      ];
+      assert_eq!(
+        Some(Error::IncompatibleValue),
+        tx.ensure_schema(&conflict).err()
+      );
+      assert!(tx.bucket("tags").is_none());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
+
+  #[test]
+  fn test_tx_all_or_nothing() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      tx.create_bucket("kept")?;
+      // A panic rolls back as well
+      let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        tx.tx.all_or_nothing::<(), _>(|| {
+          tx.create_bucket("panicked")?;
+          panic!("marker")
+        })
+      }));
+      assert!(result.is_err());
+      assert!(tx.bucket("panicked").is_none());
+      Ok(())
+    })?;
+
+    // The error of `f` is returned even if the rollback fails, the commit fails instead
+    let result = db.update(|mut tx| {
+      let r: crate::Result<()> = tx.tx.all_or_nothing(|| {
+        tx.create_bucket("removed")?;
+        // Deleted without recording it, so reverting its creation fails
+        tx.tx.split_ow_mut().as_mut().unwrap().undoing = true;
+        let deleted = tx.delete_bucket("removed");
+        tx.tx.split_ow_mut().as_mut().unwrap().undoing = false;
+        deleted?;
+        Err(Error::Other(anyhow!("marker")))
+      });
+      assert_eq!("marker", r.unwrap_err().to_string());
+      Ok(())
+    });
+    assert!(matches!(result, Err(Error::InvalidInternalState(_))));
+    db.view(|tx| {
+      assert!(tx.bucket("kept").is_some());
+      assert!(tx.bucket("removed").is_none());
+      Ok(())
+    })?;
+    db.must_check();
+    Ok(())
+  }
 }
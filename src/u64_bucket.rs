@@ -0,0 +1,213 @@
+use crate::{BucketApi, BucketRwApi, CursorApi, CursorImpl};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+/// Decodes a big-endian u64 key. Returns None for keys of any other length.
+#[inline]
+fn decode(key: &[u8]) -> Option<u64> {
+  key.try_into().ok().map(u64::from_be_bytes)
+}
+
+/// Returns the id and value of a key/value pair. Sub-buckets and keys that are not
+/// 8 bytes long are skipped.
+#[inline]
+fn entry<'tx>(kv: (&'tx [u8], Option<&'tx [u8]>)) -> Option<(u64, &'tx [u8])> {
+  let (k, v) = kv;
+  Some((decode(k)?, v?))
+}
+
+/// U64Bucket wraps a bucket whose keys are u64 ids.
+///
+/// Ids are stored as 8 byte big-endian keys so the bucket's byte order matches the
+/// numeric order of the ids. Other keys and sub-buckets in the bucket are ignored.
+///
+/// ```rust
+/// use bbolt_rs::*;
+///
+/// fn main() -> Result<()> {
+///   let mut db = Bolt::open_mem()?;
+///
+///   db.update(|mut tx| {
+///     let mut b = U64Bucket::new(tx.create_bucket("ids")?);
+///     let id = b.next_free_id().unwrap();
+///     b.put(id, "first")?;
+///     b.put(b.next_free_id().unwrap(), "second")?;
+///     Ok(())
+///   })?;
+///
+///   db.view(|tx| {
+///     let b = U64Bucket::new(tx.bucket("ids").unwrap());
+///     assert_eq!(Some(b"second".as_slice()), b.get(1));
+///     assert_eq!(vec![(0, b"first".as_slice())], b.range(..1).collect::<Vec<_>>());
+///     Ok(())
+///   })?;
+///
+///   Ok(())
+/// }
+/// ```
+pub struct U64Bucket<'tx, B: BucketApi<'tx>> {
+  bucket: B,
+  p: PhantomData<&'tx u8>,
+}
+
+impl<'tx, B: BucketApi<'tx>> U64Bucket<'tx, B> {
+  /// Wraps `bucket`
+  pub fn new(bucket: B) -> U64Bucket<'tx, B> {
+    U64Bucket {
+      bucket,
+      p: PhantomData,
+    }
+  }
+
+  /// Returns the wrapped bucket
+  pub fn into_inner(self) -> B {
+    self.bucket
+  }
+
+  /// Retrieves the value for an id.
+  ///
+  /// Returns None if the id does not exist or if it is a nested bucket.
+  pub fn get(&self, id: u64) -> Option<&[u8]> {
+    self.bucket.get(id.to_be_bytes())
+  }
+
+  /// Iterates over the ids within `range` and their values in ascending order
+  pub fn range<R: RangeBounds<u64>>(&self, range: R) -> U64Range<'tx> {
+    let mut c = self.bucket.cursor();
+    let first = match range.start_bound() {
+      Bound::Included(start) => c.seek(start.to_be_bytes()),
+      Bound::Excluded(start) => match start.checked_add(1) {
+        Some(start) => c.seek(start.to_be_bytes()),
+        None => None,
+      },
+      Bound::Unbounded => c.first(),
+    };
+    U64Range {
+      c,
+      next: first,
+      end: range.end_bound().cloned(),
+    }
+  }
+
+  /// Returns the smallest id in the bucket
+  pub fn min(&self) -> Option<u64> {
+    let mut c = self.bucket.cursor();
+    let mut kv = c.first();
+    while let Some(item) = kv {
+      if let Some((id, _)) = entry(item) {
+        return Some(id);
+      }
+      kv = c.next();
+    }
+    None
+  }
+
+  /// Returns the largest id in the bucket
+  pub fn max(&self) -> Option<u64> {
+    let mut c = self.bucket.cursor();
+    let mut kv = c.last();
+    while let Some(item) = kv {
+      if let Some((id, _)) = entry(item) {
+        return Some(id);
+      }
+      kv = c.prev();
+    }
+    None
+  }
+
+  /// Returns the id following the largest id in the bucket, or 0 if the bucket has no ids.
+  ///
+  /// Returns None if [u64::MAX] is in use.
+  pub fn next_free_id(&self) -> Option<u64> {
+    match self.max() {
+      Some(id) => id.checked_add(1),
+      None => Some(0),
+    }
+  }
+}
+
+impl<'tx, B: BucketRwApi<'tx>> U64Bucket<'tx, B> {
+  /// Sets the value for an id. See [BucketRwApi::put]
+  pub fn put<T: AsRef<[u8]>>(&mut self, id: u64, value: T) -> crate::Result<()> {
+    self.bucket.put(id.to_be_bytes(), value)
+  }
+
+  /// Removes an id. See [BucketRwApi::delete]
+  pub fn delete(&mut self, id: u64) -> crate::Result<()> {
+    self.bucket.delete(id.to_be_bytes())
+  }
+}
+
+/// The iterator returned by [U64Bucket::range]
+pub struct U64Range<'tx> {
+  c: CursorImpl<'tx>,
+  next: Option<(&'tx [u8], Option<&'tx [u8]>)>,
+  end: Bound<u64>,
+}
+
+impl<'tx> Iterator for U64Range<'tx> {
+  type Item = (u64, &'tx [u8]);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some(kv) = self.next.take() {
+      self.next = self.c.next();
+      if let Some((id, v)) = entry(kv) {
+        let in_range = match self.end {
+          Bound::Included(end) => id <= end,
+          Bound::Excluded(end) => id < end,
+          Bound::Unbounded => true,
+        };
+        if !in_range {
+          self.next = None;
+          return None;
+        }
+        return Some((id, v));
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::test_support::TestDb;
+  use crate::{BucketRwApi, DbApi, DbRwAPI, TxApi, TxRwRefApi, U64Bucket};
+
+  #[test]
+  fn test_u64_bucket() -> crate::Result<()> {
+    let mut db = TestDb::new()?;
+    db.update(|mut tx| {
+      let mut b = U64Bucket::new(tx.create_bucket("ids")?);
+      assert_eq!(None, b.min());
+      assert_eq!(Some(0), b.next_free_id());
+      for id in [3u64, 1, 300, 70000] {
+        b.put(id, id.to_string())?;
+      }
+      // Keys of other lengths and sub-buckets are skipped
+      let mut inner = b.into_inner();
+      inner.put("a", "b")?;
+      inner.put([0u8], "zero")?;
+      inner.create_bucket("sub")?;
+      let mut b = U64Bucket::new(inner);
+      b.delete(300)?;
+      b.put(u64::MAX - 1, "last")?;
+      Ok(())
+    })?;
+    db.view(|tx| {
+      let b = U64Bucket::new(tx.bucket("ids").unwrap());
+      assert_eq!(Some(b"70000".as_slice()), b.get(70000));
+      assert_eq!(None, b.get(300));
+      assert_eq!(Some(1), b.min());
+      assert_eq!(Some(u64::MAX - 1), b.max());
+      assert_eq!(Some(u64::MAX), b.next_free_id());
+      let ids = |r: Vec<(u64, &[u8])>| r.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+      assert_eq!(vec![1, 3, 70000, u64::MAX - 1], ids(b.range(..).collect()));
+      assert_eq!(vec![3], ids(b.range(2..70000).collect()));
+      assert_eq!(vec![3, 70000], ids(b.range(3..=70000).collect()));
+      assert_eq!(vec![70000, u64::MAX - 1], ids(b.range(4..).collect()));
+      assert!(b.range(u64::MAX..).next().is_none());
+      Ok(())
+    })?;
+    Ok(())
+  }
+}